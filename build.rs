@@ -0,0 +1,17 @@
+fn main() {
+    // The gRPC service is generated from its schema, reusing the messages of the HTTP API
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/routing.proto");
+        println!("cargo:rerun-if-changed=proto/ptolemy.proto");
+        // The generated service prefixes these paths with `super::`, so they are relative to the
+        // module that includes it in `api::grpc`
+        tonic_build::configure()
+            .build_client(false)
+            .extern_path(".ptolemy.RouteResponse", "super::protobuf::RouteResponse")
+            .extern_path(".ptolemy.TableResponse", "super::protobuf::TableResponse")
+            .extern_path(".ptolemy.SnappedPoint", "super::protobuf::SnappedPoint")
+            .compile(&["proto/routing.proto"], &["proto"])
+            .unwrap();
+    }
+}
@@ -1,5 +1,8 @@
+use petgraph::graph::NodeIndex;
+use ptolemy::crs::Crs;
 use ptolemy::Cartograph as InnerCartograph;
-use ptolemy::GeoPoint;
+use ptolemy::{round_duration, CartographError, GeoPoint};
+use pyo3::exceptions::{IndexError, ValueError};
 use pyo3::prelude::*;
 use pyo3::types::{PyDict, PyList};
 
@@ -14,7 +17,11 @@ struct Cartograph {
 impl Cartograph {
     #[new]
     fn new(obj: &PyRawObject, file_path: String) -> PyResult<()> {
-        let inner = InnerCartograph::open(file_path)?;
+        // Missing files raise the usual OSError subclasses, invalid contents raise ValueError
+        let inner = InnerCartograph::open(file_path).map_err(|err| match err {
+            CartographError::Io(err) => PyErr::from(err),
+            err => ValueError::py_err(err.to_string()),
+        })?;
         Ok(obj.init(Cartograph { inner }))
     }
 
@@ -40,19 +47,30 @@ impl Cartograph {
 
     /// Returns a sample of the edges inside a given region, described by two opposite corners in (lat, lon) coordinates.
     /// This function can return less than `max_num` even when there are more than that, please refer to the
-    ///  PrioritySample trait to understand how sampling works
-    #[text_signature = "(xy1, xy2, max_num, /)"]
+    ///  PrioritySample trait to understand how sampling works.
+    /// With `zoom`, only the road levels drawn at that zoom level are sampled, which is much faster
+    /// for wide regions
+    #[text_signature = "(xy1, xy2, max_num, zoom=None, /)"]
+    #[args(zoom = "None")]
     pub fn sample_edges_wm(
         &self,
         py: Python,
         xy1: (f64, f64),
         xy2: (f64, f64),
         max_num: usize,
+        zoom: Option<u8>,
     ) -> PyResult<PyObject> {
+        let max_road_level = match zoom.map(|zoom| self.inner.max_road_level_at_zoom(zoom)) {
+            None => None,
+            Some(Some(level)) => Some(level),
+            // No road is drawn at that zoom
+            Some(None) => return Ok(PyDict::new(py).to_object(py)),
+        };
+
         // Get edges
-        let edges_by_level = self
-            .inner
-            .sample_edges([xy1.0, xy1.1], [xy2.0, xy2.1], max_num);
+        let edges_by_level =
+            self.inner
+                .sample_edges([xy1.0, xy1.1], [xy2.0, xy2.1], max_num, max_road_level);
 
         // Transform each level into a HoloViews Path dict
         let result = PyDict::new(py);
@@ -81,6 +99,59 @@ impl Cartograph {
         Ok(result.to_object(py))
     }
 
+    /// Like `sample_edges_wm`, but merge the sampled edges that are adjacent and turn by at most `max_angle`
+    /// degrees into longer lines, so that there are fewer lines to draw at low zoom levels
+    #[text_signature = "(xy1, xy2, max_num, max_angle, zoom=None, /)"]
+    #[args(zoom = "None")]
+    pub fn sample_polylines_wm(
+        &self,
+        py: Python,
+        xy1: (f64, f64),
+        xy2: (f64, f64),
+        max_num: usize,
+        max_angle: f64,
+        zoom: Option<u8>,
+    ) -> PyResult<PyObject> {
+        let max_road_level = match zoom.map(|zoom| self.inner.max_road_level_at_zoom(zoom)) {
+            None => None,
+            Some(Some(level)) => Some(level),
+            // No road is drawn at that zoom
+            Some(None) => return Ok(PyDict::new(py).to_object(py)),
+        };
+
+        let polylines_by_level = self.inner.sample_polylines(
+            [xy1.0, xy1.1],
+            [xy2.0, xy2.1],
+            max_num,
+            max_road_level,
+            max_angle,
+        );
+
+        // Transform each level into a HoloViews Path dict
+        let result = PyDict::new(py);
+        for (level, polylines) in polylines_by_level {
+            // Collect x and y, interleaving with NaN between lines
+            let x = PyList::empty(py);
+            let y = PyList::empty(py);
+            for polyline in polylines {
+                for point in polyline {
+                    let xy = point.web_mercator_project();
+                    x.append(xy[0])?;
+                    y.append(xy[1])?;
+                }
+                x.append(std::f32::NAN)?;
+                y.append(std::f32::NAN)?;
+            }
+
+            let dict = PyDict::new(py);
+            dict.set_item("x", x)?;
+            dict.set_item("y", y)?;
+            result.set_item(level, dict)?;
+        }
+
+        Ok(result.to_object(py))
+    }
+
     /// Compute the strongly connected components
     #[text_signature = "()"]
     pub fn strongly_connected_components(&self) -> Vec<Vec<u32>> {
@@ -91,18 +162,148 @@ impl Cartograph {
             .collect()
     }
 
-    /// Compute the shortest path between two points, expressed in (lat, lon)
-    #[text_signature = "(from, to, /)"]
-    pub fn shortest_path(&self, from: (f64, f64), to: (f64, f64)) -> RoutePath {
+    /// Whether every node can reach all the others
+    #[text_signature = "()"]
+    pub fn is_strongly_connected(&self) -> bool {
+        self.inner.is_strongly_connected()
+    }
+
+    /// The nodes of the largest strongly connected component, sorted by their index
+    #[text_signature = "()"]
+    pub fn largest_component(&self) -> Vec<u32> {
+        self.inner
+            .largest_component()
+            .into_iter()
+            .map(|node| node.index() as u32)
+            .collect()
+    }
+
+    /// The strongly connected component of a node. The largest component has the id 0. Raises
+    /// IndexError when there is no such node
+    #[text_signature = "(node, /)"]
+    pub fn component_id(&self, node: u32) -> PyResult<u32> {
+        let node_count = self.inner.graph.node_count();
+        if node as usize >= node_count {
+            return Err(IndexError::py_err(format!(
+                "Node {} is out of range, there are {} nodes",
+                node, node_count
+            )));
+        }
+        Ok(self.inner.component_id(NodeIndex::new(node as usize)))
+    }
+
+    /// The number of nodes in each strongly connected component, from the largest to the smallest
+    #[text_signature = "()"]
+    pub fn component_sizes(&self) -> Vec<usize> {
+        self.inner.component_sizes()
+    }
+
+    /// The index of the node closest to a (lat, lon) point, like a junction, or None if it is
+    /// farther than `max_distance` meters
+    #[text_signature = "(latlon, max_distance, /)"]
+    pub fn nearest_node(&self, latlon: (f64, f64), max_distance: f64) -> Option<u32> {
+        self.inner
+            .nearest_node(&GeoPoint::from_degrees(latlon.0, latlon.1), max_distance)
+            .map(|node| node.index() as u32)
+    }
+
+    /// The indexes of the `k` nodes closest to a (lat, lon) point and their distances in meters, from the
+    /// closest to the farthest
+    #[text_signature = "(latlon, k, /)"]
+    pub fn nearest_nodes(&self, latlon: (f64, f64), k: usize) -> Vec<(u32, f64)> {
+        self.inner
+            .nearest_nodes(&GeoPoint::from_degrees(latlon.0, latlon.1), k)
+            .into_iter()
+            .map(|(node, distance)| (node.index() as u32, distance))
+            .collect()
+    }
+
+    /// The indexes of the nodes inside a region, described by two opposite corners in (lat, lon)
+    /// coordinates
+    #[text_signature = "(latlon1, latlon2, /)"]
+    pub fn nodes_in_bbox(&self, latlon1: (f64, f64), latlon2: (f64, f64)) -> Vec<u32> {
+        self.inner
+            .nodes_in_bbox(
+                &GeoPoint::from_degrees(latlon1.0, latlon1.1),
+                &GeoPoint::from_degrees(latlon2.0, latlon2.1),
+            )
+            .into_iter()
+            .map(|node| node.index() as u32)
+            .collect()
+    }
+
+    /// The distance in meters from a (lat, lon) point to the roads of each cell of a regular grid of
+    /// `cell_meters`, up to `max_cost` meters. Returns a dict with the `lat` of the rows (from south
+    /// to north), the `lon` of the columns (from west to east) and the `costs` as a nested list, with
+    /// NaN for the unreachable cells, which can be passed directly to `numpy.array()`
+    #[text_signature = "(latlon, cell_meters, max_cost, /)"]
+    pub fn cost_grid(
+        &self,
+        py: Python,
+        latlon: (f64, f64),
+        cell_meters: f64,
+        max_cost: u32,
+    ) -> PyResult<PyObject> {
+        if cell_meters.is_nan() || cell_meters <= 0. {
+            return Err(ValueError::py_err("cell_meters must be positive"));
+        }
+        let center = self
+            .inner
+            .project(&GeoPoint::from_degrees(latlon.0, latlon.1));
+        let grid = self.inner.cost_grid(&center, cell_meters, max_cost);
+
+        let size = grid.costs.len();
+        let lat: Vec<f64> = (0..size)
+            .map(|row| grid.cell_center(row, 0).lat.as_degrees())
+            .collect();
+        let lon: Vec<f64> = (0..size)
+            .map(|col| grid.cell_center(0, col).lon.as_degrees())
+            .collect();
+        let costs = PyList::empty(py);
+        for row in &grid.costs {
+            let row: Vec<f64> = row
+                .iter()
+                .map(|cost| cost.map_or(std::f64::NAN, |cost| cost as f64))
+                .collect();
+            costs.append(row)?;
+        }
+
+        let result = PyDict::new(py);
+        result.set_item("lat", lat)?;
+        result.set_item("lon", lon)?;
+        result.set_item("costs", costs)?;
+        Ok(result.to_object(py))
+    }
+
+    /// Compute the shortest path between two points, expressed in (lat, lon).
+    /// The coordinates of the path are expressed in `crs`: "wgs84" (the default, as (lon, lat)),
+    /// "web_mercator" or "utm"
+    #[text_signature = "(from, to, crs=None, /)"]
+    #[args(crs = "None")]
+    pub fn shortest_path(
+        &self,
+        from: (f64, f64),
+        to: (f64, f64),
+        crs: Option<&str>,
+    ) -> PyResult<RoutePath> {
+        let crs: Crs = crs
+            .unwrap_or("wgs84")
+            .parse()
+            .map_err(|err: ptolemy::crs::ParseCrsError| ValueError::py_err(err.to_string()))?;
+
         // Project nodes
         let from = self.inner.project(&GeoPoint::from_degrees(from.0, from.1));
         let to = self.inner.project(&GeoPoint::from_degrees(to.0, to.1));
 
         let path = self.inner.shortest_path(&from, &to);
-        RoutePath {
+        let (projection, coordinates) = crs.project_all(&path.points);
+        Ok(RoutePath {
             distance: path.distance,
+            duration: round_duration(path.duration),
             geometry: path.polyline,
-        }
+            coordinates: coordinates.into_iter().map(|[x, y]| (x, y)).collect(),
+            crs: projection.to_string(),
+        })
     }
 }
 
@@ -121,7 +322,16 @@ struct RoutePath {
     /// The route distance in meters
     #[pyo3(get)]
     pub distance: u32,
+    /// The estimated time to travel the route, in seconds
+    #[pyo3(get)]
+    pub duration: f64,
     /// The shape of the route, encoded as a polyline
     #[pyo3(get)]
     pub geometry: String,
+    /// The shape of the route, as (x, y) in the requested coordinate reference system
+    #[pyo3(get)]
+    pub coordinates: Vec<(f64, f64)>,
+    /// The coordinate reference system of `coordinates`, like "EPSG:32631"
+    #[pyo3(get)]
+    pub crs: String,
 }
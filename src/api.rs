@@ -1,53 +1,1029 @@
+mod admin;
+mod cache;
 mod data_types;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod protobuf;
+mod rate_limit;
+mod service;
 
-use crate::cartograph::*;
-use crate::utils::*;
-use actix_web::{get, web, App, HttpServer, Responder};
+use crate::precompute;
+use actix_web::dev::Service;
+use actix_web::{get, post, put, web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer};
+use admin::AdminToken;
+use cache::{LegKey, LegQuery, RouteCache};
+pub use data_types::ApiLimits;
 use data_types::*;
-use std::path::Path;
+#[cfg(feature = "grpc")]
+pub use grpc::run_grpc;
+use petgraph::graph::EdgeIndex;
+use ptolemy::*;
+pub use rate_limit::RateLimit;
+use rate_limit::RateLimiter;
+use service::{CancelOnDrop, CartographService};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
+
+/// The experiments that can be selected by the route queries, by name
+type Experiments = HashMap<String, Experiment>;
+
+/// The current closures, with their ways resolved to edges. `POST /closures` replaces them, with
+/// the admin token
+type SharedClosures = RwLock<Closures>;
+
+/// The speeds that replace the default ones until they expire. `POST /speeds` adds to them, with
+/// the admin token
+type SharedSpeeds = RwLock<SpeedOverrides>;
+
+/// The edges of each zone that the routes can avoid, by name. `PUT /avoid_zones` replaces them,
+/// with the admin token
+type SharedAvoidZones = RwLock<HashMap<String, Vec<EdgeIndex>>>;
+
+/// The identifier of the next request, to find its messages in the logs
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The response of a successful route query
+enum RouteBody {
+    Gpx(String),
+    Json(RouteResponse),
+}
 
 #[get("/route/v1/driving/{coordinates}")]
-async fn route(coords: web::Path<Coordinates>, carto: web::Data<Cartograph>) -> impl Responder {
-    // Project the points
+#[allow(clippy::too_many_arguments)]
+async fn route(
+    (request, coords): (HttpRequest, web::Path<Coordinates>),
+    options: web::Query<RouteOptions>,
+    service: web::Data<CartographService>,
+    cache: web::Data<RouteCache>,
+    limits: web::Data<ApiLimits>,
+    experiments: web::Data<Experiments>,
+    speed_profiles: web::Data<SpeedProfiles>,
+    closures: web::Data<SharedClosures>,
+    avoid_zones: web::Data<SharedAvoidZones>,
+    speeds: web::Data<SharedSpeeds>,
+) -> Result<HttpResponse, ApiError> {
+    let body = answer_route(
+        coords.into_inner(),
+        options.into_inner(),
+        &service,
+        cache,
+        &limits,
+        experiments,
+        speed_profiles,
+        &closures,
+        &avoid_zones,
+        &speeds,
+    )
+    .await?;
+    Ok(route_response(body, protobuf::accepts_protobuf(&request)))
+}
+
+#[get("/reroute/v1/driving/{coordinates}")]
+#[allow(clippy::too_many_arguments)]
+async fn reroute(
+    coords: web::Path<Coordinates>,
+    (options, reroute_options): (web::Query<RouteOptions>, web::Query<RerouteOptions>),
+    service: web::Data<CartographService>,
+    cache: web::Data<RouteCache>,
+    limits: web::Data<ApiLimits>,
+    experiments: web::Data<Experiments>,
+    speed_profiles: web::Data<SpeedProfiles>,
+    closures: web::Data<SharedClosures>,
+    avoid_zones: web::Data<SharedAvoidZones>,
+    speeds: web::Data<SharedSpeeds>,
+) -> Result<HttpResponse, ApiError> {
+    let (coords, reroute_options) = (coords.into_inner(), reroute_options.into_inner());
+    reroute_options.validate()?;
+    // Rounded to a tenth of a meter, like the cumulative distances
+    let distance_to_route = reroute_options
+        .geometry()?
+        .map(|path| (service.carto().distance_to_path(&coords.0[0], &path) * 10.).round() / 10.);
+    let max_deviation = reroute_options.max_deviation.unwrap_or(30.);
+    if let Some(distance) = distance_to_route.filter(|&distance| distance <= max_deviation) {
+        return Ok(HttpResponse::Ok().json(RerouteResponse {
+            off_route: false,
+            distance_to_route: Some(distance),
+            route: None,
+        }));
+    }
+
+    let body = answer_route(
+        coords,
+        options.into_inner(),
+        &service,
+        cache,
+        &limits,
+        experiments,
+        speed_profiles,
+        &closures,
+        &avoid_zones,
+        &speeds,
+    )
+    .await?;
+    match body {
+        RouteBody::Json(response) => Ok(HttpResponse::Ok().json(RerouteResponse {
+            off_route: true,
+            distance_to_route,
+            route: Some(response),
+        })),
+        body => Ok(route_response(body, false)),
+    }
+}
+
+/// Check a route query and answer it with the closures, avoided zones and speeds of the moment
+#[allow(clippy::too_many_arguments)]
+async fn answer_route(
+    coords: Coordinates,
+    options: RouteOptions,
+    service: &CartographService,
+    cache: web::Data<RouteCache>,
+    limits: &ApiLimits,
+    experiments: web::Data<Experiments>,
+    speed_profiles: web::Data<SpeedProfiles>,
+    closures: &SharedClosures,
+    avoid_zones: &SharedAvoidZones,
+    speeds: &SharedSpeeds,
+) -> Result<RouteBody, ApiError> {
+    options.validate()?;
+    limits.check_route(&coords, &options)?;
+    if let Some(name) = &options.experiment {
+        if !experiments.contains_key(name) {
+            return Err(ParseOptionError::UnknownExperiment(name.clone()).into());
+        }
+    }
+    let mut closed = closures
+        .read()
+        .unwrap()
+        .closed_edges(service.carto(), unix_time());
+    if let Some(names) = &options.avoid {
+        let avoid_zones = avoid_zones.read().unwrap();
+        for name in names {
+            if name == BORDERS_ZONE {
+                closed.extend(service.carto().border_edges());
+                continue;
+            }
+            let edges = avoid_zones
+                .get(name)
+                .ok_or_else(|| ParseOptionError::UnknownAvoidZone(name.clone()))?;
+            closed.extend(edges);
+        }
+    }
+    let speeds = speeds.read().unwrap().active_speeds(unix_time());
+    let is_heavy = options.is_heavy();
+    let deadline = limits.deadline();
+    let _cancel = CancelOnDrop(deadline.clone());
+    let query = move |carto: &Cartograph| {
+        let experiment = options.experiment.as_ref().map(|name| &experiments[name]);
+        compute_route(
+            carto,
+            &cache,
+            &coords,
+            &options,
+            experiment,
+            &speed_profiles,
+            &closed,
+            &speeds,
+            &deadline,
+        )
+    };
+    let result = if is_heavy {
+        service.run_heavy(query).await
+    } else {
+        service.run(query).await
+    };
+    result.map_err(|error| ApiError::Internal(error.to_string()))?
+}
+
+/// Send the answer of a route query as GPX, JSON or, when the client accepts it, Protocol Buffers
+fn route_response(body: RouteBody, protobuf: bool) -> HttpResponse {
+    match body {
+        RouteBody::Gpx(gpx) => HttpResponse::Ok()
+            .content_type("application/gpx+xml")
+            .body(gpx),
+        RouteBody::Json(response) if protobuf => {
+            protobuf::response(&protobuf::RouteResponse::from(&response))
+        }
+        RouteBody::Json(response) => HttpResponse::Ok().json(response),
+    }
+}
+
+/// Send an answer as JSON or, when the client accepts it, as Protocol Buffers
+fn encoded_response<'a, T, M>(request: &HttpRequest, response: &'a T) -> HttpResponse
+where
+    T: serde::Serialize,
+    M: prost::Message + From<&'a T>,
+{
+    if protobuf::accepts_protobuf(request) {
+        protobuf::response(&M::from(response))
+    } else {
+        HttpResponse::Ok().json(response)
+    }
+}
+
+#[get("/table/v1/driving/{coordinates}")]
+async fn table(
+    request: HttpRequest,
+    coords: web::Path<Coordinates>,
+    service: web::Data<CartographService>,
+    limits: web::Data<ApiLimits>,
+    hub_table: web::Data<Option<HubTable>>,
+) -> Result<HttpResponse, ApiError> {
+    let response = answer_table(coords.into_inner(), &service, &limits, &hub_table).await?;
+    Ok(encoded_response::<_, protobuf::TableResponse>(
+        &request, &response,
+    ))
+}
+
+/// Check a table query and answer it from the hub table, when it has all the points, or else by
+/// searching
+async fn answer_table(
+    coords: Coordinates,
+    service: &CartographService,
+    limits: &ApiLimits,
+    hub_table: &Option<HubTable>,
+) -> Result<TableResponse, ApiError> {
+    limits.check_table(&coords)?;
+    let points = coords.0;
+    if let Some(distances) = hub_table
+        .as_ref()
+        .and_then(|hubs| hubs.lookup(&points, &points))
+    {
+        return Ok(TableResponse {
+            distances,
+            precomputed: true,
+        });
+    }
+    let deadline = limits.deadline();
+    let _cancel = CancelOnDrop(deadline.clone());
+    let distances = service
+        .run_heavy(move |carto| {
+            let projected: Vec<_> = points.iter().map(|point| carto.project(point)).collect();
+            carto.distance_table_with_deadline(&projected, &projected, &deadline)
+        })
+        .await
+        .map_err(|error| ApiError::Internal(error.to_string()))??;
+    Ok(TableResponse {
+        distances,
+        precomputed: false,
+    })
+}
+
+#[get("/round_trip/v1/driving/{coordinate}")]
+async fn round_trip(
+    start: web::Path<Coordinate>,
+    options: web::Query<RoundTripOptions>,
+    service: web::Data<CartographService>,
+    limits: web::Data<ApiLimits>,
+) -> Result<HttpResponse, ApiError> {
+    let (start, options) = (start.into_inner().0, options.into_inner());
+    options.validate()?;
+    limits.check_round_trip(&options)?;
+    let (distance, seed) = (options.distance, options.seed.unwrap_or(0));
+    let deadline = limits.deadline();
+    let _cancel = CancelOnDrop(deadline.clone());
+    let (start, path) = service
+        .run_heavy(move |carto| {
+            let start = carto.project(&start);
+            let path = carto.round_trip(&start, distance, seed, &deadline);
+            (start, path)
+        })
+        .await
+        .map_err(|error| ApiError::Internal(error.to_string()))?;
+    let path = path?.ok_or(ApiError::NoRoundTrip { distance })?;
+    let geometry = match options.overview {
+        Some(Overview::False) => None,
+        Some(Overview::Simplified) => Some(GeometryResponse::Polyline(path.overview().polyline)),
+        Some(Overview::Full) | None => Some(GeometryResponse::Polyline(path.polyline)),
+    };
+    Ok(HttpResponse::Ok().json(RoundTripResponse {
+        location: [
+            start.projected.lon.as_degrees(),
+            start.projected.lat.as_degrees(),
+        ],
+        distance: path.distance,
+        duration: round_duration(path.duration),
+        geometry,
+    }))
+}
+
+#[post("/snap")]
+async fn snap(
+    http_request: HttpRequest,
+    request: web::Json<SnapRequest>,
+    service: web::Data<CartographService>,
+    limits: web::Data<ApiLimits>,
+) -> Result<HttpResponse, ApiError> {
+    limits.check_snap(&request)?;
+    let points = request.points()?;
+    // Each query already runs in its own thread, but a large one is split further so that it
+    // finishes sooner
+    let num_threads = (points.len() / 1000).max(1).min(num_cpus::get());
+    let snapped = service
+        .run_heavy(move |carto| carto.project_all(&points, num_threads))
+        .await
+        .map_err(|error| ApiError::Internal(error.to_string()))?;
+    let response = SnapResponse {
+        snapped: snapped.iter().map(SnappedPointResponse::from).collect(),
+    };
+    Ok(encoded_response::<_, protobuf::SnapResponse>(
+        &http_request,
+        &response,
+    ))
+}
+
+#[post("/closures")]
+async fn update_closures(
+    request: HttpRequest,
+    body: String,
+    service: web::Data<CartographService>,
+    closures: web::Data<SharedClosures>,
+    admin: web::Data<AdminToken>,
+) -> Result<HttpResponse, ApiError> {
+    admin.check(&request)?;
+    let new_closures: Closures = body.parse().map_err(ApiError::InvalidBody)?;
+    // Resolving the ways visits the whole graph
+    let (new_closures, closed_now) = service
+        .run(move |carto| {
+            let resolved = new_closures.resolve(carto);
+            let closed_now = resolved.closed_edges(carto, unix_time()).len();
+            (resolved, closed_now)
+        })
+        .await
+        .map_err(|error| ApiError::Internal(error.to_string()))?;
+    let response = ClosuresResponse {
+        edges: new_closures.edges.len(),
+        closed_now,
+    };
+    *closures.write().unwrap() = new_closures;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[post("/speeds")]
+async fn update_speeds(
+    request: HttpRequest,
+    body: String,
+    service: web::Data<CartographService>,
+    speeds: web::Data<SharedSpeeds>,
+    admin: web::Data<AdminToken>,
+) -> Result<HttpResponse, ApiError> {
+    admin.check(&request)?;
+    let updates = if request.content_type() == "application/json" {
+        let entries: Vec<SpeedEntryRequest> = serde_json::from_str(&body)
+            .map_err(|error| ApiError::InvalidBody(error.to_string()))?;
+        entries
+            .iter()
+            .map(SpeedEntryRequest::update)
+            .collect::<Result<_, _>>()?
+    } else {
+        body.parse::<SpeedUpdates>()
+            .map_err(ApiError::InvalidBody)?
+            .0
+    };
+    // Updating the ways visits the whole graph. The lock is held meanwhile, so that concurrent
+    // updates are not lost
+    let response = service
+        .run(move |carto| {
+            let mut speeds = speeds.write().unwrap();
+            let updated = speeds.update(carto, &updates, unix_time());
+            SpeedsResponse {
+                updated,
+                active: speeds.edges.len(),
+            }
+        })
+        .await
+        .map_err(|error| ApiError::Internal(error.to_string()))?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[put("/avoid_zones")]
+async fn update_avoid_zones(
+    request: HttpRequest,
+    body: web::Json<AvoidZonesRequest>,
+    service: web::Data<CartographService>,
+    avoid_zones: web::Data<SharedAvoidZones>,
+    admin: web::Data<AdminToken>,
+) -> Result<HttpResponse, ApiError> {
+    admin.check(&request)?;
+    let zones = body.zones()?;
+    let new_zones: HashMap<String, Vec<EdgeIndex>> = service
+        .run_heavy(move |carto| {
+            zones
+                .into_iter()
+                .map(|(name, zone)| (name, zone.edges(carto)))
+                .collect()
+        })
+        .await
+        .map_err(|error| ApiError::Internal(error.to_string()))?;
+    let response = AvoidZonesResponse {
+        zones: new_zones
+            .iter()
+            .map(|(name, edges)| (name.clone(), edges.len()))
+            .collect(),
+    };
+    *avoid_zones.write().unwrap() = new_zones;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[get("/cache/v1/stats")]
+async fn cache_stats(cache: web::Data<RouteCache>) -> HttpResponse {
+    HttpResponse::Ok().json(cache.stats())
+}
+
+#[get("/style/v1/levels")]
+async fn road_levels(service: web::Data<CartographService>) -> HttpResponse {
+    HttpResponse::Ok().json(LevelsResponse {
+        levels: &service.carto().road_levels,
+    })
+}
+
+#[get("/status")]
+async fn status(
+    service: web::Data<CartographService>,
+    initial_status: web::Data<StatusResponse>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(StatusResponse {
+        memory: service.carto().memory_usage(),
+        ..initial_status.get_ref().clone()
+    })
+}
+
+/// Describe what is served, except the memory usage, that changes while the server runs
+fn initial_status(
+    input: &Path,
+    carto: &Cartograph,
+    experiments: &Experiments,
+    hub_table: &Option<HubTable>,
+) -> io::Result<StatusResponse> {
+    let stats = Cartograph::read_stats(input)?;
+    let metadata = fs::metadata(input)?;
+    let mut experiments: Vec<_> = experiments.keys().cloned().collect();
+    experiments.sort();
+    Ok(StatusResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        file: FileStatus {
+            path: input.display().to_string(),
+            bytes: metadata.len(),
+            format: Cartograph::read_version(input)?.to_string(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|elapsed| elapsed.as_secs()),
+            metadata: carto.metadata().clone(),
+        },
+        nodes: stats.num_nodes,
+        edges: stats.num_edges,
+        components: stats.num_components,
+        bbox: [
+            stats.bbox.0.lon.as_degrees(),
+            stats.bbox.0.lat.as_degrees(),
+            stats.bbox.1.lon.as_degrees(),
+            stats.bbox.1.lat.as_degrees(),
+        ],
+        synthetic_edges: carto.allow_synthetic,
+        road_levels: carto
+            .road_levels
+            .iter()
+            .map(|level| level.name.clone())
+            .collect(),
+        experiments,
+        hub_table_points: hub_table.as_ref().map(|hubs| hubs.hubs().len()),
+        memory: MemoryUsage::default(),
+    })
+}
+
+/// The current time, in seconds since the Unix epoch, to know which closures apply
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Identify how the leg with the given index, that starts `elapsed` seconds after the route, is
+/// calculated, to cache it
+fn leg_query(options: &RouteOptions, leg: usize, elapsed: f64) -> LegQuery {
+    if let Some(depart_at) = options.depart_at {
+        return LegQuery::Timed {
+            depart_at: (depart_at + elapsed).to_bits(),
+        };
+    }
+    if let Some(name) = &options.experiment {
+        return LegQuery::Experiment { name: name.clone() };
+    }
+    if let Some(extra_edges) = &options.extra_edges {
+        let micro_degrees =
+            |point: &GeoPoint| (point.lat.as_micro_degrees(), point.lon.as_micro_degrees());
+        return LegQuery::Overlay {
+            edges: extra_edges
+                .iter()
+                .map(|edge| (micro_degrees(&edge.from), micro_degrees(&edge.to)))
+                .collect(),
+        };
+    }
+    // The randomized routes perturb the costs of the penalties and vehicle restrictions
+    let query = penalized_query(options);
+    match options.seed {
+        Some(seed) => LegQuery::Randomized {
+            seed: seed.wrapping_add(leg as u64),
+            max_stretch: options.max_stretch.unwrap_or(f64::NAN).to_bits(),
+            query: Box::new(query),
+        },
+        None => query,
+    }
+}
+
+/// Identify the penalties of the surfaces and access classes and the dimensions of the vehicle of
+/// a query, if any
+fn penalized_query(options: &RouteOptions) -> LegQuery {
+    match (
+        options.surface_penalties(),
+        options.access_penalties(),
+        options.vehicle(),
+    ) {
+        (None, None, None) => LegQuery::Shortest,
+        (surfaces, access, vehicle) => {
+            let surfaces = surfaces.unwrap_or_default();
+            let access = access.unwrap_or_default();
+            LegQuery::Penalized {
+                surfaces: surfaces
+                    .surfaces
+                    .iter()
+                    .map(|&(surface, factor)| (surface, factor.to_bits()))
+                    .collect(),
+                smoothness: surfaces
+                    .smoothness
+                    .map(|(smoothness, factor)| (smoothness, factor.to_bits())),
+                access: access
+                    .penalized
+                    .iter()
+                    .map(|&(access, factor)| (access, factor.to_bits()))
+                    .collect(),
+                forbidden_access: access.forbidden,
+                barrier: access.barrier,
+                vehicle: vehicle.map(|vehicle| {
+                    (
+                        vehicle.weight.to_bits(),
+                        vehicle.height.to_bits(),
+                        vehicle.width.to_bits(),
+                    )
+                }),
+            }
+        }
+    }
+}
+
+/// Answer a route query, with the costs of the experiment if one was selected. This may take a
+/// while, so it runs in the blocking thread pool, and the search of the shortest path of each leg
+/// gives up when the deadline passes. The closed edges, including the ones of the avoided zones, are
+/// avoided by all the queries, which also travel the edges at their overridden speeds
+#[allow(clippy::too_many_arguments)]
+fn compute_route(
+    carto: &Cartograph,
+    cache: &RouteCache,
+    coords: &Coordinates,
+    options: &RouteOptions,
+    experiment: Option<&Experiment>,
+    speed_profiles: &SpeedProfiles,
+    closed: &HashSet<EdgeIndex>,
+    speeds: &HashMap<EdgeIndex, f64>,
+    deadline: &Deadline,
+) -> Result<RouteBody, ApiError> {
+    // Project the points, failing before the search when one is too far from the roads
     let waypoints: Vec<_> = coords.0.iter().map(|point| carto.project(point)).collect();
+    if let Some(max) = options.max_snap_distance {
+        for (i, waypoint) in waypoints.iter().enumerate() {
+            let distance = waypoint.projected.haversine_distance(&waypoint.original);
+            if distance > max {
+                return Err(ApiError::NoSegment {
+                    waypoint: i,
+                    distance,
+                    max,
+                });
+            }
+        }
+    }
 
     // Calculate each path and accumulate all them
     let mut route_points: Vec<GeoPoint> = Vec::new();
     let mut distance = 0;
-    for points in waypoints.windows(2) {
-        let graph_path = carto.shortest_path(&points[0], &points[1]);
+    let mut duration = 0.;
+    let mut route_edges = Vec::new();
+    let mut arrivals = vec![0.];
+    let mut legs = Vec::with_capacity(waypoints.len() - 1);
+    let mut search_stats = Vec::with_capacity(waypoints.len() - 1);
+    let mut diversity = DiversityOptions::default();
+    if let Some(max_stretch) = options.max_stretch {
+        diversity.max_stretch = max_stretch;
+    }
+    let mut closed_indexes: Vec<u32> = closed.iter().map(|edge| edge.index() as u32).collect();
+    closed_indexes.sort_unstable();
+    let mut speed_bits: Vec<(u32, u64)> = speeds
+        .iter()
+        .map(|(edge, speed)| (edge.index() as u32, speed.to_bits()))
+        .collect();
+    speed_bits.sort_unstable();
+    for (i, points) in waypoints.windows(2).enumerate() {
+        let elapsed = duration;
+        let mut query = leg_query(options, i, elapsed);
+        if !closed.is_empty() {
+            query = LegQuery::Closed {
+                edges: closed_indexes.clone(),
+                query: Box::new(query),
+            };
+        }
+        if !speeds.is_empty() {
+            query = LegQuery::Speeds {
+                speeds: speed_bits.clone(),
+                query: Box::new(query),
+            };
+        }
+        let key = LegKey::new(&points[0], &points[1], query);
+        let mut leg_stats = None;
+        let mut search = || {
+            // Each leg leaves when the previous one arrives
+            if let Some(depart_at) = options.depart_at {
+                return carto.fastest_path_at(
+                    &points[0],
+                    &points[1],
+                    speed_profiles,
+                    depart_at + elapsed,
+                    closed,
+                    speeds,
+                    deadline,
+                );
+            }
+            // The destination may be unreachable when synthetic edges, access classes, roads too
+            // small for the vehicle or closed roads are forbidden
+            let (access, surfaces, vehicle) = (
+                options.access_penalties(),
+                options.surface_penalties(),
+                options.vehicle(),
+            );
+            let penalized = access.is_some() || surfaces.is_some() || vehicle.is_some();
+            let (access, surfaces) = (access.unwrap_or_default(), surfaces.unwrap_or_default());
+            let penalties = AccessAndSurfaces {
+                access: &access,
+                surfaces: &surfaces,
+            };
+            let restricted = |info: &EdgeInfo, from: &GeoPoint, to: &GeoPoint| match &vehicle {
+                Some(vehicle) if !info.restrictions.allows(vehicle) => u32::MAX,
+                _ => penalties.edge_cost(info, from, to),
+            };
+            // The experiments replace the penalties, which they can not be combined with
+            let base: &dyn CostModel = match experiment {
+                Some(experiment) => experiment,
+                None if penalized => &restricted,
+                None => &DistanceCost,
+            };
+            let cost = LiveSpeeds {
+                speeds,
+                cost: &ClosedEdges {
+                    edges: closed,
+                    cost: base,
+                },
+            };
+            let path = if let Some(extra_edges) = &options.extra_edges {
+                carto.cheapest_path_with_extra_edges(
+                    &points[0],
+                    &points[1],
+                    extra_edges,
+                    &cost,
+                    deadline,
+                )?
+            } else if let Some(seed) = options.seed {
+                // Each leg gets its own seed, so that they are not perturbed in the same way
+                carto.randomized_path_with_cost(
+                    &points[0],
+                    &points[1],
+                    seed.wrapping_add(i as u64),
+                    &diversity,
+                    &cost,
+                    deadline,
+                )?
+            } else if options.debug == Some(true) {
+                let (path, stats) =
+                    carto.cheapest_path_with_stats(&points[0], &points[1], &cost, deadline)?;
+                leg_stats = Some(stats);
+                path
+            } else {
+                carto.cheapest_path(&points[0], &points[1], &cost, deadline)?
+            };
+            // The speeds only change the costs of the searches, so the durations are fixed after
+            Ok(path.map(|mut path| {
+                carto.apply_speeds(&points[0], &points[1], &mut path, speeds);
+                path
+            }))
+        };
+        // The search is measured when it runs, so the cache is skipped
+        let graph_path = if options.debug == Some(true) {
+            search()?
+        } else {
+            cache.get_or_insert_with(key, search)?
+        };
+        search_stats.push(leg_stats);
+        let graph_path = graph_path.ok_or(ApiError::NoRoute { from: i, to: i + 1 })?;
         distance += graph_path.distance;
-        route_points.extend(graph_path.points);
+        duration += graph_path.duration;
+        arrivals.push(duration);
+        route_points.extend(graph_path.points.iter().copied());
+        route_edges.extend(graph_path.edges.iter().copied());
+        legs.push(graph_path);
+    }
+    // Each leg starts on the edge where the previous one ends, that only crosses a border once
+    route_edges.dedup();
+    let route_path = GraphPath::new(distance, duration, route_points).with_edges(route_edges);
+    let border_crossings = carto.border_crossings(&route_path);
+
+    // The cumulative distances are given for each point of the full geometry, so they are measured
+    // before any simplification
+    let annotation = if options.annotations == Some(true) {
+        let mut annotation = AnnotationResponse::default();
+        for leg in &legs {
+            for &edge in &leg.edges {
+                annotation.push(&carto.graph[edge]);
+            }
+        }
+        annotation.set_cumulative_distances(&route_path);
+        Some(annotation)
+    } else {
+        None
+    };
+
+    let simplify = |path: GraphPath| match options.overview {
+        Some(Overview::Simplified) => path.overview(),
+        _ => path,
+    };
+    let geometry = |path: GraphPath| match (options.overview, options.crs) {
+        (Some(Overview::False), _) => None,
+        (_, Some(crs)) => Some(GeometryResponse::line_string(
+            crs.projection(&path.points),
+            &path.points,
+        )),
+        (_, None) => Some(GeometryResponse::Polyline(path.polyline)),
+    };
+    let route_path = simplify(route_path);
+
+    // Describe each leg as turn-by-turn instructions, from the edges it traverses. As in OSRM, each
+    // intermediate waypoint is both an arrival and a departure
+    let mut summaries = vec![String::new(); legs.len()];
+    let steps = if options.steps == Some(true) {
+        let mut steps: Vec<StepResponse> = Vec::new();
+        for ((points, leg), summary) in waypoints.windows(2).zip(&legs).zip(&mut summaries) {
+            let leg_steps = carto.path_steps(&points[0], &points[1], leg);
+            steps.extend(leg_steps.iter().map(StepResponse::from));
+            *summary = leg_summary(
+                &carto.road_levels,
+                carto.road_names(),
+                leg.edges.iter().map(|&edge| &carto.graph[edge]),
+            );
+        }
+        Some(steps)
+    } else {
+        None
+    };
+
+    if options.format == Some(RouteFormat::Gpx) {
+        let waypoints: Vec<_> = waypoints
+            .iter()
+            .map(|waypoint| waypoint.projected)
+            .collect();
+        return Ok(RouteBody::Gpx(route_path.to_gpx(&waypoints, None)));
     }
-    let route_path = GraphPath::new(distance, route_points);
 
-    web::Json(RouteResponse {
+    Ok(RouteBody::Json(RouteResponse {
         waypoints: waypoints
             .iter()
-            .map(|waypoint| WaypointResponse {
-                distance: waypoint.projected.haversine_distance(&waypoint.original),
-                location: [
-                    waypoint.projected.lon.as_degrees(),
-                    waypoint.projected.lat.as_degrees(),
-                ],
+            .zip(arrivals)
+            .map(|(waypoint, arrival)| {
+                WaypointResponse::new(
+                    waypoint,
+                    &carto.approach(waypoint),
+                    arrival,
+                    options.snap_warning.unwrap_or(1000.),
+                )
             })
             .collect(),
         routes: vec![RouteItemResponse {
             distance: route_path.distance,
-            geometry: route_path.polyline,
+            duration: round_duration(route_path.duration),
+            border_crossings,
+            geometry: geometry(route_path),
+            steps,
+            annotation,
+            legs: legs
+                .into_iter()
+                .zip(summaries)
+                .zip(search_stats)
+                .map(|((leg, summary), debug)| LegResponse {
+                    distance: leg.distance,
+                    duration: round_duration(leg.duration),
+                    summary,
+                    geometry: geometry(simplify(leg)),
+                    debug,
+                })
+                .collect(),
         }],
-    })
+    }))
 }
 
 #[actix_rt::main]
-pub async fn run_api<P: AsRef<Path> + 'static>(input: P) -> std::io::Result<()> {
+#[allow(clippy::too_many_arguments)]
+pub async fn run_api<P: AsRef<Path> + 'static>(
+    input: P,
+    forbid_synthetic_edges: bool,
+    max_heavy_queries: usize,
+    cache_size: usize,
+    limits: ApiLimits,
+    experiments_file: Option<PathBuf>,
+    hub_table_file: Option<PathBuf>,
+    speed_profiles_file: Option<PathBuf>,
+    closures_file: Option<PathBuf>,
+    admin_token: Option<String>,
+    profile_load: bool,
+    index_config: IndexConfig,
+) -> std::io::Result<()> {
+    let experiments: Experiments = match experiments_file {
+        Some(path) => serde_json::from_reader(File::open(path)?)?,
+        None => HashMap::new(),
+    };
+    if !experiments.is_empty() {
+        let mut names: Vec<_> = experiments.keys().collect();
+        names.sort();
+        tracing::info!("Loaded experiments {:?}", names);
+    }
+
+    let speed_profiles: SpeedProfiles = match speed_profiles_file {
+        Some(path) => fs::read_to_string(path)?
+            .parse()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+        None => SpeedProfiles::default(),
+    };
+
+    let closures: Closures = match closures_file {
+        Some(path) => fs::read_to_string(path)?
+            .parse()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+        None => Closures::default(),
+    };
+
+    let hub_table = match hub_table_file {
+        Some(path) => {
+            let hubs = precompute::load_hub_table(input.as_ref(), path.as_path())?;
+            tracing::info!("Loaded hub table with {} points", hubs.hubs().len());
+            Some(hubs)
+        }
+        None => None,
+    };
+
     // Create a single instance of the cartography and wrap in an Data so that the threads
-    // created by HttpServer::new can all have read access to it
-    println!("will open carto");
-    let carto = web::Data::new(Cartograph::open(input)?);
-    println!("opened carto");
-    HttpServer::new(move || App::new().app_data(carto.clone()).service(route))
-        .bind("127.0.0.1:8000")?
-        .run()
-        .await
+    // created by HttpServer::new can all send queries to it
+    let (mut carto, report) =
+        Cartograph::open_with_config(&input, &index_config, |stage| match stage {
+            LoadProgress::Decoded {
+                bytes,
+                total_bytes: Some(total_bytes),
+            } => tracing::info!("Loading cartography: {}%", bytes * 100 / total_bytes.max(1)),
+            LoadProgress::ComponentsLabeled => tracing::info!("Loaded cartography"),
+            _ => {}
+        })?;
+    if profile_load {
+        tracing::info!("{}", report);
+    }
+    carto.allow_synthetic = !forbid_synthetic_edges;
+    let closures = closures.resolve(&carto);
+    if !closures.is_empty() {
+        tracing::info!("Loaded closures of {} edges", closures.edges.len());
+    }
+    let initial_status = web::Data::new(initial_status(
+        input.as_ref(),
+        &carto,
+        &experiments,
+        &hub_table,
+    )?);
+    let service = web::Data::new(CartographService::new(carto, max_heavy_queries));
+    let cache = web::Data::new(RouteCache::new(cache_size));
+    let max_snap_body = limits.max_snap_body();
+    let rate_limiter = match limits.rate_limit {
+        Some(limit) => Some(web::Data::new(RateLimiter::new(limit)?)),
+        None => None,
+    };
+    let limits = web::Data::new(limits);
+    let experiments = web::Data::new(experiments);
+    let hub_table = web::Data::new(hub_table);
+    let speed_profiles = web::Data::new(speed_profiles);
+    let closures = web::Data::new(RwLock::new(closures));
+    let admin_token = web::Data::new(AdminToken::new(admin_token));
+    let speeds: web::Data<SharedSpeeds> = web::Data::new(RwLock::new(SpeedOverrides::default()));
+    let avoid_zones: web::Data<SharedAvoidZones> = web::Data::new(RwLock::new(HashMap::new()));
+    HttpServer::new(move || {
+        let rate_limiter = rate_limiter.clone();
+        // Report the invalid requests in the same format as the other errors
+        App::new()
+            .wrap_fn(move |request, service| {
+                // Everything logged while answering, including by the queries, is in the span
+                let span = tracing::info_span!(
+                    "request",
+                    id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed),
+                    method = %request.method(),
+                    path = %request.path(),
+                );
+                let start = Instant::now();
+                // Requests without a peer address, like from a Unix socket, are not limited
+                let client = request.peer_addr().map(|addr| addr.ip());
+                let limited = match (&rate_limiter, client) {
+                    (Some(limiter), Some(client)) => limiter.check(client).err(),
+                    _ => None,
+                };
+                let call = match limited {
+                    None => Ok(span.in_scope(|| service.call(request))),
+                    Some(retry_after) => {
+                        Err(request.error_response(ApiError::TooManyRequests { retry_after }))
+                    }
+                };
+                async move {
+                    let response = match call {
+                        Ok(call) => call.await,
+                        Err(response) => Ok(response),
+                    };
+                    if let Ok(response) = &response {
+                        tracing::info!(
+                            status = response.status().as_u16(),
+                            elapsed_ms = start.elapsed().as_secs_f64() * 1000.,
+                            "Answered"
+                        );
+                    }
+                    response
+                }
+                .instrument(span)
+            })
+            .app_data(service.clone())
+            .app_data(cache.clone())
+            .app_data(limits.clone())
+            .app_data(experiments.clone())
+            .app_data(hub_table.clone())
+            .app_data(speed_profiles.clone())
+            .app_data(closures.clone())
+            .app_data(speeds.clone())
+            .app_data(avoid_zones.clone())
+            .app_data(admin_token.clone())
+            .app_data(initial_status.clone())
+            .app_data(
+                web::PathConfig::default().error_handler(|error, _| ApiError::from(error).into()),
+            )
+            .app_data(
+                web::QueryConfig::default().error_handler(|error, _| ApiError::from(error).into()),
+            )
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(max_snap_body)
+                    .error_handler(|error, _| ApiError::from(error).into()),
+            )
+            .service(route)
+            .service(reroute)
+            .service(table)
+            .service(round_trip)
+            .service(snap)
+            .service(update_closures)
+            .service(update_speeds)
+            .service(update_avoid_zones)
+            .service(cache_stats)
+            .service(road_levels)
+            .service(status)
+    })
+    .bind("127.0.0.1:8000")?
+    .run()
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn leg_query() {
+        let query = |json: &str| {
+            let options: RouteOptions = serde_json::from_str(json).unwrap();
+            super::leg_query(&options, 0, 0.)
+        };
+
+        // The randomized legs are only shared with the same penalties and vehicle
+        let randomized = query(r#"{"seed": 1}"#);
+        assert_eq!(randomized, query(r#"{"seed": 1}"#));
+        assert_ne!(randomized, query(r#"{"seed": 1, "height": 4.2}"#));
+        assert_ne!(
+            randomized,
+            query(r#"{"seed": 1, "exclude_access": "pedestrian"}"#)
+        );
+        assert_ne!(randomized, query(r#"{"seed": 2}"#));
+        assert_ne!(
+            query(r#"{"height": 4.2}"#),
+            query(r#"{"seed": 1, "height": 4.2}"#)
+        );
+    }
 }
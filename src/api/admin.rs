@@ -0,0 +1,73 @@
+//! Protect the requests that change the state of the server, like the closures, the speeds and the
+//! avoided zones, so that a public deployment can not be disrupted by its clients
+
+use super::data_types::ApiError;
+use actix_web::http::header;
+use actix_web::HttpRequest;
+
+/// The token that the clients must send in the header `Authorization: Bearer <token>` to change the
+/// state of the server. Without one, these requests are disabled
+pub struct AdminToken(Option<String>);
+
+impl AdminToken {
+    /// An empty token is the same as none, since it would let everyone in
+    pub fn new(token: Option<String>) -> Self {
+        AdminToken(token.filter(|token| !token.is_empty()))
+    }
+
+    /// Fail with `Disabled` when there is no token, or `Unauthorized` when the request does not
+    /// have it
+    pub fn check(&self, request: &HttpRequest) -> Result<(), ApiError> {
+        let token = self.0.as_ref().ok_or(ApiError::Disabled)?;
+        let given = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ApiError::Unauthorized)?;
+        if constant_time_eq(given.trim().as_bytes(), token.as_bytes()) {
+            Ok(())
+        } else {
+            Err(ApiError::Unauthorized)
+        }
+    }
+}
+
+/// Compare two strings in a time that does not depend on where they differ, so that the token can
+/// not be guessed byte by byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn check() {
+        let request = |authorization: Option<&str>| {
+            match authorization {
+                Some(value) => TestRequest::with_header(header::AUTHORIZATION, value),
+                None => TestRequest::default(),
+            }
+            .to_http_request()
+        };
+        let admin = AdminToken::new(Some("secret".to_string()));
+        assert!(admin.check(&request(Some("Bearer secret"))).is_ok());
+        for authorization in &[None, Some("Bearer other"), Some("secret"), Some("Bearer ")] {
+            assert!(matches!(
+                admin.check(&request(*authorization)),
+                Err(ApiError::Unauthorized)
+            ));
+        }
+
+        // Disabled by default, and with an empty token
+        for disabled in &[AdminToken::new(None), AdminToken::new(Some(String::new()))] {
+            assert!(matches!(
+                disabled.check(&request(Some("Bearer "))),
+                Err(ApiError::Disabled)
+            ));
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Remember the recently computed paths, since tile-based frontends tend to repeat the same requests
+
+use ptolemy::{AccessClass, GraphPath, ProjectedPoint, Smoothness, Surface, Timeout};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// A map that keeps at most `capacity` entries, dropping the least recently used ones
+pub struct LruCache<K, V> {
+    capacity: usize,
+    /// The values and the last time they were used
+    entries: HashMap<K, (V, u64)>,
+    /// The keys by the last time they were used, the oldest first
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            recency: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Get a value and mark it as the most recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let (value, used) = self.entries.get_mut(key)?;
+        self.recency.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.recency.insert(self.clock, key.clone());
+        Some(value)
+    }
+
+    /// Insert a value, dropping the least recently used one if the cache is full
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some((_, used)) = self.entries.get(&key) {
+            self.recency.remove(used);
+        } else if self.entries.len() == self.capacity {
+            let oldest = *self.recency.keys().next().unwrap();
+            let oldest_key = self.recency.remove(&oldest).unwrap();
+            self.entries.remove(&oldest_key);
+        }
+        self.clock += 1;
+        self.recency.insert(self.clock, key.clone());
+        self.entries.insert(key, (value, self.clock));
+    }
+}
+
+/// How a leg of a route is calculated, with the floats stored as their bits so that they can be
+/// hashed
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LegQuery {
+    Shortest,
+    /// Another query, `Shortest` or `Penalized`, whose costs are perturbed
+    Randomized {
+        seed: u64,
+        max_stretch: u64,
+        query: Box<LegQuery>,
+    },
+    Penalized {
+        surfaces: Vec<(Surface, u64)>,
+        smoothness: Option<(Smoothness, u64)>,
+        access: Vec<(AccessClass, u64)>,
+        forbidden_access: Vec<AccessClass>,
+        /// The extra cost of crossing a barrier, in meters
+        barrier: u32,
+        /// The weight, height and width of the vehicle
+        vehicle: Option<(u32, u32, u32)>,
+    },
+    /// The extra edges, with their endpoints as (lat, lon) in micro degrees
+    Overlay {
+        edges: Vec<((i32, i32), (i32, i32))>,
+    },
+    /// The departure time of the leg, in seconds from midnight
+    Timed {
+        depart_at: u64,
+    },
+    /// The name of the experiment, that can not change while the API runs
+    Experiment {
+        name: String,
+    },
+    /// Another query that also avoids the edges closed when it was made, by index
+    Closed {
+        edges: Vec<u32>,
+        query: Box<LegQuery>,
+    },
+    /// Another query that also uses the speeds overridden when it was made, by edge index
+    Speeds {
+        speeds: Vec<(u32, u64)>,
+        query: Box<LegQuery>,
+    },
+}
+
+/// Identify a leg of a route by its snapped endpoints and how it is calculated
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LegKey {
+    from: (u32, u32),
+    to: (u32, u32),
+    query: LegQuery,
+}
+
+impl LegKey {
+    pub fn new(from: &ProjectedPoint, to: &ProjectedPoint, query: LegQuery) -> Self {
+        let endpoint =
+            |point: &ProjectedPoint| (point.edge.index() as u32, point.edge_pos.to_bits());
+        LegKey {
+            from: endpoint(from),
+            to: endpoint(to),
+            query,
+        }
+    }
+}
+
+/// Counters of the route cache
+#[derive(Serialize, Debug, PartialEq)]
+pub struct CacheStats {
+    pub capacity: usize,
+    pub size: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// The paths of the recently calculated legs, shared by all the threads. `None` means that the
+/// destination is not reachable
+pub struct RouteCache {
+    capacity: usize,
+    paths: Mutex<LruCache<LegKey, Option<GraphPath>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl RouteCache {
+    /// Create a cache with up to `capacity` legs. With zero, nothing is cached
+    pub fn new(capacity: usize) -> Self {
+        RouteCache {
+            capacity,
+            paths: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the cached path of a leg or calculate it. The lock is not held during the calculation,
+    /// so the same leg may be calculated twice by concurrent requests. A calculation that timed out
+    /// is not cached
+    pub fn get_or_insert_with(
+        &self,
+        key: LegKey,
+        calculate: impl FnOnce() -> Result<Option<GraphPath>, Timeout>,
+    ) -> Result<Option<GraphPath>, Timeout> {
+        if self.capacity == 0 {
+            return calculate();
+        }
+        if let Some(path) = self.paths.lock().unwrap().get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(path.clone());
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let path = calculate()?;
+        self.paths.lock().unwrap().insert(key, path.clone());
+        Ok(path)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            capacity: self.capacity,
+            size: self.paths.lock().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lru_cache() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        assert_eq!(cache.get(&1), Some(&"a"));
+        // 2 is now the least recently used
+        cache.insert(3, "c");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.get(&3), Some(&"c"));
+        cache.insert(3, "d");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&3), Some(&"d"));
+
+        let mut disabled = LruCache::new(0);
+        disabled.insert(1, "a");
+        assert_eq!(disabled.get(&1), None);
+    }
+
+    #[test]
+    fn route_cache() {
+        let cache = RouteCache::new(10);
+        let point = ProjectedPoint {
+            original: ptolemy::GeoPoint::from_degrees(42.5, 1.5),
+            projected: ptolemy::GeoPoint::from_degrees(42.5, 1.5),
+            edge: petgraph::graph::EdgeIndex::new(17),
+            edge_pos: 0.5,
+        };
+        let key = LegKey::new(&point, &point, LegQuery::Shortest);
+        let error = cache.get_or_insert_with(key.clone(), || Err(Timeout));
+        assert_eq!(error.unwrap_err(), Timeout);
+        assert_eq!(cache.stats().size, 0);
+        assert!(cache
+            .get_or_insert_with(key.clone(), || Ok(None))
+            .unwrap()
+            .is_none());
+        let path = cache.get_or_insert_with(key, || panic!("The leg should be cached"));
+        assert!(path.unwrap().is_none());
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                capacity: 10,
+                size: 1,
+                hits: 1,
+                misses: 2,
+            }
+        );
+    }
+}
@@ -1,9 +1,22 @@
-use crate::utils::GeoPoint;
-use failure::Fail;
+use super::rate_limit::RateLimit;
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use ptolemy::crs::{Crs, ParseCrsError, Projection};
+use ptolemy::GeoPoint;
+use ptolemy::{
+    round_distance, round_duration, AccessClass, AccessPenalties, Approach, AvoidZone, Deadline,
+    EdgeInfo, ExtraEdge, GraphPath, Maneuver, MemoryUsage, Metadata, ProjectedPoint,
+    RoadLevelStyle, RoadNames, SearchStats, Side, Smoothness, SpeedTarget, SpeedUpdate, Step,
+    Surface, SurfacePenalties, Timeout, Vehicle,
+};
 use serde::{de, Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::num::ParseFloatError;
 use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
 
 /// Represent a list of points and can be parsed or expressed in the OSRM format:
 /// {longitude},{latitude};{longitude},{latitude}[;{longitude},{latitude} ...]
@@ -11,32 +24,24 @@ use std::str::FromStr;
 pub struct Coordinates(pub Vec<GeoPoint>);
 
 /// Errors that can happen when parsin Coordinates from a String
-#[derive(Debug, Fail, PartialEq)]
+#[derive(Debug, Error, PartialEq)]
 pub enum ParseCoordinatesError {
-    #[fail(
-        display = "Expected at least {} lon_lat pairs, got {}. Use ';' to separate pairs",
-        expected, got
-    )]
+    #[error("Expected at least {expected} lon_lat pairs, got {got}. Use ';' to separate pairs")]
     NotEnoughLonLatPairs { got: usize, expected: usize },
-    #[fail(
-        display = "Missing latitude component in pair {}. Use ',' to separate longitude and latitude",
-        pair
+    #[error(
+        "Missing latitude component in pair {pair}. Use ',' to separate longitude and latitude"
     )]
     MissingLat { pair: String },
-    #[fail(
-        display = "Too many values to parse in pair {}. Use ',' to separate longitude and latitude",
-        pair
-    )]
+    #[error("Too many values to parse in pair {pair}. Use ',' to separate longitude and latitude")]
     ExtraValue { pair: String },
-    #[fail(display = "Could not parse pair {}: {}", pair, source)]
+    #[error("Could not parse pair {pair}: {source}")]
     InvalidFloat {
         pair: String,
-        #[cause]
+        #[source]
         source: ParseFloatError,
     },
-    #[fail(
-        display = "Value {} in pair {} is out of range, it should in [{}, {}]. Make sure to use the order longitude,latitude",
-        got, pair, expected_min, expected_max
+    #[error(
+        "Value {got} in pair {pair} is out of range, it should in [{expected_min}, {expected_max}]. Make sure to use the order longitude,latitude"
     )]
     InvalidRange {
         pair: String,
@@ -46,58 +51,237 @@ pub enum ParseCoordinatesError {
     },
 }
 
-// String -> Coordinates
-impl FromStr for Coordinates {
-    type Err = ParseCoordinatesError;
+/// Errors in the values of the query parameters
+#[derive(Debug, Error, PartialEq)]
+pub enum ParseOptionError {
+    #[error("Unknown surface {0}. Expected one of paved, cobblestone, gravel, unpaved or unknown")]
+    UnknownSurface(String),
+    #[error("Unknown smoothness {0}. Expected one of excellent, good, intermediate, bad, very_bad, horrible, very_horrible or impassable")]
+    UnknownSmoothness(String),
+    #[error(
+        "Unknown access class {0}. Expected one of normal, living_street, pedestrian, motorroad or steps"
+    )]
+    UnknownAccess(String),
+    #[error("Value {got} of {name} is out of range, it should be at least {expected_min}")]
+    InvalidRange {
+        name: &'static str,
+        got: f64,
+        expected_min: f64,
+    },
+    #[error("Expected 4 values in the extra edge {0}, like lon,lat,lon,lat")]
+    ExtraEdgeValues(String),
+    #[error("Invalid extra edge {edge}: {source}")]
+    InvalidExtraEdge {
+        edge: String,
+        #[source]
+        source: ParseCoordinatesError,
+    },
+    #[error(transparent)]
+    UnknownCrs(#[from] ParseCrsError),
+    #[error("Unknown experiment {0}")]
+    UnknownExperiment(String),
+    #[error("Unknown avoid zone {0}. Create it with PUT /avoid_zones")]
+    UnknownAvoidZone(String),
+    #[error("Invalid time {0}, expected HH:MM like 08:30")]
+    InvalidTime(String),
+    #[error("Option {0} can not be combined with {1}")]
+    Incompatible(&'static str, &'static str),
+    #[error("Invalid geometry {0}, expected a polyline with precision 5")]
+    InvalidGeometry(String),
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        /// Parse a float and also check its bounds
-        fn parse_and_check_float(
-            pair: &str,
-            v: &str,
-            min: f64,
-            max: f64,
-        ) -> Result<f64, ParseCoordinatesError> {
-            let v: f64 = v
-                .parse()
-                .map_err(|source| ParseCoordinatesError::InvalidFloat {
-                    pair: pair.to_owned(),
-                    source,
-                })?;
-
-            if v < min || v > max {
-                return Err(ParseCoordinatesError::InvalidRange {
-                    pair: pair.to_owned(),
-                    got: v,
-                    expected_min: min,
-                    expected_max: max,
-                });
-            }
+/// Errors returned by the API, as OSRM-like JSON responses
+#[derive(Debug, Error)]
+pub enum ApiError {
+    /// The coordinates in the path could not be parsed
+    #[error("{0}")]
+    InvalidUrl(String),
+    /// The query string could not be parsed
+    #[error("{0}")]
+    InvalidQuery(String),
+    #[error(transparent)]
+    InvalidOptions(#[from] ParseOptionError),
+    /// The body of a POST request could not be parsed
+    #[error("{0}")]
+    InvalidBody(String),
+    /// The request exceeds one of the limits of the server
+    #[error("Too many {what}: got {got}, but the maximum is {max}")]
+    TooBig {
+        what: &'static str,
+        got: usize,
+        max: usize,
+    },
+    #[error("Could not find a route between waypoints {from} and {to}")]
+    NoRoute { from: usize, to: usize },
+    /// No loop could be found from the start of a round trip, for example in a dead end of the map
+    #[error("Could not find a loop of about {distance:.0} meters from the start")]
+    NoRoundTrip { distance: f64 },
+    /// A waypoint is farther from the roads than `RouteOptions::max_snap_distance`
+    #[error("Could not find a road within {max} meters of waypoint {waypoint}, the closest one is {distance:.0} meters away")]
+    NoSegment {
+        waypoint: usize,
+        distance: f64,
+        max: f64,
+    },
+    /// The search took longer than `ApiLimits::max_query_time`
+    #[error(transparent)]
+    Timeout(#[from] Timeout),
+    /// The client made more requests than `ApiLimits::rate_limit` allows
+    #[error("Too many requests, retry in {} seconds", retry_after_secs(*.retry_after))]
+    TooManyRequests { retry_after: Duration },
+    /// The request changes the state of the server, which was started without `--admin-token`
+    #[error("This request is disabled, start the server with --admin-token to enable it")]
+    Disabled,
+    /// The request changes the state of the server, but does not have the token of `--admin-token`
+    #[error("Missing or invalid token, send it in the header Authorization: Bearer <token>")]
+    Unauthorized,
+    #[error("The query failed: {0}")]
+    Internal(String),
+}
 
-            Ok(v)
+impl ApiError {
+    /// The value of the field `code` of the response
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidUrl(_) => "InvalidUrl",
+            ApiError::InvalidQuery(_) => "InvalidQuery",
+            ApiError::InvalidOptions(_) => "InvalidOptions",
+            ApiError::InvalidBody(_) => "InvalidBody",
+            ApiError::TooBig { .. } => "TooBig",
+            ApiError::NoRoute { .. } => "NoRoute",
+            ApiError::NoRoundTrip { .. } => "NoRoundTrip",
+            ApiError::NoSegment { .. } => "NoSegment",
+            ApiError::Timeout(_) => "Timeout",
+            ApiError::TooManyRequests { .. } => "TooManyRequests",
+            ApiError::Disabled => "Disabled",
+            ApiError::Unauthorized => "Unauthorized",
+            ApiError::Internal(_) => "InternalError",
         }
+    }
+}
 
-        let mut points: Vec<GeoPoint> = Vec::new();
+impl From<PathError> for ApiError {
+    fn from(error: PathError) -> Self {
+        match error {
+            PathError::Deserialize(error) => ApiError::InvalidUrl(error.to_string()),
+        }
+    }
+}
 
-        for pair in s.split(';') {
-            // Split pairs in ','
-            let lon_lat: Vec<&str> = pair.split(',').collect();
-            if lon_lat.len() < 2 {
-                return Err(ParseCoordinatesError::MissingLat {
-                    pair: pair.to_owned(),
-                });
-            } else if lon_lat.len() > 2 {
-                return Err(ParseCoordinatesError::ExtraValue {
-                    pair: pair.to_owned(),
-                });
+impl From<QueryPayloadError> for ApiError {
+    fn from(error: QueryPayloadError) -> Self {
+        match error {
+            QueryPayloadError::Deserialize(error) => ApiError::InvalidQuery(error.to_string()),
+        }
+    }
+}
+
+impl From<JsonPayloadError> for ApiError {
+    fn from(error: JsonPayloadError) -> Self {
+        ApiError::InvalidBody(error.to_string())
+    }
+}
+
+impl From<ParseCoordinatesError> for ApiError {
+    fn from(error: ParseCoordinatesError) -> Self {
+        ApiError::InvalidBody(error.to_string())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            // The request is valid, but the server is too busy or the query too hard right now
+            ApiError::Timeout(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Disabled => StatusCode::FORBIDDEN,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            // There is nothing wrong with the start, but no loop goes through it
+            ApiError::NoRoundTrip { .. } => StatusCode::NOT_FOUND,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            ApiError::TooManyRequests { retry_after } => {
+                response.header("Retry-After", retry_after_secs(*retry_after).to_string());
+            }
+            ApiError::Unauthorized => {
+                response.header("WWW-Authenticate", "Bearer");
             }
+            _ => {}
+        }
+        response.json(ErrorResponse {
+            code: self.code(),
+            message: self.to_string(),
+        })
+    }
+}
 
-            let lon = parse_and_check_float(pair, lon_lat[0], -180., 180.)?;
-            let lat = parse_and_check_float(pair, lon_lat[1], -90., 90.)?;
+/// The value of the `Retry-After` header, in whole seconds
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().max(1.) as u64
+}
 
-            points.push(GeoPoint::from_degrees(lat, lon));
+// String -> Coordinates
+/// Parse a `{longitude},{latitude}` pair, checking the ranges
+fn parse_pair(pair: &str) -> Result<GeoPoint, ParseCoordinatesError> {
+    /// Parse a float and also check its bounds
+    fn parse_and_check_float(
+        pair: &str,
+        v: &str,
+        min: f64,
+        max: f64,
+    ) -> Result<f64, ParseCoordinatesError> {
+        let v: f64 = v
+            .parse()
+            .map_err(|source| ParseCoordinatesError::InvalidFloat {
+                pair: pair.to_owned(),
+                source,
+            })?;
+
+        if v < min || v > max {
+            return Err(ParseCoordinatesError::InvalidRange {
+                pair: pair.to_owned(),
+                got: v,
+                expected_min: min,
+                expected_max: max,
+            });
         }
 
+        Ok(v)
+    }
+
+    // Split pairs in ','
+    let lon_lat: Vec<&str> = pair.split(',').collect();
+    if lon_lat.len() < 2 {
+        return Err(ParseCoordinatesError::MissingLat {
+            pair: pair.to_owned(),
+        });
+    } else if lon_lat.len() > 2 {
+        return Err(ParseCoordinatesError::ExtraValue {
+            pair: pair.to_owned(),
+        });
+    }
+
+    let lon = parse_and_check_float(pair, lon_lat[0], -180., 180.)?;
+    let lat = parse_and_check_float(pair, lon_lat[1], -90., 90.)?;
+
+    Ok(GeoPoint::from_degrees(lat, lon))
+}
+
+impl FromStr for Coordinates {
+    type Err = ParseCoordinatesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let points = s
+            .split(';')
+            .map(parse_pair)
+            .collect::<Result<Vec<_>, _>>()?;
+
         if points.len() < 2 {
             return Err(ParseCoordinatesError::NotEnoughLonLatPairs {
                 got: points.len(),
@@ -121,6 +305,28 @@ impl<'de> Deserialize<'de> for Coordinates {
     }
 }
 
+/// A single point in the OSRM format, {longitude},{latitude}
+#[derive(Clone, Debug, PartialEq)]
+pub struct Coordinate(pub GeoPoint);
+
+impl FromStr for Coordinate {
+    type Err = ParseCoordinatesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_pair(s).map(Coordinate)
+    }
+}
+
+impl<'de> Deserialize<'de> for Coordinate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        FromStr::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
 // Coordinates -> String
 impl fmt::Display for Coordinates {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -136,28 +342,1636 @@ impl fmt::Display for Coordinates {
     }
 }
 
+/// Optional query parameters accepted by the route endpoint
+#[derive(Deserialize, Debug)]
+pub struct RouteOptions {
+    pub format: Option<RouteFormat>,
+    /// How detailed the geometry is, `full` by default
+    pub overview: Option<Overview>,
+    /// When present, return a randomized near-optimal route, always the same for the same seed
+    pub seed: Option<u64>,
+    /// Maximum extra distance of a randomized route, as a fraction of the optimal one
+    pub max_stretch: Option<f64>,
+    /// Whether to return the turn-by-turn instructions
+    pub steps: Option<bool>,
+    /// Whether to return the attributes of each traversed edge
+    pub annotations: Option<bool>,
+    /// Comma-separated surfaces to avoid, like `cobblestone,unpaved`
+    #[serde(default, deserialize_with = "deserialize_surfaces")]
+    pub avoid_surfaces: Option<Vec<Surface>>,
+    /// Avoid the roads with this smoothness or worse, like `bad`
+    #[serde(default, deserialize_with = "deserialize_smoothness")]
+    pub avoid_smoothness: Option<Smoothness>,
+    /// How many times longer the avoided roads seem, 5 by default
+    pub surface_penalty: Option<f64>,
+    /// Comma-separated access classes to avoid, like `pedestrian,living_street`
+    #[serde(default, deserialize_with = "deserialize_access")]
+    pub avoid_access: Option<Vec<AccessClass>>,
+    /// How many times longer the roads of the avoided access classes seem, 10 by default
+    pub access_penalty: Option<f64>,
+    /// Comma-separated access classes to never use, except where the route starts or ends
+    #[serde(default, deserialize_with = "deserialize_access")]
+    pub exclude_access: Option<Vec<AccessClass>>,
+    /// How many meters longer crossing a barrier that vehicles may pass seems, like a gate
+    pub barrier_penalty: Option<u32>,
+    /// Semicolon-separated edges to add to the graph for this query, each one like
+    /// `lon,lat,lon,lat`. They can be traversed in both directions
+    #[serde(default, deserialize_with = "deserialize_extra_edges")]
+    pub extra_edges: Option<Vec<ExtraEdge>>,
+    /// Return the geometry as coordinates in this system, like `utm`, instead of a polyline
+    #[serde(default, deserialize_with = "deserialize_crs")]
+    pub crs: Option<Crs>,
+    /// The name of the experiment whose edge costs are used instead of the distances
+    pub experiment: Option<String>,
+    /// Weight of the vehicle in tonnes, to avoid the roads with a lower `maxweight`. Vehicles above
+    /// 3.5 tonnes also avoid the roads closed to heavy goods vehicles
+    pub weight: Option<f64>,
+    /// Height of the vehicle in meters, to avoid low bridges and tunnels
+    pub height: Option<f64>,
+    /// Width of the vehicle in meters, to avoid narrow roads
+    pub width: Option<f64>,
+    /// Find the fastest route when leaving at this time of day, like `08:30`, with the speed
+    /// profiles of the roads
+    #[serde(default, deserialize_with = "deserialize_time")]
+    pub depart_at: Option<f64>,
+    /// Whether to return the statistics of the search of each leg. It can not be combined with the
+    /// searches without a cost model: `seed`, `extra_edges` and `depart_at`
+    pub debug: Option<bool>,
+    /// Comma-separated names of the zones whose roads are never used, except where the route
+    /// starts or ends. The zone `borders` is the roads that cross a border
+    #[serde(default, deserialize_with = "deserialize_names")]
+    pub avoid: Option<Vec<String>>,
+    /// Warn about the waypoints snapped farther than this from the requested coordinates, in
+    /// meters, 1000 by default
+    pub snap_warning: Option<f64>,
+    /// Fail with `NoSegment` when a waypoint is snapped farther than this, in meters
+    pub max_snap_distance: Option<f64>,
+}
+
+impl RouteOptions {
+    /// Check the ranges of the numeric values
+    pub fn validate(&self) -> Result<(), ParseOptionError> {
+        let check = |name, value: Option<f64>, expected_min| match value {
+            Some(got) if got.is_nan() || got < expected_min => {
+                Err(ParseOptionError::InvalidRange {
+                    name,
+                    got,
+                    expected_min,
+                })
+            }
+            _ => Ok(()),
+        };
+        check("max_stretch", self.max_stretch, 0.)?;
+        check("surface_penalty", self.surface_penalty, 1.)?;
+        check("access_penalty", self.access_penalty, 1.)?;
+        check("weight", self.weight, 0.)?;
+        check("height", self.height, 0.)?;
+        check("width", self.width, 0.)?;
+        check("snap_warning", self.snap_warning, 0.)?;
+        check("max_snap_distance", self.max_snap_distance, 0.)?;
+
+        // GPX is always in latitude and longitude, and it is made of the geometry
+        if self.crs.is_some() && self.format == Some(RouteFormat::Gpx) {
+            return Err(ParseOptionError::Incompatible("crs", "format=gpx"));
+        }
+        if self.overview == Some(Overview::False) && self.format == Some(RouteFormat::Gpx) {
+            return Err(ParseOptionError::Incompatible(
+                "overview=false",
+                "format=gpx",
+            ));
+        }
+
+        // The extra edges are only supported by plain shortest paths
+        if self.extra_edges.is_some() {
+            let others = [
+                ("seed", self.seed.is_some()),
+                ("steps", self.steps == Some(true)),
+                ("annotations", self.annotations == Some(true)),
+                ("avoid_surfaces", self.avoid_surfaces.is_some()),
+                ("avoid_smoothness", self.avoid_smoothness.is_some()),
+                ("avoid_access", self.avoid_access.is_some()),
+                ("exclude_access", self.exclude_access.is_some()),
+                ("barrier_penalty", self.barrier_penalty.is_some()),
+                ("experiment", self.experiment.is_some()),
+                ("weight", self.weight.is_some()),
+                ("height", self.height.is_some()),
+                ("width", self.width.is_some()),
+            ];
+            if let Some(&(other, _)) = others.iter().find(|(_, used)| *used) {
+                return Err(ParseOptionError::Incompatible("extra_edges", other));
+            }
+        }
+
+        // The experiments replace the cost of the edges, like the penalties and randomization
+        if self.experiment.is_some() {
+            let others = [
+                ("seed", self.seed.is_some()),
+                ("avoid_surfaces", self.avoid_surfaces.is_some()),
+                ("avoid_smoothness", self.avoid_smoothness.is_some()),
+                ("avoid_access", self.avoid_access.is_some()),
+                ("exclude_access", self.exclude_access.is_some()),
+                ("barrier_penalty", self.barrier_penalty.is_some()),
+                ("weight", self.weight.is_some()),
+                ("height", self.height.is_some()),
+                ("width", self.width.is_some()),
+            ];
+            if let Some(&(other, _)) = others.iter().find(|(_, used)| *used) {
+                return Err(ParseOptionError::Incompatible("experiment", other));
+            }
+        }
+
+        // The fastest routes only use the speeds of the roads
+        if self.depart_at.is_some() {
+            let others = [
+                ("seed", self.seed.is_some()),
+                ("avoid_surfaces", self.avoid_surfaces.is_some()),
+                ("avoid_smoothness", self.avoid_smoothness.is_some()),
+                ("avoid_access", self.avoid_access.is_some()),
+                ("exclude_access", self.exclude_access.is_some()),
+                ("barrier_penalty", self.barrier_penalty.is_some()),
+                ("extra_edges", self.extra_edges.is_some()),
+                ("experiment", self.experiment.is_some()),
+                ("weight", self.weight.is_some()),
+                ("height", self.height.is_some()),
+                ("width", self.width.is_some()),
+            ];
+            if let Some(&(other, _)) = others.iter().find(|(_, used)| *used) {
+                return Err(ParseOptionError::Incompatible("depart_at", other));
+            }
+        }
+
+        // Only the searches with a cost model are measured
+        if self.debug == Some(true) {
+            let others = [
+                ("seed", self.seed.is_some()),
+                ("extra_edges", self.extra_edges.is_some()),
+                ("depart_at", self.depart_at.is_some()),
+            ];
+            if let Some(&(other, _)) = others.iter().find(|(_, used)| *used) {
+                return Err(ParseOptionError::Incompatible("debug", other));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the query needs more than one search per leg, so that it should not be allowed to
+    /// use all the threads
+    pub fn is_heavy(&self) -> bool {
+        self.seed.is_some()
+            || self.steps == Some(true)
+            || self.annotations == Some(true)
+            || self.surface_penalties().is_some()
+            || self.access_penalties().is_some()
+            || self.extra_edges.is_some()
+            || self.experiment.is_some()
+            || self.vehicle().is_some()
+            || self.depart_at.is_some()
+    }
+
+    /// The dimensions given by `weight`, `height` and `width`, if any
+    pub fn vehicle(&self) -> Option<Vehicle> {
+        if self.weight.is_none() && self.height.is_none() && self.width.is_none() {
+            return None;
+        }
+        Some(Vehicle {
+            weight: self.weight.unwrap_or(0.) as f32,
+            height: self.height.unwrap_or(0.) as f32,
+            width: self.width.unwrap_or(0.) as f32,
+        })
+    }
+
+    /// The penalties requested by `avoid_surfaces` and `avoid_smoothness`, if any
+    pub fn surface_penalties(&self) -> Option<SurfacePenalties> {
+        let penalty = self.surface_penalty.unwrap_or(5.);
+        let surfaces: Vec<(Surface, f64)> = self
+            .avoid_surfaces
+            .iter()
+            .flatten()
+            .map(|&surface| (surface, penalty))
+            .collect();
+        let smoothness = self
+            .avoid_smoothness
+            .map(|smoothness| (smoothness, penalty));
+        if surfaces.is_empty() && smoothness.is_none() {
+            None
+        } else {
+            Some(SurfacePenalties {
+                surfaces,
+                smoothness,
+            })
+        }
+    }
+
+    /// The penalties requested by `avoid_access`, `exclude_access` and `barrier_penalty`, if any
+    pub fn access_penalties(&self) -> Option<AccessPenalties> {
+        let penalty = self.access_penalty.unwrap_or(10.);
+        let penalized: Vec<(AccessClass, f64)> = self
+            .avoid_access
+            .iter()
+            .flatten()
+            .map(|&access| (access, penalty))
+            .collect();
+        let forbidden: Vec<AccessClass> = self.exclude_access.iter().flatten().copied().collect();
+        if penalized.is_empty() && forbidden.is_empty() && self.barrier_penalty.is_none() {
+            None
+        } else {
+            Some(AccessPenalties {
+                penalized,
+                forbidden,
+                barrier: self.barrier_penalty.unwrap_or(0),
+            })
+        }
+    }
+}
+
+/// The maximum size of the requests, so that a single one can not keep the server busy
+#[derive(Clone, Copy, Debug)]
+pub struct ApiLimits {
+    /// Maximum number of coordinates of a route
+    pub max_waypoints: usize,
+    /// Maximum number of edges in `extra_edges`
+    pub max_extra_edges: usize,
+    /// Maximum number of points snapped by a single request
+    pub max_snap_points: usize,
+    /// Maximum distance of a round trip, in meters
+    pub max_round_trip_distance: usize,
+    /// Maximum time spent searching the paths of a route or table request
+    pub max_query_time: Option<Duration>,
+    /// Maximum rate of requests of each client
+    pub rate_limit: Option<RateLimit>,
+}
+
+impl Default for ApiLimits {
+    fn default() -> Self {
+        ApiLimits {
+            max_waypoints: 100,
+            max_extra_edges: 100,
+            max_snap_points: 10_000,
+            max_round_trip_distance: 100_000,
+            max_query_time: None,
+            rate_limit: None,
+        }
+    }
+}
+
+impl ApiLimits {
+    /// Check a route query against the limits
+    pub fn check_route(
+        &self,
+        coords: &Coordinates,
+        options: &RouteOptions,
+    ) -> Result<(), ApiError> {
+        let check = |what, got, max| {
+            if got > max {
+                Err(ApiError::TooBig { what, got, max })
+            } else {
+                Ok(())
+            }
+        };
+        check("waypoints", coords.0.len(), self.max_waypoints)?;
+        check(
+            "extra edges",
+            options.extra_edges.as_ref().map_or(0, Vec::len),
+            self.max_extra_edges,
+        )
+    }
+
+    /// Check a table query against the limits. Its points count as waypoints
+    pub fn check_table(&self, coords: &Coordinates) -> Result<(), ApiError> {
+        if coords.0.len() > self.max_waypoints {
+            return Err(ApiError::TooBig {
+                what: "waypoints",
+                got: coords.0.len(),
+                max: self.max_waypoints,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check a snap query against the limits
+    pub fn check_snap(&self, request: &SnapRequest) -> Result<(), ApiError> {
+        if request.coordinates.len() > self.max_snap_points {
+            return Err(ApiError::TooBig {
+                what: "points to snap",
+                got: request.coordinates.len(),
+                max: self.max_snap_points,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check a round trip query against the limits
+    pub fn check_round_trip(&self, options: &RoundTripOptions) -> Result<(), ApiError> {
+        let got = options.distance.round() as usize;
+        if got > self.max_round_trip_distance {
+            return Err(ApiError::TooBig {
+                what: "meters in the round trip",
+                got,
+                max: self.max_round_trip_distance,
+            });
+        }
+        Ok(())
+    }
+
+    /// The deadline of a query that starts now
+    pub fn deadline(&self) -> Deadline {
+        match self.max_query_time {
+            Some(duration) => Deadline::after(duration),
+            None => Deadline::never(),
+        }
+    }
+
+    /// The maximum size in bytes of the body of a snap query, that is generous enough for the
+    /// allowed number of points
+    pub fn max_snap_body(&self) -> usize {
+        4096 + 100 * self.max_snap_points
+    }
+}
+
+/// Parse a comma-separated list of surfaces
+fn deserialize_surfaces<'de, D>(deserializer: D) -> Result<Option<Vec<Surface>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(',')
+        .map(|value| {
+            let value = value.trim();
+            match Surface::from_tag(value) {
+                Surface::Unknown if value != "unknown" => {
+                    Err(ParseOptionError::UnknownSurface(value.to_owned()))
+                }
+                surface => Ok(surface),
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+        .map_err(de::Error::custom)
+}
+
+/// Parse a comma-separated list of access classes
+fn deserialize_access<'de, D>(deserializer: D) -> Result<Option<Vec<AccessClass>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(',')
+        .map(|value| {
+            let value = value.trim();
+            value
+                .parse()
+                .map_err(|_| ParseOptionError::UnknownAccess(value.to_owned()))
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+        .map_err(de::Error::custom)
+}
+
+/// Parse a comma-separated list of names
+fn deserialize_names<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    Ok(Some(
+        s.split(',').map(|name| name.trim().to_owned()).collect(),
+    ))
+}
+
+/// Parse a smoothness
+fn deserialize_smoothness<'de, D>(deserializer: D) -> Result<Option<Smoothness>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    match Smoothness::from_tag(s.trim()) {
+        Smoothness::Unknown => Err(de::Error::custom(ParseOptionError::UnknownSmoothness(
+            s.trim().to_owned(),
+        ))),
+        smoothness => Ok(Some(smoothness)),
+    }
+}
+
+/// Parse a coordinate reference system
+fn deserialize_crs<'de, D>(deserializer: D) -> Result<Option<Crs>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse()
+        .map(Some)
+        .map_err(|error| de::Error::custom(ParseOptionError::from(error)))
+}
+
+/// Parse a time of day, like `08:30`, as seconds from midnight
+fn deserialize_time<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    let error = || de::Error::custom(ParseOptionError::InvalidTime(s.trim().to_owned()));
+    let mut parts = s.trim().split(':');
+    let (hours, minutes) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(hours), Some(minutes), None) => (hours.parse::<u32>(), minutes.parse::<u32>()),
+        _ => return Err(error()),
+    };
+    match (hours, minutes) {
+        (Ok(hours), Ok(minutes)) if hours < 24 && minutes < 60 => {
+            Ok(Some((hours * 3600 + minutes * 60) as f64))
+        }
+        _ => Err(error()),
+    }
+}
+
+/// Parse a semicolon-separated list of edges, each one like `lon,lat,lon,lat`
+fn deserialize_extra_edges<'de, D>(deserializer: D) -> Result<Option<Vec<ExtraEdge>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(';')
+        .map(|edge| {
+            let values: Vec<&str> = edge.split(',').collect();
+            if values.len() != 4 {
+                return Err(ParseOptionError::ExtraEdgeValues(edge.to_owned()));
+            }
+            let pairs = format!("{},{};{},{}", values[0], values[1], values[2], values[3]);
+            let coordinates: Coordinates =
+                pairs
+                    .parse()
+                    .map_err(|source| ParseOptionError::InvalidExtraEdge {
+                        edge: edge.to_owned(),
+                        source,
+                    })?;
+            Ok(ExtraEdge {
+                from: coordinates.0[0],
+                to: coordinates.0[1],
+                bidirectional: true,
+            })
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+        .map_err(de::Error::custom)
+}
+
+/// Query parameters of the round trip endpoint
+#[derive(Deserialize, Debug)]
+pub struct RoundTripOptions {
+    /// The wanted length of the loop, in meters
+    pub distance: f64,
+    /// Different seeds go in different directions, 0 by default
+    pub seed: Option<u64>,
+    /// How detailed the geometry is, `full` by default
+    pub overview: Option<Overview>,
+}
+
+impl RoundTripOptions {
+    /// Check the range of the distance
+    pub fn validate(&self) -> Result<(), ParseOptionError> {
+        if self.distance.is_nan() || self.distance < 1. {
+            return Err(ParseOptionError::InvalidRange {
+                name: "distance",
+                got: self.distance,
+                expected_min: 1.,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Query parameters of the reroute endpoint, besides the ones of the route endpoint
+#[derive(Deserialize, Debug)]
+pub struct RerouteOptions {
+    /// The route being followed, as a polyline with precision 5
+    pub geometry: Option<String>,
+    /// How far from the route the position can be, in meters, before it is off route. 30 by default
+    pub max_deviation: Option<f64>,
+}
+
+impl RerouteOptions {
+    /// Check the range of the deviation
+    pub fn validate(&self) -> Result<(), ParseOptionError> {
+        if let Some(max_deviation) = self.max_deviation {
+            if max_deviation.is_nan() || max_deviation < 0. {
+                return Err(ParseOptionError::InvalidRange {
+                    name: "max_deviation",
+                    got: max_deviation,
+                    expected_min: 0.,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The route being followed, if given
+    pub fn geometry(&self) -> Result<Option<GraphPath>, ParseOptionError> {
+        let geometry = match &self.geometry {
+            Some(geometry) => geometry,
+            None => return Ok(None),
+        };
+        let line = polyline::decode_polyline(geometry, 5)
+            .map_err(|_| ParseOptionError::InvalidGeometry(geometry.clone()))?;
+        let points = line
+            .0
+            .iter()
+            .map(|coord| GeoPoint::from_degrees(coord.y, coord.x))
+            .collect();
+        Ok(Some(GraphPath::new(0, 0., points)))
+    }
+}
+
+/// How the route endpoint should encode its response. Defaults to JSON
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteFormat {
+    /// OSRM-compatible JSON
+    Json,
+    /// GPX 1.1 document with the route as a track
+    Gpx,
+}
+
+/// How detailed the geometry of a route is, like the OSRM parameter of the same name
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Overview {
+    /// Without the details that are too small to be seen when the whole route is displayed
+    Simplified,
+    /// All the points of the route
+    Full,
+    /// No geometry at all
+    False,
+}
+
 #[derive(Serialize)]
 pub struct RouteResponse {
     pub waypoints: Vec<WaypointResponse>,
     pub routes: Vec<RouteItemResponse>,
 }
 
+/// The answer of the reroute endpoint: only whether the position is off route when it is close to
+/// the route being followed, or also a new route, like the ones of the route endpoint
+#[derive(Serialize)]
+pub struct RerouteResponse {
+    pub off_route: bool,
+    /// The distance from the position to the route being followed, in meters, if it was given
+    pub distance_to_route: Option<f64>,
+    #[serde(flatten)]
+    pub route: Option<RouteResponse>,
+}
+
+/// The warning of a waypoint snapped `distance` meters away, if it is more than `snap_warning`
+fn snap_warning_message(distance: f64, snap_warning: f64) -> Option<String> {
+    if distance > snap_warning {
+        Some(format!(
+            "Snapped {:.0} meters away from the requested coordinates",
+            distance
+        ))
+    } else {
+        None
+    }
+}
+
+#[derive(Serialize)]
+pub struct RoundTripResponse {
+    /// Where the loop starts and ends, on the closest road to the requested coordinates
+    pub location: [f64; 2],
+    pub distance: u32,
+    /// The estimated time in seconds, from the default speed of each road level
+    pub duration: f64,
+    /// Missing with `overview=false`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<GeometryResponse>,
+}
+
+#[derive(Serialize)]
+pub struct TableResponse {
+    /// From each point to each point, in meters
+    pub distances: Vec<Vec<u32>>,
+    /// Whether the distances were read from the hub table, instead of searched
+    pub precomputed: bool,
+}
+
+/// The body of the snap endpoint, like `{"coordinates": [[1.52, 42.50], [1.53, 42.51]]}`
+#[derive(Deserialize, Debug)]
+pub struct SnapRequest {
+    /// The points to snap, as `[longitude, latitude]`
+    pub coordinates: Vec<[f64; 2]>,
+}
+
+impl SnapRequest {
+    /// Check the ranges of the coordinates
+    pub fn points(&self) -> Result<Vec<GeoPoint>, ParseCoordinatesError> {
+        self.coordinates
+            .iter()
+            .map(|&[lon, lat]| {
+                let check = |got: f64, expected_min, expected_max| {
+                    if got >= expected_min && got <= expected_max {
+                        Ok(())
+                    } else {
+                        Err(ParseCoordinatesError::InvalidRange {
+                            pair: format!("{},{}", lon, lat),
+                            got,
+                            expected_min,
+                            expected_max,
+                        })
+                    }
+                };
+                check(lon, -180., 180.)?;
+                check(lat, -90., 90.)?;
+                Ok(GeoPoint::from_degrees(lat, lon))
+            })
+            .collect()
+    }
+}
+
+/// The snapped points, in the same order as the request
+#[derive(Serialize)]
+pub struct SnapResponse {
+    pub snapped: Vec<SnappedPointResponse>,
+}
+
+#[derive(Serialize)]
+pub struct SnappedPointResponse {
+    /// The closest point on a road
+    pub location: [f64; 2],
+    /// The index of the edge of that road
+    pub edge: usize,
+    /// The distance in meters from the original point
+    pub distance: f64,
+}
+
+impl From<&ProjectedPoint> for SnappedPointResponse {
+    fn from(point: &ProjectedPoint) -> Self {
+        SnappedPointResponse {
+            location: [
+                point.projected.lon.as_degrees(),
+                point.projected.lat.as_degrees(),
+            ],
+            edge: point.edge.index(),
+            distance: point.projected.haversine_distance(&point.original),
+        }
+    }
+}
+
+/// The closures that replaced the previous ones
+#[derive(Serialize)]
+pub struct ClosuresResponse {
+    /// How many edges are closed at some time, including the ones of the closed ways
+    pub edges: usize,
+    /// How many of them are closed now
+    pub closed_now: usize,
+}
+
+/// The name of the zone with the roads that cross a border, that routes avoid with `avoid=borders`
+pub const BORDERS_ZONE: &str = "borders";
+
+/// The zones to avoid, as a GeoJSON feature collection of polygons and multipolygons, each one with
+/// a `name` property
+#[derive(Deserialize)]
+pub struct AvoidZonesRequest {
+    pub features: Vec<ZoneFeature>,
+}
+
+#[derive(Deserialize)]
+pub struct ZoneFeature {
+    pub properties: ZoneProperties,
+    pub geometry: ZoneGeometry,
+}
+
+#[derive(Deserialize)]
+pub struct ZoneProperties {
+    pub name: String,
+}
+
+/// The coordinates are `[longitude, latitude]`
+#[derive(Deserialize)]
+#[serde(tag = "type", content = "coordinates")]
+pub enum ZoneGeometry {
+    Polygon(Vec<Vec<[f64; 2]>>),
+    MultiPolygon(Vec<Vec<Vec<[f64; 2]>>>),
+}
+
+impl AvoidZonesRequest {
+    /// Check the coordinates and names of the zones
+    pub fn zones(&self) -> Result<Vec<(String, AvoidZone)>, ApiError> {
+        let mut zones: Vec<(String, AvoidZone)> = Vec::with_capacity(self.features.len());
+        for feature in &self.features {
+            let name = &feature.properties.name;
+            if name.is_empty() || name.contains(',') {
+                return Err(ApiError::InvalidBody(format!(
+                    "Invalid zone name {:?}, it can not be empty nor contain commas",
+                    name
+                )));
+            }
+            if name == BORDERS_ZONE {
+                return Err(ApiError::InvalidBody(format!(
+                    "The zone name {} is reserved for the roads that cross a border",
+                    name
+                )));
+            }
+            if zones.iter().any(|(other, _)| other == name) {
+                return Err(ApiError::InvalidBody(format!("Duplicated zone {}", name)));
+            }
+            let rings = match &feature.geometry {
+                ZoneGeometry::Polygon(rings) => rings.iter().collect::<Vec<_>>(),
+                ZoneGeometry::MultiPolygon(polygons) => polygons.iter().flatten().collect(),
+            };
+            let rings = rings
+                .into_iter()
+                .map(|ring| {
+                    if ring.len() < 3 {
+                        return Err(ApiError::InvalidBody(format!(
+                            "The zone {} has a ring with less than 3 points",
+                            name
+                        )));
+                    }
+                    let request = SnapRequest {
+                        coordinates: ring.clone(),
+                    };
+                    Ok(request.points()?)
+                })
+                .collect::<Result<_, ApiError>>()?;
+            zones.push((name.clone(), AvoidZone::new(rings)));
+        }
+        Ok(zones)
+    }
+}
+
+/// The zones that replaced the previous ones, with how many edges each one avoids
+#[derive(Serialize)]
+pub struct AvoidZonesResponse {
+    pub zones: BTreeMap<String, usize>,
+}
+
+/// One entry of the JSON body of `POST /speeds`, with either `way_id` or `edge_id`
+#[derive(Deserialize)]
+pub struct SpeedEntryRequest {
+    pub way_id: Option<i64>,
+    pub edge_id: Option<u32>,
+    pub speed_kmh: f64,
+    /// For how long the speed applies, in seconds
+    pub ttl: u64,
+}
+
+impl SpeedEntryRequest {
+    pub fn update(&self) -> Result<SpeedUpdate, ApiError> {
+        let target = match (self.way_id, self.edge_id) {
+            (Some(way), None) => SpeedTarget::Way(way),
+            (None, Some(edge)) => SpeedTarget::Edge(edge),
+            _ => {
+                return Err(ApiError::InvalidBody(
+                    "Each speed needs either a way_id or an edge_id".to_owned(),
+                ))
+            }
+        };
+        if !self.speed_kmh.is_finite() || self.speed_kmh <= 0. {
+            return Err(ApiError::InvalidBody(format!(
+                "The speed {} is not positive",
+                self.speed_kmh
+            )));
+        }
+        Ok(SpeedUpdate {
+            target,
+            speed: self.speed_kmh,
+            ttl: self.ttl,
+        })
+    }
+}
+
+/// The speeds after an update
+#[derive(Serialize)]
+pub struct SpeedsResponse {
+    /// How many edges were updated, including the ones of the ways
+    pub updated: usize,
+    /// How many edges have a speed that did not expire yet
+    pub active: usize,
+}
+
+/// The classification of the roads in levels, from the file
+#[derive(Serialize)]
+pub struct LevelsResponse<'a> {
+    pub levels: &'a [RoadLevelStyle],
+}
+
+/// What the instance is serving, for orchestrators and dashboards
+#[derive(Serialize, Clone)]
+pub struct StatusResponse {
+    /// The version of the server
+    pub version: &'static str,
+    pub file: FileStatus,
+    pub nodes: u32,
+    pub edges: u32,
+    /// Number of strongly connected components
+    pub components: u32,
+    /// The bounding box of the nodes, as `[min_lon, min_lat, max_lon, max_lat]`
+    pub bbox: [f64; 4],
+    /// Whether the routes can use the edges invented by the generator
+    pub synthetic_edges: bool,
+    /// The names of the road levels, as classified by the generator
+    pub road_levels: Vec<String>,
+    /// The names of the experiments loaded at startup
+    pub experiments: Vec<String>,
+    /// The number of points of the hub table, if one was loaded
+    pub hub_table_points: Option<usize>,
+    /// Estimated when each request is answered, since the index of the nodes is only built when
+    /// first used
+    pub memory: MemoryUsage,
+}
+
+/// The cartography file loaded at startup
+#[derive(Serialize, Clone)]
+pub struct FileStatus {
+    pub path: String,
+    pub bytes: u64,
+    /// The version of the Ptolemy format, like `v2`
+    pub format: String,
+    /// When the file was last modified, in seconds since the Unix epoch. It is usually when the
+    /// generator wrote it
+    pub modified: Option<u64>,
+    /// Where the file comes from, as written by the generator
+    pub metadata: Metadata,
+}
+
+/// An OSRM-like error, like `{"code": "NoRoute", "message": "..."}`
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub code: &'static str,
+    pub message: String,
+}
+
 #[derive(Serialize)]
 pub struct WaypointResponse {
     pub location: [f64; 2],
     pub distance: f64,
+    /// The estimated time in seconds from the start of the route to this waypoint
+    pub duration: f64,
+    /// The heading of the road when arriving at the waypoint (or leaving the first one), in degrees
+    /// clockwise from the North
+    pub approach_bearing: u16,
+    /// The side of the road of the requested coordinates, relative to the direction of travel: one
+    /// of `left`, `right` or `on_road`
+    pub side: &'static str,
+    /// Why the route may not be the expected one, like a waypoint far from the roads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+impl WaypointResponse {
+    /// Describe a waypoint, with a warning when it is snapped farther than `snap_warning` meters
+    pub fn new(
+        waypoint: &ProjectedPoint,
+        approach: &Approach,
+        arrival: f64,
+        snap_warning: f64,
+    ) -> Self {
+        let distance = waypoint.projected.haversine_distance(&waypoint.original);
+        WaypointResponse {
+            distance,
+            warning: snap_warning_message(distance, snap_warning),
+            duration: round_duration(arrival),
+            location: [
+                waypoint.projected.lon.as_degrees(),
+                waypoint.projected.lat.as_degrees(),
+            ],
+            approach_bearing: approach.bearing.round() as u16 % 360,
+            side: match approach.side {
+                Side::Left => "left",
+                Side::Right => "right",
+                Side::OnRoad => "on_road",
+            },
+        }
+    }
 }
 
 #[derive(Serialize)]
 pub struct RouteItemResponse {
     pub distance: u32,
-    pub geometry: String,
+    /// The estimated time in seconds, from the default speed of each road level
+    pub duration: f64,
+    /// How many roads that cross a border the route takes, 0 when the file has no borders
+    pub border_crossings: usize,
+    /// Missing with `overview=false`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<GeometryResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<Vec<StepResponse>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<AnnotationResponse>,
+    /// One leg between each pair of consecutive waypoints
+    pub legs: Vec<LegResponse>,
+}
+
+/// The part of a route between two consecutive waypoints, like in OSRM, so that clients can show
+/// the time to each stop
+#[derive(Serialize)]
+pub struct LegResponse {
+    pub distance: u32,
+    pub duration: f64,
+    /// The names of the most traveled roads, like `CG-2, N-145`, or of the most traveled road levels,
+    /// like `motorway, primary`, when the roads have no name. As in OSRM, it is empty unless
+    /// `steps=true`
+    pub summary: String,
+    /// Like the geometry of the route, missing with `overview=false`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<GeometryResponse>,
+    /// What the search of the leg did, only with `debug=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug: Option<SearchStats>,
+}
+
+/// Describe a leg by the names of the two roads with the longest distance, in the order they are
+/// traveled, like OSRM. The legs without any named road are described by their two road levels
+/// with the longest distance instead, from the longest to the shortest
+pub fn leg_summary<'a>(
+    road_levels: &[RoadLevelStyle],
+    road_names: &RoadNames,
+    edges: impl Iterator<Item = &'a EdgeInfo>,
+) -> String {
+    // The distance of each name and of each level, in the order they are first traveled
+    let mut names: Vec<(u32, u64)> = Vec::new();
+    let mut levels: Vec<(u8, u64)> = Vec::new();
+    for info in edges {
+        if road_names.get(info.name).is_some() {
+            add_distance(&mut names, info.name, info.distance);
+        }
+        add_distance(&mut levels, info.road_level, info.distance);
+    }
+
+    if !names.is_empty() {
+        let mut longest: Vec<usize> = (0..names.len()).collect();
+        longest.sort_by_key(|&i| std::cmp::Reverse(names[i].1));
+        longest.truncate(2);
+        longest.sort_unstable();
+        return longest
+            .iter()
+            .filter_map(|&i| road_names.get(names[i].0))
+            .collect::<Vec<_>>()
+            .join(", ");
+    }
+    levels.sort_by_key(|&(level, distance)| (std::cmp::Reverse(distance), level));
+    levels
+        .iter()
+        .take(2)
+        .map(
+            |&(level, _)| match road_levels.iter().find(|style| style.level == level) {
+                Some(style) => style.name.clone(),
+                None => format!("level {}", level),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn add_distance<K: PartialEq>(distances: &mut Vec<(K, u64)>, key: K, distance: u32) {
+    match distances.iter_mut().find(|(other, _)| *other == key) {
+        Some((_, total)) => *total += distance as u64,
+        None => distances.push((key, distance as u64)),
+    }
+}
+
+/// The shape of the route
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum GeometryResponse {
+    /// Encoded with precision 5, like in OSRM
+    Polyline(String),
+    /// A GeoJSON line in the requested coordinate reference system, named like `EPSG:32631`
+    LineString {
+        #[serde(rename = "type")]
+        kind: &'static str,
+        crs: String,
+        coordinates: Vec<[f64; 2]>,
+    },
+}
+
+impl GeometryResponse {
+    /// Express the points in the given projection
+    pub fn line_string(projection: Projection, points: &[GeoPoint]) -> Self {
+        GeometryResponse::LineString {
+            kind: "LineString",
+            crs: projection.to_string(),
+            coordinates: projection.project_all(points),
+        }
+    }
+}
+
+/// The attributes of each traversed edge, in order
+#[derive(Serialize, Default)]
+pub struct AnnotationResponse {
+    pub distance: Vec<u32>,
+    pub duration: Vec<f64>,
+    pub road_level: Vec<u8>,
+    /// Number of lanes in the direction of travel, 0 when unknown
+    pub lanes: Vec<u8>,
+    /// Width in meters, 0 when unknown
+    pub width: Vec<f32>,
+    /// The id of the OSM way, 0 when the file does not have them
+    pub way_id: Vec<i64>,
+    /// The distance from the start of the route to each point of its full geometry, in meters
+    pub cumulative_distance: Vec<f64>,
+}
+
+impl AnnotationResponse {
+    pub fn push(&mut self, info: &EdgeInfo) {
+        self.distance.push(info.distance);
+        self.duration.push(round_duration(info.duration()));
+        self.road_level.push(info.road_level);
+        self.lanes.push(info.lanes);
+        self.width.push(info.width);
+        self.way_id.push(info.way_id);
+    }
+
+    /// Set the distance from the start to each point of the route, rounded to a tenth of a meter
+    pub fn set_cumulative_distances(&mut self, path: &GraphPath) {
+        self.cumulative_distance = path
+            .cumulative_distances()
+            .into_iter()
+            .map(round_distance)
+            .collect();
+    }
+}
+
+#[derive(Serialize)]
+pub struct StepResponse {
+    /// One of `depart`, `turn`, `roundabout` or `arrive`
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modifier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit: Option<u32>,
+    pub instruction: String,
+    pub location: [f64; 2],
+    pub distance: u32,
+    pub duration: f64,
+}
+
+impl From<&Step> for StepResponse {
+    fn from(step: &Step) -> Self {
+        let (kind, modifier, exit) = match step.maneuver {
+            Maneuver::Depart => ("depart", None, None),
+            Maneuver::Turn(direction) => ("turn", Some(direction.to_string()), None),
+            Maneuver::Roundabout { exit } => ("roundabout", None, Some(exit)),
+            Maneuver::Arrive => ("arrive", None, None),
+        };
+        StepResponse {
+            kind,
+            modifier,
+            exit,
+            instruction: step.maneuver.to_string(),
+            location: [
+                step.location.lon.as_degrees(),
+                step.location.lat.as_degrees(),
+            ],
+            distance: step.distance,
+            duration: round_duration(step.duration),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn surface_penalties() {
+        let options: RouteOptions = serde_json::from_str(
+            r#"{"avoid_surfaces": "cobblestone, unpaved", "avoid_smoothness": "bad"}"#,
+        )
+        .unwrap();
+        let penalties = options.surface_penalties().unwrap();
+        assert_eq!(
+            penalties.surfaces,
+            vec![(Surface::Cobblestone, 5.), (Surface::Unpaved, 5.)]
+        );
+        assert_eq!(penalties.smoothness, Some((Smoothness::Bad, 5.)));
+
+        let options: RouteOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.surface_penalties(), None);
+        assert!(serde_json::from_str::<RouteOptions>(r#"{"avoid_surfaces": "lava"}"#).is_err());
+    }
+
+    #[test]
+    fn vehicle() {
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"height": 4.2, "weight": 18}"#).unwrap();
+        options.validate().unwrap();
+        assert!(options.is_heavy());
+        assert_eq!(
+            options.vehicle(),
+            Some(Vehicle {
+                weight: 18.,
+                height: 4.2,
+                width: 0.,
+            })
+        );
+        let options: RouteOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.vehicle(), None);
+
+        let options: RouteOptions = serde_json::from_str(r#"{"width": -2}"#).unwrap();
+        assert!(options.validate().is_err());
+        // The randomized routes perturb the costs with the restrictions
+        let options: RouteOptions = serde_json::from_str(r#"{"height": 4, "seed": 1}"#).unwrap();
+        assert_eq!(options.validate(), Ok(()));
+    }
+
+    #[test]
+    fn leg_summary() {
+        let levels = RoadLevelStyle::defaults();
+        let edges = [
+            EdgeInfo::new(100, 5),
+            EdgeInfo::new(500, 1),
+            EdgeInfo::new(150, 5),
+            EdgeInfo::new(50, 3),
+            EdgeInfo::new(100, 7),
+        ];
+        let none = RoadNames::default();
+        assert_eq!(
+            super::leg_summary(&levels, &none, edges.iter()),
+            "primary, local"
+        );
+        assert_eq!(
+            super::leg_summary(&levels, &none, edges[4..].iter()),
+            "level 7"
+        );
+        assert_eq!(super::leg_summary(&levels, &none, [].iter()), "");
+
+        // The two longest named roads, in the order they are traveled
+        let names = RoadNames(vec![
+            "CG-2".to_owned(),
+            "N-145".to_owned(),
+            "CS-101".to_owned(),
+        ]);
+        let named: Vec<_> = [
+            (3, 100, 1),
+            (2, 400, 5),
+            (1, 50, 0),
+            (3, 30, 1),
+            (1, 200, 3),
+        ]
+        .iter()
+        .map(|&(name, distance, level)| EdgeInfo {
+            name,
+            ..EdgeInfo::new(distance, level)
+        })
+        .collect();
+        assert_eq!(
+            super::leg_summary(&levels, &names, named.iter()),
+            "N-145, CG-2"
+        );
+        assert_eq!(
+            super::leg_summary(&levels, &names, named[2..3].iter()),
+            "CG-2"
+        );
+    }
+
+    #[test]
+    fn depart_at() {
+        let options: RouteOptions = serde_json::from_str(r#"{"depart_at": "08:30"}"#).unwrap();
+        assert_eq!(options.depart_at, Some(8.5 * 3600.));
+        options.validate().unwrap();
+        for invalid in &["24:00", "8", "08:30:00", "noon"] {
+            let error =
+                serde_json::from_str::<RouteOptions>(&format!(r#"{{"depart_at": "{}"}}"#, invalid))
+                    .unwrap_err()
+                    .to_string();
+            assert!(error.starts_with("Invalid time"), "{}", error);
+        }
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"depart_at": "17:00", "avoid_surfaces": "unpaved"}"#).unwrap();
+        assert_eq!(
+            options.validate().unwrap_err().to_string(),
+            "Option depart_at can not be combined with avoid_surfaces"
+        );
+    }
+
+    #[test]
+    fn overview() {
+        let options: RouteOptions = serde_json::from_str(r#"{"overview": "simplified"}"#).unwrap();
+        assert_eq!(options.overview, Some(Overview::Simplified));
+        assert!(serde_json::from_str::<RouteOptions>(r#"{"overview": "half"}"#).is_err());
+
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"overview": "false", "format": "gpx"}"#).unwrap();
+        assert_eq!(
+            options.validate().unwrap_err().to_string(),
+            "Option overview=false can not be combined with format=gpx"
+        );
+    }
+
+    #[test]
+    fn access_penalties() {
+        let options: RouteOptions = serde_json::from_str(
+            r#"{"avoid_access": "living_street", "access_penalty": 3, "exclude_access": "pedestrian, motorroad"}"#,
+        )
+        .unwrap();
+        assert!(options.is_heavy());
+        let penalties = options.access_penalties().unwrap();
+        assert_eq!(penalties.penalized, vec![(AccessClass::LivingStreet, 3.)]);
+        assert_eq!(
+            penalties.forbidden,
+            vec![AccessClass::Pedestrian, AccessClass::Motorroad]
+        );
+
+        let options: RouteOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.access_penalties(), None);
+        let error = serde_json::from_str::<RouteOptions>(r#"{"avoid_access": "private"}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Unknown access class private."));
+
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"exclude_access": "pedestrian", "experiment": "a"}"#).unwrap();
+        assert_eq!(
+            options.validate().unwrap_err().to_string(),
+            "Option experiment can not be combined with exclude_access"
+        );
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"avoid_access": "pedestrian", "seed": 1}"#).unwrap();
+        assert_eq!(options.validate(), Ok(()));
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"exclude_access": "pedestrian", "weight": 18, "seed": 1}"#)
+                .unwrap();
+        assert_eq!(options.validate(), Ok(()));
+
+        let options: RouteOptions = serde_json::from_str(r#"{"barrier_penalty": 200}"#).unwrap();
+        assert!(options.is_heavy());
+        let penalties = options.access_penalties().unwrap();
+        assert_eq!(penalties.barrier, 200);
+        assert!(penalties.penalized.is_empty() && penalties.forbidden.is_empty());
+        assert!(serde_json::from_str::<RouteOptions>(r#"{"barrier_penalty": -1}"#).is_err());
+    }
+
+    #[test]
+    fn option_errors() {
+        let error = serde_json::from_str::<RouteOptions>(r#"{"avoid_smoothness": "awful"}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Unknown smoothness awful. Expected one of excellent"));
+
+        let options: RouteOptions = serde_json::from_str(r#"{"surface_penalty": 0.5}"#).unwrap();
+        let error = ApiError::from(options.validate().unwrap_err());
+        assert_eq!(error.code(), "InvalidOptions");
+        assert_eq!(
+            error.to_string(),
+            "Value 0.5 of surface_penalty is out of range, it should be at least 1"
+        );
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+
+        let error = ApiError::NoRoute { from: 1, to: 2 };
+        assert_eq!(
+            error.to_string(),
+            "Could not find a route between waypoints 1 and 2"
+        );
+
+        let error = ApiError::NoRoundTrip { distance: 5000. };
+        assert_eq!(error.code(), "NoRoundTrip");
+        assert_eq!(
+            error.to_string(),
+            "Could not find a loop of about 5000 meters from the start"
+        );
+        assert_eq!(error.status_code(), StatusCode::NOT_FOUND);
+
+        let error = ApiError::NoSegment {
+            waypoint: 1,
+            distance: 3120.4,
+            max: 500.,
+        };
+        assert_eq!(error.code(), "NoSegment");
+        assert_eq!(
+            error.to_string(),
+            "Could not find a road within 500 meters of waypoint 1, the closest one is 3120 meters away"
+        );
+        assert_eq!(snap_warning_message(999.6, 1000.), None);
+        assert_eq!(
+            snap_warning_message(3120.4, 1000.).unwrap(),
+            "Snapped 3120 meters away from the requested coordinates"
+        );
+
+        let error = ApiError::from(Timeout);
+        assert_eq!(error.code(), "Timeout");
+        assert_eq!(error.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let error = ApiError::TooManyRequests {
+            retry_after: Duration::from_millis(1500),
+        };
+        assert_eq!(error.to_string(), "Too many requests, retry in 2 seconds");
+        let response = error.error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get("Retry-After").unwrap(), "2");
+
+        assert_eq!(ApiError::Disabled.status_code(), StatusCode::FORBIDDEN);
+        let response = ApiError::Unauthorized.error_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers().get("WWW-Authenticate").unwrap(),
+            "Bearer"
+        );
+    }
+
+    #[test]
+    fn extra_edges() {
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"extra_edges": "1.5,42.5,1.6,42.6;1.7,42.5,1.7,42.6"}"#)
+                .unwrap();
+        let edges = options.extra_edges.as_ref().unwrap();
+        assert_eq!(edges.len(), 2);
+        assert_eq!(edges[0].to, GeoPoint::from_degrees(42.6, 1.6));
+        assert!(options.validate().is_ok());
+
+        let error = serde_json::from_str::<RouteOptions>(r#"{"extra_edges": "1.5,42.5,1.6"}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Expected 4 values in the extra edge 1.5,42.5,1.6"));
+        let error = serde_json::from_str::<RouteOptions>(r#"{"extra_edges": "1.5,99,1.6,42"}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Invalid extra edge 1.5,99,1.6,42: Value 99"));
+
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"extra_edges": "1.5,42.5,1.6,42.6", "seed": 3}"#).unwrap();
+        assert_eq!(
+            options.validate(),
+            Err(ParseOptionError::Incompatible("extra_edges", "seed"))
+        );
+    }
+
+    #[test]
+    fn debug() {
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"debug": true, "avoid_access": "pedestrian"}"#).unwrap();
+        assert_eq!(options.validate(), Ok(()));
+        for other in &[r#""seed": 3"#, r#""depart_at": "08:30""#] {
+            let json = format!(r#"{{"debug": true, {}}}"#, other);
+            let options: RouteOptions = serde_json::from_str(&json).unwrap();
+            assert!(matches!(
+                options.validate(),
+                Err(ParseOptionError::Incompatible("debug", _))
+            ));
+        }
+    }
+
+    #[test]
+    fn route_response() {
+        let response = RouteResponse {
+            waypoints: vec![
+                WaypointResponse {
+                    location: [1.5, 42.5],
+                    distance: 3.,
+                    duration: 0.,
+                    approach_bearing: 90,
+                    side: "on_road",
+                    warning: None,
+                },
+                WaypointResponse {
+                    location: [1.6, 42.6],
+                    distance: 4.,
+                    duration: 12.3,
+                    approach_bearing: 359,
+                    side: "right",
+                    warning: Some("Snapped 1500 meters away".to_owned()),
+                },
+            ],
+            routes: vec![RouteItemResponse {
+                distance: 150,
+                duration: 12.3,
+                border_crossings: 0,
+                geometry: Some(GeometryResponse::Polyline("_ibE".to_owned())),
+                steps: None,
+                annotation: None,
+                legs: vec![LegResponse {
+                    distance: 150,
+                    duration: 12.3,
+                    summary: String::new(),
+                    geometry: Some(GeometryResponse::Polyline("_ibE".to_owned())),
+                    debug: None,
+                }],
+            }],
+        };
+
+        // The OSRM clients read the duration of the route and the time to reach each waypoint
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["routes"][0]["distance"], 150);
+        assert_eq!(json["routes"][0]["duration"], 12.3);
+        assert_eq!(json["routes"][0]["border_crossings"], 0);
+        assert_eq!(json["waypoints"][1]["duration"], 12.3);
+        assert_eq!(json["waypoints"][1]["approach_bearing"], 359);
+        assert_eq!(json["waypoints"][1]["side"], "right");
+        assert!(json["waypoints"][0].get("warning").is_none());
+        assert_eq!(json["waypoints"][1]["warning"], "Snapped 1500 meters away");
+        assert!(json["routes"][0].get("steps").is_none());
+        assert_eq!(json["routes"][0]["legs"][0]["duration"], 12.3);
+        assert_eq!(json["routes"][0]["legs"][0]["summary"], "");
+        assert!(json["routes"][0]["legs"][0].get("debug").is_none());
+    }
+
+    #[test]
+    fn crs() {
+        let options: RouteOptions = serde_json::from_str(r#"{"crs": "utm"}"#).unwrap();
+        assert_eq!(options.crs, Some(Crs::Utm));
+        let error = serde_json::from_str::<RouteOptions>(r#"{"crs": "lambert"}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Unknown coordinate reference system lambert"));
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"crs": "web_mercator", "format": "gpx"}"#).unwrap();
+        assert_eq!(
+            options.validate(),
+            Err(ParseOptionError::Incompatible("crs", "format=gpx"))
+        );
+
+        let points = [
+            GeoPoint::from_degrees(0., 3.),
+            GeoPoint::from_degrees(0., 3.),
+        ];
+        let geometry = GeometryResponse::line_string(Crs::Utm.projection(&points), &points);
+        assert_eq!(
+            serde_json::to_string(&geometry).unwrap(),
+            r#"{"type":"LineString","crs":"EPSG:32631","coordinates":[[500000.0,0.0],[500000.0,0.0]]}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&GeometryResponse::Polyline("_ibE".to_owned())).unwrap(),
+            r#""_ibE""#
+        );
+    }
+
+    #[test]
+    fn experiment() {
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"experiment": "slow_primary"}"#).unwrap();
+        assert_eq!(options.experiment.as_deref(), Some("slow_primary"));
+        assert!(options.is_heavy());
+        assert_eq!(options.validate(), Ok(()));
+
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"experiment": "slow_primary", "seed": 3}"#).unwrap();
+        assert_eq!(
+            options.validate(),
+            Err(ParseOptionError::Incompatible("experiment", "seed"))
+        );
+
+        let experiments: std::collections::HashMap<String, ptolemy::Experiment> =
+            serde_json::from_str(
+                r#"{"slow_primary": {"road_levels": [1, 1.5]}, "closed": {"edges": {"12": 100}}}"#,
+            )
+            .unwrap();
+        assert_eq!(experiments["slow_primary"].road_levels, vec![1., 1.5]);
+        assert_eq!(experiments["closed"].edges[&12], 100.);
+    }
+
+    #[test]
+    fn limits() {
+        let limits = ApiLimits {
+            max_waypoints: 2,
+            max_extra_edges: 1,
+            max_snap_points: 2,
+            max_round_trip_distance: 1000,
+            max_query_time: None,
+            rate_limit: None,
+        };
+        let coords: Coordinates = "1.5,42.5;1.6,42.6".parse().unwrap();
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"extra_edges": "1.5,42.5,1.6,42.6"}"#).unwrap();
+        assert!(limits.check_route(&coords, &options).is_ok());
+
+        let coords: Coordinates = "1.5,42.5;1.6,42.6;1.7,42.7".parse().unwrap();
+        let error = limits.check_route(&coords, &options).unwrap_err();
+        assert_eq!(error.code(), "TooBig");
+        assert_eq!(
+            error.to_string(),
+            "Too many waypoints: got 3, but the maximum is 2"
+        );
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+
+        let coords: Coordinates = "1.5,42.5;1.6,42.6".parse().unwrap();
+        let options: RouteOptions =
+            serde_json::from_str(r#"{"extra_edges": "1.5,42.5,1.6,42.6;1.7,42.5,1.7,42.6"}"#)
+                .unwrap();
+        let error = limits.check_route(&coords, &options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Too many extra edges: got 2, but the maximum is 1"
+        );
+        assert!(ApiLimits::default().check_route(&coords, &options).is_ok());
+        assert!(limits.check_table(&coords).is_ok());
+        let coords: Coordinates = "1.5,42.5;1.6,42.6;1.7,42.7".parse().unwrap();
+        assert_eq!(limits.check_table(&coords).unwrap_err().code(), "TooBig");
+
+        let request: SnapRequest =
+            serde_json::from_str(r#"{"coordinates": [[1.5, 42.5], [1.6, 42.6], [1.7, 42.7]]}"#)
+                .unwrap();
+        let error = limits.check_snap(&request).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Too many points to snap: got 3, but the maximum is 2"
+        );
+
+        assert!(!limits.deadline().has_passed());
+        let limits = ApiLimits {
+            max_query_time: Some(Duration::default()),
+            ..limits
+        };
+        assert!(limits.deadline().has_passed());
+    }
+
+    #[test]
+    fn snap_request() {
+        let request: SnapRequest =
+            serde_json::from_str(r#"{"coordinates": [[1.5, 42.5], [-1.6, -42.6]]}"#).unwrap();
+        assert_eq!(
+            request.points().unwrap(),
+            vec![
+                GeoPoint::from_degrees(42.5, 1.5),
+                GeoPoint::from_degrees(-42.6, -1.6)
+            ]
+        );
+
+        let request: SnapRequest =
+            serde_json::from_str(r#"{"coordinates": [[1.5, 42.5], [42.5, 181]]}"#).unwrap();
+        let error = ApiError::from(request.points().unwrap_err());
+        assert_eq!(error.code(), "InvalidBody");
+        assert!(error
+            .to_string()
+            .starts_with("Value 181 in pair 42.5,181 is out of range"));
+        assert!(serde_json::from_str::<SnapRequest>(r#"{"coordinates": [[1.5]]}"#).is_err());
+    }
+
+    #[test]
+    fn avoid_zones() {
+        let request: AvoidZonesRequest = serde_json::from_str(
+            r#"{"type": "FeatureCollection", "features": [
+                {"type": "Feature", "properties": {"name": "market"}, "geometry": {
+                    "type": "Polygon", "coordinates": [[[1.5, 42.5], [1.6, 42.5], [1.6, 42.6], [1.5, 42.5]]]}},
+                {"type": "Feature", "properties": {"name": "race"}, "geometry": {
+                    "type": "MultiPolygon", "coordinates": [
+                        [[[1.5, 42.5], [1.6, 42.5], [1.6, 42.6]]],
+                        [[[1.7, 42.5], [1.8, 42.5], [1.8, 42.6]]]]}}
+            ]}"#,
+        )
+        .unwrap();
+        let zones = request.zones().unwrap();
+        assert_eq!(zones[0].0, "market");
+        assert_eq!(zones[0].1.rings[0][1], GeoPoint::from_degrees(42.5, 1.6));
+        assert_eq!(zones[1].1.rings.len(), 2);
+
+        let invalid = |json: &str| {
+            serde_json::from_str::<AvoidZonesRequest>(json)
+                .unwrap()
+                .zones()
+                .unwrap_err()
+                .to_string()
+        };
+        let feature = |name: &str, ring: &str| {
+            format!(
+                r#"{{"properties": {{"name": "{}"}}, "geometry": {{"type": "Polygon", "coordinates": [{}]}}}}"#,
+                name, ring
+            )
+        };
+        let triangle = "[[1.5, 42.5], [1.6, 42.5], [1.6, 42.6]]";
+        assert_eq!(
+            invalid(&format!(
+                r#"{{"features": [{}, {}]}}"#,
+                feature("a", triangle),
+                feature("a", triangle)
+            )),
+            "Duplicated zone a"
+        );
+        assert!(invalid(&format!(
+            r#"{{"features": [{}]}}"#,
+            feature("a,b", triangle)
+        ))
+        .starts_with("Invalid zone name"));
+        assert!(invalid(&format!(
+            r#"{{"features": [{}]}}"#,
+            feature("borders", triangle)
+        ))
+        .ends_with("reserved for the roads that cross a border"));
+        assert!(invalid(&format!(
+            r#"{{"features": [{}]}}"#,
+            feature("a", "[[1.5, 42.5], [1.6, 42.5]]")
+        ))
+        .ends_with("less than 3 points"));
+        assert!(invalid(&format!(
+            r#"{{"features": [{}]}}"#,
+            feature("a", "[[1.5, 42.5], [1.6, 42.5], [1.6, 92]]")
+        ))
+        .starts_with("Value 92"));
+
+        let options: RouteOptions = serde_json::from_str(r#"{"avoid": "market, race"}"#).unwrap();
+        assert_eq!(
+            options.avoid,
+            Some(vec!["market".to_owned(), "race".to_owned()])
+        );
+        assert!(options.validate().is_ok());
+        // Every search avoids the zones, like the closures
+        for other in &[r#""seed": 1"#, r#""height": 4"#, r#""depart_at": "08:30""#] {
+            let options: RouteOptions =
+                serde_json::from_str(&format!(r#"{{"avoid": "borders", {}}}"#, other)).unwrap();
+            assert!(options.validate().is_ok());
+        }
+    }
+
     #[test]
     fn coordinates() {
         // Parse back and forth
@@ -190,5 +2004,85 @@ mod test {
             "13.38886,5200.517037;13.397634,52.529407;13.428555,52.523219",
             "Value 5200.517037 in pair 13.38886,5200.517037 is out of range, it should in [-90, 90]. Make sure to use the order longitude,latitude",
         );
+
+        // A single point
+        let c: Coordinate = "13.38886,52.517037".parse().unwrap();
+        assert_eq!(c.0, GeoPoint::from_degrees(52.517037, 13.38886));
+        assert!("13.38886,52.517037;13.397634,52.529407"
+            .parse::<Coordinate>()
+            .is_err());
+    }
+
+    #[test]
+    fn round_trip_options() {
+        let options: RoundTripOptions =
+            serde_json::from_str(r#"{"distance": 5000, "seed": 3}"#).unwrap();
+        assert!(options.validate().is_ok());
+        assert_eq!(options.seed, Some(3));
+
+        let options: RoundTripOptions = serde_json::from_str(r#"{"distance": 0}"#).unwrap();
+        assert!(options.validate().is_err());
+        assert!(serde_json::from_str::<RoundTripOptions>("{}").is_err());
+
+        let limits = ApiLimits::default();
+        let options: RoundTripOptions = serde_json::from_str(r#"{"distance": 1e6}"#).unwrap();
+        assert_eq!(
+            limits.check_round_trip(&options).unwrap_err().to_string(),
+            "Too many meters in the round trip: got 1000000, but the maximum is 100000"
+        );
+    }
+
+    #[test]
+    fn cumulative_distance() {
+        let a = GeoPoint::from_degrees(42.5, 1.5);
+        let b = GeoPoint::from_degrees(42.51, 1.52);
+        let mut annotation = AnnotationResponse::default();
+        annotation.set_cumulative_distances(&GraphPath::new(2000, 100., vec![a, b]));
+        assert_eq!(annotation.cumulative_distance, vec![0., 1981.]);
+    }
+
+    #[test]
+    fn speed_entry() {
+        let entry = |json: &str| {
+            serde_json::from_str::<SpeedEntryRequest>(json)
+                .unwrap()
+                .update()
+        };
+        assert_eq!(
+            entry(r#"{"way_id": 4199, "speed_kmh": 25.5, "ttl": 600}"#).unwrap(),
+            SpeedUpdate {
+                target: SpeedTarget::Way(4199),
+                speed: 25.5,
+                ttl: 600
+            }
+        );
+        assert_eq!(
+            entry(r#"{"edge_id": 7, "speed_kmh": 90, "ttl": 60}"#)
+                .unwrap()
+                .target,
+            SpeedTarget::Edge(7)
+        );
+        assert!(entry(r#"{"speed_kmh": 90, "ttl": 60}"#).is_err());
+        assert!(entry(r#"{"way_id": 1, "edge_id": 7, "speed_kmh": 90, "ttl": 60}"#).is_err());
+        assert!(entry(r#"{"edge_id": 7, "speed_kmh": 0, "ttl": 60}"#).is_err());
+    }
+
+    #[test]
+    fn reroute_options() {
+        let options = RerouteOptions {
+            geometry: Some("_p~iF~ps|U_ulLnnqC_mqNvxq`@".to_owned()),
+            max_deviation: None,
+        };
+        options.validate().unwrap();
+        let path = options.geometry().unwrap().unwrap();
+        assert_eq!(path.points.len(), 3);
+        assert_eq!(path.points[0], GeoPoint::from_degrees(38.5, -120.2));
+
+        let options = RerouteOptions {
+            geometry: Some("_p~iF~ps|U_ulL".to_owned()),
+            max_deviation: Some(-1.),
+        };
+        assert!(options.validate().is_err());
+        assert!(options.geometry().is_err());
     }
 }
@@ -0,0 +1,310 @@
+//! Answer the route, table and nearest queries with gRPC, for the backends that prefer it to REST.
+//! The queries go through the same checks and the same `CartographService` as the HTTP API
+
+use super::cache::RouteCache;
+use super::data_types::*;
+use super::protobuf;
+use super::service::CartographService;
+use super::{
+    answer_route, answer_table, Experiments, RouteBody, SharedAvoidZones, SharedClosures,
+    SharedSpeeds,
+};
+use actix_web::web;
+use ptolemy::*;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::RwLock;
+use tonic::{Code, Request, Response, Status};
+
+mod proto {
+    tonic::include_proto!("ptolemy.grpc");
+}
+
+use proto::routing_server::{Routing, RoutingServer};
+use proto::{Coordinate, RouteRequest, TableRequest};
+
+/// The state of the gRPC server. The closures, avoided zones and speeds of the HTTP API can not be
+/// updated, so they stay empty
+pub struct RoutingService {
+    service: CartographService,
+    cache: web::Data<RouteCache>,
+    limits: ApiLimits,
+    experiments: web::Data<Experiments>,
+    speed_profiles: web::Data<SpeedProfiles>,
+    closures: SharedClosures,
+    avoid_zones: SharedAvoidZones,
+    speeds: SharedSpeeds,
+}
+
+impl RoutingService {
+    pub fn new(
+        carto: Cartograph,
+        max_heavy_queries: usize,
+        cache_size: usize,
+        limits: ApiLimits,
+    ) -> Self {
+        RoutingService {
+            service: CartographService::new(carto, max_heavy_queries),
+            cache: web::Data::new(RouteCache::new(cache_size)),
+            limits,
+            experiments: web::Data::new(HashMap::new()),
+            speed_profiles: web::Data::new(SpeedProfiles::default()),
+            closures: RwLock::new(Closures::default()),
+            avoid_zones: RwLock::new(HashMap::new()),
+            speeds: RwLock::new(SpeedOverrides::default()),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Routing for RoutingService {
+    async fn route(
+        &self,
+        request: Request<RouteRequest>,
+    ) -> Result<Response<protobuf::RouteResponse>, Status> {
+        let request = request.into_inner();
+        let coords = coordinates(&request.coordinates).map_err(status)?;
+        let options = web::Query::<RouteOptions>::from_query(&request.options)
+            .map_err(|error| status(ApiError::from(error)))?
+            .into_inner();
+        if options.format == Some(RouteFormat::Gpx) {
+            return Err(Status::invalid_argument(
+                "InvalidOptions: the routes can not be encoded in GPX",
+            ));
+        }
+        let body = answer_route(
+            coords,
+            options,
+            &self.service,
+            self.cache.clone(),
+            &self.limits,
+            self.experiments.clone(),
+            self.speed_profiles.clone(),
+            &self.closures,
+            &self.avoid_zones,
+            &self.speeds,
+        )
+        .await
+        .map_err(status)?;
+        match body {
+            RouteBody::Json(response) => Ok(Response::new((&response).into())),
+            RouteBody::Gpx(_) => unreachable!("GPX routes were rejected"),
+        }
+    }
+
+    async fn table(
+        &self,
+        request: Request<TableRequest>,
+    ) -> Result<Response<protobuf::TableResponse>, Status> {
+        let coords = coordinates(&request.into_inner().coordinates).map_err(status)?;
+        let response = answer_table(coords, &self.service, &self.limits, &None)
+            .await
+            .map_err(status)?;
+        Ok(Response::new((&response).into()))
+    }
+
+    async fn nearest(
+        &self,
+        request: Request<Coordinate>,
+    ) -> Result<Response<protobuf::SnappedPoint>, Status> {
+        let point = coordinates(&[request.into_inner()]).map_err(status)?.0[0];
+        let projected = self
+            .service
+            .run(move |carto| carto.project(&point))
+            .await
+            .map_err(|error| status(ApiError::Internal(error.to_string())))?;
+        Ok(Response::new(
+            (&SnappedPointResponse::from(&projected)).into(),
+        ))
+    }
+}
+
+/// Check the ranges of the coordinates, like the ones of the snap endpoint
+fn coordinates(coordinates: &[Coordinate]) -> Result<Coordinates, ApiError> {
+    let request = SnapRequest {
+        coordinates: coordinates
+            .iter()
+            .map(|point| [point.lon, point.lat])
+            .collect(),
+    };
+    let points = request
+        .points()
+        .map_err(|error| ApiError::InvalidUrl(error.to_string()))?;
+    Ok(Coordinates(points))
+}
+
+/// The gRPC status of an error, with the OSRM-like code of the HTTP API in its message, like
+/// `NoRoute: ...`
+fn status(error: ApiError) -> Status {
+    let code = match error {
+        ApiError::NoRoute { .. } | ApiError::NoRoundTrip { .. } | ApiError::NoSegment { .. } => {
+            Code::NotFound
+        }
+        ApiError::TooBig { .. } | ApiError::TooManyRequests { .. } => Code::ResourceExhausted,
+        ApiError::Timeout(_) => Code::DeadlineExceeded,
+        ApiError::Internal(_) => Code::Internal,
+        _ => Code::InvalidArgument,
+    };
+    Status::new(code, format!("{}: {}", error.code(), error))
+}
+
+#[actix_rt::main]
+pub async fn run_grpc<P: AsRef<Path> + 'static>(
+    input: P,
+    port: u16,
+    forbid_synthetic_edges: bool,
+    max_heavy_queries: usize,
+    cache_size: usize,
+    limits: ApiLimits,
+) -> io::Result<()> {
+    let mut carto = Cartograph::open(&input)?;
+    carto.allow_synthetic = !forbid_synthetic_edges;
+    let service = RoutingService::new(carto, max_heavy_queries, cache_size, limits);
+
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+    tracing::info!("Serving gRPC on {}", address);
+    tonic::transport::Server::builder()
+        .add_service(RoutingServer::new(service))
+        .serve(address)
+        .await
+        .map_err(|error| io::Error::other(error.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_service() -> RoutingService {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        RoutingService::new(carto, 1, 0, ApiLimits::default())
+    }
+
+    fn coordinate(lon: f64, lat: f64) -> Coordinate {
+        Coordinate { lon, lat }
+    }
+
+    #[actix_rt::test]
+    async fn route() {
+        let service = get_service();
+        let request = RouteRequest {
+            coordinates: vec![
+                coordinate(1.588908, 42.553210),
+                coordinate(1.685042, 42.564440),
+            ],
+            options: "steps=true".to_owned(),
+        };
+        let response = service
+            .route(Request::new(request.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.waypoints.len(), 2);
+        assert_eq!(response.routes[0].distance, 12183);
+        assert_eq!(response.routes[0].legs.len(), 1);
+        assert!(!response.routes[0].steps.is_empty());
+
+        // The steps and annotations describe the route that was found, not the shortest one
+        let penalized = RouteRequest {
+            coordinates: request.coordinates.clone(),
+            options: "steps=true&annotations=true&avoid_surfaces=asphalt&surface_penalty=3"
+                .to_owned(),
+        };
+        let response = service
+            .route(Request::new(penalized))
+            .await
+            .unwrap()
+            .into_inner();
+        let route = &response.routes[0];
+        let steps_distance: u32 = route.steps.iter().map(|step| step.distance).sum();
+        assert_ne!(route.distance, 12183);
+        assert!(steps_distance <= route.distance && steps_distance + 2 >= route.distance);
+        // Only part of the first and last edges is traveled
+        let edge_distances = &route.annotation.as_ref().unwrap().distance;
+        let total: u32 = edge_distances.iter().sum();
+        let inner = total - edge_distances[0] - edge_distances[edge_distances.len() - 1];
+        assert!(inner <= route.distance && route.distance <= total);
+
+        // The cumulative distances are given for the full geometry, even when it is simplified
+        let mut cumulative_distances = Vec::new();
+        for overview in &["full", "simplified"] {
+            let annotated = RouteRequest {
+                coordinates: request.coordinates.clone(),
+                options: format!("annotations=true&overview={}", overview),
+            };
+            let response = service
+                .route(Request::new(annotated))
+                .await
+                .unwrap()
+                .into_inner();
+            let annotation = response.routes[0].annotation.clone().unwrap();
+            cumulative_distances.push(annotation.cumulative_distance);
+        }
+        assert!(cumulative_distances[0].len() > 2);
+        assert_eq!(cumulative_distances[0], cumulative_distances[1]);
+
+        let invalid = |options: &str, coordinates: Vec<Coordinate>| RouteRequest {
+            coordinates,
+            options: options.to_owned(),
+        };
+        let error = service
+            .route(Request::new(invalid(
+                "steps=maybe",
+                request.coordinates.clone(),
+            )))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        assert!(error.message().starts_with("InvalidQuery: "));
+        let error = service
+            .route(Request::new(invalid(
+                "seed=1&height=4",
+                request.coordinates.clone(),
+            )))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        assert!(error
+            .message()
+            .ends_with("Option seed can not be combined with height"));
+        let error = service
+            .route(Request::new(invalid("format=gpx", request.coordinates)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument);
+        let error = service
+            .route(Request::new(invalid("", vec![coordinate(1.5, 91.)])))
+            .await
+            .unwrap_err();
+        assert!(error.message().starts_with("InvalidUrl: "));
+    }
+
+    #[actix_rt::test]
+    async fn table_and_nearest() {
+        let service = get_service();
+        let request = TableRequest {
+            coordinates: vec![
+                coordinate(1.588908, 42.553210),
+                coordinate(1.685042, 42.564440),
+            ],
+        };
+        let response = service
+            .table(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.num_points, 2);
+        assert_eq!(response.distances[0], 0);
+        assert_eq!(response.distances[1], 12183);
+        assert!(!response.precomputed);
+
+        let nearest = service
+            .nearest(Request::new(coordinate(1.5218, 42.5063)))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(nearest.distance < 10.);
+        assert!((nearest.lon - 1.5218).abs() < 0.001);
+    }
+}
@@ -0,0 +1,375 @@
+//! Encode the responses with Protocol Buffers, which is much faster than JSON for the large ones,
+//! like the tables and the snapped points. The messages follow the schema in `proto/ptolemy.proto`
+
+use super::data_types as json;
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use prost::Message;
+
+/// The media type of the responses encoded with Protocol Buffers
+pub const CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// Whether the client accepts a response encoded with Protocol Buffers, with the header
+/// `Accept: application/x-protobuf`. The other media types of the header are ignored, so that JSON
+/// is only answered when this one is missing
+pub fn accepts_protobuf(request: &HttpRequest) -> bool {
+    request
+        .headers()
+        .get_all(header::ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| media_type.split(';').next().unwrap_or("").trim() == CONTENT_TYPE)
+}
+
+/// Answer with the encoded message
+pub fn response<M: Message>(message: &M) -> HttpResponse {
+    let mut body = Vec::with_capacity(message.encoded_len());
+    message
+        .encode(&mut body)
+        .expect("A vector grows to fit the message");
+    HttpResponse::Ok().content_type(CONTENT_TYPE).body(body)
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RouteResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub waypoints: Vec<Waypoint>,
+    #[prost(message, repeated, tag = "2")]
+    pub routes: Vec<Route>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Waypoint {
+    #[prost(double, tag = "1")]
+    pub lon: f64,
+    #[prost(double, tag = "2")]
+    pub lat: f64,
+    #[prost(double, tag = "3")]
+    pub distance: f64,
+    #[prost(double, tag = "4")]
+    pub duration: f64,
+    #[prost(uint32, tag = "5")]
+    pub approach_bearing: u32,
+    #[prost(string, tag = "6")]
+    pub side: String,
+    #[prost(string, tag = "7")]
+    pub warning: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Route {
+    #[prost(uint32, tag = "1")]
+    pub distance: u32,
+    #[prost(double, tag = "2")]
+    pub duration: f64,
+    #[prost(message, optional, tag = "3")]
+    pub geometry: Option<Geometry>,
+    #[prost(message, repeated, tag = "4")]
+    pub steps: Vec<Step>,
+    #[prost(message, optional, tag = "5")]
+    pub annotation: Option<Annotation>,
+    #[prost(message, repeated, tag = "6")]
+    pub legs: Vec<Leg>,
+    #[prost(uint32, tag = "7")]
+    pub border_crossings: u32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Geometry {
+    #[prost(oneof = "GeometryKind", tags = "1, 2")]
+    pub kind: Option<GeometryKind>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum GeometryKind {
+    #[prost(string, tag = "1")]
+    Polyline(String),
+    #[prost(message, tag = "2")]
+    LineString(LineString),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LineString {
+    #[prost(string, tag = "1")]
+    pub crs: String,
+    /// The x and y of each point, one after the other
+    #[prost(double, repeated, tag = "2")]
+    pub coordinates: Vec<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Step {
+    #[prost(string, tag = "1")]
+    pub kind: String,
+    #[prost(string, tag = "2")]
+    pub modifier: String,
+    #[prost(uint32, tag = "3")]
+    pub exit: u32,
+    #[prost(string, tag = "4")]
+    pub instruction: String,
+    #[prost(double, tag = "5")]
+    pub lon: f64,
+    #[prost(double, tag = "6")]
+    pub lat: f64,
+    #[prost(uint32, tag = "7")]
+    pub distance: u32,
+    #[prost(double, tag = "8")]
+    pub duration: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Annotation {
+    #[prost(uint32, repeated, tag = "1")]
+    pub distance: Vec<u32>,
+    #[prost(double, repeated, tag = "2")]
+    pub duration: Vec<f64>,
+    #[prost(uint32, repeated, tag = "3")]
+    pub road_level: Vec<u32>,
+    #[prost(uint32, repeated, tag = "4")]
+    pub lanes: Vec<u32>,
+    #[prost(float, repeated, tag = "5")]
+    pub width: Vec<f32>,
+    #[prost(int64, repeated, tag = "6")]
+    pub way_id: Vec<i64>,
+    #[prost(double, repeated, tag = "7")]
+    pub cumulative_distance: Vec<f64>,
+}
+
+/// Without the statistics of `debug=true`, which are only sent in JSON
+#[derive(Clone, PartialEq, Message)]
+pub struct Leg {
+    #[prost(uint32, tag = "1")]
+    pub distance: u32,
+    #[prost(double, tag = "2")]
+    pub duration: f64,
+    #[prost(string, tag = "3")]
+    pub summary: String,
+    #[prost(message, optional, tag = "4")]
+    pub geometry: Option<Geometry>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TableResponse {
+    /// Row by row, from each point to each point
+    #[prost(uint32, repeated, tag = "1")]
+    pub distances: Vec<u32>,
+    #[prost(uint32, tag = "2")]
+    pub num_points: u32,
+    #[prost(bool, tag = "3")]
+    pub precomputed: bool,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SnapResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub snapped: Vec<SnappedPoint>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct SnappedPoint {
+    #[prost(double, tag = "1")]
+    pub lon: f64,
+    #[prost(double, tag = "2")]
+    pub lat: f64,
+    #[prost(uint64, tag = "3")]
+    pub edge: u64,
+    #[prost(double, tag = "4")]
+    pub distance: f64,
+}
+
+impl From<&json::RouteResponse> for RouteResponse {
+    fn from(response: &json::RouteResponse) -> Self {
+        RouteResponse {
+            waypoints: response.waypoints.iter().map(Waypoint::from).collect(),
+            routes: response.routes.iter().map(Route::from).collect(),
+        }
+    }
+}
+
+impl From<&json::WaypointResponse> for Waypoint {
+    fn from(waypoint: &json::WaypointResponse) -> Self {
+        Waypoint {
+            lon: waypoint.location[0],
+            lat: waypoint.location[1],
+            distance: waypoint.distance,
+            duration: waypoint.duration,
+            approach_bearing: waypoint.approach_bearing as u32,
+            side: waypoint.side.to_owned(),
+            warning: waypoint.warning.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&json::RouteItemResponse> for Route {
+    fn from(route: &json::RouteItemResponse) -> Self {
+        Route {
+            distance: route.distance,
+            duration: route.duration,
+            geometry: route.geometry.as_ref().map(Geometry::from),
+            steps: route
+                .steps
+                .iter()
+                .flatten()
+                .map(|step| Step {
+                    kind: step.kind.to_owned(),
+                    modifier: step.modifier.clone().unwrap_or_default(),
+                    exit: step.exit.unwrap_or(0),
+                    instruction: step.instruction.clone(),
+                    lon: step.location[0],
+                    lat: step.location[1],
+                    distance: step.distance,
+                    duration: step.duration,
+                })
+                .collect(),
+            annotation: route.annotation.as_ref().map(|annotation| Annotation {
+                distance: annotation.distance.clone(),
+                duration: annotation.duration.clone(),
+                road_level: annotation.road_level.iter().map(|&v| v as u32).collect(),
+                lanes: annotation.lanes.iter().map(|&v| v as u32).collect(),
+                width: annotation.width.clone(),
+                way_id: annotation.way_id.clone(),
+                cumulative_distance: annotation.cumulative_distance.clone(),
+            }),
+            legs: route
+                .legs
+                .iter()
+                .map(|leg| Leg {
+                    distance: leg.distance,
+                    duration: leg.duration,
+                    summary: leg.summary.clone(),
+                    geometry: leg.geometry.as_ref().map(Geometry::from),
+                })
+                .collect(),
+            border_crossings: route.border_crossings as u32,
+        }
+    }
+}
+
+impl From<&json::GeometryResponse> for Geometry {
+    fn from(geometry: &json::GeometryResponse) -> Self {
+        let kind = match geometry {
+            json::GeometryResponse::Polyline(polyline) => GeometryKind::Polyline(polyline.clone()),
+            json::GeometryResponse::LineString {
+                crs, coordinates, ..
+            } => GeometryKind::LineString(LineString {
+                crs: crs.clone(),
+                coordinates: coordinates.iter().flatten().copied().collect(),
+            }),
+        };
+        Geometry { kind: Some(kind) }
+    }
+}
+
+impl From<&json::TableResponse> for TableResponse {
+    fn from(response: &json::TableResponse) -> Self {
+        TableResponse {
+            distances: response.distances.iter().flatten().copied().collect(),
+            num_points: response.distances.len() as u32,
+            precomputed: response.precomputed,
+        }
+    }
+}
+
+impl From<&json::SnapResponse> for SnapResponse {
+    fn from(response: &json::SnapResponse) -> Self {
+        SnapResponse {
+            snapped: response.snapped.iter().map(SnappedPoint::from).collect(),
+        }
+    }
+}
+
+impl From<&json::SnappedPointResponse> for SnappedPoint {
+    fn from(point: &json::SnappedPointResponse) -> Self {
+        SnappedPoint {
+            lon: point.location[0],
+            lat: point.location[1],
+            edge: point.edge as u64,
+            distance: point.distance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn accept() {
+        let request =
+            |accept: &str| TestRequest::with_header(header::ACCEPT, accept).to_http_request();
+        assert!(accepts_protobuf(&request("application/x-protobuf")));
+        assert!(accepts_protobuf(&request(
+            "application/json;q=0.5, application/x-protobuf;q=0.9"
+        )));
+        assert!(!accepts_protobuf(&request("application/json")));
+        assert!(!accepts_protobuf(&TestRequest::default().to_http_request()));
+    }
+
+    #[test]
+    fn encode() {
+        let route = json::RouteResponse {
+            waypoints: vec![json::WaypointResponse {
+                location: [1.5218, 42.5063],
+                distance: 3.5,
+                duration: 0.,
+                approach_bearing: 97,
+                side: "left",
+                warning: None,
+            }],
+            routes: vec![json::RouteItemResponse {
+                distance: 12183,
+                duration: 842.5,
+                border_crossings: 1,
+                geometry: Some(json::GeometryResponse::LineString {
+                    kind: "LineString",
+                    crs: "EPSG:32631".to_owned(),
+                    coordinates: vec![[378553.5, 4707051.9], [378560., 4707060.]],
+                }),
+                steps: None,
+                annotation: None,
+                legs: vec![json::LegResponse {
+                    distance: 12183,
+                    duration: 842.5,
+                    summary: String::new(),
+                    geometry: Some(json::GeometryResponse::Polyline("_p~iF~ps|U".to_owned())),
+                    debug: None,
+                }],
+            }],
+        };
+        let message = RouteResponse::from(&route);
+        let mut bytes = Vec::new();
+        message.encode(&mut bytes).unwrap();
+        let decoded = RouteResponse::decode(bytes.as_slice()).unwrap();
+        assert_eq!(decoded, message);
+        assert_eq!(decoded.waypoints[0].side, "left");
+        assert_eq!(decoded.routes[0].distance, 12183);
+        assert_eq!(decoded.routes[0].border_crossings, 1);
+        assert_eq!(
+            decoded.routes[0].geometry.as_ref().unwrap().kind,
+            Some(GeometryKind::LineString(LineString {
+                crs: "EPSG:32631".to_owned(),
+                coordinates: vec![378553.5, 4707051.9, 378560., 4707060.],
+            }))
+        );
+        assert!(decoded.routes[0].steps.is_empty());
+        assert_eq!(decoded.routes[0].annotation, None);
+
+        // The packed distances are much smaller than their JSON
+        let table = json::TableResponse {
+            distances: vec![
+                vec![0, 12183, 950],
+                vec![11842, 0, 12790],
+                vec![870, 13001, 0],
+            ],
+            precomputed: false,
+        };
+        let message = TableResponse::from(&table);
+        assert_eq!(message.num_points, 3);
+        assert_eq!(message.distances[3], 11842);
+        let mut bytes = Vec::new();
+        message.encode(&mut bytes).unwrap();
+        assert_eq!(TableResponse::decode(bytes.as_slice()).unwrap(), message);
+        assert!(bytes.len() < serde_json::to_vec(&table).unwrap().len() / 2);
+    }
+}
@@ -0,0 +1,146 @@
+//! Limit how many requests each client can make, so that a public deployment is shared fairly
+
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the buckets that are full again are forgotten
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many requests a client can make: a sustained rate and a burst above it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    /// How many requests can be made at once after being idle
+    pub burst: u32,
+}
+
+/// The requests that a client can still make
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Clients {
+    buckets: HashMap<IpAddr, Bucket>,
+    /// The last time the full buckets were forgotten
+    pruned: Instant,
+}
+
+impl Clients {
+    /// Forget the clients whose buckets are full again, which behave like new ones
+    fn prune(&mut self, burst: f64, rate: f64, now: Instant) {
+        self.pruned = now;
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * rate < burst
+        });
+    }
+}
+
+/// A token bucket for each client IP address. Each request takes a token and the buckets are
+/// refilled at the sustained rate, up to the burst
+pub struct RateLimiter {
+    limit: RateLimit,
+    clients: Mutex<Clients>,
+}
+
+impl RateLimiter {
+    /// Fail with `InvalidInput` unless the rate is a positive number, since the clients could
+    /// never be told when to retry
+    pub fn new(limit: RateLimit) -> io::Result<Self> {
+        if !(limit.requests_per_second > 0. && limit.requests_per_second.is_finite()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid rate limit {}, expected a positive number of requests per second",
+                    limit.requests_per_second
+                ),
+            ));
+        }
+        Ok(RateLimiter {
+            limit,
+            clients: Mutex::new(Clients {
+                buckets: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        })
+    }
+
+    /// Take a token for a request of the client. When there is none, return how long to wait for
+    /// the next one
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let burst = self.limit.burst.max(1) as f64;
+        let rate = self.limit.requests_per_second;
+        let mut clients = self.clients.lock().unwrap();
+        if now.saturating_duration_since(clients.pruned) >= PRUNE_INTERVAL {
+            clients.prune(burst, rate, now);
+        }
+
+        let bucket = clients.buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1. {
+            bucket.tokens -= 1.;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1. - bucket.tokens) / rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_second: 2.,
+            burst: 3,
+        })
+        .unwrap();
+        let client = IpAddr::from([10, 0, 0, 1]);
+        let other = IpAddr::from([10, 0, 0, 2]);
+        let start = Instant::now();
+
+        // The burst is available at once, then the client must wait half a second per request
+        for _ in 0..3 {
+            assert_eq!(limiter.check_at(client, start), Ok(()));
+        }
+        let retry = limiter.check_at(client, start).unwrap_err();
+        assert!((retry.as_secs_f64() - 0.5).abs() < 1e-6);
+        assert_eq!(limiter.check_at(other, start), Ok(()));
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limiter.check_at(client, later), Ok(()));
+        assert!(limiter.check_at(client, later).is_err());
+
+        // The idle clients are forgotten
+        let much_later = start + PRUNE_INTERVAL * 2;
+        assert_eq!(limiter.check_at(client, much_later), Ok(()));
+        assert_eq!(limiter.clients.lock().unwrap().buckets.len(), 1);
+    }
+
+    #[test]
+    fn invalid_rate() {
+        for &rate in &[0., -2., f64::NAN, f64::INFINITY] {
+            let limit = RateLimit {
+                requests_per_second: rate,
+                burst: 3,
+            };
+            let error = RateLimiter::new(limit).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Run the queries outside of the threads that serve the requests, so that a slow query does not
+//! block the other requests handled by the same thread
+
+use ptolemy::{Cartograph, Deadline};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::{self, JoinError};
+use tracing::Span;
+
+/// Shared access to the cartography for the request handlers
+pub struct CartographService {
+    carto: Arc<Cartograph>,
+    /// Limit how many expensive queries run at the same time
+    heavy_queries: Semaphore,
+}
+
+impl CartographService {
+    /// Allow at most `max_heavy_queries` expensive queries at the same time
+    pub fn new(carto: Cartograph, max_heavy_queries: usize) -> Self {
+        CartographService {
+            carto: Arc::new(carto),
+            heavy_queries: Semaphore::new(max_heavy_queries.max(1)),
+        }
+    }
+
+    /// Direct access to the cartography, for the answers that do not need any calculation
+    pub fn carto(&self) -> &Cartograph {
+        &self.carto
+    }
+
+    /// Run a query in the blocking thread pool, in the span of the request. It only fails if the
+    /// query panics
+    pub async fn run<F, T>(&self, query: F) -> Result<T, JoinError>
+    where
+        F: FnOnce(&Cartograph) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let carto = Arc::clone(&self.carto);
+        let span = Span::current();
+        task::spawn_blocking(move || span.in_scope(|| query(&carto))).await
+    }
+
+    /// Like `run()`, but first wait until fewer than `max_heavy_queries` expensive queries are
+    /// running, so that they can not starve the cheap ones
+    pub async fn run_heavy<F, T>(&self, query: F) -> Result<T, JoinError>
+    where
+        F: FnOnce(&Cartograph) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self.heavy_queries.acquire().await;
+        self.run(query).await
+    }
+}
+
+/// Cancel a deadline when dropped. The request handlers hold one while their query runs, so that
+/// the query stops when the client disconnects and the handler is dropped
+pub struct CancelOnDrop(pub Deadline);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn run_heavy() {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let service = CartographService::new(carto, 1);
+        let num_nodes = service
+            .run_heavy(|carto| carto.graph.node_count())
+            .await
+            .unwrap();
+        assert_eq!(num_nodes, 3124);
+        assert_eq!(service.heavy_queries.available_permits(), 1);
+        assert!(service.run(|_| panic!("Query failed")).await.is_err());
+    }
+
+    #[test]
+    fn cancel_on_drop() {
+        let deadline = Deadline::never();
+        let guard = CancelOnDrop(deadline.clone());
+        assert!(!deadline.has_passed());
+        drop(guard);
+        assert!(deadline.has_passed());
+    }
+}
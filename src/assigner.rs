@@ -0,0 +1,109 @@
+//! Run traffic assignment studies from the command line
+
+use petgraph::graph::EdgeIndex;
+use petgraph::visit::EdgeRef;
+use ptolemy::*;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Assign the demands of a CSV file to the road network and write the volume of each edge as CSV.
+/// The edges closest to the points in `close` are closed, in both directions
+pub fn run_assign<P: AsRef<Path>>(
+    demand_file: P,
+    carto_file: P,
+    output: P,
+    mut options: AssignOptions,
+    close: &[GeoPoint],
+) -> io::Result<()> {
+    let mut timer = DebugTime::new();
+
+    let carto = Cartograph::open(carto_file)?;
+    timer.msg("Opened cartography");
+
+    let demands = read_demands(&carto, fs::read_to_string(demand_file)?)?;
+    timer.msg(format!("Read {} demands", format_num(demands.len())));
+
+    for point in close {
+        let edge = carto.project(point).edge;
+        let (source, target) = carto.graph.edge_endpoints(edge).unwrap();
+        options.closed_edges.push(edge);
+        options
+            .closed_edges
+            .extend(carto.graph.edges_connecting(target, source).map(|e| e.id()));
+    }
+
+    let assignment = carto.assign_traffic(&demands, &options);
+    timer.msg(format!(
+        "Assigned traffic in {} iterations, {:.1} vehicles/h could not reach their destination",
+        options.iterations, assignment.unassigned
+    ));
+
+    let mut writer = BufWriter::new(File::create(&output)?);
+    writeln!(
+        writer,
+        "edge,source_lat,source_lon,target_lat,target_lon,volume,cost"
+    )?;
+    for (i, (volume, cost)) in assignment.volumes.iter().zip(&assignment.costs).enumerate() {
+        let (_, source, target) = carto.edge_info(EdgeIndex::new(i));
+        writeln!(
+            writer,
+            "{},{},{},{},{},{:.1},{:.1}",
+            i,
+            source.lat.as_degrees(),
+            source.lon.as_degrees(),
+            target.lat.as_degrees(),
+            target.lon.as_degrees(),
+            volume,
+            cost
+        )?;
+    }
+    timer.msg(format!("Wrote results to {}", output.as_ref().display()));
+
+    Ok(())
+}
+
+/// Read a CSV with the columns `from_lat`, `from_lon`, `to_lat`, `to_lon` and `volume` (in vehicles
+/// per hour) and project the points
+fn read_demands(carto: &Cartograph, contents: String) -> io::Result<Vec<Demand>> {
+    let invalid_data = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<&str> = lines
+        .next()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .collect();
+    let find = |name: &str| {
+        header
+            .iter()
+            .position(|&column| column == name)
+            .ok_or_else(|| invalid_data(format!("Missing column {} in demand CSV", name)))
+    };
+    let columns = [
+        find("from_lat")?,
+        find("from_lon")?,
+        find("to_lat")?,
+        find("to_lon")?,
+        find("volume")?,
+    ];
+
+    lines
+        .map(|line| {
+            let values: Vec<&str> = line.split(',').map(str::trim).collect();
+            let mut parsed = [0.; 5];
+            for (value, &column) in parsed.iter_mut().zip(&columns) {
+                *value = values
+                    .get(column)
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| invalid_data(format!("Could not parse CSV line {}", line)))?;
+            }
+            Ok(Demand {
+                from: carto.project(&GeoPoint::from_degrees(parsed[0], parsed[1])),
+                to: carto.project(&GeoPoint::from_degrees(parsed[2], parsed[3])),
+                volume: parsed[4],
+            })
+        })
+        .collect()
+}
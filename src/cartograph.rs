@@ -1,130 +1,716 @@
+mod access;
+mod assign;
+mod borders;
+mod builder;
+mod checksum;
+mod closure;
+mod components;
+mod cost;
 mod data_types;
-mod sampler;
+mod deadline;
+mod diff;
+mod diversity;
+mod edge_based;
+mod error;
+mod experiment;
+mod format;
+mod graph_view;
+mod grid;
+mod hub_table;
+mod index;
+mod matching;
+mod metadata;
+mod mld;
+mod names;
+mod overlay;
+mod pareto;
+mod progress;
+mod round_trip;
+mod shapes;
+mod simplify;
+mod speed_override;
+mod speed_profile;
+mod stats;
+mod steps;
+mod style;
+mod surface;
+mod vehicle;
+mod virtual_nodes;
+mod zone;
 
 use data_types::*;
+use deadline::DeadlineCheck;
+use format::{ColumnReader, EdgeColumn};
 
+use crate::sampling::PrioritySample;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::sampling::PrioritySampler;
 use crate::utils::*;
-use byteorder::{LittleEndian, ReadBytesExt};
-use flate2::read::GzDecoder;
+use once_cell::sync::OnceCell;
 use petgraph::{
-    algo::{astar, kosaraju_scc},
+    algo::kosaraju_scc,
     graph::{EdgeIndex, NodeIndex},
-    visit::{EdgeRef, VisitMap, Visitable},
+    visit::EdgeRef,
     Graph,
 };
-use rstar::{RTree, AABB};
-use sampler::PrioritySample;
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+use rstar::{primitives::PointWithData, RTree, AABB};
 use std::cmp::Reverse;
-use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(not(target_arch = "wasm32"))]
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
+use std::mem::size_of;
+#[cfg(not(target_arch = "wasm32"))]
 use std::path::Path;
 
-pub use data_types::GraphPath;
+pub use access::{AccessClass, AccessPenalties};
+pub use assign::{AssignOptions, Assignment, Bpr, Demand};
+pub use borders::Borders;
+pub use builder::CartographBuilder;
+pub use checksum::ChecksumWriter;
+pub use closure::{ClosureWindow, Closures};
+pub use components::StronglyConnectedComponents;
+pub use cost::{
+    AccessAndSurfaces, ClosedEdges, CostModel, CycleRoutePreference, CyclewayPreference,
+    DistanceCost, LiveSpeeds,
+};
+pub use data_types::{
+    estimate_capacity, estimate_duration, estimate_speed, partial_distance, round_distance,
+    round_duration, Approach, EdgeInfo, GraphPath, ProjectedPoint, Side,
+};
+pub use deadline::Deadline;
+pub use diff::{ChangedDistance, GraphDiff};
+pub use diversity::DiversityOptions;
+pub use edge_based::{forbid_u_turns, EdgeBasedCartograph, Turn, TurnInfo};
+pub use error::{CartographError, Timeout};
+pub use experiment::Experiment;
+pub use format::{write_graph, FormatVersion};
+pub use graph_view::{GraphEdge, GraphNode};
+pub use grid::CostGrid;
+pub use hub_table::HubTable;
+pub use index::{EdgeRTree, IndexConfig};
+pub use matching::{MatchOptions, MatchedTrace};
+pub use metadata::Metadata;
+pub use mld::{MultiLevelOverlay, Partition, PartitionOptions};
+pub use names::RoadNames;
+pub use overlay::ExtraEdge;
+pub use pareto::{Ascent, Criterion, Distance, ParetoPath, Turns};
+pub use progress::{LoadProgress, LoadReport};
+pub use shapes::EdgeShapes;
+pub use speed_override::{SpeedOverride, SpeedOverrides, SpeedTarget, SpeedUpdate, SpeedUpdates};
+pub use speed_profile::SpeedProfiles;
+pub use stats::{GraphStats, MemoryUsage, SearchStats};
+pub use steps::{Maneuver, Step, TurnDirection};
+pub use style::RoadLevelStyle;
+pub use surface::{Smoothness, Surface, SurfacePenalties};
+pub use vehicle::{Restrictions, Vehicle};
+pub use zone::AvoidZone;
 
 pub struct Cartograph {
     /// The road map graph
     pub graph: Graph<GeoPoint, EdgeInfo>,
     /// The edges of the graph spatially indexed
-    pub rtree: RTree<LineWithData<EdgeIndex, [f64; 2]>>,
+    pub rtree: EdgeRTree,
+    /// Whether the path searches can use the edges that were invented by the generator to make the
+    /// graph strongly connected. Forbidding them makes some destinations unreachable
+    pub allow_synthetic: bool,
+    /// The strongly connected component of each node, indexed by the node index. Components are
+    /// numbered by decreasing size, so the main one is 0
+    components: Vec<u32>,
+    /// How the generator classified the roads in levels, with suggestions to draw them
+    pub road_levels: Vec<RoadLevelStyle>,
+    /// The nodes of the graph spatially indexed. Most uses only need the edges, so it is only built
+    /// by the first call to `node_rtree()`
+    node_rtree: OnceCell<RTree<PointWithData<NodeIndex, [f64; 2]>>>,
+    /// The edges of each road level spatially indexed, indexed by the road level, so that sampling a
+    /// wide region only searches the most important roads. Only built by the first call to
+    /// `level_rtrees()`
+    level_rtrees: OnceCell<Vec<EdgeRTree>>,
+    /// The edges that cross a border, sorted by their index. Only found by the first call to
+    /// `border_edges()`
+    border_edges: OnceCell<Vec<EdgeIndex>>,
+    /// The OSM id of each node, indexed by the node index, or empty when the file does not have them
+    osm_node_ids: Vec<i64>,
+    /// Where the file comes from, as written by the generator
+    metadata: Metadata,
+    /// The names of the roads, referenced by their edges
+    road_names: RoadNames,
+    /// The intermediate points of the contracted edges, referenced by them
+    edge_shapes: EdgeShapes,
+    /// Whether `save()` stores the Web Mercator coordinates of the nodes, so that opening the file
+    /// skips projecting them. Set when the opened file has them
+    pub projected_nodes: bool,
 }
 
 impl Cartograph {
     /// Create a cartography struct by reading the Ptolemy file
-    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Cartograph> {
-        let mut timer = crate::utils::DebugTime::new();
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Cartograph, CartographError> {
+        Cartograph::from_reader(File::open(path)?)
+    }
+
+    /// Create a cartography struct by reading the Ptolemy file, calling `progress` as each stage of the
+    /// loading is done. This is useful to display the progress when opening large files
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open_with_progress<P: AsRef<Path>, F: FnMut(LoadProgress)>(
+        path: P,
+        progress: F,
+    ) -> Result<Cartograph, CartographError> {
+        Ok(Cartograph::open_with(path, progress)?.0)
+    }
+
+    /// Like `open_with_progress()`, but also return how long each phase of the loading took, to find
+    /// where the time goes when opening large files
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open_with<P: AsRef<Path>, F: FnMut(LoadProgress)>(
+        path: P,
+        progress: F,
+    ) -> Result<(Cartograph, LoadReport), CartographError> {
+        Cartograph::open_with_config(path, &IndexConfig::default(), progress)
+    }
+
+    /// Like `open_with()`, but choose how to build the spatial index of the edges, to trade query
+    /// speed for memory or loading time
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open_with_config<P: AsRef<Path>, F: FnMut(LoadProgress)>(
+        path: P,
+        config: &IndexConfig,
+        mut progress: F,
+    ) -> Result<(Cartograph, LoadReport), CartographError> {
+        let file = File::open(path)?;
+        let total_bytes = file.metadata()?.len();
+        Cartograph::read_with_progress(file, Some(total_bytes), config, &mut progress)
+    }
+
+    /// Read the statistics of a Ptolemy file. They are usually stored at the end of the file, so this
+    /// is instant. Otherwise, the whole file is loaded to compute them
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_stats<P: AsRef<Path>>(path: P) -> Result<GraphStats, CartographError> {
+        match GraphStats::read_footer(&mut File::open(&path)?)? {
+            Some(stats) => Ok(stats),
+            None => Ok(Cartograph::open(path)?.stats()),
+        }
+    }
+
+    /// Check that a Ptolemy file is not corrupted, without decoding it. Returns the checksum, or an
+    /// error if the file has no checksum or it does not match. Note that `open()` also verifies the
+    /// checksum, when present
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn verify_checksum<P: AsRef<Path>>(path: P) -> Result<u32, CartographError> {
+        checksum::verify_file(&mut File::open(path)?)
+    }
+
+    /// Read the version of the format of a Ptolemy file, from its first bytes. Files in older
+    /// versions can still be opened, and `save()` writes them in the latest one
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_version<P: AsRef<Path>>(path: P) -> Result<FormatVersion, CartographError> {
+        Ok(ColumnReader::new(File::open(path)?)?.version)
+    }
+
+    /// Create a cartography struct from the contents of a Ptolemy file already in memory. This is
+    /// the way to load the data when compiling to WebAssembly, for example after fetching the file
+    pub fn from_bytes(bytes: &[u8]) -> Result<Cartograph, CartographError> {
+        Cartograph::from_reader(bytes)
+    }
+
+    /// Create a cartography struct by reading the Ptolemy format from any source
+    pub fn from_reader<R: Read>(reader: R) -> Result<Cartograph, CartographError> {
+        Cartograph::from_reader_with_config(reader, &IndexConfig::default())
+    }
+
+    /// Like `from_reader()`, but choose how to build the spatial index of the edges. In
+    /// WebAssembly, storing it in single precision saves memory
+    pub fn from_reader_with_config<R: Read>(
+        reader: R,
+        config: &IndexConfig,
+    ) -> Result<Cartograph, CartographError> {
+        Ok(Cartograph::read_with_progress(reader, None, config, &mut |_| {})?.0)
+    }
+
+    /// Write the cartography to a Ptolemy file, for example after modifying it in memory. The nodes
+    /// and edges are sorted like the generator does, so their indexes may change when opening it
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(io::BufWriter::new(File::create(path)?))
+    }
+
+    /// Write the cartography in the Ptolemy format to any destination
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        format::write_graph(
+            writer,
+            &self.graph,
+            |&point| point,
+            |&info| info,
+            Some(&self.osm_node_ids),
+            &self.road_levels,
+            &self.road_names,
+            &self.edge_shapes,
+            &self.metadata,
+            self.projected_nodes,
+        )
+    }
 
-        // Open file and read header
-        let mut file = GzDecoder::new(File::open(path)?);
-        let num_nodes = file.read_u32::<LittleEndian>()? as usize;
-        let num_edges = file.read_u32::<LittleEndian>()? as usize;
+    /// Decoding happens in `ColumnReader::read_column()`, projecting in `project_edges()`, indexing in
+    /// `build_rtree()` and labeling in `label_components()`, so that only the insertions in the
+    /// graph are left in this function when looking at a flamegraph
+    fn read_with_progress<R: Read>(
+        reader: R,
+        total_bytes: Option<u64>,
+        config: &IndexConfig,
+        progress: &mut dyn FnMut(LoadProgress),
+    ) -> Result<(Cartograph, LoadReport), CartographError> {
+        let mut timer = crate::utils::DebugTime::new();
+        let mut stopwatch = Stopwatch::start();
+        let mut report = LoadReport::default();
+        let mut reader = ColumnReader::new(reader)?;
+        let (num_nodes, num_edges) = (reader.num_nodes, reader.num_edges);
+        let decoded = |reader: &ColumnReader<R>| LoadProgress::Decoded {
+            bytes: reader.bytes(),
+            total_bytes,
+        };
 
         // Read nodes and insert into graph
         let mut graph = Graph::new();
-        let latitudes = Cartograph::read_delta_encoded(&mut file, num_nodes)?;
-        let longitudes = Cartograph::read_delta_encoded(&mut file, num_nodes)?;
+        let latitudes = reader.read_column(num_nodes, "latitudes")?;
+        progress(decoded(&reader));
+        let longitudes = reader.read_column(num_nodes, "longitudes")?;
+        progress(decoded(&reader));
+        report.decode += stopwatch.lap();
         for (lat, lon) in latitudes.into_iter().zip(longitudes.into_iter()) {
             graph.add_node(GeoPoint::from_micro_degrees(lat, lon));
         }
+        report.graph_insert += stopwatch.lap();
         timer.msg(format!("Read {} nodes", format_num(num_nodes)));
+        progress(LoadProgress::NodesBuilt { num_nodes });
+
+        // Read edges
+        let sources_offset = reader.bytes();
+        let sources = reader.read_column(num_edges, "sources")?;
+        progress(decoded(&reader));
+        let targets_offset = reader.bytes();
+        let targets = reader.read_column(num_edges, "targets")?;
+        progress(decoded(&reader));
+        let distances = reader.read_column(num_edges, "distances")?;
+        progress(decoded(&reader));
+        let road_levels = reader.read_column(num_edges, "road_levels")?;
+        progress(decoded(&reader));
+        let mut infos: Vec<EdgeInfo> = distances
+            .into_iter()
+            .zip(road_levels)
+            .map(|(distance, road_level)| EdgeInfo::new(distance as u32, road_level as u8))
+            .collect();
 
-        // Read edges and insert into graph
-        let sources = Cartograph::read_delta_encoded(&mut file, num_edges)?;
-        let targets = Cartograph::read_delta_encoded(&mut file, num_edges)?;
-        let distances = Cartograph::read_delta_encoded(&mut file, num_edges)?;
-        let road_levels = Cartograph::read_delta_encoded(&mut file, num_edges)?;
-        for (((source, target), distance), road_level) in sources
+        // Read the extra columns, skipping unknown ones
+        let mut road_levels = RoadLevelStyle::defaults();
+        let mut osm_node_ids = Vec::new();
+        let mut osm_node_ids_high = Vec::new();
+        let mut metadata = Metadata::default();
+        let mut road_names = RoadNames::default();
+        let mut edge_shapes = EdgeShapes::default();
+        let mut projected_nodes = None;
+        while let Some(name) = reader.read_section_name()? {
+            if let Some(column) = EdgeColumn::find(&name) {
+                column.unpack(&mut infos, reader.read_column(num_edges, &name)?);
+            } else {
+                match name.as_str() {
+                    format::NODE_IDS_COLUMN => {
+                        osm_node_ids = reader.read_column(num_nodes, &name)?;
+                    }
+                    format::NODE_IDS_HIGH_COLUMN => {
+                        osm_node_ids_high = reader.read_column(num_nodes, &name)?;
+                    }
+                    style::ROAD_LEVELS_SECTION => {
+                        road_levels = reader.read_section(&name, RoadLevelStyle::read_section)?;
+                    }
+                    metadata::METADATA_SECTION => {
+                        metadata = reader.read_section(&name, Metadata::read_section)?;
+                    }
+                    names::ROAD_NAMES_SECTION => {
+                        road_names = reader.read_section(&name, RoadNames::read_section)?;
+                    }
+                    shapes::EDGE_SHAPES_SECTION => {
+                        edge_shapes = reader.read_section(&name, EdgeShapes::read_section)?;
+                    }
+                    format::PROJECTED_NODES_SECTION => {
+                        projected_nodes = Some(reader.read_section(&name, |reader| {
+                            format::read_projected_nodes(reader, num_nodes)
+                        })?);
+                    }
+                    checksum::CHECKSUM_SECTION => reader.verify_checksum()?,
+                    _ => reader.skip_section(&name)?,
+                }
+            }
+            progress(decoded(&reader));
+        }
+        let osm_node_ids: Vec<i64> = osm_node_ids
             .into_iter()
-            .zip(targets.into_iter())
-            .zip(distances.into_iter())
-            .zip(road_levels.into_iter())
-        {
+            .enumerate()
+            .map(|(i, low)| {
+                let high = osm_node_ids_high.get(i).copied().unwrap_or(0);
+                low as u32 as i64 | (high as i64) << 32
+            })
+            .collect();
+        report.decode += stopwatch.lap();
+        report.bytes = reader.bytes();
+
+        // Insert edges into graph, checking that a corrupted column does not point to missing nodes
+        let check_nodes = |column: &[i32], name: &str, offset: u64| {
+            if column
+                .iter()
+                .all(|&node| node >= 0 && (node as usize) < num_nodes)
+            {
+                Ok(())
+            } else {
+                Err(CartographError::Corrupt {
+                    column: name.to_owned(),
+                    offset,
+                })
+            }
+        };
+        check_nodes(&sources, "sources", sources_offset)?;
+        check_nodes(&targets, "targets", targets_offset)?;
+        for ((source, target), info) in sources.into_iter().zip(targets).zip(infos) {
             graph.add_edge(
                 NodeIndex::new(source as usize),
                 NodeIndex::new(target as usize),
-                EdgeInfo {
-                    distance: distance as u32,
-                    road_level: road_level as u8,
-                },
+                info,
             );
         }
+        report.graph_insert += stopwatch.lap();
         timer.msg(format!("Read {} edges", format_num(num_edges)));
+        progress(LoadProgress::EdgesBuilt { num_edges });
 
         // Build spatial index
-        let edge_elements: Vec<LineWithData<EdgeIndex, [f64; 2]>> = graph
-            .edge_references()
-            .map(|edge| {
-                let source_node = graph[edge.source()];
-                let target_node = graph[edge.target()];
-                LineWithData::new(
-                    edge.id(),
-                    source_node.web_mercator_project(),
-                    target_node.web_mercator_project(),
-                )
-            })
-            .collect();
+        let edge_elements =
+            Cartograph::project_edges(&graph, &edge_shapes, projected_nodes.as_deref());
+        report.project = stopwatch.lap();
         timer.msg("Projected edges");
 
-        let rtree = RTree::bulk_load(edge_elements);
+        let rtree = Cartograph::build_rtree(edge_elements, config);
+        report.rtree_build = stopwatch.lap();
         timer.msg("Created spatial index");
+        progress(LoadProgress::IndexBuilt);
 
-        Ok(Cartograph { graph, rtree })
+        let components = Cartograph::label_components(&graph);
+        report.components = stopwatch.lap();
+        timer.msg("Labeled connected components");
+        progress(LoadProgress::ComponentsLabeled);
+
+        let carto = Cartograph {
+            graph,
+            rtree,
+            allow_synthetic: true,
+            components,
+            road_levels,
+            node_rtree: OnceCell::new(),
+            level_rtrees: OnceCell::new(),
+            border_edges: OnceCell::new(),
+            osm_node_ids,
+            metadata,
+            road_names,
+            edge_shapes,
+            projected_nodes: projected_nodes.is_some(),
+        };
+        Ok((carto, report))
+    }
+
+    /// The segments of the edges as lines in Web Mercator, one for a straight edge and one more for
+    /// each point of the shape of a contracted edge, taking the coordinates of their nodes from
+    /// `projected_nodes` when the file stores them
+    #[inline(never)]
+    fn project_edges(
+        graph: &Graph<GeoPoint, EdgeInfo>,
+        edge_shapes: &EdgeShapes,
+        projected_nodes: Option<&[[f64; 2]]>,
+    ) -> Vec<LineWithData<EdgeIndex, [f64; 2]>> {
+        let project = |node: NodeIndex| match projected_nodes {
+            Some(projected) => projected[node.index()],
+            None => graph[node].web_mercator_project(),
+        };
+        let mut lines = Vec::with_capacity(graph.edge_count());
+        for edge in graph.edge_references() {
+            let mut from = project(edge.source());
+            for point in edge_shapes.get(edge.weight().shape) {
+                let to = point.web_mercator_project();
+                lines.push(LineWithData::new(edge.id(), from, to));
+                from = to;
+            }
+            lines.push(LineWithData::new(edge.id(), from, project(edge.target())));
+        }
+        lines
+    }
+
+    #[inline(never)]
+    fn build_rtree(
+        edge_elements: Vec<LineWithData<EdgeIndex, [f64; 2]>>,
+        config: &IndexConfig,
+    ) -> EdgeRTree {
+        EdgeRTree::build(edge_elements, config)
     }
 
     /// Returns a sample of the edges inside a given region, described by two opposite corners in x, y coordinates.
     /// This function can return less than `max_num` even when there are more than that, please refer to the
-    ///  PrioritySample trait to understand how sampling works.
+    /// `sampling` module to understand how sampling works.
+    /// With `max_road_level`, only the edges of that level or more important ones are searched, like
+    /// for a zoomed-out view (see `max_road_level_at_zoom()`).
     /// The returned values is a map from road_level to a list of edge indexes
-    pub fn sample_edges<'a>(
-        &'a self,
+    pub fn sample_edges(
+        &self,
         xy1: [f64; 2],
         xy2: [f64; 2],
         max_num: usize,
+        max_road_level: Option<u8>,
     ) -> BTreeMap<u8, Vec<EdgeIndex>> {
-        // Build search envelope (only x and y coordinates are needed)
-        let envelope = AABB::from_corners(xy1, xy2);
+        self.sample_edges_filtered(xy1, xy2, max_num, max_road_level, |_| true)
+    }
 
-        let sampled = self
-            .rtree
-            .locate_in_envelope_intersecting(&envelope)
-            .sample_with_priority(max_num, |r_tree_element| {
-                let edge = self.graph[r_tree_element.data];
-                -(edge.road_level as i32)
-            });
+    /// Like `sample_edges()`, but only sample the edges whose info satisfies `filter`, like the ones
+    /// of the signed cycle routes with `|info| info.bicycle_route`
+    pub fn sample_edges_filtered(
+        &self,
+        xy1: [f64; 2],
+        xy2: [f64; 2],
+        max_num: usize,
+        max_road_level: Option<u8>,
+        filter: impl Fn(&EdgeInfo) -> bool,
+    ) -> BTreeMap<u8, Vec<EdgeIndex>> {
+        let priority = |&edge: &EdgeIndex| -(self.graph[edge].road_level as i32);
+        let selected = |edge: &EdgeIndex| filter(&self.graph[*edge]);
+        let sampled = match max_road_level {
+            None => self
+                .rtree
+                .locate(xy1, xy2)
+                .filter(selected)
+                .sample_with_priority(max_num, priority),
+            Some(max_road_level) => self
+                .level_rtrees()
+                .iter()
+                .take(max_road_level as usize + 1)
+                .flat_map(|rtree| rtree.locate(xy1, xy2))
+                .filter(selected)
+                .sample_with_priority(max_num, priority),
+        };
 
         // Convert from interval RTree representation to a more API-friendly return
         sampled
             .into_iter()
-            .map(|(priority, elements)| {
-                (
-                    -priority as u8,
-                    elements.into_iter().map(|e| e.data).collect(),
-                )
+            .map(|(priority, elements)| (-priority as u8, elements))
+            .collect()
+    }
+
+    /// Like `sample_edges()`, with the same result, but search vertical strips of the region in
+    /// parallel and merge their samples, for the huge regions of zoomed-out views. Each edge is
+    /// sampled by the strip that has the lowest corner of the envelopes of its segments in the
+    /// region, so that the edges crossing several strips are not counted twice
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn sample_edges_parallel(
+        &self,
+        xy1: [f64; 2],
+        xy2: [f64; 2],
+        max_num: usize,
+        max_road_level: Option<u8>,
+    ) -> BTreeMap<u8, Vec<EdgeIndex>> {
+        let rtrees = match max_road_level {
+            None => std::slice::from_ref(&self.rtree),
+            Some(max_road_level) => {
+                let level_rtrees = self.level_rtrees();
+                &level_rtrees[..level_rtrees.len().min(max_road_level as usize + 1)]
+            }
+        };
+        let (min_x, max_x) = (xy1[0].min(xy2[0]), xy1[0].max(xy2[0]));
+        let (min_y, max_y) = (xy1[1].min(xy2[1]), xy1[1].max(xy2[1]));
+        let width = max_x - min_x;
+        let num_strips = if width > 0. {
+            rayon::current_num_threads() * 2
+        } else {
+            1
+        };
+        let strip_x = |i: usize| {
+            if i == num_strips {
+                max_x
+            } else {
+                min_x + width * i as f64 / num_strips as f64
+            }
+        };
+
+        // The segments of a contracted edge may be found by several strips, which all need the
+        // lowest corner of the ones in the region
+        let lowest_x = |edge: EdgeIndex| {
+            let points: Vec<[f64; 2]> = self
+                .edge_geometry(edge)
+                .iter()
+                .map(GeoPoint::web_mercator_project)
+                .collect();
+            points
+                .windows(2)
+                .filter(|pair| {
+                    pair[0][0].max(pair[1][0]) >= min_x
+                        && pair[0][0].min(pair[1][0]) <= max_x
+                        && pair[0][1].max(pair[1][1]) >= min_y
+                        && pair[0][1].min(pair[1][1]) <= max_y
+                })
+                .map(|pair| pair[0][0].min(pair[1][0]))
+                .fold(f64::INFINITY, f64::min)
+        };
+
+        let sampler = (0..num_strips)
+            .into_par_iter()
+            .map(|i| {
+                let (start, end) = (strip_x(i), strip_x(i + 1));
+                let mut sampler = PrioritySampler::new(max_num);
+                let mut contracted = HashSet::new();
+                for rtree in rtrees {
+                    for (edge, corner) in rtree.locate_with_corner([start, xy1[1]], [end, xy2[1]]) {
+                        let corner_x = if self.graph[edge].shape == 0 {
+                            corner[0]
+                        } else if contracted.insert(edge) {
+                            lowest_x(edge)
+                        } else {
+                            continue;
+                        };
+                        let x = corner_x.max(min_x).min(max_x);
+                        if x >= start && (x < end || i + 1 == num_strips) {
+                            sampler.update(edge, -(self.graph[edge].road_level as i32));
+                        }
+                    }
+                }
+                sampler
+            })
+            .reduce(
+                || PrioritySampler::new(max_num),
+                |mut sampler, other| {
+                    sampler.merge(other);
+                    sampler
+                },
+            );
+
+        sampler
+            .finish()
+            .into_iter()
+            .map(|(priority, elements)| (-priority as u8, elements))
+            .collect()
+    }
+
+    /// Like `sample_edges()`, but merge the sampled edges of each road level that are adjacent and
+    /// roughly collinear (turning by at most `max_angle` degrees) into polylines. At low zoom levels,
+    /// this returns far fewer lines to draw. The returned value is a map from road_level to a list
+    /// of polylines
+    pub fn sample_polylines(
+        &self,
+        xy1: [f64; 2],
+        xy2: [f64; 2],
+        max_num: usize,
+        max_road_level: Option<u8>,
+        max_angle: f64,
+    ) -> BTreeMap<u8, Vec<Vec<GeoPoint>>> {
+        self.sample_edges(xy1, xy2, max_num, max_road_level)
+            .into_iter()
+            .map(|(level, edges)| {
+                let polylines = simplify::merge_collinear(&self.graph, &edges, max_angle);
+                (level, polylines)
             })
             .collect()
     }
 
+    /// The least important road level drawn at a zoom level, according to the `min_zoom` of the
+    /// styles of the road levels, or `None` if no road is drawn at that zoom
+    pub fn max_road_level_at_zoom(&self, zoom: u8) -> Option<u8> {
+        self.road_levels
+            .iter()
+            .filter(|style| style.min_zoom <= zoom)
+            .map(|style| style.level)
+            .max()
+    }
+
+    /// The id of the OSM node of a node of the graph, to find it in openstreetmap.org. Only known
+    /// when the generator was run with `--osm-ids`
+    pub fn osm_node_id(&self, node: NodeIndex) -> Option<i64> {
+        self.osm_node_ids.get(node.index()).copied()
+    }
+
+    /// The key-value pairs about where the file comes from, like `Metadata::SOURCE`. Empty for the
+    /// files written by older generators
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// The names of the roads, empty for the files written by older generators
+    pub fn road_names(&self) -> &RoadNames {
+        &self.road_names
+    }
+
+    /// The name of the road of an edge, like `CG-2`, if it has one
+    pub fn road_name(&self, info: &EdgeInfo) -> Option<&str> {
+        self.road_names.get(info.name)
+    }
+
+    /// The intermediate points of the contracted edges, empty when the generator did not contract
+    /// any
+    pub fn edge_shapes(&self) -> &EdgeShapes {
+        &self.edge_shapes
+    }
+
+    /// The points of an edge, from its source to its target, with the intermediate points of its
+    /// shape when it was contracted by the generator
+    pub fn edge_geometry(&self, edge: EdgeIndex) -> Vec<GeoPoint> {
+        let (source, target) = self.graph.edge_endpoints(edge).unwrap();
+        let shape = self.edge_shapes.get(self.graph[edge].shape);
+        let mut points = Vec::with_capacity(shape.len() + 2);
+        points.push(self.graph[source]);
+        points.extend_from_slice(shape);
+        points.push(self.graph[target]);
+        points
+    }
+
+    /// Append the points of an edge to a path that already ends at its source: the intermediate
+    /// points of its shape, then its target
+    pub fn push_edge_points(&self, edge: EdgeIndex, points: &mut Vec<GeoPoint>) {
+        points.extend_from_slice(self.edge_shapes.get(self.graph[edge].shape));
+        points.push(self.graph[self.graph.edge_endpoints(edge).unwrap().1]);
+    }
+
+    /// The points of an edge, like `edge_geometry()`, with their position on it, as a fraction of
+    /// its length like `ProjectedPoint::edge_pos`
+    fn measured_geometry(&self, edge: EdgeIndex) -> Vec<(GeoPoint, f32)> {
+        let points = self.edge_geometry(edge);
+        let mut lengths = Vec::with_capacity(points.len());
+        let mut length = 0.;
+        lengths.push(length);
+        for pair in points.windows(2) {
+            length += pair[0].haversine_distance(&pair[1]);
+            lengths.push(length);
+        }
+        points
+            .into_iter()
+            .zip(lengths)
+            .map(|(point, distance)| (point, (distance / length) as f32))
+            .collect()
+    }
+
+    /// Append the points of the shape of an edge that are strictly between two positions on it, to
+    /// a path that starts or ends inside of the edge
+    fn push_partial_points(
+        &self,
+        edge: EdgeIndex,
+        start_pos: f32,
+        end_pos: f32,
+        points: &mut Vec<GeoPoint>,
+    ) {
+        if self.graph[edge].shape == 0 {
+            return;
+        }
+        let measured = self.measured_geometry(edge);
+        points.extend(
+            measured[1..measured.len() - 1]
+                .iter()
+                .filter(|&&(_, pos)| pos > start_pos && pos < end_pos)
+                .map(|&(point, _)| point),
+        );
+    }
+
     /// Return the full information about a given edge index
     pub fn edge_info(&self, edge: EdgeIndex) -> (&EdgeInfo, &GeoPoint, &GeoPoint) {
         let weight = &self.graph[edge];
@@ -132,256 +718,3021 @@ impl Cartograph {
         (weight, &self.graph[endpoints.0], &self.graph[endpoints.1])
     }
 
-    /// Compute the strongly connected components
-    pub fn strongly_connected_components(&self) -> Vec<Vec<NodeIndex>> {
-        kosaraju_scc(&self.graph)
+    /// All the nodes of the graph, by increasing index
+    pub fn nodes(&self) -> impl Iterator<Item = GraphNode<'_>> + '_ {
+        self.graph.node_indices().map(move |index| GraphNode {
+            index,
+            point: &self.graph[index],
+        })
     }
 
-    /// Find the arc that is closest to a given point. This is usually the first step before being able to
-    /// walk the graph searching for shortest paths.
-    pub fn project(&self, point: &GeoPoint) -> ProjectedPoint {
-        let xy = point.web_mercator_project();
-        let r_tree_element = self.rtree.nearest_neighbor(&xy).unwrap();
-
-        // Convert result to GeoPoint
-        let projected = GeoPoint::from_web_mercator(r_tree_element.nearest_point(&xy));
-        // Get source and target geo points
-        let edge_index = r_tree_element.data;
-        let (source, target) = self.graph.edge_endpoints(edge_index).unwrap();
-        let source = self.graph[source];
-        let target = self.graph[target];
-
-        // Calculate the ratio over the edge where the result is
-        let dist_to_source = projected.haversine_distance(&source);
-        let dist_to_target = projected.haversine_distance(&target);
-        let edge_pos = (dist_to_source / (dist_to_source + dist_to_target)) as f32;
-
-        ProjectedPoint {
-            original: point.clone(),
-            projected,
-            edge: edge_index,
-            edge_pos,
-        }
+    /// All the directed edges of the graph, by increasing index
+    pub fn edges(&self) -> impl Iterator<Item = GraphEdge<'_>> + '_ {
+        self.graph
+            .edge_references()
+            .map(move |edge| GraphEdge::from_ref(&self.graph, edge))
     }
 
-    /// Find the shortest path between two projected points. Use project() to generate them
-    pub fn shortest_path(&self, from: &ProjectedPoint, to: &ProjectedPoint) -> GraphPath {
-        // Run A* search from graph nodes
-        let start_node = self.graph.edge_endpoints(from.edge).unwrap().1;
-        let end_node = self.graph.edge_endpoints(to.edge).unwrap().0;
-        let end_node_point = self.graph[end_node];
-        let (mut distance, nodes) = astar(
-            &self.graph,
-            start_node,
-            |node| node == end_node,
-            |edge_ref| edge_ref.weight().distance,
-            |node| self.graph[node].haversine_distance(&end_node_point) as u32,
-        )
-        .unwrap();
+    /// The edges that leave a node
+    pub fn out_edges(&self, node: NodeIndex) -> impl Iterator<Item = GraphEdge<'_>> + '_ {
+        self.graph
+            .edges(node)
+            .map(move |edge| GraphEdge::from_ref(&self.graph, edge))
+    }
 
-        // Build final sequence of geo points
-        let mut points = Vec::with_capacity(nodes.len() + 2);
-        points.push(from.projected);
-        points.extend(nodes.into_iter().map(|node| self.graph[node]));
-        points.push(to.projected);
+    /// The edges whose bounding box intersects a region, described by two opposite corners,
+    /// sorted by their index
+    pub fn edges_in_bbox(&self, corner1: &GeoPoint, corner2: &GeoPoint) -> Vec<GraphEdge<'_>> {
+        let mut edges: Vec<EdgeIndex> = self
+            .rtree
+            .locate(
+                corner1.web_mercator_project(),
+                corner2.web_mercator_project(),
+            )
+            .collect();
+        edges.sort_unstable();
+        edges
+            .into_iter()
+            .map(|edge| GraphEdge::new(&self.graph, edge))
+            .collect()
+    }
 
-        // Add initial and final segment distances
-        let extra_start_cost =
-            (self.graph[from.edge].distance as f32 * (1. - from.edge_pos)) as u32;
-        let extra_end_cost = (self.graph[to.edge].distance as f32 * to.edge_pos) as u32;
-        distance += extra_start_cost + extra_end_cost;
+    /// The edges inside a polygon or crossing its border, sorted by their index, like the roads of
+    /// a congestion charge zone. The polygon does not need to be closed (see `AvoidZone::edges()`)
+    pub fn edges_in_polygon(&self, polygon: &[GeoPoint]) -> Vec<EdgeIndex> {
+        AvoidZone::new(vec![polygon.to_vec()]).edges(self)
+    }
 
-        GraphPath::new(distance, points)
+    /// The nodes inside a polygon, sorted by their index (see `AvoidZone::nodes()`)
+    pub fn nodes_in_polygon(&self, polygon: &[GeoPoint]) -> Vec<NodeIndex> {
+        AvoidZone::new(vec![polygon.to_vec()]).nodes(self)
     }
 
-    /// Find the shortest path length from a single starting point to multiple destinations.
-    /// This method is more perfomant than calculating each path individually, however only the distance is
-    /// returned, unlike shortest_path()
-    pub fn shortest_path_multi(&self, from: &ProjectedPoint, to: &Vec<ProjectedPoint>) -> Vec<u32> {
-        // Prepare starting node
-        let start_node = self.graph.edge_endpoints(from.edge).unwrap().1;
-        let extra_start_cost =
-            (self.graph[from.edge].distance as f32 * (1. - from.edge_pos)) as u32;
-
-        // Prepare ending nodes
-        let mut final_costs = vec![0; to.len()];
-        let mut remaining_ends: Vec<(usize, u32, NodeIndex, GeoPoint)> = to
-            .into_iter()
-            .enumerate()
-            .map(|(i, to)| {
-                let end_node = self.graph.edge_endpoints(to.edge).unwrap().0;
-                let end_node_point = self.graph[end_node];
-                let extra_end_cost = (self.graph[to.edge].distance as f32 * to.edge_pos) as u32;
-                (i, extra_end_cost, end_node, end_node_point)
-            })
+    /// Flag the edges whose geometry crosses the borders, and only them, like the generator does
+    /// with `--borders`. Returns how many edges cross them
+    pub fn mark_borders(&mut self, borders: &Borders) -> usize {
+        let crossing: Vec<bool> = self
+            .graph
+            .edge_indices()
+            .map(|edge| borders.crosses(&self.edge_geometry(edge)))
             .collect();
-
-        let mut visited = self.graph.visit_map();
-        let mut visit_next: BinaryHeap<Reverse<(u32, NodeIndex)>> = BinaryHeap::new();
-        let mut scores = HashMap::new();
-
-        fn estimate_cost(
-            carto: &Cartograph,
-            remaining_ends: &Vec<(usize, u32, NodeIndex, GeoPoint)>,
-            node: NodeIndex,
-        ) -> u32 {
-            remaining_ends
-                .iter()
-                .map(|(_, _, _, end_node_point)| {
-                    carto.graph[node].haversine_distance(end_node_point) as u32
-                })
-                .min()
-                .unwrap()
+        for (info, border) in self.graph.edge_weights_mut().zip(crossing) {
+            info.border = border;
         }
+        self.border_edges = OnceCell::new();
+        self.border_edges().len()
+    }
 
-        scores.insert(start_node, 0);
-        visit_next.push(Reverse((
-            estimate_cost(self, &remaining_ends, start_node),
-            start_node,
-        )));
-
-        while let Some(Reverse((_, node))) = visit_next.pop() {
-            while let Some(pos) = remaining_ends
-                .iter()
-                .position(|(_, _, end_node, _)| node == *end_node)
-            {
-                // Reached one final node
-                let (i, extra_end_cost, _, _) = remaining_ends.remove(pos);
-                let cost = scores[&node];
-                final_costs[i] = extra_start_cost + cost + extra_end_cost;
-
-                if remaining_ends.len() == 0 {
-                    return final_costs;
-                }
-            }
+    /// The edges that cross a border, sorted by their index, like the ones a route can avoid.
+    /// Found by the first call, so the flags changed after that are ignored
+    pub fn border_edges(&self) -> &[EdgeIndex] {
+        self.border_edges.get_or_init(|| {
+            self.graph
+                .edge_references()
+                .filter(|edge| edge.weight().border)
+                .map(|edge| edge.id())
+                .collect()
+        })
+    }
 
-            // Don't visit the same node several times, as the first time it was visited it was using
-            // the shortest available path.
-            if !visited.visit(node) {
-                continue;
-            }
+    /// How many times a path crosses a border, as the number of the edges in `path.edges` that
+    /// cross one. The edges where it starts and ends count even when it only travels part of them
+    pub fn border_crossings(&self, path: &GraphPath) -> usize {
+        path.edges
+            .iter()
+            .filter(|&&edge| self.graph[edge].border)
+            .count()
+    }
 
-            // This lookup can be unwrapped without fear of panic since the node was necessarily scored
-            // before adding him to `visit_next`.
-            let node_score = scores[&node];
+    /// Build the edge-expanded representation of this cartography, where each directed edge becomes
+    /// a node and each allowed turn becomes an arc. This allows exact turn restrictions and turn
+    /// penalties, as decided by `turn_cost` (see `EdgeBasedCartograph::new()`)
+    pub fn into_edge_based<F>(self, turn_cost: F) -> EdgeBasedCartograph
+    where
+        F: Fn(&Turn) -> Option<u32>,
+    {
+        EdgeBasedCartograph::new(self, turn_cost)
+    }
 
-            for edge in self.graph.edges(node) {
-                let next = edge.target();
-                if visited.is_visited(&next) {
-                    continue;
-                }
+    /// Compute the strongly connected components
+    pub fn strongly_connected_components(&self) -> Vec<Vec<NodeIndex>> {
+        kosaraju_scc(&self.graph)
+    }
 
-                let mut next_score = node_score + edge.weight().distance;
+    /// Iterate over the strongly connected components one at a time, without holding them all,
+    /// like `carto.strongly_connected_components_iter().min_size(100)` for the big ones
+    pub fn strongly_connected_components_iter(
+        &self,
+    ) -> StronglyConnectedComponents<'_, GeoPoint, EdgeInfo> {
+        StronglyConnectedComponents::new(&self.graph)
+    }
 
-                use std::collections::hash_map::Entry::*;
-                match scores.entry(next) {
-                    Occupied(ent) => {
-                        let old_score = *ent.get();
-                        if next_score < old_score {
-                            *ent.into_mut() = next_score;
-                        } else {
-                            next_score = old_score;
-                        }
-                    }
-                    Vacant(ent) => {
-                        ent.insert(next_score);
-                    }
-                }
+    /// Whether every node can reach all the others. This stops at the first component found, so it
+    /// is fast when the graph is not
+    pub fn is_strongly_connected(&self) -> bool {
+        self.strongly_connected_components_iter()
+            .next()
+            .is_none_or(|nodes| nodes.len() == self.graph.node_count())
+    }
 
-                let next_estimate_score = next_score + estimate_cost(self, &remaining_ends, next);
-                visit_next.push(Reverse((next_estimate_score, next)));
+    /// The nodes of the largest strongly connected component, sorted by their index. Empty when the
+    /// graph is
+    pub fn largest_component(&self) -> Vec<NodeIndex> {
+        let mut largest = Vec::new();
+        for nodes in self.strongly_connected_components_iter() {
+            if nodes.len() > largest.len() {
+                largest = nodes;
             }
         }
+        largest.sort_unstable();
+        largest
+    }
 
-        final_costs
+    /// The strongly connected component of a node. Two nodes can reach each other if and only if
+    /// they are in the same component. The largest component has the id 0, the second largest 1, etc
+    pub fn component_id(&self, node: NodeIndex) -> u32 {
+        self.components[node.index()]
     }
 
-    /// Read a list of delta-encoded values
-    fn read_delta_encoded<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<i32>> {
-        let mut result = Vec::with_capacity(len);
+    /// Compare with a newer version of the cartography, like one produced by another version of the
+    /// generator from the same extract
+    pub fn diff(&self, new: &Cartograph) -> GraphDiff {
+        GraphDiff::compute(self, new)
+    }
 
-        // Read first
-        let mut prev = reader.read_i32::<LittleEndian>()?;
-        result.push(prev);
+    /// Compute the statistics of the graph
+    pub fn stats(&self) -> GraphStats {
+        GraphStats::compute(
+            &self.graph,
+            |&point| point,
+            |info| (info.road_level, info.distance),
+        )
+    }
 
-        // Read others
-        for _ in 1..len {
-            let delta = reader.read_i32::<LittleEndian>()?;
-            result.push(prev + delta);
-            prev += delta;
+    /// The number of nodes in each strongly connected component, indexed by the component id, so
+    /// sorted from the largest to the smallest
+    pub fn component_sizes(&self) -> Vec<usize> {
+        let num_components = self
+            .components
+            .iter()
+            .max()
+            .map_or(0, |&max| max as usize + 1);
+        let mut sizes = vec![0; num_components];
+        for &component in &self.components {
+            sizes[component as usize] += 1;
         }
+        sizes
+    }
 
-        Ok(result)
+    /// Find the arc that is closest to a given point. This is usually the first step before being able to
+    /// walk the graph searching for shortest paths.
+    pub fn project(&self, point: &GeoPoint) -> ProjectedPoint {
+        match self.rtree.exact_nearest(point.web_mercator_project()) {
+            Some(edge) => self.project_on_edge(point, edge).0,
+            None => self.nearest_projections(point).next().unwrap(),
+        }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    /// How a vehicle traveling along the edge of the projected point arrives at it: the heading of
+    /// the edge and the side of the road where the original point is. On a contracted edge, the
+    /// heading is the one of the segment of its shape at the position of the point
+    pub fn approach(&self, point: &ProjectedPoint) -> Approach {
+        let points = self.measured_geometry(point.edge);
+        let segment = points
+            .windows(2)
+            .position(|pair| pair[1].1 >= point.edge_pos)
+            .unwrap_or(points.len() - 2);
+        let bearing = points[segment].0.bearing(&points[segment + 1].0);
+        let side = if point.original.haversine_distance(&point.projected) < Side::ON_ROAD_DISTANCE {
+            Side::OnRoad
+        } else {
+            // The original point is to the right when it is clockwise from the heading
+            let relative = (point.projected.bearing(&point.original) - bearing + 360.) % 360.;
+            if relative < 180. {
+                Side::Right
+            } else {
+                Side::Left
+            }
+        };
+        Approach { bearing, side }
+    }
 
-    fn get_carto() -> Cartograph {
-        Cartograph::open("test_data/andorra.ptolemy").unwrap()
+    /// Estimate how much memory the cartography uses, for example to monitor a server
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            graph: self.graph.node_count() * size_of::<petgraph::graph::Node<GeoPoint>>()
+                + self.graph.edge_count() * size_of::<petgraph::graph::Edge<EdgeInfo>>(),
+            edge_index: self.rtree.memory_usage()
+                + self
+                    .level_rtrees
+                    .get()
+                    .map_or(0, |rtrees| rtrees.iter().map(EdgeRTree::memory_usage).sum()),
+            node_index: self.node_rtree.get().map_or(0, |rtree| {
+                rtree.size() * size_of::<PointWithData<NodeIndex, [f64; 2]>>()
+            }),
+            components: self.components.len() * size_of::<u32>(),
+        }
     }
 
-    #[test]
-    fn open() {
-        let carto = get_carto();
-        assert_eq!(carto.graph.node_count(), 3124);
-        assert_eq!(carto.graph.edge_count(), 5831);
-        assert_eq!(carto.rtree.size(), carto.graph.edge_count());
-        assert_eq!(carto.strongly_connected_components().len(), 1);
+    /// The node closest to the point, like a junction, if it is not farther than `max_distance`
+    /// meters
+    pub fn nearest_node(&self, point: &GeoPoint, max_distance: f64) -> Option<NodeIndex> {
+        let element = self
+            .node_rtree()
+            .nearest_neighbor(&point.web_mercator_project())?;
+        if point.haversine_distance(&self.graph[element.data]) <= max_distance {
+            Some(element.data)
+        } else {
+            None
+        }
     }
 
-    #[test]
-    fn project() {
-        let carto = get_carto();
+    /// The `k` nodes closest to the point, with their distances in meters, from the closest to the
+    /// farthest. Tools that work on junctions, like graph editors, can snap to them directly
+    pub fn nearest_nodes(&self, point: &GeoPoint, k: usize) -> Vec<(NodeIndex, f64)> {
+        let mut nodes: Vec<(NodeIndex, f64)> = self
+            .node_rtree()
+            .nearest_neighbor_iter(&point.web_mercator_project())
+            .take(k)
+            .map(|element| {
+                let distance = point.haversine_distance(&self.graph[element.data]);
+                (element.data, distance)
+            })
+            .collect();
+        // The web mercator order is the same, except for rounding errors
+        nodes.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        nodes
+    }
 
-        // Project to a road nearby
-        let p = GeoPoint::from_degrees(42.552221, 1.586691);
-        let res = carto.project(&p);
-        assert_eq!(res.original, p);
-        assert_eq!(res.projected, GeoPoint::from_degrees(42.553210, 1.588908));
-        assert_eq!(res.edge, EdgeIndex::new(4199));
-        assert_eq!(res.edge_pos, 0.1256024);
-        assert_eq!(
-            res.original.haversine_distance(&res.projected),
-            212.3022254769895
+    /// The nodes inside a region, described by two opposite corners, sorted by their index
+    pub fn nodes_in_bbox(&self, corner1: &GeoPoint, corner2: &GeoPoint) -> Vec<NodeIndex> {
+        let envelope = AABB::from_corners(
+            corner1.web_mercator_project(),
+            corner2.web_mercator_project(),
         );
-
-        // Project to source node
-        let source = carto.graph[carto.graph.edge_endpoints(res.edge).unwrap().0];
-        let res_source = carto.project(&source);
-        assert_eq!(res_source.projected, source);
-        assert_eq!(res_source.edge, res.edge);
-        assert_eq!(res_source.edge_pos, 0.);
+        let mut nodes: Vec<NodeIndex> = self
+            .node_rtree()
+            .locate_in_envelope(&envelope)
+            .map(|element| element.data)
+            .collect();
+        nodes.sort_unstable();
+        nodes
     }
 
-    #[test]
-    fn shortest_path() {
-        let carto = get_carto();
-
-        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
-        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+    /// The spatial indexes of the edges of each road level, built on the first call. Edges added
+    /// to the graph after that are not indexed
+    fn level_rtrees(&self) -> &[EdgeRTree] {
+        self.level_rtrees.get_or_init(|| {
+            let mut lines_by_level: Vec<Vec<_>> = Vec::new();
+            for line in Cartograph::project_edges(&self.graph, &self.edge_shapes, None) {
+                let level = self.graph[line.data].road_level as usize;
+                if lines_by_level.len() <= level {
+                    lines_by_level.resize_with(level + 1, Vec::new);
+                }
+                lines_by_level[level].push(line);
+            }
+            lines_by_level
+                .into_iter()
+                .map(|lines| EdgeRTree::build(lines, &IndexConfig::default()))
+                .collect()
+        })
+    }
 
-        let res = carto.shortest_path(&from, &to);
-        assert_eq!(res.distance, 12124);
-        assert_eq!(res.points.len(), 111);
+    /// The spatial index of the nodes, built on the first call. Nodes added to the graph after that
+    /// are not indexed
+    fn node_rtree(&self) -> &RTree<PointWithData<NodeIndex, [f64; 2]>> {
+        self.node_rtree.get_or_init(|| {
+            RTree::bulk_load(
+                self.graph
+                    .node_indices()
+                    .map(|node| PointWithData::new(node, self.graph[node].web_mercator_project()))
+                    .collect(),
+            )
+        })
     }
 
-    #[test]
+    /// Project many points at once, like `project()`, splitting them among `num_threads` threads.
+    /// The results are in the same order as the points
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn project_all(&self, points: &[GeoPoint], num_threads: usize) -> Vec<ProjectedPoint> {
+        let chunk_size = points.len().div_ceil(num_threads.max(1));
+        if chunk_size == 0 {
+            return Vec::new();
+        }
+        crossbeam::scope(|scope| {
+            let handles: Vec<_> = points
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move |_| {
+                        chunk
+                            .iter()
+                            .map(|point| self.project(point))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+        .unwrap()
+    }
+
+    /// Find up to `max_num` arcs that are closest to a given point, sorted by distance. Arcs farther than
+    /// `max_distance` meters are ignored, except for the closest one, that is always returned.
+    pub fn project_candidates(
+        &self,
+        point: &GeoPoint,
+        max_distance: f64,
+        max_num: usize,
+    ) -> Vec<ProjectedPoint> {
+        self.nearest_projections(point)
+            .enumerate()
+            .take_while(|(i, projected)| {
+                *i == 0 || projected.projected.haversine_distance(point) <= max_distance
+            })
+            .take(max_num.max(1))
+            .map(|(_, projected)| projected)
+            .collect()
+    }
+
+    /// The projections of a point on the closest edges, sorted by distance. When the index is not
+    /// exact, the candidates are sorted again by their exact distance. The other segments of a
+    /// contracted edge are skipped once it is projected
+    fn nearest_projections<'a>(
+        &'a self,
+        point: &'a GeoPoint,
+    ) -> impl Iterator<Item = ProjectedPoint> + 'a {
+        let error = self.rtree.error();
+        let mut seen = HashSet::new();
+        let mut candidates = self
+            .rtree
+            .nearest_iter(point.web_mercator_project())
+            .filter(move |&(edge, _)| seen.insert(edge))
+            .peekable();
+        let mut pending: Vec<(ProjectedPoint, f64)> = Vec::new();
+        std::iter::from_fn(move || loop {
+            // The closest pending projection is returned once no other candidate can be closer
+            let closest = pending
+                .iter()
+                .enumerate()
+                .min_by(|a, b| (a.1).1.partial_cmp(&(b.1).1).unwrap());
+            match (closest, candidates.peek()) {
+                (Some((_, &(_, distance))), Some(&(_, next))) if distance > next - error => {}
+                (Some((i, _)), _) => return Some(pending.remove(i).0),
+                (None, None) => return None,
+                (None, Some(_)) => {}
+            }
+            let (edge, _) = candidates.next().unwrap();
+            pending.push(self.project_on_edge(point, edge));
+        })
+    }
+
+    /// Project a point on an edge, on the closest segment of its shape when it was contracted, also
+    /// returning their distance in web mercator meters
+    fn project_on_edge(&self, point: &GeoPoint, edge_index: EdgeIndex) -> (ProjectedPoint, f64) {
+        let points = self.edge_geometry(edge_index);
+        let xy = point.web_mercator_project();
+        let (segment, projected_xy, distance) = points
+            .windows(2)
+            .enumerate()
+            .map(|(i, pair)| {
+                let line = LineWithData::new(
+                    edge_index,
+                    pair[0].web_mercator_project(),
+                    pair[1].web_mercator_project(),
+                );
+                let projected_xy = line.nearest_point(&xy);
+                let distance =
+                    ((projected_xy[0] - xy[0]).powi(2) + (projected_xy[1] - xy[1]).powi(2)).sqrt();
+                (i, projected_xy, distance)
+            })
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
+            .unwrap();
+        let projected = GeoPoint::from_web_mercator(projected_xy);
+
+        // Calculate the ratio over the length of the edge where the result is
+        let length = |points: &[GeoPoint]| -> f64 {
+            points
+                .windows(2)
+                .map(|pair| pair[0].haversine_distance(&pair[1]))
+                .sum()
+        };
+        let dist_to_source =
+            length(&points[..=segment]) + points[segment].haversine_distance(&projected);
+        let dist_to_target =
+            projected.haversine_distance(&points[segment + 1]) + length(&points[segment + 1..]);
+        let edge_pos = (dist_to_source / (dist_to_source + dist_to_target)) as f32;
+
+        let projected = ProjectedPoint {
+            original: point.clone(),
+            projected,
+            edge: edge_index,
+            edge_pos,
+        };
+        (projected, distance)
+    }
+
+    /// The distance in meters from a point to the closest part of the path, like from the position
+    /// of a vehicle to its route, to find out that it left the route and needs another one
+    pub fn distance_to_path(&self, point: &GeoPoint, path: &GraphPath) -> f64 {
+        simplify::distance_to_line(point, &path.points)
+    }
+
+    /// Find the shortest path between two projected points. Use project() to generate them.
+    /// Panics if the destination is not reachable, see `try_shortest_path()`
+    pub fn shortest_path(&self, from: &ProjectedPoint, to: &ProjectedPoint) -> GraphPath {
+        self.try_shortest_path(from, to)
+            .expect("The destination is not reachable")
+    }
+
+    /// Find the shortest path between two projected points. Returns `None` if the destination is not
+    /// reachable, which can happen when synthetic edges are not allowed or were not generated.
+    /// The path starts and ends exactly at the points, possibly going backwards on their two-way
+    /// roads, as if their edges were split there
+    pub fn try_shortest_path(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+    ) -> Option<GraphPath> {
+        self.shortest_path_with_deadline(from, to, &Deadline::never())
+            .expect("A search without deadline can not time out")
+    }
+
+    /// Like `try_shortest_path()`, but give up with `Timeout` when the deadline passes. This bounds
+    /// the time spent on pathological queries, for example between points that are far apart
+    pub fn shortest_path_with_deadline(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        deadline: &Deadline,
+    ) -> Result<Option<GraphPath>, Timeout> {
+        self.cheapest_path(from, to, &DistanceCost, deadline)
+    }
+
+    /// Like `shortest_path_with_deadline()`, but also return how much work the search did and how
+    /// long it took, to tune the heuristics
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn shortest_path_with_stats(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        deadline: &Deadline,
+    ) -> Result<(Option<GraphPath>, SearchStats), Timeout> {
+        self.cheapest_path_with_stats(from, to, &DistanceCost, deadline)
+    }
+
+    /// Like `cheapest_path()`, but also return how much work the search did and how long it took,
+    /// like `shortest_path_with_stats()`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cheapest_path_with_stats<C: CostModel + ?Sized>(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        cost: &C,
+        deadline: &Deadline,
+    ) -> Result<(Option<GraphPath>, SearchStats), Timeout> {
+        let start = std::time::Instant::now();
+        let check = DeadlineCheck::new(deadline);
+        let found = virtual_nodes::search(self, from, std::slice::from_ref(to), cost, &check).pop();
+        check.result()?;
+        let found = found.flatten();
+        let (settled, pushes) = check.counts();
+        let heuristic_tightness = found.as_ref().map(|path| {
+            let estimate = cost.heuristic(&from.projected, &to.projected);
+            estimate as f64 / path.cost.max(estimate).max(1) as f64
+        });
+        let stats = SearchStats {
+            settled,
+            pushes,
+            time_ms: start.elapsed().as_secs_f64() * 1000.,
+            heuristic_tightness,
+        };
+        Ok((found.map(|path| self.graph_path(from, to, &path)), stats))
+    }
+
+    /// Find the cheapest path between two projected points, according to the given cost model. This
+    /// allows custom penalties, like avoiding unpaved roads or preferring the highways. The returned
+    /// distance is the real one. Returns `None` if the destination is not reachable
+    pub fn shortest_path_with_cost<C: CostModel + ?Sized>(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        cost: &C,
+    ) -> Option<GraphPath> {
+        self.cheapest_path(from, to, cost, &Deadline::never())
+            .expect("A search without deadline can not time out")
+    }
+
+    /// Like `shortest_path_with_cost()`, but give up with `Timeout` when the deadline passes, like
+    /// `shortest_path_with_deadline()`
+    pub fn cheapest_path<C: CostModel + ?Sized>(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        cost: &C,
+        deadline: &Deadline,
+    ) -> Result<Option<GraphPath>, Timeout> {
+        let check = DeadlineCheck::new(deadline);
+        let found = virtual_nodes::search(self, from, std::slice::from_ref(to), cost, &check).pop();
+        check.result()?;
+        Ok(found.flatten().map(|path| self.graph_path(from, to, &path)))
+    }
+
+    /// Describe a path found between the virtual nodes of two projected points
+    fn graph_path(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        path: &virtual_nodes::VirtualPath,
+    ) -> GraphPath {
+        // Build final sequence of geo points, following the shapes of the contracted edges
+        let mut points = Vec::with_capacity(path.nodes.len() + 2);
+        points.push(from.projected);
+        let (start, end) = (&path.start, &path.end);
+        if let Some(&first) = path.nodes.first() {
+            self.push_partial_points(start.edge, start.edge_pos, 1., &mut points);
+            points.push(self.graph[first]);
+            for &edge in &path.inner {
+                self.push_edge_points(edge, &mut points);
+            }
+            self.push_partial_points(end.edge, 0., end.edge_pos, &mut points);
+        } else {
+            self.push_partial_points(start.edge, start.edge_pos, end.edge_pos, &mut points);
+        }
+        points.push(to.projected);
+
+        GraphPath::new(path.distance(self), path.duration(self), points).with_edges(path.edges())
+    }
+
+    /// Find the cheapest path between two projected points with the costs of the last customization
+    /// of the overlay, which must have been built for this cartography. The returned distance is
+    /// the real one. Returns `None` if the destination is not reachable
+    pub fn shortest_path_with_overlay(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        overlay: &MultiLevelOverlay,
+    ) -> Option<GraphPath> {
+        overlay
+            .search(self, from, to)
+            .map(|path| self.graph_path(from, to, &path))
+    }
+
+    /// Find the fastest path between two projected points when leaving at a time of day, in seconds
+    /// from midnight, with the speeds of the roads at the time they are entered. The known speeds,
+    /// in km/h, replace the profiles of their edges, like in `shortest_path_with_speeds()`, and the
+    /// closed edges are never traversed, except the ones where the path starts and ends. The
+    /// returned duration is the one with the speeds. Returns `None` if the destination is not
+    /// reachable, and gives up with `Timeout` when the deadline passes
+    #[allow(clippy::too_many_arguments)]
+    pub fn fastest_path_at(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        profiles: &SpeedProfiles,
+        depart_at: f64,
+        closed: &HashSet<EdgeIndex>,
+        speeds: &HashMap<EdgeIndex, f64>,
+        deadline: &Deadline,
+    ) -> Result<Option<GraphPath>, Timeout> {
+        speed_profile::fastest_path(
+            self, from, to, profiles, depart_at, closed, speeds, deadline,
+        )
+    }
+
+    /// Find the shortest path between two projected points, multiplying the distance of each edge by
+    /// the penalty of its surface. The returned distance is the real one, without penalties.
+    /// Returns `None` if the destination is not reachable
+    pub fn shortest_path_with_penalties(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        penalties: &SurfacePenalties,
+    ) -> Option<GraphPath> {
+        self.shortest_path_with_cost(from, to, penalties)
+    }
+
+    /// Find the shortest path between two projected points, multiplying the distance of each edge by
+    /// the penalties of its access class and surface, adding the cost of its barrier, and never
+    /// traversing the forbidden access classes. The returned distance is the real one. Returns `None`
+    /// if the destination is not reachable without the forbidden edges
+    pub fn shortest_path_with_access(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        access: &AccessPenalties,
+        surfaces: &SurfacePenalties,
+    ) -> Option<GraphPath> {
+        self.shortest_path_with_closures(from, to, &HashSet::new(), access, surfaces)
+    }
+
+    /// Like `shortest_path_with_access()`, but also never traversing the closed edges, except the
+    /// ones where the path starts and ends. See `Closures::closed_edges()`
+    pub fn shortest_path_with_closures(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        closed: &HashSet<EdgeIndex>,
+        access: &AccessPenalties,
+        surfaces: &SurfacePenalties,
+    ) -> Option<GraphPath> {
+        let cost = ClosedEdges {
+            edges: closed,
+            cost: &AccessAndSurfaces { access, surfaces },
+        };
+        self.shortest_path_with_cost(from, to, &cost)
+    }
+
+    /// Like `shortest_path_with_closures()` without penalties, but traveling the edges at the given
+    /// speeds, in km/h, like the ones of `SpeedOverrides::active_speeds()`. See `LiveSpeeds`. The
+    /// returned duration uses the given speeds
+    pub fn shortest_path_with_speeds(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        speeds: &HashMap<EdgeIndex, f64>,
+        closed: &HashSet<EdgeIndex>,
+    ) -> Option<GraphPath> {
+        let cost = ClosedEdges {
+            edges: closed,
+            cost: &LiveSpeeds {
+                speeds,
+                cost: &DistanceCost,
+            },
+        };
+        let mut path = self.shortest_path_with_cost(from, to, &cost)?;
+        self.apply_speeds(from, to, &mut path, speeds);
+        Some(path)
+    }
+
+    /// Find the shortest path between two projected points, multiplying the distance of each edge by
+    /// the factor of the experiment. The returned distance is the real one. Returns `None` if the
+    /// destination is not reachable
+    pub fn shortest_path_with_experiment(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        experiment: &Experiment,
+    ) -> Option<GraphPath> {
+        self.shortest_path_with_cost(from, to, experiment)
+    }
+
+    /// Find the shortest path between two projected points, as if the given edges were also in the
+    /// graph. This allows evaluating proposed roads without generating the cartography again.
+    /// Returns `None` if the destination is not reachable
+    pub fn shortest_path_with_extra_edges(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        extra_edges: &[ExtraEdge],
+    ) -> Option<GraphPath> {
+        self.cheapest_path_with_extra_edges(
+            from,
+            to,
+            extra_edges,
+            &DistanceCost,
+            &Deadline::never(),
+        )
+        .expect("A search without deadline can not time out")
+    }
+
+    /// Like `shortest_path_with_extra_edges()`, but weighing the edges of the graph with the given
+    /// cost model, while the extra edges cost their distance, and giving up with `Timeout` when the
+    /// deadline passes. The returned distance is the real one
+    pub fn cheapest_path_with_extra_edges<C: CostModel + ?Sized>(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        extra_edges: &[ExtraEdge],
+        cost: &C,
+        deadline: &Deadline,
+    ) -> Result<Option<GraphPath>, Timeout> {
+        overlay::cheapest_path(self, from, to, extra_edges, cost, deadline)
+    }
+
+    /// Replace the default duration of the traveled part of each edge of a path between two projected
+    /// points with its known speed, in km/h. The path must have been searched with `LiveSpeeds` for
+    /// the same speeds, to be the fastest one
+    pub fn apply_speeds(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        path: &mut GraphPath,
+        speeds: &HashMap<EdgeIndex, f64>,
+    ) {
+        if speeds.is_empty() {
+            return;
+        }
+        let fractions = self.edge_fractions(from, to, &path.edges);
+        for (edge, fraction) in path.edges.iter().zip(fractions) {
+            if let Some(&speed) = speeds.get(edge) {
+                let info = &self.graph[*edge];
+                path.duration +=
+                    fraction as f64 * (info.distance as f64 * 3.6 / speed - info.duration());
+            }
+        }
+    }
+
+    /// The traveled fraction of each edge of a path between two projected points. The path may leave
+    /// and reach them on the opposite edges of theirs, where their positions are mirrored
+    fn edge_fractions(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        edges: &[EdgeIndex],
+    ) -> Vec<f32> {
+        let position = |point: &ProjectedPoint, edge: EdgeIndex| {
+            if edge == point.edge {
+                point.edge_pos
+            } else {
+                1. - point.edge_pos
+            }
+        };
+        let last = edges.len() - 1;
+        edges
+            .iter()
+            .enumerate()
+            .map(|(i, &edge)| match (i == 0, i == last) {
+                (true, true) => position(to, edge) - position(from, edge),
+                (true, false) => 1. - position(from, edge),
+                (false, true) => position(to, edge),
+                (false, false) => 1.,
+            })
+            .collect()
+    }
+
+    /// The duration of the partial edges traversed from `from` to the end of its edge and from the
+    /// start of the edge of `to` to it
+    fn endpoints_duration(&self, from: &ProjectedPoint, to: &ProjectedPoint) -> f64 {
+        self.graph[from.edge].duration() * (1. - from.edge_pos as f64)
+            + self.graph[to.edge].duration() * to.edge_pos as f64
+    }
+
+    /// Whether the path searches can traverse the given edge
+    pub fn is_routable(&self, edge: &EdgeInfo) -> bool {
+        self.allow_synthetic || !edge.synthetic
+    }
+
+    /// Find the shortest path between two projected points and return the sequence of traversed edges,
+    /// including the ones where the points are. Returns `None` if the destination is not reachable
+    pub fn shortest_path_edges(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+    ) -> Option<Vec<EdgeIndex>> {
+        let deadline = Deadline::never();
+        let check = DeadlineCheck::new(&deadline);
+        virtual_nodes::search(self, from, std::slice::from_ref(to), &DistanceCost, &check)
+            .pop()
+            .flatten()
+            .map(|path| path.edges())
+    }
+
+    /// Find the shortest path between two projected points and describe it as turn-by-turn
+    /// instructions, starting with `Maneuver::Depart` and ending with `Maneuver::Arrive`. Returns
+    /// `None` if the destination is not reachable
+    pub fn route_steps(&self, from: &ProjectedPoint, to: &ProjectedPoint) -> Option<Vec<Step>> {
+        let edges = self.shortest_path_edges(from, to)?;
+        Some(steps::path_steps(self, from, to, &edges))
+    }
+
+    /// Describe a path found by any search, like `randomized_path()`, as turn-by-turn instructions
+    /// from its edges. See `route_steps()`
+    pub fn path_steps(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        path: &GraphPath,
+    ) -> Vec<Step> {
+        steps::path_steps(self, from, to, &path.edges)
+    }
+
+    /// Find the shortest path length from a single starting point to multiple destinations.
+    /// This method is more perfomant than calculating each path individually, however only the distance is
+    /// returned, unlike shortest_path()
+    pub fn shortest_path_multi(&self, from: &ProjectedPoint, to: &Vec<ProjectedPoint>) -> Vec<u32> {
+        self.shortest_path_multi_with_deadline(from, to, &Deadline::never())
+            .expect("A search without deadline can not time out")
+    }
+
+    /// Like `shortest_path_multi()`, but give up with `Timeout` when the deadline passes
+    pub fn shortest_path_multi_with_deadline(
+        &self,
+        from: &ProjectedPoint,
+        to: &[ProjectedPoint],
+        deadline: &Deadline,
+    ) -> Result<Vec<u32>, Timeout> {
+        let check = DeadlineCheck::new(deadline);
+        let paths = virtual_nodes::search(self, from, to, &DistanceCost, &check);
+        check.result()?;
+        Ok(paths
+            .into_iter()
+            .map(|path| path.map_or(0, |path| path.cost))
+            .collect())
+    }
+
+    /// Find the shortest paths from a single starting point to multiple destinations, with one search
+    /// like `shortest_path_multi()`, but returning the full paths, with their durations. The
+    /// unreachable destinations are `None`
+    pub fn shortest_paths_multi(
+        &self,
+        from: &ProjectedPoint,
+        to: &[ProjectedPoint],
+    ) -> Vec<Option<GraphPath>> {
+        let deadline = Deadline::never();
+        let check = DeadlineCheck::new(&deadline);
+        virtual_nodes::search(self, from, to, &DistanceCost, &check)
+            .into_iter()
+            .zip(to)
+            .map(|(path, to)| path.map(|path| self.graph_path(from, to, &path)))
+            .collect()
+    }
+
+    /// Find the closest of the targets from a projected point, by the network, and return its index
+    /// and the shortest path to it. The search stops as soon as it is found, so this is cheaper than
+    /// `shortest_path_multi()` when only the closest one matters, like the nearest charging station.
+    /// Returns `None` if no target is reachable
+    pub fn closest_target(
+        &self,
+        from: &ProjectedPoint,
+        targets: &[ProjectedPoint],
+    ) -> Option<(usize, GraphPath)> {
+        let deadline = Deadline::never();
+        let check = DeadlineCheck::new(&deadline);
+        let (i, path) = virtual_nodes::search_closest(self, from, targets, &DistanceCost, &check)?;
+        Some((i, self.graph_path(from, &targets[i], &path)))
+    }
+
+    /// The shortest distance in meters from each source to each target, like `shortest_path_multi()`
+    pub fn distance_table(
+        &self,
+        sources: &[ProjectedPoint],
+        targets: &[ProjectedPoint],
+    ) -> Vec<Vec<u32>> {
+        self.distance_table_with_deadline(sources, targets, &Deadline::never())
+            .expect("A search without deadline can not time out")
+    }
+
+    /// Like `distance_table()`, but give up with `Timeout` when the deadline passes. The deadline
+    /// applies to the whole table, not to each source
+    pub fn distance_table_with_deadline(
+        &self,
+        sources: &[ProjectedPoint],
+        targets: &[ProjectedPoint],
+        deadline: &Deadline,
+    ) -> Result<Vec<Vec<u32>>, Timeout> {
+        if targets.is_empty() {
+            return Ok(vec![Vec::new(); sources.len()]);
+        }
+        sources
+            .iter()
+            .map(|source| self.shortest_path_multi_with_deadline(source, targets, deadline))
+            .collect()
+    }
+
+    /// The distance to reach each cell of a regular grid of `cell_meters` around a projected
+    /// point, up to `max_cost` meters, to plot the reachable area as a raster or as contour lines
+    pub fn cost_grid(&self, center: &ProjectedPoint, cell_meters: f64, max_cost: u32) -> CostGrid {
+        grid::cost_grid(self, center, cell_meters, max_cost)
+    }
+
+    /// Simulate the traffic produced by the given demands, taking congestion into account, and return
+    /// the volume on each edge. This can be used to evaluate what-if scenarios, like closing a street
+    /// with `options.closed_edges`. See `assign::assign()` for the details of the method
+    pub fn assign_traffic(&self, demands: &[Demand], options: &AssignOptions) -> Assignment {
+        assign::assign(self, demands, options)
+    }
+
+    /// Calculate the congested cost of each edge, in equivalent meters, given the traffic volume on
+    /// each edge (indexed by the edge index), for example from `assign_traffic()` or from external
+    /// counts
+    pub fn congested_costs(&self, volumes: &[f64], bpr: &Bpr) -> Vec<f64> {
+        assign::congested_costs(self, volumes, bpr)
+    }
+
+    /// Find a randomized near-optimal path between two projected points. Different seeds return
+    /// different paths, but the same seed always returns the same one. See `diverse_paths()`
+    pub fn randomized_path(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        seed: u64,
+        options: &DiversityOptions,
+    ) -> GraphPath {
+        self.randomized_path_with_cost(from, to, seed, options, &DistanceCost, &Deadline::never())
+            .expect("A search without deadline can not time out")
+            .expect("The destination is not reachable")
+    }
+
+    /// Like `randomized_path()`, but perturbing the costs of the given model instead of the
+    /// distances, and giving up with `Timeout` when the deadline passes. Returns `None` if the
+    /// destination is not reachable
+    pub fn randomized_path_with_cost<C: CostModel + ?Sized>(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        seed: u64,
+        options: &DiversityOptions,
+        cost: &C,
+        deadline: &Deadline,
+    ) -> Result<Option<GraphPath>, Timeout> {
+        let paths = diversity::diverse_paths(self, from, to, 1, seed, options, cost, deadline)?;
+        Ok(paths.and_then(|mut paths| paths.pop()))
+    }
+
+    /// Find `count` near-optimal paths between two projected points, spreading them over different
+    /// streets. This is useful to dispatch many vehicles between the same origin and destination
+    /// without sending all of them through the same roads. Every path is at most
+    /// `options.max_stretch` longer than the shortest one and the result is deterministic for a
+    /// given `seed`
+    pub fn diverse_paths(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        count: usize,
+        seed: u64,
+        options: &DiversityOptions,
+    ) -> Vec<GraphPath> {
+        let deadline = Deadline::never();
+        diversity::diverse_paths(
+            self,
+            from,
+            to,
+            count,
+            seed,
+            options,
+            &DistanceCost,
+            &deadline,
+        )
+        .expect("A search without deadline can not time out")
+        .expect("The destination is not reachable")
+    }
+
+    /// Generate a loop of about `target_distance` meters that starts and ends at the same point, for
+    /// example for a running or cycling app. Different seeds go in different directions, but the
+    /// same seed always returns the same loop. Returns `None` if no loop could be found, and gives
+    /// up with `Timeout` when the deadline passes
+    pub fn round_trip(
+        &self,
+        start: &ProjectedPoint,
+        target_distance: f64,
+        seed: u64,
+        deadline: &Deadline,
+    ) -> Result<Option<GraphPath>, Timeout> {
+        round_trip::round_trip(self, start, target_distance, seed, deadline)
+    }
+
+    /// Find the Pareto front of paths between two projected points over two criteria, for example distance
+    /// and number of turns. That is, every returned path is better than all the others in at least one
+    /// criterion. To limit the number of paths, a path is only kept if none of the other ones is better
+    /// in both criteria by a factor of more than `1 + epsilon`.
+    /// The paths are sorted by the first criterion
+    pub fn pareto_paths(
+        &self,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+        criteria: [&dyn Criterion; 2],
+        epsilon: f64,
+    ) -> Vec<ParetoPath> {
+        pareto::pareto_paths(self, from, to, criteria, epsilon)
+    }
+
+    /// Match a sequence of GPS points to the most likely path on the road network, using a Hidden
+    /// Markov Model. The trace should usually be thinned first, see `trace::thin_trace()`.
+    /// Returns `None` if the trace is empty
+    pub fn match_trace(&self, trace: &[GeoPoint], options: &MatchOptions) -> Option<MatchedTrace> {
+        matching::match_trace(self, trace, options)
+    }
+
+    /// Compute the strongly connected components and number them by decreasing size. They are
+    /// labeled as they are found, then renumbered, so that they are never all held in memory
+    #[inline(never)]
+    fn label_components(graph: &Graph<GeoPoint, EdgeInfo>) -> Vec<u32> {
+        let mut labels = vec![0; graph.node_count()];
+        let mut sizes = Vec::new();
+        for nodes in StronglyConnectedComponents::new(graph) {
+            for node in &nodes {
+                labels[node.index()] = sizes.len() as u32;
+            }
+            sizes.push(nodes.len());
+        }
+
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        order.sort_by_key(|&found| Reverse(sizes[found]));
+        let mut ids = vec![0; sizes.len()];
+        for (id, found) in order.into_iter().enumerate() {
+            ids[found] = id as u32;
+        }
+        for label in &mut labels {
+            *label = ids[*label as usize];
+        }
+        labels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use flate2::{write::GzEncoder, Compression};
+    use std::convert::TryInto;
+
+    fn get_carto() -> Cartograph {
+        Cartograph::open("test_data/andorra.ptolemy").unwrap()
+    }
+
+    #[test]
+    fn open() {
+        let carto = get_carto();
+        assert_eq!(carto.graph.node_count(), 3124);
+        assert_eq!(carto.graph.edge_count(), 5831);
+        assert_eq!(carto.rtree.size(), carto.graph.edge_count());
+        assert_eq!(carto.strongly_connected_components().len(), 1);
+        assert!(carto.is_strongly_connected());
+        assert_eq!(carto.largest_component().len(), 3124);
+        assert_eq!(carto.road_levels, RoadLevelStyle::defaults());
+        assert_eq!(carto.road_levels[1].name, "primary");
+    }
+
+    #[test]
+    fn components() {
+        let carto = get_carto();
+        assert_eq!(carto.component_sizes(), vec![3124]);
+        assert!(carto
+            .graph
+            .node_indices()
+            .all(|node| carto.component_id(node) == 0));
+
+        // Without the synthetic edges the graph falls apart
+        let graph = carto.graph.filter_map(
+            |_, &point| Some(point),
+            |_, info| if info.synthetic { None } else { Some(*info) },
+        );
+        let components = Cartograph::label_components(&graph);
+        let mut sizes = vec![0; *components.iter().max().unwrap() as usize + 1];
+        for &id in &components {
+            sizes[id as usize] += 1;
+        }
+        assert!(sizes.len() > 1);
+        assert!(sizes.windows(2).all(|pair| pair[0] >= pair[1]));
+        let mut expected: Vec<usize> = kosaraju_scc(&graph).iter().map(Vec::len).collect();
+        expected.sort_by_key(|&size| Reverse(size));
+        assert_eq!(sizes, expected);
+        assert_eq!(
+            StronglyConnectedComponents::new(&graph)
+                .min_size(2)
+                .map(|nodes| nodes.len())
+                .sum::<usize>(),
+            sizes.iter().filter(|&&size| size >= 2).sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn open_with_progress() {
+        let mut stages = Vec::new();
+        let carto =
+            Cartograph::open_with_progress("test_data/andorra.ptolemy", |stage| stages.push(stage))
+                .unwrap();
+        assert_eq!(carto.graph.node_count(), 3124);
+
+        // The file is decoded column by column, up to the end
+        let file_size = std::fs::metadata("test_data/andorra.ptolemy")
+            .unwrap()
+            .len();
+        let decoded: Vec<u64> = stages
+            .iter()
+            .filter_map(|stage| match stage {
+                LoadProgress::Decoded { bytes, total_bytes } => {
+                    assert_eq!(*total_bytes, Some(file_size));
+                    Some(*bytes)
+                }
+                _ => None,
+            })
+            .collect();
+        assert!(decoded.len() >= 6);
+        assert!(decoded.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(*decoded.last().unwrap(), file_size);
+
+        assert_eq!(
+            &stages[stages.len() - 3..],
+            &[
+                LoadProgress::EdgesBuilt { num_edges: 5831 },
+                LoadProgress::IndexBuilt,
+                LoadProgress::ComponentsLabeled
+            ]
+        );
+    }
+
+    #[test]
+    fn open_with() {
+        let (carto, report) = Cartograph::open_with("test_data/andorra.ptolemy", |_| {}).unwrap();
+        assert_eq!(carto.graph.edge_count(), 5831);
+
+        let file_size = std::fs::metadata("test_data/andorra.ptolemy")
+            .unwrap()
+            .len();
+        assert_eq!(report.bytes, file_size);
+        assert!(report.decode > std::time::Duration::default());
+        assert_eq!(
+            report
+                .phases()
+                .iter()
+                .map(|(_, duration)| *duration)
+                .sum::<std::time::Duration>(),
+            report.total()
+        );
+
+        let table = report.to_string();
+        assert_eq!(table.lines().count(), 7);
+        assert!(table.contains("rtree build"));
+    }
+
+    #[test]
+    fn index_config() {
+        let carto = get_carto();
+        let xy1 = GeoPoint::from_degrees(42.4, 1.4).web_mercator_project();
+        let xy2 = GeoPoint::from_degrees(42.7, 1.8).web_mercator_project();
+        let mut sample = carto.sample_edges(xy1, xy2, 100, None);
+        sample.values_mut().for_each(|edges| edges.sort());
+
+        for &(f32_envelopes, parallel) in &[(true, false), (false, true), (true, true)] {
+            let config = IndexConfig {
+                f32_envelopes,
+                parallel,
+            };
+            let (other, _) =
+                Cartograph::open_with_config("test_data/andorra.ptolemy", &config, |_| {}).unwrap();
+            assert_eq!(other.rtree.size(), carto.rtree.size());
+
+            // The same projections, even if edges at the same distance may come in another order
+            for i in 0..100 {
+                let p = GeoPoint::from_degrees(
+                    42.43 + i as f64 * 0.0021,
+                    1.42 + (i * 37 % 100) as f64 * 0.0028,
+                );
+                let distance =
+                    |projected: &ProjectedPoint| projected.projected.haversine_distance(&p);
+                assert!((distance(&other.project(&p)) - distance(&carto.project(&p))).abs() < 1e-6);
+                let candidates = other.project_candidates(&p, 200., 5);
+                let expected = carto.project_candidates(&p, 200., 5);
+                assert_eq!(candidates.len(), expected.len());
+                for (a, b) in candidates.iter().zip(&expected) {
+                    assert!((distance(a) - distance(b)).abs() < 1e-6);
+                }
+            }
+
+            let mut other_sample = other.sample_edges(xy1, xy2, 100, None);
+            other_sample.values_mut().for_each(|edges| edges.sort());
+            assert_eq!(other_sample, sample);
+        }
+
+        let mut builder = CartographBuilder::default();
+        let a = builder.add_node(GeoPoint::from_degrees(42.5, 1.5));
+        let b = builder.add_node(GeoPoint::from_degrees(42.501, 1.5));
+        builder.add_edge(a, b, EdgeInfo::new(111, 5));
+        builder.index_config(IndexConfig {
+            f32_envelopes: true,
+            parallel: true,
+        });
+        let carto = builder.build();
+        let p = GeoPoint::from_degrees(42.5005, 1.5001);
+        assert_eq!(carto.project(&p).edge, EdgeIndex::new(0));
+    }
+
+    #[test]
+    fn memory_usage() {
+        let carto = get_carto();
+        let before = carto.memory_usage();
+        assert!(before.graph > 5831 * size_of::<EdgeInfo>());
+        assert!(before.edge_index >= 5831 * size_of::<[f64; 4]>());
+        assert_eq!(before.node_index, 0);
+        assert_eq!(before.components, 3124 * 4);
+
+        carto.nearest_node(&GeoPoint::from_degrees(42.5, 1.5), 100.);
+        let after = carto.memory_usage();
+        assert!(after.node_index >= 3124 * size_of::<[f64; 2]>());
+        assert_eq!(after.total(), before.total() + after.node_index);
+    }
+
+    #[test]
+    fn stats() {
+        let carto = get_carto();
+        let stats = carto.stats();
+        assert_eq!(stats.num_nodes, 3124);
+        assert_eq!(stats.num_edges, 5831);
+        assert_eq!(stats.num_components, 1);
+        assert!(stats.bbox.0.lat.as_degrees() > 42.4 && stats.bbox.1.lat.as_degrees() < 42.7);
+        assert_eq!(stats.length_by_level.len(), 6);
+        assert_eq!(stats.degree_histogram.iter().sum::<u32>(), 3124);
+        assert_eq!(stats.degree_histogram[0], 0);
+
+        // The generator stores the same statistics at the end of the file
+        let stored = Cartograph::read_stats("test_data/andorra.ptolemy").unwrap();
+        assert_eq!(stored, stats);
+
+        let mut bytes = Vec::new();
+        stats.write_section(&mut bytes).unwrap();
+        let mut cursor = io::Cursor::new(bytes);
+        assert_eq!(GraphStats::read_footer(&mut cursor).unwrap(), Some(stats));
+        let mut cursor = io::Cursor::new(vec![0; 20]);
+        assert_eq!(GraphStats::read_footer(&mut cursor).unwrap(), None);
+    }
+
+    #[test]
+    fn checksum() {
+        let checksum = Cartograph::verify_checksum("test_data/andorra.ptolemy").unwrap();
+        let mut bytes = std::fs::read("test_data/andorra.ptolemy").unwrap();
+        let stored = &bytes[bytes.len() - 12..bytes.len() - 8];
+        assert_eq!(stored, &checksum.to_le_bytes());
+
+        // Flip a bit of the statistics, that are not needed to build the graph
+        let stats_pos = bytes.len() - checksum::SECTION_SIZE as usize - 20;
+        bytes[stats_pos] ^= 1;
+        let error = Cartograph::from_bytes(&bytes).err().unwrap();
+        assert!(
+            matches!(error, CartographError::ChecksumMismatch { found, .. } if found == checksum)
+        );
+        assert!(error.to_string().starts_with("Checksum mismatch"));
+    }
+
+    #[test]
+    fn open_v1() {
+        // Rewrite the fixture like the first versions of the generator did, in a single gzip
+        // stream without magic bytes, extra columns nor sections
+        let carto = get_carto();
+        let nodes = carto.graph.raw_nodes();
+        let edges = carto.graph.raw_edges();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_u32::<LittleEndian>(nodes.len() as u32)
+            .unwrap();
+        encoder
+            .write_u32::<LittleEndian>(edges.len() as u32)
+            .unwrap();
+        let columns: Vec<Vec<i32>> = vec![
+            nodes
+                .iter()
+                .map(|node| node.weight.lat.as_micro_degrees())
+                .collect(),
+            nodes
+                .iter()
+                .map(|node| node.weight.lon.as_micro_degrees())
+                .collect(),
+            edges
+                .iter()
+                .map(|edge| edge.source().index() as i32)
+                .collect(),
+            edges
+                .iter()
+                .map(|edge| edge.target().index() as i32)
+                .collect(),
+            edges
+                .iter()
+                .map(|edge| edge.weight.distance as i32)
+                .collect(),
+            edges
+                .iter()
+                .map(|edge| edge.weight.road_level as i32)
+                .collect(),
+        ];
+        for column in columns {
+            let mut prev = 0;
+            for value in column {
+                encoder.write_i32::<LittleEndian>(value - prev).unwrap();
+                prev = value;
+            }
+        }
+        let bytes = encoder.finish().unwrap();
+
+        let old = Cartograph::from_bytes(&bytes).unwrap();
+        assert!(carto.diff(&old).is_empty());
+        assert_eq!(old.road_levels, RoadLevelStyle::defaults());
+
+        // Upgrade it to the latest version
+        let file = tempfile::NamedTempFile::new().unwrap();
+        old.save(file.path()).unwrap();
+        Cartograph::verify_checksum(file.path()).unwrap();
+        assert!(carto
+            .diff(&Cartograph::open(file.path()).unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn save() {
+        let carto = get_carto();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        carto.save(file.path()).unwrap();
+        Cartograph::verify_checksum(file.path()).unwrap();
+        let saved = Cartograph::open(file.path()).unwrap();
+        assert!(carto.diff(&saved).is_empty());
+        assert_eq!(saved.road_levels, carto.road_levels);
+        assert_eq!(saved.stats(), carto.stats());
+        assert_eq!(Cartograph::read_stats(file.path()).unwrap(), carto.stats());
+
+        let from = saved.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = saved.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        assert_eq!(saved.shortest_path(&from, &to).distance, 12183);
+
+        // The attributes of the edges are kept, even when changed in memory
+        let mut builder = CartographBuilder::default();
+        let a = builder.add_node(GeoPoint::from_degrees(42.5, 1.5));
+        let b = builder.add_node(GeoPoint::from_degrees(42.501, 1.5));
+        let mut info = EdgeInfo::new(111, 2);
+        info.access = AccessClass::LivingStreet;
+        info.surface = Surface::Gravel;
+        info.lanes = 2;
+        info.width = 6.5;
+        info.reversible = true;
+        builder.add_edge(a, b, info);
+        let mut bytes = Vec::new();
+        builder.build().write_to(&mut bytes).unwrap();
+        let saved = Cartograph::from_bytes(&bytes).unwrap();
+        assert_eq!(saved.graph.raw_edges()[0].weight, info);
+        assert_eq!(saved.osm_node_id(a), None);
+    }
+
+    #[test]
+    fn osm_ids() {
+        let mut graph = Graph::new();
+        let a = graph.add_node(GeoPoint::from_degrees(42.501, 1.5));
+        let b = graph.add_node(GeoPoint::from_degrees(42.5, 1.5));
+        let mut info = EdgeInfo::new(111, 2);
+        info.way_id = 9_876_543_210;
+        graph.add_edge(a, b, info);
+        graph.add_edge(b, a, EdgeInfo::new(111, 2));
+        let node_ids = [5_000_000_001, 42];
+        let mut bytes = Vec::new();
+        format::write_graph(
+            &mut bytes,
+            &graph,
+            |&point| point,
+            |&info| info,
+            Some(&node_ids),
+            &RoadLevelStyle::defaults(),
+            &RoadNames::default(),
+            &EdgeShapes::default(),
+            &Metadata::default(),
+            false,
+        )
+        .unwrap();
+
+        // The nodes are sorted by their coordinates
+        let carto = Cartograph::from_bytes(&bytes).unwrap();
+        assert_eq!(carto.osm_node_id(NodeIndex::new(0)), Some(42));
+        assert_eq!(carto.osm_node_id(NodeIndex::new(1)), Some(5_000_000_001));
+        let way_ids: Vec<_> = carto
+            .graph
+            .raw_edges()
+            .iter()
+            .map(|edge| edge.weight.way_id)
+            .collect();
+        assert_eq!(way_ids, vec![0, 9_876_543_210]);
+
+        // Written again, with the ids
+        let mut saved = Vec::new();
+        carto.write_to(&mut saved).unwrap();
+        assert_eq!(saved, bytes);
+    }
+
+    #[test]
+    fn projected_nodes() {
+        let mut carto = get_carto();
+        assert!(!carto.projected_nodes);
+        let mut plain = Vec::new();
+        carto.write_to(&mut plain).unwrap();
+        carto.projected_nodes = true;
+        let mut bytes = Vec::new();
+        carto.write_to(&mut bytes).unwrap();
+        let section = 1 + format::PROJECTED_NODES_SECTION.len() + 8 + 3124 * 16;
+        assert_eq!(bytes.len(), plain.len() + section);
+
+        // The index is the same as when projecting the nodes
+        let reopened = Cartograph::from_bytes(&bytes).unwrap();
+        assert!(reopened.projected_nodes);
+        let projected = Cartograph::from_bytes(&plain).unwrap();
+        let xy1 = GeoPoint::from_degrees(42.4, 1.4).web_mercator_project();
+        let xy2 = GeoPoint::from_degrees(42.7, 1.8).web_mercator_project();
+        let mut edges: Vec<_> = reopened.rtree.locate_with_corner(xy1, xy2).collect();
+        let mut expected: Vec<_> = projected.rtree.locate_with_corner(xy1, xy2).collect();
+        edges.sort_by_key(|&(edge, _)| edge);
+        expected.sort_by_key(|&(edge, _)| edge);
+        assert_eq!(edges.len(), 5831);
+        assert_eq!(edges, expected);
+
+        // Kept when saved again
+        let mut saved = Vec::new();
+        reopened.write_to(&mut saved).unwrap();
+        assert_eq!(saved, bytes);
+    }
+
+    #[test]
+    fn errors() {
+        let error = Cartograph::open("test_data/missing.ptolemy").err().unwrap();
+        assert!(
+            matches!(&error, CartographError::Io(err) if err.kind() == io::ErrorKind::NotFound)
+        );
+        assert_eq!(io::Error::from(error).kind(), io::ErrorKind::NotFound);
+
+        let error = Cartograph::from_bytes(b"PBF").err().unwrap();
+        assert!(matches!(error, CartographError::BadMagic));
+        let error = Cartograph::from_bytes(b"PTOLEMY-v1\0\0").err().unwrap();
+        assert!(matches!(error, CartographError::UnsupportedVersion(version) if version == "v1"));
+
+        // Corrupt the gzip stream of the longitudes, that starts after the latitudes
+        let mut bytes = std::fs::read("test_data/andorra.ptolemy").unwrap();
+        let lat_size = u64::from_le_bytes(bytes[18..26].try_into().unwrap()) as usize;
+        let lon_offset = 26 + lat_size;
+        for byte in &mut bytes[lon_offset + 8 + 20..lon_offset + 8 + 40] {
+            *byte = !*byte;
+        }
+        match Cartograph::from_bytes(&bytes).err().unwrap() {
+            CartographError::Corrupt { column, offset } => {
+                assert_eq!(column, "longitudes");
+                assert_eq!(offset, lon_offset as u64);
+            }
+            error => panic!("Unexpected error {}", error),
+        }
+        let error = io::Error::from(Cartograph::from_bytes(&bytes[..1000]).err().unwrap());
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn from_bytes() {
+        let bytes = std::fs::read("test_data/andorra.ptolemy").unwrap();
+        let carto = Cartograph::from_bytes(&bytes).unwrap();
+        assert_eq!(carto.graph.node_count(), 3124);
+        let section = style::ROAD_LEVELS_SECTION.as_bytes();
+        assert!(bytes.windows(section.len()).any(|window| window == section));
+        assert_eq!(carto.graph.edge_count(), 5831);
+
+        assert!(Cartograph::from_bytes(&bytes[..1000]).is_err());
+    }
+
+    #[test]
+    fn project() {
+        let carto = get_carto();
+
+        // Project to a road nearby
+        let p = GeoPoint::from_degrees(42.552221, 1.586691);
+        let res = carto.project(&p);
+        assert_eq!(res.original, p);
+        assert_eq!(res.projected, GeoPoint::from_degrees(42.553210, 1.588908));
+        assert_eq!(res.edge, EdgeIndex::new(4199));
+        assert_eq!(res.edge_pos, 0.1256024);
+        assert_eq!(
+            res.original.haversine_distance(&res.projected),
+            212.3022254769895
+        );
+
+        // Project to source node
+        let source = carto.graph[carto.graph.edge_endpoints(res.edge).unwrap().0];
+        let res_source = carto.project(&source);
+        assert_eq!(res_source.projected, source);
+        assert_eq!(res_source.edge, res.edge);
+        assert_eq!(res_source.edge_pos, 0.);
+    }
+
+    #[test]
+    fn approach() {
+        let carto = get_carto();
+        let projected = carto.project(&GeoPoint::from_degrees(42.509827, 1.537439));
+        let approach = carto.approach(&projected);
+        assert!(approach.bearing >= 0. && approach.bearing < 360.);
+        assert_ne!(approach.side, Side::OnRoad);
+
+        // On the edge in the opposite direction, the heading and the side are reversed
+        let (source, target) = carto.graph.edge_endpoints(projected.edge).unwrap();
+        let reverse = carto.graph.find_edge(target, source).unwrap();
+        let opposite = carto.approach(&ProjectedPoint {
+            edge: reverse,
+            edge_pos: 1. - projected.edge_pos,
+            ..projected
+        });
+        let turn = (opposite.bearing - approach.bearing + 360.) % 360.;
+        assert!((turn - 180.).abs() < 1., "{}", turn);
+        assert_ne!(opposite.side, approach.side);
+        assert_ne!(opposite.side, Side::OnRoad);
+
+        // A point of the road itself
+        let on_road = carto.project(&projected.projected);
+        assert_eq!(carto.approach(&on_road).side, Side::OnRoad);
+    }
+
+    #[test]
+    fn project_on_shape() {
+        // A one-way road that bends to the north between its nodes
+        let mut builder = CartographBuilder::default();
+        let a = builder.add_node(GeoPoint::from_degrees(42.5, 1.5));
+        let b = builder.add_node(GeoPoint::from_degrees(42.5, 1.502));
+        let bend = GeoPoint::from_degrees(42.501, 1.501);
+        let mut info = EdgeInfo::new(277, 5);
+        info.shape = 1;
+        let edge = builder.add_edge(a, b, info);
+        builder.edge_shapes(EdgeShapes(vec![vec![bend]]));
+        let carto = builder.build();
+        assert_eq!(carto.rtree.size(), 2);
+
+        // Close to the bend, which is far from the straight line between the nodes
+        let near_bend = carto.project(&GeoPoint::from_degrees(42.5011, 1.501));
+        assert_eq!(near_bend.edge, edge);
+        assert!(near_bend.projected.haversine_distance(&bend) < 1.);
+        assert!((near_bend.edge_pos - 0.5).abs() < 0.01);
+
+        // The heading is the one of the segment of each point
+        let first = carto.project(&GeoPoint::from_degrees(42.5005, 1.5004));
+        let bearing = carto.approach(&first).bearing;
+        assert!(bearing > 30. && bearing < 45., "{}", bearing);
+        let second = carto.project(&GeoPoint::from_degrees(42.5005, 1.5016));
+        let bearing = carto.approach(&second).bearing;
+        assert!(bearing > 135. && bearing < 150., "{}", bearing);
+
+        // The path between them goes through the bend
+        let path = carto.shortest_path(&first, &second);
+        assert_eq!(path.points, vec![first.projected, bend, second.projected]);
+
+        // The edge is found once, although both of its segments are in the region
+        let (corner1, corner2) = (
+            GeoPoint::from_degrees(42.499, 1.499),
+            GeoPoint::from_degrees(42.502, 1.503),
+        );
+        assert_eq!(carto.edges_in_bbox(&corner1, &corner2).len(), 1);
+        let (xy1, xy2) = (
+            corner1.web_mercator_project(),
+            corner2.web_mercator_project(),
+        );
+        let sample = carto.sample_edges(xy1, xy2, 10, None);
+        assert_eq!(sample[&5], vec![edge]);
+        assert_eq!(carto.sample_edges_parallel(xy1, xy2, 10, None), sample);
+    }
+
+    #[test]
+    fn nearest_node() {
+        let carto = get_carto();
+        let node = NodeIndex::new(42);
+        let point = carto.graph[node];
+
+        // A few meters away, the node is still the closest one
+        let near = GeoPoint::from_degrees(point.lat.as_degrees() + 0.00001, point.lon.as_degrees());
+        assert_eq!(carto.nearest_node(&near, 10.), Some(node));
+        assert_eq!(carto.nearest_node(&near, 0.5), None);
+
+        let nearest = carto.nearest_nodes(&near, 5);
+        assert_eq!(nearest.len(), 5);
+        assert_eq!(nearest[0].0, node);
+        assert!(nearest.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        let farthest = nearest[4].1;
+        assert!(carto
+            .graph
+            .node_indices()
+            .filter(|node| nearest.iter().all(|nearest| nearest.0 != *node))
+            .all(|node| near.haversine_distance(&carto.graph[node]) >= farthest - 1e-6));
+        assert!(carto.nearest_nodes(&near, 0).is_empty());
+
+        let corner1 = GeoPoint::from_degrees(
+            point.lat.as_degrees() - 0.001,
+            point.lon.as_degrees() - 0.001,
+        );
+        let corner2 = GeoPoint::from_degrees(
+            point.lat.as_degrees() + 0.001,
+            point.lon.as_degrees() + 0.001,
+        );
+        let nodes = carto.nodes_in_bbox(&corner1, &corner2);
+        assert!(nodes.contains(&node));
+        assert!(nodes.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(nodes.iter().all(|&node| {
+            let lat = carto.graph[node].lat.as_degrees();
+            lat >= corner1.lat.as_degrees() && lat <= corner2.lat.as_degrees()
+        }));
+        assert_eq!(carto.nodes_in_bbox(&corner2, &corner1), nodes);
+    }
+
+    #[test]
+    fn graph_introspection() {
+        let carto = get_carto();
+        assert_eq!(carto.nodes().count(), carto.graph.node_count());
+        assert_eq!(carto.edges().count(), carto.graph.edge_count());
+        let node = carto.nodes().nth(42).unwrap();
+        assert_eq!(node.index, NodeIndex::new(42));
+        assert_eq!(node.point, &carto.graph[node.index]);
+
+        let out_edges: Vec<_> = carto.out_edges(node.index).collect();
+        assert!(!out_edges.is_empty());
+        for edge in &out_edges {
+            assert_eq!(edge.source, node.index);
+            assert_eq!(edge.from, node.point);
+            assert_eq!(edge.to, &carto.graph[edge.target]);
+            assert_eq!(edge, &GraphEdge::new(&carto.graph, edge.index));
+        }
+
+        let corner1 = GeoPoint::from_degrees(
+            node.point.lat.as_degrees() - 0.001,
+            node.point.lon.as_degrees() - 0.001,
+        );
+        let corner2 = GeoPoint::from_degrees(
+            node.point.lat.as_degrees() + 0.001,
+            node.point.lon.as_degrees() + 0.001,
+        );
+        let edges = carto.edges_in_bbox(&corner1, &corner2);
+        assert!(edges.windows(2).all(|pair| pair[0].index < pair[1].index));
+        for edge in &out_edges {
+            assert!(edges.contains(edge));
+        }
+    }
+
+    #[test]
+    fn shortest_path() {
+        let carto = get_carto();
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+
+        let res = carto.shortest_path(&from, &to);
+        assert_eq!(res.distance, 12183);
+        assert_eq!(res.points.len(), 111);
+
+        // The duration is between the one at the highest and the lowest default speeds
+        assert!(res.duration > estimate_duration(12183, 0));
+        assert!(res.duration < estimate_duration(12183, 5));
+    }
+
+    #[test]
+    fn overview() {
+        let carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let path = carto.shortest_path(&from, &to);
+
+        let overview = path.overview();
+        assert!(overview.points.len() < path.points.len());
+        assert_eq!(overview.points.first(), path.points.first());
+        assert_eq!(overview.points.last(), path.points.last());
+        assert_eq!(overview.distance, 12183);
+        assert!(overview.polyline.len() < path.polyline.len());
+        assert_eq!(path.simplify(0.).points, path.points);
+    }
+
+    #[test]
+    fn partial_edges() {
+        assert_eq!(partial_distance(100, 0.999), 99);
+        assert_eq!(partial_distance(100, 1.), 100);
+
+        // All the searches truncate the partial edges the same way
+        let carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        assert_eq!(carto.shortest_path_multi(&from, &vec![to]), vec![12183]);
+        assert_eq!(carto.distance_table(&[from], &[to]), vec![vec![12183]]);
+    }
+
+    #[test]
     fn shortest_path_multi() {
         let carto = get_carto();
 
         let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
-        let to1 = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
-        let to2 = carto.project(&GeoPoint::from_degrees(42.440226, 1.492084));
-        let to3 = carto.project(&GeoPoint::from_degrees(42.500441, 1.519031));
-        let to = vec![to1, to2, to3];
+        let to1 = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let to2 = carto.project(&GeoPoint::from_degrees(42.440226, 1.492084));
+        let to3 = carto.project(&GeoPoint::from_degrees(42.500441, 1.519031));
+        let to = vec![to1, to2, to3];
+
+        let single_distances: Vec<u32> = to
+            .iter()
+            .map(|to| carto.shortest_path(&from, to).distance)
+            .collect();
+        assert_eq!(carto.shortest_path_multi(&from, &to), single_distances);
+
+        // The full paths are the same as the single ones
+        let paths = carto.shortest_paths_multi(&from, &to);
+        for (path, to) in paths.into_iter().zip(&to) {
+            let single = carto.shortest_path(&from, to);
+            let path = path.unwrap();
+            assert_eq!(path.distance, single.distance);
+            assert_eq!(path.duration, single.duration);
+        }
+    }
+
+    #[test]
+    fn closest_target() {
+        let carto = get_carto();
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to1 = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let to2 = carto.project(&GeoPoint::from_degrees(42.440226, 1.492084));
+        let to3 = carto.project(&GeoPoint::from_degrees(42.500441, 1.519031));
+        let to = vec![to1, to2, to3];
+
+        let distances = carto.shortest_path_multi(&from, &to);
+        let closest = (0..to.len()).min_by_key(|&i| distances[i]).unwrap();
+        let (i, path) = carto.closest_target(&from, &to).unwrap();
+        assert_eq!(i, closest);
+        assert_eq!(path.distance, distances[closest]);
+        let expected = carto.shortest_path(&from, &to[closest]);
+        assert_eq!(path.points, expected.points);
+        assert_eq!(path.duration, expected.duration);
+
+        assert!(carto.closest_target(&from, &[]).is_none());
+        let (i, path) = carto.closest_target(&from, &[to1, from]).unwrap();
+        assert_eq!((i, path.distance), (1, 0));
+    }
+
+    #[test]
+    fn deadline() {
+        let carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+
+        let deadline = Deadline::after(std::time::Duration::from_secs(60));
+        let path = carto.shortest_path_with_deadline(&from, &to, &deadline);
+        assert_eq!(path.unwrap().unwrap().distance, 12183);
+        let closed = HashSet::new();
+        let cost = ClosedEdges {
+            edges: &closed,
+            cost: &AccessAndSurfaces {
+                access: &AccessPenalties::default(),
+                surfaces: &SurfacePenalties::default(),
+            },
+        };
+        let path = carto.cheapest_path(&from, &to, &cost, &deadline);
+        assert_eq!(path.unwrap().unwrap().distance, 12183);
+        assert_eq!(
+            carto.distance_table_with_deadline(&[from], &[to], &deadline),
+            Ok(vec![vec![12183]])
+        );
+
+        // The clones share the cancellation
+        deadline.clone().cancel();
+        assert!(deadline.has_passed());
+        let path = carto.shortest_path_with_deadline(&from, &to, &deadline);
+        assert_eq!(path.unwrap_err(), Timeout);
+        assert_eq!(
+            carto.shortest_path_multi_with_deadline(&from, &[to], &deadline),
+            Err(Timeout)
+        );
+        let penalties = SurfacePenalties::default();
+        let path = carto.cheapest_path(&from, &to, &penalties, &deadline);
+        assert_eq!(path.unwrap_err(), Timeout);
+        let path = carto.cheapest_path(&from, &to, &Experiment::default(), &deadline);
+        assert_eq!(path.unwrap_err(), Timeout);
+        let profiles = SpeedProfiles::default();
+        let path = carto.fastest_path_at(
+            &from,
+            &to,
+            &profiles,
+            0.,
+            &closed,
+            &HashMap::new(),
+            &deadline,
+        );
+        assert_eq!(path.unwrap_err(), Timeout);
+        let path = carto.cheapest_path_with_extra_edges(&from, &to, &[], &DistanceCost, &deadline);
+        assert_eq!(path.unwrap_err(), Timeout);
+        assert_eq!(
+            carto.round_trip(&from, 5000., 3, &deadline).unwrap_err(),
+            Timeout
+        );
+
+        let deadline = Deadline::after(std::time::Duration::default());
+        assert_eq!(
+            carto.distance_table_with_deadline(&[from], &[to], &deadline),
+            Err(Timeout)
+        );
+        assert!(!Deadline::never().has_passed());
+    }
+
+    #[test]
+    fn project_all() {
+        let carto = get_carto();
+
+        let points: Vec<_> = (0..100)
+            .map(|i| GeoPoint::from_degrees(42.45 + 0.002 * i as f64, 1.45 + 0.003 * i as f64))
+            .collect();
+        // Compare the edges and positions, since `edge_pos` is NaN on the edges with a single point
+        let key = |projected: &ProjectedPoint| (projected.edge, projected.projected);
+        let expected: Vec<_> = points
+            .iter()
+            .map(|point| key(&carto.project(point)))
+            .collect();
+        for &num_threads in &[3, 1000] {
+            let projected = carto.project_all(&points, num_threads);
+            assert_eq!(projected.iter().map(key).collect::<Vec<_>>(), expected);
+        }
+        assert_eq!(carto.project_all(&[], 3), vec![]);
+    }
+
+    #[test]
+    fn project_candidates() {
+        let carto = get_carto();
+
+        let p = GeoPoint::from_degrees(42.552221, 1.586691);
+        let res = carto.project_candidates(&p, 300., 5);
+        assert_eq!(res[0], carto.project(&p));
+        assert!(res.len() > 1 && res.len() <= 5);
+        for pair in res.windows(2) {
+            assert!(
+                pair[0].projected.haversine_distance(&p)
+                    <= pair[1].projected.haversine_distance(&p) + 1e-3
+            );
+        }
+
+        // The closest is always returned
+        assert_eq!(carto.project_candidates(&p, 1., 5), vec![res[0]]);
+    }
+
+    #[test]
+    fn match_trace() {
+        let carto = get_carto();
+
+        // Create a noisy trace from a known route
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let route = carto.shortest_path(&from, &to);
+        let trace: Vec<GeoPoint> = route
+            .points
+            .iter()
+            .enumerate()
+            .map(|(i, point)| {
+                let noise = if i % 2 == 0 { 5e-5 } else { -5e-5 };
+                GeoPoint::from_degrees(point.lat.as_degrees() + noise, point.lon.as_degrees())
+            })
+            .collect();
+
+        let matched = carto.match_trace(&trace, &MatchOptions::default()).unwrap();
+        assert_eq!(matched.points.len(), trace.len());
+        let error = (matched.path.distance as f64 - route.distance as f64).abs();
+        assert!(
+            error < 0.01 * route.distance as f64,
+            "{}",
+            matched.path.distance
+        );
+
+        assert!(carto.match_trace(&[], &MatchOptions::default()).is_none());
+    }
+
+    #[test]
+    fn sample_edges_by_level() {
+        let carto = get_carto();
+        assert_eq!(carto.max_road_level_at_zoom(3), None);
+        assert_eq!(carto.max_road_level_at_zoom(7), Some(1));
+        assert_eq!(carto.max_road_level_at_zoom(18), Some(5));
+
+        let xy1 = GeoPoint::from_degrees(42.4, 1.4).web_mercator_project();
+        let xy2 = GeoPoint::from_degrees(42.7, 1.8).web_mercator_project();
+        let arterial = carto.sample_edges(xy1, xy2, 1000, Some(2));
+        assert!(!arterial.is_empty());
+        assert!(arterial.keys().all(|&level| level <= 2));
+        for edges in arterial.values() {
+            for &edge in edges {
+                assert!(carto.graph[edge].road_level <= 2);
+            }
+        }
+
+        // All the levels are the same as the whole index
+        let mut all = carto.sample_edges(xy1, xy2, 100_000, Some(u8::MAX));
+        let mut expected = carto.sample_edges(xy1, xy2, 100_000, None);
+        all.values_mut().for_each(|edges| edges.sort());
+        expected.values_mut().for_each(|edges| edges.sort());
+        assert_eq!(all, expected);
+
+        // The fixture has no route relations, so filter by another flag
+        let roundabouts = carto.sample_edges_filtered(xy1, xy2, 1000, None, |info| info.roundabout);
+        assert!(!roundabouts.is_empty());
+        assert!(roundabouts
+            .values()
+            .flatten()
+            .all(|&edge| carto.graph[edge].roundabout));
+        assert!(carto
+            .sample_edges_filtered(xy1, xy2, 1000, None, |info| info.bicycle_route)
+            .is_empty());
+    }
+
+    #[test]
+    fn sample_edges_parallel() {
+        let carto = get_carto();
+        let sorted = |mut sample: BTreeMap<u8, Vec<EdgeIndex>>| {
+            sample.values_mut().for_each(|edges| edges.sort());
+            sample
+        };
+
+        let xy1 = GeoPoint::from_degrees(42.4, 1.4).web_mercator_project();
+        let xy2 = GeoPoint::from_degrees(42.7, 1.8).web_mercator_project();
+        for &max_num in &[100, 100_000] {
+            for &max_road_level in &[None, Some(2)] {
+                assert_eq!(
+                    sorted(carto.sample_edges_parallel(xy2, xy1, max_num, max_road_level)),
+                    sorted(carto.sample_edges(xy1, xy2, max_num, max_road_level))
+                );
+            }
+        }
+        assert_eq!(
+            sorted(carto.sample_edges_parallel([xy1[0], xy1[1]], [xy1[0], xy2[1]], 1000, None)),
+            sorted(carto.sample_edges([xy1[0], xy1[1]], [xy1[0], xy2[1]], 1000, None))
+        );
+    }
+
+    #[test]
+    fn sample_polylines() {
+        let carto = get_carto();
+
+        let xy1 = GeoPoint::from_degrees(42.4, 1.4).web_mercator_project();
+        let xy2 = GeoPoint::from_degrees(42.7, 1.8).web_mercator_project();
+        let edges = carto.sample_edges(xy1, xy2, 1000, None);
+        let straight = carto.sample_polylines(xy1, xy2, 1000, None, 0.);
+        let merged = carto.sample_polylines(xy1, xy2, 1000, None, 30.);
+        assert_eq!(
+            edges.keys().collect::<Vec<_>>(),
+            merged.keys().collect::<Vec<_>>()
+        );
+
+        let num_segments = |polylines: &Vec<Vec<GeoPoint>>| -> usize {
+            polylines.iter().map(|polyline| polyline.len() - 1).sum()
+        };
+        for (level, polylines) in &merged {
+            // The same segments are drawn, with fewer polylines
+            assert_eq!(num_segments(polylines), num_segments(&straight[level]));
+            assert!(polylines.len() <= straight[level].len());
+            assert!(straight[level].len() <= edges[level].len());
+        }
+        let count = |sample: &BTreeMap<u8, Vec<Vec<GeoPoint>>>| -> usize {
+            sample.values().map(Vec::len).sum()
+        };
+        assert!(count(&merged) < count(&straight));
+    }
+
+    #[test]
+    fn shortest_path_with_extra_edges() {
+        let carto = get_carto();
+
+        let a = GeoPoint::from_degrees(42.553210, 1.588908);
+        let b = GeoPoint::from_degrees(42.564440, 1.685042);
+        let (from, to) = (carto.project(&a), carto.project(&b));
+
+        // Without extra edges, it is the same as the shortest path
+        let res = carto
+            .shortest_path_with_extra_edges(&from, &to, &[])
+            .unwrap();
+        assert_eq!(res.distance, 12183);
+
+        // A proposed straight road is much shorter
+        let bridge = ExtraEdge {
+            from: a,
+            to: b,
+            bidirectional: false,
+        };
+        let res = carto
+            .shortest_path_with_extra_edges(&from, &to, &[bridge])
+            .unwrap();
+        assert!(res.distance < 12183);
+
+        // But it is one-way
+        let reverse = carto.shortest_path_with_extra_edges(&to, &from, &[bridge]);
+        assert_eq!(
+            reverse.unwrap().distance,
+            carto.shortest_path(&to, &from).distance
+        );
+
+        // The closed edges of the graph are avoided, with their real distance
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        let closed: HashSet<_> = [edges[edges.len() / 4]].iter().copied().collect();
+        let cost = ClosedEdges {
+            edges: &closed,
+            cost: &DistanceCost,
+        };
+        let detour = carto
+            .cheapest_path_with_extra_edges(&from, &to, &[], &cost, &Deadline::never())
+            .unwrap()
+            .unwrap();
+        assert!(detour.distance > 12183);
+        assert!(detour.edges.iter().all(|edge| !closed.contains(edge)));
+        assert_eq!(
+            Some(detour.distance),
+            carto
+                .shortest_path_with_closures(
+                    &from,
+                    &to,
+                    &closed,
+                    &Default::default(),
+                    &Default::default()
+                )
+                .map(|path| path.distance)
+        );
+    }
+
+    #[test]
+    fn pareto_paths() {
+        let carto = get_carto();
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let turns = Turns { min_angle: 45. };
 
-        let single_distances: Vec<u32> = to
+        let front = carto.pareto_paths(&from, &to, [&Distance, &turns], 0.);
+        assert!(front.len() > 1);
+        // The first one is the shortest path
+        assert_eq!(front[0].costs[0], 12183);
+        assert_eq!(front[0].path.distance, 12183);
+        for pair in front.windows(2) {
+            assert!(pair[0].costs[0] < pair[1].costs[0]);
+            assert!(pair[0].costs[1] > pair[1].costs[1]);
+            assert_eq!(pair[1].costs[0], pair[1].path.distance);
+        }
+
+        // Epsilon-dominance returns fewer paths
+        let approx_front = carto.pareto_paths(&from, &to, [&Distance, &turns], 0.2);
+        assert!(!approx_front.is_empty() && approx_front.len() < front.len());
+
+        // Flat terrain does not trade-off with distance
+        let flat = Ascent(|_: &GeoPoint| 0.);
+        let front = carto.pareto_paths(&from, &to, [&Distance, &flat], 0.);
+        assert_eq!(front.len(), 1);
+        assert_eq!(front[0].costs, [12183, 0]);
+
+        // Both points on the same two-way road, reached forward or going back on the opposite edge,
+        // without a loop
+        let projected = carto.project(&GeoPoint::from_degrees(42.509827, 1.537439));
+        let (source, target) = carto.graph.edge_endpoints(projected.edge).unwrap();
+        let (source, target) = (carto.graph[source], carto.graph[target]);
+        let along = |fraction: f64| {
+            let lat =
+                source.lat.as_degrees() * (1. - fraction) + target.lat.as_degrees() * fraction;
+            let lon =
+                source.lon.as_degrees() * (1. - fraction) + target.lon.as_degrees() * fraction;
+            carto.project(&GeoPoint::from_degrees(lat, lon))
+        };
+        let (a, b) = (along(0.3), along(0.7));
+        for &(from, to) in &[(a, b), (b, a)] {
+            let front = carto.pareto_paths(&from, &to, [&Distance, &turns], 0.);
+            let shortest = carto.shortest_path(&from, &to);
+            assert_eq!(front.len(), 1);
+            assert_eq!(front[0].costs[1], 0);
+            assert_eq!(front[0].path.distance, shortest.distance);
+            assert_eq!(front[0].path.points, shortest.points);
+            assert!((front[0].path.duration - shortest.duration).abs() < 1e-6);
+        }
+
+        // The shorter road turns sharply once, then goes straight onto the road of the
+        // destination. The other one bends gently, but arrives across it, so the last turn makes
+        // it worse in both criteria
+        let mut builder = CartographBuilder::default();
+        let points: Vec<_> = [
+            (0., -1.),
+            (0., 0.),
+            (0., 0.5),
+            (0.5, 0.866),
+            (1.366, 1.366),
+            (2.366, 1.366),
+            (3.232, 0.866),
+            (3.732, 0.),
+            (4.732, 0.),
+        ]
+        .iter()
+        .map(|&(x, y)| GeoPoint::from_degrees(y / 1000., x / 1000.))
+        .collect();
+        let nodes: Vec<_> = points
             .iter()
-            .map(|to| carto.shortest_path(&from, to).distance)
+            .map(|&point| builder.add_node(point))
             .collect();
-        assert_eq!(carto.shortest_path_multi(&from, &to), single_distances);
+        for &(a, b) in &[
+            (0, 1),
+            (1, 2),
+            (2, 7),
+            (1, 3),
+            (3, 4),
+            (4, 5),
+            (5, 6),
+            (6, 7),
+            (7, 8),
+        ] {
+            let distance = points[a].haversine_distance(&points[b]).round() as u32;
+            builder.add_edge(nodes[a], nodes[b], EdgeInfo::new(distance, 5));
+        }
+        let carto = builder.build();
+        let from = carto.project(&GeoPoint::from_degrees(-0.0005, 0.));
+        let to = carto.project(&GeoPoint::from_degrees(0., 0.004232));
+        let front = carto.pareto_paths(&from, &to, [&Distance, &turns], 0.);
+        assert_eq!(front.len(), 1);
+        assert_eq!(front[0].costs[1], 1);
+        assert_eq!(front[0].path.points.len(), 5);
+
+        // Both roads arrive at the same node without turning, but the shorter one arrives across
+        // the road of the destination, so the longer one is kept for the turn it avoids after it
+        let mut builder = CartographBuilder::default();
+        let points: Vec<_> = [
+            (0., -1.),
+            (0., 0.),
+            (0.643, 0.766),
+            (0.643, 1.766),
+            (0.069, 2.585),
+        ]
+        .iter()
+        .map(|&(x, y)| GeoPoint::from_degrees(y / 1000., x / 1000.))
+        .collect();
+        let nodes: Vec<_> = points
+            .iter()
+            .map(|&point| builder.add_node(point))
+            .collect();
+        for &(a, b) in &[(0, 1), (1, 3), (1, 2), (2, 3), (3, 4)] {
+            let distance = points[a].haversine_distance(&points[b]).round() as u32;
+            builder.add_edge(nodes[a], nodes[b], EdgeInfo::new(distance, 5));
+        }
+        let carto = builder.build();
+        let from = carto.project(&GeoPoint::from_degrees(-0.0005, 0.));
+        let to = carto.project(&GeoPoint::from_degrees(0.0021755, 0.000356));
+        let front = carto.pareto_paths(&from, &to, [&Distance, &turns], 0.);
+        assert_eq!(front.len(), 2);
+        assert_eq!(front[0].costs[1], 1);
+        assert_eq!(front[0].path.points.len(), 4);
+        assert_eq!(front[1].costs[1], 0);
+        assert_eq!(front[1].path.points.len(), 5);
+    }
+
+    #[test]
+    fn congested_costs() {
+        let carto = get_carto();
+        let edge = EdgeIndex::new(17);
+        let info = carto.graph[edge];
+        assert!(info.capacity >= estimate_capacity(info.road_level, 1));
+
+        let mut volumes = vec![0.; carto.graph.edge_count()];
+        let bpr = Bpr::default();
+        let costs = carto.congested_costs(&volumes, &bpr);
+        assert_eq!(costs[edge.index()], info.distance as f64);
+
+        // At capacity, the cost is 15% higher
+        volumes[edge.index()] = info.capacity as f64;
+        let costs = carto.congested_costs(&volumes, &bpr);
+        assert!((costs[edge.index()] - 1.15 * info.distance as f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn round_trip() {
+        let carto = get_carto();
+        let start = carto.project(&GeoPoint::from_degrees(42.507500, 1.521800));
+
+        let deadline = Deadline::never();
+        let path = carto
+            .round_trip(&start, 5000., 3, &deadline)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.points.first(), Some(&start.projected));
+        assert_eq!(path.points.last(), Some(&start.projected));
+        assert!(
+            (path.distance as f64 - 5000.).abs() <= 1000.,
+            "{}",
+            path.distance
+        );
+
+        // Deterministic for the same seed
+        let again = carto
+            .round_trip(&start, 5000., 3, &deadline)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.points, again.points);
+        let other = carto
+            .round_trip(&start, 5000., 4, &deadline)
+            .unwrap()
+            .unwrap();
+        assert_ne!(path.points, other.points);
+
+        let longer = carto
+            .round_trip(&start, 20000., 3, &deadline)
+            .unwrap()
+            .unwrap();
+        assert!(longer.distance > path.distance);
+    }
+
+    #[test]
+    fn diverse_paths() {
+        let mut carto = get_carto();
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let options = DiversityOptions::default();
+
+        let paths = carto.diverse_paths(&from, &to, 5, 17, &options);
+        assert_eq!(paths.len(), 5);
+        for path in &paths {
+            assert!(path.distance >= 12183);
+            assert!(path.distance as f64 <= 12183. * 1.1);
+        }
+        assert!(paths.iter().any(|path| path.points != paths[0].points));
+
+        // Deterministic for the same seed
+        let again = carto.diverse_paths(&from, &to, 5, 17, &options);
+        for (a, b) in paths.iter().zip(&again) {
+            assert_eq!(a.points, b.points);
+        }
+        let single = carto.randomized_path(&from, &to, 17, &options);
+        assert_eq!(single.points, paths[0].points);
+
+        // Without perturbation, this is the shortest path
+        let options = DiversityOptions {
+            noise: 0.,
+            penalty: 0.,
+            ..options
+        };
+        assert_eq!(
+            carto.randomized_path(&from, &to, 17, &options).distance,
+            12183
+        );
+
+        // The closed edges are never traversed, and the shortest path is then the detour
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        let closed: HashSet<_> = [edges[edges.len() / 4]].iter().copied().collect();
+        let cost = ClosedEdges {
+            edges: &closed,
+            cost: &DistanceCost,
+        };
+        let deadline = Deadline::never();
+        let randomized = carto
+            .randomized_path_with_cost(&from, &to, 17, &options, &cost, &deadline)
+            .unwrap()
+            .unwrap();
+        assert!(randomized.edges.iter().all(|edge| !closed.contains(edge)));
+        assert_eq!(
+            Some(randomized.distance),
+            carto
+                .shortest_path_with_cost(&from, &to, &cost)
+                .map(|path| path.distance)
+        );
+
+        // Like the forbidden access classes and their penalties
+        carto.graph[edges[edges.len() / 4]].access = AccessClass::Pedestrian;
+        let access = AccessPenalties {
+            penalized: Vec::new(),
+            forbidden: vec![AccessClass::Pedestrian],
+            barrier: 0,
+        };
+        let surfaces = SurfacePenalties::default();
+        let cost = AccessAndSurfaces {
+            access: &access,
+            surfaces: &surfaces,
+        };
+        let randomized = carto
+            .randomized_path_with_cost(&from, &to, 17, &options, &cost, &deadline)
+            .unwrap()
+            .unwrap();
+        assert!(randomized.edges.iter().all(|edge| !closed.contains(edge)));
+        assert_eq!(
+            Some(randomized.distance),
+            carto
+                .shortest_path_with_access(&from, &to, &access, &surfaces)
+                .map(|path| path.distance)
+        );
+    }
+
+    #[test]
+    fn assign_traffic() {
+        let carto = get_carto();
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let shortest = carto.shortest_path(&from, &to);
+        let demands = vec![Demand {
+            from,
+            to,
+            volume: 100.,
+        }];
+
+        // Light traffic follows the shortest path
+        let options = AssignOptions::default();
+        let result = carto.assign_traffic(&demands, &options);
+        assert_eq!(result.volumes.len(), carto.graph.edge_count());
+        assert_eq!(result.unassigned, 0.);
+        assert_eq!(result.volumes[from.edge.index()], 100.);
+        assert_eq!(result.volumes[to.edge.index()], 100.);
+        let loaded = result.volumes.iter().filter(|&&v| v > 0.).count();
+        assert_eq!(loaded, shortest.points.len() - 1);
+
+        // Closing a street moves the traffic elsewhere
+        let busy_edge = (0..carto.graph.edge_count())
+            .map(EdgeIndex::new)
+            .find(|&edge| edge != from.edge && edge != to.edge && result.volumes[edge.index()] > 0.)
+            .unwrap();
+        let options = AssignOptions {
+            closed_edges: vec![busy_edge],
+            ..AssignOptions::default()
+        };
+        let result = carto.assign_traffic(&demands, &options);
+        assert_eq!(result.unassigned, 0.);
+        assert_eq!(result.volumes[busy_edge.index()], 0.);
+
+        // Heavy traffic spreads over several paths
+        let demands = vec![Demand {
+            from,
+            to,
+            volume: 10000.,
+        }];
+        let result = carto.assign_traffic(&demands, &AssignOptions::default());
+        let loaded = result.volumes.iter().filter(|&&v| v > 0.).count();
+        assert!(loaded > shortest.points.len() - 1);
+        assert!(result.costs[busy_edge.index()] > carto.graph[busy_edge].distance as f64);
+    }
+
+    #[test]
+    fn virtual_nodes() {
+        let carto = get_carto();
+
+        // A long edge of a two-way road
+        let edge = carto
+            .graph
+            .edge_references()
+            .find(|edge| {
+                edge.weight().distance > 200
+                    && carto
+                        .graph
+                        .find_edge(edge.target(), edge.source())
+                        .is_some()
+            })
+            .unwrap();
+        let opposite = carto.graph.find_edge(edge.target(), edge.source()).unwrap();
+        let (source, target) = (carto.graph[edge.source()], carto.graph[edge.target()]);
+        let point = |fraction: f64| {
+            let lat =
+                source.lat.as_degrees() * (1. - fraction) + target.lat.as_degrees() * fraction;
+            let lon =
+                source.lon.as_degrees() * (1. - fraction) + target.lon.as_degrees() * fraction;
+            carto
+                .project_on_edge(&GeoPoint::from_degrees(lat, lon), edge.id())
+                .0
+        };
+        let (a, b) = (point(0.2), point(0.8));
+
+        // Forwards, the path stays on the edge
+        let path = carto.shortest_path(&a, &b);
+        let expected = edge.weight().distance as f64 * 0.6;
+        assert!((path.distance as f64 - expected).abs() < 2.);
+        assert_eq!(path.points, vec![a.projected, b.projected]);
+        assert_eq!(carto.shortest_path_edges(&a, &b), Some(vec![edge.id()]));
+
+        // Backwards, it uses the opposite edge instead of going around
+        let path = carto.shortest_path(&b, &a);
+        let expected = carto.graph[opposite].distance as f64 * 0.6;
+        assert!((path.distance as f64 - expected).abs() < 2.);
+        assert_eq!(path.points, vec![b.projected, a.projected]);
+        assert_eq!(carto.shortest_path_edges(&b, &a), Some(vec![opposite]));
+        assert_eq!(
+            carto.shortest_path_multi(&b, &vec![a, b]),
+            vec![path.distance, 0]
+        );
+
+        // The searches with penalties, closures, speeds or noise leave and reach the points alike
+        let (access, none) = (AccessPenalties::default(), SurfacePenalties::default());
+        let penalized = carto.shortest_path_with_penalties(&b, &a, &none).unwrap();
+        assert_eq!(penalized.edges, vec![opposite]);
+        let closed = HashSet::new();
+        let avoiding = carto
+            .shortest_path_with_closures(&b, &a, &closed, &access, &none)
+            .unwrap();
+        assert_eq!(avoiding.edges, vec![opposite]);
+        let speeds = carto
+            .shortest_path_with_speeds(&b, &a, &HashMap::new(), &closed)
+            .unwrap();
+        assert_eq!(speeds.distance, path.distance);
+        assert!((speeds.duration - path.duration).abs() < 1e-6);
+        let randomized = carto.randomized_path(&b, &a, 1, &DiversityOptions::default());
+        assert_eq!(randomized.edges, vec![opposite]);
+    }
+
+    #[test]
+    fn shortest_path_edges() {
+        let carto = get_carto();
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let path = carto.shortest_path(&from, &to);
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        assert_eq!(edges.len(), path.points.len() - 1);
+        assert_eq!(edges[0], from.edge);
+        assert_eq!(edges[edges.len() - 1], to.edge);
+        for pair in edges.windows(2) {
+            let (_, target) = carto.graph.edge_endpoints(pair[0]).unwrap();
+            let (source, _) = carto.graph.edge_endpoints(pair[1]).unwrap();
+            assert_eq!(target, source);
+        }
+
+        // Lanes are parsed for some of the roads
+        assert!(edges.iter().any(|&edge| carto.graph[edge].lanes > 0));
+    }
+
+    #[test]
+    fn synthetic_edges() {
+        let mut carto = get_carto();
+        let synthetic = carto
+            .graph
+            .edge_references()
+            .filter(|edge| edge.weight().synthetic)
+            .count();
+        assert!(synthetic > 0 && synthetic < carto.graph.edge_count() / 10);
+
+        // The road is cut at the border, so one of the directions relies on invented edges
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        assert!(edges.iter().any(|&edge| carto.graph[edge].synthetic));
+
+        carto.allow_synthetic = false;
+        assert!(carto.try_shortest_path(&from, &to).is_none());
+        assert!(carto.shortest_path_edges(&from, &to).is_none());
+        assert!(carto.route_steps(&from, &to).is_none());
+        let experiment = Experiment::default();
+        assert!(carto
+            .shortest_path_with_experiment(&from, &to, &experiment)
+            .is_none());
+        let from = carto.project(&GeoPoint::from_degrees(42.507800, 1.521100));
+        let to = carto.project(&GeoPoint::from_degrees(42.509600, 1.538600));
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        assert!(edges.iter().all(|&edge| !carto.graph[edge].synthetic));
+    }
+
+    #[test]
+    fn shortest_path_with_penalties() {
+        let mut carto = get_carto();
+        assert!(carto
+            .graph
+            .edge_references()
+            .any(|edge| edge.weight().surface == Surface::Cobblestone));
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let penalties = SurfacePenalties::default();
+        let path = carto.shortest_path_with_penalties(&from, &to, &penalties);
+        assert_eq!(path.unwrap().distance, 12183);
+
+        // Avoiding paved roads makes the route longer
+        let penalties = SurfacePenalties {
+            surfaces: vec![(Surface::Paved, 3.)],
+            smoothness: None,
+        };
+        let path = carto.shortest_path_with_penalties(&from, &to, &penalties);
+        assert!(path.unwrap().distance > 12183);
+
+        // Without the invented edges, the destination is not reachable
+        carto.allow_synthetic = false;
+        assert!(carto
+            .shortest_path_with_penalties(&from, &to, &penalties)
+            .is_none());
+    }
+
+    #[test]
+    fn shortest_path_with_access() {
+        let mut carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let none = SurfacePenalties::default();
+
+        // Turn a road in the middle of the shortest path into a pedestrian zone
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        let zone = edges[edges.len() / 4];
+        carto.graph[zone].access = AccessClass::Pedestrian;
+
+        let access = AccessPenalties::default();
+        let path = carto.shortest_path_with_access(&from, &to, &access, &none);
+        assert_eq!(path.unwrap().distance, 12183);
+
+        // The barriers are crossed unless their penalty is higher than going around them
+        carto.graph[zone].barrier = true;
+        let access = AccessPenalties {
+            barrier: 100,
+            ..AccessPenalties::default()
+        };
+        let path = carto.shortest_path_with_access(&from, &to, &access, &none);
+        assert_eq!(path.unwrap().distance, 12183);
+        let access = AccessPenalties {
+            barrier: 100_000,
+            ..AccessPenalties::default()
+        };
+        let path = carto.shortest_path_with_access(&from, &to, &access, &none);
+        assert!(path.unwrap().distance > 12183);
+        carto.graph[zone].barrier = false;
+
+        // A small penalty is not enough to go around it, but forbidding it is
+        let access = AccessPenalties {
+            penalized: vec![(AccessClass::Pedestrian, 1.01)],
+            forbidden: vec![],
+            barrier: 0,
+        };
+        let path = carto.shortest_path_with_access(&from, &to, &access, &none);
+        assert_eq!(path.unwrap().distance, 12183);
+        let access = AccessPenalties {
+            penalized: vec![],
+            forbidden: vec![AccessClass::Pedestrian],
+            barrier: 0,
+        };
+        let path = carto.shortest_path_with_access(&from, &to, &access, &none);
+        assert!(path.unwrap().distance > 12183);
+
+        // The destination may become unreachable
+        for &edge in &edges[1..edges.len() - 1] {
+            carto.graph[edge].access = AccessClass::Pedestrian;
+        }
+        assert!(carto
+            .shortest_path_with_access(&from, &to, &access, &none)
+            .is_none());
+    }
+
+    #[test]
+    fn shortest_path_with_closures() {
+        let carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let access = AccessPenalties::default();
+        let none = SurfacePenalties::default();
+
+        // Close a road of the shortest path, but only for a while
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        let closures: Closures = format!("edge:{},1000,2000", edges[edges.len() / 4].index())
+            .parse()
+            .unwrap();
+        let path = |time| {
+            let closed = closures.closed_edges(&carto, time);
+            carto
+                .shortest_path_with_closures(&from, &to, &closed, &access, &none)
+                .unwrap()
+                .distance
+        };
+        assert_eq!(path(0), 12183);
+        assert!(path(1500) > 12183);
+        assert_eq!(path(2000), 12183);
+    }
+
+    #[test]
+    fn borders() {
+        let mut carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let access = AccessPenalties::default();
+        let none = SurfacePenalties::default();
+        let path = carto.shortest_path(&from, &to);
+        assert_eq!(carto.border_crossings(&path), 0);
+
+        // A short border across the middle of a road of the shortest path
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        let geometry = carto.edge_geometry(edges[edges.len() / 4]);
+        let (a, b) = (geometry[0], geometry[1]);
+        let (lat, lon) = (
+            (a.lat.as_degrees() + b.lat.as_degrees()) / 2.,
+            (a.lon.as_degrees() + b.lon.as_degrees()) / 2.,
+        );
+        let (d_lat, d_lon) = (
+            (b.lat.as_degrees() - a.lat.as_degrees()) / 5.,
+            (b.lon.as_degrees() - a.lon.as_degrees()) / 5.,
+        );
+        let border = Borders::new(&[vec![
+            GeoPoint::from_degrees(lat - d_lon, lon + d_lat),
+            GeoPoint::from_degrees(lat + d_lon, lon - d_lat),
+        ]]);
+        let num_borders = carto.mark_borders(&border);
+        assert!(num_borders >= 1);
+        assert_eq!(carto.border_edges().len(), num_borders);
+        assert!(carto.border_edges().contains(&edges[edges.len() / 4]));
+        assert_eq!(carto.border_crossings(&path), 1);
+        assert_eq!(carto.border_crossings(&path.overview()), 1);
+
+        let closed: HashSet<_> = carto.border_edges().iter().copied().collect();
+        let avoiding = carto
+            .shortest_path_with_closures(&from, &to, &closed, &access, &none)
+            .unwrap();
+        assert!(avoiding.distance > path.distance);
+        assert_eq!(carto.border_crossings(&avoiding), 0);
+
+        assert_eq!(carto.mark_borders(&Borders::new(&[])), 0);
+        assert!(carto.border_edges().is_empty());
+    }
+
+    #[test]
+    fn shortest_path_with_speeds() {
+        let carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let closed = HashSet::new();
+        let shortest = carto.shortest_path(&from, &to);
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        let with_speeds = |speeds: &HashMap<EdgeIndex, f64>| {
+            carto
+                .shortest_path_with_speeds(&from, &to, speeds, &closed)
+                .unwrap()
+        };
+        let same = with_speeds(&HashMap::new());
+        assert_eq!(same.distance, shortest.distance);
+        assert!((same.duration - shortest.duration).abs() < 1e-6);
+
+        // A faster road keeps the path, but shortens its duration
+        let middle = edges[edges.len() / 2];
+        let info = &carto.graph[middle];
+        let faster = with_speeds(&[(middle, 2. * estimate_speed(info.road_level))].into());
+        assert_eq!(faster.distance, shortest.distance);
+        assert!((shortest.duration - faster.duration - info.duration() / 2.).abs() < 1e-6);
+
+        // A slow road is kept when the detour is longer, and a jammed one is avoided
+        let slow_edge = edges[edges.len() / 4];
+        let slow = with_speeds(&[(slow_edge, 1.)].into());
+        assert_eq!(slow.distance, shortest.distance);
+        let slow_duration = carto.graph[slow_edge].distance as f64 * 3.6;
+        assert!(slow.duration > shortest.duration + slow_duration / 2.);
+        let jammed = with_speeds(&[(slow_edge, 0.01)].into());
+        assert!(jammed.distance > shortest.distance);
+
+        // The other searches use the speeds in their costs, and their durations are fixed after
+        let speeds: HashMap<_, _> = [(slow_edge, 0.01)].into();
+        let cost = LiveSpeeds {
+            speeds: &speeds,
+            cost: &DistanceCost,
+        };
+        let mut extra = carto
+            .cheapest_path_with_extra_edges(&from, &to, &[], &cost, &Deadline::never())
+            .unwrap()
+            .unwrap();
+        carto.apply_speeds(&from, &to, &mut extra, &speeds);
+        assert_eq!(extra.distance, jammed.distance);
+        assert!((extra.duration - jammed.duration).abs() < 1e-6);
+    }
+
+    #[test]
+    fn distance_to_path() {
+        let carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let path = carto.shortest_path(&from, &to);
+        let middle = path.points[path.points.len() / 2];
+        assert!(carto.distance_to_path(&middle, &path) < 0.01);
+        let aside = GeoPoint::from_degrees(middle.lat.as_degrees() + 0.01, middle.lon.as_degrees());
+        let distance = carto.distance_to_path(&aside, &path);
+        assert!(distance > 0. && distance <= aside.haversine_distance(&middle) + 1.);
+    }
+
+    #[test]
+    fn shortest_path_with_stats() {
+        let carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let (path, stats) = carto
+            .shortest_path_with_stats(&from, &to, &Deadline::never())
+            .unwrap();
+        assert_eq!(path.unwrap().distance, 12183);
+        assert!(stats.settled > 0 && stats.pushes >= stats.settled);
+        let tightness = stats.heuristic_tightness.unwrap();
+        assert!(tightness > 0.5 && tightness <= 1.);
+
+        // A closer destination needs fewer nodes
+        let close = carto.project(&GeoPoint::from_degrees(42.554, 1.59));
+        let (_, close_stats) = carto
+            .shortest_path_with_stats(&from, &close, &Deadline::never())
+            .unwrap();
+        assert!(close_stats.settled < stats.settled);
+
+        // The stats are the ones of the search with the given costs
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        let closed: HashSet<_> = [edges[edges.len() / 4]].iter().copied().collect();
+        let cost = ClosedEdges {
+            edges: &closed,
+            cost: &DistanceCost,
+        };
+        let (detour, detour_stats) = carto
+            .cheapest_path_with_stats(&from, &to, &cost, &Deadline::never())
+            .unwrap();
+        let detour = detour.unwrap();
+        assert_eq!(
+            Some(detour.points),
+            carto
+                .shortest_path_with_cost(&from, &to, &cost)
+                .map(|path| path.points)
+        );
+        assert_ne!(detour_stats.settled, stats.settled);
+        assert!(detour_stats.heuristic_tightness < stats.heuristic_tightness);
+    }
+
+    #[test]
+    fn shortest_path_with_cost() {
+        let mut carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let shortest = carto.shortest_path(&from, &to);
+        let path = carto
+            .shortest_path_with_cost(&from, &to, &DistanceCost)
+            .unwrap();
+        assert_eq!(path.distance, 12183);
+        assert_eq!(path.duration, shortest.duration);
+        assert_eq!(path.points, shortest.points);
+
+        // Avoid a road in the middle of the shortest path
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        let unpaved = edges[edges.len() / 4];
+        carto.graph[unpaved].surface = Surface::Unpaved;
+        let avoid_unpaved = |info: &EdgeInfo, _: &GeoPoint, _: &GeoPoint| match info.surface {
+            Surface::Unpaved => info.distance * 1000,
+            _ => info.distance,
+        };
+        let path = carto
+            .shortest_path_with_cost(&from, &to, &avoid_unpaved)
+            .unwrap();
+        assert!(path.distance > 12183);
+        assert_ne!(path.points, shortest.points);
+
+        // The penalties and forbidden edges of the access classes work the same
+        carto.graph[unpaved].access = AccessClass::Pedestrian;
+        let access = AccessPenalties {
+            penalized: vec![],
+            forbidden: vec![AccessClass::Pedestrian],
+            barrier: 0,
+        };
+        let path = carto.shortest_path_with_cost(&from, &to, &access).unwrap();
+        assert!(path.distance > 12183);
+        for &edge in &edges[1..edges.len() - 1] {
+            carto.graph[edge].access = AccessClass::Pedestrian;
+        }
+        assert!(carto.shortest_path_with_cost(&from, &to, &access).is_none());
+    }
+
+    #[test]
+    fn fastest_path_at() {
+        let carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let shortest = carto.shortest_path(&from, &to);
+        let profiles = SpeedProfiles::default();
+        let (closed, speeds) = (HashSet::new(), HashMap::new());
+        let deadline = Deadline::never();
+        let fastest = carto
+            .fastest_path_at(&from, &to, &profiles, 0., &closed, &speeds, &deadline)
+            .unwrap()
+            .unwrap();
+        assert!(fastest.duration <= shortest.duration + 1e-6);
+        assert!(fastest.distance >= shortest.distance);
+        assert_eq!(fastest.points[0], from.projected);
+        assert_eq!(*fastest.points.last().unwrap(), to.projected);
+
+        // Everything is twice as slow at rush hour
+        let mut rush_hour = [1.; speed_profile::HOURS];
+        rush_hour[8] = 0.5;
+        let mut profiles = SpeedProfiles::default();
+        for level in 0..6 {
+            profiles.road_levels.insert(level, rush_hour);
+        }
+        let at_night = carto
+            .fastest_path_at(
+                &from,
+                &to,
+                &profiles,
+                3. * 3600.,
+                &closed,
+                &speeds,
+                &deadline,
+            )
+            .unwrap()
+            .unwrap();
+        assert!((at_night.duration - fastest.duration).abs() < 1e-6);
+        let slow = carto
+            .fastest_path_at(
+                &from,
+                &to,
+                &profiles,
+                7.9 * 3600.,
+                &closed,
+                &speeds,
+                &deadline,
+            )
+            .unwrap()
+            .unwrap();
+        assert!(slow.duration > 1.5 * fastest.duration);
+        assert!(slow.duration < 2. * fastest.duration);
+
+        // Both points on the same edge
+        let same = carto
+            .fastest_path_at(&from, &from, &profiles, 0., &closed, &speeds, &deadline)
+            .unwrap()
+            .unwrap();
+        assert_eq!(same.distance, 0);
+
+        // The closed edges are avoided
+        let closed: HashSet<_> = [fastest.edges[fastest.edges.len() / 4]]
+            .iter()
+            .copied()
+            .collect();
+        let detour = carto
+            .fastest_path_at(
+                &from,
+                &to,
+                &profiles,
+                3. * 3600.,
+                &closed,
+                &speeds,
+                &deadline,
+            )
+            .unwrap()
+            .unwrap();
+        assert!(detour.duration > fastest.duration);
+        assert!(detour.edges[1..detour.edges.len() - 1]
+            .iter()
+            .all(|edge| !closed.contains(edge)));
+
+        // The known speeds replace the profiles, so a jam is avoided too
+        let jam: HashMap<_, _> = closed.iter().map(|&edge| (edge, 0.01)).collect();
+        let around = carto
+            .fastest_path_at(
+                &from,
+                &to,
+                &profiles,
+                3. * 3600.,
+                &HashSet::new(),
+                &jam,
+                &deadline,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(around.edges, detour.edges);
+        assert!((around.duration - detour.duration).abs() < 1e-6);
+    }
+
+    #[test]
+    fn vehicle_restrictions() {
+        let mut carto = get_carto();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let truck = Vehicle {
+            weight: 18.,
+            height: 4.2,
+            width: 2.5,
+        };
+        let path = carto.shortest_path_with_cost(&from, &to, &truck).unwrap();
+        assert_eq!(path.distance, 12183);
+
+        // A low bridge in the middle of the shortest path
+        let edges = carto.shortest_path_edges(&from, &to).unwrap();
+        carto.graph[edges[edges.len() / 4]].restrictions.max_height = 3.8;
+        let path = carto.shortest_path_with_cost(&from, &to, &truck).unwrap();
+        assert!(path.distance > 12183);
+        let van = Vehicle {
+            height: 2.5,
+            ..Vehicle::default()
+        };
+        let path = carto.shortest_path_with_cost(&from, &to, &van).unwrap();
+        assert_eq!(path.distance, 12183);
+    }
+
+    #[test]
+    fn cost_grid() {
+        let carto = get_carto();
+        let center = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let grid = carto.cost_grid(&center, 100., 2000);
+        assert_eq!(grid.costs.len(), 41);
+        assert!(grid.costs.iter().all(|row| row.len() == 41));
+        assert_eq!(grid.costs[20][20], Some(0));
+        let middle = grid.cell_center(20, 20);
+        assert!(middle.haversine_distance(&center.projected) < 1.);
+        let corner = grid.cell_center(0, 0);
+        assert!((corner.haversine_distance(&middle) - 2000. * 2f64.sqrt()).abs() < 10.);
+
+        // The cost of a cell is the one of its closest road, so it is never more than the
+        // distance to any of its roads
+        let corner1 = grid.cell_center(10, 10);
+        let corner2 = grid.cell_center(30, 30);
+        let mut checked = 0;
+        for node in carto.nodes_in_bbox(&corner1, &corner2).into_iter().take(50) {
+            let to = carto.project(&carto.graph[node]);
+            let distance = carto.shortest_path(&center, &to).distance;
+            let (row, col) = grid.cell(&to.projected).unwrap();
+            if distance <= 2000 {
+                assert!(grid.costs[row][col].unwrap() <= distance + 1);
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+
+        let reachable = grid.costs.iter().flatten().filter(|cost| cost.is_some());
+        assert!(reachable.count() >= 20);
+        assert!(grid
+            .costs
+            .iter()
+            .flatten()
+            .flatten()
+            .all(|&cost| cost <= 2000));
+    }
+
+    #[test]
+    fn shortest_path_with_experiment() {
+        let carto = get_carto();
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let path = carto
+            .shortest_path_with_experiment(&from, &to, &Experiment::default())
+            .unwrap();
+        assert_eq!(path.points, carto.shortest_path(&from, &to).points);
+        assert_eq!(path.distance, 12183);
+
+        // Making the edges of the shortest path more expensive forces a detour
+        let base_points = path.points;
+        let mut experiment = Experiment::default();
+        for edge in carto.shortest_path_edges(&from, &to).unwrap() {
+            experiment.edges.insert(edge.index() as u32, 3.);
+        }
+        let path = carto
+            .shortest_path_with_experiment(&from, &to, &experiment)
+            .unwrap();
+        assert_ne!(path.points, base_points);
+        assert!(path.distance > 12183);
+    }
+
+    #[test]
+    fn route_steps() {
+        let carto = get_carto();
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let steps = carto.route_steps(&from, &to).unwrap();
+        assert_eq!(steps.len(), 22);
+        assert_eq!(steps[0].maneuver, Maneuver::Depart);
+        assert_eq!(steps[0].location, from.projected);
+        assert_eq!(steps[1].maneuver, Maneuver::Roundabout { exit: 4 });
+        assert_eq!(steps[4].maneuver, Maneuver::Roundabout { exit: 2 });
+        assert_eq!(
+            steps[4].maneuver.to_string(),
+            "Take the 2nd exit at the roundabout"
+        );
+        assert_eq!(
+            steps[2].maneuver,
+            Maneuver::Turn(TurnDirection::SlightRight)
+        );
+        assert_eq!(steps[21].maneuver, Maneuver::Arrive);
+        assert_eq!(steps[21].location, to.projected);
+        assert!(steps
+            .iter()
+            .all(|step| step.maneuver != Maneuver::Turn(TurnDirection::Straight)));
+
+        // The distances add up to the path distance, except for rounding
+        let distance: u32 = steps.iter().map(|step| step.distance).sum();
+        assert!(distance <= 12183 && distance + 2 >= 12183);
+        let duration: f64 = steps.iter().map(|step| step.duration).sum();
+        assert!((duration - carto.shortest_path(&from, &to).duration).abs() < 1e-6);
+
+        // The steps of another path follow its own edges
+        let mut experiment = Experiment::default();
+        for edge in carto.shortest_path_edges(&from, &to).unwrap() {
+            experiment.edges.insert(edge.index() as u32, 3.);
+        }
+        let detour = carto
+            .shortest_path_with_experiment(&from, &to, &experiment)
+            .unwrap();
+        let steps = carto.path_steps(&from, &to, &detour);
+        let distance: u32 = steps.iter().map(|step| step.distance).sum();
+        assert!(detour.distance > 12183);
+        assert!(distance <= detour.distance && distance + 2 >= detour.distance);
+    }
+
+    #[test]
+    fn edge_based() {
+        let from = GeoPoint::from_degrees(42.553210, 1.588908);
+        let to = GeoPoint::from_degrees(42.564440, 1.685042);
+
+        // Without any restrictions, the result is the same as the node-based graph
+        let carto = get_carto().into_edge_based(|_turn| Some(0));
+        assert_eq!(carto.graph.node_count(), carto.carto.graph.edge_count());
+        let from = carto.carto.project(&from);
+        let to = carto.carto.project(&to);
+        let res = carto.shortest_path(&from, &to).unwrap();
+        assert_eq!(res.distance, 12183);
+
+        // The turn penalties change the search, but not the distance, that is the one of the edges
+        let carto = get_carto().into_edge_based(|_turn| Some(100));
+        let penalized = carto.shortest_path(&from, &to).unwrap();
+        let edges = &penalized.edges;
+        let graph = &carto.carto.graph;
+        let inner: u32 = edges[1..edges.len() - 1]
+            .iter()
+            .map(|&edge| graph[edge].distance)
+            .sum();
+        let expected = partial_distance(graph[from.edge].distance, 1. - from.edge_pos)
+            + inner
+            + partial_distance(graph[to.edge].distance, to.edge_pos);
+        assert_eq!(penalized.distance, expected);
+        assert!(penalized.distance >= res.distance);
+
+        // Forbidding U-turns can only make it longer
+        let carto = get_carto().into_edge_based(forbid_u_turns);
+        let restricted = carto.shortest_path(&from, &to).unwrap();
+        assert!(restricted.distance >= res.distance);
+
+        // Same edge
+        let mut to = from;
+        to.edge_pos = 1.;
+        let res = carto.shortest_path(&from, &to).unwrap();
+        assert_eq!(res.points.len(), 2);
+        assert!(res.distance <= carto.carto.graph[from.edge].distance);
+
+        // Back on the same edge, a loop is needed, like in the node-based graph
+        let mut from = from;
+        from.edge_pos = 0.8;
+        to.edge_pos = 0.2;
+        let carto = get_carto().into_edge_based(|_turn| Some(0));
+        let res = carto.shortest_path(&from, &to).unwrap();
+        let node_based = carto.carto.shortest_path(&from, &to);
+        assert_eq!(res.distance, node_based.distance);
+        assert!(res.points.len() > 2);
+        assert_eq!(res.points.first(), Some(&from.projected));
+        assert_eq!(res.points.last(), Some(&to.projected));
+        let carto = get_carto().into_edge_based(|_turn| Some(100));
+        let penalized = carto.shortest_path(&from, &to).unwrap();
+        assert_eq!(penalized.distance, node_based.distance);
     }
 }
@@ -0,0 +1,189 @@
+use super::data_types::EdgeInfo;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Who is allowed on a road, beyond its road level. Most roads are `Normal`, the other classes can
+/// be penalized or forbidden for each query
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AccessClass {
+    Normal,
+    /// `highway=living_street`, where pedestrians have priority and the speed is very low
+    LivingStreet,
+    /// `highway=pedestrian`, where vehicles are usually only allowed for deliveries
+    Pedestrian,
+    /// `motorroad=yes`, closed to slow vehicles like mopeds, bicycles and tractors
+    Motorroad,
+    /// `highway=steps`, only generated by the foot profile, that wheelchairs and strollers can
+    /// not use
+    Steps,
+}
+
+impl AccessClass {
+    /// Classify a road from the values of its tags `highway` and `motorroad`
+    pub fn from_tags(highway: Option<&str>, motorroad: Option<&str>) -> Self {
+        match (highway, motorroad) {
+            (Some("pedestrian"), _) => AccessClass::Pedestrian,
+            (Some("steps"), _) => AccessClass::Steps,
+            (Some("living_street"), _) => AccessClass::LivingStreet,
+            (_, Some("yes")) => AccessClass::Motorroad,
+            _ => AccessClass::Normal,
+        }
+    }
+
+    /// Decode the value stored in the file
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => AccessClass::LivingStreet,
+            2 => AccessClass::Pedestrian,
+            3 => AccessClass::Motorroad,
+            4 => AccessClass::Steps,
+            _ => AccessClass::Normal,
+        }
+    }
+}
+
+impl fmt::Display for AccessClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AccessClass::Normal => "normal",
+            AccessClass::LivingStreet => "living_street",
+            AccessClass::Pedestrian => "pedestrian",
+            AccessClass::Motorroad => "motorroad",
+            AccessClass::Steps => "steps",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for AccessClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(AccessClass::Normal),
+            "living_street" => Ok(AccessClass::LivingStreet),
+            "pedestrian" => Ok(AccessClass::Pedestrian),
+            "motorroad" => Ok(AccessClass::Motorroad),
+            "steps" => Ok(AccessClass::Steps),
+            _ => Err(format!("Unknown access class {}", s)),
+        }
+    }
+}
+
+/// Extra costs of the edges according to their access class, chosen for each query. For example,
+/// delivery vans may use pedestrian zones only as a last resort and mopeds can never use motorroads
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccessPenalties {
+    /// Multiply the cost of the edges of these classes by the given factors
+    pub penalized: Vec<(AccessClass, f64)>,
+    /// Never traverse the edges of these classes. The edges where the path starts and ends are
+    /// always allowed
+    pub forbidden: Vec<AccessClass>,
+    /// Add this cost, in meters, to the edges that cross a barrier vehicles may pass, like a gate
+    pub barrier: u32,
+}
+
+impl AccessPenalties {
+    /// The factor to apply to the distance of an edge. It is never less than 1, so that the
+    /// distance is still a valid lower bound of the cost
+    pub fn factor(&self, info: &EdgeInfo) -> f64 {
+        self.penalized
+            .iter()
+            .filter(|&&(access, _)| info.access == access)
+            .map(|&(_, penalty)| penalty)
+            .product::<f64>()
+            .max(1.)
+    }
+
+    /// The cost to add to the scaled distance of an edge, to cross its barrier
+    pub fn extra_cost(&self, info: &EdgeInfo) -> u32 {
+        if info.barrier {
+            self.barrier
+        } else {
+            0
+        }
+    }
+
+    /// Whether the edge can be traversed at all
+    pub fn allows(&self, info: &EdgeInfo) -> bool {
+        !self.forbidden.contains(&info.access)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartograph::{Restrictions, Smoothness, Surface};
+
+    #[test]
+    fn access_classes() {
+        assert_eq!(
+            AccessClass::from_tags(Some("pedestrian"), None),
+            AccessClass::Pedestrian
+        );
+        assert_eq!(
+            AccessClass::from_tags(Some("steps"), None),
+            AccessClass::Steps
+        );
+        assert_eq!(
+            AccessClass::from_tags(Some("trunk"), Some("yes")),
+            AccessClass::Motorroad
+        );
+        assert_eq!(
+            AccessClass::from_tags(Some("trunk"), Some("no")),
+            AccessClass::Normal
+        );
+        for &access in &[
+            AccessClass::Normal,
+            AccessClass::LivingStreet,
+            AccessClass::Pedestrian,
+            AccessClass::Motorroad,
+            AccessClass::Steps,
+        ] {
+            assert_eq!(AccessClass::from_u8(access as u8), access);
+            assert_eq!(access.to_string().parse::<AccessClass>(), Ok(access));
+        }
+        assert!("private".parse::<AccessClass>().is_err());
+
+        let penalties = AccessPenalties {
+            penalized: vec![(AccessClass::LivingStreet, 10.)],
+            forbidden: vec![AccessClass::Pedestrian],
+            barrier: 30,
+        };
+        let mut info = EdgeInfo {
+            distance: 10,
+            road_level: 5,
+            roundabout: false,
+            capacity: 600,
+            lanes: 0,
+            width: 0.,
+            synthetic: false,
+            conditional: false,
+            reversible: false,
+            barrier: false,
+            cycleway: false,
+            bicycle_route: false,
+            bus_route: false,
+            border: false,
+            track_grade: 0,
+            surface: Surface::Unknown,
+            smoothness: Smoothness::Unknown,
+            access: AccessClass::LivingStreet,
+            restrictions: Restrictions::default(),
+            way_id: 0,
+            name: 0,
+            shape: 0,
+        };
+        assert_eq!(penalties.factor(&info), 10.);
+        assert!(penalties.allows(&info));
+        assert_eq!(penalties.extra_cost(&info), 0);
+        info.barrier = true;
+        assert_eq!(penalties.extra_cost(&info), 30);
+        info.access = AccessClass::Pedestrian;
+        assert_eq!(penalties.factor(&info), 1.);
+        assert!(!penalties.allows(&info));
+    }
+}
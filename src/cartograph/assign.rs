@@ -0,0 +1,186 @@
+use super::data_types::*;
+use super::Cartograph;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// The number of vehicles per hour going from one point to another
+#[derive(Clone, Copy, Debug)]
+pub struct Demand {
+    pub from: ProjectedPoint,
+    pub to: ProjectedPoint,
+    pub volume: f64,
+}
+
+/// The volume-delay function of the Bureau of Public Roads, that estimates how much slower an edge
+/// becomes with traffic: `cost = free_flow_cost * (1 + alpha * (volume / capacity) ^ beta)`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bpr {
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Bpr {
+    /// The congested cost of an edge
+    pub fn cost(&self, free_flow_cost: f64, volume: f64, capacity: f64) -> f64 {
+        free_flow_cost * (1. + self.alpha * (volume / capacity).powf(self.beta))
+    }
+}
+
+impl Default for Bpr {
+    /// The original parameters, calibrated for highways
+    fn default() -> Self {
+        Bpr {
+            alpha: 0.15,
+            beta: 4.,
+        }
+    }
+}
+
+/// Parameters of the traffic assignment
+#[derive(Clone, Debug)]
+pub struct AssignOptions {
+    /// Number of iterations of the method of successive averages
+    pub iterations: usize,
+    /// The volume-delay function
+    pub bpr: Bpr,
+    /// Edges that can't be used, for example to simulate a street being closed
+    pub closed_edges: Vec<EdgeIndex>,
+}
+
+impl Default for AssignOptions {
+    fn default() -> Self {
+        AssignOptions {
+            iterations: 20,
+            bpr: Bpr::default(),
+            closed_edges: Vec::new(),
+        }
+    }
+}
+
+/// The result of the traffic assignment, with one value per edge, indexed by the edge index
+#[derive(Clone, Debug)]
+pub struct Assignment {
+    /// The number of vehicles per hour on each edge
+    pub volumes: Vec<f64>,
+    /// The congested cost of each edge, in equivalent meters
+    pub costs: Vec<f64>,
+    /// The volume of the demands that could not be assigned because the destination is not reachable
+    pub unassigned: f64,
+}
+
+/// Assign the demands to the road network with the method of successive averages (MSA). Each iteration
+/// routes all demands on the shortest paths given the current congested costs (all-or-nothing) and
+/// moves the volumes a step of `1 / (k + 1)` toward this solution, converging to the user equilibrium
+pub fn assign(carto: &Cartograph, demands: &[Demand], options: &AssignOptions) -> Assignment {
+    let num_edges = carto.graph.edge_count();
+    let closed: HashSet<EdgeIndex> = options.closed_edges.iter().cloned().collect();
+
+    // Group the demands by their starting node, so that a single search serves all of them
+    let mut by_origin: HashMap<NodeIndex, Vec<&Demand>> = HashMap::new();
+    for demand in demands {
+        let start = carto.graph.edge_endpoints(demand.from.edge).unwrap().1;
+        by_origin.entry(start).or_insert_with(Vec::new).push(demand);
+    }
+
+    let mut volumes = vec![0.; num_edges];
+    let mut unassigned = 0.;
+    for k in 0..options.iterations.max(1) {
+        // The search uses integer costs, in millimeters
+        let costs: Vec<u64> = congested_costs(carto, &volumes, &options.bpr)
+            .into_iter()
+            .map(|cost| (cost * 1000.).round() as u64)
+            .collect();
+
+        // All-or-nothing assignment
+        let mut loads = vec![0.; num_edges];
+        unassigned = 0.;
+        for (&start, demands) in &by_origin {
+            let predecessors = shortest_path_tree(carto, start, &costs, &closed);
+            for demand in demands {
+                let (from, to) = (&demand.from, &demand.to);
+                loads[from.edge.index()] += demand.volume;
+                if from.edge == to.edge && from.edge_pos <= to.edge_pos {
+                    continue;
+                }
+
+                let end = carto.graph.edge_endpoints(to.edge).unwrap().0;
+                if end != start && predecessors[end.index()].is_none() {
+                    unassigned += demand.volume;
+                    loads[from.edge.index()] -= demand.volume;
+                    continue;
+                }
+
+                let mut node = end;
+                while let Some(edge) = predecessors[node.index()] {
+                    loads[edge.index()] += demand.volume;
+                    node = carto.graph.edge_endpoints(edge).unwrap().0;
+                }
+                loads[to.edge.index()] += demand.volume;
+            }
+        }
+
+        // Average with the previous solution
+        let step = 1. / (k + 1) as f64;
+        for (volume, load) in volumes.iter_mut().zip(loads) {
+            *volume += step * (load - *volume);
+        }
+    }
+
+    let costs = congested_costs(carto, &volumes, &options.bpr);
+    Assignment {
+        volumes,
+        costs,
+        unassigned,
+    }
+}
+
+/// Calculate the congested cost of each edge in equivalent meters, given their volumes
+pub fn congested_costs(carto: &Cartograph, volumes: &[f64], bpr: &Bpr) -> Vec<f64> {
+    carto
+        .graph
+        .edge_references()
+        .map(|edge| {
+            let info = edge.weight();
+            let volume = volumes[edge.id().index()];
+            bpr.cost(info.distance as f64, volume, info.capacity.max(1) as f64)
+        })
+        .collect()
+}
+
+/// Run Dijkstra's algorithm from `start` to all nodes and return the edge used to reach each one
+fn shortest_path_tree(
+    carto: &Cartograph,
+    start: NodeIndex,
+    costs: &[u64],
+    closed: &HashSet<EdgeIndex>,
+) -> Vec<Option<EdgeIndex>> {
+    let num_nodes = carto.graph.node_count();
+    let mut predecessors = vec![None; num_nodes];
+    let mut scores = vec![u64::MAX; num_nodes];
+    let mut visit_next = BinaryHeap::new();
+
+    scores[start.index()] = 0;
+    visit_next.push(Reverse((0, start)));
+    while let Some(Reverse((score, node))) = visit_next.pop() {
+        if score > scores[node.index()] {
+            continue;
+        }
+
+        for edge in carto.graph.edges(node) {
+            if closed.contains(&edge.id()) || !carto.is_routable(edge.weight()) {
+                continue;
+            }
+            let next = edge.target();
+            let next_score = score + costs[edge.id().index()];
+            if next != start && next_score < scores[next.index()] {
+                scores[next.index()] = next_score;
+                predecessors[next.index()] = Some(edge.id());
+                visit_next.push(Reverse((next_score, next)));
+            }
+        }
+    }
+
+    predecessors
+}
@@ -0,0 +1,190 @@
+use crate::utils::GeoPoint;
+use rstar::primitives::Line;
+use rstar::{RTree, AABB};
+use serde_json::Value;
+use std::io;
+
+/// The borders between regions, like countries, to flag the edges that cross them. They are the
+/// segments of lines or of the rings of polygons, spatially indexed in Web Mercator, so that only
+/// the segments close to an edge are tested
+pub struct Borders {
+    segments: RTree<Line<[f64; 2]>>,
+}
+
+impl Borders {
+    /// Create the borders from lines of points. The rings of polygons must be closed, repeating
+    /// their first point at the end, like in GeoJSON
+    pub fn new(lines: &[Vec<GeoPoint>]) -> Self {
+        let segments = lines
+            .iter()
+            .flat_map(|line| {
+                let points: Vec<_> = line.iter().map(GeoPoint::web_mercator_project).collect();
+                (1..points.len())
+                    .map(|i| Line::new(points[i - 1], points[i]))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        Borders {
+            segments: RTree::bulk_load(segments),
+        }
+    }
+
+    /// Read the borders from GeoJSON: a feature collection, a feature or a geometry, with polygons,
+    /// multipolygons, lines and multilines, like the administrative boundaries exported from
+    /// OpenStreetMap. The coordinates are `[longitude, latitude]`
+    pub fn from_geojson(json: &str) -> io::Result<Self> {
+        let value: Value = serde_json::from_str(json)?;
+        let mut lines = Vec::new();
+        collect_lines(&value, &mut lines)?;
+        Ok(Borders::new(&lines))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.size() == 0
+    }
+
+    /// Whether going along the points crosses a border. Reaching a border counts, but leaving it
+    /// does not, so that a road with a node on the border only crosses it once
+    pub fn crosses(&self, points: &[GeoPoint]) -> bool {
+        let points: Vec<_> = points.iter().map(GeoPoint::web_mercator_project).collect();
+        points.windows(2).any(|pair| {
+            let (a, b) = (pair[0], pair[1]);
+            let envelope = AABB::from_corners(a, b);
+            self.segments
+                .locate_in_envelope_intersecting(&envelope)
+                .any(|segment| crosses(a, b, segment.from, segment.to))
+        })
+    }
+}
+
+/// Add the lines of a GeoJSON object to `lines`
+fn collect_lines(value: &Value, lines: &mut Vec<Vec<GeoPoint>>) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let coordinates = || {
+        value
+            .get("coordinates")
+            .ok_or_else(|| invalid("A geometry has no coordinates".to_owned()))
+    };
+    match value.get("type").and_then(Value::as_str) {
+        Some("FeatureCollection") => {
+            let features = value
+                .get("features")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid("The feature collection has no features".to_owned()))?;
+            for feature in features {
+                collect_lines(feature, lines)?;
+            }
+        }
+        Some("Feature") => {
+            if let Some(geometry) = value.get("geometry").filter(|geometry| !geometry.is_null()) {
+                collect_lines(geometry, lines)?;
+            }
+        }
+        Some("GeometryCollection") => {
+            for geometry in value
+                .get("geometries")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                collect_lines(geometry, lines)?;
+            }
+        }
+        Some("LineString") => lines.push(parse_line(coordinates()?)?),
+        Some("MultiLineString") | Some("Polygon") => {
+            for line in as_array(coordinates()?)? {
+                lines.push(parse_line(line)?);
+            }
+        }
+        Some("MultiPolygon") => {
+            for polygon in as_array(coordinates()?)? {
+                for ring in as_array(polygon)? {
+                    lines.push(parse_line(ring)?);
+                }
+            }
+        }
+        // The points have no border
+        Some("Point") | Some("MultiPoint") => {}
+        other => return Err(invalid(format!("Unknown GeoJSON type {:?}", other))),
+    }
+    Ok(())
+}
+
+fn as_array(value: &Value) -> io::Result<&Vec<Value>> {
+    value.as_array().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Expected an array of coordinates, got {}", value),
+        )
+    })
+}
+
+/// Parse `[[lon, lat], ...]`
+fn parse_line(value: &Value) -> io::Result<Vec<GeoPoint>> {
+    as_array(value)?
+        .iter()
+        .map(|position| match as_array(position)?.as_slice() {
+            [lon, lat, ..] => match (lon.as_f64(), lat.as_f64()) {
+                (Some(lon), Some(lat)) => Ok(GeoPoint::from_degrees(lat, lon)),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid position {}", position),
+                )),
+            },
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid position {}", position),
+            )),
+        })
+        .collect()
+}
+
+/// Whether going from `a` to `b` crosses the segment from `p` to `q`, or ends on it
+fn crosses(a: [f64; 2], b: [f64; 2], p: [f64; 2], q: [f64; 2]) -> bool {
+    let orientation = |o: [f64; 2], u: [f64; 2], v: [f64; 2]| {
+        let cross = (u[0] - o[0]) * (v[1] - o[1]) - (u[1] - o[1]) * (v[0] - o[0]);
+        cross.partial_cmp(&0.).unwrap_or(std::cmp::Ordering::Equal) as i8
+    };
+    let (d1, d2) = (orientation(p, q, a), orientation(p, q, b));
+    let (d3, d4) = (orientation(a, b, p), orientation(a, b, q));
+    let ends_on_segment = d2 == 0
+        && b[0] >= p[0].min(q[0])
+        && b[0] <= p[0].max(q[0])
+        && b[1] >= p[1].min(q[1])
+        && b[1] <= p[1].max(q[1]);
+    (d1 * d2 < 0 && d3 * d4 <= 0) || ends_on_segment
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crossings() {
+        // A square around (42.5, 1.5) and a line along the meridian 2
+        let json = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"name": "square"}, "geometry": {"type": "Polygon",
+                "coordinates": [[[1.4, 42.4], [1.6, 42.4], [1.6, 42.6], [1.4, 42.6], [1.4, 42.4]]]}},
+            {"type": "Feature", "properties": {}, "geometry": {"type": "LineString",
+                "coordinates": [[2.0, 42.0], [2.0, 43.0]]}}
+        ]}"#;
+        let borders = Borders::from_geojson(json).unwrap();
+        assert!(!borders.is_empty());
+        let point = |lat, lon| GeoPoint::from_degrees(lat, lon);
+
+        assert!(borders.crosses(&[point(42.5, 1.5), point(42.5, 1.7)]));
+        assert!(borders.crosses(&[point(42.5, 1.7), point(42.5, 1.5)]));
+        assert!(!borders.crosses(&[point(42.5, 1.45), point(42.5, 1.55)]));
+        assert!(!borders.crosses(&[point(42.5, 1.7), point(42.5, 1.9)]));
+        assert!(borders.crosses(&[point(42.5, 1.9), point(42.6, 1.95), point(42.5, 2.1)]));
+
+        // A road with a node on the border reaches it, then leaves it
+        assert!(borders.crosses(&[point(42.5, 1.9), point(42.5, 2.0)]));
+        assert!(!borders.crosses(&[point(42.5, 2.0), point(42.5, 2.1)]));
+
+        assert!(Borders::from_geojson(r#"{"type": "Polygon"}"#).is_err());
+        assert!(Borders::from_geojson(r#"{"type": "LineString", "coordinates": [[1]]}"#).is_err());
+        assert!(Borders::from_geojson(r#"{"type": "Circle"}"#).is_err());
+        assert!(Borders::from_geojson("[]").is_err());
+    }
+}
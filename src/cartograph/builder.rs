@@ -0,0 +1,116 @@
+use super::data_types::EdgeInfo;
+use super::{Cartograph, EdgeShapes, IndexConfig, Metadata, RoadLevelStyle, RoadNames};
+use crate::utils::GeoPoint;
+use once_cell::sync::OnceCell;
+use petgraph::graph::{EdgeIndex, Graph, NodeIndex};
+
+/// Build a cartography from the nodes and edges of any source, like proprietary road data or a
+/// synthetic grid, without writing a file
+#[derive(Default)]
+pub struct CartographBuilder {
+    graph: Graph<GeoPoint, EdgeInfo>,
+    road_levels: Option<Vec<RoadLevelStyle>>,
+    road_names: RoadNames,
+    edge_shapes: EdgeShapes,
+    index_config: IndexConfig,
+}
+
+impl CartographBuilder {
+    pub fn add_node(&mut self, point: GeoPoint) -> NodeIndex {
+        self.graph.add_node(point)
+    }
+
+    /// Add a one-way edge from `a` to `b`. Two-way roads need an edge in each direction
+    pub fn add_edge(&mut self, a: NodeIndex, b: NodeIndex, info: EdgeInfo) -> EdgeIndex {
+        self.graph.add_edge(a, b, info)
+    }
+
+    /// Classify the road levels with these styles, instead of `RoadLevelStyle::defaults()`
+    pub fn road_levels(&mut self, road_levels: Vec<RoadLevelStyle>) -> &mut Self {
+        self.road_levels = Some(road_levels);
+        self
+    }
+
+    /// Name the roads, referenced by the `name` of the edges
+    pub fn road_names(&mut self, road_names: RoadNames) -> &mut Self {
+        self.road_names = road_names;
+        self
+    }
+
+    /// Give the edges intermediate points, referenced by the `shape` of the edges
+    pub fn edge_shapes(&mut self, edge_shapes: EdgeShapes) -> &mut Self {
+        self.edge_shapes = edge_shapes;
+        self
+    }
+
+    /// Build the spatial index of the edges with this configuration, instead of the default one
+    pub fn index_config(&mut self, config: IndexConfig) -> &mut Self {
+        self.index_config = config;
+        self
+    }
+
+    /// Index the edges and label the strongly connected components, like when a file is opened
+    pub fn build(self) -> Cartograph {
+        let rtree = Cartograph::build_rtree(
+            Cartograph::project_edges(&self.graph, &self.edge_shapes, None),
+            &self.index_config,
+        );
+        let components = Cartograph::label_components(&self.graph);
+        Cartograph {
+            graph: self.graph,
+            rtree,
+            allow_synthetic: true,
+            components,
+            road_levels: self.road_levels.unwrap_or_else(RoadLevelStyle::defaults),
+            node_rtree: OnceCell::new(),
+            level_rtrees: OnceCell::new(),
+            border_edges: OnceCell::new(),
+            osm_node_ids: Vec::new(),
+            metadata: Metadata::default(),
+            road_names: self.road_names,
+            edge_shapes: self.edge_shapes,
+            projected_nodes: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grid() {
+        // A 3 x 3 grid of two-way residential roads, about 111 m apart
+        let mut builder = CartographBuilder::default();
+        let nodes: Vec<Vec<NodeIndex>> = (0..3)
+            .map(|i| {
+                (0..3)
+                    .map(|j| {
+                        let lon = 1.5 + j as f64 * 0.001 / (42.5f64).to_radians().cos();
+                        builder.add_node(GeoPoint::from_degrees(42.5 + i as f64 * 0.001, lon))
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut add_road = |a: NodeIndex, b: NodeIndex| {
+            builder.add_edge(a, b, EdgeInfo::new(111, 5));
+            builder.add_edge(b, a, EdgeInfo::new(111, 5));
+        };
+        for (i, row) in nodes.iter().enumerate() {
+            for (j, pair) in row.windows(2).enumerate() {
+                add_road(pair[0], pair[1]);
+                add_road(nodes[j][i], nodes[j + 1][i]);
+            }
+        }
+        let carto = builder.build();
+        assert_eq!(carto.graph.node_count(), 9);
+        assert_eq!(carto.graph.edge_count(), 24);
+        assert_eq!(carto.component_sizes(), vec![9]);
+        assert_eq!(carto.road_levels, RoadLevelStyle::defaults());
+
+        // Between opposite corners
+        let from = carto.project(&carto.graph[nodes[0][0]]);
+        let to = carto.project(&carto.graph[nodes[2][2]]);
+        assert_eq!(carto.shortest_path(&from, &to).distance, 111 * 4);
+    }
+}
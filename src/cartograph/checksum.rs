@@ -0,0 +1,131 @@
+use super::error::CartographError;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use crc32fast::Hasher;
+use std::io::{self, Read, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{Seek, SeekFrom};
+
+/// The name of the file section with the checksum, that is always the last one
+pub const CHECKSUM_SECTION: &str = "checksum";
+
+/// The total size of the checksum section: the name, the payload size, the CRC-32 of all the
+/// previous bytes and the section size, so that it can be found from the end of the file like the
+/// statistics
+pub const SECTION_SIZE: u64 = 1 + CHECKSUM_SECTION.len() as u64 + 8 + 4 + 8;
+
+/// Wrap a writer to compute the checksum of what is written to it
+pub struct ChecksumWriter<W> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ChecksumWriter {
+            inner,
+            hasher: Hasher::new(),
+        }
+    }
+
+    /// Write the checksum of everything written so far as the final section
+    pub fn finish(mut self) -> io::Result<W> {
+        let checksum = self.hasher.clone().finalize();
+        write_section(&mut self.inner, checksum)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_section<W: Write>(writer: &mut W, checksum: u32) -> io::Result<()> {
+    writer.write_u8(CHECKSUM_SECTION.len() as u8)?;
+    writer.write_all(CHECKSUM_SECTION.as_bytes())?;
+    writer.write_u64::<LittleEndian>(4 + 8)?;
+    writer.write_u32::<LittleEndian>(checksum)?;
+    writer.write_u64::<LittleEndian>(SECTION_SIZE)
+}
+
+/// Read the contents of the section, after its name, and check them against the checksum of the
+/// previous bytes
+pub fn verify_section<R: Read>(reader: &mut R, expected: u32) -> Result<(), CartographError> {
+    let size = reader.read_u64::<LittleEndian>()?;
+    if size != 4 + 8 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid checksum section").into());
+    }
+    let checksum = reader.read_u32::<LittleEndian>()?;
+    reader.read_u64::<LittleEndian>()?;
+    check(checksum, expected)
+}
+
+/// Check the checksum of a whole file without decoding it, returning the checksum
+#[cfg(not(target_arch = "wasm32"))]
+pub fn verify_file<R: Read + Seek>(reader: &mut R) -> Result<u32, CartographError> {
+    let file_size = reader.seek(SeekFrom::End(0))?;
+    if file_size < SECTION_SIZE {
+        return Err(CartographError::MissingChecksum);
+    }
+    reader.seek(SeekFrom::End(-(SECTION_SIZE as i64)))?;
+    let mut name = vec![0; reader.read_u8()? as usize];
+    if name.len() != CHECKSUM_SECTION.len() {
+        return Err(CartographError::MissingChecksum);
+    }
+    reader.read_exact(&mut name)?;
+    if name != CHECKSUM_SECTION.as_bytes() {
+        return Err(CartographError::MissingChecksum);
+    }
+    reader.read_u64::<LittleEndian>()?;
+    let checksum = reader.read_u32::<LittleEndian>()?;
+
+    reader.seek(SeekFrom::Start(0))?;
+    let mut hasher = ChecksumWriter::new(io::sink());
+    io::copy(&mut reader.take(file_size - SECTION_SIZE), &mut hasher)?;
+    check(checksum, hasher.hasher.finalize())?;
+    Ok(checksum)
+}
+
+fn check(checksum: u32, expected: u32) -> Result<(), CartographError> {
+    if checksum == expected {
+        Ok(())
+    } else {
+        Err(CartographError::ChecksumMismatch {
+            expected,
+            found: checksum,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn verify_file() {
+        let mut writer = ChecksumWriter::new(Vec::new());
+        writer.write_all(b"PTOLEMY-v2 and some columns").unwrap();
+        let mut bytes = writer.finish().unwrap();
+        assert_eq!(bytes.len() as u64, 27 + SECTION_SIZE);
+        let checksum = super::verify_file(&mut Cursor::new(&bytes)).unwrap();
+        let mut hasher = Hasher::new();
+        hasher.update(b"PTOLEMY-v2 and some columns");
+        assert_eq!(checksum, hasher.finalize());
+
+        bytes[3] ^= 1;
+        let error = super::verify_file(&mut Cursor::new(&bytes)).unwrap_err();
+        assert!(
+            matches!(error, CartographError::ChecksumMismatch { found, .. } if found == checksum)
+        );
+        let error = super::verify_file(&mut Cursor::new(&bytes[..20])).unwrap_err();
+        assert!(matches!(error, CartographError::MissingChecksum));
+    }
+}
@@ -0,0 +1,217 @@
+use super::data_types::EdgeInfo;
+use super::Cartograph;
+use petgraph::graph::EdgeIndex;
+use petgraph::visit::EdgeRef;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+/// When a closure applies, in seconds since the Unix epoch, from `start` included to `end`
+/// excluded. A missing end is unbounded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClosureWindow {
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+}
+
+impl ClosureWindow {
+    /// Whether the closure applies at the given time
+    pub fn contains(&self, time: u64) -> bool {
+        self.start.is_none_or(|start| start <= time) && self.end.is_none_or(|end| time < end)
+    }
+}
+
+/// Roads that can not be traversed for a while, like a mountain pass closed for the winter or a
+/// flooded street, without generating the cartography again.
+///
+/// They are read from CSV lines with an OSM way (`way:4199`) or an edge index (`edge:120`),
+/// optionally followed by the start and end of the closure, in seconds since the Unix epoch, like
+/// `way:4199,1700000000,1700600000`. Either can be empty, and a road closed in several windows has
+/// one line for each. Empty lines and the ones starting with `#` are skipped
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Closures {
+    /// The closed OSM ways, by their id
+    pub ways: HashMap<i64, Vec<ClosureWindow>>,
+    /// The closed edges, by their index
+    pub edges: HashMap<u32, Vec<ClosureWindow>>,
+}
+
+impl Closures {
+    pub fn is_empty(&self) -> bool {
+        self.ways.is_empty() && self.edges.is_empty()
+    }
+
+    /// Whether an edge is closed at the given time
+    pub fn is_closed(&self, edge: EdgeIndex, info: &EdgeInfo, time: u64) -> bool {
+        let closed = |windows: Option<&Vec<ClosureWindow>>| {
+            windows.is_some_and(|windows| windows.iter().any(|window| window.contains(time)))
+        };
+        closed(self.edges.get(&(edge.index() as u32)))
+            || (info.way_id != 0 && closed(self.ways.get(&info.way_id)))
+    }
+
+    /// Replace the closed ways by the edges generated from them, so that `closed_edges()` does not
+    /// have to visit the whole graph. The ways are only known when the cartography was generated
+    /// with `--osm-ids`
+    pub fn resolve(&self, carto: &Cartograph) -> Closures {
+        let mut edges = self.edges.clone();
+        if !self.ways.is_empty() {
+            for edge in carto.graph.edge_references() {
+                let way_id = edge.weight().way_id;
+                if let Some(windows) = self.ways.get(&way_id).filter(|_| way_id != 0) {
+                    edges
+                        .entry(edge.id().index() as u32)
+                        .or_default()
+                        .extend(windows);
+                }
+            }
+        }
+        Closures {
+            ways: HashMap::new(),
+            edges,
+        }
+    }
+
+    /// The edges that are closed at the given time. The edges that are not in the graph are ignored
+    pub fn closed_edges(&self, carto: &Cartograph, time: u64) -> HashSet<EdgeIndex> {
+        let graph = &carto.graph;
+        let mut closed: HashSet<EdgeIndex> = self
+            .edges
+            .keys()
+            .map(|&edge| EdgeIndex::new(edge as usize))
+            .filter(|&edge| edge.index() < graph.edge_count())
+            .filter(|&edge| self.is_closed(edge, &graph[edge], time))
+            .collect();
+        if !self.ways.is_empty() {
+            closed.extend(
+                graph
+                    .edge_references()
+                    .filter(|edge| self.is_closed(edge.id(), edge.weight(), time))
+                    .map(|edge| edge.id()),
+            );
+        }
+        closed
+    }
+}
+
+impl FromStr for Closures {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut closures = Closures::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("Invalid closure at line {}: {}", i + 1, message);
+            let values: Vec<&str> = line.split(',').map(str::trim).collect();
+            if values.len() != 1 && values.len() != 3 {
+                return Err(error(&format!(
+                    "expected 1 or 3 values, got {}",
+                    values.len()
+                )));
+            }
+            let parse_time = |value: &str| match value {
+                "" => Ok(None),
+                value => value
+                    .parse::<u64>()
+                    .map(Some)
+                    .map_err(|_| error(&format!("the time {} is not a Unix timestamp", value))),
+            };
+            let window = match values[..] {
+                [_, start, end] => ClosureWindow {
+                    start: parse_time(start)?,
+                    end: parse_time(end)?,
+                },
+                _ => ClosureWindow::default(),
+            };
+            if let (Some(start), Some(end)) = (window.start, window.end) {
+                if end <= start {
+                    return Err(error("the closure ends before it starts"));
+                }
+            }
+            if let Some(way) = values[0]
+                .strip_prefix("way:")
+                .and_then(|id| id.parse().ok())
+            {
+                closures.ways.entry(way).or_default().push(window);
+            } else if let Some(edge) = values[0]
+                .strip_prefix("edge:")
+                .and_then(|id| id.parse().ok())
+            {
+                closures.edges.entry(edge).or_default().push(window);
+            } else {
+                return Err(error("expected way:<OSM id> or edge:<index>"));
+            }
+        }
+        Ok(closures)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let closures: Closures =
+            "# Winter\nway:4199,1700000000,1700600000\n\nedge:12\nedge:12,,100"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            closures.ways[&4199],
+            vec![ClosureWindow {
+                start: Some(1700000000),
+                end: Some(1700600000)
+            }]
+        );
+        assert_eq!(
+            closures.edges[&12],
+            vec![
+                ClosureWindow::default(),
+                ClosureWindow {
+                    start: None,
+                    end: Some(100)
+                }
+            ]
+        );
+
+        assert!("node:12".parse::<Closures>().is_err());
+        assert!("edge:12,100".parse::<Closures>().is_err());
+        assert!("edge:12,tomorrow,".parse::<Closures>().is_err());
+        assert!("edge:12,100,100".parse::<Closures>().is_err());
+    }
+
+    #[test]
+    fn closed_edges() {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let closures: Closures = "edge:7,100,200\nedge:9\nedge:99999999".parse().unwrap();
+        let at = |time| {
+            let mut edges: Vec<_> = closures
+                .closed_edges(&carto, time)
+                .into_iter()
+                .map(|edge| edge.index())
+                .collect();
+            edges.sort();
+            edges
+        };
+        assert_eq!(at(50), vec![9]);
+        assert_eq!(at(100), vec![7, 9]);
+        assert_eq!(at(200), vec![9]);
+
+        let window = ClosureWindow {
+            start: Some(100),
+            end: None,
+        };
+        assert!(!window.contains(99));
+        assert!(window.contains(u64::MAX));
+
+        // The file has no way ids, so closing a way closes nothing
+        let mut info = carto.graph[EdgeIndex::new(3)];
+        let closures: Closures = "way:0".parse().unwrap();
+        assert!(closures.resolve(&carto).is_empty());
+        info.way_id = 4199;
+        let closures: Closures = "way:4199".parse().unwrap();
+        assert!(closures.is_closed(EdgeIndex::new(3), &info, 0));
+    }
+}
@@ -0,0 +1,171 @@
+use petgraph::graph::{Graph, Neighbors, NodeIndex};
+
+/// The index of the nodes that were not visited yet
+const UNVISITED: u32 = u32::MAX;
+
+/// An iterator over the strongly connected components of a graph, computed one at a time with
+/// Tarjan's algorithm, without recursion. Only the nodes of the component being returned are
+/// allocated, besides two numbers and a flag per node, so that a graph can be checked without
+/// holding all its components. They come in reverse topological order: no edge leaves a component
+/// towards the ones returned after it
+pub struct StronglyConnectedComponents<'a, N, E> {
+    graph: &'a Graph<N, E>,
+    /// The smaller components are skipped, without allocating them
+    min_size: usize,
+    /// The order in which each node was visited
+    index: Vec<u32>,
+    /// The lowest index reachable from each node through the nodes of its subtree
+    lowlink: Vec<u32>,
+    on_stack: Vec<bool>,
+    /// The visited nodes whose component is not known yet
+    stack: Vec<NodeIndex>,
+    /// The nodes being explored, with their remaining neighbors
+    dfs: Vec<(NodeIndex, Neighbors<'a, E>)>,
+    num_visited: u32,
+    /// The next node to start a search from, if it was not visited yet
+    next_root: usize,
+}
+
+impl<'a, N, E> StronglyConnectedComponents<'a, N, E> {
+    pub fn new(graph: &'a Graph<N, E>) -> Self {
+        StronglyConnectedComponents {
+            graph,
+            min_size: 1,
+            index: vec![UNVISITED; graph.node_count()],
+            lowlink: vec![UNVISITED; graph.node_count()],
+            on_stack: vec![false; graph.node_count()],
+            stack: Vec::new(),
+            dfs: Vec::new(),
+            num_visited: 0,
+            next_root: 0,
+        }
+    }
+
+    /// Only return the components with at least `min_size` nodes, like the ones big enough to
+    /// matter when validating a graph
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    fn visit(&mut self, node: NodeIndex) {
+        self.index[node.index()] = self.num_visited;
+        self.lowlink[node.index()] = self.num_visited;
+        self.num_visited += 1;
+        self.on_stack[node.index()] = true;
+        self.stack.push(node);
+        self.dfs.push((node, self.graph.neighbors(node)));
+    }
+
+    /// Remove the component of `root` from the stack, and return it if it is big enough
+    fn pop_component(&mut self, root: NodeIndex) -> Option<Vec<NodeIndex>> {
+        let start = self.stack.iter().rposition(|&node| node == root).unwrap();
+        for &node in &self.stack[start..] {
+            self.on_stack[node.index()] = false;
+        }
+        if self.stack.len() - start >= self.min_size {
+            Some(self.stack.split_off(start))
+        } else {
+            self.stack.truncate(start);
+            None
+        }
+    }
+}
+
+impl<'a, N, E> Iterator for StronglyConnectedComponents<'a, N, E> {
+    type Item = Vec<NodeIndex>;
+
+    fn next(&mut self) -> Option<Vec<NodeIndex>> {
+        loop {
+            if self.dfs.is_empty() {
+                while self.next_root < self.index.len() && self.index[self.next_root] != UNVISITED {
+                    self.next_root += 1;
+                }
+                if self.next_root == self.index.len() {
+                    return None;
+                }
+                self.visit(NodeIndex::new(self.next_root));
+            }
+
+            while let Some((node, neighbors)) = self.dfs.last_mut() {
+                let node = *node;
+                match neighbors.next() {
+                    Some(next) if self.index[next.index()] == UNVISITED => self.visit(next),
+                    Some(next) => {
+                        if self.on_stack[next.index()] {
+                            self.lowlink[node.index()] =
+                                self.lowlink[node.index()].min(self.index[next.index()]);
+                        }
+                    }
+                    None => {
+                        self.dfs.pop();
+                        if let Some(&(parent, _)) = self.dfs.last() {
+                            self.lowlink[parent.index()] =
+                                self.lowlink[parent.index()].min(self.lowlink[node.index()]);
+                        }
+                        if self.lowlink[node.index()] == self.index[node.index()] {
+                            if let Some(component) = self.pop_component(node) {
+                                return Some(component);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use petgraph::algo::kosaraju_scc;
+
+    fn sorted(mut components: Vec<Vec<NodeIndex>>) -> Vec<Vec<NodeIndex>> {
+        components.iter_mut().for_each(|nodes| nodes.sort());
+        components.sort();
+        components
+    }
+
+    #[test]
+    fn components() {
+        // Two cycles joined by a one-way edge, a node that only leaves and an isolated one
+        let mut graph = Graph::<(), ()>::new();
+        let nodes: Vec<_> = (0..8).map(|_| graph.add_node(())).collect();
+        for &(a, b) in &[
+            (0, 1),
+            (1, 2),
+            (2, 0),
+            (2, 3),
+            (3, 4),
+            (4, 3),
+            (5, 4),
+            (1, 1),
+        ] {
+            graph.add_edge(nodes[a], nodes[b], ());
+        }
+        graph.add_edge(nodes[6], nodes[0], ());
+
+        let components: Vec<_> = StronglyConnectedComponents::new(&graph).collect();
+        assert_eq!(sorted(components.clone()), sorted(kosaraju_scc(&graph)));
+        // The component of 3 and 4 is reached from the other one, so it comes first
+        let position = |node: usize| {
+            components
+                .iter()
+                .position(|component| component.contains(&nodes[node]))
+                .unwrap()
+        };
+        assert!(position(3) < position(0));
+
+        let large: Vec<_> = StronglyConnectedComponents::new(&graph)
+            .min_size(2)
+            .collect();
+        assert_eq!(
+            sorted(large),
+            vec![vec![nodes[0], nodes[1], nodes[2]], vec![nodes[3], nodes[4]]]
+        );
+        assert_eq!(
+            StronglyConnectedComponents::new(&Graph::<(), ()>::new()).next(),
+            None
+        );
+    }
+}
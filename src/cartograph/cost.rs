@@ -0,0 +1,302 @@
+use super::access::AccessPenalties;
+use super::data_types::{estimate_speed, EdgeInfo};
+use super::experiment::Experiment;
+use super::surface::SurfacePenalties;
+use crate::utils::GeoPoint;
+use petgraph::graph::EdgeIndex;
+use std::collections::{HashMap, HashSet};
+
+/// How a search weighs the edges, to find the cheapest path instead of the shortest one. For
+/// example, a model can avoid unpaved roads or prefer the highways without changing the search.
+///
+/// The heuristic must never be more than the cost of the cheapest path between both points, or the
+/// search may miss the best path. The default one, the straight-line distance, is right for any
+/// model whose edges never cost less than their distance
+pub trait CostModel {
+    /// The cost of traversing the whole edge between its two points. The edges that cost `u32::MAX`
+    /// are never traversed, except the ones where the path starts and ends
+    fn edge_cost(&self, info: &EdgeInfo, from: &GeoPoint, to: &GeoPoint) -> u32;
+
+    /// The cost of traversing the whole edge with the given index, which the searches use. Only the
+    /// models that depend on the edge itself, like a random noise or its closure, override it
+    fn indexed_cost(
+        &self,
+        _edge: EdgeIndex,
+        info: &EdgeInfo,
+        from: &GeoPoint,
+        to: &GeoPoint,
+    ) -> u32 {
+        self.edge_cost(info, from, to)
+    }
+
+    /// A lower bound of the cost of going from a point to another
+    fn heuristic(&self, from: &GeoPoint, to: &GeoPoint) -> u32 {
+        from.haversine_distance(to) as u32
+    }
+}
+
+/// The cost of an edge is its distance, which finds the shortest path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DistanceCost;
+
+impl CostModel for DistanceCost {
+    fn edge_cost(&self, info: &EdgeInfo, _from: &GeoPoint, _to: &GeoPoint) -> u32 {
+        info.distance
+    }
+}
+
+/// Any function of the edge and its points can be used as a cost model with the default heuristic
+impl<F: Fn(&EdgeInfo, &GeoPoint, &GeoPoint) -> u32> CostModel for F {
+    fn edge_cost(&self, info: &EdgeInfo, from: &GeoPoint, to: &GeoPoint) -> u32 {
+        self(info, from, to)
+    }
+}
+
+impl CostModel for SurfacePenalties {
+    fn edge_cost(&self, info: &EdgeInfo, _from: &GeoPoint, _to: &GeoPoint) -> u32 {
+        scaled_cost(info.distance, self.factor(info))
+    }
+}
+
+impl CostModel for AccessPenalties {
+    fn edge_cost(&self, info: &EdgeInfo, _from: &GeoPoint, _to: &GeoPoint) -> u32 {
+        if self.allows(info) {
+            scaled_cost(info.distance, self.factor(info))
+                .saturating_add(self.extra_cost(info))
+                .min(u32::MAX - 1)
+        } else {
+            u32::MAX
+        }
+    }
+}
+
+/// The penalties of both the access classes and the surfaces, like in
+/// `Cartograph::shortest_path_with_access()`. Their factors multiply the distance of each edge, and
+/// the cost of its barrier is added once
+#[derive(Clone, Copy, Debug)]
+pub struct AccessAndSurfaces<'a> {
+    pub access: &'a AccessPenalties,
+    pub surfaces: &'a SurfacePenalties,
+}
+
+impl CostModel for AccessAndSurfaces<'_> {
+    fn edge_cost(&self, info: &EdgeInfo, _from: &GeoPoint, _to: &GeoPoint) -> u32 {
+        if self.access.allows(info) {
+            let factor = self.access.factor(info) * self.surfaces.factor(info);
+            scaled_cost(info.distance, factor)
+                .saturating_add(self.access.extra_cost(info))
+                .min(u32::MAX - 1)
+        } else {
+            u32::MAX
+        }
+    }
+}
+
+/// Never traverse the closed edges, like the ones of `Closures::closed_edges()`, and weigh the other
+/// ones with `cost`. As for any model, the edges where the path starts and ends are still traversed
+#[derive(Clone, Copy, Debug)]
+pub struct ClosedEdges<'a, C: ?Sized> {
+    pub edges: &'a HashSet<EdgeIndex>,
+    pub cost: &'a C,
+}
+
+impl<C: CostModel + ?Sized> CostModel for ClosedEdges<'_, C> {
+    fn edge_cost(&self, info: &EdgeInfo, from: &GeoPoint, to: &GeoPoint) -> u32 {
+        self.cost.edge_cost(info, from, to)
+    }
+
+    fn indexed_cost(
+        &self,
+        edge: EdgeIndex,
+        info: &EdgeInfo,
+        from: &GeoPoint,
+        to: &GeoPoint,
+    ) -> u32 {
+        if self.edges.contains(&edge) {
+            u32::MAX
+        } else {
+            self.cost.indexed_cost(edge, info, from, to)
+        }
+    }
+
+    fn heuristic(&self, from: &GeoPoint, to: &GeoPoint) -> u32 {
+        self.cost.heuristic(from, to)
+    }
+}
+
+impl CostModel for Experiment {
+    /// Without the index of the edge, only the factor of its road level is known
+    fn edge_cost(&self, info: &EdgeInfo, from: &GeoPoint, to: &GeoPoint) -> u32 {
+        self.indexed_cost(EdgeIndex::end(), info, from, to)
+    }
+
+    fn indexed_cost(
+        &self,
+        edge: EdgeIndex,
+        info: &EdgeInfo,
+        _from: &GeoPoint,
+        _to: &GeoPoint,
+    ) -> u32 {
+        scaled_cost(info.distance, self.factor(edge, info))
+    }
+}
+
+/// Travel the edges at the given speeds, in km/h, like the ones of
+/// `SpeedOverrides::active_speeds()`, and weigh them with `cost` otherwise. The edges slower than
+/// their default speed cost proportionally more, while the faster ones keep their cost, so that the
+/// heuristic of `cost` stays right
+#[derive(Clone, Copy, Debug)]
+pub struct LiveSpeeds<'a, C: ?Sized> {
+    pub speeds: &'a HashMap<EdgeIndex, f64>,
+    pub cost: &'a C,
+}
+
+impl<C: CostModel + ?Sized> CostModel for LiveSpeeds<'_, C> {
+    fn edge_cost(&self, info: &EdgeInfo, from: &GeoPoint, to: &GeoPoint) -> u32 {
+        self.cost.edge_cost(info, from, to)
+    }
+
+    fn indexed_cost(
+        &self,
+        edge: EdgeIndex,
+        info: &EdgeInfo,
+        from: &GeoPoint,
+        to: &GeoPoint,
+    ) -> u32 {
+        let cost = self.cost.indexed_cost(edge, info, from, to);
+        match self.speeds.get(&edge) {
+            Some(&speed) if cost != u32::MAX => {
+                scaled_cost(cost, (estimate_speed(info.road_level) / speed).max(1.))
+            }
+            _ => cost,
+        }
+    }
+
+    fn heuristic(&self, from: &GeoPoint, to: &GeoPoint) -> u32 {
+        self.cost.heuristic(from, to)
+    }
+}
+
+/// Prefer the dedicated bicycle infrastructure, like cycleways and cycle lanes, by multiplying the
+/// distance of the other edges by `factor`. Factors below 1 are ignored, so that the default
+/// heuristic stays right
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CyclewayPreference {
+    pub factor: f64,
+}
+
+impl CostModel for CyclewayPreference {
+    fn edge_cost(&self, info: &EdgeInfo, _from: &GeoPoint, _to: &GeoPoint) -> u32 {
+        if info.cycleway {
+            info.distance
+        } else {
+            scaled_cost(info.distance, self.factor.max(1.))
+        }
+    }
+}
+
+/// Prefer the signed cycle routes, by multiplying the distance of the edges that are not part of one
+/// by `factor`. Like for `CyclewayPreference`, factors below 1 are ignored
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CycleRoutePreference {
+    pub factor: f64,
+}
+
+impl CostModel for CycleRoutePreference {
+    fn edge_cost(&self, info: &EdgeInfo, _from: &GeoPoint, _to: &GeoPoint) -> u32 {
+        if info.bicycle_route {
+            info.distance
+        } else {
+            scaled_cost(info.distance, self.factor.max(1.))
+        }
+    }
+}
+
+/// A distance multiplied by a factor, saturating below the cost of the forbidden edges
+pub(super) fn scaled_cost(distance: u32, factor: f64) -> u32 {
+    (distance as f64 * factor)
+        .round()
+        .min((u32::MAX - 1) as f64) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartograph::{AccessClass, Surface};
+
+    #[test]
+    fn models() {
+        let point = GeoPoint::from_degrees(42.5, 1.5);
+        let mut info = EdgeInfo::new(100, 2);
+        info.surface = Surface::Unpaved;
+        assert_eq!(DistanceCost.edge_cost(&info, &point, &point), 100);
+
+        let penalties = SurfacePenalties {
+            surfaces: vec![(Surface::Unpaved, 2.5)],
+            smoothness: None,
+        };
+        assert_eq!(penalties.edge_cost(&info, &point, &point), 250);
+
+        info.access = AccessClass::Pedestrian;
+        let access = AccessPenalties {
+            penalized: Vec::new(),
+            forbidden: vec![AccessClass::Pedestrian],
+            barrier: 50,
+        };
+        assert_eq!(access.edge_cost(&info, &point, &point), u32::MAX);
+        info.access = AccessClass::Normal;
+        info.barrier = true;
+        assert_eq!(access.edge_cost(&info, &point, &point), 150);
+
+        let cycleways = CyclewayPreference { factor: 2. };
+        assert_eq!(cycleways.edge_cost(&info, &point, &point), 200);
+        info.cycleway = true;
+        assert_eq!(cycleways.edge_cost(&info, &point, &point), 100);
+
+        let routes = CycleRoutePreference { factor: 1.5 };
+        assert_eq!(routes.edge_cost(&info, &point, &point), 150);
+        info.bicycle_route = true;
+        assert_eq!(routes.edge_cost(&info, &point, &point), 100);
+
+        let both = AccessAndSurfaces {
+            access: &access,
+            surfaces: &penalties,
+        };
+        assert_eq!(both.edge_cost(&info, &point, &point), 300);
+        let mut edges = HashSet::new();
+        edges.insert(EdgeIndex::new(3));
+        let closed = ClosedEdges {
+            edges: &edges,
+            cost: &both,
+        };
+        let index = |i| closed.indexed_cost(EdgeIndex::new(i), &info, &point, &point);
+        assert_eq!((index(2), index(3)), (300, u32::MAX));
+
+        let mut speeds = HashMap::new();
+        speeds.insert(EdgeIndex::new(2), estimate_speed(info.road_level) / 2.);
+        speeds.insert(EdgeIndex::new(4), estimate_speed(info.road_level) * 2.);
+        let slower = LiveSpeeds {
+            speeds: &speeds,
+            cost: &closed,
+        };
+        let index = |i| slower.indexed_cost(EdgeIndex::new(i), &info, &point, &point);
+        assert_eq!((index(2), index(3), index(4)), (600, u32::MAX, 300));
+
+        let mut experiment = Experiment::default();
+        experiment.edges.insert(5, 3.);
+        let index = |i| experiment.indexed_cost(EdgeIndex::new(i), &info, &point, &point);
+        assert_eq!((index(4), index(5)), (100, 300));
+
+        let lower = CycleRoutePreference { factor: 0.5 };
+        info.bicycle_route = false;
+        assert_eq!(lower.edge_cost(&info, &point, &point), 100);
+
+        let closure = |info: &EdgeInfo, _: &GeoPoint, _: &GeoPoint| info.distance * 3;
+        assert_eq!(closure.edge_cost(&info, &point, &point), 300);
+        let other = GeoPoint::from_degrees(42.51, 1.5);
+        assert_eq!(
+            closure.heuristic(&point, &other),
+            point.haversine_distance(&other) as u32
+        );
+    }
+}
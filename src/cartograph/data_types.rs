@@ -1,8 +1,15 @@
+use super::access::AccessClass;
+use super::simplify::{overview_tolerance, ramer_douglas_peucker};
+use super::surface::{Smoothness, Surface};
+use super::vehicle::Restrictions;
 use crate::utils::GeoPoint;
 use geo_types::Coordinate;
 use petgraph::graph::EdgeIndex;
 use polyline::encode_coordinates;
 use rstar::{primitives::Line, Envelope, Point, PointDistance, RTreeObject, AABB};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
 use std::hash::{Hash, Hasher};
 
 /// Extend a rstar::primitives::Line with arbitrary data.
@@ -28,6 +35,10 @@ impl<T, P: Point> LineWithData<T, P> {
     pub fn nearest_point(&self, query_point: &P) -> P {
         self.line.nearest_point(query_point)
     }
+
+    pub fn endpoints(&self) -> (P, P) {
+        (self.line.from, self.line.to)
+    }
 }
 
 impl<T, P: Point> RTreeObject for LineWithData<T, P> {
@@ -58,32 +69,200 @@ impl<T: Hash, P: Point> Hash for LineWithData<T, P> {
 /// Note that the actual graph edge is a wrapper around this weight.
 /// It is inserted into the petgraph structure.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EdgeInfo {
     pub distance: u32,
     pub road_level: u8,
+    /// Whether the edge is part of a roundabout
+    pub roundabout: bool,
+    /// How many vehicles per hour can go through this edge
+    pub capacity: u32,
+    /// Number of lanes in this direction, 0 when unknown
+    pub lanes: u8,
+    /// Width of the road in meters, 0 when unknown
+    pub width: f32,
+    /// Whether the edge was invented to connect the graph and does not exist in reality
+    pub synthetic: bool,
+    /// Whether this direction is only open at some times, as given by `oneway:conditional`
+    pub conditional: bool,
+    /// Whether the road changes direction during the day, like `oneway=reversible`. Both
+    /// directions have an edge, but only one of them is open at a time
+    pub reversible: bool,
+    /// Whether the edge starts at a barrier that vehicles may pass, like a gate with `access=yes`.
+    /// Queries can add a penalty to cross it
+    pub barrier: bool,
+    /// Whether the edge has a dedicated bicycle infrastructure, like a cycleway or a cycle lane,
+    /// that cost models for bicycles can prefer
+    pub cycleway: bool,
+    /// Whether the edge is part of a signed cycle route, from the OSM route relations, that cost
+    /// models for bicycles can prefer
+    pub bicycle_route: bool,
+    /// Whether the edge is part of a bus route, from the OSM route relations
+    pub bus_route: bool,
+    /// Whether the edge crosses a border between regions, like countries, given to the generator
+    /// with `--borders`. Routes can count or avoid them
+    pub border: bool,
+    /// The `tracktype` grade of tracks, from 1 (solid) to 5 (soft), 0 when unknown or not a track.
+    /// Cost functions can use it to avoid the worst tracks
+    pub track_grade: u8,
+    pub surface: Surface,
+    pub smoothness: Smoothness,
+    /// Who is allowed on the road, like pedestrian zones, that queries can penalize or forbid
+    pub access: AccessClass,
+    /// The legal limits of weight, height and width, that queries for trucks avoid
+    pub restrictions: Restrictions,
+    /// The id of the OSM way of the road, to find it in openstreetmap.org. It is 0 when unknown,
+    /// unless the generator was run with `--osm-ids`
+    pub way_id: i64,
+    /// The index of the name of the road in `Cartograph::road_names()`, 0 when it has none
+    pub name: u32,
+    /// The index of the intermediate points of the edge in `Cartograph::edge_shapes()`, 0 when it
+    /// is a straight line between its nodes. Only the contracted edges have a shape
+    pub shape: u32,
+}
+
+/// Typical capacity of one lane, in vehicles per hour, indexed by the road level. The paths of the
+/// level 6 and the cycleways of the level 7 are only generated by the foot and bicycle profiles, and
+/// the unknown levels are local roads
+const LANE_CAPACITY_BY_LEVEL: [u32; 8] = [2000, 1800, 1500, 1000, 800, 600, 300, 300];
+
+/// Estimate the capacity of one direction of a road, in vehicles per hour, from its road level and
+/// number of lanes in that direction
+pub fn estimate_capacity(road_level: u8, lanes: u8) -> u32 {
+    LANE_CAPACITY_BY_LEVEL
+        .get(road_level as usize)
+        .unwrap_or(&LANE_CAPACITY_BY_LEVEL[5])
+        * lanes.max(1) as u32
+}
+
+/// Default speed, in km/h, indexed by the road level. This is only an estimate until the speeds are
+/// known for each road. The paths of the level 6 are traveled at a walking speed and the cycleways
+/// of the level 7 at a cycling speed, and the unknown levels are local roads
+const SPEED_BY_LEVEL: [f64; 8] = [100., 80., 60., 50., 40., 25., 5., 15.];
+
+/// The default speed, in km/h, of the roads of the given level
+pub fn estimate_speed(road_level: u8) -> f64 {
+    *SPEED_BY_LEVEL
+        .get(road_level as usize)
+        .unwrap_or(&SPEED_BY_LEVEL[5])
+}
+
+/// Estimate the time, in seconds, to travel the given distance in a road of the given level
+pub fn estimate_duration(distance: u32, road_level: u8) -> f64 {
+    distance as f64 * 3.6 / estimate_speed(road_level)
+}
+
+/// Round a duration in seconds to a tenth of a second, like OSRM. The durations are only rounded
+/// when they are returned, so that the sum of the rounded parts may differ a bit from the total
+pub fn round_duration(duration: f64) -> f64 {
+    (duration * 10.).round() / 10.
+}
+
+/// Round a distance in meters that is not a whole number, like from a point to the road where it is
+/// snapped, to a tenth of a meter, like the durations
+pub fn round_distance(distance: f64) -> f64 {
+    (distance * 10.).round() / 10.
+}
+
+/// The distance, in meters, covered by a fraction of an edge, like from a projected point to the
+/// end of its edge. It is truncated to whole meters, so that all the searches agree on the distance
+/// of the same path and a part of an edge is never longer than the full edge
+pub fn partial_distance(distance: u32, fraction: f32) -> u32 {
+    (distance as f32 * fraction) as u32
+}
+
+impl EdgeInfo {
+    /// An edge with only the mandatory attributes, like in a file without any extra column. The
+    /// capacity is estimated for a single lane and all the other attributes are unknown
+    pub fn new(distance: u32, road_level: u8) -> Self {
+        EdgeInfo {
+            distance,
+            road_level,
+            roundabout: false,
+            capacity: estimate_capacity(road_level, 1),
+            lanes: 0,
+            width: 0.,
+            synthetic: false,
+            conditional: false,
+            reversible: false,
+            barrier: false,
+            cycleway: false,
+            bicycle_route: false,
+            bus_route: false,
+            border: false,
+            track_grade: 0,
+            surface: Surface::Unknown,
+            smoothness: Smoothness::Unknown,
+            access: AccessClass::Normal,
+            restrictions: Restrictions::default(),
+            way_id: 0,
+            name: 0,
+            shape: 0,
+        }
+    }
+
+    /// The estimated time, in seconds, to traverse the edge
+    pub fn duration(&self) -> f64 {
+        estimate_duration(self.distance, self.road_level)
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProjectedPoint {
     pub original: GeoPoint,
     pub projected: GeoPoint,
+    #[cfg_attr(feature = "serde", serde(with = "serde_edge_index"))]
     pub edge: EdgeIndex,
-    /// The ratio over the edge where the projected point is.
+    /// The ratio over the edge where the projected point is, along its shape when it has one.
     /// 0 = at source, 1 = at target
     pub edge_pos: f32,
 }
 
+/// The side of the road where a point is, relative to the direction of travel
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Side {
+    Left,
+    Right,
+    /// Less than `Side::ON_ROAD_DISTANCE` from the road
+    OnRoad,
+}
+
+impl Side {
+    /// The distance in meters under which a point is considered to be on the road
+    pub const ON_ROAD_DISTANCE: f64 = 1.;
+}
+
+/// How a vehicle arrives at a projected point
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Approach {
+    /// The heading of the road at the projected point, in the direction of travel, in degrees
+    /// clockwise from the North, in the range [0, 360)
+    pub bearing: f64,
+    /// The side of the road where the original point is, like the door of a delivery
+    pub side: Side,
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct GraphPath {
     pub distance: u32,
+    /// The estimated time to travel the path, in seconds
+    pub duration: f64,
     pub points: Vec<GeoPoint>,
     /// A polyline-encoded string of the points vector, with precision 5
     pub polyline: String,
+    /// The traversed edges, including the ones where the path starts and ends. Empty when the path
+    /// was not found in the graph, like a decoded geometry
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub edges: Vec<EdgeIndex>,
 }
 
 impl GraphPath {
     /// Build a new graph path, encoding the polyline from the points
-    pub fn new(distance: u32, points: Vec<GeoPoint>) -> Self {
+    pub fn new(distance: u32, duration: f64, points: Vec<GeoPoint>) -> Self {
         let polyline = encode_coordinates(
             points.iter().map(|point| Coordinate {
                 x: point.lon.as_degrees(),
@@ -95,8 +274,268 @@ impl GraphPath {
 
         Self {
             distance,
+            duration,
             points,
             polyline,
+            edges: Vec::new(),
+        }
+    }
+
+    /// The same path, with the edges that it traverses
+    pub fn with_edges(mut self, edges: Vec<EdgeIndex>) -> Self {
+        self.edges = edges;
+        self
+    }
+
+    /// The same path with fewer points: those closer than `tolerance` meters to the line of the
+    /// others are removed. The distance and duration are still the ones of the full path
+    pub fn simplify(&self, tolerance: f64) -> GraphPath {
+        GraphPath::new(
+            self.distance,
+            self.duration,
+            ramer_douglas_peucker(&self.points, tolerance),
+        )
+        .with_edges(self.edges.clone())
+    }
+
+    /// The distance in meters from the start of the path to each of its points, measured along the
+    /// geometry. The first one is 0 and the last one is close to `distance`, which is the sum of the
+    /// rounded distances of the edges
+    pub fn cumulative_distances(&self) -> Vec<f64> {
+        let mut total = 0.;
+        let mut distances = Vec::with_capacity(self.points.len());
+        for (i, point) in self.points.iter().enumerate() {
+            if i > 0 {
+                total += self.points[i - 1].haversine_distance(point);
+            }
+            distances.push(total);
+        }
+        distances
+    }
+
+    /// The path simplified to be drawn whole on a screen, removing the details smaller than a pixel
+    pub fn overview(&self) -> GraphPath {
+        self.simplify(overview_tolerance(&self.points))
+    }
+
+    /// Export the path as a GPX 1.1 document with a single track. The `waypoints` are written as
+    /// `<wpt>` elements, usually the points requested by the user. If `elevations` is given, it must
+    /// have one value, in meters, for each point of the path
+    pub fn to_gpx(&self, waypoints: &[GeoPoint], elevations: Option<&[f64]>) -> String {
+        if let Some(elevations) = elevations {
+            assert_eq!(elevations.len(), self.points.len());
+        }
+        let waypoints: Vec<_> = waypoints.iter().map(|waypoint| (*waypoint, None)).collect();
+        self.write_gpx(&waypoints, |i, _| {
+            elevations.map(|elevations| elevations[i])
+        })
+    }
+
+    /// Like `to_gpx()`, but get the elevation of each point of the path and of each waypoint, in
+    /// meters, from a function, like a lookup in a digital elevation model. The points where it
+    /// returns `None` are written without elevation
+    pub fn to_gpx_with_elevation<F>(&self, waypoints: &[GeoPoint], elevation: F) -> String
+    where
+        F: Fn(&GeoPoint) -> Option<f64>,
+    {
+        let waypoints: Vec<_> = waypoints
+            .iter()
+            .map(|waypoint| (*waypoint, elevation(waypoint)))
+            .collect();
+        self.write_gpx(&waypoints, |_, point| elevation(point))
+    }
+
+    /// Write the GPX document, with the elevation of each waypoint and of the i-th point of the
+    /// track when known
+    fn write_gpx<F>(&self, waypoints: &[(GeoPoint, Option<f64>)], elevation: F) -> String
+    where
+        F: Fn(usize, &GeoPoint) -> Option<f64>,
+    {
+        let mut gpx = String::new();
+        writeln!(gpx, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(
+            gpx,
+            r#"<gpx version="1.1" creator="ptolemy" xmlns="http://www.topografix.com/GPX/1/1">"#
+        )
+        .unwrap();
+
+        for (waypoint, elevation) in waypoints {
+            write_gpx_point(&mut gpx, "  ", "wpt", waypoint, *elevation);
+        }
+
+        writeln!(gpx, "  <trk>").unwrap();
+        writeln!(gpx, "    <trkseg>").unwrap();
+        for (i, point) in self.points.iter().enumerate() {
+            write_gpx_point(&mut gpx, "      ", "trkpt", point, elevation(i, point));
         }
+        writeln!(gpx, "    </trkseg>").unwrap();
+        writeln!(gpx, "  </trk>").unwrap();
+        writeln!(gpx, "</gpx>").unwrap();
+
+        gpx
+    }
+}
+
+/// Write a GPX element with the coordinates of a point and its elevation, if known
+fn write_gpx_point(gpx: &mut String, indent: &str, tag: &str, point: &GeoPoint, ele: Option<f64>) {
+    let (lat, lon) = (point.lat.as_degrees(), point.lon.as_degrees());
+    match ele {
+        None => writeln!(gpx, r#"{}<{} lat="{}" lon="{}"/>"#, indent, tag, lat, lon),
+        Some(ele) => writeln!(
+            gpx,
+            r#"{}<{} lat="{}" lon="{}"><ele>{:.1}</ele></{}>"#,
+            indent, tag, lat, lon, ele, tag
+        ),
+    }
+    .unwrap();
+}
+
+/// Serialize edge indexes as plain integers
+#[cfg(feature = "serde")]
+mod serde_edge_index {
+    use petgraph::graph::EdgeIndex;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(edge: &EdgeIndex, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(edge.index() as u32)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<EdgeIndex, D::Error> {
+        u32::deserialize(deserializer).map(|index| EdgeIndex::new(index as usize))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gpx() {
+        let a = GeoPoint::from_degrees(42.5, 1.5);
+        let b = GeoPoint::from_degrees(42.51, 1.52);
+        let path = GraphPath::new(1234, 61.7, vec![a, b]);
+
+        let gpx = path.to_gpx(&[a, b], None);
+        assert!(gpx.starts_with("<?xml"));
+        assert_eq!(gpx.matches("<wpt ").count(), 2);
+        assert!(gpx.contains(r#"<trkpt lat="42.51" lon="1.52"/>"#));
+        assert!(!gpx.contains("<ele>"));
+
+        let gpx = path.to_gpx(&[], Some(&[1000., 1017.25]));
+        assert_eq!(gpx.matches("<wpt ").count(), 0);
+        assert!(gpx.contains(r#"<trkpt lat="42.5" lon="1.5"><ele>1000.0</ele></trkpt>"#));
+        assert!(gpx.trim_end().ends_with("</gpx>"));
+    }
+
+    #[test]
+    fn cumulative_distances() {
+        let a = GeoPoint::from_degrees(42.5, 1.5);
+        let b = GeoPoint::from_degrees(42.51, 1.52);
+        let c = GeoPoint::from_degrees(42.52, 1.52);
+        let path = GraphPath::new(3000, 120., vec![a, b, b, c]);
+        let distances = path.cumulative_distances();
+        assert_eq!(distances.len(), 4);
+        assert_eq!(distances[0], 0.);
+        assert_eq!(distances[1], a.haversine_distance(&b));
+        assert_eq!(distances[2], distances[1]);
+        assert!((distances[3] - distances[1] - b.haversine_distance(&c)).abs() < 1e-9);
+        assert!(GraphPath::new(0, 0., vec![])
+            .cumulative_distances()
+            .is_empty());
+    }
+
+    #[test]
+    fn gpx_with_elevation() {
+        let a = GeoPoint::from_degrees(42.5, 1.5);
+        let b = GeoPoint::from_degrees(42.51, 1.52);
+        let path = GraphPath::new(1234, 61.7, vec![a, b]);
+
+        // Only the points in the south have a known elevation
+        let gpx = path.to_gpx_with_elevation(&[a, b], |point| {
+            if point.lat.as_degrees() < 42.505 {
+                Some(1000.)
+            } else {
+                None
+            }
+        });
+        assert!(gpx.contains(r#"<wpt lat="42.5" lon="1.5"><ele>1000.0</ele></wpt>"#));
+        assert!(gpx.contains(r#"<wpt lat="42.51" lon="1.52"/>"#));
+        assert!(gpx.contains(r#"<trkpt lat="42.5" lon="1.5"><ele>1000.0</ele></trkpt>"#));
+        assert!(gpx.contains(r#"<trkpt lat="42.51" lon="1.52"/>"#));
+        assert_eq!(
+            path.to_gpx_with_elevation(&[a, b], |_| None),
+            path.to_gpx(&[a, b], None)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let point = ProjectedPoint {
+            original: GeoPoint::from_degrees(42.5, 1.5),
+            projected: GeoPoint::from_degrees(42.51, 1.52),
+            edge: EdgeIndex::new(17),
+            edge_pos: 0.25,
+        };
+        let json = serde_json::to_string(&point).unwrap();
+        assert_eq!(
+            json,
+            r#"{"original":{"lat":42500000,"lon":1500000},"projected":{"lat":42510000,"lon":1520000},"edge":17,"edge_pos":0.25}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ProjectedPoint>(&json).unwrap(),
+            point
+        );
+
+        let path = GraphPath::new(1234, 61.7, vec![point.original, point.projected]);
+        let json = serde_json::to_string(&path).unwrap();
+        let path2: GraphPath = serde_json::from_str(&json).unwrap();
+        assert_eq!(path2.distance, path.distance);
+        assert_eq!(path2.duration, path.duration);
+        assert_eq!(path2.points, path.points);
+        assert_eq!(path2.polyline, path.polyline);
+
+        let info = EdgeInfo {
+            distance: 17,
+            road_level: 3,
+            roundabout: true,
+            capacity: 1000,
+            lanes: 2,
+            width: 7.5,
+            synthetic: false,
+            conditional: false,
+            reversible: false,
+            barrier: false,
+            cycleway: false,
+            bicycle_route: false,
+            bus_route: false,
+            border: false,
+            track_grade: 4,
+            surface: Surface::Gravel,
+            smoothness: Smoothness::Bad,
+            access: AccessClass::Normal,
+            restrictions: Restrictions {
+                max_height: 3.5,
+                no_hgv: true,
+                ..Restrictions::default()
+            },
+            way_id: 8_000_000_000,
+            name: 2,
+            shape: 0,
+        };
+        let json = serde_json::to_string(&info).unwrap();
+        assert_eq!(serde_json::from_str::<EdgeInfo>(&json).unwrap(), info);
+    }
+
+    #[test]
+    fn duration() {
+        // 1 km at 50 km/h
+        assert_eq!(estimate_duration(1000, 3), 72.);
+        assert_eq!(estimate_duration(1000, 17), estimate_duration(1000, 5));
+        assert_eq!(estimate_duration(1000, 6), 720.);
+        assert_eq!(estimate_duration(1000, 7), 240.);
+        assert_eq!(round_duration(72.349), 72.3);
+        assert_eq!(round_duration(0.05), 0.1);
+        assert_eq!(round_distance(348.0034756867468), 348.);
     }
 }
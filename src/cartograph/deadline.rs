@@ -0,0 +1,103 @@
+use super::error::Timeout;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+use std::time::Instant;
+
+/// Number of steps of a search between two readings of the clock
+const CHECK_INTERVAL: u32 = 256;
+
+/// When to give up a search: after some time, or when it is cancelled from another thread. The
+/// clones share the cancellation, so one can be kept to stop the search that received the other
+#[derive(Clone, Debug, Default)]
+pub struct Deadline {
+    at: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Deadline {
+    /// A deadline that never passes, unless it is cancelled
+    pub fn never() -> Self {
+        Deadline::default()
+    }
+
+    /// A deadline that passes after the given duration from now
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn after(duration: Duration) -> Self {
+        Deadline {
+            at: Some(Instant::now() + duration),
+            cancelled: Arc::default(),
+        }
+    }
+
+    /// Stop the searches with this deadline or any of its clones
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether it was cancelled or its time has passed
+    pub fn has_passed(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || matches!(self.at, Some(at) if Instant::now() >= at)
+    }
+}
+
+/// Check a deadline from the inner loop of a search, only reading the clock every few steps. It
+/// also counts the work of the search, for `SearchStats`
+pub(super) struct DeadlineCheck<'a> {
+    deadline: &'a Deadline,
+    steps: Cell<u32>,
+    passed: Cell<bool>,
+    settled: Cell<u32>,
+    pushes: Cell<u32>,
+}
+
+impl<'a> DeadlineCheck<'a> {
+    pub fn new(deadline: &'a Deadline) -> Self {
+        DeadlineCheck {
+            deadline,
+            steps: Cell::new(0),
+            passed: Cell::new(false),
+            settled: Cell::new(0),
+            pushes: Cell::new(0),
+        }
+    }
+
+    /// Count a node whose cheapest cost was found
+    pub fn settle(&self) {
+        self.settled.set(self.settled.get().saturating_add(1));
+    }
+
+    /// Count a node pushed to the queue of the search
+    pub fn push(&self) {
+        self.pushes.set(self.pushes.get().saturating_add(1));
+    }
+
+    /// How many nodes were settled and pushed
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn counts(&self) -> (u32, u32) {
+        (self.settled.get(), self.pushes.get())
+    }
+
+    /// Count one step of the search and return whether it should stop. Once it returns true, it
+    /// always does
+    pub fn step(&self) -> bool {
+        let steps = self.steps.get();
+        if !self.passed.get() && steps.is_multiple_of(CHECK_INTERVAL) {
+            self.passed.set(self.deadline.has_passed());
+        }
+        self.steps.set(steps.wrapping_add(1));
+        self.passed.get()
+    }
+
+    /// Fail if the search was stopped by the deadline
+    pub fn result(&self) -> Result<(), Timeout> {
+        if self.passed.get() {
+            Err(Timeout)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use super::Cartograph;
+use crate::utils::{format_num, GeoPoint};
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use std::collections::HashMap;
+use std::fmt;
+
+/// How many examples of each kind of difference are displayed
+const MAX_EXAMPLES: usize = 5;
+
+/// The differences between two cartographies, like the outputs of two versions of the generator for
+/// the same extract. Nodes are matched by their coordinates and edges by the coordinates of their
+/// endpoints, since the indexes are not stable between files
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphDiff {
+    pub old_num_nodes: usize,
+    pub new_num_nodes: usize,
+    pub old_num_edges: usize,
+    pub new_num_edges: usize,
+    /// The nodes only in the new cartography, sorted by latitude and longitude
+    pub added_nodes: Vec<GeoPoint>,
+    /// The nodes only in the old cartography, sorted by latitude and longitude
+    pub removed_nodes: Vec<GeoPoint>,
+    /// The edges only in the new cartography, as `(source, target)`
+    pub added_edges: Vec<(GeoPoint, GeoPoint)>,
+    /// The edges only in the old cartography, as `(source, target)`
+    pub removed_edges: Vec<(GeoPoint, GeoPoint)>,
+    /// The edges in both cartographies, but with a different distance
+    pub changed_distances: Vec<ChangedDistance>,
+    /// The number of nodes of each strongly connected component, from the largest one
+    pub old_component_sizes: Vec<usize>,
+    pub new_component_sizes: Vec<usize>,
+    /// The nodes in both cartographies that were in the main component and are no longer
+    pub left_main_component: Vec<GeoPoint>,
+    /// The nodes in both cartographies that were not in the main component and now are
+    pub joined_main_component: Vec<GeoPoint>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChangedDistance {
+    pub source: GeoPoint,
+    pub target: GeoPoint,
+    /// In meters
+    pub old_distance: u32,
+    pub new_distance: u32,
+}
+
+/// A node or edge, identified by the coordinates in micro degrees
+type NodeKey = (i32, i32);
+type EdgeKey = (NodeKey, NodeKey);
+
+impl GraphDiff {
+    pub fn compute(old: &Cartograph, new: &Cartograph) -> GraphDiff {
+        let old_nodes = nodes_by_key(old);
+        let new_nodes = nodes_by_key(new);
+        let old_edges = edges_by_key(old);
+        let new_edges = edges_by_key(new);
+
+        let added_nodes = sorted_keys(new_nodes.keys().filter(|key| !old_nodes.contains_key(key)));
+        let removed_nodes =
+            sorted_keys(old_nodes.keys().filter(|key| !new_nodes.contains_key(key)));
+
+        let mut added_edges: Vec<EdgeKey> = new_edges
+            .keys()
+            .filter(|key| !old_edges.contains_key(key))
+            .copied()
+            .collect();
+        added_edges.sort_unstable();
+        let mut removed_edges: Vec<EdgeKey> = old_edges
+            .keys()
+            .filter(|key| !new_edges.contains_key(key))
+            .copied()
+            .collect();
+        removed_edges.sort_unstable();
+        let mut changed_distances: Vec<(EdgeKey, u32, u32)> = old_edges
+            .iter()
+            .filter_map(|(key, &old_distance)| match new_edges.get(key) {
+                Some(&new_distance) if new_distance != old_distance => {
+                    Some((*key, old_distance, new_distance))
+                }
+                _ => None,
+            })
+            .collect();
+        changed_distances.sort_unstable();
+
+        // The main component is always the first one
+        let mut left_main_component = Vec::new();
+        let mut joined_main_component = Vec::new();
+        for (key, &old_node) in &old_nodes {
+            if let Some(&new_node) = new_nodes.get(key) {
+                let was_main = old.component_id(old_node) == 0;
+                let is_main = new.component_id(new_node) == 0;
+                if was_main && !is_main {
+                    left_main_component.push(*key);
+                } else if !was_main && is_main {
+                    joined_main_component.push(*key);
+                }
+            }
+        }
+
+        GraphDiff {
+            old_num_nodes: old.graph.node_count(),
+            new_num_nodes: new.graph.node_count(),
+            old_num_edges: old.graph.edge_count(),
+            new_num_edges: new.graph.edge_count(),
+            added_nodes,
+            removed_nodes,
+            added_edges: added_edges.into_iter().map(edge_points).collect(),
+            removed_edges: removed_edges.into_iter().map(edge_points).collect(),
+            changed_distances: changed_distances
+                .into_iter()
+                .map(|(key, old_distance, new_distance)| {
+                    let (source, target) = edge_points(key);
+                    ChangedDistance {
+                        source,
+                        target,
+                        old_distance,
+                        new_distance,
+                    }
+                })
+                .collect(),
+            old_component_sizes: old.component_sizes(),
+            new_component_sizes: new.component_sizes(),
+            left_main_component: sorted_keys(left_main_component.iter()),
+            joined_main_component: sorted_keys(joined_main_component.iter()),
+        }
+    }
+
+    /// Whether both cartographies have the same nodes, edges, distances and components
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.changed_distances.is_empty()
+            && self.old_component_sizes == self.new_component_sizes
+            && self.left_main_component.is_empty()
+            && self.joined_main_component.is_empty()
+    }
+}
+
+fn nodes_by_key(carto: &Cartograph) -> HashMap<NodeKey, NodeIndex> {
+    carto
+        .graph
+        .node_indices()
+        .map(|node| (node_key(&carto.graph[node]), node))
+        .collect()
+}
+
+/// The distance of each edge. When there are parallel edges, the shortest one is kept
+fn edges_by_key(carto: &Cartograph) -> HashMap<EdgeKey, u32> {
+    let mut edges = HashMap::with_capacity(carto.graph.edge_count());
+    for edge in carto.graph.edge_references() {
+        let key = (
+            node_key(&carto.graph[edge.source()]),
+            node_key(&carto.graph[edge.target()]),
+        );
+        let distance = edges.entry(key).or_insert(edge.weight().distance);
+        *distance = (*distance).min(edge.weight().distance);
+    }
+    edges
+}
+
+fn node_key(point: &GeoPoint) -> NodeKey {
+    (point.lat.as_micro_degrees(), point.lon.as_micro_degrees())
+}
+
+fn node_point((lat, lon): NodeKey) -> GeoPoint {
+    GeoPoint::from_micro_degrees(lat, lon)
+}
+
+fn edge_points((source, target): EdgeKey) -> (GeoPoint, GeoPoint) {
+    (node_point(source), node_point(target))
+}
+
+fn sorted_keys<'a>(keys: impl Iterator<Item = &'a NodeKey>) -> Vec<GeoPoint> {
+    let mut keys: Vec<NodeKey> = keys.copied().collect();
+    keys.sort_unstable();
+    keys.into_iter().map(node_point).collect()
+}
+
+/// A summary of the differences, with some examples of each kind
+impl fmt::Display for GraphDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let point = |point: &GeoPoint| {
+            format!(
+                "({:.6}, {:.6})",
+                point.lat.as_degrees(),
+                point.lon.as_degrees()
+            )
+        };
+
+        writeln!(
+            f,
+            "Nodes: {} -> {} (+{} added, -{} removed)",
+            format_num(self.old_num_nodes),
+            format_num(self.new_num_nodes),
+            format_num(self.added_nodes.len()),
+            format_num(self.removed_nodes.len())
+        )?;
+        for node in self.added_nodes.iter().take(MAX_EXAMPLES) {
+            writeln!(f, "  + {}", point(node))?;
+        }
+        for node in self.removed_nodes.iter().take(MAX_EXAMPLES) {
+            writeln!(f, "  - {}", point(node))?;
+        }
+
+        writeln!(
+            f,
+            "Edges: {} -> {} (+{} added, -{} removed)",
+            format_num(self.old_num_edges),
+            format_num(self.new_num_edges),
+            format_num(self.added_edges.len()),
+            format_num(self.removed_edges.len())
+        )?;
+        for (source, target) in self.added_edges.iter().take(MAX_EXAMPLES) {
+            writeln!(f, "  + {} -> {}", point(source), point(target))?;
+        }
+        for (source, target) in self.removed_edges.iter().take(MAX_EXAMPLES) {
+            writeln!(f, "  - {} -> {}", point(source), point(target))?;
+        }
+
+        writeln!(
+            f,
+            "Changed distances: {}",
+            format_num(self.changed_distances.len())
+        )?;
+        for changed in self.changed_distances.iter().take(MAX_EXAMPLES) {
+            writeln!(
+                f,
+                "  {} -> {}: {} m -> {} m",
+                point(&changed.source),
+                point(&changed.target),
+                changed.old_distance,
+                changed.new_distance
+            )?;
+        }
+
+        writeln!(
+            f,
+            "Strongly connected components: {} -> {}, main component with {} -> {} nodes",
+            format_num(self.old_component_sizes.len()),
+            format_num(self.new_component_sizes.len()),
+            format_num(self.old_component_sizes.first().copied().unwrap_or(0)),
+            format_num(self.new_component_sizes.first().copied().unwrap_or(0))
+        )?;
+        writeln!(
+            f,
+            "Nodes that left the main component: {}, that joined it: {}",
+            format_num(self.left_main_component.len()),
+            format_num(self.joined_main_component.len())
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use petgraph::graph::EdgeIndex;
+
+    #[test]
+    fn diff() {
+        let old = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let mut new = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let same = GraphDiff::compute(&old, &new);
+        assert!(same.is_empty());
+        assert_eq!(same.old_num_edges, 5831);
+
+        // Change a distance, remove an edge and add an isolated node
+        new.graph[EdgeIndex::new(0)].distance += 10;
+        let (source, target) = new.graph.edge_endpoints(EdgeIndex::new(1)).unwrap();
+        let removed = (new.graph[source], new.graph[target]);
+        new.graph.remove_edge(EdgeIndex::new(1));
+        let added = GeoPoint::from_degrees(42.5, 1.5);
+        new.graph.add_node(added);
+
+        let diff = GraphDiff::compute(&old, &new);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.added_nodes, vec![added]);
+        assert!(diff.removed_nodes.is_empty());
+        assert_eq!(diff.removed_edges, vec![removed]);
+        assert!(diff.added_edges.is_empty());
+        assert_eq!(diff.changed_distances.len(), 1);
+        assert_eq!(
+            diff.changed_distances[0].new_distance,
+            diff.changed_distances[0].old_distance + 10
+        );
+        assert!(diff.to_string().contains("Changed distances: 1"));
+    }
+}
@@ -0,0 +1,150 @@
+use super::cost::{scaled_cost, CostModel};
+use super::data_types::*;
+use super::deadline::Deadline;
+use super::error::Timeout;
+use super::Cartograph;
+use crate::utils::GeoPoint;
+use petgraph::graph::EdgeIndex;
+use std::collections::HashMap;
+
+/// Maximum number of searches for each route before falling back to the optimal one
+const MAX_ATTEMPTS: usize = 4;
+
+/// Control how far randomized routes can stray from the optimal one
+#[derive(Clone, Copy, Debug)]
+pub struct DiversityOptions {
+    /// Maximum extra distance of a route, as a fraction of the optimal distance. For example, 0.1 means
+    /// routes can be up to 10% longer than the shortest path
+    pub max_stretch: f64,
+    /// Maximum random increase of each edge cost, as a fraction of its distance
+    pub noise: f64,
+    /// Extra cost of an edge for each previous route that used it, as a fraction of its distance
+    pub penalty: f64,
+}
+
+impl Default for DiversityOptions {
+    fn default() -> Self {
+        DiversityOptions {
+            max_stretch: 0.1,
+            noise: 0.2,
+            penalty: 0.5,
+        }
+    }
+}
+
+/// Generate `count` near-optimal paths between two points. Each path is searched with randomly
+/// perturbed edge costs, plus a penalty on the edges used by the previous paths, so that they tend
+/// to spread over different streets. Paths longer than allowed by `options.max_stretch` are
+/// searched again with less perturbation, until falling back to the cheapest path with `cost`.
+/// The result only depends on `seed`, so the same request always returns the same paths. Returns
+/// `None` if the destination is not reachable
+#[allow(clippy::too_many_arguments)]
+pub fn diverse_paths<C: CostModel + ?Sized>(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &ProjectedPoint,
+    count: usize,
+    seed: u64,
+    options: &DiversityOptions,
+    cost: &C,
+    deadline: &Deadline,
+) -> Result<Option<Vec<GraphPath>>, Timeout> {
+    let optimal = match search(carto, from, to, cost, |_| 1., deadline)? {
+        Some(optimal) => optimal,
+        None => return Ok(None),
+    };
+    let max_distance = optimal.distance as f64 * (1. + options.max_stretch);
+
+    let mut uses: HashMap<EdgeIndex, u32> = HashMap::new();
+    let mut paths = Vec::with_capacity(count);
+    for round in 0..count {
+        let mut scale = 1.;
+        let mut found = None;
+        for _ in 0..MAX_ATTEMPTS {
+            let factor = |edge| {
+                let noise = options.noise * random_unit(seed, round as u64, edge);
+                let penalty = options.penalty * *uses.get(&edge).unwrap_or(&0) as f64;
+                1. + scale * (noise + penalty)
+            };
+            let path = search(carto, from, to, cost, factor, deadline)?;
+            if let Some(path) = path.filter(|path| path.distance as f64 <= max_distance) {
+                found = Some(path);
+                break;
+            }
+            scale /= 2.;
+        }
+
+        let path = found.unwrap_or_else(|| optimal.clone());
+        for &edge in &path.edges {
+            *uses.entry(edge).or_insert(0) += 1;
+        }
+        paths.push(path);
+    }
+
+    Ok(Some(paths))
+}
+
+/// Run an A* search where the cost of each edge is its cost with `cost` multiplied by
+/// `factor(edge)`, that must be at least 1. Return the path with its real distance
+pub(super) fn search<C, F>(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &ProjectedPoint,
+    cost: &C,
+    factor: F,
+    deadline: &Deadline,
+) -> Result<Option<GraphPath>, Timeout>
+where
+    C: CostModel + ?Sized,
+    F: Fn(EdgeIndex) -> f64,
+{
+    carto.cheapest_path(from, to, &Factors { cost, factor }, deadline)
+}
+
+/// The cost model of the searches: the cost of each edge multiplied by its factor
+struct Factors<'a, C: ?Sized, F> {
+    cost: &'a C,
+    factor: F,
+}
+
+impl<C, F> CostModel for Factors<'_, C, F>
+where
+    C: CostModel + ?Sized,
+    F: Fn(EdgeIndex) -> f64,
+{
+    fn edge_cost(&self, info: &EdgeInfo, from: &GeoPoint, to: &GeoPoint) -> u32 {
+        self.cost.edge_cost(info, from, to)
+    }
+
+    fn indexed_cost(
+        &self,
+        edge: EdgeIndex,
+        info: &EdgeInfo,
+        from: &GeoPoint,
+        to: &GeoPoint,
+    ) -> u32 {
+        match self.cost.indexed_cost(edge, info, from, to) {
+            u32::MAX => u32::MAX,
+            cost => scaled_cost(cost, (self.factor)(edge)),
+        }
+    }
+
+    fn heuristic(&self, from: &GeoPoint, to: &GeoPoint) -> u32 {
+        self.cost.heuristic(from, to)
+    }
+}
+
+/// Return a pseudo-random number in [0, 1) that is fully determined by its arguments, using the
+/// SplitMix64 mixing function. This avoids keeping a random generator state during the search
+fn random_unit(seed: u64, round: u64, edge: EdgeIndex) -> f64 {
+    let mut x = seed ^ mix(round << 32 | edge.index() as u64);
+    x = mix(x);
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+pub(super) fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,204 @@
+use super::data_types::*;
+use super::Cartograph;
+use crate::utils::GeoPoint;
+use petgraph::{
+    algo::astar,
+    graph::{EdgeIndex, NodeIndex},
+    visit::EdgeRef,
+    Graph,
+};
+
+/// The edge-expanded representation of the road map. Each node of this graph is a directed edge
+/// of the original graph (with the same index) and each arc is an allowed turn from one edge to
+/// the next one. Unlike the node-based graph, this can express turn restrictions (by not creating
+/// the arc) and turn penalties (by adding to the arc cost).
+pub struct EdgeBasedCartograph {
+    /// The original node-based cartography
+    pub carto: Cartograph,
+    /// The edge-expanded graph. The node with index `i` represents the edge with index `i` of
+    /// `carto.graph`
+    pub graph: Graph<EdgeIndex, TurnInfo>,
+}
+
+/// Describe a possible turn, that is, leaving the edge `from` into the edge `to` through
+/// their common node `via`
+#[derive(Clone, Copy)]
+pub struct Turn<'a> {
+    pub from: EdgeIndex,
+    pub to: EdgeIndex,
+    pub via: NodeIndex,
+    /// The original cartography, to allow the cost function to inspect the edges
+    pub carto: &'a Cartograph,
+}
+
+/// Represents the extra data for each turn in the edge-expanded graph
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TurnInfo {
+    /// The extra cost of the turn itself, as returned by the turn cost function
+    pub penalty: u32,
+    /// The cost of taking the turn and then traversing the whole next edge, for the search. The
+    /// distance of the paths does not include the penalties
+    pub cost: u32,
+}
+
+impl<'a> Turn<'a> {
+    /// The turn angle in degrees, in the range [-180, 180). Positive values are turns to the
+    /// right, negative ones to the left and zero means going straight ahead
+    pub fn angle(&self) -> f64 {
+        let (from_source, via, to_target) = self.points();
+        let bearing_in = from_source.bearing(&via);
+        let bearing_out = via.bearing(&to_target);
+        (bearing_out - bearing_in + 540.) % 360. - 180.
+    }
+
+    /// Whether this turn goes back to where it came from
+    pub fn is_u_turn(&self) -> bool {
+        let (from_source, _) = self.carto.graph.edge_endpoints(self.from).unwrap();
+        let (_, to_target) = self.carto.graph.edge_endpoints(self.to).unwrap();
+        from_source == to_target
+    }
+
+    /// Return the source of `from`, the common node and the target of `to`
+    fn points(&self) -> (GeoPoint, GeoPoint, GeoPoint) {
+        let graph = &self.carto.graph;
+        let (from_source, via) = graph.edge_endpoints(self.from).unwrap();
+        let (_, to_target) = graph.edge_endpoints(self.to).unwrap();
+        (graph[from_source], graph[via], graph[to_target])
+    }
+}
+
+impl EdgeBasedCartograph {
+    /// Build the edge-expanded graph. The function `turn_cost` is called for every possible turn
+    /// and returns its penalty or `None` if the turn is forbidden
+    pub fn new<F>(carto: Cartograph, turn_cost: F) -> Self
+    where
+        F: Fn(&Turn) -> Option<u32>,
+    {
+        let mut graph = Graph::with_capacity(carto.graph.edge_count(), 0);
+
+        // Create one node per original edge, with matching indexes
+        for edge in carto.graph.edge_indices() {
+            graph.add_node(edge);
+        }
+
+        // Create one arc per allowed turn
+        for from in carto.graph.edge_references() {
+            let via = from.target();
+            for to in carto.graph.edges(via) {
+                if !carto.is_routable(from.weight()) || !carto.is_routable(to.weight()) {
+                    continue;
+                }
+                let turn = Turn {
+                    from: from.id(),
+                    to: to.id(),
+                    via,
+                    carto: &carto,
+                };
+                if let Some(penalty) = turn_cost(&turn) {
+                    graph.add_edge(
+                        NodeIndex::new(from.id().index()),
+                        NodeIndex::new(to.id().index()),
+                        TurnInfo {
+                            penalty,
+                            cost: to.weight().distance + penalty,
+                        },
+                    );
+                }
+            }
+        }
+
+        EdgeBasedCartograph { carto, graph }
+    }
+
+    /// Find the shortest path between two projected points, respecting the turn restrictions and
+    /// penalties. Use `carto.project()` to generate the points.
+    /// Returns `None` if the destination can't be reached with the allowed turns
+    pub fn shortest_path(&self, from: &ProjectedPoint, to: &ProjectedPoint) -> Option<GraphPath> {
+        let graph = &self.carto.graph;
+
+        // Both points are in the same edge, in the right order
+        if from.edge == to.edge && from.edge_pos <= to.edge_pos {
+            let distance = partial_distance(graph[from.edge].distance, to.edge_pos - from.edge_pos);
+            let duration = graph[from.edge].duration() * (to.edge_pos - from.edge_pos) as f64;
+            let mut points = vec![from.projected];
+            self.carto
+                .push_partial_points(from.edge, from.edge_pos, to.edge_pos, &mut points);
+            points.push(to.projected);
+            return Some(GraphPath::new(distance, duration, points).with_edges(vec![from.edge]));
+        }
+
+        // Reaching a node in the expanded graph means that the whole edge was traversed, so the
+        // heuristic is measured from the end of the edge to the end of the destination edge
+        let start_node = NodeIndex::new(from.edge.index());
+        let end_node = NodeIndex::new(to.edge.index());
+        let end_point = graph[graph.edge_endpoints(to.edge).unwrap().1];
+        let search = |start: NodeIndex| {
+            astar(
+                &self.graph,
+                start,
+                |node| node == end_node,
+                |edge_ref| edge_ref.weight().cost,
+                |node| {
+                    let target = graph.edge_endpoints(self.graph[node]).unwrap().1;
+                    graph[target].haversine_distance(&end_point) as u32
+                },
+            )
+        };
+        let (_, nodes) = if start_node == end_node {
+            // Going back on the same edge needs a loop: leave it by one of its turns and search the
+            // way back to it from there
+            self.graph
+                .edges(start_node)
+                .filter_map(|turn| {
+                    let (cost, nodes) = search(turn.target())?;
+                    let mut loop_nodes = Vec::with_capacity(nodes.len() + 1);
+                    loop_nodes.push(start_node);
+                    loop_nodes.extend(nodes);
+                    Some((turn.weight().cost + cost, loop_nodes))
+                })
+                .min_by_key(|(cost, _)| *cost)?
+        } else {
+            search(start_node)?
+        };
+
+        // Build final sequence of geo points: each inner edge contributes its shape and target
+        let mut points = Vec::with_capacity(nodes.len() + 1);
+        points.push(from.projected);
+        self.carto
+            .push_partial_points(from.edge, from.edge_pos, 1., &mut points);
+        points.push(graph[graph.edge_endpoints(from.edge).unwrap().1]);
+        for &node in &nodes[1..nodes.len() - 1] {
+            self.carto.push_edge_points(self.graph[node], &mut points);
+        }
+        self.carto
+            .push_partial_points(to.edge, 0., to.edge_pos, &mut points);
+        points.push(to.projected);
+
+        // The search cost also has the turn penalties, so the distance is the one of the partial
+        // edges of both points and of the whole inner edges, like the duration
+        let inner = &nodes[1..nodes.len() - 1];
+        let distance = partial_distance(graph[from.edge].distance, 1. - from.edge_pos)
+            + inner
+                .iter()
+                .map(|&node| graph[self.graph[node]].distance)
+                .sum::<u32>()
+            + partial_distance(graph[to.edge].distance, to.edge_pos);
+        let duration = self.carto.endpoints_duration(from, to)
+            + inner
+                .iter()
+                .map(|&node| graph[self.graph[node]].duration())
+                .sum::<f64>();
+
+        let edges = nodes.into_iter().map(|node| self.graph[node]).collect();
+        Some(GraphPath::new(distance, duration, points).with_edges(edges))
+    }
+}
+
+/// A turn cost function that forbids all U-turns, except at dead ends
+pub fn forbid_u_turns(turn: &Turn) -> Option<u32> {
+    if turn.is_u_turn() && turn.carto.graph.edges(turn.via).count() > 1 {
+        None
+    } else {
+        Some(0)
+    }
+}
@@ -0,0 +1,57 @@
+use std::io;
+use thiserror::Error;
+
+/// Why a Ptolemy file could not be loaded
+#[derive(Error, Debug)]
+pub enum CartographError {
+    /// The file could not be read, for example because it does not exist
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// The file does not start with the Ptolemy magic bytes
+    #[error("Not a Ptolemy file")]
+    BadMagic,
+    /// The file is in a version of the format that is not supported
+    #[error("Unsupported Ptolemy format version {0}, expected v2")]
+    UnsupportedVersion(String),
+    /// A column could not be decoded. `offset` is the position of its first byte in the file
+    #[error("Corrupt column {column} at byte {offset}")]
+    Corrupt { column: String, offset: u64 },
+    /// The file has no checksum section
+    #[error("The file has no checksum")]
+    MissingChecksum,
+    /// The stored checksum does not match the contents of the file
+    #[error(
+        "Checksum mismatch, expected {expected:08x} but the file says {found:08x}: the file is corrupted"
+    )]
+    ChecksumMismatch { expected: u32, found: u32 },
+}
+
+/// Classify an error that happened while decoding `column`, that started at `offset`. The errors of
+/// the underlying reader are kept, the others mean the data is invalid
+pub fn decoding_error(error: io::Error, column: &str, offset: u64) -> CartographError {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => {
+            CartographError::Corrupt {
+                column: column.to_owned(),
+                offset,
+            }
+        }
+        _ => CartographError::Io(error),
+    }
+}
+
+/// Allow the commands that return `io::Result` to report these errors, with `InvalidData` for any
+/// problem with the contents of the file
+impl From<CartographError> for io::Error {
+    fn from(error: CartographError) -> Self {
+        match error {
+            CartographError::Io(error) => error,
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+/// A search was stopped before it finished, because its `Deadline` passed or was cancelled
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("The search took too long and was stopped")]
+pub struct Timeout;
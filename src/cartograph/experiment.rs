@@ -0,0 +1,86 @@
+use super::data_types::EdgeInfo;
+use petgraph::graph::EdgeIndex;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Overrides of the cost of the edges, to compare a change of the cost model with the base one
+/// on the same cartography, like `{"road_levels": [1, 1, 1.2], "edges": {"4199": 3}}`
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Experiment {
+    /// Multiply the cost of the edges of each road level, starting from 0. The missing levels keep
+    /// their cost
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub road_levels: Vec<f64>,
+    /// Multiply the cost of single edges, by their index
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub edges: HashMap<u32, f64>,
+}
+
+impl Experiment {
+    /// The factor to apply to the distance of an edge. It is never less than 1, so that the
+    /// distance is still a valid lower bound of the cost
+    pub fn factor(&self, edge: EdgeIndex, info: &EdgeInfo) -> f64 {
+        let level_factor = self
+            .road_levels
+            .get(info.road_level as usize)
+            .copied()
+            .unwrap_or(1.);
+        let edge_factor = self
+            .edges
+            .get(&(edge.index() as u32))
+            .copied()
+            .unwrap_or(1.);
+        (level_factor * edge_factor).max(1.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartograph::{AccessClass, Restrictions, Smoothness, Surface};
+
+    #[test]
+    fn factor() {
+        let info = |road_level| EdgeInfo {
+            road_level,
+            distance: 100,
+            roundabout: false,
+            capacity: 1000,
+            lanes: 0,
+            width: 0.,
+            synthetic: false,
+            conditional: false,
+            reversible: false,
+            barrier: false,
+            cycleway: false,
+            bicycle_route: false,
+            bus_route: false,
+            border: false,
+            track_grade: 0,
+            surface: Surface::Unknown,
+            smoothness: Smoothness::Unknown,
+            access: AccessClass::Normal,
+            restrictions: Restrictions::default(),
+            way_id: 0,
+            name: 0,
+            shape: 0,
+        };
+        let mut experiment = Experiment {
+            road_levels: vec![1., 2., 0.5],
+            edges: HashMap::new(),
+        };
+        experiment.edges.insert(7, 3.);
+
+        assert_eq!(experiment.factor(EdgeIndex::new(0), &info(1)), 2.);
+        assert_eq!(experiment.factor(EdgeIndex::new(7), &info(1)), 6.);
+        assert_eq!(experiment.factor(EdgeIndex::new(0), &info(2)), 1.);
+        assert_eq!(experiment.factor(EdgeIndex::new(7), &info(2)), 1.5);
+        assert_eq!(experiment.factor(EdgeIndex::new(0), &info(5)), 1.);
+        assert_eq!(
+            Experiment::default().factor(EdgeIndex::new(7), &info(0)),
+            1.
+        );
+    }
+}
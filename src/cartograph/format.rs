@@ -0,0 +1,789 @@
+use super::access::AccessClass;
+use super::checksum::{self, ChecksumWriter};
+use super::data_types::EdgeInfo;
+use super::error::{decoding_error, CartographError};
+use super::metadata::Metadata;
+use super::names::RoadNames;
+use super::progress::CountingReader;
+use super::shapes::EdgeShapes;
+use super::stats::GraphStats;
+use super::style::RoadLevelStyle;
+use super::surface::{Smoothness, Surface};
+use super::vehicle::Restrictions;
+use crate::utils::GeoPoint;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use petgraph::graph::Graph;
+use petgraph::visit::EdgeRef;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// Every Ptolemy file starts with these bytes, followed by the version of the format
+const MAGIC_PREFIX: &[u8] = b"PTOLEMY-v";
+
+/// The first bytes of a gzip stream, that start the files in v1
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// The versions of the Ptolemy format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatVersion {
+    /// The whole file is a gzip stream of the header and the mandatory columns, without magic bytes
+    V1,
+    /// Each column is compressed on its own, followed by named extra columns and sections
+    V2,
+}
+
+impl FormatVersion {
+    /// The version that is written
+    pub const LATEST: FormatVersion = FormatVersion::V2;
+
+    /// The magic bytes that start the files in this version, since v2
+    fn magic(self) -> &'static [u8; 10] {
+        match self {
+            FormatVersion::V1 => unreachable!("v1 files have no magic bytes"),
+            FormatVersion::V2 => b"PTOLEMY-v2",
+        }
+    }
+
+    /// Choose the version to decode a file with from its first bytes
+    pub fn from_magic(magic: &[u8; 10]) -> Result<FormatVersion, CartographError> {
+        if magic.starts_with(GZIP_MAGIC) {
+            return Ok(FormatVersion::V1);
+        }
+        if !magic.starts_with(MAGIC_PREFIX) {
+            return Err(CartographError::BadMagic);
+        }
+        match magic {
+            magic if magic == FormatVersion::V2.magic() => Ok(FormatVersion::V2),
+            _ => {
+                let version = String::from_utf8_lossy(&magic[MAGIC_PREFIX.len() - 1..]);
+                Err(CartographError::UnsupportedVersion(version.into_owned()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatVersion::V1 => write!(f, "v1"),
+            FormatVersion::V2 => write!(f, "v2"),
+        }
+    }
+}
+
+/// The names of the columns with the OSM ids of the nodes. Like the way ids, they are split in
+/// their lowest and highest 32 bits
+pub const NODE_IDS_COLUMN: &str = "node_osm_ids";
+pub const NODE_IDS_HIGH_COLUMN: &str = "node_osm_ids_high";
+
+/// The name of the file section with the Web Mercator coordinates of the nodes
+pub const PROJECTED_NODES_SECTION: &str = "projected_nodes";
+
+/// An extra column of the edges, with how each edge is stored in it
+pub struct EdgeColumn {
+    pub name: &'static str,
+    pack: fn(&EdgeInfo) -> i32,
+    unpack: fn(&mut EdgeInfo, i32),
+    /// Whether the column is only written when some edge has a value other than 0
+    optional: bool,
+}
+
+impl EdgeColumn {
+    /// Set the attributes stored in this column to the edges, in the order of the file
+    pub fn unpack(&self, infos: &mut [EdgeInfo], values: Vec<i32>) {
+        for (info, value) in infos.iter_mut().zip(values) {
+            (self.unpack)(info, value);
+        }
+    }
+
+    /// Find a column by its name, or `None` if it is not about the edges
+    pub fn find(name: &str) -> Option<&'static EdgeColumn> {
+        EDGE_COLUMNS.iter().find(|column| column.name == name)
+    }
+}
+
+/// The extra columns of the edges, in the order they are written
+const EDGE_COLUMNS: [EdgeColumn; 10] = [
+    EdgeColumn {
+        name: "edge_flags",
+        pack: |info| {
+            info.roundabout as i32
+                | (info.synthetic as i32) << 1
+                | (info.conditional as i32) << 2
+                | (info.reversible as i32) << 3
+                | (info.barrier as i32) << 4
+                | (info.cycleway as i32) << 5
+                | (info.bicycle_route as i32) << 6
+                | (info.bus_route as i32) << 7
+                | (info.border as i32) << 8
+        },
+        unpack: |info, flags| {
+            info.roundabout = flags & 1 != 0;
+            info.synthetic = flags & 2 != 0;
+            info.conditional = flags & 4 != 0;
+            info.reversible = flags & 8 != 0;
+            info.barrier = flags & 16 != 0;
+            info.cycleway = flags & 32 != 0;
+            info.bicycle_route = flags & 64 != 0;
+            info.bus_route = flags & 128 != 0;
+            info.border = flags & 256 != 0;
+        },
+        optional: false,
+    },
+    EdgeColumn {
+        name: "edge_capacities",
+        pack: |info| info.capacity as i32,
+        unpack: |info, capacity| info.capacity = capacity as u32,
+        optional: false,
+    },
+    // The number of lanes (first byte) and the width in decimeters (second byte)
+    EdgeColumn {
+        name: "edge_lanes",
+        pack: |info| {
+            let width = (info.width * 10.).round().min(255.) as i32;
+            info.lanes as i32 | width << 8
+        },
+        unpack: |info, lanes| {
+            info.lanes = (lanes & 0xff) as u8;
+            info.width = (lanes >> 8 & 0xff) as f32 / 10.;
+        },
+        optional: false,
+    },
+    // The track grade (first byte), surface (second byte) and smoothness (third byte)
+    EdgeColumn {
+        name: "edge_surface",
+        pack: |info| {
+            info.track_grade as i32 | (info.surface as i32) << 8 | (info.smoothness as i32) << 16
+        },
+        unpack: |info, surface| {
+            info.track_grade = (surface & 0xff) as u8;
+            info.surface = Surface::from_u8((surface >> 8 & 0xff) as u8);
+            info.smoothness = Smoothness::from_u8((surface >> 16 & 0xff) as u8);
+        },
+        optional: false,
+    },
+    EdgeColumn {
+        name: "edge_access",
+        pack: |info| info.access as i32,
+        unpack: |info, access| info.access = AccessClass::from_u8(access as u8),
+        optional: false,
+    },
+    // The maximum weight in quarter tonnes (first byte), height (second byte) and width (third
+    // byte) in decimeters and whether heavy goods vehicles are forbidden (fourth byte)
+    EdgeColumn {
+        name: "edge_restrictions",
+        pack: |info| {
+            let restrictions = &info.restrictions;
+            pack_limit(restrictions.max_weight * 4.)
+                | pack_limit(restrictions.max_height * 10.) << 8
+                | pack_limit(restrictions.max_width * 10.) << 16
+                | (restrictions.no_hgv as i32) << 24
+        },
+        unpack: |info, packed| {
+            info.restrictions = Restrictions {
+                max_weight: (packed & 0xff) as f32 / 4.,
+                max_height: (packed >> 8 & 0xff) as f32 / 10.,
+                max_width: (packed >> 16 & 0xff) as f32 / 10.,
+                no_hgv: packed >> 24 & 1 != 0,
+            };
+        },
+        optional: true,
+    },
+    EdgeColumn {
+        name: "edge_way_ids",
+        pack: |info| info.way_id as i32,
+        unpack: |info, low| info.way_id = info.way_id & !0xffff_ffff | low as u32 as i64,
+        optional: true,
+    },
+    EdgeColumn {
+        name: "edge_way_ids_high",
+        pack: |info| (info.way_id >> 32) as i32,
+        unpack: |info, high| info.way_id = info.way_id & 0xffff_ffff | (high as i64) << 32,
+        optional: true,
+    },
+    EdgeColumn {
+        name: "edge_names",
+        pack: |info| info.name as i32,
+        unpack: |info, name| info.name = name as u32,
+        optional: true,
+    },
+    EdgeColumn {
+        name: "edge_shapes",
+        pack: |info| info.shape as i32,
+        unpack: |info, shape| info.shape = shape as u32,
+        optional: true,
+    },
+];
+
+/// Store a limit in a byte, never rounding a real limit to 0, which means that there is none
+fn pack_limit(limit: f32) -> i32 {
+    if limit > 0. {
+        limit.round().clamp(1., 255.) as i32
+    } else {
+        0
+    }
+}
+
+/// Write a Ptolemy file: the header, the columns, the sections and finally the checksum of all that
+pub struct ColumnWriter<W: Write> {
+    writer: ChecksumWriter<W>,
+}
+
+impl<W: Write> ColumnWriter<W> {
+    /// Write the header of the latest version of the format
+    pub fn new(writer: W, num_nodes: usize, num_edges: usize) -> io::Result<Self> {
+        let mut writer = ChecksumWriter::new(writer);
+        writer.write_all(FormatVersion::LATEST.magic())?;
+        writer.write_u32::<LittleEndian>(num_nodes as u32)?;
+        writer.write_u32::<LittleEndian>(num_edges as u32)?;
+        Ok(ColumnWriter { writer })
+    }
+
+    /// Write the mandatory columns, in order
+    pub fn write_columns(&mut self, columns: &[Vec<i32>]) -> io::Result<()> {
+        for column in compress_all(columns.iter().map(Vec::as_slice).collect()) {
+            self.writer.write_u64::<LittleEndian>(column.len() as u64)?;
+            self.writer.write_all(&column)?;
+        }
+        Ok(())
+    }
+
+    /// Write extra columns, prefixed by their names, so that readers can skip unknown ones
+    pub fn write_named_columns(&mut self, columns: &[(&str, Vec<i32>)]) -> io::Result<()> {
+        let values = columns
+            .iter()
+            .map(|(_, values)| values.as_slice())
+            .collect();
+        for ((name, _), column) in columns.iter().zip(compress_all(values)) {
+            self.writer.write_u8(name.len() as u8)?;
+            self.writer.write_all(name.as_bytes())?;
+            self.writer.write_u64::<LittleEndian>(column.len() as u64)?;
+            self.writer.write_all(&column)?;
+        }
+        Ok(())
+    }
+
+    /// Where to write the other sections, like the road levels
+    pub fn sections(&mut self) -> &mut ChecksumWriter<W> {
+        &mut self.writer
+    }
+
+    /// Write the checksum of everything before it and return the inner writer
+    pub fn finish(self) -> io::Result<W> {
+        self.writer.finish()
+    }
+}
+
+/// The file, after the magic bytes were read to choose the version
+type Sniffed<R> = io::Chain<io::Cursor<[u8; 10]>, R>;
+
+/// Where the columns are read from
+pub enum Source<R: Read> {
+    /// The file itself, since v2
+    File(CountingReader<Sniffed<R>>),
+    /// The gzip stream of the whole file, in v1
+    Gzip(GzDecoder<CountingReader<Sniffed<R>>>),
+}
+
+impl<R: Read> Source<R> {
+    /// The reader of the file, to count the bytes and compute the checksum
+    fn file(&self) -> &CountingReader<Sniffed<R>> {
+        match self {
+            Source::File(reader) => reader,
+            Source::Gzip(decoder) => decoder.get_ref(),
+        }
+    }
+}
+
+impl<R: Read> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::File(reader) => reader.read(buf),
+            Source::Gzip(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Read a Ptolemy file written by `ColumnWriter`, or by the older versions of the generator,
+/// keeping track of the position to report where the corrupted columns are
+pub struct ColumnReader<R: Read> {
+    source: Source<R>,
+    pub version: FormatVersion,
+    pub num_nodes: usize,
+    pub num_edges: usize,
+    /// The position and the checksum before the name of the current section
+    section_start: (u64, u32),
+}
+
+impl<R: Read> ColumnReader<R> {
+    /// Read the header, failing if the version is not supported
+    pub fn new(mut reader: R) -> Result<Self, CartographError> {
+        let mut magic = [0; 10];
+        match reader.read_exact(&mut magic) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(CartographError::BadMagic)
+            }
+            result => result?,
+        }
+        let version = FormatVersion::from_magic(&magic)?;
+
+        // Read the magic bytes again, so that they are counted and part of the checksum
+        let mut file = CountingReader::new(io::Cursor::new(magic).chain(reader));
+        let mut source = match version {
+            FormatVersion::V1 => Source::Gzip(GzDecoder::new(file)),
+            _ => {
+                file.read_exact(&mut magic)?;
+                Source::File(file)
+            }
+        };
+        let offset = source.file().bytes;
+        let mut read_size = || -> io::Result<(usize, usize)> {
+            let num_nodes = source.read_u32::<LittleEndian>()? as usize;
+            let num_edges = source.read_u32::<LittleEndian>()? as usize;
+            Ok((num_nodes, num_edges))
+        };
+        let (num_nodes, num_edges) =
+            read_size().map_err(|err| decoding_error(err, "header", offset))?;
+        Ok(ColumnReader {
+            source,
+            version,
+            num_nodes,
+            num_edges,
+            section_start: (0, 0),
+        })
+    }
+
+    /// How many bytes of the file were read so far
+    pub fn bytes(&self) -> u64 {
+        self.source.file().bytes
+    }
+
+    /// Read a column of `len` values. `name` is only used to report errors
+    pub fn read_column(&mut self, len: usize, name: &str) -> Result<Vec<i32>, CartographError> {
+        let offset = self.bytes();
+        let values = match self.version {
+            FormatVersion::V1 => read_deltas(&mut self.source, len),
+            _ => decompress(&mut self.source, len),
+        };
+        values.map_err(|err| decoding_error(err, name, offset))
+    }
+
+    /// Read the name that starts an extra column or a section, or `None` at the end of the file
+    pub fn read_section_name(&mut self) -> Result<Option<String>, CartographError> {
+        // The mandatory columns were all there was before v2
+        if self.version == FormatVersion::V1 {
+            return Ok(None);
+        }
+        self.section_start = (self.bytes(), self.source.file().checksum());
+        let offset = self.bytes();
+        let reader = &mut self.source;
+        let mut read_name = || -> io::Result<Option<String>> {
+            let len = match reader.read_u8() {
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            let mut name = vec![0; len as usize];
+            reader.read_exact(&mut name)?;
+            String::from_utf8(name)
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        };
+        read_name().map_err(|err| decoding_error(err, "section name", offset))
+    }
+
+    /// Read the contents of the current section with `read`
+    pub fn read_section<T>(
+        &mut self,
+        name: &str,
+        read: impl FnOnce(&mut Source<R>) -> io::Result<T>,
+    ) -> Result<T, CartographError> {
+        read(&mut self.source).map_err(|err| decoding_error(err, name, self.section_start.0))
+    }
+
+    /// Check the checksum section against the checksum of all the bytes before it
+    pub fn verify_checksum(&mut self) -> Result<(), CartographError> {
+        let (offset, expected) = self.section_start;
+        checksum::verify_section(&mut self.source, expected).map_err(|err| match err {
+            CartographError::Io(err) => decoding_error(err, checksum::CHECKSUM_SECTION, offset),
+            err => err,
+        })
+    }
+
+    /// Skip the contents of a section that is not known
+    pub fn skip_section(&mut self, name: &str) -> Result<(), CartographError> {
+        self.read_section(name, |reader| {
+            let size = reader.read_u64::<LittleEndian>()?;
+            let skipped = io::copy(&mut reader.by_ref().take(size), &mut io::sink())?;
+            if skipped < size {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Write the Web Mercator coordinates of the points as a file section, as pairs of `f64`. They are not
+/// compressed, so that reading them is faster than projecting the points again
+pub fn write_projected_nodes<W: Write>(
+    writer: &mut W,
+    points: impl ExactSizeIterator<Item = GeoPoint>,
+) -> io::Result<()> {
+    writer.write_u8(PROJECTED_NODES_SECTION.len() as u8)?;
+    writer.write_all(PROJECTED_NODES_SECTION.as_bytes())?;
+    writer.write_u64::<LittleEndian>(points.len() as u64 * 16)?;
+    for point in points {
+        let [x, y] = point.web_mercator_project();
+        writer.write_f64::<LittleEndian>(x)?;
+        writer.write_f64::<LittleEndian>(y)?;
+    }
+    Ok(())
+}
+
+/// Read the contents of the section, after its name, failing unless it has `num_nodes` points
+pub fn read_projected_nodes<R: Read>(
+    reader: &mut R,
+    num_nodes: usize,
+) -> io::Result<Vec<[f64; 2]>> {
+    let size = reader.read_u64::<LittleEndian>()?;
+    if size != num_nodes as u64 * 16 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the projected nodes do not match the nodes",
+        ));
+    }
+    let mut payload = vec![0; size as usize];
+    reader.read_exact(&mut payload)?;
+    Ok(payload
+        .chunks_exact(16)
+        .map(|mut chunk| {
+            let x = chunk.read_f64::<LittleEndian>().unwrap();
+            let y = chunk.read_f64::<LittleEndian>().unwrap();
+            [x, y]
+        })
+        .collect())
+}
+
+/// Write a graph in the Ptolemy format, with the classification of its road levels, the names of
+/// its roads, the shapes of its edges and its metadata (unless empty), its statistics and the
+/// checksum. The nodes are sorted by their coordinates and the edges by their endpoints, so
+/// the indexes are not kept. The OSM ids of the nodes, indexed like them, are only written when
+/// given and not all 0. The missing ones are written as 0. With `projected_nodes`, the Web Mercator
+/// coordinates of the nodes are stored too, so that opening the file does not compute them
+#[allow(clippy::too_many_arguments)]
+pub fn write_graph<W: Write, N, E>(
+    writer: W,
+    graph: &Graph<N, E>,
+    point: impl Fn(&N) -> GeoPoint,
+    info: impl Fn(&E) -> EdgeInfo,
+    node_ids: Option<&[i64]>,
+    road_levels: &[RoadLevelStyle],
+    road_names: &RoadNames,
+    edge_shapes: &EdgeShapes,
+    metadata: &Metadata,
+    projected_nodes: bool,
+) -> io::Result<()> {
+    let mut writer = ColumnWriter::new(writer, graph.node_count(), graph.edge_count())?;
+
+    // Extract nodes and sort by (lat, lon)
+    // This code uses delta encoding, so we use i32 instead of u32, even though
+    // the original data is guaranteed to be non-negative
+    struct Node {
+        index: i32,
+        lat: i32,
+        lon: i32,
+    }
+    let mut nodes: Vec<Node> = graph
+        .node_indices()
+        .map(|index| {
+            let point = point(&graph[index]);
+            Node {
+                index: index.index() as i32,
+                lat: point.lat.as_micro_degrees(),
+                lon: point.lon.as_micro_degrees(),
+            }
+        })
+        .collect();
+    nodes.sort_by_key(|node| (node.lat, node.lon));
+
+    // Extract remap of node indexes:
+    // node_index_map[old_index] = new_index
+    let mut node_index_map = vec![i32::MAX; graph.node_count()];
+    for (i, node) in nodes.iter().enumerate() {
+        node_index_map[node.index as usize] = i as i32;
+    }
+
+    // Extract edges and sort by (source, target)
+    let mut edges: Vec<(i32, i32, EdgeInfo)> = graph
+        .edge_references()
+        .map(|edge| {
+            (
+                node_index_map[edge.source().index()],
+                node_index_map[edge.target().index()],
+                info(edge.weight()),
+            )
+        })
+        .collect();
+    edges.sort_by_key(|edge| (edge.0, edge.1));
+
+    writer.write_columns(&[
+        nodes.iter().map(|node| node.lat).collect(),
+        nodes.iter().map(|node| node.lon).collect(),
+        edges.iter().map(|edge| edge.0).collect(),
+        edges.iter().map(|edge| edge.1).collect(),
+        edges.iter().map(|edge| edge.2.distance as i32).collect(),
+        edges.iter().map(|edge| edge.2.road_level as i32).collect(),
+    ])?;
+    let mut extra_columns: Vec<_> = EDGE_COLUMNS
+        .iter()
+        .map(|column| {
+            let values: Vec<_> = edges.iter().map(|edge| (column.pack)(&edge.2)).collect();
+            (column, values)
+        })
+        .filter(|(column, values)| !column.optional || values.iter().any(|&value| value != 0))
+        .map(|(column, values)| (column.name, values))
+        .collect();
+    if let Some(node_ids) = node_ids.filter(|ids| ids.iter().any(|&id| id != 0)) {
+        let (low, high) = nodes
+            .iter()
+            .map(|node| {
+                let id = node_ids.get(node.index as usize).copied().unwrap_or(0);
+                (id as i32, (id >> 32) as i32)
+            })
+            .unzip();
+        extra_columns.push((NODE_IDS_COLUMN, low));
+        extra_columns.push((NODE_IDS_HIGH_COLUMN, high));
+    }
+    writer.write_named_columns(&extra_columns)?;
+
+    RoadLevelStyle::write_section(writer.sections(), road_levels)?;
+    if !road_names.is_empty() {
+        road_names.write_section(writer.sections())?;
+    }
+    if !edge_shapes.is_empty() {
+        edge_shapes.write_section(writer.sections())?;
+    }
+    if !metadata.is_empty() {
+        metadata.write_section(writer.sections())?;
+    }
+    if projected_nodes {
+        // Projected from the stored coordinates, to be exactly what opening the file would compute
+        let points = nodes
+            .iter()
+            .map(|node| GeoPoint::from_micro_degrees(node.lat, node.lon));
+        write_projected_nodes(writer.sections(), points)?;
+    }
+
+    // Statistics go at the end, so that they can be read from the end of the file
+    let stats = GraphStats::compute(graph, point, |edge| {
+        let info = info(edge);
+        (info.road_level, info.distance)
+    });
+    stats.write_section(writer.sections())?;
+
+    // Followed only by the checksum of everything before it
+    writer.finish()?.flush()
+}
+
+/// Compress all columns in parallel
+#[cfg(not(target_arch = "wasm32"))]
+fn compress_all(columns: Vec<&[i32]>) -> Vec<Vec<u8>> {
+    crossbeam::scope(|scope| {
+        let threads: Vec<_> = columns
+            .into_iter()
+            .map(|column| scope.spawn(move |_| compress(column)))
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect()
+    })
+    .unwrap()
+}
+
+/// Compress all columns, one after the other since there are no threads
+#[cfg(target_arch = "wasm32")]
+fn compress_all(columns: Vec<&[i32]>) -> Vec<Vec<u8>> {
+    columns.into_iter().map(compress).collect()
+}
+
+/// Compress a column of i32 using delta encoding + gzip
+fn compress(values: &[i32]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let mut prev = 0;
+    for &value in values {
+        encoder
+            .write_i32::<LittleEndian>(value.wrapping_sub(prev))
+            .unwrap();
+        prev = value;
+    }
+    encoder.finish().unwrap()
+}
+
+/// Read a column written by `compress()`, prefixed by its size
+#[inline(never)]
+fn decompress<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<i32>> {
+    let size = reader.read_u64::<LittleEndian>()?;
+    let mut decoder = GzDecoder::new(reader.take(size));
+    let values = read_deltas(&mut decoder, len)?;
+
+    // Skip what was not consumed by the decoder
+    io::copy(&mut decoder.into_inner(), &mut io::sink())?;
+    Ok(values)
+}
+
+/// Read `len` delta-encoded values
+fn read_deltas<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<i32>> {
+    let mut values = Vec::with_capacity(len);
+    let mut prev = 0;
+    for _ in 0..len {
+        prev = reader.read_i32::<LittleEndian>()?.wrapping_add(prev);
+        values.push(prev);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn columns_round_trip() {
+        let columns = vec![
+            vec![3, -7, 1_000_000, i32::MIN, 0],
+            vec![],
+            vec![i32::MAX, 5],
+        ];
+        let mut writer = ColumnWriter::new(Vec::new(), 5, 2).unwrap();
+        writer.write_columns(&columns).unwrap();
+        writer
+            .write_named_columns(&[("unknown", vec![1, 2]), ("edge_flags", vec![5, 26])])
+            .unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = ColumnReader::new(bytes.as_slice()).unwrap();
+        assert_eq!((reader.num_nodes, reader.num_edges), (5, 2));
+        for column in &columns {
+            assert_eq!(&reader.read_column(column.len(), "test").unwrap(), column);
+        }
+
+        // Unknown columns are skipped, the known ones are unpacked
+        assert_eq!(reader.read_section_name().unwrap().unwrap(), "unknown");
+        reader.skip_section("unknown").unwrap();
+        let name = reader.read_section_name().unwrap().unwrap();
+        let column = EdgeColumn::find(&name).unwrap();
+        let mut infos = vec![EdgeInfo::new(10, 5); 2];
+        column.unpack(&mut infos, reader.read_column(2, &name).unwrap());
+        assert!(infos[0].roundabout && infos[0].conditional && !infos[0].synthetic);
+        assert!(infos[1].synthetic && infos[1].reversible && !infos[1].roundabout);
+        assert!(infos[1].barrier && !infos[0].barrier);
+
+        assert_eq!(
+            reader.read_section_name().unwrap().unwrap(),
+            checksum::CHECKSUM_SECTION
+        );
+        reader.verify_checksum().unwrap();
+        assert_eq!(reader.read_section_name().unwrap(), None);
+    }
+
+    #[test]
+    fn edge_columns_round_trip() {
+        let mut info = EdgeInfo::new(10, 2);
+        info.roundabout = true;
+        info.cycleway = true;
+        info.bus_route = true;
+        info.border = true;
+        info.shape = 3;
+        info.lanes = 3;
+        info.width = 7.5;
+        info.track_grade = 2;
+        info.surface = Surface::Gravel;
+        info.smoothness = Smoothness::Bad;
+        info.access = AccessClass::Motorroad;
+        info.restrictions = Restrictions {
+            max_weight: 7.5,
+            max_height: 4.2,
+            max_width: 2.3,
+            no_hgv: true,
+        };
+
+        info.capacity = 1234;
+        let mut unpacked = EdgeInfo::new(10, 2);
+        for column in &EDGE_COLUMNS {
+            column.unpack(
+                std::slice::from_mut(&mut unpacked),
+                vec![(column.pack)(&info)],
+            );
+            assert_eq!(EdgeColumn::find(column.name).unwrap().name, column.name);
+        }
+        assert_eq!(unpacked, info);
+    }
+
+    #[test]
+    fn projected_nodes_round_trip() {
+        let points = [
+            GeoPoint::from_degrees(42.5, 1.5),
+            GeoPoint::from_degrees(-33.9, 151.2),
+        ];
+        let mut bytes = Vec::new();
+        write_projected_nodes(&mut bytes, points.iter().copied()).unwrap();
+        let mut reader = &bytes[1 + PROJECTED_NODES_SECTION.len()..];
+        let projected = read_projected_nodes(&mut reader, 2).unwrap();
+        assert_eq!(projected[0], points[0].web_mercator_project());
+        assert_eq!(projected[1], points[1].web_mercator_project());
+        assert!(reader.is_empty());
+
+        // A section of another graph, or cut short
+        let mut reader = &bytes[1 + PROJECTED_NODES_SECTION.len()..];
+        assert!(read_projected_nodes(&mut reader, 3).is_err());
+        let mut reader = &bytes[1 + PROJECTED_NODES_SECTION.len()..bytes.len() - 1];
+        assert!(read_projected_nodes(&mut reader, 2).is_err());
+    }
+
+    #[test]
+    fn versions() {
+        let bytes = ColumnWriter::new(Vec::new(), 0, 0)
+            .unwrap()
+            .finish()
+            .unwrap();
+        let reader = ColumnReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.version, FormatVersion::LATEST);
+
+        let error = ColumnReader::new(&b"PTOLEMY-v3\0\0"[..]).err().unwrap();
+        assert!(matches!(error, CartographError::UnsupportedVersion(version) if version == "v3"));
+        let error = ColumnReader::new(&b"PTOLEMY"[..]).err().unwrap();
+        assert!(matches!(error, CartographError::BadMagic));
+        let error = ColumnReader::new(&b"<?xml version"[..]).err().unwrap();
+        assert!(matches!(error, CartographError::BadMagic));
+    }
+
+    #[test]
+    fn gzipped_v1() {
+        // The header and the delta-encoded columns, all in a single gzip stream
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        for &value in &[2, 1, 1, 1, 5, -1, 0, 1, 10, 2] {
+            encoder.write_i32::<LittleEndian>(value).unwrap();
+        }
+        let bytes = encoder.finish().unwrap();
+
+        let mut reader = ColumnReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.version, FormatVersion::V1);
+        assert_eq!((reader.num_nodes, reader.num_edges), (2, 1));
+        assert_eq!(reader.read_column(2, "latitudes").unwrap(), vec![1, 2]);
+        assert_eq!(reader.read_column(2, "longitudes").unwrap(), vec![5, 4]);
+        assert_eq!(reader.read_column(1, "sources").unwrap(), vec![0]);
+        assert_eq!(reader.read_column(1, "targets").unwrap(), vec![1]);
+        assert_eq!(reader.read_column(1, "distances").unwrap(), vec![10]);
+        assert_eq!(reader.read_column(1, "road_levels").unwrap(), vec![2]);
+        assert_eq!(reader.read_section_name().unwrap(), None);
+        assert_eq!(reader.bytes(), bytes.len() as u64);
+
+        // Columns longer than the stream
+        let mut reader = ColumnReader::new(bytes.as_slice()).unwrap();
+        let error = reader.read_column(20, "latitudes").err().unwrap();
+        assert!(matches!(error, CartographError::Corrupt { column, .. } if column == "latitudes"));
+    }
+}
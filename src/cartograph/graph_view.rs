@@ -0,0 +1,55 @@
+use super::data_types::EdgeInfo;
+use crate::utils::GeoPoint;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use petgraph::Graph;
+
+/// A node of the graph, as returned by `Cartograph::nodes()`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GraphNode<'a> {
+    pub index: NodeIndex,
+    pub point: &'a GeoPoint,
+}
+
+/// A directed edge of the graph with its endpoints, as returned by `Cartograph::edges()` and the
+/// other introspection methods
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GraphEdge<'a> {
+    pub index: EdgeIndex,
+    pub source: NodeIndex,
+    pub target: NodeIndex,
+    pub info: &'a EdgeInfo,
+    /// The points of the source and target nodes
+    pub from: &'a GeoPoint,
+    pub to: &'a GeoPoint,
+}
+
+impl<'a> GraphEdge<'a> {
+    /// Describe an edge of the graph. Panics if it does not exist
+    pub fn new(graph: &'a Graph<GeoPoint, EdgeInfo>, index: EdgeIndex) -> Self {
+        let (source, target) = graph.edge_endpoints(index).unwrap();
+        GraphEdge {
+            index,
+            source,
+            target,
+            info: &graph[index],
+            from: &graph[source],
+            to: &graph[target],
+        }
+    }
+
+    /// Describe an edge from a reference returned by petgraph
+    pub(super) fn from_ref(
+        graph: &'a Graph<GeoPoint, EdgeInfo>,
+        edge: petgraph::graph::EdgeReference<'a, EdgeInfo>,
+    ) -> Self {
+        GraphEdge {
+            index: edge.id(),
+            source: edge.source(),
+            target: edge.target(),
+            info: edge.weight(),
+            from: &graph[edge.source()],
+            to: &graph[edge.target()],
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use super::cost::DistanceCost;
+use super::data_types::*;
+use super::virtual_nodes;
+use super::Cartograph;
+use crate::utils::GeoPoint;
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+
+/// The radius of the Earth used by `GeoPoint::haversine_distance()`, in meters
+const EARTH_RADIUS: f64 = 6_371_000.;
+
+/// The costs to reach the cells of a regular grid around a point, to plot them as a raster or as
+/// contour lines
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostGrid {
+    /// The center of the south-west cell
+    pub origin: GeoPoint,
+    /// The size of the cells, in degrees of latitude and of longitude. Both are about the requested
+    /// size in meters around the center
+    pub lat_step: f64,
+    pub lon_step: f64,
+    /// The distance in meters to reach the closest road in each cell, by rows from south to north
+    /// and columns from west to east. `None` when the cell has no road reachable within the
+    /// maximum cost
+    pub costs: Vec<Vec<Option<u32>>>,
+}
+
+impl CostGrid {
+    /// The center of a cell
+    pub fn cell_center(&self, row: usize, col: usize) -> GeoPoint {
+        GeoPoint::from_degrees(
+            self.origin.lat.as_degrees() + row as f64 * self.lat_step,
+            self.origin.lon.as_degrees() + col as f64 * self.lon_step,
+        )
+    }
+
+    /// The row and column of the cell with a point, if it is inside the grid
+    pub fn cell(&self, point: &GeoPoint) -> Option<(usize, usize)> {
+        let row = ((point.lat.as_degrees() - self.origin.lat.as_degrees()) / self.lat_step).round();
+        let col = ((point.lon.as_degrees() - self.origin.lon.as_degrees()) / self.lon_step).round();
+        let size = self.costs.len() as f64;
+        if row < 0. || col < 0. || row >= size || col >= size {
+            return None;
+        }
+        Some((row as usize, col as usize))
+    }
+
+    /// Lower the costs of the cells crossed by a straight road, whose cost grows linearly from one
+    /// end to the other. It is sampled at least twice per cell
+    fn add_line(&mut self, from: &GeoPoint, to: &GeoPoint, costs: (f64, f64), max_cost: u32) {
+        let cell_meters = self.lat_step.to_radians() * EARTH_RADIUS;
+        let steps = (2. * from.haversine_distance(to) / cell_meters)
+            .ceil()
+            .max(1.) as usize;
+        let (lat1, lon1) = (from.lat.as_degrees(), from.lon.as_degrees());
+        let (lat2, lon2) = (to.lat.as_degrees(), to.lon.as_degrees());
+        for i in 0..=steps {
+            let t = i as f64 / steps as f64;
+            let cost = costs.0 + t * (costs.1 - costs.0);
+            if cost > max_cost as f64 {
+                continue;
+            }
+            let point = GeoPoint::from_degrees(lat1 + t * (lat2 - lat1), lon1 + t * (lon2 - lon1));
+            if let Some((row, col)) = self.cell(&point) {
+                let cell = &mut self.costs[row][col];
+                let cost = cost as u32;
+                if cell.is_none_or(|old| cost < old) {
+                    *cell = Some(cost);
+                }
+            }
+        }
+    }
+}
+
+/// Compute the distance from a point to every road up to `max_cost` meters away and keep the
+/// smallest one in each cell of a grid of `cell_meters` centered on the point. Since a road can not
+/// be closer by the network than in a straight line, the grid extends `max_cost` meters in each
+/// direction. Panics if the cells are not positive
+pub fn cost_grid(
+    carto: &Cartograph,
+    center: &ProjectedPoint,
+    cell_meters: f64,
+    max_cost: u32,
+) -> CostGrid {
+    assert!(cell_meters > 0., "The cells must have a positive size");
+    let graph = &carto.graph;
+    let half = (max_cost as f64 / cell_meters).ceil() as usize;
+    let size = 2 * half + 1;
+    let lat_step = (cell_meters / EARTH_RADIUS).to_degrees();
+    let lon_step = lat_step / center.projected.lat.as_radians().cos();
+    let mut grid = CostGrid {
+        origin: GeoPoint::from_degrees(
+            center.projected.lat.as_degrees() - half as f64 * lat_step,
+            center.projected.lon.as_degrees() - half as f64 * lon_step,
+        ),
+        lat_step,
+        lon_step,
+        costs: vec![vec![None; size]; size],
+    };
+
+    // Leave the point forwards or, on two-way roads, backwards
+    let mut scores: HashMap<NodeIndex, u32> = HashMap::new();
+    let mut visit_next = BinaryHeap::new();
+    for start in virtual_nodes::endpoints(carto, center, &DistanceCost) {
+        let node = graph.edge_endpoints(start.edge).unwrap().1;
+        let score = partial_distance(graph[start.edge].distance, 1. - start.edge_pos);
+        grid.add_line(
+            &center.projected,
+            &graph[node],
+            (0., score as f64),
+            max_cost,
+        );
+        if score <= max_cost && scores.get(&node).is_none_or(|&old| score < old) {
+            scores.insert(node, score);
+            visit_next.push(Reverse((score, node)));
+        }
+    }
+
+    while let Some(Reverse((score, node))) = visit_next.pop() {
+        if score > scores[&node] {
+            continue;
+        }
+
+        for edge in graph.edges(node) {
+            if !carto.is_routable(edge.weight()) {
+                continue;
+            }
+            let next = edge.target();
+            let next_score = score + edge.weight().distance;
+            grid.add_line(
+                &graph[node],
+                &graph[next],
+                (score as f64, next_score as f64),
+                max_cost,
+            );
+            if next_score > max_cost {
+                continue;
+            }
+            match scores.entry(next) {
+                Entry::Occupied(entry) if *entry.get() <= next_score => continue,
+                Entry::Occupied(mut entry) => {
+                    entry.insert(next_score);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(next_score);
+                }
+            }
+            visit_next.push(Reverse((next_score, next)));
+        }
+    }
+
+    grid
+}
@@ -0,0 +1,169 @@
+use super::{Cartograph, CartographError, ProjectedPoint};
+use crate::utils::GeoPoint;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+/// The first bytes of a hub table file
+const MAGIC: &[u8; 15] = b"PTOLEMY-HUBS-v1";
+
+/// The distances between all pairs of a fixed set of points, like the stores of a retailer, computed
+/// once and stored in a sidecar file of the cartography. The queries between these points can then
+/// be answered without searching
+#[derive(Clone, Debug, PartialEq)]
+pub struct HubTable {
+    /// The checksum of the cartography file that was used, to detect a stale table
+    pub checksum: u32,
+    hubs: Vec<GeoPoint>,
+    /// The index of each hub, by its coordinates in micro degrees
+    index: HashMap<(i32, i32), usize>,
+    /// Row-major, from each hub to each hub, in meters
+    distances: Vec<u32>,
+}
+
+impl HubTable {
+    /// Compute the distances between all pairs of hubs, like `Cartograph::distance_table()`
+    pub fn compute(carto: &Cartograph, hubs: Vec<GeoPoint>, checksum: u32) -> HubTable {
+        let projected: Vec<ProjectedPoint> = hubs.iter().map(|hub| carto.project(hub)).collect();
+        let distances = carto
+            .distance_table(&projected, &projected)
+            .into_iter()
+            .flatten()
+            .collect();
+        HubTable::new(hubs, distances, checksum)
+    }
+
+    fn new(hubs: Vec<GeoPoint>, distances: Vec<u32>, checksum: u32) -> HubTable {
+        let index = hubs
+            .iter()
+            .enumerate()
+            .map(|(i, hub)| (key(hub), i))
+            .collect();
+        HubTable {
+            checksum,
+            hubs,
+            index,
+            distances,
+        }
+    }
+
+    pub fn hubs(&self) -> &[GeoPoint] {
+        &self.hubs
+    }
+
+    /// The index of the hub at exactly these coordinates
+    pub fn hub(&self, point: &GeoPoint) -> Option<usize> {
+        self.index.get(&key(point)).copied()
+    }
+
+    /// The distance in meters between two hubs, given by their indexes
+    pub fn distance(&self, from: usize, to: usize) -> u32 {
+        self.distances[from * self.hubs.len() + to]
+    }
+
+    /// The distances from each source to each target, if all of them are hubs
+    pub fn lookup(&self, sources: &[GeoPoint], targets: &[GeoPoint]) -> Option<Vec<Vec<u32>>> {
+        let sources = sources
+            .iter()
+            .map(|point| self.hub(point))
+            .collect::<Option<Vec<_>>>()?;
+        let targets = targets
+            .iter()
+            .map(|point| self.hub(point))
+            .collect::<Option<Vec<_>>>()?;
+        Some(
+            sources
+                .iter()
+                .map(|&from| targets.iter().map(|&to| self.distance(from, to)).collect())
+                .collect(),
+        )
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(self.checksum)?;
+        writer.write_u32::<LittleEndian>(self.hubs.len() as u32)?;
+        for hub in &self.hubs {
+            writer.write_i32::<LittleEndian>(hub.lat.as_micro_degrees())?;
+            writer.write_i32::<LittleEndian>(hub.lon.as_micro_degrees())?;
+        }
+        for &distance in &self.distances {
+            writer.write_u32::<LittleEndian>(distance)?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> Result<HubTable, CartographError> {
+        let mut magic = [0; 15];
+        match reader.read_exact(&mut magic) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(CartographError::BadMagic)
+            }
+            result => result?,
+        }
+        if &magic != MAGIC {
+            return Err(CartographError::BadMagic);
+        }
+
+        let mut read = || -> io::Result<HubTable> {
+            let checksum = reader.read_u32::<LittleEndian>()?;
+            let num_hubs = reader.read_u32::<LittleEndian>()? as usize;
+            let mut hubs = Vec::with_capacity(num_hubs);
+            for _ in 0..num_hubs {
+                let lat = reader.read_i32::<LittleEndian>()?;
+                let lon = reader.read_i32::<LittleEndian>()?;
+                hubs.push(GeoPoint::from_micro_degrees(lat, lon));
+            }
+            let mut distances = Vec::with_capacity(num_hubs * num_hubs);
+            for _ in 0..num_hubs * num_hubs {
+                distances.push(reader.read_u32::<LittleEndian>()?);
+            }
+            Ok(HubTable::new(hubs, distances, checksum))
+        };
+        read().map_err(|err| super::error::decoding_error(err, "hub table", MAGIC.len() as u64))
+    }
+}
+
+fn key(point: &GeoPoint) -> (i32, i32) {
+    (point.lat.as_micro_degrees(), point.lon.as_micro_degrees())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hub_table() {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let hubs = vec![
+            GeoPoint::from_degrees(42.553210, 1.588908),
+            GeoPoint::from_degrees(42.564440, 1.685042),
+            GeoPoint::from_degrees(42.507, 1.521),
+        ];
+        let table = HubTable::compute(&carto, hubs.clone(), 42);
+        assert_eq!(table.hub(&hubs[1]), Some(1));
+        assert_eq!(table.hub(&GeoPoint::from_degrees(42.5, 1.5)), None);
+
+        // The same as a live search
+        let projected: Vec<_> = hubs.iter().map(|hub| carto.project(hub)).collect();
+        let live = carto.distance_table(&projected[..2], &projected[1..]);
+        assert_eq!(table.lookup(&hubs[..2], &hubs[1..]), Some(live));
+        assert!(table.distance(0, 1) > 0);
+        assert_eq!(
+            table.lookup(&hubs, &[GeoPoint::from_degrees(42.5, 1.5)]),
+            None
+        );
+
+        let mut bytes = Vec::new();
+        table.write(&mut bytes).unwrap();
+        assert_eq!(HubTable::read(&mut &bytes[..]).unwrap(), table);
+        assert!(matches!(
+            HubTable::read(&mut &bytes[..bytes.len() - 1]),
+            Err(CartographError::Corrupt { .. })
+        ));
+        assert!(matches!(
+            HubTable::read(&mut &b"PTOLEMY-v2"[..]),
+            Err(CartographError::BadMagic)
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+use super::data_types::LineWithData;
+use petgraph::graph::EdgeIndex;
+use rstar::{PointDistance, RTree, RTreeNum, RTreeObject, AABB};
+use std::collections::HashSet;
+use std::mem::size_of;
+
+/// How to build the spatial index of the edges when loading a cartography. The default is a single
+/// tree in double precision, which is the fastest to query
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IndexConfig {
+    /// Store the lines in single precision, which takes about half the memory. The index is only
+    /// used to find the candidate edges, the projections are still computed in double precision
+    pub f32_envelopes: bool,
+    /// Split the edges in vertical strips, one tree per thread, built in parallel. Loading is faster
+    /// with many cores, but each query searches all the trees
+    pub parallel: bool,
+}
+
+/// A type of the coordinates of the lines in the index
+trait Scalar: RTreeNum {
+    /// The largest error of a distance computed in this type, in web mercator meters
+    const ERROR: f64;
+
+    fn from_f64(value: f64) -> Self;
+
+    fn to_f64(self) -> f64;
+}
+
+impl Scalar for f64 {
+    const ERROR: f64 = 0.;
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Scalar for f32 {
+    /// The coordinates go up to 2e7 meters, where the resolution of f32 is 2 meters. Rounding the
+    /// query point and both ends of a line moves their distance by less than 3 meters
+    const ERROR: f64 = 3.;
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+type Line<S> = LineWithData<EdgeIndex, [S; 2]>;
+
+fn convert<S: Scalar>(xy: [f64; 2]) -> [S; 2] {
+    [S::from_f64(xy[0]), S::from_f64(xy[1])]
+}
+
+/// The spatial index of the edges, by their web mercator coordinates. A contracted edge has a line
+/// for each segment of its shape
+pub struct EdgeRTree {
+    /// Only one of them has trees, depending on the precision. There is a single tree, unless it
+    /// was built in parallel
+    exact: Vec<RTree<Line<f64>>>,
+    compact: Vec<RTree<Line<f32>>>,
+}
+
+impl EdgeRTree {
+    pub fn build(lines: Vec<Line<f64>>, config: &IndexConfig) -> Self {
+        if config.f32_envelopes {
+            let lines = lines
+                .into_iter()
+                .map(|line| {
+                    let (from, to) = line.endpoints();
+                    LineWithData::new(line.data, convert(from), convert(to))
+                })
+                .collect();
+            EdgeRTree {
+                exact: Vec::new(),
+                compact: bulk_load(lines, config.parallel),
+            }
+        } else {
+            EdgeRTree {
+                exact: bulk_load(lines, config.parallel),
+                compact: Vec::new(),
+            }
+        }
+    }
+
+    /// The number of lines in the index, which is the number of edges when none has a shape
+    pub fn size(&self) -> usize {
+        let exact: usize = self.exact.iter().map(RTree::size).sum();
+        let compact: usize = self.compact.iter().map(RTree::size).sum();
+        exact + compact
+    }
+
+    /// An estimate of the memory used by the lines, in bytes
+    pub fn memory_usage(&self) -> usize {
+        let exact: usize = self.exact.iter().map(RTree::size).sum();
+        let compact: usize = self.compact.iter().map(RTree::size).sum();
+        exact * size_of::<Line<f64>>() + compact * size_of::<Line<f32>>()
+    }
+
+    /// The largest error of the distances returned by `nearest_iter()`
+    pub fn error(&self) -> f64 {
+        if self.compact.is_empty() {
+            f64::ERROR
+        } else {
+            f32::ERROR
+        }
+    }
+
+    /// The closest edge, when it can be found exactly without sorting the candidates again
+    pub fn exact_nearest(&self, xy: [f64; 2]) -> Option<EdgeIndex> {
+        match self.exact.as_slice() {
+            [tree] => tree.nearest_neighbor(&xy).map(|line| line.data),
+            _ => None,
+        }
+    }
+
+    /// The edges sorted by their distance to a point, in web mercator meters. The distances are up
+    /// to `error()` meters wrong, so the order may be slightly different from the exact one. A
+    /// contracted edge comes again for each segment of its shape, the first time at the distance of
+    /// the closest one
+    pub fn nearest_iter<'a>(&'a self, xy: [f64; 2]) -> impl Iterator<Item = (EdgeIndex, f64)> + 'a {
+        let mut iters: Vec<_> = nearest_iters(&self.exact, xy)
+            .chain(nearest_iters(&self.compact, xy))
+            .map(Iterator::peekable)
+            .collect();
+
+        // Merge the sorted iterators of each tree
+        std::iter::from_fn(move || {
+            let closest = iters
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(i, iter)| Some((i, iter.peek()?.1)))
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())?
+                .0;
+            iters[closest].next()
+        })
+    }
+
+    /// The edges with a segment whose envelope intersects the rectangle described by two opposite
+    /// corners, each one once
+    pub fn locate<'a>(
+        &'a self,
+        xy1: [f64; 2],
+        xy2: [f64; 2],
+    ) -> impl Iterator<Item = EdgeIndex> + 'a {
+        let mut seen = HashSet::new();
+        locate(&self.exact, xy1, xy2)
+            .chain(locate(&self.compact, xy1, xy2))
+            .filter(move |&edge| seen.insert(edge))
+    }
+
+    /// Like `locate()`, but also return the lowest corner of the envelope of each segment, to tell
+    /// which part of a split query an edge belongs to. A contracted edge comes once for each of
+    /// its segments that intersects the rectangle
+    pub fn locate_with_corner<'a>(
+        &'a self,
+        xy1: [f64; 2],
+        xy2: [f64; 2],
+    ) -> impl Iterator<Item = (EdgeIndex, [f64; 2])> + 'a {
+        locate_with_corner(&self.exact, xy1, xy2).chain(locate_with_corner(&self.compact, xy1, xy2))
+    }
+}
+
+fn nearest_iters<'a, S: Scalar>(
+    trees: &'a [RTree<Line<S>>],
+    xy: [f64; 2],
+) -> impl Iterator<Item = Box<dyn Iterator<Item = (EdgeIndex, f64)> + 'a>> + 'a {
+    let point = convert::<S>(xy);
+    trees.iter().map(move |tree| {
+        let iter = tree
+            .nearest_neighbor_iter(&point)
+            .map(move |line| (line.data, line.distance_2(&point).to_f64().sqrt()));
+        Box::new(iter) as Box<dyn Iterator<Item = _>>
+    })
+}
+
+fn locate<'a, S: Scalar>(
+    trees: &'a [RTree<Line<S>>],
+    xy1: [f64; 2],
+    xy2: [f64; 2],
+) -> impl Iterator<Item = EdgeIndex> + 'a {
+    let envelope = AABB::from_corners(convert::<S>(xy1), convert::<S>(xy2));
+    trees.iter().flat_map(move |tree| {
+        tree.locate_in_envelope_intersecting(&envelope)
+            .map(|line| line.data)
+    })
+}
+
+fn locate_with_corner<'a, S: Scalar>(
+    trees: &'a [RTree<Line<S>>],
+    xy1: [f64; 2],
+    xy2: [f64; 2],
+) -> impl Iterator<Item = (EdgeIndex, [f64; 2])> + 'a {
+    let envelope = AABB::from_corners(convert::<S>(xy1), convert::<S>(xy2));
+    trees.iter().flat_map(move |tree| {
+        tree.locate_in_envelope_intersecting(&envelope).map(|line| {
+            let lower = line.envelope().lower();
+            (line.data, [lower[0].to_f64(), lower[1].to_f64()])
+        })
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn bulk_load<S: Scalar + Send>(mut lines: Vec<Line<S>>, parallel: bool) -> Vec<RTree<Line<S>>> {
+    use rstar::Envelope;
+
+    let num_trees = if parallel { num_cpus::get() } else { 1 };
+    if num_trees <= 1 || lines.len() < num_trees {
+        return vec![RTree::bulk_load(lines)];
+    }
+
+    // Split in vertical strips, so that most queries only find close candidates in one tree
+    lines.sort_unstable_by(|a, b| {
+        let a = a.envelope().center()[0];
+        let b = b.envelope().center()[0];
+        a.partial_cmp(&b).unwrap()
+    });
+    let strip_len = lines.len().div_ceil(num_trees);
+    let mut strips = Vec::with_capacity(num_trees);
+    while lines.len() > strip_len {
+        strips.push(lines.split_off(lines.len() - strip_len));
+    }
+    strips.push(lines);
+
+    crossbeam::scope(|scope| {
+        let threads: Vec<_> = strips
+            .into_iter()
+            .map(|strip| scope.spawn(move |_| RTree::bulk_load(strip)))
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect()
+    })
+    .unwrap()
+}
+
+/// There are no threads in WebAssembly, so the index is always a single tree
+#[cfg(target_arch = "wasm32")]
+fn bulk_load<S: Scalar>(lines: Vec<Line<S>>, _parallel: bool) -> Vec<RTree<Line<S>>> {
+    vec![RTree::bulk_load(lines)]
+}
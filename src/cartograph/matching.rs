@@ -0,0 +1,260 @@
+use super::cost::DistanceCost;
+use super::data_types::*;
+use super::deadline::{Deadline, DeadlineCheck};
+use super::virtual_nodes;
+use super::Cartograph;
+use crate::utils::GeoPoint;
+
+/// Parameters of the Hidden Markov Model used to match a GPS trace to the road network, as described
+/// by Newson and Krumm in "Hidden Markov Map Matching Through Noise and Sparseness" (2009)
+#[derive(Clone, Copy, Debug)]
+pub struct MatchOptions {
+    /// Standard deviation of the GPS measurement noise, in meters
+    pub sigma: f64,
+    /// Expected difference between the route distance and the straight-line distance of two
+    /// consecutive trace points, in meters
+    pub beta: f64,
+    /// Maximum distance between a trace point and its candidate road positions, in meters
+    pub search_radius: f64,
+    /// Maximum number of candidate road positions for each trace point
+    pub max_candidates: usize,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            sigma: 10.,
+            beta: 20.,
+            search_radius: 50.,
+            max_candidates: 8,
+        }
+    }
+}
+
+/// The result of matching a GPS trace to the road network
+#[derive(Clone, Debug)]
+pub struct MatchedTrace {
+    /// The road position matched to each trace point
+    pub points: Vec<ProjectedPoint>,
+    /// The path on the road network that goes through all matched points
+    pub path: GraphPath,
+}
+
+/// Find the most likely sequence of road positions for the trace, using the Viterbi algorithm. The
+/// candidates that can not be reached from the previous ones are impossible transitions. When none
+/// of them can, for example across a gap in a disconnected graph, the sequence starts again from
+/// that point and the path jumps straight to it. Returns `None` if the trace is empty
+pub fn match_trace(
+    carto: &Cartograph,
+    trace: &[GeoPoint],
+    options: &MatchOptions,
+) -> Option<MatchedTrace> {
+    if trace.is_empty() {
+        return None;
+    }
+
+    // Log-probability of observing the trace point when the vehicle is at the candidate position
+    let emission = |candidate: &ProjectedPoint| {
+        let distance = candidate.projected.haversine_distance(&candidate.original);
+        -0.5 * (distance / options.sigma).powi(2)
+    };
+
+    let candidates: Vec<Vec<ProjectedPoint>> = trace
+        .iter()
+        .map(|point| carto.project_candidates(point, options.search_radius, options.max_candidates))
+        .collect();
+
+    // Forward pass: keep the best score of each candidate and where it came from
+    let mut scores: Vec<f64> = candidates[0].iter().map(emission).collect();
+    let mut back_pointers: Vec<Vec<usize>> = Vec::with_capacity(trace.len() - 1);
+    for (t, step_candidates) in candidates.iter().enumerate().skip(1) {
+        let straight_distance = trace[t - 1].haversine_distance(&trace[t]);
+        let mut new_scores = vec![f64::NEG_INFINITY; step_candidates.len()];
+        let mut pointers = vec![0; step_candidates.len()];
+
+        for (i, prev) in candidates[t - 1].iter().enumerate() {
+            let distances = route_distances(carto, prev, step_candidates);
+            for (j, (candidate, distance)) in step_candidates.iter().zip(distances).enumerate() {
+                // Log-probability of the transition, based on how much the route deviates from
+                // the straight line between the two trace points. It is minus infinity for the
+                // unreachable candidates
+                let transition = -(distance - straight_distance).abs() / options.beta;
+                let score = scores[i] + transition + emission(candidate);
+                if score > new_scores[j] {
+                    new_scores[j] = score;
+                    pointers[j] = i;
+                }
+            }
+        }
+        if new_scores.iter().all(|&score| score == f64::NEG_INFINITY) {
+            // The sequence before the gap ends at its best candidate
+            new_scores = step_candidates.iter().map(emission).collect();
+            pointers = vec![best_candidate(&scores); step_candidates.len()];
+        }
+
+        scores = new_scores;
+        back_pointers.push(pointers);
+    }
+
+    // Backward pass: follow the pointers from the best final candidate
+    let mut best = best_candidate(&scores);
+    let mut chosen = vec![best];
+    for pointers in back_pointers.iter().rev() {
+        best = pointers[best];
+        chosen.push(best);
+    }
+    chosen.reverse();
+    let points: Vec<ProjectedPoint> = chosen
+        .into_iter()
+        .zip(candidates)
+        .map(|(i, step_candidates)| step_candidates[i])
+        .collect();
+
+    // Build the full path
+    let mut distance = 0;
+    let mut duration = 0.;
+    let mut path_points = vec![points[0].projected];
+    let mut edges = Vec::new();
+    for pair in points.windows(2) {
+        let leg = match route(carto, &pair[0], &pair[1]) {
+            Some(leg) => leg,
+            None => {
+                path_points.push(pair[1].projected);
+                continue;
+            }
+        };
+        distance += leg.distance;
+        duration += leg.duration;
+        path_points.extend(leg.points.into_iter().skip(1));
+        edges.extend(leg.edges);
+    }
+
+    // Each leg starts on the edge where the previous one ends
+    edges.dedup();
+    Some(MatchedTrace {
+        points,
+        path: GraphPath::new(distance, duration, path_points).with_edges(edges),
+    })
+}
+
+/// The index of the candidate with the best score
+fn best_candidate(scores: &[f64]) -> usize {
+    (0..scores.len())
+        .max_by(|&a, &b| scores[a].partial_cmp(&scores[b]).unwrap())
+        .unwrap()
+}
+
+/// Calculate the route distance from one position to many others, that is infinite for the
+/// unreachable ones
+fn route_distances(carto: &Cartograph, from: &ProjectedPoint, to: &[ProjectedPoint]) -> Vec<f64> {
+    let deadline = Deadline::never();
+    let check = DeadlineCheck::new(&deadline);
+    virtual_nodes::search(carto, from, to, &DistanceCost, &check)
+        .into_iter()
+        .zip(to)
+        .map(|(path, to)| match same_edge_distance(carto, from, to) {
+            Some(direct) => direct as f64,
+            None => path.map_or(f64::INFINITY, |path| path.cost as f64),
+        })
+        .collect()
+}
+
+/// Calculate the route from one position to another. Returns `None` if it is not reachable
+fn route(carto: &Cartograph, from: &ProjectedPoint, to: &ProjectedPoint) -> Option<GraphPath> {
+    match same_edge_distance(carto, from, to) {
+        Some(distance) => {
+            let duration = carto.graph[from.edge].duration() * (to.edge_pos - from.edge_pos) as f64;
+            let mut points = vec![from.projected];
+            carto.push_partial_points(from.edge, from.edge_pos, to.edge_pos, &mut points);
+            points.push(to.projected);
+            Some(GraphPath::new(distance, duration, points).with_edges(vec![from.edge]))
+        }
+        None => carto.try_shortest_path(from, to),
+    }
+}
+
+/// The distance between two positions, if the second one can be reached by moving forward on the
+/// edge of the first one
+fn same_edge_distance(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &ProjectedPoint,
+) -> Option<u32> {
+    if from.edge == to.edge && from.edge_pos <= to.edge_pos {
+        Some(partial_distance(
+            carto.graph[from.edge].distance,
+            to.edge_pos - from.edge_pos,
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartograph::CartographBuilder;
+
+    #[test]
+    fn unreachable_candidates() {
+        let mut carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let a = GeoPoint::from_degrees(42.553210, 1.588908);
+        let b = GeoPoint::from_degrees(42.564440, 1.685042);
+        let (from, to) = (carto.project(&a), carto.project(&b));
+
+        // Without the invented edges, the second point is not reachable
+        carto.allow_synthetic = false;
+        let distances = route_distances(&carto, &from, &[to, from]);
+        assert_eq!(distances, vec![f64::INFINITY, 0.]);
+        assert!(route(&carto, &from, &to).is_none());
+
+        // The trace is cut there, instead of preferring the unreachable candidate
+        let options = MatchOptions {
+            max_candidates: 1,
+            ..MatchOptions::default()
+        };
+        let matched = match_trace(&carto, &[a, b], &options).unwrap();
+        assert_eq!(matched.points, vec![from, to]);
+        assert_eq!(matched.path.distance, 0);
+        assert_eq!(matched.path.points, vec![from.projected, to.projected]);
+    }
+
+    #[test]
+    fn gap_after_second_candidate() {
+        // Two parallel one-way roads 22 meters apart and a road one kilometer away, that are not
+        // connected
+        let mut builder = CartographBuilder::default();
+        let point = |x: f64, y: f64| GeoPoint::from_degrees(y, x);
+        for &(a, b) in &[
+            ((0., 0.), (0.002, 0.)),
+            ((0., 0.0002), (0.002, 0.0002)),
+            ((0., 0.01), (0.002, 0.01)),
+        ] {
+            let (a, b) = (point(a.0, a.1), point(b.0, b.1));
+            let distance = a.haversine_distance(&b).round() as u32;
+            let (a, b) = (builder.add_node(a), builder.add_node(b));
+            builder.add_edge(a, b, EdgeInfo::new(distance, 5));
+        }
+        let carto = builder.build();
+
+        // The second point is a bit closer to the other road, that can not be reached from the
+        // first point. Then the trace jumps to the far road
+        let trace = [
+            point(0.0002, 0.),
+            point(0.0015, 0.00011),
+            point(0.001, 0.01),
+        ];
+        let options = MatchOptions::default();
+        let candidates = carto.project_candidates(&trace[1], options.search_radius, 8);
+        assert_eq!(candidates.len(), 2);
+        assert_ne!(candidates[0].edge, carto.project(&trace[0]).edge);
+
+        let matched = match_trace(&carto, &trace, &options).unwrap();
+        let edges: Vec<_> = matched
+            .points
+            .iter()
+            .map(|point| point.edge.index())
+            .collect();
+        assert_eq!(edges, vec![0, 0, 2]);
+    }
+}
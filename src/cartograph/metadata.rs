@@ -0,0 +1,103 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+/// The name of the file section with the metadata
+pub const METADATA_SECTION: &str = "metadata";
+
+/// Free key-value pairs about where a file comes from, to track the provenance of a deployed graph.
+/// The generator writes the keys below, but any other can be added
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Metadata(pub BTreeMap<String, String>);
+
+impl Metadata {
+    /// The name of the OSM file the graph was generated from
+    pub const SOURCE: &'static str = "source";
+    /// When the OSM data was last updated, in seconds since the Unix epoch, as given by osmosis
+    pub const REPLICATION_TIMESTAMP: &'static str = "replication_timestamp";
+    /// The version of Ptolemy that generated the file
+    pub const GENERATOR_VERSION: &'static str = "generator_version";
+    /// Which roads were kept by the generator
+    pub const PROFILE: &'static str = "profile";
+    /// The bounding box of the nodes, as `min_lon,min_lat,max_lon,max_lat`
+    pub const BBOX: &'static str = "bbox";
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn insert(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_owned(), value);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Write the pairs as a file section. The strings are prefixed by their length, as a `u16`
+    pub fn write_section<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut payload = Vec::new();
+        payload.write_u16::<LittleEndian>(self.0.len() as u16)?;
+        for (key, value) in &self.0 {
+            write_str(&mut payload, key)?;
+            write_str(&mut payload, value)?;
+        }
+
+        writer.write_u8(METADATA_SECTION.len() as u8)?;
+        writer.write_all(METADATA_SECTION.as_bytes())?;
+        writer.write_u64::<LittleEndian>(payload.len() as u64)?;
+        writer.write_all(&payload)
+    }
+
+    /// Read the contents of the section, after its name
+    pub fn read_section<R: Read>(reader: &mut R) -> io::Result<Metadata> {
+        let size = reader.read_u64::<LittleEndian>()?;
+        let mut payload = Vec::new();
+        reader.take(size).read_to_end(&mut payload)?;
+        if payload.len() as u64 != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut payload = payload.as_slice();
+        let pairs = (0..payload.read_u16::<LittleEndian>()?)
+            .map(|_| Ok((read_str(&mut payload)?, read_str(&mut payload)?)))
+            .collect::<io::Result<_>>()?;
+        Ok(Metadata(pairs))
+    }
+}
+
+fn write_str<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    writer.write_u16::<LittleEndian>(value.len() as u16)?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_str<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut value = vec![0; reader.read_u16::<LittleEndian>()? as usize];
+    reader.read_exact(&mut value)?;
+    String::from_utf8(value).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn section() {
+        let mut metadata = Metadata::default();
+        metadata.insert(Metadata::SOURCE, "andorra-latest.osm.pbf".to_owned());
+        metadata.insert("région", "Andorre".to_owned());
+        let mut bytes = Vec::new();
+        metadata.write_section(&mut bytes).unwrap();
+
+        let mut reader = &bytes[1 + METADATA_SECTION.len()..];
+        let read = Metadata::read_section(&mut reader).unwrap();
+        assert_eq!(read, metadata);
+        assert_eq!(read.get("région"), Some("Andorre"));
+        assert!(reader.is_empty());
+        let mut reader = &bytes[1 + METADATA_SECTION.len()..bytes.len() - 1];
+        assert!(Metadata::read_section(&mut reader).is_err());
+    }
+}
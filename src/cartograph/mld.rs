@@ -0,0 +1,756 @@
+use super::cost::CostModel;
+use super::data_types::partial_distance;
+use super::virtual_nodes::{self, Endpoint, VirtualPath};
+use super::{Cartograph, CartographError, DistanceCost, ProjectedPoint};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{self, Read, Write};
+
+/// The first bytes of a multi-level overlay file
+const MAGIC: &[u8; 14] = b"PTOLEMY-MLD-v1";
+
+/// How many values are read at once, so that a truncated file fails before allocating the sizes it
+/// claims
+const READ_CHUNK: usize = 1 << 16;
+
+/// How `Partition::inertial()` splits the nodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PartitionOptions {
+    /// The maximum number of nodes of the finest cells
+    pub cell_size: usize,
+    /// The number of levels of cells, each one grouping the cells of the previous one
+    pub levels: usize,
+}
+
+impl Default for PartitionOptions {
+    fn default() -> Self {
+        PartitionOptions {
+            cell_size: 256,
+            levels: 3,
+        }
+    }
+}
+
+/// Nested cells of nodes, from the finest level to the coarsest one, for multi-level routing (also
+/// known as customizable route planning). A cell is the set of nodes with the same id at a level,
+/// and its id at the next level drops the last bits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Partition {
+    /// The finest cell of each node
+    leaves: Vec<u32>,
+    /// How many bisections separate the cells of a level from the ones of the next
+    step: u32,
+    levels: usize,
+}
+
+impl Partition {
+    /// Split the nodes in two halves again and again, until the cells have at most `cell_size`
+    /// nodes. Each part is sorted along four directions, and the median of the one that cuts the
+    /// fewest edges splits it. This is the inertial step of Inertial Flow, without the max-flow
+    /// refinement of the cut
+    pub fn inertial(carto: &Cartograph, options: &PartitionOptions) -> Partition {
+        let graph = &carto.graph;
+        let levels = options.levels.clamp(1, 31);
+        let num_cells = graph.node_count().div_ceil(options.cell_size.max(1));
+        let depth = (usize::BITS - num_cells.saturating_sub(1).leading_zeros())
+            .max(levels as u32)
+            .min(31);
+        let step = depth / levels as u32;
+
+        let xy: Vec<[f64; 2]> = graph
+            .node_indices()
+            .map(|node| graph[node].web_mercator_project())
+            .collect();
+        let directions = [[1., 0.], [0., 1.], [1., 1.], [1., -1.]];
+        // 0 or 1 for the nodes of the part being split, 2 for the others
+        let mut sides = vec![2u8; graph.node_count()];
+        let mut parts: Vec<Vec<u32>> = vec![(0..graph.node_count() as u32).collect()];
+        for _ in 0..depth {
+            let mut next_parts = Vec::with_capacity(2 * parts.len());
+            for mut part in parts {
+                let half = part.len() / 2;
+                let mut best: Option<(usize, Vec<u32>)> = None;
+                for direction in &directions {
+                    let projection = |node: &u32| {
+                        let [x, y] = xy[*node as usize];
+                        x * direction[0] + y * direction[1]
+                    };
+                    part.sort_by(|a, b| projection(a).total_cmp(&projection(b)).then(a.cmp(b)));
+                    for (i, &node) in part.iter().enumerate() {
+                        sides[node as usize] = (i >= half) as u8;
+                    }
+                    let cut = part
+                        .iter()
+                        .flat_map(|&node| graph.edges(NodeIndex::new(node as usize)))
+                        .filter(|edge| {
+                            let (source, target) = (edge.source().index(), edge.target().index());
+                            sides[target] != 2 && sides[source] != sides[target]
+                        })
+                        .count();
+                    if best.as_ref().is_none_or(|(best_cut, _)| cut < *best_cut) {
+                        best = Some((cut, part.clone()));
+                    }
+                }
+                for &node in &part {
+                    sides[node as usize] = 2;
+                }
+                let mut part = best.map_or(part, |(_, part)| part);
+                let upper = part.split_off(half);
+                next_parts.push(part);
+                next_parts.push(upper);
+            }
+            parts = next_parts;
+        }
+
+        let mut leaves = vec![0; graph.node_count()];
+        for (cell, part) in parts.iter().enumerate() {
+            for &node in part {
+                leaves[node as usize] = cell as u32;
+            }
+        }
+        Partition {
+            leaves,
+            step,
+            levels,
+        }
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels
+    }
+
+    /// The cell of a node at a level, 0 being the finest
+    pub fn cell(&self, level: usize, node: NodeIndex) -> u32 {
+        self.leaves[node.index()] >> (level as u32 * self.step)
+    }
+
+    /// The lowest level where the node shares a cell with one of the others, or `levels()` when it
+    /// shares none
+    fn shared_level(&self, node: NodeIndex, others: &[NodeIndex]) -> usize {
+        (0..self.levels)
+            .find(|&level| {
+                let cell = self.cell(level, node);
+                others.iter().any(|&other| self.cell(level, other) == cell)
+            })
+            .unwrap_or(self.levels)
+    }
+}
+
+/// The costs between the boundary nodes of a cell, the ones with an edge to or from another cell
+#[derive(Clone, Debug, PartialEq)]
+struct Cell {
+    boundary: Vec<NodeIndex>,
+    /// Row-major, from each boundary node to each one, `u32::MAX` when not reachable inside the cell
+    costs: Vec<u32>,
+}
+
+/// A step of a search on the overlay
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Arc {
+    Edge(EdgeIndex),
+    /// The cheapest path inside the cell of the origin at a level, between two boundary nodes
+    Shortcut(usize),
+}
+
+/// The score of each node reached by a search, with the last step to it
+type Scores = HashMap<NodeIndex, (u32, Option<(NodeIndex, Arc)>)>;
+
+/// The cells of a partition with the costs between their boundary nodes, for routing in two steps:
+/// the partition and the boundaries are computed once, then `customize()` computes the costs for
+/// a cost model, which is much faster and can be done again when the costs change, like with
+/// traffic. The searches then go through the cells that contain neither endpoint with the costs of
+/// their boundaries, instead of exploring them
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiLevelOverlay {
+    /// The checksum of the cartography file that was used, to detect a stale overlay
+    pub checksum: u32,
+    partition: Partition,
+    /// The cost of each edge in the last customization, `u32::MAX` for the forbidden ones
+    edge_costs: Vec<u32>,
+    /// For each level, the cells by their id
+    cells: Vec<HashMap<u32, Cell>>,
+    /// For each level, the position of each node in the boundary of its cell, `u32::MAX` when it is
+    /// not on it
+    positions: Vec<Vec<u32>>,
+}
+
+impl MultiLevelOverlay {
+    /// Find the boundaries of the cells and customize them for the distances
+    pub fn new(carto: &Cartograph, partition: Partition, checksum: u32) -> MultiLevelOverlay {
+        let graph = &carto.graph;
+        let mut cells: Vec<HashMap<u32, Cell>> = vec![HashMap::new(); partition.levels];
+        for (level, cells) in cells.iter_mut().enumerate() {
+            let mut on_boundary = vec![false; graph.node_count()];
+            for edge in graph.edge_references() {
+                if partition.cell(level, edge.source()) != partition.cell(level, edge.target()) {
+                    on_boundary[edge.source().index()] = true;
+                    on_boundary[edge.target().index()] = true;
+                }
+            }
+            for node in graph
+                .node_indices()
+                .filter(|node| on_boundary[node.index()])
+            {
+                cells
+                    .entry(partition.cell(level, node))
+                    .or_insert_with(|| Cell {
+                        boundary: Vec::new(),
+                        costs: Vec::new(),
+                    })
+                    .boundary
+                    .push(node);
+            }
+        }
+        let mut overlay = MultiLevelOverlay::from_cells(checksum, partition, Vec::new(), cells);
+        overlay.customize(carto, &DistanceCost);
+        overlay
+    }
+
+    fn from_cells(
+        checksum: u32,
+        partition: Partition,
+        edge_costs: Vec<u32>,
+        cells: Vec<HashMap<u32, Cell>>,
+    ) -> MultiLevelOverlay {
+        let positions = cells
+            .iter()
+            .map(|cells| {
+                let mut positions = vec![u32::MAX; partition.leaves.len()];
+                for cell in cells.values() {
+                    for (i, node) in cell.boundary.iter().enumerate() {
+                        positions[node.index()] = i as u32;
+                    }
+                }
+                positions
+            })
+            .collect();
+        MultiLevelOverlay {
+            checksum,
+            partition,
+            edge_costs,
+            cells,
+            positions,
+        }
+    }
+
+    pub fn partition(&self) -> &Partition {
+        &self.partition
+    }
+
+    /// Compute the costs between the boundary nodes of all cells for a cost model, from the finest
+    /// level to the coarsest, each level searching on the previous one. The edges that cost
+    /// `u32::MAX` and the synthetic ones, when they are not allowed, are never traversed
+    pub fn customize<C: CostModel + ?Sized>(&mut self, carto: &Cartograph, cost: &C) {
+        let graph = &carto.graph;
+        self.edge_costs = graph
+            .edge_references()
+            .map(|edge| {
+                let (source, target) = (&graph[edge.source()], &graph[edge.target()]);
+                if carto.is_routable(edge.weight()) {
+                    cost.indexed_cost(edge.id(), edge.weight(), source, target)
+                } else {
+                    u32::MAX
+                }
+            })
+            .collect();
+
+        for level in 0..self.partition.levels {
+            let inner = level.checked_sub(1);
+            let mut costs: Vec<(u32, Vec<u32>)> = Vec::with_capacity(self.cells[level].len());
+            for (&id, cell) in &self.cells[level] {
+                let mut cell_costs = Vec::with_capacity(cell.boundary.len().pow(2));
+                for &from in &cell.boundary {
+                    let scores = self.dijkstra(
+                        carto,
+                        &[(from, 0)],
+                        |node| (self.partition.cell(level, node) == id).then_some(inner),
+                        |_, _| false,
+                    );
+                    cell_costs.extend(
+                        cell.boundary
+                            .iter()
+                            .map(|to| scores.get(to).map_or(u32::MAX, |&(score, _)| score)),
+                    );
+                }
+                costs.push((id, cell_costs));
+            }
+            for (id, cell_costs) in costs {
+                self.cells[level].get_mut(&id).unwrap().costs = cell_costs;
+            }
+        }
+    }
+
+    /// Run a Dijkstra search from the sources, with their initial scores. `level_of` tells which
+    /// arcs leave a node: the edges of the graph for `Some(None)`, the shortcuts of its cell at a
+    /// level and the edges to other cells for `Some(Some(level))`, nothing for `None`. Stop when
+    /// `stop` is true for a settled node. Returns the score and the last step to each node
+    fn dijkstra(
+        &self,
+        carto: &Cartograph,
+        sources: &[(NodeIndex, u32)],
+        level_of: impl Fn(NodeIndex) -> Option<Option<usize>>,
+        mut stop: impl FnMut(NodeIndex, u32) -> bool,
+    ) -> Scores {
+        let graph = &carto.graph;
+        let mut scores: Scores = HashMap::new();
+        let mut visit_next = BinaryHeap::new();
+        for &(node, score) in sources {
+            if scores.get(&node).is_none_or(|&(old, _)| score < old) {
+                scores.insert(node, (score, None));
+                visit_next.push(Reverse((score, node)));
+            }
+        }
+
+        while let Some(Reverse((score, node))) = visit_next.pop() {
+            if score > scores[&node].0 {
+                continue;
+            }
+            if stop(node, score) {
+                break;
+            }
+            let level = match level_of(node) {
+                Some(level) => level,
+                None => continue,
+            };
+            let mut relax = |next: NodeIndex, cost: u32, arc: Arc| {
+                if cost == u32::MAX || level_of(next).is_none() {
+                    return;
+                }
+                let next_score = score.saturating_add(cost);
+                if scores.get(&next).is_none_or(|&(old, _)| next_score < old) {
+                    scores.insert(next, (next_score, Some((node, arc))));
+                    visit_next.push(Reverse((next_score, next)));
+                }
+            };
+
+            let cell = level.map(|level| self.partition.cell(level, node));
+            if let Some(level) = level {
+                let position = self.positions[level][node.index()];
+                if position != u32::MAX {
+                    let shortcuts = &self.cells[level][&cell.unwrap()];
+                    let row = position as usize * shortcuts.boundary.len();
+                    for (&next, &cost) in shortcuts
+                        .boundary
+                        .iter()
+                        .zip(&shortcuts.costs[row..row + shortcuts.boundary.len()])
+                    {
+                        if next != node {
+                            relax(next, cost, Arc::Shortcut(level));
+                        }
+                    }
+                }
+            }
+            for edge in graph.edges(node) {
+                // Inside the cell, only the shortcuts are used
+                if level
+                    .is_some_and(|level| self.partition.cell(level, edge.target()) == cell.unwrap())
+                {
+                    continue;
+                }
+                relax(
+                    edge.target(),
+                    self.edge_costs[edge.id().index()],
+                    Arc::Edge(edge.id()),
+                );
+            }
+        }
+        scores
+    }
+
+    /// Replace a shortcut by the edges of the cheapest path inside its cell, recursively
+    fn unpack(
+        &self,
+        carto: &Cartograph,
+        level: usize,
+        from: NodeIndex,
+        to: NodeIndex,
+        edges: &mut Vec<EdgeIndex>,
+    ) {
+        let cell = self.partition.cell(level, from);
+        let inner = level.checked_sub(1);
+        let scores = self.dijkstra(
+            carto,
+            &[(from, 0)],
+            |node| (self.partition.cell(level, node) == cell).then_some(inner),
+            |node, _| node == to,
+        );
+        self.collect_edges(carto, &scores, to, edges);
+    }
+
+    /// Append the edges of the path found to a node, unpacking its shortcuts
+    fn collect_edges(
+        &self,
+        carto: &Cartograph,
+        scores: &Scores,
+        to: NodeIndex,
+        edges: &mut Vec<EdgeIndex>,
+    ) {
+        let mut arcs = Vec::new();
+        let mut node = to;
+        while let Some((parent, arc)) = scores[&node].1 {
+            arcs.push((parent, arc, node));
+            node = parent;
+        }
+        for (from, arc, to) in arcs.into_iter().rev() {
+            match arc {
+                Arc::Edge(edge) => edges.push(edge),
+                Arc::Shortcut(level) => self.unpack(carto, level, from, to, edges),
+            }
+        }
+    }
+
+    /// Find the cheapest path between two projected points with the costs of the last
+    /// customization, like `virtual_nodes::search()`
+    pub(super) fn search(
+        &self,
+        carto: &Cartograph,
+        from: &ProjectedPoint,
+        to: &ProjectedPoint,
+    ) -> Option<VirtualPath> {
+        let graph = &carto.graph;
+        let starts = virtual_nodes::endpoints(carto, from, &DistanceCost);
+        let ends = virtual_nodes::endpoints(carto, to, &DistanceCost);
+        // The edges of the points are always traversed, even when the model forbids them
+        let endpoint_cost = |edge: EdgeIndex, fraction: f32| match self.edge_costs[edge.index()] {
+            u32::MAX => partial_distance(graph[edge].distance, fraction),
+            edge_cost => partial_distance(edge_cost, fraction),
+        };
+
+        // The cheapest path found so far: its cost, where it reaches the destination and either the
+        // start on the same edge or the last node
+        let mut best: Option<(u32, Endpoint, Result<NodeIndex, Endpoint>)> = None;
+        for start in &starts {
+            for end in &ends {
+                if start.edge == end.edge && start.edge_pos <= end.edge_pos {
+                    let cost = endpoint_cost(start.edge, end.edge_pos - start.edge_pos);
+                    if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                        best = Some((cost, *end, Err(*start)));
+                    }
+                }
+            }
+        }
+
+        let sources: Vec<(NodeIndex, u32)> = starts
+            .iter()
+            .map(|start| {
+                let node = graph.edge_endpoints(start.edge).unwrap().1;
+                (node, endpoint_cost(start.edge, 1. - start.edge_pos))
+            })
+            .collect();
+        let mut end_nodes: HashMap<NodeIndex, Vec<(u32, Endpoint)>> = HashMap::new();
+        for end in &ends {
+            let node = graph.edge_endpoints(end.edge).unwrap().0;
+            let cost = endpoint_cost(end.edge, end.edge_pos);
+            end_nodes.entry(node).or_default().push((cost, *end));
+        }
+
+        // Each node is explored at the highest level where its cell contains neither point
+        let mut anchors: Vec<NodeIndex> = sources.iter().map(|&(node, _)| node).collect();
+        anchors.extend(end_nodes.keys());
+        let scores = self.dijkstra(
+            carto,
+            &sources,
+            |node| Some(self.partition.shared_level(node, &anchors).checked_sub(1)),
+            |node, score| {
+                if best.is_some_and(|(best_cost, _, _)| best_cost <= score) {
+                    return true;
+                }
+                for &(cost, end) in end_nodes.get(&node).into_iter().flatten() {
+                    let cost = score.saturating_add(cost);
+                    if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                        best = Some((cost, end, Ok(node)));
+                    }
+                }
+                false
+            },
+        );
+
+        let (cost, end, last) = best?;
+        let last = match last {
+            Err(start) => {
+                return Some(VirtualPath {
+                    cost,
+                    start,
+                    end,
+                    nodes: Vec::new(),
+                    inner: Vec::new(),
+                })
+            }
+            Ok(last) => last,
+        };
+        let mut inner = Vec::new();
+        self.collect_edges(carto, &scores, last, &mut inner);
+        let first = match inner.first() {
+            Some(&edge) => graph.edge_endpoints(edge).unwrap().0,
+            None => last,
+        };
+        let start = *starts
+            .iter()
+            .find(|start| graph.edge_endpoints(start.edge).unwrap().1 == first)
+            .unwrap();
+        let mut nodes = vec![first];
+        nodes.extend(
+            inner
+                .iter()
+                .map(|&edge| graph.edge_endpoints(edge).unwrap().1),
+        );
+        Some(VirtualPath {
+            cost,
+            start,
+            end,
+            nodes,
+            inner,
+        })
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(self.checksum)?;
+        writer.write_u32::<LittleEndian>(self.partition.levels as u32)?;
+        writer.write_u32::<LittleEndian>(self.partition.step)?;
+        writer.write_u32::<LittleEndian>(self.partition.leaves.len() as u32)?;
+        for &leaf in &self.partition.leaves {
+            writer.write_u32::<LittleEndian>(leaf)?;
+        }
+        writer.write_u32::<LittleEndian>(self.edge_costs.len() as u32)?;
+        for &cost in &self.edge_costs {
+            writer.write_u32::<LittleEndian>(cost)?;
+        }
+        for cells in &self.cells {
+            // Sorted, so that the same overlay always gives the same file
+            let mut ids: Vec<_> = cells.keys().copied().collect();
+            ids.sort_unstable();
+            writer.write_u32::<LittleEndian>(ids.len() as u32)?;
+            for id in ids {
+                let cell = &cells[&id];
+                writer.write_u32::<LittleEndian>(id)?;
+                writer.write_u32::<LittleEndian>(cell.boundary.len() as u32)?;
+                for node in &cell.boundary {
+                    writer.write_u32::<LittleEndian>(node.index() as u32)?;
+                }
+                for &cost in &cell.costs {
+                    writer.write_u32::<LittleEndian>(cost)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read an overlay written for this cartography. The sizes and the node indices are checked
+    /// against it, so that a corrupted or foreign file is an error instead of a panic in the
+    /// searches
+    pub fn read<R: Read>(
+        reader: &mut R,
+        carto: &Cartograph,
+    ) -> Result<MultiLevelOverlay, CartographError> {
+        let mut magic = [0; 14];
+        match reader.read_exact(&mut magic) {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(CartographError::BadMagic)
+            }
+            result => result?,
+        }
+        if &magic != MAGIC {
+            return Err(CartographError::BadMagic);
+        }
+
+        let mut read = || -> io::Result<MultiLevelOverlay> {
+            let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+            let mut read_u32s = |len: usize| -> io::Result<Vec<u32>> {
+                let mut values = Vec::with_capacity(len.min(READ_CHUNK));
+                while values.len() < len {
+                    let start = values.len();
+                    values.resize(start + (len - start).min(READ_CHUNK), 0);
+                    reader.read_u32_into::<LittleEndian>(&mut values[start..])?;
+                }
+                Ok(values)
+            };
+            let header = read_u32s(4)?;
+            let (checksum, levels, step) = (header[0], header[1] as usize, header[2]);
+            let num_nodes = carto.graph.node_count();
+            // The cell of the coarsest level drops `(levels - 1) * step` bits of the leaves
+            if levels == 0 || (levels as u64 - 1) * step as u64 >= 32 {
+                return Err(invalid(format!(
+                    "Invalid {} levels of {} bits",
+                    levels, step
+                )));
+            }
+            if header[3] as usize != num_nodes {
+                return Err(invalid(format!(
+                    "The overlay has {} nodes, the cartography {}",
+                    header[3], num_nodes
+                )));
+            }
+            let leaves = read_u32s(num_nodes)?;
+            let num_edges = read_u32s(1)?[0] as usize;
+            if num_edges != carto.graph.edge_count() {
+                return Err(invalid(format!(
+                    "The overlay has {} edges, the cartography {}",
+                    num_edges,
+                    carto.graph.edge_count()
+                )));
+            }
+            let edge_costs = read_u32s(num_edges)?;
+            let mut cells = Vec::with_capacity(levels);
+            for _ in 0..levels {
+                let num_cells = read_u32s(1)?[0] as usize;
+                if num_cells > num_nodes {
+                    return Err(invalid(format!("Too many cells: {}", num_cells)));
+                }
+                let mut level_cells = HashMap::with_capacity(num_cells);
+                for _ in 0..num_cells {
+                    let header = read_u32s(2)?;
+                    let len = header[1] as usize;
+                    if len > num_nodes {
+                        return Err(invalid(format!("Too many boundary nodes: {}", len)));
+                    }
+                    let boundary = read_u32s(len)?
+                        .into_iter()
+                        .map(|node| match node as usize {
+                            node if node < num_nodes => Ok(NodeIndex::new(node)),
+                            node => Err(invalid(format!("Invalid node {}", node))),
+                        })
+                        .collect::<io::Result<_>>()?;
+                    let costs = read_u32s(len * len)?;
+                    level_cells.insert(header[0], Cell { boundary, costs });
+                }
+                cells.push(level_cells);
+            }
+            let partition = Partition {
+                leaves,
+                step,
+                levels,
+            };
+            Ok(MultiLevelOverlay::from_cells(
+                checksum, partition, edge_costs, cells,
+            ))
+        };
+        read().map_err(|err| super::error::decoding_error(err, "overlay", MAGIC.len() as u64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartograph::EdgeInfo;
+    use crate::utils::GeoPoint;
+
+    #[test]
+    fn partition() {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let options = PartitionOptions {
+            cell_size: 64,
+            levels: 3,
+        };
+        let partition = Partition::inertial(&carto, &options);
+        assert_eq!(partition.levels(), 3);
+        let mut sizes: HashMap<u32, usize> = HashMap::new();
+        for node in carto.graph.node_indices() {
+            *sizes.entry(partition.cell(0, node)).or_default() += 1;
+            assert_eq!(
+                partition.cell(2, node),
+                partition.cell(1, node) >> partition.step
+            );
+        }
+        assert!(sizes.values().all(|&size| size <= 64));
+        assert!(carto
+            .graph
+            .node_indices()
+            .any(|node| partition.cell(2, node) != 0));
+        assert_eq!(partition, Partition::inertial(&carto, &options));
+    }
+
+    #[test]
+    fn search() {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let options = PartitionOptions {
+            cell_size: 64,
+            levels: 3,
+        };
+        let mut overlay = MultiLevelOverlay::new(&carto, Partition::inertial(&carto, &options), 42);
+        let points: Vec<_> = [
+            (42.553210, 1.588908),
+            (42.564440, 1.685042),
+            (42.507, 1.521),
+            (42.467, 1.491),
+            (42.5775, 1.6654),
+        ]
+        .iter()
+        .map(|&(lat, lon)| carto.project(&GeoPoint::from_degrees(lat, lon)))
+        .collect();
+        let path = carto
+            .shortest_path_with_overlay(&points[0], &points[1], &overlay)
+            .unwrap();
+        assert_eq!(path.distance, 12183);
+        for from in &points {
+            for to in &points {
+                let expected = carto
+                    .shortest_path_with_cost(from, to, &DistanceCost)
+                    .map(|path| path.distance);
+                let path = carto.shortest_path_with_overlay(from, to, &overlay);
+                assert_eq!(path.as_ref().map(|path| path.distance), expected);
+                if let Some(path) = path {
+                    assert_eq!(path.points.first(), Some(&from.projected));
+                    assert_eq!(path.points.last(), Some(&to.projected));
+                }
+            }
+        }
+
+        // A new customization changes the costs, not the cells
+        let level_cost = |info: &EdgeInfo, _: &GeoPoint, _: &GeoPoint| {
+            info.distance * (1 + info.road_level as u32)
+        };
+        overlay.customize(&carto, &level_cost);
+        for from in &points {
+            for to in &points {
+                let expected = carto.shortest_path_with_cost(from, to, &level_cost);
+                let path = carto.shortest_path_with_overlay(from, to, &overlay);
+                assert_eq!(
+                    path.map(|path| path.distance),
+                    expected.map(|path| path.distance)
+                );
+            }
+        }
+
+        let mut bytes = Vec::new();
+        overlay.write(&mut bytes).unwrap();
+        assert_eq!(
+            MultiLevelOverlay::read(&mut bytes.as_slice(), &carto).unwrap(),
+            overlay
+        );
+        assert!(MultiLevelOverlay::read(&mut &bytes[..bytes.len() - 1], &carto).is_err());
+        assert!(matches!(
+            MultiLevelOverlay::read(&mut &b"PTOLEMY-HUBS-v1"[..], &carto),
+            Err(CartographError::BadMagic)
+        ));
+
+        // The corrupted or foreign files are errors, without allocating what they claim
+        let corrupt = |offset: usize, value: u32| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            MultiLevelOverlay::read(&mut bytes.as_slice(), &carto)
+        };
+        let num_nodes = carto.graph.node_count();
+        let cells_start = MAGIC.len() + 4 * (4 + num_nodes + 1 + carto.graph.edge_count());
+        for &(offset, value) in &[
+            (MAGIC.len() + 4, 1000),
+            (MAGIC.len() + 4, 0),
+            (MAGIC.len() + 12, u32::MAX),
+            (MAGIC.len() + 16 + 4 * num_nodes, u32::MAX),
+            (cells_start, u32::MAX),
+            (cells_start + 8, u32::MAX),
+            (cells_start + 12, num_nodes as u32),
+        ] {
+            assert!(matches!(
+                corrupt(offset, value),
+                Err(CartographError::Corrupt { .. })
+            ));
+        }
+        let mut builder = crate::CartographBuilder::default();
+        builder.add_node(GeoPoint::from_degrees(42.5, 1.5));
+        assert!(MultiLevelOverlay::read(&mut bytes.as_slice(), &builder.build()).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+
+/// The name of the file section with the names of the roads
+pub const ROAD_NAMES_SECTION: &str = "road_names";
+
+/// The names of the roads, referenced by `EdgeInfo::name`: the index 0 is for the roads without a
+/// name and the others start at 1. A road is named by its `ref`, like `CG-2`, or its `name` when it
+/// has no ref
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoadNames(pub Vec<String>);
+
+impl RoadNames {
+    /// The name with the given index, `None` for the roads without a name
+    pub fn get(&self, index: u32) -> Option<&str> {
+        let position = (index as usize).checked_sub(1)?;
+        self.0.get(position).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Write the names as a file section. The strings are prefixed by their length, as a `u16`
+    pub fn write_section<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut payload = Vec::new();
+        payload.write_u32::<LittleEndian>(self.0.len() as u32)?;
+        for name in &self.0 {
+            payload.write_u16::<LittleEndian>(name.len() as u16)?;
+            payload.write_all(name.as_bytes())?;
+        }
+
+        writer.write_u8(ROAD_NAMES_SECTION.len() as u8)?;
+        writer.write_all(ROAD_NAMES_SECTION.as_bytes())?;
+        writer.write_u64::<LittleEndian>(payload.len() as u64)?;
+        writer.write_all(&payload)
+    }
+
+    /// Read the contents of the section, after its name
+    pub fn read_section<R: Read>(reader: &mut R) -> io::Result<RoadNames> {
+        let size = reader.read_u64::<LittleEndian>()?;
+        let mut payload = Vec::new();
+        reader.take(size).read_to_end(&mut payload)?;
+        if payload.len() as u64 != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut payload = payload.as_slice();
+        let names = (0..payload.read_u32::<LittleEndian>()?)
+            .map(|_| {
+                let mut name = vec![0; payload.read_u16::<LittleEndian>()? as usize];
+                payload.read_exact(&mut name)?;
+                String::from_utf8(name)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(RoadNames(names))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn section() {
+        let names = RoadNames(vec!["CG-2".to_owned(), "Avinguda Meritxell".to_owned()]);
+        assert_eq!(names.get(0), None);
+        assert_eq!(names.get(1), Some("CG-2"));
+        assert_eq!(names.get(3), None);
+
+        let mut bytes = Vec::new();
+        names.write_section(&mut bytes).unwrap();
+        let mut reader = &bytes[1 + ROAD_NAMES_SECTION.len()..];
+        assert_eq!(RoadNames::read_section(&mut reader).unwrap(), names);
+        assert!(reader.is_empty());
+        let mut reader = &bytes[1 + ROAD_NAMES_SECTION.len()..bytes.len() - 1];
+        assert!(RoadNames::read_section(&mut reader).is_err());
+    }
+}
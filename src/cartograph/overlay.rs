@@ -0,0 +1,177 @@
+use super::cost::CostModel;
+use super::data_types::*;
+use super::deadline::{Deadline, DeadlineCheck};
+use super::error::Timeout;
+use super::Cartograph;
+use crate::utils::GeoPoint;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::{EdgeRef, VisitMap, Visitable};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// An edge that is not in the cartography, like a temporary haul road or a proposed bridge. It
+/// connects the nodes closest to its endpoints in a straight line and its duration is estimated as
+/// if it were a road of the smallest level
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExtraEdge {
+    pub from: GeoPoint,
+    pub to: GeoPoint,
+    /// Whether it can also be traversed from `to` to `from`
+    pub bidirectional: bool,
+}
+
+/// An edge leaving a node in the search: its target, cost, distance, duration and index in the
+/// graph, which the extra edges do not have
+type Arc = (NodeIndex, u32, u32, f64, Option<EdgeIndex>);
+
+/// Find the cheapest path between two projected points in the graph merged with the extra edges,
+/// that are only kept for this search. The graph edges are weighed with `cost`, while the extra
+/// ones cost their distance. Gives up with `Timeout` when the deadline passes
+pub fn cheapest_path<C: CostModel + ?Sized>(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &ProjectedPoint,
+    extra_edges: &[ExtraEdge],
+    cost: &C,
+    deadline: &Deadline,
+) -> Result<Option<GraphPath>, Timeout> {
+    let graph = &carto.graph;
+    let check = DeadlineCheck::new(deadline);
+
+    // Connect the extra edges to the graph
+    let mut overlay: HashMap<NodeIndex, Vec<Arc>> = HashMap::new();
+    for extra_edge in extra_edges {
+        let a = nearest_node(carto, &extra_edge.from);
+        let b = nearest_node(carto, &extra_edge.to);
+        if a == b {
+            continue;
+        }
+        let distance = graph[a].haversine_distance(&graph[b]).round() as u32;
+        let duration = estimate_duration(distance, 5);
+        overlay
+            .entry(a)
+            .or_default()
+            .push((b, distance, distance, duration, None));
+        if extra_edge.bidirectional {
+            overlay
+                .entry(b)
+                .or_default()
+                .push((a, distance, distance, duration, None));
+        }
+    }
+
+    // Run A* search from graph nodes. The heuristic of the model is still a lower bound, since the
+    // extra edges are straight lines
+    let start_node = graph.edge_endpoints(from.edge).unwrap().1;
+    let end_node = graph.edge_endpoints(to.edge).unwrap().0;
+    let end_node_point = graph[end_node];
+    let estimate = |node: NodeIndex| cost.heuristic(&graph[node], &end_node_point);
+
+    let mut visited = graph.visit_map();
+    let mut visit_next = BinaryHeap::new();
+    let mut scores = HashMap::new();
+    let mut previous = HashMap::new();
+    scores.insert(start_node, 0);
+    visit_next.push(Reverse((estimate(start_node), start_node)));
+
+    while let Some(Reverse((_, node))) = visit_next.pop() {
+        if check.step() {
+            break;
+        }
+        if node == end_node {
+            // Walk back the traversed edges, with the node each one reaches
+            let mut steps = Vec::new();
+            let mut distance = 0;
+            let mut duration = carto.endpoints_duration(from, to);
+            let mut current = node;
+            while let Some(&(prev, edge_distance, edge_duration, edge)) = previous.get(&current) {
+                steps.push((current, edge));
+                distance += edge_distance;
+                duration += edge_duration;
+                current = prev;
+            }
+
+            // The graph edges follow their shapes, while the extra ones are straight and are not
+            // in the traversed edges
+            let mut points = Vec::with_capacity(steps.len() + 3);
+            let mut edges = Vec::with_capacity(steps.len() + 2);
+            points.push(from.projected);
+            carto.push_partial_points(from.edge, from.edge_pos, 1., &mut points);
+            points.push(graph[start_node]);
+            edges.push(from.edge);
+            for (next, edge) in steps.into_iter().rev() {
+                match edge {
+                    Some(edge) => {
+                        carto.push_edge_points(edge, &mut points);
+                        edges.push(edge);
+                    }
+                    None => points.push(graph[next]),
+                }
+            }
+            carto.push_partial_points(to.edge, 0., to.edge_pos, &mut points);
+            points.push(to.projected);
+            edges.push(to.edge);
+
+            let extra_start_cost = partial_distance(graph[from.edge].distance, 1. - from.edge_pos);
+            let extra_end_cost = partial_distance(graph[to.edge].distance, to.edge_pos);
+            let distance = extra_start_cost + distance + extra_end_cost;
+            return Ok(Some(
+                GraphPath::new(distance, duration, points).with_edges(edges),
+            ));
+        }
+
+        if !visited.visit(node) {
+            continue;
+        }
+
+        let node_score = scores[&node];
+        let graph_edges = graph
+            .edges(node)
+            .filter(|edge| carto.is_routable(edge.weight()))
+            .map(|edge| {
+                let info = edge.weight();
+                let edge_cost =
+                    cost.indexed_cost(edge.id(), info, &graph[node], &graph[edge.target()]);
+                (
+                    edge.target(),
+                    edge_cost,
+                    info.distance,
+                    info.duration(),
+                    Some(edge.id()),
+                )
+            })
+            .filter(|&(_, edge_cost, _, _, _)| edge_cost != u32::MAX);
+        let overlay_edges = overlay.get(&node).into_iter().flatten().copied();
+        for (next, edge_cost, distance, duration, edge) in graph_edges.chain(overlay_edges) {
+            if visited.is_visited(&next) {
+                continue;
+            }
+            let next_score = node_score + edge_cost;
+            match scores.get(&next) {
+                Some(&score) if score <= next_score => {}
+                _ => {
+                    scores.insert(next, next_score);
+                    previous.insert(next, (node, distance, duration, edge));
+                    visit_next.push(Reverse((next_score + estimate(next), next)));
+                }
+            }
+        }
+    }
+
+    check.result()?;
+    Ok(None)
+}
+
+/// The endpoint of the closest edge that is the closest to the point
+fn nearest_node(carto: &Cartograph, point: &GeoPoint) -> NodeIndex {
+    let projected = carto.project(point);
+    let (source, target) = carto.graph.edge_endpoints(projected.edge).unwrap();
+    if projected.edge_pos < 0.5 {
+        source
+    } else {
+        target
+    }
+}
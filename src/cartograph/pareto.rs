@@ -0,0 +1,286 @@
+use super::cost::DistanceCost;
+use super::data_types::*;
+use super::edge_based::Turn;
+use super::virtual_nodes::{self, Endpoint, VirtualPath};
+use super::Cartograph;
+use crate::utils::GeoPoint;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// A criterion to be minimized by the multi-objective search
+pub trait Criterion {
+    /// The cost of traversing `edge`, given the edge that was used to arrive at its source node
+    /// (`None` at the start of the path)
+    fn cost(&self, carto: &Cartograph, prev_edge: Option<EdgeIndex>, edge: EdgeIndex) -> u32;
+
+    /// Whether the cost is spread along the edge, so that the part of the edge where a path starts
+    /// or ends costs a part of it, like the distance. Otherwise, it is paid whole when entering the
+    /// edge, like a turn
+    fn along_edge(&self) -> bool {
+        true
+    }
+}
+
+/// The distance in meters
+pub struct Distance;
+
+/// The number of turns sharper than `min_angle` degrees, in both directions
+pub struct Turns {
+    pub min_angle: f64,
+}
+
+/// The total ascent in meters, given a function that returns the elevation of a point
+pub struct Ascent<F: Fn(&GeoPoint) -> f64>(pub F);
+
+impl Criterion for Distance {
+    fn cost(&self, carto: &Cartograph, _prev_edge: Option<EdgeIndex>, edge: EdgeIndex) -> u32 {
+        carto.graph[edge].distance
+    }
+}
+
+impl Criterion for Turns {
+    fn cost(&self, carto: &Cartograph, prev_edge: Option<EdgeIndex>, edge: EdgeIndex) -> u32 {
+        match prev_edge {
+            None => 0,
+            Some(prev_edge) => {
+                let turn = Turn {
+                    from: prev_edge,
+                    to: edge,
+                    via: carto.graph.edge_endpoints(edge).unwrap().0,
+                    carto,
+                };
+                (turn.angle().abs() > self.min_angle) as u32
+            }
+        }
+    }
+
+    fn along_edge(&self) -> bool {
+        false
+    }
+}
+
+impl<F: Fn(&GeoPoint) -> f64> Criterion for Ascent<F> {
+    fn cost(&self, carto: &Cartograph, _prev_edge: Option<EdgeIndex>, edge: EdgeIndex) -> u32 {
+        let (source, target) = carto.graph.edge_endpoints(edge).unwrap();
+        let ascent = (self.0)(&carto.graph[target]) - (self.0)(&carto.graph[source]);
+        ascent.max(0.).round() as u32
+    }
+}
+
+/// One of the paths of the Pareto front
+#[derive(Clone, Debug)]
+pub struct ParetoPath {
+    /// The value of each criterion for this path
+    pub costs: [u32; 2],
+    pub path: GraphPath,
+}
+
+/// A partial path in the search
+struct Label {
+    costs: [u32; 2],
+    node: NodeIndex,
+    edge: Option<EdgeIndex>,
+    prev: Option<usize>,
+    /// Where the path leaves the first point
+    start: Endpoint,
+    dominated: bool,
+}
+
+/// A path that reaches the second point: from the node of a label or, without one, directly from
+/// a start on the same edge
+struct Final {
+    costs: [u32; 2],
+    label: Option<usize>,
+    start: Endpoint,
+    end: Endpoint,
+}
+
+/// Return whether `a` epsilon-dominates `b`, that is, `b` is not better than `a` by a factor of
+/// more than `1 + epsilon` in any criterion
+fn dominates(a: [u32; 2], b: [u32; 2], epsilon: f64) -> bool {
+    a.iter()
+        .zip(b.iter())
+        .all(|(&a, &b)| a as f64 <= (1. + epsilon) * b as f64)
+}
+
+/// Search for all the Pareto-optimal paths between two points, using a label-setting algorithm.
+/// A path is only kept if no other path is better in both criteria by a factor of `1 + epsilon`,
+/// so larger values of `epsilon` return fewer paths and run faster. Like the other searches, the
+/// paths may leave and reach the points on the opposite edges of two-way roads
+pub fn pareto_paths(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &ProjectedPoint,
+    criteria: [&dyn Criterion; 2],
+    epsilon: f64,
+) -> Vec<ParetoPath> {
+    let edge_costs = |prev_edge, edge, fraction: f32| {
+        let mut costs = [0; 2];
+        for (cost, criterion) in costs.iter_mut().zip(criteria.iter()) {
+            *cost = criterion.cost(carto, prev_edge, edge);
+            if criterion.along_edge() {
+                *cost = (*cost as f32 * fraction) as u32;
+            }
+        }
+        costs
+    };
+    let add = |a: [u32; 2], b: [u32; 2]| [a[0] + b[0], a[1] + b[1]];
+    let keep_final = |finals: &mut Vec<Final>, new: Final| {
+        if !finals
+            .iter()
+            .any(|f| dominates(f.costs, new.costs, epsilon))
+        {
+            finals.retain(|f| !dominates(new.costs, f.costs, 0.));
+            finals.push(new);
+        }
+    };
+
+    let starts = virtual_nodes::endpoints(carto, from, &DistanceCost);
+    let ends = virtual_nodes::endpoints(carto, to, &DistanceCost);
+
+    // The points on the same edge are also joined without leaving it
+    let mut finals: Vec<Final> = Vec::new();
+    for start in &starts {
+        for end in &ends {
+            if start.edge == end.edge && start.edge_pos <= end.edge_pos {
+                let costs = edge_costs(None, start.edge, end.edge_pos - start.edge_pos);
+                keep_final(
+                    &mut finals,
+                    Final {
+                        costs,
+                        label: None,
+                        start: *start,
+                        end: *end,
+                    },
+                );
+            }
+        }
+    }
+
+    // The cost of a criterion that is not along the edges, like the turns, also depends on the edge
+    // used to arrive at a node, so the labels only compete with the ones arriving by the same edge
+    let by_edge = criteria.iter().any(|criterion| !criterion.along_edge());
+    let front_key = |node, edge| (node, if by_edge { Some(edge) } else { None });
+
+    let mut labels = Vec::new();
+    let mut fronts: HashMap<(NodeIndex, Option<EdgeIndex>), Vec<usize>> = HashMap::new();
+    let mut visit_next = BinaryHeap::new();
+    for start in &starts {
+        let node = carto.graph.edge_endpoints(start.edge).unwrap().1;
+        let costs = edge_costs(None, start.edge, 1. - start.edge_pos);
+        fronts
+            .entry(front_key(node, start.edge))
+            .or_default()
+            .push(labels.len());
+        visit_next.push(Reverse((costs, labels.len())));
+        labels.push(Label {
+            costs,
+            node,
+            edge: Some(start.edge),
+            prev: None,
+            start: *start,
+            dominated: false,
+        });
+    }
+
+    while let Some(Reverse((costs, label_id))) = visit_next.pop() {
+        if labels[label_id].dominated {
+            continue;
+        }
+        let node = labels[label_id].node;
+        let prev_edge = labels[label_id].edge;
+        let start = labels[label_id].start;
+
+        for end in &ends {
+            if carto.graph.edge_endpoints(end.edge).unwrap().0 == node {
+                let costs = add(costs, edge_costs(prev_edge, end.edge, end.edge_pos));
+                keep_final(
+                    &mut finals,
+                    Final {
+                        costs,
+                        label: Some(label_id),
+                        start,
+                        end: *end,
+                    },
+                );
+            }
+        }
+
+        for edge in carto.graph.edges(node) {
+            if !carto.is_routable(edge.weight()) {
+                continue;
+            }
+            let next = edge.target();
+            let next_costs = add(costs, edge_costs(prev_edge, edge.id(), 1.));
+
+            // The final costs can only be larger than the current ones
+            if finals
+                .iter()
+                .any(|f| dominates(f.costs, next_costs, epsilon))
+            {
+                continue;
+            }
+
+            let front = fronts.entry(front_key(next, edge.id())).or_default();
+            if front
+                .iter()
+                .any(|&other| dominates(labels[other].costs, next_costs, epsilon))
+            {
+                continue;
+            }
+            front.retain(|&other| {
+                let keep = !dominates(next_costs, labels[other].costs, 0.);
+                if !keep {
+                    labels[other].dominated = true;
+                }
+                keep
+            });
+
+            let next_id = labels.len();
+            front.push(next_id);
+            labels.push(Label {
+                costs: next_costs,
+                node: next,
+                edge: Some(edge.id()),
+                prev: Some(label_id),
+                start,
+                dominated: false,
+            });
+            visit_next.push(Reverse((next_costs, next_id)));
+        }
+    }
+
+    finals.sort_by_key(|f| f.costs);
+    finals
+        .into_iter()
+        .map(|f| {
+            // Walk back the labels, collecting their nodes and the edges between them
+            let mut nodes = Vec::new();
+            let mut inner = Vec::new();
+            let mut label = f.label;
+            while let Some(id) = label {
+                nodes.push(labels[id].node);
+                if labels[id].prev.is_some() {
+                    inner.push(labels[id].edge.unwrap());
+                }
+                label = labels[id].prev;
+            }
+            nodes.reverse();
+            inner.reverse();
+
+            let path = VirtualPath {
+                cost: f.costs[0],
+                start: f.start,
+                end: f.end,
+                nodes,
+                inner,
+            };
+            ParetoPath {
+                costs: f.costs,
+                path: carto.graph_path(from, to, &path),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,117 @@
+use crate::utils::format_bytes;
+use crc32fast::Hasher;
+use std::fmt;
+use std::io::{self, Read};
+use std::time::Duration;
+
+/// A stage of the loading of a cartography, reported by `Cartograph::open_with_progress()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadProgress {
+    /// Another column of the file was decoded. `total_bytes` is the size of the file, when known
+    Decoded {
+        bytes: u64,
+        total_bytes: Option<u64>,
+    },
+    /// All the nodes were inserted in the graph
+    NodesBuilt { num_nodes: usize },
+    /// All the edges were inserted in the graph
+    EdgesBuilt { num_edges: usize },
+    /// The spatial index of the edges was created
+    IndexBuilt,
+    /// The strongly connected components were labeled. This is the last stage
+    ComponentsLabeled,
+}
+
+/// How long each phase of the loading of a cartography took, returned by `Cartograph::open_with()`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LoadReport {
+    /// The size of the file
+    pub bytes: u64,
+    /// Reading and decompressing the columns and sections, and verifying the checksum
+    pub decode: Duration,
+    /// Inserting the nodes and edges in the graph
+    pub graph_insert: Duration,
+    /// Projecting the edges to Web Mercator
+    pub project: Duration,
+    /// Building the spatial index of the edges
+    pub rtree_build: Duration,
+    /// Labeling the strongly connected components
+    pub components: Duration,
+}
+
+impl LoadReport {
+    pub fn total(&self) -> Duration {
+        self.decode + self.graph_insert + self.project + self.rtree_build + self.components
+    }
+
+    /// The name and duration of each phase, in the order they run
+    pub fn phases(&self) -> [(&'static str, Duration); 5] {
+        [
+            ("decode", self.decode),
+            ("graph insert", self.graph_insert),
+            ("project", self.project),
+            ("rtree build", self.rtree_build),
+            ("components", self.components),
+        ]
+    }
+}
+
+/// A table with the duration and share of each phase
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        writeln!(
+            f,
+            "Loaded {} in {:.3}s",
+            format_bytes(self.bytes),
+            total.as_secs_f64()
+        )?;
+        writeln!(f, "{:<14} {:>9} {:>7}", "Phase", "Duration", "Share")?;
+        for (name, duration) in self.phases().iter() {
+            let share = if total.as_nanos() == 0 {
+                0.
+            } else {
+                duration.as_secs_f64() / total.as_secs_f64() * 100.
+            };
+            writeln!(
+                f,
+                "{:<14} {:>8.3}s {:>6.1}%",
+                name,
+                duration.as_secs_f64(),
+                share
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Wrap a reader to count how many bytes were read from it and compute their checksum
+pub struct CountingReader<R> {
+    inner: R,
+    pub bytes: u64,
+    hasher: Hasher,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R) -> Self {
+        CountingReader {
+            inner,
+            bytes: 0,
+            hasher: Hasher::new(),
+        }
+    }
+
+    /// The CRC-32 of the bytes read so far
+    pub fn checksum(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.bytes += len as u64;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+}
@@ -0,0 +1,207 @@
+use super::cost::DistanceCost;
+use super::data_types::*;
+use super::deadline::{Deadline, DeadlineCheck};
+use super::diversity::{self, mix};
+use super::error::Timeout;
+use super::Cartograph;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Maximum number of loops searched, each one scaled by how far the previous one was from the
+/// requested distance
+const MAX_ATTEMPTS: usize = 8;
+
+/// How close to the requested distance a loop must be to stop searching, as a fraction of it
+const TOLERANCE: f64 = 0.05;
+
+/// How far from the start the corners can be, as a fraction of the wanted distance to them
+const SLACK: f64 = 0.25;
+
+/// Extra cost of the roads already traveled by the previous legs, in either direction, as a
+/// fraction of their distance. This avoids coming back by the same way
+const PENALTY: f64 = 4.;
+
+/// Generate a loop that starts and ends at `start` and is about `target_distance` meters long.
+/// The loop goes around a triangle whose two other corners are about a third of the distance away
+/// by road, in directions 60 degrees apart. The distance to the corners is adjusted until the
+/// length of the loop is within 5% of the target, or the closest of the attempts is returned.
+/// The direction of the triangle only depends on `seed`, so different seeds give different loops
+/// from the same point. Return `None` if no loop could be found, for example because the start is
+/// in a dead end of the map, and give up with `Timeout` when the deadline passes
+pub fn round_trip(
+    carto: &Cartograph,
+    start: &ProjectedPoint,
+    target_distance: f64,
+    seed: u64,
+    deadline: &Deadline,
+) -> Result<Option<GraphPath>, Timeout> {
+    let bearing = 360. * (mix(seed) >> 11) as f64 / (1u64 << 53) as f64;
+    let mut radius = target_distance / 3.;
+    let mut best: Option<GraphPath> = None;
+    for _ in 0..MAX_ATTEMPTS {
+        let reached = reachable(carto, start, (radius * (1. + SLACK)) as u32, deadline)?;
+        let corners: Option<Vec<_>> = [bearing - 30., bearing + 30.]
+            .iter()
+            .map(|&bearing| corner(carto, start, &reached, bearing, radius))
+            .collect();
+        let path = match corners {
+            Some(corners) => search_loop(carto, start, &corners, deadline)?,
+            None => None,
+        };
+        let path = match path {
+            Some(path) => path,
+            None => break,
+        };
+
+        let error = (path.distance as f64 - target_distance).abs();
+        let done = error <= TOLERANCE * target_distance || path.distance == 0;
+        radius *= (target_distance / (path.distance as f64).max(1.)).min(2.);
+        if best
+            .as_ref()
+            .is_none_or(|best| error < (best.distance as f64 - target_distance).abs())
+        {
+            best = Some(path);
+        }
+        if done {
+            break;
+        }
+    }
+    Ok(best)
+}
+
+/// The distance by road from `start` to the nodes that are at most `max_distance` away
+fn reachable(
+    carto: &Cartograph,
+    start: &ProjectedPoint,
+    max_distance: u32,
+    deadline: &Deadline,
+) -> Result<HashMap<NodeIndex, u32>, Timeout> {
+    let graph = &carto.graph;
+    let check = DeadlineCheck::new(deadline);
+    let mut scores = HashMap::new();
+    let mut visit_next = BinaryHeap::new();
+    let node = graph.edge_endpoints(start.edge).unwrap().1;
+    let score = partial_distance(graph[start.edge].distance, 1. - start.edge_pos);
+    if score <= max_distance {
+        scores.insert(node, score);
+        visit_next.push(Reverse((score, node)));
+    }
+
+    while let Some(Reverse((score, node))) = visit_next.pop() {
+        if check.step() {
+            break;
+        }
+        if score > scores[&node] {
+            continue;
+        }
+        for edge in graph.edges(node) {
+            if !carto.is_routable(edge.weight()) {
+                continue;
+            }
+            let next_score = score + edge.weight().distance;
+            if next_score > max_distance {
+                continue;
+            }
+            match scores.entry(edge.target()) {
+                Entry::Occupied(entry) if *entry.get() <= next_score => continue,
+                Entry::Occupied(mut entry) => {
+                    entry.insert(next_score);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(next_score);
+                }
+            }
+            visit_next.push(Reverse((next_score, edge.target())));
+        }
+    }
+
+    check.result()?;
+    Ok(scores)
+}
+
+/// Pick the reached node closest to the given bearing among the ones about `radius` meters away by
+/// road, or the farthest one if none is
+fn corner(
+    carto: &Cartograph,
+    start: &ProjectedPoint,
+    reached: &HashMap<NodeIndex, u32>,
+    bearing: f64,
+    radius: f64,
+) -> Option<ProjectedPoint> {
+    let graph = &carto.graph;
+    let deviation = |node: NodeIndex| {
+        let delta = (start.projected.bearing(&graph[node]) - bearing).rem_euclid(360.);
+        delta.min(360. - delta)
+    };
+    let in_ring: Vec<NodeIndex> = reached
+        .iter()
+        .filter(|&(_, &distance)| (distance as f64 - radius).abs() <= SLACK * radius)
+        .map(|(&node, _)| node)
+        .collect();
+    // Break the ties by the node index, because the order of the map is random
+    let node = if in_ring.is_empty() {
+        reached
+            .iter()
+            .max_by_key(|&(&node, &distance)| (distance, Reverse(node)))
+            .map(|(&node, _)| node)
+    } else {
+        in_ring.into_iter().min_by(|&a, &b| {
+            deviation(a)
+                .partial_cmp(&deviation(b))
+                .unwrap()
+                .then(a.cmp(&b))
+        })
+    }?;
+    Some(carto.project(&graph[node]))
+}
+
+/// Search the legs from `start` through each corner and back, penalizing the roads of the previous
+/// legs
+fn search_loop(
+    carto: &Cartograph,
+    start: &ProjectedPoint,
+    corners: &[ProjectedPoint],
+    deadline: &Deadline,
+) -> Result<Option<GraphPath>, Timeout> {
+    let graph = &carto.graph;
+    // The traveled roads, as pairs of nodes in increasing order to cover both directions
+    let mut traveled: HashSet<(NodeIndex, NodeIndex)> = HashSet::new();
+    let road = |edge: EdgeIndex| {
+        let (a, b) = graph.edge_endpoints(edge).unwrap();
+        (a.min(b), a.max(b))
+    };
+
+    let mut distance = 0;
+    let mut duration = 0.;
+    let mut points = vec![start.projected];
+    let mut path_edges = Vec::new();
+    let mut from = start;
+    for to in corners.iter().chain(Some(start)) {
+        let factor = |edge| {
+            if traveled.contains(&road(edge)) {
+                1. + PENALTY
+            } else {
+                1.
+            }
+        };
+        let leg = match diversity::search(carto, from, to, &DistanceCost, factor, deadline)? {
+            Some(leg) => leg,
+            None => return Ok(None),
+        };
+        traveled.extend(leg.edges.iter().copied().map(road));
+        distance += leg.distance;
+        duration += leg.duration;
+        points.extend_from_slice(&leg.points[1..]);
+        path_edges.extend(leg.edges);
+        from = to;
+    }
+
+    // Each leg starts on the edge where the previous one ends
+    path_edges.dedup();
+    Ok(Some(
+        GraphPath::new(distance, duration, points).with_edges(path_edges),
+    ))
+}
@@ -0,0 +1,97 @@
+use crate::utils::GeoPoint;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+
+/// The name of the file section with the shapes of the edges
+pub const EDGE_SHAPES_SECTION: &str = "shape_points";
+
+/// The intermediate points of the edges that are not a straight line between their nodes,
+/// referenced by `EdgeInfo::shape`: the index 0 is for the straight edges and the others start at
+/// 1. The generator creates them when it contracts the chains of nodes that only connect two others
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EdgeShapes(pub Vec<Vec<GeoPoint>>);
+
+impl EdgeShapes {
+    /// The points of the shape with the given index, in the direction of its edge, without the
+    /// nodes of the edge. Empty for the straight edges
+    pub fn get(&self, index: u32) -> &[GeoPoint] {
+        (index as usize)
+            .checked_sub(1)
+            .and_then(|position| self.0.get(position))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Write the shapes as a file section. Each one is prefixed by its number of points, as a
+    /// `u32`, followed by their latitude and longitude in micro degrees
+    pub fn write_section<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut payload = Vec::new();
+        payload.write_u32::<LittleEndian>(self.0.len() as u32)?;
+        for shape in &self.0 {
+            payload.write_u32::<LittleEndian>(shape.len() as u32)?;
+            for point in shape {
+                payload.write_i32::<LittleEndian>(point.lat.as_micro_degrees())?;
+                payload.write_i32::<LittleEndian>(point.lon.as_micro_degrees())?;
+            }
+        }
+
+        writer.write_u8(EDGE_SHAPES_SECTION.len() as u8)?;
+        writer.write_all(EDGE_SHAPES_SECTION.as_bytes())?;
+        writer.write_u64::<LittleEndian>(payload.len() as u64)?;
+        writer.write_all(&payload)
+    }
+
+    /// Read the contents of the section, after its name
+    pub fn read_section<R: Read>(reader: &mut R) -> io::Result<EdgeShapes> {
+        let size = reader.read_u64::<LittleEndian>()?;
+        let mut payload = Vec::new();
+        reader.take(size).read_to_end(&mut payload)?;
+        if payload.len() as u64 != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut payload = payload.as_slice();
+        let shapes = (0..payload.read_u32::<LittleEndian>()?)
+            .map(|_| {
+                (0..payload.read_u32::<LittleEndian>()?)
+                    .map(|_| {
+                        let lat = payload.read_i32::<LittleEndian>()?;
+                        let lon = payload.read_i32::<LittleEndian>()?;
+                        Ok(GeoPoint::from_micro_degrees(lat, lon))
+                    })
+                    .collect()
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(EdgeShapes(shapes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn section() {
+        let shapes = EdgeShapes(vec![
+            vec![GeoPoint::from_micro_degrees(42_506_300, 1_521_800)],
+            vec![
+                GeoPoint::from_micro_degrees(42_507_100, 1_522_500),
+                GeoPoint::from_micro_degrees(42_508_000, -1_523_100),
+            ],
+        ]);
+        assert!(shapes.get(0).is_empty());
+        assert_eq!(shapes.get(2).len(), 2);
+        assert!(shapes.get(3).is_empty());
+
+        let mut bytes = Vec::new();
+        shapes.write_section(&mut bytes).unwrap();
+        let mut reader = &bytes[1 + EDGE_SHAPES_SECTION.len()..];
+        assert_eq!(EdgeShapes::read_section(&mut reader).unwrap(), shapes);
+        assert!(reader.is_empty());
+        let mut reader = &bytes[1 + EDGE_SHAPES_SECTION.len()..bytes.len() - 1];
+        assert!(EdgeShapes::read_section(&mut reader).is_err());
+    }
+}
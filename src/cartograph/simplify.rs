@@ -0,0 +1,227 @@
+use super::data_types::EdgeInfo;
+use crate::utils::GeoPoint;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::Graph;
+use std::collections::{BTreeSet, HashMap};
+
+/// Merge the given edges into the longest possible polylines, joining two edges that share a node
+/// when the direction changes by at most `max_angle` degrees. The direction of the edges is ignored,
+/// so both edges of a two-way road are drawn once
+pub fn merge_collinear(
+    graph: &Graph<GeoPoint, EdgeInfo>,
+    edges: &[EdgeIndex],
+    max_angle: f64,
+) -> Vec<Vec<GeoPoint>> {
+    // Collect the segments, sorted so that the result does not depend on the order of the edges
+    let segments: BTreeSet<(NodeIndex, NodeIndex)> = edges
+        .iter()
+        .map(|&edge| {
+            let (a, b) = graph.edge_endpoints(edge).unwrap();
+            (a.min(b), a.max(b))
+        })
+        .filter(|(a, b)| a != b)
+        .collect();
+    let segments: Vec<_> = segments.into_iter().collect();
+    let mut segments_by_node: HashMap<NodeIndex, Vec<usize>> = HashMap::new();
+    for (i, &(a, b)) in segments.iter().enumerate() {
+        segments_by_node.entry(a).or_default().push(i);
+        segments_by_node.entry(b).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut polylines = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let (a, b) = segments[start];
+
+        // Grow the polyline from its last node, then reverse it and grow from the other end
+        let mut nodes = vec![a, b];
+        for _ in 0..2 {
+            loop {
+                let last = nodes[nodes.len() - 1];
+                let before = nodes[nodes.len() - 2];
+                let bearing = graph[before].bearing(&graph[last]);
+                let next = segments_by_node[&last]
+                    .iter()
+                    .filter(|&&i| !used[i])
+                    .map(|&i| {
+                        let (a, b) = segments[i];
+                        let other = if a == last { b } else { a };
+                        let turn = turn_angle(bearing, graph[last].bearing(&graph[other]));
+                        (i, other, turn)
+                    })
+                    .filter(|&(_, _, turn)| turn <= max_angle)
+                    .min_by(|x, y| x.2.partial_cmp(&y.2).unwrap());
+                match next {
+                    Some((i, other, _)) => {
+                        used[i] = true;
+                        nodes.push(other);
+                    }
+                    None => break,
+                }
+            }
+            nodes.reverse();
+        }
+
+        polylines.push(nodes.into_iter().map(|node| graph[node]).collect());
+    }
+
+    polylines
+}
+
+/// The absolute change of direction between two bearings, in degrees from 0 to 180
+fn turn_angle(from: f64, to: f64) -> f64 {
+    let diff = (to - from).rem_euclid(360.);
+    diff.min(360. - diff)
+}
+
+/// Simplify a line with the Ramer-Douglas-Peucker algorithm, removing the points that are closer
+/// than `tolerance` meters to the simplified line. The first and last points are always kept
+pub fn ramer_douglas_peucker(points: &[GeoPoint], tolerance: f64) -> Vec<GeoPoint> {
+    if points.len() <= 2 {
+        return points.to_vec();
+    }
+
+    // Work in Web Mercator, that stretches the distances by 1 / cos(latitude)
+    let tolerance = tolerance / mean_latitude(points).cos();
+    let xy: Vec<[f64; 2]> = points.iter().map(GeoPoint::web_mercator_project).collect();
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    // Iterative, so that long routes do not overflow the stack
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = ranges.pop() {
+        let farthest = (start + 1..end)
+            .map(|i| (i, segment_distance(xy[i], xy[start], xy[end])))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                ranges.push((start, i));
+                ranges.push((i, end));
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter(|&(_, keep)| keep)
+        .map(|(&point, _)| point)
+        .collect()
+}
+
+/// The tolerance, in meters, to simplify a line to draw it whole: the size of a pixel at the
+/// highest zoom level where the line fits in 1024 pixels, like the simplified overview of OSRM
+pub fn overview_tolerance(points: &[GeoPoint]) -> f64 {
+    const VIEWPORT_PIXELS: f64 = 1024.;
+    const TILE_PIXELS: f64 = 256.;
+    const MAX_ZOOM: f64 = 18.;
+    let world = 2. * std::f64::consts::PI * 6_378_137.;
+
+    let xy: Vec<[f64; 2]> = points.iter().map(GeoPoint::web_mercator_project).collect();
+    let span = |axis: usize| {
+        let values = xy.iter().map(|p| p[axis]);
+        values.clone().fold(f64::MIN, f64::max) - values.fold(f64::MAX, f64::min)
+    };
+    let span = span(0).max(span(1));
+    let zoom = (VIEWPORT_PIXELS / TILE_PIXELS * world / span)
+        .log2()
+        .floor()
+        .clamp(0., MAX_ZOOM);
+    let pixel = world / (TILE_PIXELS * zoom.exp2());
+    pixel * mean_latitude(points).cos()
+}
+
+/// The distance in meters from a point to the closest segment of a line, or infinity for an empty
+/// line. The segments are straight in Web Mercator, scaled at the latitude of the point
+pub fn distance_to_line(point: &GeoPoint, line: &[GeoPoint]) -> f64 {
+    let xy = point.web_mercator_project();
+    let line: Vec<[f64; 2]> = line.iter().map(GeoPoint::web_mercator_project).collect();
+    let closest = match line.len() {
+        0 => return f64::INFINITY,
+        1 => segment_distance(xy, line[0], line[0]),
+        _ => line
+            .windows(2)
+            .map(|pair| segment_distance(xy, pair[0], pair[1]))
+            .fold(f64::INFINITY, f64::min),
+    };
+    closest * point.lat.as_radians().cos()
+}
+
+fn mean_latitude(points: &[GeoPoint]) -> f64 {
+    points
+        .iter()
+        .map(|point| point.lat.as_radians())
+        .sum::<f64>()
+        / points.len() as f64
+}
+
+/// The distance from `p` to the segment from `a` to `b`
+fn segment_distance(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let length_2 = dx * dx + dy * dy;
+    let t = if length_2 == 0. {
+        0.
+    } else {
+        (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / length_2).clamp(0., 1.)
+    };
+    let (x, y) = (a[0] + t * dx, a[1] + t * dy);
+    ((p[0] - x).powi(2) + (p[1] - y).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ramer_douglas_peucker() {
+        // About 111 m between each point, with a bump of 11 m in the middle
+        let line = vec![
+            GeoPoint::from_degrees(42.5, 1.5),
+            GeoPoint::from_degrees(42.501, 1.5),
+            GeoPoint::from_degrees(42.502, 1.5001),
+            GeoPoint::from_degrees(42.503, 1.5),
+            GeoPoint::from_degrees(42.504, 1.5),
+        ];
+        let simplified = super::ramer_douglas_peucker(&line, 20.);
+        assert_eq!(simplified, vec![line[0], line[4]]);
+        let simplified = super::ramer_douglas_peucker(&line, 5.);
+        assert_eq!(simplified, vec![line[0], line[2], line[4]]);
+        assert_eq!(super::ramer_douglas_peucker(&line[..2], 1000.), &line[..2]);
+
+        // A longer line is seen from farther away
+        let tolerance = overview_tolerance(&line);
+        assert!(tolerance > 0.1 && tolerance < 1., "{}", tolerance);
+        let far = [line[0], GeoPoint::from_degrees(43.5, 2.5)];
+        assert!(overview_tolerance(&far) > 100. * tolerance);
+    }
+
+    #[test]
+    fn distance_to_line() {
+        let line = vec![
+            GeoPoint::from_degrees(42.5, 1.5),
+            GeoPoint::from_degrees(42.501, 1.5),
+            GeoPoint::from_degrees(42.501, 1.502),
+        ];
+        // Next to the first segment, next to the second one and beyond the end. The radius of the
+        // Earth of Web Mercator is a bit larger than the one of the haversine distance
+        let beside = GeoPoint::from_degrees(42.5005, 1.5001);
+        let expected = beside.haversine_distance(&GeoPoint::from_degrees(42.5005, 1.5));
+        assert!((super::distance_to_line(&beside, &line) - expected).abs() < 0.002 * expected);
+        let above = GeoPoint::from_degrees(42.5012, 1.501);
+        let expected = above.haversine_distance(&GeoPoint::from_degrees(42.501, 1.501));
+        assert!((super::distance_to_line(&above, &line) - expected).abs() < 0.002 * expected);
+        let beyond = GeoPoint::from_degrees(42.501, 1.503);
+        let expected = beyond.haversine_distance(&line[2]);
+        assert!((super::distance_to_line(&beyond, &line) - expected).abs() < 0.002 * expected);
+
+        assert_eq!(super::distance_to_line(&line[1], &line), 0.);
+        assert!((super::distance_to_line(&beyond, &line[2..]) - expected).abs() < 0.002 * expected);
+        assert_eq!(super::distance_to_line(&beyond, &[]), f64::INFINITY);
+    }
+}
@@ -0,0 +1,196 @@
+use super::Cartograph;
+use petgraph::graph::EdgeIndex;
+use petgraph::visit::EdgeRef;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// The roads that a speed update applies to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpeedTarget {
+    /// All the edges of an OSM way, by its id
+    Way(i64),
+    /// A single edge, by its index
+    Edge(u32),
+}
+
+/// A speed measured on a road, like by a traffic feed, that replaces its default speed for `ttl`
+/// seconds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpeedUpdate {
+    pub target: SpeedTarget,
+    /// The speed, in km/h
+    pub speed: f64,
+    pub ttl: u64,
+}
+
+/// A batch of speed updates, in the order they were received.
+///
+/// They are read from CSV lines with an OSM way (`way:4199`) or an edge index (`edge:120`), the
+/// speed in km/h and how long it applies, in seconds, like `way:4199,25.5,600`. Empty lines and the
+/// ones starting with `#` are skipped
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpeedUpdates(pub Vec<SpeedUpdate>);
+
+impl FromStr for SpeedUpdates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut updates = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("Invalid speed at line {}: {}", i + 1, message);
+            let values: Vec<&str> = line.split(',').map(str::trim).collect();
+            if values.len() != 3 {
+                return Err(error(&format!("expected 3 values, got {}", values.len())));
+            }
+            let target = if let Some(way) = values[0]
+                .strip_prefix("way:")
+                .and_then(|id| id.parse().ok())
+            {
+                SpeedTarget::Way(way)
+            } else if let Some(edge) = values[0]
+                .strip_prefix("edge:")
+                .and_then(|id| id.parse().ok())
+            {
+                SpeedTarget::Edge(edge)
+            } else {
+                return Err(error("expected way:<OSM id> or edge:<index>"));
+            };
+            let speed = values[1]
+                .parse::<f64>()
+                .ok()
+                .filter(|speed| speed.is_finite() && *speed > 0.)
+                .ok_or_else(|| error(&format!("the speed {} is not positive", values[1])))?;
+            let ttl = values[2]
+                .parse::<u64>()
+                .map_err(|_| error(&format!("the ttl {} is not a number of seconds", values[2])))?;
+            updates.push(SpeedUpdate { target, speed, ttl });
+        }
+        Ok(SpeedUpdates(updates))
+    }
+}
+
+/// A speed that replaces the default one of an edge until it expires
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpeedOverride {
+    /// The speed, in km/h
+    pub speed: f64,
+    /// When it stops applying, in seconds since the Unix epoch
+    pub expires: u64,
+}
+
+/// The speeds that replace the default ones of the edges, like the ones of a traffic feed. Each
+/// update adds to the previous ones, and they expire on their own
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpeedOverrides {
+    /// The overridden edges, by their index
+    pub edges: HashMap<u32, SpeedOverride>,
+}
+
+impl SpeedOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    /// Apply the updates received at the given time, in seconds since the Unix epoch, replacing the
+    /// previous speeds of the same edges, and forget the expired ones. The ways are replaced by
+    /// their edges, visiting the whole graph, and are only known when the cartography was
+    /// generated with `--osm-ids`. The edges that are not in the graph are ignored. Return how many
+    /// edges were updated
+    pub fn update(&mut self, carto: &Cartograph, updates: &[SpeedUpdate], now: u64) -> usize {
+        let mut by_way: HashMap<i64, SpeedOverride> = HashMap::new();
+        let mut updated = 0;
+        for update in updates {
+            let speed = SpeedOverride {
+                speed: update.speed,
+                expires: now.saturating_add(update.ttl),
+            };
+            match update.target {
+                SpeedTarget::Way(way) => {
+                    by_way.insert(way, speed);
+                }
+                SpeedTarget::Edge(edge) if (edge as usize) < carto.graph.edge_count() => {
+                    self.edges.insert(edge, speed);
+                    updated += 1;
+                }
+                SpeedTarget::Edge(_) => {}
+            }
+        }
+        if !by_way.is_empty() {
+            for edge in carto.graph.edge_references() {
+                let way_id = edge.weight().way_id;
+                if let Some(&speed) = by_way.get(&way_id).filter(|_| way_id != 0) {
+                    self.edges.insert(edge.id().index() as u32, speed);
+                    updated += 1;
+                }
+            }
+        }
+        self.edges.retain(|_, speed| speed.expires > now);
+        updated
+    }
+
+    /// The speeds that apply at the given time, by edge
+    pub fn active_speeds(&self, now: u64) -> HashMap<EdgeIndex, f64> {
+        self.edges
+            .iter()
+            .filter(|(_, speed)| speed.expires > now)
+            .map(|(&edge, speed)| (EdgeIndex::new(edge as usize), speed.speed))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let updates: SpeedUpdates = "# Jam\nway:4199,12.5,600\n\nedge:7,90,60".parse().unwrap();
+        assert_eq!(
+            updates.0,
+            vec![
+                SpeedUpdate {
+                    target: SpeedTarget::Way(4199),
+                    speed: 12.5,
+                    ttl: 600
+                },
+                SpeedUpdate {
+                    target: SpeedTarget::Edge(7),
+                    speed: 90.,
+                    ttl: 60
+                }
+            ]
+        );
+
+        assert!("node:7,90,60".parse::<SpeedUpdates>().is_err());
+        assert!("edge:7,90".parse::<SpeedUpdates>().is_err());
+        assert!("edge:7,0,60".parse::<SpeedUpdates>().is_err());
+        assert!("edge:7,90,-1".parse::<SpeedUpdates>().is_err());
+    }
+
+    #[test]
+    fn update() {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let updates: SpeedUpdates =
+            "edge:7,20,100\nedge:9,30,200\nedge:99999999,30,200\nway:4199,5,100"
+                .parse()
+                .unwrap();
+        let mut overrides = SpeedOverrides::default();
+        // The file has no way ids, so updating a way updates nothing
+        assert_eq!(overrides.update(&carto, &updates.0, 1000), 2);
+        let speeds = overrides.active_speeds(1050);
+        assert_eq!(speeds.len(), 2);
+        assert_eq!(speeds[&EdgeIndex::new(7)], 20.);
+        assert_eq!(overrides.active_speeds(1100).len(), 1);
+
+        // A later update replaces the speed and forgets the expired ones
+        let updates: SpeedUpdates = "edge:9,45,10".parse().unwrap();
+        assert_eq!(overrides.update(&carto, &updates.0, 1150), 1);
+        assert_eq!(overrides.edges.len(), 1);
+        assert_eq!(overrides.active_speeds(1155)[&EdgeIndex::new(9)], 45.);
+        assert!(overrides.active_speeds(1160).is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+use super::cost::DistanceCost;
+use super::data_types::*;
+use super::deadline::{Deadline, DeadlineCheck};
+use super::error::Timeout;
+use super::virtual_nodes::{self, Endpoint, VirtualPath};
+use super::Cartograph;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::str::FromStr;
+
+/// The number of factors of a profile, one for each hour from midnight
+pub const HOURS: usize = 24;
+
+/// Factors of the default speed of the roads at each hour of the day, like 0.5 at rush hour, to find
+/// the fastest path for a departure time. The edges without a profile of their own use the one of
+/// their road level, or always keep their default speed.
+///
+/// They are read from CSV lines of 25 values: `level:3` or `edge:4199`, then the factors from
+/// midnight to 23:00, like `level:1,1,1,1,1,1,1,0.8,0.5,0.7,...`. Empty lines and the ones
+/// starting with `#` are skipped
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpeedProfiles {
+    /// The profile of each road level
+    pub road_levels: HashMap<u8, [f64; HOURS]>,
+    /// The profile of single edges, by their index
+    pub edges: HashMap<u32, [f64; HOURS]>,
+}
+
+impl SpeedProfiles {
+    /// The factor of the speed of an edge at a time of day, in seconds from midnight. It changes
+    /// linearly from one hour to the next
+    pub fn factor(&self, edge: EdgeIndex, info: &EdgeInfo, time: f64) -> f64 {
+        let profile = match self
+            .edges
+            .get(&(edge.index() as u32))
+            .or_else(|| self.road_levels.get(&info.road_level))
+        {
+            Some(profile) => profile,
+            None => return 1.,
+        };
+        let hours = (time / 3600.).rem_euclid(HOURS as f64);
+        let hour = hours.floor() as usize % HOURS;
+        let next = profile[(hour + 1) % HOURS];
+        profile[hour] + hours.fract() * (next - profile[hour])
+    }
+
+    /// The time to traverse a whole edge, in seconds, when entering it at a time of day
+    pub fn duration(&self, edge: EdgeIndex, info: &EdgeInfo, time: f64) -> f64 {
+        info.duration() / self.factor(edge, info, time)
+    }
+}
+
+impl FromStr for SpeedProfiles {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut profiles = SpeedProfiles::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error =
+                |message: &str| format!("Invalid speed profile at line {}: {}", i + 1, message);
+            let values: Vec<&str> = line.split(',').map(str::trim).collect();
+            if values.len() != HOURS + 1 {
+                return Err(error(&format!(
+                    "expected {} values, got {}",
+                    HOURS + 1,
+                    values.len()
+                )));
+            }
+            let mut profile = [0.; HOURS];
+            for (factor, value) in profile.iter_mut().zip(&values[1..]) {
+                *factor = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|factor| factor.is_finite() && *factor > 0.)
+                    .ok_or_else(|| error(&format!("the factor {} is not positive", value)))?;
+            }
+            let parse_key = |prefix: &str| values[0].strip_prefix(prefix)?.parse().ok();
+            if let Some(level) = parse_key("level:") {
+                profiles.road_levels.insert(level as u8, profile);
+            } else if let Some(edge) = parse_key("edge:") {
+                profiles.edges.insert(edge, profile);
+            } else {
+                return Err(error("expected level:<road level> or edge:<index>"));
+            }
+        }
+        Ok(profiles)
+    }
+}
+
+/// How a node was reached by the search
+#[derive(Clone, Copy, Debug)]
+enum Parent {
+    Start(Endpoint),
+    Edge(EdgeIndex),
+}
+
+/// Find the fastest path between two projected points when leaving at a time of day, in seconds
+/// from midnight. The speed of each edge is the one when it is entered, so this is a Dijkstra search
+/// on the arrival times, that never traverses the closed edges. The known speeds, in km/h, replace
+/// the profiles of their edges. The returned duration is the one with the speeds. Returns `None` if
+/// the destination is not reachable, and gives up with `Timeout` when the deadline passes
+#[allow(clippy::too_many_arguments)]
+pub fn fastest_path(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &ProjectedPoint,
+    profiles: &SpeedProfiles,
+    depart_at: f64,
+    closed: &HashSet<EdgeIndex>,
+    speeds: &HashMap<EdgeIndex, f64>,
+    deadline: &Deadline,
+) -> Result<Option<GraphPath>, Timeout> {
+    let graph = &carto.graph;
+    let check = DeadlineCheck::new(deadline);
+    let duration = |edge: EdgeIndex, time: f64| match speeds.get(&edge) {
+        Some(&speed) => graph[edge].distance as f64 * 3.6 / speed,
+        None => profiles.duration(edge, &graph[edge], depart_at + time),
+    };
+    let starts = virtual_nodes::endpoints(carto, from, &DistanceCost);
+    let ends = virtual_nodes::endpoints(carto, to, &DistanceCost);
+
+    // The earliest arrival found so far and how: directly from a start on the same edge, or from
+    // the source node of the end edge
+    let mut best: Option<(f64, Endpoint, Option<Endpoint>)> = None;
+    let improve = |best: &mut Option<_>, arrival: f64, end: Endpoint, direct: Option<Endpoint>| {
+        if best.is_none_or(|(best_arrival, _, _)| arrival < best_arrival) {
+            *best = Some((arrival, end, direct));
+        }
+    };
+    for start in &starts {
+        for end in &ends {
+            if start.edge == end.edge && start.edge_pos <= end.edge_pos {
+                let fraction = (end.edge_pos - start.edge_pos) as f64;
+                improve(
+                    &mut best,
+                    duration(start.edge, 0.) * fraction,
+                    *end,
+                    Some(*start),
+                );
+            }
+        }
+    }
+
+    // The times are never negative, so their bits are in the same order
+    let mut times: HashMap<NodeIndex, f64> = HashMap::new();
+    let mut parents: HashMap<NodeIndex, Parent> = HashMap::new();
+    let mut visit_next = BinaryHeap::new();
+    for start in &starts {
+        let node = graph.edge_endpoints(start.edge).unwrap().1;
+        let time = duration(start.edge, 0.) * (1. - start.edge_pos as f64);
+        if times.get(&node).is_none_or(|&old| time < old) {
+            times.insert(node, time);
+            parents.insert(node, Parent::Start(*start));
+            visit_next.push(Reverse((time.to_bits(), node)));
+        }
+    }
+
+    while let Some(Reverse((time, node))) = visit_next.pop() {
+        if check.step() {
+            break;
+        }
+        let time = f64::from_bits(time);
+        if time > times[&node] {
+            continue;
+        }
+        if best.is_some_and(|(best_arrival, _, _)| time >= best_arrival) {
+            break;
+        }
+
+        for end in &ends {
+            if graph.edge_endpoints(end.edge).unwrap().0 == node {
+                let arrival = time + duration(end.edge, time) * end.edge_pos as f64;
+                improve(&mut best, arrival, *end, None);
+            }
+        }
+        for edge in graph.edges(node) {
+            if !carto.is_routable(edge.weight()) || closed.contains(&edge.id()) {
+                continue;
+            }
+            let next = edge.target();
+            let next_time = time + duration(edge.id(), time);
+            if times.get(&next).is_none_or(|&old| next_time < old) {
+                times.insert(next, next_time);
+                parents.insert(next, Parent::Edge(edge.id()));
+                visit_next.push(Reverse((next_time.to_bits(), next)));
+            }
+        }
+    }
+
+    check.result()?;
+    let (arrival, end, direct) = match best {
+        Some(best) => best,
+        None => return Ok(None),
+    };
+    let path = match direct {
+        Some(start) => VirtualPath {
+            cost: arrival.round() as u32,
+            start,
+            end,
+            nodes: Vec::new(),
+            inner: Vec::new(),
+        },
+        None => {
+            let mut nodes = vec![graph.edge_endpoints(end.edge).unwrap().0];
+            let mut inner = Vec::new();
+            let start = loop {
+                match parents[nodes.last().unwrap()] {
+                    Parent::Start(start) => break start,
+                    Parent::Edge(edge) => {
+                        inner.push(edge);
+                        nodes.push(graph.edge_endpoints(edge).unwrap().0);
+                    }
+                }
+            };
+            nodes.reverse();
+            inner.reverse();
+            VirtualPath {
+                cost: arrival.round() as u32,
+                start,
+                end,
+                nodes,
+                inner,
+            }
+        }
+    };
+    let mut graph_path = carto.graph_path(from, to, &path);
+    graph_path.duration = arrival;
+    Ok(Some(graph_path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn profiles() {
+        let mut rush_hour = vec!["1"; HOURS];
+        rush_hour[8] = "0.5";
+        let csv = format!(
+            "# Slower at 8:00\nlevel:3,{}\n\nedge:7,{}\n",
+            rush_hour.join(","),
+            vec!["2"; HOURS].join(",")
+        );
+        let profiles: SpeedProfiles = csv.parse().unwrap();
+        assert_eq!(profiles.road_levels.len(), 1);
+
+        let info = EdgeInfo::new(1000, 3);
+        let edge = EdgeIndex::new(1);
+        assert_eq!(profiles.factor(edge, &info, 3. * 3600.), 1.);
+        assert_eq!(profiles.factor(edge, &info, 8. * 3600.), 0.5);
+        assert_eq!(profiles.factor(edge, &info, 8.5 * 3600.), 0.75);
+        assert_eq!(profiles.factor(edge, &info, 7.5 * 3600.), 0.75);
+        // The next day
+        assert_eq!(profiles.factor(edge, &info, 32. * 3600.), 0.5);
+        assert_eq!(
+            profiles.duration(edge, &info, 8. * 3600.),
+            2. * info.duration()
+        );
+        assert_eq!(profiles.factor(EdgeIndex::new(7), &info, 8. * 3600.), 2.);
+        assert_eq!(profiles.factor(edge, &EdgeInfo::new(1000, 2), 0.), 1.);
+
+        assert!("level:3,1,1".parse::<SpeedProfiles>().is_err());
+        let negative = format!("level:3,{}", vec!["-1"; HOURS].join(","));
+        assert!(negative.parse::<SpeedProfiles>().is_err());
+        let unknown = format!("way:3,{}", vec!["1"; HOURS].join(","));
+        assert!(unknown.parse::<SpeedProfiles>().is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use super::checksum;
+use super::StronglyConnectedComponents;
+use crate::utils::GeoPoint;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use petgraph::Graph;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// The name of the file section with the statistics
+pub const STATS_SECTION: &str = "stats";
+
+/// Summary of a road graph, to quickly inspect a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphStats {
+    pub num_nodes: u32,
+    pub num_edges: u32,
+    /// Number of strongly connected components
+    pub num_components: u32,
+    /// The south-west and north-east corners of the bounding box of all nodes
+    pub bbox: (GeoPoint, GeoPoint),
+    /// Total length of the edges in meters, indexed by the road level
+    pub length_by_level: Vec<u64>,
+    /// Number of nodes with a given number of outgoing edges, indexed by this number
+    pub degree_histogram: Vec<u32>,
+}
+
+/// An estimate of the memory used by a loaded cartography, in bytes. Only the elements are counted,
+/// not the spare capacity of the vectors nor the inner nodes of the spatial indexes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MemoryUsage {
+    /// The nodes and edges of the graph
+    pub graph: usize,
+    /// The spatial indexes of the edges, including the ones by road level once they are built
+    pub edge_index: usize,
+    /// The spatial index of the nodes, that is only built when first used
+    pub node_index: usize,
+    /// The strongly connected component of each node
+    pub components: usize,
+}
+
+/// What a path search did, to tune the heuristics
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SearchStats {
+    /// The nodes whose cheapest cost was found. A node may be settled again when the estimate of
+    /// the remaining cost grows
+    pub settled: u32,
+    /// The nodes pushed to the priority queue, including the ones pushed again with a lower cost
+    pub pushes: u32,
+    /// How long the search took, in milliseconds
+    pub time_ms: f64,
+    /// The straight-line estimate between both points divided by the cost of the path found, from
+    /// 0 to 1. The closer to 1, the fewer nodes are visited. `None` when there is no path
+    pub heuristic_tightness: Option<f64>,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.graph + self.edge_index + self.node_index + self.components
+    }
+}
+
+impl GraphStats {
+    /// Compute the statistics of any graph, given how to extract the position of a node and the
+    /// road level and distance of an edge
+    pub fn compute<N, E>(
+        graph: &Graph<N, E>,
+        point: impl Fn(&N) -> GeoPoint,
+        level_and_distance: impl Fn(&E) -> (u8, u32),
+    ) -> GraphStats {
+        let (mut min_lat, mut min_lon) = (i32::MAX, i32::MAX);
+        let (mut max_lat, mut max_lon) = (i32::MIN, i32::MIN);
+        let mut degree_histogram = Vec::new();
+        for node in graph.node_indices() {
+            let point = point(&graph[node]);
+            min_lat = min_lat.min(point.lat.as_micro_degrees());
+            min_lon = min_lon.min(point.lon.as_micro_degrees());
+            max_lat = max_lat.max(point.lat.as_micro_degrees());
+            max_lon = max_lon.max(point.lon.as_micro_degrees());
+
+            let degree = graph.edges(node).count();
+            if degree >= degree_histogram.len() {
+                degree_histogram.resize(degree + 1, 0);
+            }
+            degree_histogram[degree] += 1;
+        }
+        if graph.node_count() == 0 {
+            min_lat = 0;
+            min_lon = 0;
+            max_lat = 0;
+            max_lon = 0;
+        }
+
+        let mut length_by_level = Vec::new();
+        for edge in graph.edge_references() {
+            let (level, distance) = level_and_distance(edge.weight());
+            if level as usize >= length_by_level.len() {
+                length_by_level.resize(level as usize + 1, 0);
+            }
+            length_by_level[level as usize] += distance as u64;
+        }
+
+        GraphStats {
+            num_nodes: graph.node_count() as u32,
+            num_edges: graph.edge_count() as u32,
+            num_components: StronglyConnectedComponents::new(graph).count() as u32,
+            bbox: (
+                GeoPoint::from_micro_degrees(min_lat, min_lon),
+                GeoPoint::from_micro_degrees(max_lat, max_lon),
+            ),
+            length_by_level,
+            degree_histogram,
+        }
+    }
+
+    /// Write the statistics as the contents of a file section. The section ends with its own total
+    /// size, so that it can be found from the end of the file without reading the rest
+    pub fn write_section<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut payload = Vec::new();
+        payload.write_u32::<LittleEndian>(self.num_nodes)?;
+        payload.write_u32::<LittleEndian>(self.num_edges)?;
+        payload.write_u32::<LittleEndian>(self.num_components)?;
+        for point in &[self.bbox.0, self.bbox.1] {
+            payload.write_i32::<LittleEndian>(point.lat.as_micro_degrees())?;
+            payload.write_i32::<LittleEndian>(point.lon.as_micro_degrees())?;
+        }
+        payload.write_u32::<LittleEndian>(self.length_by_level.len() as u32)?;
+        for &length in &self.length_by_level {
+            payload.write_u64::<LittleEndian>(length)?;
+        }
+        payload.write_u32::<LittleEndian>(self.degree_histogram.len() as u32)?;
+        for &count in &self.degree_histogram {
+            payload.write_u32::<LittleEndian>(count)?;
+        }
+
+        let section_size = 1 + STATS_SECTION.len() + 8 + payload.len() + 8;
+        payload.write_u64::<LittleEndian>(section_size as u64)?;
+
+        writer.write_u8(STATS_SECTION.len() as u8)?;
+        writer.write_all(STATS_SECTION.as_bytes())?;
+        writer.write_u64::<LittleEndian>(payload.len() as u64)?;
+        writer.write_all(&payload)
+    }
+
+    /// Read the statistics section at the end of a file, if any. It may be followed by the checksum
+    pub fn read_footer<R: Read + Seek>(reader: &mut R) -> io::Result<Option<GraphStats>> {
+        let mut end = reader.seek(SeekFrom::End(0))?;
+        if end >= checksum::SECTION_SIZE
+            && read_section_name(reader, end, checksum::SECTION_SIZE)?
+                == checksum::CHECKSUM_SECTION.as_bytes()
+        {
+            end -= checksum::SECTION_SIZE;
+        }
+
+        if end < 8 {
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Start(end - 8))?;
+        let section_size = reader.read_u64::<LittleEndian>()?;
+        if section_size > end || section_size < (1 + STATS_SECTION.len() + 16) as u64 {
+            return Ok(None);
+        }
+        if read_section_name(reader, end, section_size)? != STATS_SECTION.as_bytes() {
+            return Ok(None);
+        }
+        reader.read_u64::<LittleEndian>()?;
+        GraphStats::read_payload(reader).map(Some)
+    }
+
+    /// Read the contents of the section, after its size
+    fn read_payload<R: Read>(reader: &mut R) -> io::Result<GraphStats> {
+        let num_nodes = reader.read_u32::<LittleEndian>()?;
+        let num_edges = reader.read_u32::<LittleEndian>()?;
+        let num_components = reader.read_u32::<LittleEndian>()?;
+        let mut corners = [GeoPoint::from_micro_degrees(0, 0); 2];
+        for corner in &mut corners {
+            let lat = reader.read_i32::<LittleEndian>()?;
+            let lon = reader.read_i32::<LittleEndian>()?;
+            *corner = GeoPoint::from_micro_degrees(lat, lon);
+        }
+        let length_by_level = (0..reader.read_u32::<LittleEndian>()?)
+            .map(|_| reader.read_u64::<LittleEndian>())
+            .collect::<io::Result<_>>()?;
+        let degree_histogram = (0..reader.read_u32::<LittleEndian>()?)
+            .map(|_| reader.read_u32::<LittleEndian>())
+            .collect::<io::Result<_>>()?;
+        Ok(GraphStats {
+            num_nodes,
+            num_edges,
+            num_components,
+            bbox: (corners[0], corners[1]),
+            length_by_level,
+            degree_histogram,
+        })
+    }
+}
+
+/// Read the name of the section of the given size that ends at `end`, leaving the reader after it
+fn read_section_name<R: Read + Seek>(reader: &mut R, end: u64, size: u64) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(end - size))?;
+    let mut name = vec![0; reader.read_u8()? as usize];
+    if name.len() as u64 + 1 > size {
+        return Ok(Vec::new());
+    }
+    reader.read_exact(&mut name)?;
+    Ok(name)
+}
+
+impl fmt::Display for GraphStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Nodes: {}", self.num_nodes)?;
+        writeln!(f, "Edges: {}", self.num_edges)?;
+        writeln!(f, "Strongly connected components: {}", self.num_components)?;
+        writeln!(
+            f,
+            "Bounding box: ({:.6}, {:.6}) to ({:.6}, {:.6})",
+            self.bbox.0.lat.as_degrees(),
+            self.bbox.0.lon.as_degrees(),
+            self.bbox.1.lat.as_degrees(),
+            self.bbox.1.lon.as_degrees()
+        )?;
+        writeln!(f, "Length by road level:")?;
+        for (level, &length) in self.length_by_level.iter().enumerate() {
+            writeln!(f, "  {}: {:.1} km", level, length as f64 / 1000.)?;
+        }
+        write!(f, "Nodes by number of outgoing edges:")?;
+        for (degree, &count) in self.degree_histogram.iter().enumerate() {
+            write!(f, "\n  {}: {}", degree, count)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use super::data_types::*;
+use super::edge_based::Turn;
+use super::Cartograph;
+use crate::utils::GeoPoint;
+use petgraph::graph::EdgeIndex;
+use petgraph::visit::EdgeRef;
+use std::fmt;
+
+/// A turn-by-turn instruction of a route
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub maneuver: Maneuver,
+    /// Where the maneuver happens
+    pub location: GeoPoint,
+    /// The distance in meters from this maneuver to the next one
+    pub distance: u32,
+    /// The estimated time in seconds from this maneuver to the next one
+    pub duration: f64,
+}
+
+/// What the driver should do at a given step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Maneuver {
+    Depart,
+    Turn(TurnDirection),
+    /// Enter the roundabout and take the given exit, counting from 1
+    Roundabout {
+        exit: u32,
+    },
+    Arrive,
+}
+
+/// How sharp a turn is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TurnDirection {
+    SharpLeft,
+    Left,
+    SlightLeft,
+    Straight,
+    SlightRight,
+    Right,
+    SharpRight,
+    UTurn,
+}
+
+impl TurnDirection {
+    /// Classify a turn angle, in degrees. Positive angles are turns to the right
+    pub fn from_angle(angle: f64) -> Self {
+        match angle {
+            a if a <= -135. => TurnDirection::SharpLeft,
+            a if a <= -60. => TurnDirection::Left,
+            a if a <= -20. => TurnDirection::SlightLeft,
+            a if a < 20. => TurnDirection::Straight,
+            a if a < 60. => TurnDirection::SlightRight,
+            a if a < 135. => TurnDirection::Right,
+            _ => TurnDirection::SharpRight,
+        }
+    }
+}
+
+impl fmt::Display for TurnDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TurnDirection::SharpLeft => "sharp left",
+            TurnDirection::Left => "left",
+            TurnDirection::SlightLeft => "slight left",
+            TurnDirection::Straight => "straight",
+            TurnDirection::SlightRight => "slight right",
+            TurnDirection::Right => "right",
+            TurnDirection::SharpRight => "sharp right",
+            TurnDirection::UTurn => "uturn",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Human-readable instruction, like "Take the 2nd exit at the roundabout"
+impl fmt::Display for Maneuver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Maneuver::Depart => write!(f, "Depart"),
+            Maneuver::Turn(TurnDirection::UTurn) => write!(f, "Make a U-turn"),
+            Maneuver::Turn(direction) => write!(f, "Turn {}", direction),
+            Maneuver::Roundabout { exit } => {
+                let suffix = match (exit % 10, exit % 100) {
+                    (1, n) if n != 11 => "st",
+                    (2, n) if n != 12 => "nd",
+                    (3, n) if n != 13 => "rd",
+                    _ => "th",
+                };
+                write!(f, "Take the {}{} exit at the roundabout", exit, suffix)
+            }
+            Maneuver::Arrive => write!(f, "Arrive at destination"),
+        }
+    }
+}
+
+/// Describe the path that traverses the given edges between two projected points as a list of
+/// maneuvers. A turn is only reported when the driver has another option at that junction, so that bends of
+/// the road do not generate instructions. The maneuvers inside a roundabout are summarized as a single
+/// step with the exit number
+pub fn path_steps(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &ProjectedPoint,
+    edges: &[EdgeIndex],
+) -> Vec<Step> {
+    let graph = &carto.graph;
+    let fractions = carto.edge_fractions(from, to, edges);
+    let edge_distance = |i: usize| partial_distance(graph[edges[i]].distance, fractions[i]);
+    let edge_duration = |i: usize| graph[edges[i]].duration() * fractions[i] as f64;
+
+    let mut steps = vec![Step {
+        maneuver: Maneuver::Depart,
+        location: from.projected,
+        distance: edge_distance(0),
+        duration: edge_duration(0),
+    }];
+    let mut roundabout_step = None;
+    let mut exits = 0;
+    for i in 1..edges.len() {
+        let (prev, next) = (edges[i - 1], edges[i]);
+        let via = graph.edge_endpoints(next).unwrap().0;
+        let location = graph[via];
+        let exit_edges = || {
+            graph
+                .edges(via)
+                .filter(|edge| !edge.weight().roundabout)
+                .count() as u32
+        };
+
+        match (graph[prev].roundabout, graph[next].roundabout) {
+            (false, true) => {
+                // Entering
+                roundabout_step = Some(steps.len());
+                exits = 0;
+                steps.push(Step {
+                    maneuver: Maneuver::Roundabout { exit: 0 },
+                    location,
+                    distance: 0,
+                    duration: 0.,
+                });
+            }
+            (true, true) => exits += exit_edges(),
+            (true, false) => {
+                // Exiting. The route can start inside the roundabout
+                let step_index = roundabout_step.take().unwrap_or_else(|| {
+                    steps.push(Step {
+                        maneuver: Maneuver::Roundabout { exit: 0 },
+                        location,
+                        distance: 0,
+                        duration: 0.,
+                    });
+                    steps.len() - 1
+                });
+                steps[step_index].maneuver = Maneuver::Roundabout { exit: exits + 1 };
+            }
+            (false, false) => {
+                let turn = Turn {
+                    from: prev,
+                    to: next,
+                    via,
+                    carto,
+                };
+                let prev_source = graph.edge_endpoints(prev).unwrap().0;
+                let options = graph
+                    .edges(via)
+                    .filter(|edge| edge.target() != prev_source)
+                    .count();
+                let direction = if turn.is_u_turn() {
+                    TurnDirection::UTurn
+                } else {
+                    TurnDirection::from_angle(turn.angle())
+                };
+                if direction != TurnDirection::Straight && (options > 1 || turn.is_u_turn()) {
+                    steps.push(Step {
+                        maneuver: Maneuver::Turn(direction),
+                        location,
+                        distance: 0,
+                        duration: 0.,
+                    });
+                }
+            }
+        }
+
+        let step = steps.last_mut().unwrap();
+        step.distance += edge_distance(i);
+        step.duration += edge_duration(i);
+    }
+
+    steps.push(Step {
+        maneuver: Maneuver::Arrive,
+        location: to.projected,
+        distance: 0,
+        duration: 0.,
+    });
+    steps
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn instructions() {
+        assert_eq!(TurnDirection::from_angle(-170.), TurnDirection::SharpLeft);
+        assert_eq!(TurnDirection::from_angle(-10.), TurnDirection::Straight);
+        assert_eq!(TurnDirection::from_angle(45.), TurnDirection::SlightRight);
+        assert_eq!(TurnDirection::from_angle(90.), TurnDirection::Right);
+
+        let exit = |exit| Maneuver::Roundabout { exit }.to_string();
+        assert_eq!(exit(1), "Take the 1st exit at the roundabout");
+        assert_eq!(exit(3), "Take the 3rd exit at the roundabout");
+        assert_eq!(exit(11), "Take the 11th exit at the roundabout");
+        assert_eq!(Maneuver::Turn(TurnDirection::Left).to_string(), "Turn left");
+        assert_eq!(
+            Maneuver::Turn(TurnDirection::UTurn).to_string(),
+            "Make a U-turn"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// The name of the file section with the classification of the road levels
+pub const ROAD_LEVELS_SECTION: &str = "road_levels";
+
+/// How the generator classified the roads of a level and how frontends should draw them
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RoadLevelStyle {
+    pub level: u8,
+    /// A short name for the level, like `primary`
+    pub name: String,
+    /// The values of the OSM tag `highway` of the roads in this level
+    pub highways: Vec<String>,
+    /// Suggested color, as `#rrggbb`
+    pub color: String,
+    /// Suggested minimum zoom level at which the roads are drawn
+    pub min_zoom: u8,
+}
+
+impl RoadLevelStyle {
+    /// The classification used by the generator, that is assumed for files without the section
+    pub fn defaults() -> Vec<RoadLevelStyle> {
+        let levels: [(&str, &[&str], &str, u8); 6] = [
+            (
+                "motorway",
+                &["motorway", "motorway_link", "trunk", "trunk_link"],
+                "#e892a2",
+                5,
+            ),
+            ("primary", &["primary", "primary_link"], "#f9b29c", 7),
+            ("secondary", &["secondary", "secondary_link"], "#fcd6a4", 9),
+            ("tertiary", &["tertiary", "tertiary_link"], "#f7fabf", 11),
+            ("unclassified", &["unclassified"], "#d4d4d4", 12),
+            (
+                "local",
+                &[
+                    "residential",
+                    "service",
+                    "living_street",
+                    "road",
+                    "rest_area",
+                    "services",
+                ],
+                "#bbbbbb",
+                13,
+            ),
+        ];
+        levels
+            .iter()
+            .enumerate()
+            .map(
+                |(level, &(name, highways, color, min_zoom))| RoadLevelStyle {
+                    level: level as u8,
+                    name: name.to_owned(),
+                    highways: highways.iter().map(|&highway| highway.to_owned()).collect(),
+                    color: color.to_owned(),
+                    min_zoom,
+                },
+            )
+            .collect()
+    }
+
+    /// Write the road levels as a file section
+    pub fn write_section<W: Write>(writer: &mut W, levels: &[RoadLevelStyle]) -> io::Result<()> {
+        let mut payload = Vec::new();
+        payload.write_u8(levels.len() as u8)?;
+        for level in levels {
+            payload.write_u8(level.level)?;
+            write_str(&mut payload, &level.name)?;
+            payload.write_u8(level.highways.len() as u8)?;
+            for highway in &level.highways {
+                write_str(&mut payload, highway)?;
+            }
+            write_str(&mut payload, &level.color)?;
+            payload.write_u8(level.min_zoom)?;
+        }
+
+        writer.write_u8(ROAD_LEVELS_SECTION.len() as u8)?;
+        writer.write_all(ROAD_LEVELS_SECTION.as_bytes())?;
+        writer.write_u64::<LittleEndian>(payload.len() as u64)?;
+        writer.write_all(&payload)
+    }
+
+    /// Read the contents of the section, after its name
+    pub fn read_section<R: Read>(reader: &mut R) -> io::Result<Vec<RoadLevelStyle>> {
+        let size = reader.read_u64::<LittleEndian>()?;
+        let mut payload = Vec::new();
+        reader.take(size).read_to_end(&mut payload)?;
+        if payload.len() as u64 != size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut payload = payload.as_slice();
+        (0..payload.read_u8()?)
+            .map(|_| {
+                let level = payload.read_u8()?;
+                let name = read_str(&mut payload)?;
+                let highways = (0..payload.read_u8()?)
+                    .map(|_| read_str(&mut payload))
+                    .collect::<io::Result<_>>()?;
+                Ok(RoadLevelStyle {
+                    level,
+                    name,
+                    highways,
+                    color: read_str(&mut payload)?,
+                    min_zoom: payload.read_u8()?,
+                })
+            })
+            .collect()
+    }
+}
+
+fn write_str<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    writer.write_u8(value.len() as u8)?;
+    writer.write_all(value.as_bytes())
+}
+
+fn read_str<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut value = vec![0; reader.read_u8()? as usize];
+    reader.read_exact(&mut value)?;
+    String::from_utf8(value).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn section() {
+        let mut levels = RoadLevelStyle::defaults();
+        levels[5].highways.push("track".to_owned());
+        let mut bytes = Vec::new();
+        RoadLevelStyle::write_section(&mut bytes, &levels).unwrap();
+
+        let mut reader = &bytes[1 + ROAD_LEVELS_SECTION.len()..];
+        assert_eq!(RoadLevelStyle::read_section(&mut reader).unwrap(), levels);
+        assert!(reader.is_empty());
+        let mut reader = &bytes[1 + ROAD_LEVELS_SECTION.len()..bytes.len() - 1];
+        assert!(RoadLevelStyle::read_section(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use super::data_types::EdgeInfo;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The kind of surface of a road, from the tag `surface`. The values are ordered from the best to
+/// the worst, after `Unknown`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Surface {
+    Unknown,
+    /// Asphalt, concrete, paving stones, etc
+    Paved,
+    /// Cobblestones and setts
+    Cobblestone,
+    /// Gravel and compacted surfaces
+    Gravel,
+    /// Dirt, grass, sand, etc
+    Unpaved,
+}
+
+/// How suitable a road is for vehicles with narrow wheels, from the tag `smoothness`. The values are
+/// ordered from the best to the worst, after `Unknown`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Smoothness {
+    Unknown,
+    Excellent,
+    Good,
+    Intermediate,
+    Bad,
+    VeryBad,
+    Horrible,
+    VeryHorrible,
+    Impassable,
+}
+
+impl Surface {
+    /// Classify the value of the tag `surface`
+    pub fn from_tag(value: &str) -> Self {
+        match value {
+            "paved" | "asphalt" | "chipseal" | "concrete" | "concrete:plates"
+            | "concrete:lanes" | "paving_stones" | "metal" | "wood" => Surface::Paved,
+            "cobblestone" | "cobblestone:flattened" | "sett" | "unhewn_cobblestone" => {
+                Surface::Cobblestone
+            }
+            "compacted" | "fine_gravel" | "gravel" | "pebblestone" => Surface::Gravel,
+            "unpaved" | "dirt" | "earth" | "ground" | "grass" | "grass_paver" | "mud" | "sand"
+            | "woodchips" => Surface::Unpaved,
+            _ => Surface::Unknown,
+        }
+    }
+
+    /// Decode the value stored in the file
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Surface::Paved,
+            2 => Surface::Cobblestone,
+            3 => Surface::Gravel,
+            4 => Surface::Unpaved,
+            _ => Surface::Unknown,
+        }
+    }
+}
+
+impl Smoothness {
+    /// Classify the value of the tag `smoothness`
+    pub fn from_tag(value: &str) -> Self {
+        match value {
+            "excellent" => Smoothness::Excellent,
+            "good" => Smoothness::Good,
+            "intermediate" => Smoothness::Intermediate,
+            "bad" => Smoothness::Bad,
+            "very_bad" => Smoothness::VeryBad,
+            "horrible" => Smoothness::Horrible,
+            "very_horrible" => Smoothness::VeryHorrible,
+            "impassable" => Smoothness::Impassable,
+            _ => Smoothness::Unknown,
+        }
+    }
+
+    /// Decode the value stored in the file
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Smoothness::Excellent,
+            2 => Smoothness::Good,
+            3 => Smoothness::Intermediate,
+            4 => Smoothness::Bad,
+            5 => Smoothness::VeryBad,
+            6 => Smoothness::Horrible,
+            7 => Smoothness::VeryHorrible,
+            8 => Smoothness::Impassable,
+            _ => Smoothness::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for Surface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Surface::Unknown => "unknown",
+            Surface::Paved => "paved",
+            Surface::Cobblestone => "cobblestone",
+            Surface::Gravel => "gravel",
+            Surface::Unpaved => "unpaved",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Surface {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Surface::from_tag(s) {
+            Surface::Unknown if s != "unknown" => Err(format!("Unknown surface {}", s)),
+            surface => Ok(surface),
+        }
+    }
+}
+
+impl FromStr for Smoothness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Smoothness::from_tag(s) {
+            Smoothness::Unknown => Err(format!("Unknown smoothness {}", s)),
+            smoothness => Ok(smoothness),
+        }
+    }
+}
+
+/// Extra costs of the edges according to their surface, chosen for each query. For example,
+/// cyclists may want to avoid cobblestones
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SurfacePenalties {
+    /// Multiply the cost of the edges with these surfaces by the given factors
+    pub surfaces: Vec<(Surface, f64)>,
+    /// Multiply the cost of the edges with this smoothness or worse by the given factor
+    pub smoothness: Option<(Smoothness, f64)>,
+}
+
+impl SurfacePenalties {
+    /// The factor to apply to the distance of an edge. It is never less than 1, so that the
+    /// distance is still a valid lower bound of the cost
+    pub fn factor(&self, info: &EdgeInfo) -> f64 {
+        let mut factor = 1.;
+        for &(surface, penalty) in &self.surfaces {
+            if info.surface == surface {
+                factor *= penalty;
+            }
+        }
+        if let Some((smoothness, penalty)) = self.smoothness {
+            if info.smoothness != Smoothness::Unknown && info.smoothness >= smoothness {
+                factor *= penalty;
+            }
+        }
+        factor.max(1.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartograph::{AccessClass, Restrictions};
+
+    #[test]
+    fn surfaces() {
+        assert_eq!(Surface::from_tag("sett"), Surface::Cobblestone);
+        assert_eq!(Surface::from_tag("asphalt"), Surface::Paved);
+        assert_eq!(Surface::from_tag("lava"), Surface::Unknown);
+        for &surface in &[Surface::Paved, Surface::Cobblestone, Surface::Unpaved] {
+            assert_eq!(Surface::from_u8(surface as u8), surface);
+            assert_eq!(surface.to_string().parse::<Surface>(), Ok(surface));
+        }
+        assert!("lava".parse::<Surface>().is_err());
+
+        assert_eq!(Smoothness::from_tag("very_bad"), Smoothness::VeryBad);
+        assert!(Smoothness::Horrible > Smoothness::Bad);
+        assert_eq!(
+            Smoothness::from_u8(Smoothness::Good as u8),
+            Smoothness::Good
+        );
+
+        let penalties = SurfacePenalties {
+            surfaces: vec![(Surface::Cobblestone, 5.)],
+            smoothness: Some((Smoothness::Bad, 2.)),
+        };
+        let mut info = EdgeInfo {
+            distance: 10,
+            road_level: 5,
+            roundabout: false,
+            capacity: 600,
+            lanes: 0,
+            width: 0.,
+            synthetic: false,
+            conditional: false,
+            reversible: false,
+            barrier: false,
+            cycleway: false,
+            bicycle_route: false,
+            bus_route: false,
+            border: false,
+            track_grade: 0,
+            surface: Surface::Cobblestone,
+            smoothness: Smoothness::Unknown,
+            access: AccessClass::Normal,
+            restrictions: Restrictions::default(),
+            way_id: 0,
+            name: 0,
+            shape: 0,
+        };
+        assert_eq!(penalties.factor(&info), 5.);
+        info.smoothness = Smoothness::Horrible;
+        assert_eq!(penalties.factor(&info), 10.);
+        info.surface = Surface::Paved;
+        info.smoothness = Smoothness::Good;
+        assert_eq!(penalties.factor(&info), 1.);
+    }
+}
@@ -0,0 +1,112 @@
+use super::data_types::EdgeInfo;
+use super::CostModel;
+use crate::utils::GeoPoint;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Vehicles heavier than this, in tonnes, are heavy goods vehicles, as used by the tag `hgv`
+const HGV_WEIGHT: f32 = 3.5;
+
+/// The legal limits of a road, as given by `maxweight`, `maxheight`, `maxwidth` and `hgv`. The
+/// limits are 0 when there is none or it is unknown
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Restrictions {
+    /// Maximum weight in tonnes
+    pub max_weight: f32,
+    /// Maximum height in meters, like under low bridges and in tunnels
+    pub max_height: f32,
+    /// Maximum width in meters
+    pub max_width: f32,
+    /// Whether heavy goods vehicles are not allowed (`hgv=no`)
+    pub no_hgv: bool,
+}
+
+impl Restrictions {
+    /// Whether there is no restriction at all
+    pub fn is_empty(&self) -> bool {
+        *self == Restrictions::default()
+    }
+
+    /// Whether a vehicle can use the road. The unknown dimensions of the vehicle are always allowed
+    pub fn allows(&self, vehicle: &Vehicle) -> bool {
+        let fits = |limit: f32, size: f32| limit <= 0. || size <= limit;
+        fits(self.max_weight, vehicle.weight)
+            && fits(self.max_height, vehicle.height)
+            && fits(self.max_width, vehicle.width)
+            && !(self.no_hgv && vehicle.is_hgv())
+    }
+}
+
+/// The dimensions of a vehicle, to avoid the roads it can not use. They are 0 when unknown. As a
+/// cost model, it finds the shortest path among the allowed roads
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vehicle {
+    /// Weight in tonnes
+    pub weight: f32,
+    /// Height in meters
+    pub height: f32,
+    /// Width in meters
+    pub width: f32,
+}
+
+impl Vehicle {
+    /// Whether the vehicle is a heavy goods vehicle, by its weight
+    pub fn is_hgv(&self) -> bool {
+        self.weight > HGV_WEIGHT
+    }
+}
+
+impl CostModel for Vehicle {
+    fn edge_cost(&self, info: &EdgeInfo, _from: &GeoPoint, _to: &GeoPoint) -> u32 {
+        if info.restrictions.allows(self) {
+            info.distance
+        } else {
+            u32::MAX
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn restrictions() {
+        let truck = Vehicle {
+            weight: 18.,
+            height: 4.2,
+            width: 2.5,
+        };
+        let van = Vehicle {
+            weight: 3.,
+            height: 2.5,
+            width: 0.,
+        };
+        assert!(Restrictions::default().allows(&truck));
+        assert!(Restrictions::default().is_empty());
+
+        let bridge = Restrictions {
+            max_height: 3.8,
+            ..Restrictions::default()
+        };
+        assert!(!bridge.allows(&truck));
+        assert!(bridge.allows(&van));
+        assert!(bridge.allows(&Vehicle::default()));
+
+        let village = Restrictions {
+            max_width: 2.,
+            no_hgv: true,
+            ..Restrictions::default()
+        };
+        assert!(!village.allows(&truck));
+        assert!(village.allows(&van));
+
+        let point = GeoPoint::from_degrees(42.5, 1.5);
+        let mut info = EdgeInfo::new(100, 2);
+        assert_eq!(truck.edge_cost(&info, &point, &point), 100);
+        info.restrictions.max_weight = 7.5;
+        assert_eq!(truck.edge_cost(&info, &point, &point), u32::MAX);
+        assert_eq!(van.edge_cost(&info, &point, &point), 100);
+    }
+}
@@ -0,0 +1,340 @@
+use super::cost::CostModel;
+use super::data_types::*;
+use super::deadline::DeadlineCheck;
+use super::Cartograph;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+
+/// A position on an edge where a path can start or end
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Endpoint {
+    pub edge: EdgeIndex,
+    pub edge_pos: f32,
+}
+
+/// The cheapest path between the virtual nodes of two projected points
+#[derive(Clone, Debug)]
+pub struct VirtualPath {
+    /// The cost of the path in the model of the search, which is the distance by default
+    pub cost: u32,
+    /// The edge where the path leaves the first point
+    pub start: Endpoint,
+    /// The edge where the path reaches the second point. When it is the same as `start`, the path
+    /// does not leave it and `inner` is empty
+    pub end: Endpoint,
+    /// The graph nodes traversed between both edges
+    pub nodes: Vec<NodeIndex>,
+    /// The edges between consecutive nodes
+    pub inner: Vec<EdgeIndex>,
+}
+
+impl VirtualPath {
+    /// The real distance of the path, in meters
+    pub fn distance(&self, carto: &Cartograph) -> u32 {
+        let graph = &carto.graph;
+        if self.nodes.is_empty() {
+            let fraction = self.end.edge_pos - self.start.edge_pos;
+            return partial_distance(graph[self.start.edge].distance, fraction);
+        }
+        partial_distance(graph[self.start.edge].distance, 1. - self.start.edge_pos)
+            + self
+                .inner
+                .iter()
+                .map(|&edge| graph[edge].distance)
+                .sum::<u32>()
+            + partial_distance(graph[self.end.edge].distance, self.end.edge_pos)
+    }
+
+    /// The duration of the path, in seconds
+    pub fn duration(&self, carto: &Cartograph) -> f64 {
+        let graph = &carto.graph;
+        if self.nodes.is_empty() {
+            let fraction = (self.end.edge_pos - self.start.edge_pos) as f64;
+            return graph[self.start.edge].duration() * fraction;
+        }
+        graph[self.start.edge].duration() * (1. - self.start.edge_pos as f64)
+            + self
+                .inner
+                .iter()
+                .map(|&edge| graph[edge].duration())
+                .sum::<f64>()
+            + graph[self.end.edge].duration() * self.end.edge_pos as f64
+    }
+
+    /// The traversed edges, including the ones of both points
+    pub fn edges(&self) -> Vec<EdgeIndex> {
+        if self.nodes.is_empty() {
+            return vec![self.start.edge];
+        }
+        let mut edges = Vec::with_capacity(self.inner.len() + 2);
+        edges.push(self.start.edge);
+        edges.extend_from_slice(&self.inner);
+        edges.push(self.end.edge);
+        edges
+    }
+}
+
+/// The cost of a whole edge in the given model
+fn edge_cost<C: CostModel + ?Sized>(carto: &Cartograph, cost: &C, edge: EdgeIndex) -> u32 {
+    let (source, target) = carto.graph.edge_endpoints(edge).unwrap();
+    cost.indexed_cost(
+        edge,
+        &carto.graph[edge],
+        &carto.graph[source],
+        &carto.graph[target],
+    )
+}
+
+/// The positions where a path can leave or reach a projected point: its own edge and, on two-way
+/// roads, the cheapest opposite edge at the mirrored position
+pub fn endpoints<C: CostModel + ?Sized>(
+    carto: &Cartograph,
+    point: &ProjectedPoint,
+    cost: &C,
+) -> Vec<Endpoint> {
+    let (source, target) = carto.graph.edge_endpoints(point.edge).unwrap();
+    let mut endpoints = vec![Endpoint {
+        edge: point.edge,
+        edge_pos: point.edge_pos,
+    }];
+    let opposite = carto
+        .graph
+        .edges_connecting(target, source)
+        .filter(|edge_ref| carto.is_routable(edge_ref.weight()))
+        .map(|edge_ref| (edge_cost(carto, cost, edge_ref.id()), edge_ref.id()))
+        .filter(|&(cost, _)| cost != u32::MAX)
+        .min_by_key(|&(cost, _)| cost);
+    if let Some((_, opposite)) = opposite {
+        endpoints.push(Endpoint {
+            edge: opposite,
+            edge_pos: 1. - point.edge_pos,
+        });
+    }
+    endpoints
+}
+
+/// Find the cheapest path from `from` to each of the points in `to`, as if a virtual node was
+/// inserted in the graph at each of them, splitting their edges and the opposite ones. So the paths
+/// may start by going backwards on a two-way road and the costs of the partial edges are exact.
+///
+/// This is an A* search from the virtual source, whose estimate is the heuristic of the cost model
+/// to the closest destination that is not settled yet. Since the estimate grows as the destinations
+/// are settled, the nodes may be visited more than once. Returns `None` for the destinations that
+/// are not reachable, or for all of them when the deadline passes, which the caller must check
+pub fn search<C: CostModel + ?Sized>(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &[ProjectedPoint],
+    cost: &C,
+    check: &DeadlineCheck,
+) -> Vec<Option<VirtualPath>> {
+    search_until(carto, from, to, cost, check, false)
+}
+
+/// Like `search()`, but stop as soon as the cheapest destination is found and return its index
+/// and path. Returns `None` if no destination is reachable or the deadline passes
+pub fn search_closest<C: CostModel + ?Sized>(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &[ProjectedPoint],
+    cost: &C,
+    check: &DeadlineCheck,
+) -> Option<(usize, VirtualPath)> {
+    search_until(carto, from, to, cost, check, true)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, path)| Some((i, path?)))
+        .min_by_key(|(_, path)| path.cost)
+}
+
+/// Run the search until all destinations are settled or, with `first`, until one of them is. The
+/// paths to the destinations that were not settled are dropped, since they may not be the cheapest
+fn search_until<C: CostModel + ?Sized>(
+    carto: &Cartograph,
+    from: &ProjectedPoint,
+    to: &[ProjectedPoint],
+    cost: &C,
+    check: &DeadlineCheck,
+    first: bool,
+) -> Vec<Option<VirtualPath>> {
+    let graph = &carto.graph;
+    let starts = endpoints(carto, from, cost);
+    let ends: Vec<_> = to
+        .iter()
+        .map(|point| endpoints(carto, point, cost))
+        .collect();
+
+    // The edges of the points are always traversed, even when the model forbids them
+    let endpoint_cost = |edge: EdgeIndex, fraction: f32| match edge_cost(carto, cost, edge) {
+        u32::MAX => partial_distance(graph[edge].distance, fraction),
+        edge_cost => partial_distance(edge_cost, fraction),
+    };
+
+    // The best path found so far to each destination: its cost, where it reaches the destination
+    // and how it gets there
+    let mut best: Vec<Option<(u32, Endpoint, Last)>> = vec![None; to.len()];
+    let mut settled = vec![false; to.len()];
+    let improve = |best: &mut Option<(u32, Endpoint, Last)>, cost, end, last| {
+        if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+            *best = Some((cost, end, last));
+        }
+    };
+
+    // Both points on the same edge, in the right order
+    for (i, ends) in ends.iter().enumerate() {
+        for start in &starts {
+            for end in ends {
+                if start.edge == end.edge && start.edge_pos <= end.edge_pos {
+                    let cost = endpoint_cost(start.edge, end.edge_pos - start.edge_pos);
+                    improve(&mut best[i], cost, *end, Last::Direct(*start));
+                }
+            }
+        }
+    }
+
+    // The destinations reached from each node, with the cost of the partial edge
+    let mut end_nodes: HashMap<NodeIndex, Vec<(usize, u32, Endpoint)>> = HashMap::new();
+    for (i, ends) in ends.iter().enumerate() {
+        for end in ends {
+            let source = graph.edge_endpoints(end.edge).unwrap().0;
+            let cost = endpoint_cost(end.edge, end.edge_pos);
+            end_nodes.entry(source).or_default().push((i, cost, *end));
+        }
+    }
+    let estimate = |settled: &[bool], node: NodeIndex| {
+        end_nodes
+            .iter()
+            .filter(|(_, ends)| ends.iter().any(|&(i, _, _)| !settled[i]))
+            .map(|(&end_node, _)| cost.heuristic(&graph[node], &graph[end_node]))
+            .min()
+            .unwrap_or(0)
+    };
+
+    let mut scores: HashMap<NodeIndex, u32> = HashMap::new();
+    let mut parents: HashMap<NodeIndex, EdgeIndex> = HashMap::new();
+    let mut visit_next = BinaryHeap::new();
+    for start in &starts {
+        let node = graph.edge_endpoints(start.edge).unwrap().1;
+        let score = endpoint_cost(start.edge, 1. - start.edge_pos);
+        if scores.get(&node).is_none_or(|&old| score < old) {
+            scores.insert(node, score);
+            let estimated = score.saturating_add(estimate(&settled, node));
+            visit_next.push(Reverse((estimated, score, node)));
+            check.push();
+        }
+    }
+
+    while let Some(Reverse((estimated, score, node))) = visit_next.pop() {
+        if check.step() {
+            return vec![None; to.len()];
+        }
+
+        // Every path that is not found yet costs at least `estimated`
+        for (i, best) in best.iter().enumerate() {
+            if best.is_some_and(|(cost, _, _)| cost <= estimated) {
+                settled[i] = true;
+            }
+        }
+        if settled.iter().all(|&settled| settled)
+            || (first && settled.iter().any(|&settled| settled))
+        {
+            for (best, &settled) in best.iter_mut().zip(&settled) {
+                if !settled {
+                    *best = None;
+                }
+            }
+            break;
+        }
+        if score > scores[&node] {
+            continue;
+        }
+        check.settle();
+
+        if let Some(ends) = end_nodes.get(&node) {
+            for &(i, cost, end) in ends {
+                improve(
+                    &mut best[i],
+                    score.saturating_add(cost),
+                    end,
+                    Last::Node(node),
+                );
+            }
+        }
+
+        for edge in graph.edges(node) {
+            if !carto.is_routable(edge.weight()) {
+                continue;
+            }
+            let next = edge.target();
+            let cost = cost.indexed_cost(edge.id(), edge.weight(), &graph[node], &graph[next]);
+            if cost == u32::MAX {
+                continue;
+            }
+            let next_score = score.saturating_add(cost);
+            match scores.entry(next) {
+                Entry::Occupied(entry) if *entry.get() <= next_score => continue,
+                Entry::Occupied(mut entry) => {
+                    entry.insert(next_score);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(next_score);
+                }
+            }
+            parents.insert(next, edge.id());
+            let estimated = next_score.saturating_add(estimate(&settled, next));
+            visit_next.push(Reverse((estimated, next_score, next)));
+            check.push();
+        }
+    }
+
+    best.into_iter()
+        .map(|best| {
+            let (cost, end, last) = best?;
+            let last = match last {
+                Last::Direct(start) => {
+                    return Some(VirtualPath {
+                        cost,
+                        start,
+                        end,
+                        nodes: Vec::new(),
+                        inner: Vec::new(),
+                    })
+                }
+                Last::Node(last) => last,
+            };
+
+            // The first node is a start whose score was never improved, so it has no parent
+            let mut nodes = vec![last];
+            let mut inner = Vec::new();
+            while let Some(&parent) = parents.get(nodes.last().unwrap()) {
+                inner.push(parent);
+                nodes.push(graph.edge_endpoints(parent).unwrap().0);
+            }
+            nodes.reverse();
+            inner.reverse();
+            let start = *starts
+                .iter()
+                .find(|start| graph.edge_endpoints(start.edge).unwrap().1 == nodes[0])
+                .unwrap();
+            Some(VirtualPath {
+                cost,
+                start,
+                end,
+                nodes,
+                inner,
+            })
+        })
+        .collect()
+}
+
+/// How a path reaches its destination
+#[derive(Clone, Copy)]
+enum Last {
+    /// Without leaving the edge of this start
+    Direct(Endpoint),
+    /// From this node, through the edge of the destination
+    Node(NodeIndex),
+}
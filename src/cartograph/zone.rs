@@ -0,0 +1,248 @@
+use super::Cartograph;
+use crate::utils::GeoPoint;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use rstar::AABB;
+
+/// An area whose roads should not be used, like the surroundings of an event. It is made of rings
+/// of points, and a point is inside when it is inside an odd number of them, so that a polygon with
+/// holes or several polygons are given by all their rings. The rings do not need to be closed
+#[derive(Clone, Debug, PartialEq)]
+pub struct AvoidZone {
+    pub rings: Vec<Vec<GeoPoint>>,
+}
+
+impl AvoidZone {
+    pub fn new(rings: Vec<Vec<GeoPoint>>) -> Self {
+        AvoidZone { rings }
+    }
+
+    /// The edges inside the zone or crossing its border, sorted by their index. The candidates are
+    /// found with the index of the edges, then each segment of their geometry, with the points of
+    /// their shape, is tested against the rings, in Web Mercator
+    pub fn edges(&self, carto: &Cartograph) -> Vec<EdgeIndex> {
+        let rings = self.project();
+        let (min, max) = match bounds(&rings) {
+            Some(bounds) => bounds,
+            None => return Vec::new(),
+        };
+
+        let mut edges: Vec<EdgeIndex> = carto
+            .rtree
+            .locate(min, max)
+            .filter(|&edge| {
+                let points: Vec<[f64; 2]> = carto
+                    .edge_geometry(edge)
+                    .iter()
+                    .map(GeoPoint::web_mercator_project)
+                    .collect();
+                contains(&rings, points[0])
+                    || points
+                        .windows(2)
+                        .any(|pair| contains(&rings, pair[1]) || crosses(&rings, pair[0], pair[1]))
+            })
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    }
+
+    /// The nodes inside the zone, sorted by their index. The nodes on its border may or may not be
+    /// included
+    pub fn nodes(&self, carto: &Cartograph) -> Vec<NodeIndex> {
+        let rings = self.project();
+        let (min, max) = match bounds(&rings) {
+            Some(bounds) => bounds,
+            None => return Vec::new(),
+        };
+
+        let mut nodes: Vec<NodeIndex> = carto
+            .node_rtree()
+            .locate_in_envelope(&AABB::from_corners(min, max))
+            .filter(|element| contains(&rings, *element.position()))
+            .map(|element| element.data)
+            .collect();
+        nodes.sort_unstable();
+        nodes
+    }
+
+    /// The rings, in Web Mercator
+    fn project(&self) -> Vec<Vec<[f64; 2]>> {
+        self.rings
+            .iter()
+            .map(|ring| ring.iter().map(GeoPoint::web_mercator_project).collect())
+            .collect()
+    }
+}
+
+/// The lowest and highest corners of the bounding box of the rings, if they have any point
+fn bounds(rings: &[Vec<[f64; 2]>]) -> Option<([f64; 2], [f64; 2])> {
+    let mut points = rings.iter().flatten();
+    let first = *points.next()?;
+    Some(points.fold((first, first), |(min, max), xy| {
+        (
+            [min[0].min(xy[0]), min[1].min(xy[1])],
+            [max[0].max(xy[0]), max[1].max(xy[1])],
+        )
+    }))
+}
+
+/// Whether a point is inside an odd number of rings, by casting a ray to the right
+fn contains(rings: &[Vec<[f64; 2]>], xy: [f64; 2]) -> bool {
+    let mut inside = false;
+    for (p, q) in rings.iter().flat_map(|ring| segments(ring)) {
+        if (p[1] > xy[1]) != (q[1] > xy[1])
+            && xy[0] < p[0] + (xy[1] - p[1]) / (q[1] - p[1]) * (q[0] - p[0])
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Whether the segment from `a` to `b` touches the border of any ring
+fn crosses(rings: &[Vec<[f64; 2]>], a: [f64; 2], b: [f64; 2]) -> bool {
+    rings
+        .iter()
+        .flat_map(|ring| segments(ring))
+        .any(|(p, q)| intersect(a, b, p, q))
+}
+
+/// The segments of a ring, including the one that closes it
+fn segments(ring: &[[f64; 2]]) -> impl Iterator<Item = ([f64; 2], [f64; 2])> + '_ {
+    let closing = ring.last().zip(ring.first()).map(|(&p, &q)| (p, q));
+    ring.windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .chain(closing)
+}
+
+/// Whether two segments have at least a point in common
+fn intersect(a: [f64; 2], b: [f64; 2], p: [f64; 2], q: [f64; 2]) -> bool {
+    let orientation = |o: [f64; 2], u: [f64; 2], v: [f64; 2]| {
+        let cross = (u[0] - o[0]) * (v[1] - o[1]) - (u[1] - o[1]) * (v[0] - o[0]);
+        cross.partial_cmp(&0.).unwrap_or(std::cmp::Ordering::Equal) as i8
+    };
+    // Whether `v`, collinear with the segment from `o` to `u`, is on it
+    let on_segment = |o: [f64; 2], u: [f64; 2], v: [f64; 2]| {
+        v[0] >= o[0].min(u[0])
+            && v[0] <= o[0].max(u[0])
+            && v[1] >= o[1].min(u[1])
+            && v[1] <= o[1].max(u[1])
+    };
+    let (d1, d2) = (orientation(p, q, a), orientation(p, q, b));
+    let (d3, d4) = (orientation(a, b, p), orientation(a, b, q));
+    (d1 * d2 < 0 && d3 * d4 < 0)
+        || (d1 == 0 && on_segment(p, q, a))
+        || (d2 == 0 && on_segment(p, q, b))
+        || (d3 == 0 && on_segment(a, b, p))
+        || (d4 == 0 && on_segment(a, b, q))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn geometry() {
+        let square = vec![vec![[0., 0.], [4., 0.], [4., 4.], [0., 4.]]];
+        assert!(contains(&square, [1., 1.]));
+        assert!(!contains(&square, [5., 1.]));
+        let with_hole = vec![
+            square[0].clone(),
+            vec![[1., 1.], [3., 1.], [3., 3.], [1., 3.]],
+        ];
+        assert!(!contains(&with_hole, [2., 2.]));
+        assert!(contains(&with_hole, [0.5, 2.]));
+
+        assert!(crosses(&square, [-1., 2.], [5., 2.]));
+        assert!(crosses(&square, [4., 4.], [5., 5.]));
+        assert!(!crosses(&square, [5., 0.], [5., 4.]));
+        assert!(intersect([0., 0.], [2., 0.], [1., 0.], [3., 0.]));
+        assert!(!intersect([0., 0.], [1., 0.], [2., 0.], [3., 0.]));
+    }
+
+    #[test]
+    fn edges() {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        let path_edges = carto.shortest_path_edges(&from, &to).unwrap();
+
+        // A small square around a point in the middle of the path
+        let middle = carto.graph[carto
+            .graph
+            .edge_endpoints(path_edges[path_edges.len() / 4])
+            .unwrap()
+            .0];
+        let (lat, lon) = (middle.lat.as_degrees(), middle.lon.as_degrees());
+        let delta = 0.0005;
+        let zone = AvoidZone::new(vec![vec![
+            GeoPoint::from_degrees(lat - delta, lon - delta),
+            GeoPoint::from_degrees(lat - delta, lon + delta),
+            GeoPoint::from_degrees(lat + delta, lon + delta),
+            GeoPoint::from_degrees(lat + delta, lon - delta),
+        ]]);
+        let edges = zone.edges(&carto);
+        assert!(edges.contains(&path_edges[path_edges.len() / 4]));
+        assert!(
+            edges.len()
+                <= carto
+                    .edges_in_bbox(&zone.rings[0][0], &zone.rings[0][2])
+                    .len()
+        );
+
+        // Avoiding them gives a longer path
+        let closed: HashSet<EdgeIndex> = edges.into_iter().collect();
+        let detour = carto
+            .shortest_path_with_closures(
+                &from,
+                &to,
+                &closed,
+                &Default::default(),
+                &Default::default(),
+            )
+            .unwrap();
+        assert!(detour.distance > 12183);
+
+        assert!(AvoidZone::new(vec![]).edges(&carto).is_empty());
+    }
+
+    #[test]
+    fn polygon() {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        // A triangle, which is half of its bounding box
+        let (south_west, north_east) = (
+            GeoPoint::from_degrees(42.50, 1.50),
+            GeoPoint::from_degrees(42.52, 1.54),
+        );
+        let triangle = [south_west, GeoPoint::from_degrees(42.50, 1.54), north_east];
+        let rings = vec![triangle
+            .iter()
+            .map(GeoPoint::web_mercator_project)
+            .collect::<Vec<_>>()];
+
+        let edges = carto.edges_in_polygon(&triangle);
+        assert!(!edges.is_empty());
+        assert!(edges.len() < carto.edges_in_bbox(&south_west, &north_east).len());
+        for &edge in &edges {
+            let (source, target) = carto.graph.edge_endpoints(edge).unwrap();
+            let a = carto.graph[source].web_mercator_project();
+            let b = carto.graph[target].web_mercator_project();
+            assert!(contains(&rings, a) || contains(&rings, b) || crosses(&rings, a, b));
+        }
+
+        let nodes = carto.nodes_in_polygon(&triangle);
+        assert!(!nodes.is_empty());
+        assert!(nodes.len() < carto.nodes_in_bbox(&south_west, &north_east).len());
+        for node in nodes {
+            assert!(contains(&rings, carto.graph[node].web_mercator_project()));
+            // Any edge from a node inside is in the polygon
+            for edge in carto.out_edges(node) {
+                assert!(edges.binary_search(&edge.index).is_ok());
+            }
+        }
+
+        assert!(carto.edges_in_polygon(&[]).is_empty());
+        assert!(carto.nodes_in_polygon(&[]).is_empty());
+    }
+}
@@ -0,0 +1,265 @@
+//! Express the points in the coordinate reference system requested by the user, for the consumers
+//! that need planar meters instead of degrees.
+//!
+//! The supported systems are:
+//! - `wgs84`: longitude and latitude in degrees (EPSG:4326), the default
+//! - `web_mercator`: meters East of Greenwich and North of the Equator, as used by the web maps
+//!   (EPSG:3857)
+//! - `utm`: meters in the UTM zone that contains the center of the geometry (EPSG:326xx in the
+//!   North and EPSG:327xx in the South). The special zones around Norway and Svalbard are not used
+//!
+//! The coordinates are always in the order `[x, y]`, that is, `[lon, lat]` for WGS84.
+
+use crate::utils::GeoPoint;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A coordinate reference system requested by the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Crs {
+    Wgs84,
+    WebMercator,
+    /// The UTM zone is chosen by `projection()`, from the points to express
+    Utm,
+}
+
+/// A fully specified projection, ready to express points
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Wgs84,
+    WebMercator,
+    Utm(UtmZone),
+}
+
+/// One of the 60 zones of the Universal Transverse Mercator system, in one of the hemispheres
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UtmZone {
+    /// From 1 to 60, each 6 degrees wide, starting at the longitude -180
+    pub number: u8,
+    pub north: bool,
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("Unknown coordinate reference system {0}. Expected one of wgs84, web_mercator or utm")]
+pub struct ParseCrsError(String);
+
+impl Crs {
+    /// Choose the projection to express the given points
+    pub fn projection(self, points: &[GeoPoint]) -> Projection {
+        match self {
+            Crs::Wgs84 => Projection::Wgs84,
+            Crs::WebMercator => Projection::WebMercator,
+            Crs::Utm => Projection::Utm(UtmZone::containing(&center(points))),
+        }
+    }
+
+    /// Express the points in this system, returning the projection that was used
+    pub fn project_all(self, points: &[GeoPoint]) -> (Projection, Vec<[f64; 2]>) {
+        let projection = self.projection(points);
+        (projection, projection.project_all(points))
+    }
+}
+
+impl FromStr for Crs {
+    type Err = ParseCrsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "wgs84" | "epsg:4326" => Ok(Crs::Wgs84),
+            "web_mercator" | "epsg:3857" => Ok(Crs::WebMercator),
+            "utm" => Ok(Crs::Utm),
+            _ => Err(ParseCrsError(s.to_owned())),
+        }
+    }
+}
+
+impl Projection {
+    /// Express a point as `[x, y]`
+    pub fn project(&self, point: &GeoPoint) -> [f64; 2] {
+        match self {
+            Projection::Wgs84 => [point.lon.as_degrees(), point.lat.as_degrees()],
+            Projection::WebMercator => point.web_mercator_project(),
+            Projection::Utm(zone) => zone.project(point),
+        }
+    }
+
+    pub fn project_all(&self, points: &[GeoPoint]) -> Vec<[f64; 2]> {
+        points.iter().map(|point| self.project(point)).collect()
+    }
+
+    /// The EPSG code of the projection
+    pub fn epsg(&self) -> u32 {
+        match self {
+            Projection::Wgs84 => 4326,
+            Projection::WebMercator => 3857,
+            Projection::Utm(zone) => zone.epsg(),
+        }
+    }
+}
+
+/// As `EPSG:code`
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EPSG:{}", self.epsg())
+    }
+}
+
+impl UtmZone {
+    /// The zone that contains the point
+    pub fn containing(point: &GeoPoint) -> Self {
+        let number = ((point.lon.as_degrees() + 180.) / 6.).floor() as i32 + 1;
+        UtmZone {
+            number: number.clamp(1, 60) as u8,
+            north: point.lat.as_degrees() >= 0.,
+        }
+    }
+
+    pub fn epsg(&self) -> u32 {
+        (if self.north { 32600 } else { 32700 }) + self.number as u32
+    }
+
+    /// The longitude of the center of the zone, in degrees
+    pub fn central_meridian(&self) -> f64 {
+        self.number as f64 * 6. - 183.
+    }
+
+    /// Express the point as `[easting, northing]` in meters. Points outside the zone can also be
+    /// expressed, with less accuracy as they get farther from it.
+    /// Based on the series of Krüger, as described in https://arxiv.org/abs/1002.1417
+    pub fn project(&self, point: &GeoPoint) -> [f64; 2] {
+        let a = 6_378_137.;
+        let f: f64 = 1. / 298.257_223_563;
+        let k0 = 0.9996;
+        let n = f / (2. - f);
+        let rectifying_radius = a / (1. + n) * (1. + n.powi(2) / 4. + n.powi(4) / 64.);
+        let alpha = [
+            n / 2. - 2. * n.powi(2) / 3. + 5. * n.powi(3) / 16.,
+            13. * n.powi(2) / 48. - 3. * n.powi(3) / 5.,
+            61. * n.powi(3) / 240.,
+        ];
+
+        let lat = point.lat.as_radians();
+        let delta_lon = point.lon.as_radians() - self.central_meridian().to_radians();
+        let e = 2. * n.sqrt() / (1. + n);
+        let t = (lat.sin().atanh() - e * (e * lat.sin()).atanh()).sinh();
+        let xi = t.atan2(delta_lon.cos());
+        let eta = (delta_lon.sin() / (1. + t * t).sqrt()).atanh();
+
+        let mut x = eta;
+        let mut y = xi;
+        for (j, alpha) in alpha.iter().enumerate() {
+            let k = 2. * (j + 1) as f64;
+            x += alpha * (k * xi).cos() * (k * eta).sinh();
+            y += alpha * (k * xi).sin() * (k * eta).cosh();
+        }
+
+        let false_northing = if self.north { 0. } else { 10_000_000. };
+        [
+            500_000. + k0 * rectifying_radius * x,
+            false_northing + k0 * rectifying_radius * y,
+        ]
+    }
+}
+
+/// The center of the bounding box of the points
+fn center(points: &[GeoPoint]) -> GeoPoint {
+    if points.is_empty() {
+        return GeoPoint::from_micro_degrees(0, 0);
+    }
+    let mut min = (i32::MAX, i32::MAX);
+    let mut max = (i32::MIN, i32::MIN);
+    for point in points {
+        let (lat, lon) = (point.lat.as_micro_degrees(), point.lon.as_micro_degrees());
+        min = (min.0.min(lat), min.1.min(lon));
+        max = (max.0.max(lat), max.1.max(lon));
+    }
+    GeoPoint::from_micro_degrees(
+        ((min.0 as i64 + max.0 as i64) / 2) as i32,
+        ((min.1 as i64 + max.1 as i64) / 2) as i32,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(got: [f64; 2], expected: [f64; 2], max_error: f64) {
+        assert!(
+            (got[0] - expected[0]).abs() < max_error && (got[1] - expected[1]).abs() < max_error,
+            "{:?} ~ {:?}",
+            got,
+            expected
+        );
+    }
+
+    #[test]
+    fn utm() {
+        // The CN Tower, in Toronto, is at 17T 630084 4833439
+        let cn_tower = GeoPoint::from_degrees(43.642567, -79.387139);
+        let zone = UtmZone::containing(&cn_tower);
+        assert_eq!(
+            zone,
+            UtmZone {
+                number: 17,
+                north: true
+            }
+        );
+        assert_eq!(zone.epsg(), 32617);
+        assert_close(zone.project(&cn_tower), [630084., 4833439.], 1.);
+
+        // On the central meridian, the northing is the scaled length of the meridian arc
+        let zone = UtmZone::containing(&GeoPoint::from_degrees(45., 3.));
+        assert_eq!(zone.epsg(), 32631);
+        assert_close(
+            zone.project(&GeoPoint::from_degrees(45., 3.)),
+            [500_000., 0.9996 * 4_984_944.378],
+            0.01,
+        );
+        assert_close(
+            zone.project(&GeoPoint::from_degrees(0., 3.)),
+            [500_000., 0.],
+            1e-6,
+        );
+
+        // The southern hemisphere mirrors the northern one
+        let south = UtmZone {
+            number: 31,
+            north: false,
+        };
+        let [x, y] = zone.project(&GeoPoint::from_degrees(10., 5.));
+        assert_close(
+            south.project(&GeoPoint::from_degrees(-10., 5.)),
+            [x, 10_000_000. - y],
+            1e-6,
+        );
+        assert_eq!(south.epsg(), 32731);
+    }
+
+    #[test]
+    fn projections() {
+        let points = [
+            GeoPoint::from_degrees(42.5, 1.45),
+            GeoPoint::from_degrees(42.6, 1.65),
+        ];
+        let (projection, xy) = Crs::Wgs84.project_all(&points);
+        assert_eq!(projection.to_string(), "EPSG:4326");
+        assert_eq!(xy, vec![[1.45, 42.5], [1.65, 42.6]]);
+
+        let (projection, xy) = Crs::WebMercator.project_all(&points);
+        assert_eq!(projection.to_string(), "EPSG:3857");
+        assert_eq!(xy[0], points[0].web_mercator_project());
+
+        // The points are on both sides of the border between the zones 31 and 30, but their
+        // center is in the zone 31
+        let points = [
+            GeoPoint::from_degrees(42.5, -0.5),
+            GeoPoint::from_degrees(42.6, 1.5),
+        ];
+        assert_eq!(Crs::Utm.projection(&points).to_string(), "EPSG:32631");
+
+        assert_eq!("UTM".parse(), Ok(Crs::Utm));
+        assert_eq!("EPSG:3857".parse(), Ok(Crs::WebMercator));
+        assert!("mercator".parse::<Crs>().is_err());
+    }
+}
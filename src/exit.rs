@@ -0,0 +1,105 @@
+//! Report command failures with distinct exit codes, so that scripts can react to them
+
+use serde_json::json;
+use std::io;
+use std::process;
+use std::str::FromStr;
+
+/// The exit code of the process when a command fails
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Any other error
+    Other = 1,
+    /// An input file does not exist
+    InputNotFound = 2,
+    /// An input file is not in the expected format
+    UnsupportedFormat = 3,
+    /// The cartography could not be generated
+    GenerationFailure = 4,
+    /// The API could not listen on its address
+    BindFailure = 5,
+}
+
+impl ExitCode {
+    /// Classify an error of a command, given the code to use when the error is specific to it
+    pub fn classify(error: &io::Error, command_failure: ExitCode) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => ExitCode::InputNotFound,
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+                ExitCode::UnsupportedFormat
+            }
+            _ => command_failure,
+        }
+    }
+
+    /// The name used in the JSON output
+    pub fn name(self) -> &'static str {
+        match self {
+            ExitCode::Other => "other",
+            ExitCode::InputNotFound => "input_not_found",
+            ExitCode::UnsupportedFormat => "unsupported_format",
+            ExitCode::GenerationFailure => "generation_failure",
+            ExitCode::BindFailure => "bind_failure",
+        }
+    }
+}
+
+/// How errors are written to the standard error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Text,
+    /// A single line like `{"error": "input_not_found", "code": 2, "message": "..."}`
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("Invalid error format {}, expected text or json", s)),
+        }
+    }
+}
+
+/// Write the error of a command and exit with its code
+pub fn exit_with_error(error: &io::Error, code: ExitCode, format: ErrorFormat) -> ! {
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {}", error),
+        ErrorFormat::Json => eprintln!(
+            "{}",
+            json!({
+                "error": code.name(),
+                "code": code as i32,
+                "message": error.to_string(),
+            })
+        ),
+    }
+    process::exit(code as i32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classify() {
+        let error = |kind| io::Error::new(kind, "error");
+        assert_eq!(
+            ExitCode::classify(&error(io::ErrorKind::NotFound), ExitCode::BindFailure),
+            ExitCode::InputNotFound
+        );
+        assert_eq!(
+            ExitCode::classify(&error(io::ErrorKind::InvalidData), ExitCode::Other),
+            ExitCode::UnsupportedFormat
+        );
+        assert_eq!(
+            ExitCode::classify(&error(io::ErrorKind::AddrInUse), ExitCode::BindFailure),
+            ExitCode::BindFailure
+        );
+        assert_eq!("json".parse(), Ok(ErrorFormat::Json));
+        assert!("xml".parse::<ErrorFormat>().is_err());
+    }
+}
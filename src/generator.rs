@@ -1,31 +1,118 @@
 mod data_types;
+mod merge;
 mod parser;
+mod progress;
+mod report;
 
-use crate::utils::{format_bytes, format_num, DebugTime};
+pub use data_types::DuplicateArcs;
+pub use merge::merge;
+pub use parser::{filter::TagFilter, Profile, TravelMode};
+pub use progress::{NoProgress, Phase, Progress, ProgressBars};
+pub use report::{GenerationReport, GenerationWarning, WarningReason};
+
+use crate::{format_bytes, format_num, Borders, DebugTime, Metadata, Stopwatch};
 use osmpbf::*;
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// How to generate a cartography file with `generate()`. The default is the one of the command line
+#[derive(Clone, Debug)]
+pub struct GenerateOptions {
+    /// How many threads to use, or all the hyperthreads available when `None`
+    pub num_threads: Option<usize>,
+    /// Nodes that can't be reached from roads with a level up to this one are removed, unless it's
+    /// `None`
+    pub prune_level: Option<u8>,
+    /// Add edges that don't exist in reality to make the graph strongly connected
+    pub synthetic_edges: bool,
+    /// Sort the edges, so that the result does not depend on the number of threads
+    pub sorted_output: bool,
+    /// Replace the chains of nodes that only connect two others by single edges, whose shapes keep
+    /// the points of the removed nodes
+    pub contract_chains: bool,
+    /// What to do with the arcs between the same nodes
+    pub duplicate_arcs: DuplicateArcs,
+    /// Store the ids of the OSM nodes and ways, to link the graph to openstreetmap.org
+    pub osm_ids: bool,
+    /// Store the Web Mercator coordinates of the nodes, so that opening the file skips projecting
+    /// them. The file is 16 bytes per node larger
+    pub projected_nodes: bool,
+    /// A GeoJSON file with the borders, like the administrative boundaries of the countries, to
+    /// flag the edges that cross them
+    pub borders: Option<PathBuf>,
+    /// Which ways are considered roads
+    pub profile: Profile,
+    /// Extra key-value pairs to write in the file, like the region of the extract. They replace the
+    /// ones written by the generator with the same key
+    pub metadata: Metadata,
+    /// Fail when a problem is found in the input, instead of skipping the broken ways or tags
+    pub strict: bool,
+    /// Do not log the duration of each step
+    pub quiet: bool,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        GenerateOptions {
+            num_threads: None,
+            prune_level: Some(2),
+            synthetic_edges: true,
+            sorted_output: false,
+            contract_chains: false,
+            duplicate_arcs: DuplicateArcs::default(),
+            osm_ids: false,
+            projected_nodes: false,
+            borders: None,
+            profile: Profile::default(),
+            metadata: Metadata::default(),
+            strict: false,
+            quiet: false,
+        }
+    }
+}
 
+/// Generate the cartography file from the OSM data. The phases that read the file report their
+/// progress to `progress` and the duration of each step is logged, unless `options.quiet` is set.
+/// Returns what was generated and how long it took. With `options.strict`, the problems found in the
+/// input are an error of kind `InvalidData` and no file is written
 pub fn generate<P: AsRef<Path>>(
-    num_threads: Option<usize>,
     input_file: P,
     output_file: P,
-) -> io::Result<()> {
-    let mut timer = DebugTime::new();
+    options: &GenerateOptions,
+    progress: &dyn Progress,
+) -> io::Result<GenerationReport> {
+    let mut timer = if options.quiet {
+        DebugTime::quiet()
+    } else {
+        DebugTime::new()
+    };
+    let mut stopwatch = Stopwatch::start();
+    let mut report = GenerationReport::default();
+    let profile = &options.profile;
+
+    // Read the borders first, to fail before the long steps
+    let borders = match &options.borders {
+        Some(path) => Some(Borders::from_geojson(&fs::read_to_string(path)?)?),
+        None => None,
+    };
 
     // Detect threads
-    let num_threads = num_threads.unwrap_or(num_cpus::get());
+    let num_threads = options.num_threads.unwrap_or(num_cpus::get());
     timer.msg(format!("Will use {} threads", num_threads));
 
-    // Read input file
-    let mmap = unsafe { Mmap::from_path(&input_file)? };
-    let size = fs::metadata(&input_file)?.len();
+    // Read input file, converting it to PBF if needed
+    let input = data_types::PbfInput::open(&input_file)?;
+    if input.format() != data_types::InputFormat::Pbf {
+        timer.msg(format!("Converted {:?} input to PBF", input.format()));
+    }
+    let mmap = unsafe { Mmap::from_path(input.path())? };
+    report.input_bytes = fs::metadata(&input_file)?.len();
     let file = data_types::OSMFile::from_mmap(&mmap)?;
     timer.msg(format!(
         "Loaded {} blobs from {}",
         format_num(file.blobs.len()),
-        format_bytes(size)
+        format_bytes(report.input_bytes)
     ));
 
     // Classify file
@@ -37,41 +124,118 @@ pub fn generate<P: AsRef<Path>>(
         format_num(file.relations_blobs.len()),
     ));
 
+    // Describe where the file comes from
+    let mut metadata = Metadata::default();
+    if let Some(name) = input_file.as_ref().file_name() {
+        metadata.insert(Metadata::SOURCE, name.to_string_lossy().into_owned());
+    }
+    if let Some(timestamp) = data_types::read_replication_timestamp(input.path())? {
+        metadata.insert(Metadata::REPLICATION_TIMESTAMP, timestamp.to_string());
+    }
+    metadata.insert(
+        Metadata::GENERATOR_VERSION,
+        env!("CARGO_PKG_VERSION").to_owned(),
+    );
+    metadata.insert(Metadata::PROFILE, profile.to_string());
+    report.read = stopwatch.lap();
+
+    // Load the ways of the cycle and bus routes
+    let routes = parser::relation::parse_file(&file, progress);
+    let (bicycle_ways, bus_ways) = routes.stats();
+    timer.msg(format!(
+        "Found {} ways in cycle routes and {} in bus routes",
+        format_num(bicycle_ways),
+        format_num(bus_ways)
+    ));
+
     // Detect used nodes and junctions
-    let (junctions, num_ways) = parser::junction::parse_file(&file, num_threads);
-    let stats = junctions.stats();
+    let (junctions, mut parsed_ways, mut warnings) =
+        parser::junction::parse_file(&file, num_threads, profile, &routes, progress)?;
+    drop(routes);
+    report.ways = parsed_ways.iter().map(|ways| ways.len()).sum();
+    let road_names = data_types::ParsedWays::merge_names(&mut parsed_ways);
+    report.road_names = road_names.0.len();
+    let (internal_nodes, junction_nodes) = junctions.stats();
+    report.internal_nodes = internal_nodes;
+    report.junctions = junction_nodes;
     timer.msg(format!(
-        "Found {} junctions and {} internal nodes from {} ways",
-        format_num(stats.1),
-        format_num(stats.0),
-        format_num(num_ways),
+        "Found {} junctions and {} internal nodes from {} ways with {} names",
+        format_num(report.junctions),
+        format_num(report.internal_nodes),
+        format_num(report.ways),
+        format_num(report.road_names),
     ));
+    report.junctions_parse = stopwatch.lap();
 
     // Load node info
-    let nodes = parser::node::parse_file(&file, &junctions, num_threads);
+    let nodes = parser::node::parse_file(&file, &junctions, num_threads, profile, progress);
     timer.msg(format!(
-        "Loaded info about {} nodes, of which {} are barriers",
+        "Loaded info about {} nodes, of which {} are barriers and {} passable barriers",
         format_num(nodes.len()),
-        format_num(nodes.barrier_len())
+        format_num(nodes.barrier_len()),
+        format_num(nodes.passable_barrier_len())
     ));
+    report.barriers = nodes.barrier_len();
+    report.passable_barriers = nodes.passable_barrier_len();
+    report.nodes_parse = stopwatch.lap();
 
-    // Load ways again to create arcs
-    let mut graph = parser::graph::parse_file(&file, &nodes, &junctions, num_threads);
+    // Split the parsed ways to create arcs
+    let mut graph = parser::graph::parse_file(
+        &parsed_ways,
+        &nodes,
+        &junctions,
+        num_threads,
+        options.duplicate_arcs,
+        &mut warnings,
+        progress,
+    )?;
     timer.msg(format!(
         "Create graph with {} nodes and {} edges",
         format_num(graph.node_len()),
         format_num(graph.edge_len())
     ));
     drop(file);
+    drop(parsed_ways);
     drop(nodes);
     drop(junctions);
     drop(mmap);
+    drop(input);
+    report.graph_build = stopwatch.lap();
+
+    // The threads find the problems in any order
+    warnings.sort();
+    if let Some(first) = warnings.first() {
+        timer.msg(format!(
+            "Skipped {} problems in the input, like {}",
+            format_num(warnings.len()),
+            first
+        ));
+        if options.strict {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Found {} problems in the input, like {}",
+                    format_num(warnings.len()),
+                    first
+                ),
+            ));
+        }
+    }
+    report.warnings = warnings;
 
     // Prune nodes
     let node_len = graph.node_len();
     let edge_len = graph.edge_len();
-    graph.retain_reachable_nodes(2);
-    timer.msg("Pruned unreachable nodes");
+    if let Some(prune_level) = options.prune_level {
+        graph.retain_reachable_nodes(prune_level);
+        timer.msg(format!(
+            "Pruned nodes unreachable from roads up to level {}",
+            prune_level
+        ));
+    } else {
+        graph.retain_connected_nodes();
+        timer.msg("Removed nodes without edges, skipped pruning");
+    }
     timer.msg(format!(
         "Graph now has {} nodes (-{}) and {} edges (-{})",
         format_num(graph.node_len()),
@@ -79,36 +243,276 @@ pub fn generate<P: AsRef<Path>>(
         format_num(graph.edge_len()),
         format_num(edge_len - graph.edge_len())
     ));
+    report.pruned_nodes = node_len - graph.node_len();
+    report.pruned_edges = edge_len - graph.edge_len();
+    report.prune = stopwatch.lap();
 
-    // Connect weakly-connected components
     let edge_len = graph.edge_len();
-    graph.fix_dead_ends();
-    timer.msg("Weakly-connected components were strongly connected");
-    timer.msg(format!(
-        "Graph now has {} edges (+{})",
-        format_num(graph.edge_len()),
-        format_num(graph.edge_len() - edge_len)
-    ));
+    if options.sorted_output {
+        // The threads insert the edges in any order, which changes the synthetic edges
+        graph.sort_edges();
+        timer.msg("Sorted the edges");
+    }
 
-    // Connect all components
-    let edge_len = graph.edge_len();
-    graph.strongly_connect();
-    timer.msg("All smaller components were strongly connected with the main one");
-    timer.msg(format!(
-        "Graph now has {} edges (+{})",
-        format_num(graph.edge_len()),
-        format_num(graph.edge_len() - edge_len)
-    ));
+    if options.synthetic_edges {
+        // Connect weakly-connected components
+        let edge_len = graph.edge_len();
+        graph.fix_dead_ends();
+        timer.msg("Weakly-connected components were strongly connected");
+        timer.msg(format!(
+            "Graph now has {} edges (+{})",
+            format_num(graph.edge_len()),
+            format_num(graph.edge_len() - edge_len)
+        ));
+
+        // Connect all components
+        let edge_len = graph.edge_len();
+        graph.strongly_connect();
+        timer.msg("All smaller components were strongly connected with the main one");
+        report.components = 1;
+        timer.msg(format!(
+            "Graph now has {} edges (+{})",
+            format_num(graph.edge_len()),
+            format_num(graph.edge_len() - edge_len)
+        ));
+    } else {
+        report.components = graph.scc().len();
+        timer.msg(format!(
+            "Skipped synthetic edges, graph has {} strongly connected components",
+            format_num(report.components)
+        ));
+    }
+    report.synthetic_edges = graph.edge_len() - edge_len;
+
+    if options.contract_chains {
+        let edge_len = graph.edge_len();
+        report.contracted_nodes = graph.contract_chains(options.osm_ids);
+        timer.msg(format!(
+            "Contracted {} nodes in chains, graph now has {} nodes and {} edges (-{})",
+            format_num(report.contracted_nodes),
+            format_num(graph.node_len()),
+            format_num(graph.edge_len()),
+            format_num(edge_len - graph.edge_len())
+        ));
+    }
+    if let Some(borders) = &borders {
+        report.border_edges = graph.mark_borders(borders);
+        timer.msg(format!(
+            "Found {} edges that cross borders",
+            format_num(report.border_edges)
+        ));
+    }
+
+    report.connect = stopwatch.lap();
 
     // Serialize
-    parser::serialize::serialize(&graph, &output_file)?;
+    metadata.insert(Metadata::BBOX, parser::serialize::bbox(&graph));
+    metadata.0.extend(options.metadata.0.clone());
+    parser::serialize::serialize(
+        &graph,
+        &parser::road_levels(profile),
+        &road_names,
+        &metadata,
+        options.osm_ids,
+        options.projected_nodes,
+        &output_file,
+    )?;
+    report.output_bytes = fs::metadata(&output_file)?.len();
+    report.nodes = graph.node_len();
+    report.edges = graph.edge_len();
     timer.msg(format!(
         "Wrote results to {}, size = {}",
         output_file.as_ref().display(),
-        format_bytes(fs::metadata(&output_file)?.len())
+        format_bytes(report.output_bytes)
     ));
+    report.serialize = stopwatch.lap();
 
+    report.peak_memory = report::peak_memory();
     timer.msg("Done! #DFTBA");
 
-    Ok(())
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Cartograph, DiversityOptions, GeoPoint, GraphPath, ProjectedPoint};
+    use petgraph::graph::EdgeIndex;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn generate_report() {
+        let output = NamedTempFile::new().unwrap();
+        let mut metadata = Metadata::default();
+        metadata.insert("region", "Andorra".to_owned());
+        let options = GenerateOptions {
+            sorted_output: true,
+            metadata,
+            strict: true,
+            quiet: true,
+            ..GenerateOptions::default()
+        };
+        let report = generate(
+            Path::new("test_data/andorra-latest.osm.pbf"),
+            output.path(),
+            &options,
+            &NoProgress,
+        )
+        .unwrap();
+
+        let carto = Cartograph::open(output.path()).unwrap();
+        assert_eq!(report.nodes, carto.graph.node_count());
+        assert_eq!(report.edges, carto.graph.edge_count());
+        assert_eq!(report.road_names, carto.road_names().0.len());
+        assert!(carto
+            .graph
+            .raw_edges()
+            .iter()
+            .any(|edge| carto.road_name(&edge.weight) == Some("CG-2")));
+        let metadata = carto.metadata();
+        assert_eq!(
+            metadata.get(Metadata::SOURCE),
+            Some("andorra-latest.osm.pbf")
+        );
+        assert_eq!(
+            metadata.get(Metadata::REPLICATION_TIMESTAMP),
+            Some("1578088742")
+        );
+        assert_eq!(metadata.get(Metadata::PROFILE), Some("default"));
+        assert_eq!(metadata.get("region"), Some("Andorra"));
+        let bbox: Vec<f64> = metadata
+            .get(Metadata::BBOX)
+            .unwrap()
+            .split(',')
+            .map(|value| value.parse().unwrap())
+            .collect();
+        assert!(bbox[0] < 1.5 && bbox[2] > 1.7 && bbox[1] < 42.5 && bbox[3] > 42.6);
+        assert_eq!(
+            report.output_bytes,
+            fs::metadata(output.path()).unwrap().len()
+        );
+        assert_eq!(report.components, 1);
+        assert!(report.junctions + report.internal_nodes >= report.nodes + report.pruned_nodes);
+        assert!(report.synthetic_edges > 0);
+        assert!(report.warnings.is_empty());
+        if cfg!(target_os = "linux") {
+            assert!(report.peak_memory.unwrap() > report.output_bytes);
+        }
+        assert_eq!(
+            report.total(),
+            report.phases().iter().map(|(_, duration)| *duration).sum()
+        );
+    }
+
+    #[test]
+    fn generate_contracted() {
+        let generate_with = |contract_chains| {
+            let output = NamedTempFile::new().unwrap();
+            let options = GenerateOptions {
+                contract_chains,
+                quiet: true,
+                ..GenerateOptions::default()
+            };
+            let report = generate(
+                Path::new("test_data/andorra-latest.osm.pbf"),
+                output.path(),
+                &options,
+                &NoProgress,
+            )
+            .unwrap();
+            (report, Cartograph::open(output.path()).unwrap())
+        };
+        let (_, full) = generate_with(false);
+        let (report, contracted) = generate_with(true);
+        assert!(report.contracted_nodes > 0);
+        assert_eq!(
+            contracted.graph.node_count(),
+            full.graph.node_count() - report.contracted_nodes
+        );
+        assert!(full.edge_shapes().is_empty());
+        assert!(!contracted.edge_shapes().is_empty());
+
+        // The routes have the same length and go through the removed nodes
+        let route = |carto: &Cartograph| {
+            let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+            let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+            carto.shortest_path(&from, &to)
+        };
+        let (path, contracted_path) = (route(&full), route(&contracted));
+        assert!((path.distance as i64 - contracted_path.distance as i64).abs() <= 5);
+        assert!(contracted_path.points.len() > 2);
+        let length = |path: &GraphPath| *path.cumulative_distances().last().unwrap();
+        assert!((length(&path) - length(&contracted_path)).abs() < 10.);
+
+        // The other searches find the same route here and also follow the shapes. Some can not go
+        // backwards on the edges of the points, so they are put on the edges of the route
+        let on_edge = |point: ProjectedPoint, edge: EdgeIndex| {
+            if point.edge == edge {
+                point
+            } else {
+                ProjectedPoint {
+                    edge,
+                    edge_pos: 1. - point.edge_pos,
+                    ..point
+                }
+            }
+        };
+        let from = on_edge(
+            contracted.project(&GeoPoint::from_degrees(42.553210, 1.588908)),
+            contracted_path.edges[0],
+        );
+        let to = on_edge(
+            contracted.project(&GeoPoint::from_degrees(42.564440, 1.685042)),
+            *contracted_path.edges.last().unwrap(),
+        );
+        let follows_shapes =
+            |path: &GraphPath| (length(path) - length(&contracted_path)).abs() < 10.;
+        let randomized = contracted.randomized_path(&from, &to, 7, &DiversityOptions::default());
+        assert!(follows_shapes(&randomized));
+        let extra = contracted
+            .shortest_path_with_extra_edges(&from, &to, &[])
+            .unwrap();
+        assert!(follows_shapes(&extra));
+        let edge_based = contracted.into_edge_based(|_| Some(0));
+        assert!(follows_shapes(
+            &edge_based.shortest_path(&from, &to).unwrap()
+        ));
+    }
+
+    #[test]
+    fn generate_xml() {
+        let output = NamedTempFile::new().unwrap();
+        let options = GenerateOptions {
+            prune_level: None,
+            projected_nodes: true,
+            strict: true,
+            quiet: true,
+            ..GenerateOptions::default()
+        };
+        let report = generate(
+            Path::new("test_data/tiny.osm"),
+            output.path(),
+            &options,
+            &NoProgress,
+        )
+        .unwrap();
+        assert_eq!(report.ways, 1);
+
+        let carto = Cartograph::open(output.path()).unwrap();
+        assert_eq!(carto.graph.node_count(), 2);
+        assert_eq!(carto.graph.edge_count(), 2);
+        assert!(carto
+            .graph
+            .raw_edges()
+            .iter()
+            .all(|edge| carto.road_name(&edge.weight) == Some("Carrer d'Andorra")));
+        // The way is a member of a cycle route relation
+        assert!(carto
+            .graph
+            .raw_edges()
+            .iter()
+            .all(|edge| edge.weight.bicycle_route && !edge.weight.bus_route));
+        assert_eq!(carto.metadata().get(Metadata::SOURCE), Some("tiny.osm"));
+        assert!(carto.projected_nodes);
+    }
 }
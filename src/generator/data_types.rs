@@ -1,13 +1,16 @@
+mod arcs;
 mod disk_bit_vec;
 mod disk_vec;
 mod graph;
 mod junction;
 mod node;
 mod osm_file;
+mod osm_xml;
+mod ways;
 
-pub use disk_bit_vec::*;
-pub use disk_vec::*;
+pub use arcs::*;
 pub use graph::*;
 pub use junction::*;
 pub use node::*;
 pub use osm_file::*;
+pub use ways::*;
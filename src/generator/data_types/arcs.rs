@@ -0,0 +1,270 @@
+use super::disk_vec::DiskVec;
+use super::graph::{EdgeInfo, NodeIndex};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::str::FromStr;
+
+/// How many arcs are stored in each temporary file. With about 32 bytes per arc, each file has
+/// 32 MiB
+const ARCS_PER_CHUNK: usize = 1 << 20;
+
+/// A directed arc between two nodes of the graph
+#[derive(Copy, Clone, Debug)]
+pub struct Arc {
+    pub from: NodeIndex,
+    pub to: NodeIndex,
+    pub info: EdgeInfo,
+}
+
+/// What to do with the arcs between the same nodes, in the same direction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateArcs {
+    /// Merge them in a single edge with `EdgeInfo::merge()`. This fixes the ways that overlap, like
+    /// badly tagged roundabouts, but also joins parallel carriageways and slip roads
+    #[default]
+    Merge,
+    /// Keep them all as parallel edges
+    KeepAll,
+    /// Keep only the shortest one. The ones with the same distance are merged
+    KeepShortest,
+}
+
+impl FromStr for DuplicateArcs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "merge" => Ok(DuplicateArcs::Merge),
+            "keep-all" => Ok(DuplicateArcs::KeepAll),
+            "keep-shortest" => Ok(DuplicateArcs::KeepShortest),
+            _ => Err(format!(
+                "Invalid duplicate arcs policy {}, expected merge, keep-all or keep-shortest",
+                s
+            )),
+        }
+    }
+}
+
+/// Collect the arcs of the graph in temporary files, so that the kernel can swap them out while
+/// the ways are parsed, instead of growing the graph in memory
+pub struct ArcAccumulator {
+    chunks: Vec<DiskVec<Arc>>,
+    chunk_capacity: usize,
+}
+
+impl ArcAccumulator {
+    pub fn new() -> Self {
+        Self::with_chunk_capacity(ARCS_PER_CHUNK)
+    }
+
+    pub fn with_chunk_capacity(chunk_capacity: usize) -> Self {
+        ArcAccumulator {
+            chunks: Vec::new(),
+            chunk_capacity: chunk_capacity.max(1),
+        }
+    }
+
+    pub fn push(&mut self, arc: Arc) -> io::Result<()> {
+        let has_room = matches!(self.chunks.last(), Some(chunk) if chunk.len() < chunk.capacity());
+        if !has_room {
+            self.chunks.push(DiskVec::new(self.chunk_capacity)?);
+        }
+        self.chunks.last_mut().unwrap().push(arc);
+        Ok(())
+    }
+
+    /// The number of arcs pushed, including the duplicated ones
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.len()).sum()
+    }
+
+    /// Sort each chunk in place and merge them, so that the arcs are returned by increasing
+    /// `(from, to)`. Duplicated arcs are handled according to `duplicates`
+    pub fn into_sorted(mut self, duplicates: DuplicateArcs) -> SortedArcs {
+        let mut heap = BinaryHeap::with_capacity(self.chunks.len());
+        for (i, chunk) in self.chunks.iter_mut().enumerate() {
+            chunk.sort_unstable_by_key(|arc| (arc.from, arc.to));
+            if let Some(arc) = chunk.first() {
+                heap.push(Reverse((arc.from, arc.to, i, 0)));
+            }
+        }
+        SortedArcs {
+            chunks: self.chunks,
+            heap,
+            duplicates,
+            parallel: Vec::new(),
+        }
+    }
+}
+
+/// The arcs of an `ArcAccumulator`, sorted and with the duplicates handled
+pub struct SortedArcs {
+    chunks: Vec<DiskVec<Arc>>,
+    /// The next arc of each chunk, as `(from, to, chunk, position)`
+    heap: BinaryHeap<Reverse<(NodeIndex, NodeIndex, usize, usize)>>,
+    duplicates: DuplicateArcs,
+    /// The parallel arcs that were not returned yet, in reverse order, when they are all kept
+    parallel: Vec<Arc>,
+}
+
+impl SortedArcs {
+    /// Remove the smallest arc from the heap, replacing it with the next one of its chunk
+    fn pop(&mut self) -> Option<Arc> {
+        let Reverse((_, _, chunk, pos)) = self.heap.pop()?;
+        let arc = self.chunks[chunk][pos];
+        if let Some(next) = self.chunks[chunk].get(pos + 1) {
+            self.heap
+                .push(Reverse((next.from, next.to, chunk, pos + 1)));
+        }
+        Some(arc)
+    }
+
+    /// Remove the next arc if it is a duplicate of `arc`
+    fn pop_duplicate(&mut self, arc: &Arc) -> Option<Arc> {
+        match self.heap.peek() {
+            Some(Reverse((from, to, _, _))) if (*from, *to) == (arc.from, arc.to) => self.pop(),
+            _ => None,
+        }
+    }
+}
+
+impl Iterator for SortedArcs {
+    type Item = Arc;
+
+    fn next(&mut self) -> Option<Arc> {
+        if let Some(arc) = self.parallel.pop() {
+            return Some(arc);
+        }
+
+        let mut arc = self.pop()?;
+        match self.duplicates {
+            DuplicateArcs::Merge => {
+                while let Some(duplicate) = self.pop_duplicate(&arc) {
+                    arc.info.merge(&duplicate.info);
+                }
+            }
+            DuplicateArcs::KeepShortest => {
+                while let Some(duplicate) = self.pop_duplicate(&arc) {
+                    if duplicate.info.distance < arc.info.distance {
+                        arc = duplicate;
+                    } else if duplicate.info.distance == arc.info.distance {
+                        arc.info.merge(&duplicate.info);
+                    }
+                }
+            }
+            DuplicateArcs::KeepAll => {
+                while let Some(duplicate) = self.pop_duplicate(&arc) {
+                    self.parallel.push(duplicate);
+                }
+                if !self.parallel.is_empty() {
+                    // The arcs are pushed by the threads in any order, so they are sorted by all
+                    // their attributes for the output to be always the same
+                    self.parallel.push(arc);
+                    self.parallel
+                        .sort_unstable_by_key(|arc| Reverse(arc.info.sort_key()));
+                    arc = self.parallel.pop().unwrap();
+                }
+            }
+        }
+        Some(arc)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AccessClass, Restrictions, Smoothness, Surface};
+
+    fn arc(from: usize, to: usize, distance: u32) -> Arc {
+        Arc {
+            from: NodeIndex::new(from),
+            to: NodeIndex::new(to),
+            info: EdgeInfo {
+                road_level: 3,
+                distance,
+                roundabout: false,
+                capacity: 1000,
+                lanes: 0,
+                width: 0.,
+                synthetic: false,
+                conditional: false,
+                reversible: false,
+                barrier: false,
+                cycleway: false,
+                bicycle_route: false,
+                bus_route: false,
+                border: false,
+                track_grade: 0,
+                surface: Surface::Unknown,
+                smoothness: Smoothness::Unknown,
+                access: AccessClass::Normal,
+                restrictions: Restrictions::default(),
+                way_id: 0,
+                name: 0,
+                shape: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn sorted_arcs() {
+        let mut arcs = ArcAccumulator::with_chunk_capacity(3);
+        for &(from, to, distance) in &[
+            (2, 1, 10),
+            (0, 1, 20),
+            (2, 1, 5),
+            (1, 0, 30),
+            (0, 1, 25),
+            (0, 2, 40),
+            (2, 1, 7),
+        ] {
+            arcs.push(arc(from, to, distance)).unwrap();
+        }
+        assert_eq!(arcs.len(), 7);
+        assert_eq!(arcs.chunks.len(), 3);
+
+        let sorted: Vec<_> = arcs
+            .into_sorted(DuplicateArcs::Merge)
+            .map(|arc| (arc.from.index(), arc.to.index(), arc.info.distance))
+            .collect();
+        assert_eq!(sorted, vec![(0, 1, 20), (0, 2, 40), (1, 0, 30), (2, 1, 5)]);
+
+        assert_eq!(
+            ArcAccumulator::new()
+                .into_sorted(DuplicateArcs::default())
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn duplicate_arcs() {
+        let sorted = |duplicates| {
+            let mut arcs = ArcAccumulator::with_chunk_capacity(2);
+            for &(from, to, distance, road_level) in
+                &[(0, 1, 20, 1), (1, 0, 30, 2), (0, 1, 20, 3), (0, 1, 25, 4)]
+            {
+                let mut arc = arc(from, to, distance);
+                arc.info.road_level = road_level;
+                arcs.push(arc).unwrap();
+            }
+            arcs.into_sorted(duplicates)
+                .map(|arc| (arc.from.index(), arc.info.distance, arc.info.road_level))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sorted(DuplicateArcs::Merge), vec![(0, 20, 4), (1, 30, 2)]);
+        // The arcs with the same distance are merged
+        assert_eq!(
+            sorted(DuplicateArcs::KeepShortest),
+            vec![(0, 20, 3), (1, 30, 2)]
+        );
+        assert_eq!(
+            sorted(DuplicateArcs::KeepAll),
+            vec![(0, 20, 1), (0, 20, 3), (0, 25, 4), (1, 30, 2)]
+        );
+        assert_eq!("keep-all".parse(), Ok(DuplicateArcs::KeepAll));
+        assert!("keep".parse::<DuplicateArcs>().is_err());
+    }
+}
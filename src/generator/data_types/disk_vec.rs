@@ -2,7 +2,7 @@ use memmap::MmapMut;
 use std::io;
 use std::marker::PhantomData;
 use std::mem::size_of;
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, Index};
 use tempfile::tempfile;
 
 /// A fixed-sized vector that uses memory mapped as backstorage, so that the kernel
@@ -88,6 +88,43 @@ impl<T> Drop for DiskVec<T> {
     }
 }
 
+/// A growable vector stored in `DiskVec`s of a fixed capacity, that are created as needed
+pub struct ChunkedDiskVec<T> {
+    chunks: Vec<DiskVec<T>>,
+    chunk_capacity: usize,
+    len: usize,
+}
+
+impl<T> ChunkedDiskVec<T> {
+    pub fn new(chunk_capacity: usize) -> Self {
+        ChunkedDiskVec {
+            chunks: Vec::new(),
+            chunk_capacity: chunk_capacity.max(1),
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: T) -> io::Result<()> {
+        if self.len.is_multiple_of(self.chunk_capacity) {
+            self.chunks.push(DiskVec::new(self.chunk_capacity)?);
+        }
+        self.chunks.last_mut().unwrap().push(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<T> Index<usize> for ChunkedDiskVec<T> {
+    type Output = T;
+    fn index(&self, index: usize) -> &T {
+        &self.chunks[index / self.chunk_capacity][index % self.chunk_capacity]
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -126,4 +163,19 @@ mod test {
             vec![3.14, 3.14, 3.14, 3.14]
         );
     }
+
+    #[test]
+    fn chunked() {
+        let mut v = ChunkedDiskVec::new(3);
+        for i in 0..10 {
+            v.push(i).unwrap();
+        }
+
+        assert_eq!(v.len(), 10);
+        assert_eq!(v.chunks.len(), 4);
+        assert_eq!(
+            (0..10).map(|i| v[i]).collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>()
+        );
+    }
 }
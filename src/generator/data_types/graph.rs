@@ -1,5 +1,6 @@
-use super::node::Nodes;
-use crate::utils::GeoPoint;
+use super::arcs::SortedArcs;
+use crate::{estimate_capacity, Borders, EdgeShapes, GeoPoint};
+use crate::{AccessClass, Restrictions, Smoothness, Surface};
 use petgraph;
 use petgraph::algo::kosaraju_scc;
 use petgraph::visit::{EdgeRef, VisitMap};
@@ -9,37 +10,46 @@ pub type NodeIndex = petgraph::graph::NodeIndex<u32>;
 
 pub struct Graph {
     pub graph: petgraph::Graph<NodeInfo, EdgeInfo, petgraph::Directed>,
+    /// The intermediate points of the contracted edges, referenced by `EdgeInfo::shape`
+    pub shapes: EdgeShapes,
 }
 
-impl<'a> Graph {
-    /// Create a new empty graph that will accept nodes from `Nodes`.
-    pub fn new(nodes: &'a Nodes) -> Self {
-        let mut graph = petgraph::Graph::with_capacity(nodes.len(), 0);
+impl Graph {
+    /// Create the graph with the `num_nodes` given nodes, like the ones from `Nodes`, and arcs, that
+    /// are already sorted. The edges are inserted in this order
+    pub fn from_arcs(
+        num_nodes: usize,
+        nodes: impl IntoIterator<Item = NodeInfo>,
+        arcs: SortedArcs,
+    ) -> Self {
+        let mut graph = petgraph::Graph::with_capacity(num_nodes, 0);
 
         // Create nodes
-        for &point in nodes.points() {
-            graph.add_node(NodeInfo { point });
+        for node in nodes {
+            graph.add_node(node);
         }
 
-        Self { graph }
+        for arc in arcs {
+            graph.add_edge(arc.from, arc.to, arc.info);
+        }
+
+        Self {
+            graph,
+            shapes: EdgeShapes::default(),
+        }
     }
 
-    /// Add a new arc to the graph. If the arc already exists, keep the highest road level and
-    /// least distance. This happens quite a bit with roundabouts that are not correctly tagged
-    pub fn push_arc(&mut self, from: NodeIndex, to: NodeIndex, road_level: u8, distance: u32) {
-        if let Some(edge) = self.graph.find_edge(from, to) {
-            let edge = &mut self.graph[edge];
-            edge.road_level = edge.road_level.max(road_level);
-            edge.distance = edge.distance.min(distance);
-        } else {
-            self.graph.add_edge(
-                from,
-                to,
-                EdgeInfo {
-                    road_level,
-                    distance,
-                },
-            );
+    /// Reinsert the edges sorted by source and target. The nodes are already sorted by their OSM id
+    pub fn sort_edges(&mut self) {
+        let mut edges: Vec<_> = self
+            .graph
+            .edge_references()
+            .map(|edge| (edge.source(), edge.target(), *edge.weight()))
+            .collect();
+        edges.sort_by_key(|&(source, target, _)| (source, target));
+        self.graph.clear_edges();
+        for (source, target, info) in edges {
+            self.graph.add_edge(source, target, info);
         }
     }
 
@@ -64,6 +74,13 @@ impl<'a> Graph {
             .retain_nodes(|_graph, node_index| visitor.discovered.is_visited(&node_index));
     }
 
+    /// Remove the nodes that are not the endpoint of any edge, like the internal nodes of the ways
+    pub fn retain_connected_nodes(&mut self) {
+        self.graph.retain_nodes(|graph, node_index| {
+            graph.neighbors_undirected(node_index).next().is_some()
+        });
+    }
+
     /// Add fake edges to avoid dead-ends in the graph.
     /// More precisely, every edge that weakly connects two strongly-connected
     /// subgraphs will be "doubled", that is, a new reversed copy will be added
@@ -88,7 +105,20 @@ impl<'a> Graph {
             let source = edge.source();
             let target = edge.target();
             if component_ids[source.index()] != component_ids[target.index()] {
-                let info = edge.weight().clone();
+                // The reversed edge does not follow the roundabout direction nor its restrictions, and
+                // it ends at the barrier of the original one
+                let info = EdgeInfo {
+                    roundabout: false,
+                    synthetic: true,
+                    conditional: false,
+                    reversible: false,
+                    barrier: false,
+                    cycleway: false,
+                    bicycle_route: false,
+                    bus_route: false,
+                    border: false,
+                    ..*edge.weight()
+                };
                 new_edges.push((target, source, info));
             }
         }
@@ -148,12 +178,152 @@ impl<'a> Graph {
             let info = EdgeInfo {
                 distance,
                 road_level: 5,
+                roundabout: false,
+                capacity: estimate_capacity(5, 1),
+                lanes: 0,
+                width: 0.,
+                synthetic: true,
+                conditional: false,
+                reversible: false,
+                barrier: false,
+                cycleway: false,
+                bicycle_route: false,
+                bus_route: false,
+                border: false,
+                track_grade: 0,
+                surface: Surface::Unknown,
+                smoothness: Smoothness::Unknown,
+                access: AccessClass::Normal,
+                restrictions: Restrictions::default(),
+                way_id: 0,
+                name: 0,
+                shape: 0,
             };
             self.graph.add_edge(node_index, base_index, info);
             self.graph.add_edge(base_index, node_index, info);
         }
     }
 
+    /// Replace the chains of nodes that only connect two others by single edges, keeping the points
+    /// of the removed nodes in the shapes of the new edges. Such nodes are left where ways were
+    /// split or by barriers that vehicles may pass. A node is only removed when both of its
+    /// neighbors are connected to it in the same directions, the arcs through it have the same
+    /// attributes and it is not a barrier. The way ids are only compared with `keep_way_ids`.
+    /// Returns the number of removed nodes
+    pub fn contract_chains(&mut self, keep_way_ids: bool) -> usize {
+        let mut shapes = std::mem::take(&mut self.shapes.0);
+        let mut contracted = 0;
+        for node in self.graph.node_indices() {
+            let arcs = match self.pass_through_arcs(node, keep_way_ids) {
+                Some(arcs) => arcs,
+                None => continue,
+            };
+            let point = self.graph[node].point;
+            let mut edges: Vec<_> = arcs.iter().flat_map(|&(from, to)| vec![from, to]).collect();
+            let mut new_edges = Vec::new();
+            for (from, to) in arcs {
+                let (source, _) = self.graph.edge_endpoints(from).unwrap();
+                let (_, target) = self.graph.edge_endpoints(to).unwrap();
+                let (from, to) = (self.graph[from], self.graph[to]);
+                let mut shape = take_shape(&mut shapes, from.shape);
+                shape.push(point);
+                shape.extend(take_shape(&mut shapes, to.shape));
+                shapes.push(shape);
+                let info = EdgeInfo {
+                    distance: from.distance + to.distance,
+                    shape: shapes.len() as u32,
+                    ..from
+                };
+                new_edges.push((source, target, info));
+            }
+            // Removing an edge moves the last one to its index
+            edges.sort_unstable_by(|a, b| b.cmp(a));
+            for edge in edges {
+                self.graph.remove_edge(edge);
+            }
+            for (source, target, info) in new_edges {
+                self.graph.add_edge(source, target, info);
+            }
+            contracted += 1;
+        }
+        self.retain_connected_nodes();
+
+        // Only keep the shapes of the remaining edges, in the order of the edges
+        let mut kept = Vec::new();
+        for info in self.graph.edge_weights_mut() {
+            if info.shape != 0 {
+                kept.push(take_shape(&mut shapes, info.shape));
+                info.shape = kept.len() as u32;
+            }
+        }
+        self.shapes = EdgeShapes(kept);
+        contracted
+    }
+
+    /// The pairs of arcs, entering and leaving `node`, that can be merged to remove it: one pair
+    /// for a one-way road or two for a two-way one. `None` if the node must be kept
+    fn pass_through_arcs(
+        &self,
+        node: NodeIndex,
+        keep_way_ids: bool,
+    ) -> Option<Vec<(petgraph::graph::EdgeIndex, petgraph::graph::EdgeIndex)>> {
+        let incoming: Vec<_> = self
+            .graph
+            .edges_directed(node, petgraph::Direction::Incoming)
+            .collect();
+        let outgoing: Vec<_> = self
+            .graph
+            .edges_directed(node, petgraph::Direction::Outgoing)
+            .collect();
+        let mut neighbors: Vec<NodeIndex> = incoming
+            .iter()
+            .map(|edge| edge.source())
+            .chain(outgoing.iter().map(|edge| edge.target()))
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        if neighbors.len() != 2 || neighbors.contains(&node) || incoming.len() != outgoing.len() {
+            return None;
+        }
+
+        // Each arc entering from one neighbor continues to the other one
+        let mut arcs = Vec::new();
+        for from in &incoming {
+            let to = outgoing.iter().find(|to| to.target() != from.source())?;
+            if outgoing
+                .iter()
+                .filter(|to| to.target() != from.source())
+                .count()
+                != 1
+                || to.weight().barrier
+                || !same_attributes(from.weight(), to.weight(), keep_way_ids)
+            {
+                return None;
+            }
+            arcs.push((from.id(), to.id()));
+        }
+        match arcs.len() {
+            1 | 2 => Some(arcs),
+            _ => None,
+        }
+    }
+
+    /// Flag the edges whose geometry, with their shape, crosses the borders. Returns how many
+    /// edges cross them
+    pub fn mark_borders(&mut self, borders: &Borders) -> usize {
+        let mut num_borders = 0;
+        for edge in self.graph.edge_indices() {
+            let (from, to) = self.graph.edge_endpoints(edge).unwrap();
+            let mut points = vec![self.graph[from].point];
+            points.extend_from_slice(self.shapes.get(self.graph[edge].shape));
+            points.push(self.graph[to].point);
+            let border = borders.crosses(&points);
+            self.graph[edge].border = border;
+            num_borders += border as usize;
+        }
+        num_borders
+    }
+
     /// Return the list of strongly-connected-components
     pub fn scc(&self) -> Vec<Vec<NodeIndex>> {
         kosaraju_scc(&self.graph)
@@ -168,10 +338,36 @@ impl<'a> Graph {
     }
 }
 
+/// Whether two consecutive arcs can be merged in a single edge, ignoring their distances, their
+/// shapes and whether the first one starts at a barrier
+fn same_attributes(a: &EdgeInfo, b: &EdgeInfo, keep_way_ids: bool) -> bool {
+    let key = |info: &EdgeInfo| {
+        EdgeInfo {
+            distance: 0,
+            barrier: false,
+            shape: 0,
+            way_id: if keep_way_ids { info.way_id } else { 0 },
+            ..*info
+        }
+        .sort_key()
+    };
+    key(a) == key(b)
+}
+
+/// Move a shape out of the list, leaving it empty. The index 0 is the straight line
+fn take_shape(shapes: &mut [Vec<GeoPoint>], index: u32) -> Vec<GeoPoint> {
+    match index.checked_sub(1) {
+        Some(position) => std::mem::take(&mut shapes[position as usize]),
+        None => Vec::new(),
+    }
+}
+
 /// Extra data associated to each node
 #[derive(Copy, Clone, Debug)]
 pub struct NodeInfo {
     pub point: GeoPoint,
+    /// The OSM id, 0 when unknown
+    pub id: i64,
 }
 
 /// Extra data associated to each edge
@@ -180,4 +376,276 @@ pub struct EdgeInfo {
     pub road_level: u8,
     /// Distance in meters
     pub distance: u32,
+    /// Whether the edge is part of a roundabout (`junction=roundabout`)
+    pub roundabout: bool,
+    /// Estimated number of vehicles per hour
+    pub capacity: u32,
+    /// Number of lanes in this direction, 0 when unknown
+    pub lanes: u8,
+    /// Width of the road in meters, 0 when unknown
+    pub width: f32,
+    /// Whether the edge was invented to connect the graph and does not exist in reality
+    pub synthetic: bool,
+    /// Whether this direction is only open at some times (`oneway:conditional`)
+    pub conditional: bool,
+    /// Whether the road changes direction during the day (`oneway=reversible`)
+    pub reversible: bool,
+    /// Whether the edge starts at a barrier that vehicles may pass (`barrier=gate` with `access=yes`)
+    pub barrier: bool,
+    /// Whether the road has a dedicated bicycle infrastructure (`highway=cycleway`,
+    /// `bicycle=designated` or a cycle lane or track)
+    pub cycleway: bool,
+    /// Whether the road is part of a cycle route relation (`route=bicycle` or `mtb`)
+    pub bicycle_route: bool,
+    /// Whether the road is part of a bus route relation (`route=bus` or `trolleybus`)
+    pub bus_route: bool,
+    /// Whether the edge crosses one of the borders given with `--borders`
+    pub border: bool,
+    /// The `tracktype` grade, from 1 (solid) to 5 (soft), 0 when unknown or not a track
+    pub track_grade: u8,
+    pub surface: Surface,
+    pub smoothness: Smoothness,
+    /// Who is allowed on the road (`highway=pedestrian`, `living_street` or `motorroad=yes`)
+    pub access: AccessClass,
+    /// The limits of weight, height and width (`maxweight`, `maxheight`, `maxwidth` and `hgv`)
+    pub restrictions: Restrictions,
+    /// The OSM id of the way, 0 when unknown
+    pub way_id: i64,
+    /// The index of the name of the road (`ref` or `name`), 0 when it has none
+    pub name: u32,
+    /// The index of the intermediate points of the edge in `Graph::shapes`, 0 when it is a straight
+    /// line between its nodes
+    pub shape: u32,
+}
+
+impl From<&crate::EdgeInfo> for EdgeInfo {
+    fn from(info: &crate::EdgeInfo) -> Self {
+        EdgeInfo {
+            road_level: info.road_level,
+            distance: info.distance,
+            roundabout: info.roundabout,
+            capacity: info.capacity,
+            lanes: info.lanes,
+            width: info.width,
+            synthetic: info.synthetic,
+            conditional: info.conditional,
+            reversible: info.reversible,
+            barrier: info.barrier,
+            cycleway: info.cycleway,
+            bicycle_route: info.bicycle_route,
+            bus_route: info.bus_route,
+            border: info.border,
+            track_grade: info.track_grade,
+            surface: info.surface,
+            smoothness: info.smoothness,
+            access: info.access,
+            restrictions: info.restrictions,
+            way_id: info.way_id,
+            name: info.name,
+            shape: info.shape,
+        }
+    }
+}
+
+impl From<&EdgeInfo> for crate::EdgeInfo {
+    fn from(info: &EdgeInfo) -> Self {
+        crate::EdgeInfo {
+            road_level: info.road_level,
+            distance: info.distance,
+            roundabout: info.roundabout,
+            capacity: info.capacity,
+            lanes: info.lanes,
+            width: info.width,
+            synthetic: info.synthetic,
+            conditional: info.conditional,
+            reversible: info.reversible,
+            barrier: info.barrier,
+            cycleway: info.cycleway,
+            bicycle_route: info.bicycle_route,
+            bus_route: info.bus_route,
+            border: info.border,
+            track_grade: info.track_grade,
+            surface: info.surface,
+            smoothness: info.smoothness,
+            access: info.access,
+            restrictions: info.restrictions,
+            way_id: info.way_id,
+            name: info.name,
+            shape: info.shape,
+        }
+    }
+}
+
+impl EdgeInfo {
+    /// All the attributes, to sort the parallel edges
+    pub fn sort_key(&self) -> impl Ord {
+        let flags = (self.roundabout as u16)
+            | (self.synthetic as u16) << 1
+            | (self.conditional as u16) << 2
+            | (self.reversible as u16) << 3
+            | (self.barrier as u16) << 4
+            | (self.cycleway as u16) << 5
+            | (self.bicycle_route as u16) << 6
+            | (self.bus_route as u16) << 7
+            | (self.border as u16) << 8;
+        (
+            self.distance,
+            self.road_level,
+            self.capacity,
+            self.lanes,
+            // The widths are positive, so their bits are in the same order
+            self.width.to_bits(),
+            flags,
+            self.track_grade,
+            self.surface,
+            self.smoothness,
+            self.access,
+            // Like the widths, the limits are never negative
+            (
+                self.restrictions.max_weight.to_bits(),
+                self.restrictions.max_height.to_bits(),
+                self.restrictions.max_width.to_bits(),
+                self.restrictions.no_hgv,
+            ),
+            (self.way_id, self.name, self.shape),
+        )
+    }
+
+    /// Merge the info of another arc between the same nodes, keeping the highest road level and
+    /// least distance. This happens quite a bit with roundabouts that are not correctly tagged
+    pub fn merge(&mut self, other: &EdgeInfo) {
+        self.road_level = self.road_level.max(other.road_level);
+        self.distance = self.distance.min(other.distance);
+        self.roundabout |= other.roundabout;
+        self.capacity = self.capacity.max(other.capacity);
+        self.lanes = self.lanes.max(other.lanes);
+        self.width = self.width.max(other.width);
+        self.synthetic &= other.synthetic;
+        // The nodes are always connected if any of the arcs is always open
+        self.conditional &= other.conditional;
+        self.reversible &= other.reversible;
+        self.barrier &= other.barrier;
+        self.cycleway |= other.cycleway;
+        self.bicycle_route |= other.bicycle_route;
+        self.bus_route |= other.bus_route;
+        self.border |= other.border;
+        self.track_grade = self.track_grade.max(other.track_grade);
+        // Keep the worst known surface, so that the order of the arcs does not matter
+        self.surface = self.surface.max(other.surface);
+        self.smoothness = self.smoothness.max(other.smoothness);
+        if other.access == AccessClass::Normal {
+            self.access = AccessClass::Normal;
+        }
+        // Keep the least restrictive limits, since one of the arcs can be used anyway
+        let loosest = |limit: f32, other: f32| {
+            if limit <= 0. || other <= 0. {
+                0.
+            } else {
+                limit.max(other)
+            }
+        };
+        let restrictions = &mut self.restrictions;
+        restrictions.max_weight = loosest(restrictions.max_weight, other.restrictions.max_weight);
+        restrictions.max_height = loosest(restrictions.max_height, other.restrictions.max_height);
+        restrictions.max_width = loosest(restrictions.max_width, other.restrictions.max_width);
+        restrictions.no_hgv &= other.restrictions.no_hgv;
+        // Keep the smallest known id, also so that the order does not matter
+        if self.way_id == 0 || (other.way_id != 0 && other.way_id < self.way_id) {
+            self.way_id = other.way_id;
+        }
+        if self.name == 0 || (other.name != 0 && other.name < self.name) {
+            self.name = other.name;
+        }
+        if self.shape == 0 {
+            self.shape = other.shape;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn contract_chains() {
+        // A two-way road a - b - c - d, split at b and with a gate at c, and a one-way road d -> e -> a
+        let mut graph = petgraph::Graph::new();
+        let nodes: Vec<NodeIndex> = (0..5)
+            .map(|i| {
+                graph.add_node(NodeInfo {
+                    point: GeoPoint::from_degrees(42.5, 1.5 + i as f64 * 0.001),
+                    id: i,
+                })
+            })
+            .collect();
+        let info = |distance, way_id| {
+            let mut info = EdgeInfo::from(&crate::EdgeInfo::new(distance, 3));
+            info.way_id = way_id;
+            info
+        };
+        let gate = EdgeInfo {
+            barrier: true,
+            ..info(30, 2)
+        };
+        for &(from, to, info) in &[
+            (0, 1, info(10, 1)),
+            (1, 0, info(10, 1)),
+            (1, 2, info(20, 2)),
+            (2, 1, info(20, 2)),
+            (2, 3, gate),
+            (3, 2, info(30, 2)),
+            (3, 4, info(40, 3)),
+            (4, 0, info(50, 3)),
+        ] {
+            graph.add_edge(nodes[from], nodes[to], info);
+        }
+        let graph = Graph {
+            graph,
+            shapes: EdgeShapes::default(),
+        };
+
+        // The ways are split, so b is only contracted without their ids. The gate keeps c, and the
+        // one-way road is contracted at e
+        let mut with_ids = Graph {
+            graph: graph.graph.clone(),
+            shapes: EdgeShapes::default(),
+        };
+        assert_eq!(with_ids.contract_chains(true), 1);
+        assert_eq!(with_ids.node_len(), 4);
+        assert_eq!(with_ids.edge_len(), 7);
+
+        let mut without_ids = graph;
+        assert_eq!(without_ids.contract_chains(false), 2);
+        assert_eq!(without_ids.node_len(), 3);
+        assert_eq!(without_ids.edge_len(), 5);
+        let mut edges: Vec<_> = without_ids
+            .graph
+            .edge_references()
+            .map(|edge| {
+                let shape = without_ids.shapes.get(edge.weight().shape);
+                let ids = |node: NodeIndex| without_ids.graph[node].id;
+                let shape_ids: Vec<_> = shape
+                    .iter()
+                    .map(|point| (point.lon.as_degrees() * 1000.).round() as i64 - 1500)
+                    .collect();
+                (
+                    ids(edge.source()),
+                    ids(edge.target()),
+                    edge.weight().distance,
+                    shape_ids,
+                )
+            })
+            .collect();
+        edges.sort();
+        assert_eq!(
+            edges,
+            vec![
+                (0, 2, 30, vec![1]),
+                (2, 0, 30, vec![1]),
+                (2, 3, 30, vec![]),
+                (3, 0, 90, vec![4]),
+                (3, 2, 30, vec![]),
+            ]
+        );
+    }
 }
@@ -1,6 +1,6 @@
 use super::disk_bit_vec::DiskBitVec;
 use super::disk_vec::DiskVec;
-use crate::utils::GeoPoint;
+use crate::GeoPoint;
 use std::mem::replace;
 use std::ops::Range;
 
@@ -9,6 +9,17 @@ const PAGES_PER_SECTION: usize = 1024;
 
 pub type NodeId = i64;
 
+/// Whether vehicles can go through a node
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Barrier {
+    None,
+    /// A barrier like a bollard, that splits the roads
+    Blocking,
+    /// A barrier like a gate that the access tags open to vehicles. The roads are split there, so
+    /// that queries can add a penalty to cross it
+    Passable,
+}
+
 /// Represent a basic OSM node, with some parsed fields
 #[derive(Copy, Clone, Debug)]
 pub struct OSMNode {
@@ -16,7 +27,7 @@ pub struct OSMNode {
     /// Global offset, not used when writing to the databse, only when returning from it
     pub offset: usize,
     pub point: GeoPoint,
-    pub barrier: bool,
+    pub barrier: Barrier,
 }
 
 impl OSMNode {
@@ -26,7 +37,7 @@ impl OSMNode {
             id,
             offset: 0,
             point: GeoPoint::from_degrees(0., 0.),
-            barrier: false,
+            barrier: Barrier::None,
         }
     }
 }
@@ -45,6 +56,7 @@ pub struct NodesBuilder {
     index_entries: Vec<IndexProto>,
     len: usize,
     barrier_len: usize,
+    passable_barrier_len: usize,
 }
 
 impl NodesBuilder {
@@ -73,6 +85,7 @@ impl NodesBuilder {
             index_entries: Vec::new(),
             len: 0,
             barrier_len: 0,
+            passable_barrier_len: 0,
         }
     }
 
@@ -103,8 +116,10 @@ impl NodesBuilder {
         self.partial_section.push(node);
         self.ids_curr_page += 1;
         self.len += 1;
-        if node.barrier {
-            self.barrier_len += 1;
+        match node.barrier {
+            Barrier::None => {}
+            Barrier::Blocking => self.barrier_len += 1,
+            Barrier::Passable => self.passable_barrier_len += 1,
         }
     }
 
@@ -152,6 +167,7 @@ pub struct Nodes {
     index: Index,
     len: usize,
     barrier_len: usize,
+    passable_barrier_len: usize,
 }
 
 impl Nodes {
@@ -162,9 +178,11 @@ impl Nodes {
         let mut all_index_entries = Vec::new();
         let mut len = 0;
         let mut barrier_len = 0;
+        let mut passable_barrier_len = 0;
         for builder in builders {
             len += builder.len;
             barrier_len += builder.barrier_len;
+            passable_barrier_len += builder.passable_barrier_len;
             let (sections, mut index_entries) = builder.finish();
 
             // Increment section pointers
@@ -181,6 +199,7 @@ impl Nodes {
             index: Index::from_entries(all_index_entries),
             len,
             barrier_len,
+            passable_barrier_len,
         }
     }
 
@@ -193,7 +212,7 @@ impl Nodes {
                 id,
                 offset: meta.nodes_offset + i,
                 point: section.points[offset],
-                barrier: section.barriers.get_bit(offset),
+                barrier: section.barrier(offset),
             }
         })
     }
@@ -203,11 +222,16 @@ impl Nodes {
         self.len
     }
 
-    /// The total number of indexed nodes that are barriers
+    /// The total number of indexed nodes that are blocking barriers
     pub fn barrier_len(&self) -> usize {
         self.barrier_len
     }
 
+    /// The total number of indexed nodes that are barriers vehicles may pass
+    pub fn passable_barrier_len(&self) -> usize {
+        self.passable_barrier_len
+    }
+
     /// Return an iterator over the points, in ascending `id` order
     pub fn points<'a>(&'a self) -> impl Iterator<Item = &'a GeoPoint> {
         self.index.metas.iter().flat_map(move |meta| {
@@ -217,6 +241,13 @@ impl Nodes {
         })
     }
 
+    /// Return an iterator over the ids, in ascending order, like `points()`
+    pub fn ids(&self) -> impl Iterator<Item = &NodeId> {
+        self.index.metas.iter().flat_map(move |meta| {
+            self.sections[meta.section].ids[meta.section_ids_range.clone()].iter()
+        })
+    }
+
     fn search(&self, id: NodeId) -> Option<(IndexMeta, usize)> {
         // Search the index for the page
         self.index.search(id).and_then(|meta| {
@@ -255,6 +286,8 @@ struct NodesSection {
     points: DiskVec<GeoPoint>,
     /// The node's barrier flag, represented as one bit per node, packed with no holes
     barriers: DiskBitVec,
+    /// Whether the barrier can be passed, like `barriers`
+    passable: DiskBitVec,
 }
 
 impl NodesSection {
@@ -263,15 +296,29 @@ impl NodesSection {
             ids: DiskVec::new(capacity).unwrap(),
             points: DiskVec::new(capacity).unwrap(),
             barriers: DiskBitVec::zeros(capacity).unwrap(),
+            passable: DiskBitVec::zeros(capacity).unwrap(),
         }
     }
 
     fn push(&mut self, node: OSMNode) {
-        self.barriers.set_bit(self.points.len(), node.barrier);
+        self.barriers
+            .set_bit(self.points.len(), node.barrier != Barrier::None);
+        self.passable
+            .set_bit(self.points.len(), node.barrier == Barrier::Passable);
         self.points.push(node.point);
         self.ids.push(node.id);
     }
 
+    fn barrier(&self, offset: usize) -> Barrier {
+        if !self.barriers.get_bit(offset) {
+            Barrier::None
+        } else if self.passable.get_bit(offset) {
+            Barrier::Passable
+        } else {
+            Barrier::Blocking
+        }
+    }
+
     fn pad_ids(&mut self, len: usize) {
         for _ in 0..len {
             self.ids.push(0);
@@ -373,7 +420,7 @@ mod test {
         let nodes = Nodes::from_builders(vec![builder]);
 
         for id in 0..30 {
-            assert_eq!(nodes.offset(id), Some(id as usize));
+            assert_eq!(nodes.node(id).map(|node| node.offset), Some(id as usize));
         }
     }
 
@@ -392,7 +439,7 @@ mod test {
 
         for (block, offsets) in blocks.into_iter().zip(offsets.into_iter()) {
             for (id, offset) in block.zip(offsets) {
-                assert_eq!(nodes.offset(id), Some(offset));
+                assert_eq!(nodes.node(id).map(|node| node.offset), Some(offset));
             }
         }
     }
@@ -416,7 +463,7 @@ mod test {
 
         for (block, offsets) in blocks.into_iter().zip(offsets.into_iter()) {
             for (id, offset) in block.zip(offsets) {
-                assert_eq!(nodes.offset(id), Some(offset));
+                assert_eq!(nodes.node(id).map(|node| node.offset), Some(offset));
             }
         }
     }
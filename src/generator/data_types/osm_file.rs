@@ -1,5 +1,81 @@
+use super::osm_xml;
+use byteorder::{BigEndian, ReadBytesExt};
+use bzip2::read::MultiBzDecoder;
+use flate2::read::ZlibDecoder;
 use osmpbf::*;
-use std::io;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// The formats of the OSM files that the generator reads
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    Pbf,
+    Xml,
+    Bzip2Xml,
+}
+
+impl InputFormat {
+    /// Detect the format of a file by its first bytes, whatever its extension
+    pub fn detect<P: AsRef<Path>>(path: P) -> io::Result<InputFormat> {
+        let mut start = Vec::with_capacity(64);
+        File::open(path)?.take(64).read_to_end(&mut start)?;
+        let first = start.iter().find(|byte| !byte.is_ascii_whitespace());
+        Ok(if start.starts_with(b"BZh") {
+            InputFormat::Bzip2Xml
+        } else if first == Some(&b'<') || start.starts_with(b"\xef\xbb\xbf<") {
+            InputFormat::Xml
+        } else {
+            InputFormat::Pbf
+        })
+    }
+}
+
+/// The input of the generator as a PBF file, which is the only format that the parsers read. The
+/// XML files are converted to a temporary file, deleted when this is dropped
+pub struct PbfInput {
+    path: PathBuf,
+    format: InputFormat,
+    _converted: Option<NamedTempFile>,
+}
+
+impl PbfInput {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<PbfInput> {
+        let path = path.as_ref();
+        let format = InputFormat::detect(path)?;
+        let reader: Box<dyn Read> = match format {
+            InputFormat::Pbf => {
+                return Ok(PbfInput {
+                    path: path.to_owned(),
+                    format,
+                    _converted: None,
+                })
+            }
+            InputFormat::Xml => Box::new(File::open(path)?),
+            InputFormat::Bzip2Xml => Box::new(MultiBzDecoder::new(File::open(path)?)),
+        };
+        let converted = NamedTempFile::new()?;
+        let mut writer = BufWriter::new(converted.reopen()?);
+        osm_xml::convert_xml(BufReader::new(reader), &mut writer)?;
+        writer.flush()?;
+        Ok(PbfInput {
+            path: converted.path().to_owned(),
+            format,
+            _converted: Some(converted),
+        })
+    }
+
+    /// The path of the PBF file, that is the original one unless it was converted
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The format of the original file
+    pub fn format(&self) -> InputFormat {
+        self.format
+    }
+}
 
 /// Represent an OSM PBF file with its blobs memory-mapped
 pub struct OSMFile<'a> {
@@ -30,7 +106,10 @@ pub struct RelationsBlob<'a>(MmapBlob<'a>);
 impl<'a> OSMFile<'a> {
     pub fn from_mmap(mmap: &'a Mmap) -> io::Result<Self> {
         let reader = MmapBlobReader::new(mmap);
-        let blobs: Vec<MmapBlob> = reader.collect::<Result<_>>()?;
+        // The data is already in memory, so any error is about the format
+        let blobs: Vec<MmapBlob> = reader
+            .collect::<Result<_>>()
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?;
         Ok(OSMFile { blobs })
     }
 }
@@ -104,6 +183,86 @@ impl<'a> OSMClassifiedFile<'a> {
     }
 }
 
+/// Read the `osmosis_replication_timestamp` of the header of a PBF file, in seconds since the Unix
+/// epoch. It tells when the data was last updated, but is not exposed by `HeaderBlock`, so the first
+/// blob is decoded here
+pub fn read_replication_timestamp<P: AsRef<Path>>(path: P) -> io::Result<Option<i64>> {
+    let mut file = File::open(path)?;
+    let mut header = vec![0; file.read_u32::<BigEndian>()? as usize];
+    file.read_exact(&mut header)?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid PBF header");
+    let blob_size = proto_fields(&header)
+        .find_map(|field| match field {
+            (3, ProtoValue::Varint(size)) => Some(size),
+            _ => None,
+        })
+        .ok_or_else(invalid)?;
+    let mut blob = vec![0; blob_size as usize];
+    file.read_exact(&mut blob)?;
+
+    let mut block = Vec::new();
+    for field in proto_fields(&blob) {
+        match field {
+            (1, ProtoValue::Bytes(raw)) => block.extend_from_slice(raw),
+            (3, ProtoValue::Bytes(zlib_data)) => {
+                ZlibDecoder::new(zlib_data).read_to_end(&mut block)?;
+            }
+            _ => {}
+        }
+    }
+    let timestamp = proto_fields(&block).find_map(|field| match field {
+        (32, ProtoValue::Varint(timestamp)) => Some(timestamp as i64),
+        _ => None,
+    });
+    Ok(timestamp)
+}
+
+/// The value of a field of a protobuf message
+enum ProtoValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Iterate over the numbers and values of the fields of a protobuf message, until the end or an
+/// invalid field
+fn proto_fields(mut message: &[u8]) -> impl Iterator<Item = (u64, ProtoValue<'_>)> {
+    fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = bytes.split_first()?;
+            *bytes = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+    std::iter::from_fn(move || {
+        let key = read_varint(&mut message)?;
+        let value = match key & 0x7 {
+            0 => ProtoValue::Varint(read_varint(&mut message)?),
+            2 => {
+                let len = read_varint(&mut message)? as usize;
+                if len > message.len() {
+                    return None;
+                }
+                let (bytes, rest) = message.split_at(len);
+                message = rest;
+                ProtoValue::Bytes(bytes)
+            }
+            wire_type @ (1 | 5) => {
+                let len = if wire_type == 1 { 8 } else { 4 };
+                message = message.get(len..)?;
+                ProtoValue::Fixed
+            }
+            _ => return None,
+        };
+        Some((key >> 3, value))
+    })
+}
+
 impl<'a> HeaderBlob<'a> {
     pub fn decode(&self) -> Box<HeaderBlock> {
         match self.0.decode().unwrap() {
@@ -157,3 +316,40 @@ impl<'a> RelationsBlob<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn input_formats() {
+        let pbf = "test_data/andorra-latest.osm.pbf";
+        assert_eq!(InputFormat::detect(pbf).unwrap(), InputFormat::Pbf);
+        assert_eq!(PbfInput::open(pbf).unwrap().path(), Path::new(pbf));
+
+        let xml = "test_data/tiny.osm";
+        assert_eq!(InputFormat::detect(xml).unwrap(), InputFormat::Xml);
+        let mut bz2 = NamedTempFile::new().unwrap();
+        let mut encoder = bzip2::write::BzEncoder::new(&mut bz2, bzip2::Compression::default());
+        io::copy(&mut File::open(xml).unwrap(), &mut encoder).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(
+            InputFormat::detect(bz2.path()).unwrap(),
+            InputFormat::Bzip2Xml
+        );
+
+        // Both are converted to the same file
+        let read = |path: &Path| {
+            let input = PbfInput::open(path).unwrap();
+            assert_ne!(input.path(), path);
+            std::fs::read(input.path()).unwrap()
+        };
+        assert_eq!(read(Path::new(xml)), read(bz2.path()));
+    }
+
+    #[test]
+    fn replication_timestamp() {
+        let timestamp = read_replication_timestamp("test_data/andorra-latest.osm.pbf").unwrap();
+        assert_eq!(timestamp, Some(1_578_088_742));
+    }
+}
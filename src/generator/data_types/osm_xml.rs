@@ -0,0 +1,491 @@
+//! Convert OSM XML files, like the small extracts of Overpass queries, to the PBF format, so that
+//! they go through the same parsers. The whole file is kept in memory while it is converted
+
+use byteorder::{BigEndian, WriteBytesExt};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::io::{self, BufRead, Write};
+
+/// How many nodes, ways or relations are written in each blob
+const BLOCK_SIZE: usize = 8000;
+
+/// The coordinates are stored in units of 100 nanodegrees, the default granularity
+const UNITS_PER_DEGREE: f64 = 1e7;
+
+type Tags = Vec<(String, String)>;
+
+#[derive(Debug, Default)]
+struct XmlNode {
+    id: i64,
+    lat: f64,
+    lon: f64,
+    tags: Tags,
+}
+
+#[derive(Debug, Default)]
+struct XmlWay {
+    id: i64,
+    refs: Vec<i64>,
+    tags: Tags,
+}
+
+#[derive(Debug, Default)]
+struct XmlRelation {
+    id: i64,
+    /// The type (0 for nodes, 1 for ways and 2 for relations), id and role of each member
+    members: Vec<(u64, i64, String)>,
+    tags: Tags,
+}
+
+/// The kind of entity whose children are being read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Entity {
+    Node,
+    Way,
+    Relation,
+}
+
+/// The entities of an XML file
+#[derive(Debug, Default)]
+struct XmlData {
+    nodes: Vec<XmlNode>,
+    ways: Vec<XmlWay>,
+    relations: Vec<XmlRelation>,
+}
+
+/// Read an OSM XML file and write it as PBF, with the nodes, ways and relations in this order and
+/// sorted by id, like in the extracts
+pub fn convert_xml<R: BufRead, W: Write>(reader: R, writer: &mut W) -> io::Result<()> {
+    let mut data = read_xml(reader)?;
+    data.nodes.sort_by_key(|node| node.id);
+    data.ways.sort_by_key(|way| way.id);
+    data.relations.sort_by_key(|relation| relation.id);
+
+    let mut header = Vec::new();
+    for feature in &["OsmSchema-V0.6", "DenseNodes"] {
+        write_bytes(&mut header, 4, feature.as_bytes());
+    }
+    write_bytes(&mut header, 16, b"ptolemy");
+    write_blob(writer, "OSMHeader", &header)?;
+
+    for nodes in data.nodes.chunks(BLOCK_SIZE) {
+        write_blob(writer, "OSMData", &nodes_block(nodes))?;
+    }
+    for ways in data.ways.chunks(BLOCK_SIZE) {
+        write_blob(writer, "OSMData", &ways_block(ways))?;
+    }
+    for relations in data.relations.chunks(BLOCK_SIZE) {
+        write_blob(writer, "OSMData", &relations_block(relations))?;
+    }
+    Ok(())
+}
+
+fn read_xml<R: BufRead>(reader: R) -> io::Result<XmlData> {
+    let mut reader = Reader::from_reader(reader);
+    let mut data = XmlData::default();
+    let mut parent = None;
+    let mut buf = Vec::new();
+    loop {
+        let result = match reader.read_event(&mut buf) {
+            Ok(Event::Start(element)) => read_element(&mut data, parent, &element).map(|entity| {
+                // The tags and references that follow belong to this entity
+                if entity.is_some() {
+                    parent = entity;
+                }
+            }),
+            Ok(Event::Empty(element)) => read_element(&mut data, parent, &element).map(|_| ()),
+            Ok(Event::End(element)) => {
+                if matches!(element.name(), b"node" | b"way" | b"relation") {
+                    parent = None;
+                }
+                Ok(())
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => Ok(()),
+            Err(error) => Err(error.to_string()),
+        };
+        result.map_err(|message| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Invalid OSM XML at byte {}: {}",
+                    reader.buffer_position(),
+                    message
+                ),
+            )
+        })?;
+        buf.clear();
+    }
+    Ok(data)
+}
+
+/// Read an element inside the `parent` entity, returning which entity it starts
+fn read_element(
+    data: &mut XmlData,
+    parent: Option<Entity>,
+    element: &BytesStart,
+) -> Result<Option<Entity>, String> {
+    let attributes = Attributes::new(element)?;
+    match element.name() {
+        b"node" => {
+            data.nodes.push(XmlNode {
+                id: attributes.parse("id")?,
+                lat: attributes.parse("lat")?,
+                lon: attributes.parse("lon")?,
+                tags: Vec::new(),
+            });
+            Ok(Some(Entity::Node))
+        }
+        b"way" => {
+            data.ways.push(XmlWay {
+                id: attributes.parse("id")?,
+                ..XmlWay::default()
+            });
+            Ok(Some(Entity::Way))
+        }
+        b"relation" => {
+            data.relations.push(XmlRelation {
+                id: attributes.parse("id")?,
+                ..XmlRelation::default()
+            });
+            Ok(Some(Entity::Relation))
+        }
+        b"nd" => {
+            let way = match parent {
+                Some(Entity::Way) => data.ways.last_mut(),
+                _ => None,
+            };
+            let way = way.ok_or("<nd> outside of a way")?;
+            way.refs.push(attributes.parse("ref")?);
+            Ok(None)
+        }
+        b"member" => {
+            let member_type = match attributes.get("type")? {
+                "node" => 0,
+                "way" => 1,
+                "relation" => 2,
+                other => return Err(format!("unknown member type {}", other)),
+            };
+            let relation = match parent {
+                Some(Entity::Relation) => data.relations.last_mut(),
+                _ => None,
+            };
+            let relation = relation.ok_or("<member> outside of a relation")?;
+            relation.members.push((
+                member_type,
+                attributes.parse("ref")?,
+                attributes.get("role")?.to_owned(),
+            ));
+            Ok(None)
+        }
+        b"tag" => {
+            add_tag(data, parent, &attributes)?;
+            Ok(None)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Add a `<tag>` to the entity that contains it
+fn add_tag(
+    data: &mut XmlData,
+    parent: Option<Entity>,
+    attributes: &Attributes,
+) -> Result<(), String> {
+    let tag = (
+        attributes.get("k")?.to_owned(),
+        attributes.get("v")?.to_owned(),
+    );
+    let tags = match parent {
+        Some(Entity::Node) => data.nodes.last_mut().map(|node| &mut node.tags),
+        Some(Entity::Way) => data.ways.last_mut().map(|way| &mut way.tags),
+        Some(Entity::Relation) => data.relations.last_mut().map(|relation| &mut relation.tags),
+        None => None,
+    };
+    tags.ok_or("<tag> outside of an entity")?.push(tag);
+    Ok(())
+}
+
+/// The unescaped attributes of an element
+struct Attributes {
+    element: String,
+    values: Vec<(String, String)>,
+}
+
+impl Attributes {
+    fn new(element: &BytesStart) -> Result<Self, String> {
+        let values = element
+            .attributes()
+            .map(|attribute| {
+                let attribute = attribute.map_err(|error| error.to_string())?;
+                let value = attribute
+                    .unescaped_value()
+                    .map_err(|error| error.to_string())?;
+                Ok((
+                    String::from_utf8_lossy(attribute.key).into_owned(),
+                    String::from_utf8_lossy(&value).into_owned(),
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Attributes {
+            element: String::from_utf8_lossy(element.name()).into_owned(),
+            values,
+        })
+    }
+
+    fn get(&self, name: &str) -> Result<&str, String> {
+        self.values
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| format!("<{}> has no attribute {}", self.element, name))
+    }
+
+    fn parse<T: std::str::FromStr>(&self, name: &str) -> Result<T, String> {
+        let value = self.get(name)?;
+        value
+            .parse()
+            .map_err(|_| format!("<{}> has an invalid {}: {}", self.element, name, value))
+    }
+}
+
+/// The strings of a block, referenced by their index. The index 0 is reserved
+struct StringTable {
+    strings: Vec<String>,
+}
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable {
+            strings: vec![String::new()],
+        }
+    }
+
+    fn index(&mut self, s: &str) -> u64 {
+        // The blocks are small, so a linear search is fast enough
+        match self.strings.iter().position(|other| other == s) {
+            Some(index) => index as u64,
+            None => {
+                self.strings.push(s.to_owned());
+                self.strings.len() as u64 - 1
+            }
+        }
+    }
+
+    /// Encode a primitive block with this table and a single group
+    fn block(&self, group: &[u8]) -> Vec<u8> {
+        let mut table = Vec::new();
+        for s in &self.strings {
+            write_bytes(&mut table, 1, s.as_bytes());
+        }
+        let mut block = Vec::new();
+        write_bytes(&mut block, 1, &table);
+        write_bytes(&mut block, 2, group);
+        block
+    }
+}
+
+fn nodes_block(nodes: &[XmlNode]) -> Vec<u8> {
+    let mut strings = StringTable::new();
+    let degrees = |value: f64| (value * UNITS_PER_DEGREE).round() as i64;
+    let mut keys_vals = Vec::new();
+    for node in nodes {
+        for (key, value) in &node.tags {
+            keys_vals.push(strings.index(key));
+            keys_vals.push(strings.index(value));
+        }
+        keys_vals.push(0);
+    }
+    // The parsers do not read the info of the nodes, but it must be there
+    let mut info = Vec::new();
+    for field in 1..=5 {
+        write_packed(&mut info, field, nodes.iter().map(|_| 0));
+    }
+    let mut dense = Vec::new();
+    write_packed(
+        &mut dense,
+        1,
+        delta_zigzag(nodes.iter().map(|node| node.id)),
+    );
+    write_bytes(&mut dense, 5, &info);
+    write_packed(
+        &mut dense,
+        8,
+        delta_zigzag(nodes.iter().map(|node| degrees(node.lat))),
+    );
+    write_packed(
+        &mut dense,
+        9,
+        delta_zigzag(nodes.iter().map(|node| degrees(node.lon))),
+    );
+    write_packed(&mut dense, 10, keys_vals);
+    let mut group = Vec::new();
+    write_bytes(&mut group, 2, &dense);
+    strings.block(&group)
+}
+
+fn ways_block(ways: &[XmlWay]) -> Vec<u8> {
+    let mut strings = StringTable::new();
+    let mut group = Vec::new();
+    for way in ways {
+        let mut message = Vec::new();
+        write_varint_field(&mut message, 1, way.id as u64);
+        write_tags(&mut message, &mut strings, &way.tags);
+        write_packed(&mut message, 8, delta_zigzag(way.refs.iter().copied()));
+        write_bytes(&mut group, 3, &message);
+    }
+    strings.block(&group)
+}
+
+fn relations_block(relations: &[XmlRelation]) -> Vec<u8> {
+    let mut strings = StringTable::new();
+    let mut group = Vec::new();
+    for relation in relations {
+        let mut message = Vec::new();
+        write_varint_field(&mut message, 1, relation.id as u64);
+        write_tags(&mut message, &mut strings, &relation.tags);
+        let roles: Vec<_> = relation
+            .members
+            .iter()
+            .map(|(_, _, role)| strings.index(role))
+            .collect();
+        write_packed(&mut message, 8, roles);
+        write_packed(
+            &mut message,
+            9,
+            delta_zigzag(relation.members.iter().map(|&(_, id, _)| id)),
+        );
+        write_packed(
+            &mut message,
+            10,
+            relation
+                .members
+                .iter()
+                .map(|&(member_type, _, _)| member_type),
+        );
+        write_bytes(&mut group, 4, &message);
+    }
+    strings.block(&group)
+}
+
+/// Write the keys and values of a way or relation, as indexes in the strings table
+fn write_tags(message: &mut Vec<u8>, strings: &mut StringTable, tags: &Tags) {
+    let keys: Vec<_> = tags.iter().map(|(key, _)| strings.index(key)).collect();
+    let values: Vec<_> = tags.iter().map(|(_, value)| strings.index(value)).collect();
+    write_packed(message, 2, keys);
+    write_packed(message, 3, values);
+}
+
+/// Write a blob compressed with zlib, preceded by its header
+fn write_blob<W: Write>(writer: &mut W, blob_type: &str, data: &[u8]) -> io::Result<()> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    let mut blob = Vec::new();
+    write_varint_field(&mut blob, 2, data.len() as u64);
+    write_bytes(&mut blob, 3, &encoder.finish()?);
+
+    let mut header = Vec::new();
+    write_bytes(&mut header, 1, blob_type.as_bytes());
+    write_varint_field(&mut header, 3, blob.len() as u64);
+    writer.write_u32::<BigEndian>(header.len() as u32)?;
+    writer.write_all(&header)?;
+    writer.write_all(&blob)
+}
+
+/// The differences between consecutive values, zigzag encoded, as in the `sint64` fields
+fn delta_zigzag<I: IntoIterator<Item = i64>>(values: I) -> Vec<u64> {
+    let mut previous = 0;
+    values
+        .into_iter()
+        .map(|value: i64| {
+            let delta = value.wrapping_sub(previous);
+            previous = value;
+            ((delta << 1) ^ (delta >> 63)) as u64
+        })
+        .collect()
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(buf, field << 3);
+    write_varint(buf, value);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(buf, field << 3 | 2);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_packed<I: IntoIterator<Item = u64>>(buf: &mut Vec<u8>, field: u64, values: I) {
+    let mut packed = Vec::new();
+    for value in values {
+        write_varint(&mut packed, value);
+    }
+    if !packed.is_empty() {
+        write_bytes(buf, field, &packed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::generator::data_types::{OSMClassifiedFile, OSMFile};
+    use osmpbf::Mmap;
+    use std::fs::File;
+    use std::io::BufReader;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn convert() {
+        let mut output = NamedTempFile::new().unwrap();
+        let input = BufReader::new(File::open("test_data/tiny.osm").unwrap());
+        convert_xml(input, &mut output).unwrap();
+        let mmap = unsafe { Mmap::from_path(output.path()).unwrap() };
+        let file = OSMClassifiedFile::from_file(OSMFile::from_mmap(&mmap).unwrap());
+        assert_eq!(file.nodes_blobs.len(), 1);
+        assert_eq!(file.relations_blobs.len(), 1);
+
+        let mut nodes = Vec::new();
+        file.nodes_blobs[0].for_each(|node| {
+            let tags: Vec<_> = node.tags().collect();
+            let units = |degrees: f64| (degrees * UNITS_PER_DEGREE).round() as i64;
+            nodes.push((node.id, units(node.lat()), units(node.lon()), tags.len()));
+        });
+        assert_eq!(
+            nodes,
+            vec![
+                (1, 425_063_000, 15_218_000, 0),
+                (2, 425_071_000, 15_225_000, 1),
+                (3, 425_080_000, 15_231_000, 0)
+            ]
+        );
+        file.ways_blobs[0].for_each(|way| {
+            assert_eq!(way.id(), 10);
+            assert_eq!(way.refs().collect::<Vec<_>>(), vec![1, 2, 3]);
+            assert_eq!(
+                way.tags().collect::<Vec<_>>(),
+                vec![("highway", "primary"), ("name", "Carrer d'Andorra")]
+            );
+        });
+        file.relations_blobs[0].for_each(|relation| {
+            assert_eq!(relation.id(), 20);
+            let members: Vec<_> = relation.members().map(|member| member.member_id).collect();
+            assert_eq!(members, vec![10]);
+        });
+
+        let error = convert_xml(r#"<osm><way id="x"/></osm>"#.as_bytes(), &mut Vec::new());
+        assert_eq!(error.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let error = convert_xml(r#"<osm><nd ref="1"/></osm>"#.as_bytes(), &mut Vec::new());
+        assert!(error.is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use super::disk_vec::ChunkedDiskVec;
+use super::graph::EdgeInfo;
+use super::node::NodeId;
+use crate::RoadNames;
+use std::collections::HashMap;
+use std::io;
+
+/// How many ways are stored in each temporary file. With about 72 bytes per way, each file has
+/// 18 MiB
+const WAYS_PER_CHUNK: usize = 1 << 18;
+
+/// How many node references are stored in each temporary file. With 8 bytes per reference, each
+/// file has 32 MiB
+const REFS_PER_CHUNK: usize = 1 << 22;
+
+/// A road, with the tags already parsed in the info of its arcs. Their distance is zero, since it
+/// depends on how the way is split in segments
+#[derive(Copy, Clone, Debug)]
+pub struct ParsedWay {
+    /// The info of the arcs in the order of the nodes, if the road can be traveled in this direction
+    pub direct: Option<EdgeInfo>,
+    /// The info of the arcs against the order of the nodes
+    pub reverse: Option<EdgeInfo>,
+    refs_start: usize,
+    refs_len: usize,
+}
+
+impl ParsedWay {
+    /// The OSM id of the way
+    pub fn way_id(&self) -> i64 {
+        self.direct.or(self.reverse).map_or(0, |info| info.way_id)
+    }
+}
+
+/// The roads found while detecting the junctions, kept in temporary files so that the ways blobs
+/// are only decoded once. Each storage numbers the names of its roads, until `merge_names()`
+/// gives them their final index
+pub struct ParsedWays {
+    ways: ChunkedDiskVec<ParsedWay>,
+    refs: ChunkedDiskVec<NodeId>,
+    names: HashMap<String, u32>,
+    /// The final index of each name, by its index in this storage minus 1
+    final_names: Vec<u32>,
+}
+
+impl ParsedWays {
+    pub fn new() -> Self {
+        ParsedWays {
+            ways: ChunkedDiskVec::new(WAYS_PER_CHUNK),
+            refs: ChunkedDiskVec::new(REFS_PER_CHUNK),
+            names: HashMap::new(),
+            final_names: Vec::new(),
+        }
+    }
+
+    /// The index of a road name in this storage, starting at 1
+    pub fn name_index(&mut self, name: &str) -> u32 {
+        if let Some(&index) = self.names.get(name) {
+            return index;
+        }
+        let index = self.names.len() as u32 + 1;
+        self.names.insert(name.to_owned(), index);
+        index
+    }
+
+    /// Sort the names of all the storages, so that the file does not depend on how the ways were
+    /// split between the threads
+    pub fn merge_names(all: &mut [ParsedWays]) -> RoadNames {
+        let mut names: Vec<String> = all
+            .iter()
+            .flat_map(|parsed| parsed.names.keys().cloned())
+            .collect();
+        names.sort();
+        names.dedup();
+        for parsed in all.iter_mut() {
+            parsed.final_names = vec![0; parsed.names.len()];
+            for (name, index) in parsed.names.drain() {
+                let position = names.binary_search(&name).unwrap();
+                parsed.final_names[index as usize - 1] = position as u32 + 1;
+            }
+        }
+        RoadNames(names)
+    }
+
+    /// The final index of a name of this storage, after `merge_names()`
+    pub fn final_name(&self, index: u32) -> u32 {
+        match index {
+            0 => 0,
+            _ => self.final_names[index as usize - 1],
+        }
+    }
+
+    pub fn push<I: Iterator<Item = NodeId>>(
+        &mut self,
+        direct: Option<EdgeInfo>,
+        reverse: Option<EdgeInfo>,
+        refs: I,
+    ) -> io::Result<()> {
+        let refs_start = self.refs.len();
+        for id in refs {
+            self.refs.push(id)?;
+        }
+        self.ways.push(ParsedWay {
+            direct,
+            reverse,
+            refs_start,
+            refs_len: self.refs.len() - refs_start,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.ways.len()
+    }
+
+    /// The way at the given position and the ids of its nodes
+    pub fn get(&self, index: usize) -> (&ParsedWay, impl Iterator<Item = NodeId> + '_) {
+        let way = &self.ways[index];
+        let refs = (way.refs_start..way.refs_start + way.refs_len).map(move |i| self.refs[i]);
+        (way, refs)
+    }
+}
@@ -0,0 +1,238 @@
+//! Stitch several cartographies into a single one, like the extracts of adjacent countries
+
+use super::data_types::{Arc, ArcAccumulator, DuplicateArcs, EdgeInfo, Graph, NodeIndex, NodeInfo};
+use super::parser;
+use crate::{format_bytes, format_num, DebugTime};
+use crate::{Cartograph, Metadata, RoadLevelStyle, RoadNames};
+use petgraph::visit::EdgeRef;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Merge the cartography files into `output_file`. The nodes with the same coordinates are merged
+/// and the edges between the same nodes too, keeping their most important attributes. The synthetic
+/// edges of the inputs are dropped, since the roads that were cut at the border of an extract may
+/// continue in another one. When `synthetic_edges` is set, they are invented again to make the
+/// merged graph strongly connected. The road levels are the ones of the first file. The OSM ids are
+/// kept when the inputs have them, and the road names too. The projected coordinates of the nodes are
+/// stored when all the inputs store them. The metadata are combined with `merge_metadata()`
+pub fn merge<P: AsRef<Path>>(
+    input_files: &[P],
+    output_file: P,
+    synthetic_edges: bool,
+    quiet: bool,
+) -> io::Result<()> {
+    let mut timer = if quiet {
+        DebugTime::quiet()
+    } else {
+        DebugTime::new()
+    };
+
+    // Collect the nodes, indexed by their coordinates in micro degrees
+    let mut points: Vec<NodeInfo> = Vec::new();
+    let mut node_by_point: HashMap<(i32, i32), NodeIndex> = HashMap::new();
+    let mut arcs = ArcAccumulator::new();
+    let mut road_levels: Option<Vec<RoadLevelStyle>> = None;
+    let mut metadata = Vec::new();
+    let mut road_names = RoadNames::default();
+    let mut name_indexes: HashMap<String, u32> = HashMap::new();
+    let mut projected_nodes = true;
+    for input_file in input_files {
+        let carto = Cartograph::open(input_file)?;
+        metadata.push(carto.metadata().clone());
+        projected_nodes &= carto.projected_nodes;
+
+        // The new index of each name of the file, with 0 for the roads without a name
+        let names: Vec<u32> = std::iter::once(0)
+            .chain(carto.road_names().0.iter().map(|name| {
+                *name_indexes.entry(name.clone()).or_insert_with(|| {
+                    road_names.0.push(name.clone());
+                    road_names.0.len() as u32
+                })
+            }))
+            .collect();
+        if road_levels.is_none() {
+            road_levels = Some(carto.road_levels.clone());
+        } else if road_levels.as_ref() != Some(&carto.road_levels) {
+            timer.msg(format!(
+                "Warning: {} classifies the road levels differently, the first file is used",
+                input_file.as_ref().display()
+            ));
+        }
+
+        let nodes: Vec<NodeIndex> = carto
+            .graph
+            .raw_nodes()
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let point = node.weight;
+                let id = carto.osm_node_id(NodeIndex::new(i)).unwrap_or(0);
+                let key = (point.lat.as_micro_degrees(), point.lon.as_micro_degrees());
+                let index = *node_by_point.entry(key).or_insert_with(|| {
+                    points.push(NodeInfo { point, id: 0 });
+                    NodeIndex::new(points.len() - 1)
+                });
+                let node = &mut points[index.index()];
+                if node.id == 0 {
+                    node.id = id;
+                }
+                index
+            })
+            .collect();
+        let mut num_synthetic = 0;
+        for edge in carto.graph.edge_references() {
+            if edge.weight().synthetic {
+                num_synthetic += 1;
+                continue;
+            }
+            let info: EdgeInfo = edge.weight().into();
+            arcs.push(Arc {
+                from: nodes[edge.source().index()],
+                to: nodes[edge.target().index()],
+                info: EdgeInfo {
+                    name: names.get(info.name as usize).copied().unwrap_or(0),
+                    ..info
+                },
+            })?;
+        }
+        timer.msg(format!(
+            "Read {} nodes and {} edges from {}, dropped {} synthetic edges",
+            format_num(carto.graph.node_count()),
+            format_num(carto.graph.edge_count() - num_synthetic),
+            input_file.as_ref().display(),
+            format_num(num_synthetic)
+        ));
+    }
+
+    let num_arcs = arcs.len();
+    let mut graph = Graph::from_arcs(points.len(), points, arcs.into_sorted(DuplicateArcs::Merge));
+    graph.retain_connected_nodes();
+    timer.msg(format!(
+        "Merged into {} nodes and {} edges (-{} duplicated edges)",
+        format_num(graph.node_len()),
+        format_num(graph.edge_len()),
+        format_num(num_arcs - graph.edge_len())
+    ));
+
+    if synthetic_edges {
+        graph.fix_dead_ends();
+        graph.strongly_connect();
+        timer.msg(format!(
+            "Strongly connected the graph, that now has {} edges",
+            format_num(graph.edge_len())
+        ));
+    }
+
+    let road_levels = road_levels.unwrap_or_else(RoadLevelStyle::defaults);
+    let mut metadata = merge_metadata(&metadata);
+    metadata.insert(Metadata::BBOX, parser::serialize::bbox(&graph));
+    parser::serialize::serialize(
+        &graph,
+        &road_levels,
+        &road_names,
+        &metadata,
+        true,
+        projected_nodes,
+        &output_file,
+    )?;
+    timer.msg(format!(
+        "Wrote results to {}, size = {}",
+        output_file.as_ref().display(),
+        format_bytes(fs::metadata(&output_file)?.len())
+    ));
+
+    Ok(())
+}
+
+/// Combine the metadata of the merged files: the values that are the same in all of them are kept,
+/// the sources are listed, separated by commas, and the replication timestamp is the oldest one.
+/// The generator version is the current one
+fn merge_metadata(inputs: &[Metadata]) -> Metadata {
+    let mut merged = Metadata::default();
+    if let Some((first, others)) = inputs.split_first() {
+        for (key, value) in &first.0 {
+            if others.iter().all(|other| other.get(key) == Some(value)) {
+                merged.insert(key, value.clone());
+            }
+        }
+    }
+    let sources: Vec<&str> = inputs
+        .iter()
+        .filter_map(|metadata| metadata.get(Metadata::SOURCE))
+        .collect();
+    if !sources.is_empty() {
+        merged.insert(Metadata::SOURCE, sources.join(","));
+    }
+    let oldest = inputs
+        .iter()
+        .filter_map(|metadata| {
+            metadata
+                .get(Metadata::REPLICATION_TIMESTAMP)?
+                .parse::<i64>()
+                .ok()
+        })
+        .min();
+    if let Some(timestamp) = oldest {
+        merged.insert(Metadata::REPLICATION_TIMESTAMP, timestamp.to_string());
+    }
+    merged.insert(
+        Metadata::GENERATOR_VERSION,
+        env!("CARGO_PKG_VERSION").to_owned(),
+    );
+    merged
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::GeoPoint;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn merge_with_itself() {
+        let input = "test_data/andorra.ptolemy";
+        let output = NamedTempFile::new().unwrap();
+        merge(&[input, input], output.path().to_str().unwrap(), true, true).unwrap();
+
+        // All nodes and edges are duplicated, so nothing changes
+        let carto = Cartograph::open(output.path()).unwrap();
+        assert_eq!(carto.graph.node_count(), 3124);
+        assert_eq!(carto.graph.edge_count(), 5831);
+
+        let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
+        let to = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));
+        assert_eq!(carto.shortest_path(&from, &to).distance, 12183);
+    }
+
+    #[test]
+    fn metadata() {
+        let metadata = |pairs: &[(&str, &str)]| {
+            Metadata(
+                pairs
+                    .iter()
+                    .map(|&(key, value)| (key.to_owned(), value.to_owned()))
+                    .collect(),
+            )
+        };
+        let merged = merge_metadata(&[
+            metadata(&[
+                ("source", "andorra.osm.pbf"),
+                ("replication_timestamp", "1580000000"),
+                ("profile", "default"),
+                ("region", "Andorra"),
+            ]),
+            metadata(&[
+                ("source", "monaco.osm.pbf"),
+                ("replication_timestamp", "1570000000"),
+                ("profile", "default"),
+            ]),
+        ]);
+        assert_eq!(merged.get("source"), Some("andorra.osm.pbf,monaco.osm.pbf"));
+        assert_eq!(merged.get("replication_timestamp"), Some("1570000000"));
+        assert_eq!(merged.get("profile"), Some("default"));
+        assert_eq!(merged.get("region"), None);
+        assert!(merged.get("generator_version").is_some());
+    }
+}
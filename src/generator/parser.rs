@@ -1,25 +1,208 @@
+pub mod filter;
 pub mod graph;
 pub mod junction;
 pub mod node;
+pub mod relation;
 pub mod serialize;
 
+use crate::generator::data_types::{Barrier, EdgeInfo};
+use crate::{estimate_capacity, AccessClass, Restrictions, RoadLevelStyle, Smoothness, Surface};
+use filter::TagFilter;
 use osmpbf::Way;
+use std::fmt;
+use std::str::FromStr;
 
-/// Detect whether a given node is a barrier
-pub fn parse_barrier<'a, I: Iterator<Item = (&'a str, &'a str)>>(mut node_tags: I) -> bool {
-    node_tags
-        .find(|tag| tag.0 == "barrier")
-        .map(|tag| match tag.1 {
-            "border_control" | "block" | "bollard" | "chain" | "debris" | "gate"
-            | "jersey_barrier" | "kent_carriage_gap" => true,
-            _ => false,
-        })
-        .unwrap_or(false)
+/// Detect whether a given node is a barrier, and whether the travel mode may pass it by its access
+/// tags, like a gate with `access=yes` or `motor_vehicle=destination` for cars, or `foot=yes` on
+/// foot and `bicycle=yes` by bicycle
+pub fn parse_barrier<'a, I: Iterator<Item = (&'a str, &'a str)>>(
+    node_tags: I,
+    mode: TravelMode,
+) -> Barrier {
+    let tags: Vec<(&str, &str)> = node_tags.collect();
+    let get_tag = |name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1);
+    match get_tag("barrier") {
+        Some("border_control")
+        | Some("block")
+        | Some("bollard")
+        | Some("chain")
+        | Some("debris")
+        | Some("gate")
+        | Some("jersey_barrier")
+        | Some("kent_carriage_gap") => {}
+        _ => return Barrier::None,
+    }
+
+    // The most specific access tag of the travel mode wins
+    let names: &[&str] = match mode {
+        TravelMode::Car => &["motorcar", "motor_vehicle", "vehicle", "access"],
+        TravelMode::Foot => &["foot", "access"],
+        TravelMode::Bicycle => &["bicycle", "access"],
+    };
+    match names.iter().find_map(|&name| get_tag(name)) {
+        Some("yes") | Some("permissive") | Some("designated") | Some("destination")
+        | Some("delivery") => Barrier::Passable,
+        // The barriers are made to stop the motor vehicles, so pedestrians and cyclists pass the
+        // untagged ones, like the bollards at the entrance of a cycleway
+        None if mode != TravelMode::Car => Barrier::Passable,
+        _ => Barrier::Blocking,
+    }
+}
+
+/// A signed route whose member ways are flagged in the graph
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteKind {
+    /// A cycle route (`route=bicycle` or `mtb`)
+    Bicycle,
+    /// A bus route (`route=bus` or `trolleybus`)
+    Bus,
+}
+
+/// Detect whether a relation is a route (`type=route`) whose ways are flagged, from its tags
+pub fn parse_route<'a, I: Iterator<Item = (&'a str, &'a str)>>(
+    relation_tags: I,
+) -> Option<RouteKind> {
+    let tags: Vec<(&str, &str)> = relation_tags.collect();
+    let get_tag = |name| {
+        tags.iter()
+            .find(|tag| tag.0 == name)
+            .map(|tag| tag.1.trim())
+    };
+    if get_tag("type") != Some("route") {
+        return None;
+    }
+    match get_tag("route") {
+        Some("bicycle") | Some("mtb") => Some(RouteKind::Bicycle),
+        Some("bus") | Some("trolleybus") => Some(RouteKind::Bus),
+        _ => None,
+    }
+}
+
+/// Who travels on the generated graph
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TravelMode {
+    /// The roads open to motor vehicles, respecting their directions
+    #[default]
+    Car,
+    /// Also the footways, paths, crossings and steps, in both directions, without the motorways
+    /// and the ways with `foot=no`. They have the road level 6 and the steps have the access class
+    /// `Steps`, so that wheelchairs can avoid them
+    Foot,
+    /// Also the cycleways and the paths open to bicycles, without the motorways and the ways with
+    /// `bicycle=no`. The cycleways have the road level 7, and the one-way roads can be traveled
+    /// against their direction where bicycles are allowed to, like with `oneway:bicycle=no`
+    Bicycle,
+}
+
+impl FromStr for TravelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "car" => Ok(TravelMode::Car),
+            "foot" => Ok(TravelMode::Foot),
+            "bicycle" => Ok(TravelMode::Bicycle),
+            _ => Err(format!(
+                "Unknown profile {}, expected car, foot or bicycle",
+                s
+            )),
+        }
+    }
+}
+
+/// The road level of the paths of the foot profile, after all the levels of the roads
+pub const FOOT_LEVEL: u8 = 6;
+
+/// The road level of the cycleways of the bicycle profile
+pub const CYCLE_LEVEL: u8 = 7;
+
+/// Options that change which ways are part of the graph
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub mode: TravelMode,
+    /// Include the ways with `highway=track` whose `tracktype` is at most this grade, from 1 (solid)
+    /// to 5 (soft), like for agricultural or forestry vehicles. Tracks without a grade are included too
+    pub max_track_grade: Option<u8>,
+    /// Include the pedestrian zones (`highway=pedestrian`), that vehicles usually can only use for
+    /// deliveries. Their edges have the access class `Pedestrian`, so that queries can avoid them
+    pub pedestrian_zones: bool,
+    /// Only keep the roads whose tags satisfy all these conditions, like `hgv!=no`
+    pub filters: Vec<TagFilter>,
+}
+
+/// Describe the options that are not the default, like `max_track_grade=3, hgv!=no`, or `default`
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut options: Vec<String> = Vec::new();
+        match self.mode {
+            TravelMode::Car => {}
+            TravelMode::Foot => options.push("foot".to_owned()),
+            TravelMode::Bicycle => options.push("bicycle".to_owned()),
+        }
+        if let Some(grade) = self.max_track_grade {
+            options.push(format!("max_track_grade={}", grade));
+        }
+        if self.pedestrian_zones {
+            options.push("pedestrian_zones".to_owned());
+        }
+        options.extend(self.filters.iter().map(TagFilter::to_string));
+        if options.is_empty() {
+            f.write_str("default")
+        } else {
+            f.write_str(&options.join(", "))
+        }
+    }
 }
 
-/// Convert the value of the tag `highway` to a `road_level` (from 0 to 5)
-pub fn parse_road_level(way: &Way) -> Option<u8> {
-    get_tag(way, "highway").and_then(|value| match value {
+/// Convert the value of the tag `highway` to a `road_level` (from 0 to 5, 6 for the paths of the
+/// foot profile or 7 for the cycleways of the bicycle profile), or `None` if the way is not a road
+/// or does not satisfy the filters
+pub fn parse_road_level(way: &Way, profile: &Profile) -> Option<u8> {
+    road_level_from_tags(|name| get_tag(way, name), profile)
+}
+
+/// Like `parse_road_level()`, given a way to get the value of a tag
+fn road_level_from_tags<'a>(
+    get_tag: impl Fn(&str) -> Option<&'a str>,
+    profile: &Profile,
+) -> Option<u8> {
+    if !profile
+        .filters
+        .iter()
+        .all(|filter| filter.matches(&get_tag))
+    {
+        return None;
+    }
+    let foot = profile.mode == TravelMode::Foot;
+    let bicycle = profile.mode == TravelMode::Bicycle;
+    let tag = |name| get_tag(name).map(str::trim);
+    if (foot && tag("foot") == Some("no")) || (bicycle && tag("bicycle") == Some("no")) {
+        return None;
+    }
+    let bicycle_allowed = matches!(
+        tag("bicycle"),
+        Some("yes") | Some("designated") | Some("permissive")
+    );
+    get_tag("highway").and_then(|value| match value {
+        "track" => profile
+            .max_track_grade
+            .filter(|&max_grade| parse_track_grade_tag(get_tag("tracktype")) <= max_grade)
+            .map(|_| 5),
+        "footway" | "path" | "steps" | "pedestrian" | "crossing" if foot => Some(FOOT_LEVEL),
+        "cycleway" if bicycle => Some(CYCLE_LEVEL),
+        "footway" | "path" | "pedestrian" | "bridleway" if bicycle && bicycle_allowed => {
+            Some(CYCLE_LEVEL)
+        }
+        "motorway" | "motorway_link" if foot || bicycle => None,
+        "pedestrian" if profile.pedestrian_zones => Some(5),
+        _ => highway_level(value),
+    })
+}
+
+/// The road level of the values of the tag `highway` that are always roads. This must agree with
+/// `RoadLevelStyle::defaults()`, that is stored in the file
+fn highway_level(highway: &str) -> Option<u8> {
+    match highway {
         "motorway" => Some(0),
         "motorway_link" => Some(0),
         "trunk" => Some(0),
@@ -38,38 +221,837 @@ pub fn parse_road_level(way: &Way) -> Option<u8> {
         "rest_area" => Some(5),
         "services" => Some(5),
         _ => None,
-    })
+    }
 }
 
+/// The road levels of the roads included with this profile, as stored in the file
+pub fn road_levels(profile: &Profile) -> Vec<RoadLevelStyle> {
+    let mut levels = RoadLevelStyle::defaults();
+    if profile.max_track_grade.is_some() {
+        levels[5].highways.push("track".to_owned());
+    }
+    if profile.pedestrian_zones && profile.mode != TravelMode::Foot {
+        levels[5].highways.push("pedestrian".to_owned());
+    }
+    if profile.mode == TravelMode::Bicycle {
+        levels.push(RoadLevelStyle {
+            level: CYCLE_LEVEL,
+            name: "cycleway".to_owned(),
+            highways: ["cycleway", "footway", "path", "pedestrian", "bridleway"]
+                .iter()
+                .map(|&highway| highway.to_owned())
+                .collect(),
+            color: "#0000ff".to_owned(),
+            min_zoom: 14,
+        });
+    }
+    if profile.mode == TravelMode::Foot {
+        levels.push(RoadLevelStyle {
+            level: FOOT_LEVEL,
+            name: "path".to_owned(),
+            highways: ["footway", "path", "steps", "pedestrian", "crossing"]
+                .iter()
+                .map(|&highway| highway.to_owned())
+                .collect(),
+            color: "#fa8072".to_owned(),
+            min_zoom: 15,
+        });
+    }
+    levels
+}
+
+/// Convert the value of the tag `tracktype` to a grade from 1 (solid) to 5 (soft), 0 when unknown
+pub fn parse_track_grade(way: &Way) -> u8 {
+    parse_track_grade_tag(get_tag(way, "tracktype"))
+}
+
+fn parse_track_grade_tag(tracktype: Option<&str>) -> u8 {
+    match tracktype {
+        Some("grade1") => 1,
+        Some("grade2") => 2,
+        Some("grade3") => 3,
+        Some("grade4") => 4,
+        Some("grade5") => 5,
+        _ => 0,
+    }
+}
+
+/// Classify who is allowed on the way, from the tags `highway` and `motorroad`
+pub fn parse_access(way: &Way) -> AccessClass {
+    AccessClass::from_tags(get_tag(way, "highway"), get_tag(way, "motorroad"))
+}
+
+/// Detect whether the way is part of a roundabout
+pub fn parse_roundabout(way: &Way) -> bool {
+    matches!(
+        get_tag(way, "junction"),
+        Some("roundabout") | Some("circular")
+    )
+}
+
+/// The directions in which a way can be traveled
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
 pub struct Direction {
+    /// In the order of the nodes of the way
     pub direct: bool,
+    /// Against the order of the nodes of the way
     pub reverse: bool,
+    /// Whether the direct direction is only open at some times, by `oneway:conditional`
+    pub direct_conditional: bool,
+    pub reverse_conditional: bool,
+    /// Whether the way changes direction during the day, like `oneway=reversible`. Both directions
+    /// are open, since the times are unknown
+    pub reversible: bool,
 }
 
-/// Convert the value of the tag `oneway`
+/// Convert the tags `oneway` and `oneway:conditional`
 pub fn parse_oneway(way: &Way) -> Direction {
-    let junction = get_tag(way, "junction");
-    let highway = get_tag(way, "highway");
-    let oneway = get_tag(way, "oneway");
+    parse_direction(|name| get_tag(way, name))
+}
+
+/// Convert the tags `oneway` and `oneway:conditional`, given a way to get the value of a tag
+fn parse_direction<'a>(get_tag: impl Fn(&str) -> Option<&'a str>) -> Direction {
+    let junction = get_tag("junction");
+    let highway = get_tag("highway");
 
     // As per https://wiki.openstreetmap.org/wiki/Key:oneway, for roundabouts and motorways,
     // ways are single-way by default, unless explicitly stated otherwise
-    let implied = if junction == Some("roundabout") || highway == Some("motorway") {
-        (true, false)
+    let implied_oneway = matches!(
+        (junction, highway),
+        (Some("roundabout"), _) | (Some("circular"), _) | (_, Some("motorway"))
+    );
+
+    let mut direction = Direction::default();
+    match get_tag("oneway").map(str::trim) {
+        Some("yes") | Some("true") | Some("1") => direction.direct = true,
+        Some("no") | Some("false") | Some("0") => {
+            direction.direct = true;
+            direction.reverse = true;
+        }
+        Some("-1") | Some("reverse") => direction.reverse = true,
+        Some("reversible") | Some("alternating") => {
+            direction.direct = true;
+            direction.reverse = true;
+            direction.reversible = true;
+        }
+        _ => {
+            direction.direct = true;
+            direction.reverse = !implied_oneway;
+        }
+    }
+
+    // Like `yes @ (Mo-Fr 07:00-09:00)`. The direction that is only open at some times is kept, but
+    // marked, since the times are not evaluated
+    let conditional = get_tag("oneway:conditional")
+        .and_then(|value| value.split('@').next())
+        .map(str::trim);
+    match conditional {
+        Some("yes") | Some("true") | Some("1") => {
+            direction.reverse_conditional = direction.reverse;
+        }
+        Some("-1") | Some("reverse") => {
+            direction.direct_conditional = direction.direct;
+        }
+        Some("no") | Some("false") | Some("0") => {
+            direction.direct_conditional = !direction.direct;
+            direction.reverse_conditional = !direction.reverse;
+            direction.direct = true;
+            direction.reverse = true;
+        }
+        _ => {}
+    }
+
+    direction
+}
+
+/// Like `parse_direction()`, but for bicycles, that may be allowed against the direction of one-way
+/// roads (`oneway:bicycle=no` or a contra-flow cycle lane, like `cycleway=opposite_lane`), or not
+/// (`oneway:bicycle=yes`)
+fn bicycle_direction<'a>(get_tag: impl Fn(&str) -> Option<&'a str>) -> Direction {
+    let mut direction = parse_direction(&get_tag);
+    let contra_flow = [
+        "cycleway",
+        "cycleway:left",
+        "cycleway:right",
+        "cycleway:both",
+    ]
+    .iter()
+    .filter_map(|&name| get_tag(name))
+    .any(|value| value.trim().starts_with("opposite"));
+    match get_tag("oneway:bicycle").map(str::trim) {
+        Some("no") | Some("false") | Some("0") => {
+            direction.direct = true;
+            direction.reverse = true;
+        }
+        Some("yes") | Some("true") | Some("1") => direction.reverse = false,
+        _ if contra_flow => {
+            direction.direct = true;
+            direction.reverse = true;
+        }
+        _ => {}
+    }
+    direction
+}
+
+/// Whether the way has a dedicated bicycle infrastructure: a cycleway, a path designated for
+/// bicycles or a cycle lane or track along the road
+fn is_cycleway<'a>(get_tag: impl Fn(&str) -> Option<&'a str>) -> bool {
+    get_tag("highway") == Some("cycleway")
+        || get_tag("bicycle").map(str::trim) == Some("designated")
+        || [
+            "cycleway",
+            "cycleway:left",
+            "cycleway:right",
+            "cycleway:both",
+        ]
+        .iter()
+        .filter_map(|&name| get_tag(name))
+        .any(|value| {
+            matches!(
+                value.trim(),
+                "lane" | "track" | "opposite_lane" | "opposite_track"
+            )
+        })
+}
+
+/// The number of lanes in each direction of the way, 0 when unknown
+pub struct Lanes {
+    pub forward: u8,
+    pub backward: u8,
+}
+
+/// Parse the tags `lanes`, `lanes:forward` and `lanes:backward`. For two-way roads, when only the
+/// total is known, the lanes are split between both directions, with the extra one going forward
+pub fn parse_lanes(way: &Way, direction: &Direction) -> Lanes {
+    let parse = |name| get_tag(way, name).and_then(|value| value.trim().parse::<u8>().ok());
+    let total = parse("lanes");
+
+    if direction.direct && direction.reverse {
+        let forward = parse("lanes:forward");
+        let backward = parse("lanes:backward");
+        Lanes {
+            forward: forward
+                .or_else(|| total.map(|t| t.saturating_sub(backward.unwrap_or(t / 2))))
+                .unwrap_or(0),
+            backward: backward
+                .or_else(|| total.map(|t| t.saturating_sub(forward.unwrap_or(t - t / 2))))
+                .unwrap_or(0),
+        }
     } else {
-        (true, true)
+        let lanes = total.unwrap_or(0);
+        Lanes {
+            forward: if direction.direct { lanes } else { 0 },
+            backward: if direction.reverse { lanes } else { 0 },
+        }
+    }
+}
+
+/// Parse the tag `width`, in meters or with units like `350 cm` and `12'`, like `maxwidth`.
+/// Returns 0 when unknown
+pub fn parse_width(way: &Way) -> f32 {
+    get_tag(way, "width").and_then(parse_length).unwrap_or(0.)
+}
+
+/// Parse the tags `surface` and `smoothness`
+pub fn parse_surface(way: &Way) -> (Surface, Smoothness) {
+    (
+        get_tag(way, "surface").map_or(Surface::Unknown, Surface::from_tag),
+        get_tag(way, "smoothness").map_or(Smoothness::Unknown, Smoothness::from_tag),
+    )
+}
+
+/// Parse the tags `maxweight`, `maxheight`, `maxwidth` and `hgv`
+pub fn parse_restrictions(way: &Way) -> Restrictions {
+    restrictions_from_tags(|name| get_tag(way, name))
+}
+
+/// Parse the tags `maxweight`, `maxheight`, `maxwidth` and `hgv`, given a way to get the value of a
+/// tag. The values that can not be parsed, like `default` or `below_default`, are no limit
+fn restrictions_from_tags<'a>(get_tag: impl Fn(&str) -> Option<&'a str>) -> Restrictions {
+    Restrictions {
+        max_weight: get_tag("maxweight").and_then(parse_weight).unwrap_or(0.),
+        max_height: get_tag("maxheight").and_then(parse_length).unwrap_or(0.),
+        max_width: get_tag("maxwidth").and_then(parse_length).unwrap_or(0.),
+        no_hgv: get_tag("hgv").map(str::trim) == Some("no"),
+    }
+}
+
+/// Parse a weight in tonnes, like `7.5`, `7.5 t`, `7500 kg` or `10 st`
+fn parse_weight(value: &str) -> Option<f32> {
+    let (number, unit) = split_unit(value)?;
+    match unit {
+        "" | "t" => Some(number),
+        "kg" => Some(number / 1000.),
+        "st" => Some(number * 0.907_185),
+        "lbs" | "lb" => Some(number * 0.000_453_592),
+        _ => None,
+    }
+}
+
+/// Parse a length in meters, like `3.5`, `3.5 m`, `12 ft` or `12'6"`
+fn parse_length(value: &str) -> Option<f32> {
+    if let Some(pos) = value.find('\'') {
+        let feet = value[..pos].trim().parse::<f32>().ok()?;
+        let inches = value[pos + 1..].trim().trim_end_matches('"').trim();
+        let inches = if inches.is_empty() {
+            0.
+        } else {
+            inches.parse::<f32>().ok()?
+        };
+        let meters = (feet * 12. + inches) * 0.0254;
+        return Some(meters).filter(|meters| meters.is_finite() && *meters > 0.);
+    }
+    let (number, unit) = split_unit(value)?;
+    match unit {
+        "" | "m" => Some(number),
+        "cm" => Some(number / 100.),
+        "ft" => Some(number * 0.3048),
+        "in" | "\"" => Some(number * 0.0254),
+        _ => None,
+    }
+}
+
+/// Split a value like `7.5 t` into its positive number and its unit
+fn split_unit(value: &str) -> Option<(f32, &str)> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let number = value[..end]
+        .parse::<f32>()
+        .ok()
+        .filter(|number| number.is_finite() && *number > 0.)?;
+    Some((number, value[end..].trim()))
+}
+
+/// The numeric tags of a road whose value can not be parsed, like `maxweight=heavy`. They are
+/// ignored, as if the tag was not there
+pub fn parse_bad_tags<'a>(way: &'a Way) -> Vec<(&'static str, &'a str)> {
+    bad_tags(|name| get_tag(way, name))
+}
+
+/// Like `parse_bad_tags()`, given a way to get the value of a tag. The usual keywords of the limits,
+/// like `none` or `default`, are valid
+fn bad_tags<'a>(get_tag: impl Fn(&str) -> Option<&'a str>) -> Vec<(&'static str, &'a str)> {
+    let is_keyword = |value: &str| {
+        ["none", "default", "below_default", "no_sign", "unsigned"].contains(&value.trim())
+    };
+    let is_valid = |key: &str, value: &str| match key {
+        "lanes" | "lanes:forward" | "lanes:backward" => value.trim().parse::<u8>().is_ok(),
+        "width" => parse_length(value).is_some(),
+        "maxweight" => is_keyword(value) || parse_weight(value).is_some(),
+        _ => is_keyword(value) || parse_length(value).is_some(),
     };
+    let keys = [
+        "lanes",
+        "lanes:forward",
+        "lanes:backward",
+        "width",
+        "maxweight",
+        "maxheight",
+        "maxwidth",
+    ];
+    keys.iter()
+        .filter_map(|&key| {
+            get_tag(key)
+                .filter(|value| !is_valid(key, value))
+                .map(|value| (key, value))
+        })
+        .collect()
+}
+
+/// Parse all the tags of a road into the info of its arcs in each direction, `None` when it can not
+/// be traveled in that direction. The distance and name are left as zero, and the route flags unset
+pub fn parse_arcs_info(
+    way: &Way,
+    road_level: u8,
+    profile: &Profile,
+) -> (Option<EdgeInfo>, Option<EdgeInfo>) {
+    // Pedestrians can walk in both directions of the one-way roads
+    let direction = match profile.mode {
+        TravelMode::Car => parse_oneway(way),
+        TravelMode::Foot => Direction {
+            direct: true,
+            reverse: true,
+            ..Direction::default()
+        },
+        TravelMode::Bicycle => bicycle_direction(|name| get_tag(way, name)),
+    };
+    let cycleway = is_cycleway(|name| get_tag(way, name));
+    let roundabout = parse_roundabout(way);
+    let lanes = parse_lanes(way, &direction);
+    let width = parse_width(way);
+    let track_grade = parse_track_grade(way);
+    let (surface, smoothness) = parse_surface(way);
+    let access = parse_access(way);
+    let restrictions = parse_restrictions(way);
 
-    let (direct, reverse) = match oneway {
-        Some("yes") | Some("true") | Some("1") => (true, false),
-        Some("no") | Some("false") | Some("0") => (true, false),
-        Some("-1") => (false, true),
-        _ => implied,
+    let info = |lanes, conditional| EdgeInfo {
+        road_level,
+        distance: 0,
+        roundabout,
+        capacity: estimate_capacity(road_level, lanes),
+        lanes,
+        width,
+        synthetic: false,
+        conditional,
+        reversible: direction.reversible,
+        barrier: false,
+        cycleway,
+        bicycle_route: false,
+        bus_route: false,
+        border: false,
+        track_grade,
+        surface,
+        smoothness,
+        access,
+        restrictions,
+        way_id: way.id(),
+        name: 0,
+        shape: 0,
     };
+    (
+        direction
+            .direct
+            .then(|| info(lanes.forward, direction.direct_conditional)),
+        direction
+            .reverse
+            .then(|| info(lanes.backward, direction.reverse_conditional)),
+    )
+}
 
-    Direction { direct, reverse }
+/// The name of a road, for the summaries of the routes: its `ref`, like `CG-2`, or else its `name`
+pub fn parse_name<'a>(way: &'a Way) -> Option<&'a str> {
+    name_from_tags(|name| get_tag(way, name))
+}
+
+fn name_from_tags<'a>(get_tag: impl Fn(&str) -> Option<&'a str>) -> Option<&'a str> {
+    let value = |key| {
+        get_tag(key)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    value("ref").or_else(|| value("name"))
 }
 
 fn get_tag<'a>(way: &'a Way, name: &'_ str) -> Option<&'a str> {
     way.tags().find(|tag| tag.0 == name).map(|tag| tag.1)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn road_levels() {
+        let levels = super::road_levels(&Profile::default());
+        for style in &levels {
+            for highway in &style.highways {
+                assert_eq!(highway_level(highway), Some(style.level), "{}", highway);
+            }
+        }
+        assert_eq!(highway_level("track"), None);
+        let with_tracks = super::road_levels(&Profile {
+            max_track_grade: Some(3),
+            ..Profile::default()
+        });
+        assert_eq!(with_tracks[5].highways.last().unwrap(), "track");
+        let with_pedestrian_zones = super::road_levels(&Profile {
+            pedestrian_zones: true,
+            ..Profile::default()
+        });
+        assert_eq!(
+            with_pedestrian_zones[5].highways.last().unwrap(),
+            "pedestrian"
+        );
+
+        let foot = Profile {
+            mode: TravelMode::Foot,
+            ..Profile::default()
+        };
+        let foot_levels = super::road_levels(&foot);
+        assert_eq!(foot_levels.len(), 7);
+        for highway in &foot_levels[6].highways {
+            let level =
+                road_level_from_tags(|name| (name == "highway").then_some(&**highway), &foot);
+            assert_eq!(level, Some(FOOT_LEVEL), "{}", highway);
+        }
+    }
+
+    #[test]
+    fn foot_profile() {
+        let level = |tags: &[(&'static str, &'static str)], mode| {
+            let profile = Profile {
+                mode,
+                ..Profile::default()
+            };
+            road_level_from_tags(
+                |name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1),
+                &profile,
+            )
+        };
+        assert_eq!(level(&[("highway", "footway")], TravelMode::Car), None);
+        assert_eq!(
+            level(
+                &[("highway", "footway"), ("footway", "crossing")],
+                TravelMode::Foot
+            ),
+            Some(6)
+        );
+        assert_eq!(level(&[("highway", "steps")], TravelMode::Foot), Some(6));
+        assert_eq!(level(&[("highway", "motorway")], TravelMode::Car), Some(0));
+        assert_eq!(level(&[("highway", "motorway")], TravelMode::Foot), None);
+        assert_eq!(level(&[("highway", "primary")], TravelMode::Foot), Some(1));
+        assert_eq!(
+            level(&[("highway", "primary"), ("foot", "no")], TravelMode::Foot),
+            None
+        );
+        assert_eq!(crate::estimate_duration(500, FOOT_LEVEL), 360.);
+    }
+
+    #[test]
+    fn bicycle_profile() {
+        let level = |tags: &[(&'static str, &'static str)]| {
+            let profile = Profile {
+                mode: TravelMode::Bicycle,
+                ..Profile::default()
+            };
+            road_level_from_tags(
+                |name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1),
+                &profile,
+            )
+        };
+        assert_eq!(level(&[("highway", "cycleway")]), Some(CYCLE_LEVEL));
+        assert_eq!(level(&[("highway", "footway")]), None);
+        assert_eq!(
+            level(&[("highway", "footway"), ("bicycle", "yes")]),
+            Some(CYCLE_LEVEL)
+        );
+        assert_eq!(level(&[("highway", "trunk"), ("bicycle", "no")]), None);
+        assert_eq!(level(&[("highway", "motorway")]), None);
+        assert_eq!(level(&[("highway", "residential")]), Some(5));
+        let bicycle_levels = super::road_levels(&Profile {
+            mode: TravelMode::Bicycle,
+            ..Profile::default()
+        });
+        assert_eq!(bicycle_levels.last().unwrap().level, CYCLE_LEVEL);
+
+        let direction = |tags: &[(&'static str, &'static str)]| {
+            bicycle_direction(|name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1))
+        };
+        let oneway = direction(&[("highway", "residential"), ("oneway", "yes")]);
+        assert!(oneway.direct && !oneway.reverse);
+        let contra_flow = direction(&[
+            ("highway", "residential"),
+            ("oneway", "yes"),
+            ("oneway:bicycle", "no"),
+        ]);
+        assert!(contra_flow.direct && contra_flow.reverse);
+        let contra_flow = direction(&[("oneway", "yes"), ("cycleway:left", "opposite_lane")]);
+        assert!(contra_flow.direct && contra_flow.reverse);
+        let oneway = direction(&[("highway", "cycleway"), ("oneway:bicycle", "yes")]);
+        assert!(oneway.direct && !oneway.reverse);
+
+        let cycleway = |tags: &[(&'static str, &'static str)]| {
+            is_cycleway(|name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1))
+        };
+        assert!(cycleway(&[("highway", "cycleway")]));
+        assert!(cycleway(&[
+            ("highway", "primary"),
+            ("cycleway:right", "track")
+        ]));
+        assert!(cycleway(&[("highway", "path"), ("bicycle", "designated")]));
+        assert!(!cycleway(&[
+            ("highway", "primary"),
+            ("cycleway", "shared_lane")
+        ]));
+        assert!(!cycleway(&[("highway", "primary"), ("bicycle", "yes")]));
+    }
+
+    fn direction(tags: &[(&'static str, &'static str)]) -> Direction {
+        parse_direction(|name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1))
+    }
+
+    #[test]
+    fn restrictions() {
+        let parse = |tags: &[(&'static str, &'static str)]| {
+            restrictions_from_tags(|name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1))
+        };
+        assert_eq!(parse(&[("highway", "primary")]), Restrictions::default());
+        assert_eq!(
+            parse(&[
+                ("maxweight", "7.5"),
+                ("maxheight", "3.8 m"),
+                ("maxwidth", "2.2"),
+                ("hgv", "no")
+            ]),
+            Restrictions {
+                max_weight: 7.5,
+                max_height: 3.8,
+                max_width: 2.2,
+                no_hgv: true,
+            }
+        );
+        let restrictions = parse(&[
+            ("maxweight", "3500 kg"),
+            ("maxheight", "12'6\""),
+            ("maxwidth", "default"),
+            ("hgv", "destination"),
+        ]);
+        assert_eq!(restrictions.max_weight, 3.5);
+        assert!((restrictions.max_height - 3.81).abs() < 1e-6);
+        assert_eq!(restrictions.max_width, 0.);
+        assert!(!restrictions.no_hgv);
+
+        assert_eq!(parse_length("14 ft"), Some(14. * 0.3048));
+        assert_eq!(parse_length("3.5m"), Some(3.5));
+        assert_eq!(parse_length("350 cm"), Some(3.5));
+        assert_eq!(parse_length("12'"), Some(12. * 0.3048));
+        assert_eq!(parse_length("none"), None);
+        assert_eq!(parse_length("0"), None);
+        assert_eq!(parse_weight("20 st"), Some(20. * 0.907_185));
+        assert_eq!(parse_weight("7.5 tons"), None);
+    }
+
+    #[test]
+    fn barriers() {
+        let parse = |tags: &[(&'static str, &'static str)]| {
+            parse_barrier(tags.iter().copied(), TravelMode::Car)
+        };
+        assert_eq!(parse(&[("highway", "crossing")]), Barrier::None);
+        assert_eq!(parse(&[("barrier", "kerb")]), Barrier::None);
+        assert_eq!(parse(&[("barrier", "gate")]), Barrier::Blocking);
+        assert_eq!(
+            parse(&[("barrier", "gate"), ("access", "yes")]),
+            Barrier::Passable
+        );
+        assert_eq!(
+            parse(&[("barrier", "bollard"), ("motor_vehicle", "destination")]),
+            Barrier::Passable
+        );
+        assert_eq!(
+            parse(&[
+                ("barrier", "gate"),
+                ("access", "yes"),
+                ("motor_vehicle", "no")
+            ]),
+            Barrier::Blocking
+        );
+        assert_eq!(
+            parse(&[
+                ("barrier", "gate"),
+                ("motor_vehicle", "no"),
+                ("motorcar", "yes")
+            ]),
+            Barrier::Passable
+        );
+
+        // On foot, only the access tags of the pedestrians count
+        let foot = |tags: &[(&'static str, &'static str)]| {
+            parse_barrier(tags.iter().copied(), TravelMode::Foot)
+        };
+        assert_eq!(foot(&[("barrier", "bollard")]), Barrier::Passable);
+        assert_eq!(
+            foot(&[("barrier", "gate"), ("motor_vehicle", "no")]),
+            Barrier::Passable
+        );
+        assert_eq!(
+            foot(&[("barrier", "gate"), ("access", "private")]),
+            Barrier::Blocking
+        );
+        assert_eq!(
+            foot(&[("barrier", "gate"), ("access", "private"), ("foot", "yes")]),
+            Barrier::Passable
+        );
+        assert_eq!(
+            foot(&[("barrier", "gate"), ("foot", "no"), ("vehicle", "yes")]),
+            Barrier::Blocking
+        );
+
+        // Likewise by bicycle
+        let bicycle = |tags: &[(&'static str, &'static str)]| {
+            parse_barrier(tags.iter().copied(), TravelMode::Bicycle)
+        };
+        assert_eq!(bicycle(&[("barrier", "bollard")]), Barrier::Passable);
+        assert_eq!(
+            bicycle(&[("barrier", "gate"), ("access", "no")]),
+            Barrier::Blocking
+        );
+        assert_eq!(
+            bicycle(&[("barrier", "gate"), ("access", "no"), ("bicycle", "yes")]),
+            Barrier::Passable
+        );
+        assert_eq!(
+            bicycle(&[("barrier", "chain"), ("bicycle", "no"), ("foot", "yes")]),
+            Barrier::Blocking
+        );
+    }
+
+    #[test]
+    fn routes() {
+        let parse = |tags: &[(&'static str, &'static str)]| parse_route(tags.iter().copied());
+        assert_eq!(
+            parse(&[("type", "route"), ("route", "bicycle")]),
+            Some(RouteKind::Bicycle)
+        );
+        assert_eq!(
+            parse(&[("type", "route"), ("route", "mtb")]),
+            Some(RouteKind::Bicycle)
+        );
+        assert_eq!(
+            parse(&[("route", "trolleybus"), ("type", "route")]),
+            Some(RouteKind::Bus)
+        );
+        assert_eq!(parse(&[("type", "route"), ("route", "hiking")]), None);
+        assert_eq!(parse(&[("type", "route")]), None);
+        assert_eq!(parse(&[("type", "route_master"), ("route", "bus")]), None);
+    }
+
+    #[test]
+    fn names() {
+        let parse = |tags: &[(&'static str, &'static str)]| {
+            name_from_tags(|name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1))
+        };
+        assert_eq!(
+            parse(&[("ref", "CG-2"), ("name", "Carretera General 2")]),
+            Some("CG-2")
+        );
+        assert_eq!(
+            parse(&[("name", "Avinguda Meritxell")]),
+            Some("Avinguda Meritxell")
+        );
+        assert_eq!(parse(&[("highway", "residential"), ("name", " ")]), None);
+    }
+
+    #[test]
+    fn profile_description() {
+        assert_eq!(Profile::default().to_string(), "default");
+        let profile = Profile {
+            mode: TravelMode::Car,
+            max_track_grade: Some(3),
+            pedestrian_zones: false,
+            filters: vec!["hgv!=no".parse().unwrap()],
+        };
+        assert_eq!(profile.to_string(), "max_track_grade=3, hgv!=no");
+        let profile = Profile {
+            mode: "foot".parse().unwrap(),
+            ..Profile::default()
+        };
+        assert_eq!(profile.to_string(), "foot");
+        assert!("horse".parse::<TravelMode>().is_err());
+    }
+
+    #[test]
+    fn bad_tag_values() {
+        let parse = |tags: &[(&'static str, &'static str)]| {
+            bad_tags(|name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1))
+        };
+        let valid = parse(&[
+            ("highway", "primary"),
+            ("lanes", "2"),
+            ("width", "7 m"),
+            ("maxweight", "7.5"),
+            ("maxheight", "default"),
+            ("maxwidth", "none"),
+        ]);
+        assert!(valid.is_empty());
+        assert_eq!(
+            parse(&[("lanes", "2;3"), ("maxweight", "heavy"), ("maxheight", "4")]),
+            vec![("lanes", "2;3"), ("maxweight", "heavy")]
+        );
+    }
+
+    #[test]
+    fn oneway() {
+        let both = Direction {
+            direct: true,
+            reverse: true,
+            ..Direction::default()
+        };
+        let direct = Direction {
+            direct: true,
+            ..Direction::default()
+        };
+        let reverse = Direction {
+            reverse: true,
+            ..Direction::default()
+        };
+
+        assert_eq!(direction(&[("highway", "residential")]), both);
+        assert_eq!(
+            direction(&[("highway", "residential"), ("oneway", "yes")]),
+            direct
+        );
+        assert_eq!(
+            direction(&[("highway", "primary"), ("oneway", "-1")]),
+            reverse
+        );
+        assert_eq!(direction(&[("highway", "primary"), ("oneway", "no")]), both);
+
+        // Implied by roundabouts and motorways, unless explicitly stated otherwise
+        assert_eq!(direction(&[("highway", "motorway")]), direct);
+        assert_eq!(
+            direction(&[("highway", "tertiary"), ("junction", "roundabout")]),
+            direct
+        );
+        assert_eq!(
+            direction(&[("highway", "motorway"), ("oneway", "no")]),
+            both
+        );
+        assert_eq!(
+            direction(&[("junction", "roundabout"), ("oneway", "-1")]),
+            reverse
+        );
+
+        assert_eq!(
+            direction(&[("highway", "secondary"), ("oneway", "reversible")]),
+            Direction {
+                reversible: true,
+                ..both
+            }
+        );
+
+        // A two-way road that is one-way at rush hour
+        assert_eq!(
+            direction(&[
+                ("highway", "residential"),
+                ("oneway:conditional", "yes @ (Mo-Fr 07:00-09:00)")
+            ]),
+            Direction {
+                reverse_conditional: true,
+                ..both
+            }
+        );
+        // A one-way road that is two-way at night
+        assert_eq!(
+            direction(&[
+                ("highway", "residential"),
+                ("oneway", "yes"),
+                ("oneway:conditional", "no @ (19:00-06:00)")
+            ]),
+            Direction {
+                reverse_conditional: true,
+                ..both
+            }
+        );
+        assert_eq!(
+            direction(&[
+                ("highway", "service"),
+                ("oneway:conditional", "-1 @ (Sa,Su)")
+            ]),
+            Direction {
+                direct_conditional: true,
+                ..both
+            }
+        );
+        // An unknown condition value changes nothing
+        assert_eq!(
+            direction(&[
+                ("highway", "service"),
+                ("oneway", "yes"),
+                ("oneway:conditional", "maybe @ (Sa,Su)")
+            ]),
+            direct
+        );
+    }
+}
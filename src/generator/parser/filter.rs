@@ -0,0 +1,132 @@
+//! Conditions on the tags of the ways, to build specialized graphs, like for heavy vehicles
+
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+
+/// A condition on one tag of the ways:
+/// - `key=value` and `key!=value` compare the value
+/// - `key~pattern` and `key!~pattern` match the whole value against a regular expression
+/// - `key` and `!key` check whether the tag is present
+///
+/// The negated conditions hold for the ways without the tag
+#[derive(Clone, Debug)]
+pub struct TagFilter {
+    key: String,
+    condition: Condition,
+    /// The condition as it was given
+    text: String,
+}
+
+#[derive(Clone, Debug)]
+enum Condition {
+    Present,
+    Absent,
+    Equals(String),
+    NotEquals(String),
+    Matches(Regex),
+    NotMatches(Regex),
+}
+
+impl TagFilter {
+    /// Whether the tags of a way satisfy the condition, given a way to get the value of a tag
+    pub fn matches<'a>(&self, get_tag: impl Fn(&str) -> Option<&'a str>) -> bool {
+        let value = get_tag(&self.key);
+        match &self.condition {
+            Condition::Present => value.is_some(),
+            Condition::Absent => value.is_none(),
+            Condition::Equals(expected) => value == Some(expected.as_str()),
+            Condition::NotEquals(expected) => value != Some(expected.as_str()),
+            Condition::Matches(regex) => value.is_some_and(|value| regex.is_match(value)),
+            Condition::NotMatches(regex) => !value.is_some_and(|value| regex.is_match(value)),
+        }
+    }
+}
+
+impl FromStr for TagFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, condition) = match s.find(['=', '~']) {
+            None => match s.strip_prefix('!') {
+                Some(key) => (key, Condition::Absent),
+                None => (s, Condition::Present),
+            },
+            Some(pos) => {
+                let value = &s[pos + 1..];
+                let (key, negated) = match s[..pos].strip_suffix('!') {
+                    Some(key) => (key, true),
+                    None => (&s[..pos], false),
+                };
+                let condition = if s[pos..].starts_with('=') {
+                    if negated {
+                        Condition::NotEquals(value.to_owned())
+                    } else {
+                        Condition::Equals(value.to_owned())
+                    }
+                } else {
+                    // Match the whole value, like the other conditions
+                    let regex = Regex::new(&format!("^(?:{})$", value))
+                        .map_err(|err| format!("Invalid pattern in filter {}: {}", s, err))?;
+                    if negated {
+                        Condition::NotMatches(regex)
+                    } else {
+                        Condition::Matches(regex)
+                    }
+                };
+                (key, condition)
+            }
+        };
+        if key.is_empty() {
+            return Err(format!(
+                "Invalid filter {}, expected key=value, key~pattern or key",
+                s
+            ));
+        }
+        Ok(TagFilter {
+            key: key.to_owned(),
+            condition,
+            text: s.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for TagFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(filter: &str, tags: &[(&'static str, &'static str)]) -> bool {
+        let filter: TagFilter = filter.parse().unwrap();
+        filter.matches(|name| tags.iter().find(|tag| tag.0 == name).map(|tag| tag.1))
+    }
+
+    #[test]
+    fn tag_filters() {
+        let primary = [("highway", "primary"), ("hgv", "no")];
+        let motorway = [("highway", "motorway")];
+        let pattern = "highway~(motorway|trunk|primary)";
+        assert!(matches(pattern, &primary));
+        assert!(matches(pattern, &motorway));
+        assert!(!matches(pattern, &[("highway", "primary_link")]));
+        assert!(!matches(pattern, &[]));
+        assert!(!matches("highway!~motorway|trunk", &motorway));
+        assert!(matches("highway!~motorway|trunk", &[]));
+
+        assert!(!matches("hgv!=no", &primary));
+        assert!(matches("hgv!=no", &motorway));
+        assert!(matches("hgv=no", &primary));
+        assert!(matches("hgv", &primary));
+        assert!(matches("!hgv", &motorway));
+        assert!(matches("width=", &[("width", "")]));
+
+        assert!("=no".parse::<TagFilter>().is_err());
+        assert!("!".parse::<TagFilter>().is_err());
+        assert!("highway~(motorway".parse::<TagFilter>().is_err());
+    }
+}
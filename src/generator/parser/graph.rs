@@ -1,49 +1,85 @@
-//! This file implements the third step in the processes: loading the ways,
-//! detecting the road segments
+//! This file implements the third step in the processes: splitting the roads
+//! parsed with the junctions in segments
 
 use crate::generator::data_types::*;
+use crate::generator::progress::{Phase, PhaseCounter, Progress};
+use crate::generator::report::{GenerationWarning, WarningReason};
 use crossbeam;
+use std::io;
+use std::ops::Range;
 
-/// Build the roadmap graph
-pub fn parse_file<'a>(
-    file: &'a OSMClassifiedFile<'a>,
-    nodes: &'a Nodes,
-    junctions: &'a Junctions,
+/// How many roads are split by each task
+const WAYS_PER_TASK: usize = 8000;
+
+/// Build the roadmap graph. The arcs are first accumulated in temporary files, then sorted and
+/// merged out of memory. The problems found in the ways are pushed into `warnings`
+pub fn parse_file(
+    parsed: &[ParsedWays],
+    nodes: &Nodes,
+    junctions: &Junctions,
     num_threads: usize,
-) -> Graph {
-    if num_threads == 1 {
-        parse_file_sequential(file, nodes, junctions)
+    duplicates: DuplicateArcs,
+    warnings: &mut Vec<GenerationWarning>,
+    progress: &dyn Progress,
+) -> io::Result<Graph> {
+    let tasks = split_tasks(parsed);
+    let counter = PhaseCounter::new(progress, Phase::Graph, tasks.len());
+    let arcs = if num_threads == 1 {
+        parse_file_sequential(tasks, nodes, junctions, warnings, &counter)?
     } else {
-        parse_file_parallel(file, nodes, junctions, num_threads)
-    }
+        parse_file_parallel(tasks, nodes, junctions, num_threads, warnings, &counter)?
+    };
+    let node_infos = nodes
+        .points()
+        .zip(nodes.ids())
+        .map(|(&point, &id)| NodeInfo { point, id });
+    Ok(Graph::from_arcs(
+        nodes.len(),
+        node_infos,
+        arcs.into_sorted(duplicates),
+    ))
 }
 
-#[derive(Copy, Clone)]
-struct Arc {
-    from: NodeIndex,
-    to: NodeIndex,
-    road_level: u8,
-    distance: u32,
+/// A range of the roads parsed by one of the threads
+type Task<'a> = (&'a ParsedWays, Range<usize>);
+
+fn split_tasks(parsed: &[ParsedWays]) -> Vec<Task<'_>> {
+    parsed
+        .iter()
+        .flat_map(|ways| {
+            (0..ways.len())
+                .step_by(WAYS_PER_TASK)
+                .map(move |start| (ways, start..ways.len().min(start + WAYS_PER_TASK)))
+        })
+        .collect()
 }
 
-/// Parse the raw ways from a given compressed blob
-/// Handle each way that is a road, adding arcs into the graph.
+/// Handle each road of the task, adding arcs into the graph.
 /// First, the way will be split into segments. A segment is a sequence of nodes,
 /// with those at start and end are junction nodes and all the others are non-junctions.
-/// Then, the segment is defined as "blocked" if any of the nodes is a barrier.
+/// The barriers that vehicles may pass also end the segments, so that their edges can be penalized.
+/// Then, the segment is defined as "blocked" if any of the nodes is a blocking barrier.
 /// Finally, an unblocked segment will push new arcs to the graph. It can push up
 /// to two arcs if the way is both-ways.
-fn parse_ways<'a>(ways: &WaysBlob, nodes: &'a Nodes, junctions: &'a Junctions) -> Vec<Arc> {
+/// The ways with missing nodes and the segments that go nowhere are skipped with a warning.
+fn parse_ways(
+    task: Task,
+    nodes: &Nodes,
+    junctions: &Junctions,
+) -> (Vec<Arc>, Vec<GenerationWarning>) {
+    let (ways, range) = task;
     let mut arcs = Vec::new();
-    ways.for_each(|way| {
-        // Parse tags
-        let road_level = match super::parse_road_level(&way) {
-            None => return,
-            Some(x) => x,
+    let mut warnings = Vec::new();
+    for i in range {
+        let (way, mut it) = ways.get(i);
+        let warning = |reason| GenerationWarning {
+            way_id: way.way_id(),
+            reason,
         };
-        let direction = super::parse_oneway(&way);
-
-        let mut it = way.refs();
+        if let Some(node_id) = ways.get(i).1.find(|&id| nodes.node(id).is_none()) {
+            warnings.push(warning(WarningReason::MissingNode(node_id)));
+            continue;
+        }
 
         // Handle first node
         let node_id = it.next().unwrap();
@@ -51,77 +87,96 @@ fn parse_ways<'a>(ways: &WaysBlob, nodes: &'a Nodes, junctions: &'a Junctions) -
         let mut seg_start = node;
         let mut prev_node = node;
         let mut distance: f64 = 0.;
-        let mut blocked = node.barrier;
+        let mut blocked = node.barrier == Barrier::Blocking;
 
         // Handle the other nodes
         for node_id in it {
             let node = nodes.node(node_id).unwrap();
             distance += prev_node.point.haversine_distance(&node.point);
             prev_node = node;
-            blocked |= node.barrier;
+            blocked |= node.barrier == Barrier::Blocking;
 
-            if junctions.is_junction(node.id) {
-                if !blocked {
+            if junctions.is_junction(node.id) || node.barrier == Barrier::Passable {
+                if seg_start.id == node.id && distance == 0. {
+                    warnings.push(warning(WarningReason::ZeroLengthSegment(node.id)));
+                } else if !blocked {
                     // Commit segment
-                    if direction.direct {
+                    let distance = distance.round() as u32;
+                    if let Some(info) = way.direct {
                         arcs.push(Arc {
                             from: NodeIndex::new(seg_start.offset),
                             to: NodeIndex::new(node.offset),
-                            road_level,
-                            distance: distance.round() as u32,
+                            info: EdgeInfo {
+                                distance,
+                                barrier: seg_start.barrier == Barrier::Passable,
+                                name: ways.final_name(info.name),
+                                ..info
+                            },
                         });
                     }
-                    if direction.reverse {
+                    if let Some(info) = way.reverse {
                         arcs.push(Arc {
                             from: NodeIndex::new(node.offset),
                             to: NodeIndex::new(seg_start.offset),
-                            road_level,
-                            distance: distance.round() as u32,
+                            info: EdgeInfo {
+                                distance,
+                                barrier: node.barrier == Barrier::Passable,
+                                name: ways.final_name(info.name),
+                                ..info
+                            },
                         });
                     }
                 }
                 seg_start = node;
                 distance = 0.;
-                blocked = node.barrier;
+                blocked = node.barrier == Barrier::Blocking;
             }
         }
 
         // By definition, the last node is a junction, so the last segment will be commited
         assert_eq!(distance, 0.);
-    });
-    arcs
+    }
+    (arcs, warnings)
 }
 
-fn parse_file_sequential<'a>(
-    file: &'a OSMClassifiedFile<'a>,
-    nodes: &'a Nodes,
-    junctions: &'a Junctions,
-) -> Graph {
-    let mut graph = Graph::new(nodes);
-    for ways in &file.ways_blobs {
-        for arc in parse_ways(ways, nodes, junctions) {
-            graph.push_arc(arc.from, arc.to, arc.road_level, arc.distance);
+fn parse_file_sequential(
+    tasks: Vec<Task>,
+    nodes: &Nodes,
+    junctions: &Junctions,
+    warnings: &mut Vec<GenerationWarning>,
+    counter: &PhaseCounter,
+) -> io::Result<ArcAccumulator> {
+    let mut arcs = ArcAccumulator::new();
+    for task in tasks {
+        let (task_arcs, task_warnings) = parse_ways(task, nodes, junctions);
+        for arc in task_arcs {
+            arcs.push(arc)?;
         }
+        warnings.extend(task_warnings);
+        counter.inc();
     }
-    graph
+    Ok(arcs)
 }
 
-fn parse_file_parallel<'a>(
-    file: &'a OSMClassifiedFile<'a>,
-    nodes: &'a Nodes,
-    junctions: &'a Junctions,
+fn parse_file_parallel(
+    tasks: Vec<Task>,
+    nodes: &Nodes,
+    junctions: &Junctions,
     num_threads: usize,
-) -> Graph {
+    warnings: &mut Vec<GenerationWarning>,
+    counter: &PhaseCounter,
+) -> io::Result<ArcAccumulator> {
     crossbeam::scope(|scope| {
         // Create a work queue that will be filled once by this thread and will be
         // consumed by the worker ones.
-        let (task_sender, task_receiver) = crossbeam::bounded(file.ways_blobs.len());
-        for task in &file.ways_blobs {
+        let (task_sender, task_receiver) = crossbeam::bounded(tasks.len());
+        for task in tasks {
             task_sender.send(task).unwrap();
         }
         drop(task_sender);
 
-        // Create a return channel, that will be used to return the created nodes of each blob
+        // Create a return channel, that will be used to return the created arcs and the warnings
+        // of each task
         let (result_sender, result_receiver) = crossbeam::bounded(2 * num_threads);
 
         // Spawn the threads
@@ -130,23 +185,169 @@ fn parse_file_parallel<'a>(
             let task_receiver = task_receiver.clone();
             let result_sender = result_sender.clone();
             scope.spawn(move |_| {
-                for ways in task_receiver {
+                for task in task_receiver {
                     result_sender
-                        .send(parse_ways(ways, nodes, junctions))
+                        .send(parse_ways(task, nodes, junctions))
                         .unwrap();
                 }
             });
         }
         drop(result_sender);
 
-        // Consume the results and push then in order to the storage builder
-        let mut graph = Graph::new(nodes);
-        for arcs in result_receiver {
+        // Consume the results and push then to the accumulator
+        let mut accumulator = ArcAccumulator::new();
+        for (arcs, task_warnings) in result_receiver {
             for arc in arcs {
-                graph.push_arc(arc.from, arc.to, arc.road_level, arc.distance);
+                accumulator.push(arc)?;
             }
+            warnings.extend(task_warnings);
+            counter.inc();
         }
-        graph
+        Ok(accumulator)
     })
     .unwrap()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::generator::parser::{parse_barrier, TravelMode, CYCLE_LEVEL};
+    use crate::GeoPoint;
+
+    #[test]
+    fn skip_broken_ways() {
+        let mut nodes = NodesBuilder::new();
+        for id in 1..=3 {
+            nodes.push(OSMNode {
+                point: GeoPoint::from_degrees(42.5, 1.5 + id as f64 / 1000.),
+                ..OSMNode::with_id(id)
+            });
+        }
+        nodes.finish_block();
+        let nodes = Nodes::from_builders(vec![nodes]);
+
+        // The node 9 is not in the file and the node 3 is repeated
+        let mut junctions = JunctionsBuilder::new();
+        let mut parsed = ParsedWays::new();
+        for (way_id, refs) in [(10, vec![1, 2]), (11, vec![2, 9, 3]), (12, vec![3, 3, 1])] {
+            for (i, &id) in refs.iter().enumerate() {
+                if i == 0 || i == refs.len() - 1 {
+                    junctions.handle_junction(id);
+                } else {
+                    junctions.handle_internal(id);
+                }
+            }
+            let info = EdgeInfo {
+                way_id,
+                ..EdgeInfo::from(&crate::EdgeInfo::new(0, 5))
+            };
+            parsed.push(Some(info), None, refs.into_iter()).unwrap();
+        }
+        junctions.sort();
+        let junctions = Junctions::from_builders(vec![junctions]);
+
+        let (arcs, warnings) = parse_ways((&parsed, 0..3), &nodes, &junctions);
+        let arcs: Vec<_> = arcs
+            .iter()
+            .map(|arc| (arc.info.way_id, arc.from.index(), arc.to.index()))
+            .collect();
+        assert_eq!(arcs, vec![(10, 0, 1), (12, 2, 0)]);
+        assert_eq!(
+            warnings,
+            vec![
+                GenerationWarning {
+                    way_id: 11,
+                    reason: WarningReason::MissingNode(9),
+                },
+                GenerationWarning {
+                    way_id: 12,
+                    reason: WarningReason::ZeroLengthSegment(3),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn split_at_passable_barriers() {
+        let mut nodes = NodesBuilder::new();
+        for (id, barrier) in [
+            (1, Barrier::None),
+            (2, Barrier::Passable),
+            (3, Barrier::Blocking),
+            (4, Barrier::None),
+        ] {
+            nodes.push(OSMNode {
+                point: GeoPoint::from_degrees(42.5, 1.5 + id as f64 / 1000.),
+                barrier,
+                ..OSMNode::with_id(id)
+            });
+        }
+        nodes.finish_block();
+        let nodes = Nodes::from_builders(vec![nodes]);
+        assert_eq!((nodes.barrier_len(), nodes.passable_barrier_len()), (1, 1));
+
+        // The gate 2 splits the first way, the bollard 3 blocks the second one
+        let mut junctions = JunctionsBuilder::new();
+        let mut parsed = ParsedWays::new();
+        for (way_id, refs) in [(10, [1, 2, 4]), (11, [1, 3, 4])] {
+            junctions.handle_junction(refs[0]);
+            junctions.handle_internal(refs[1]);
+            junctions.handle_junction(refs[2]);
+            let info = EdgeInfo {
+                way_id,
+                ..EdgeInfo::from(&crate::EdgeInfo::new(0, 5))
+            };
+            parsed
+                .push(Some(info), Some(info), refs.iter().copied())
+                .unwrap();
+        }
+        junctions.sort();
+        let junctions = Junctions::from_builders(vec![junctions]);
+
+        let (arcs, warnings) = parse_ways((&parsed, 0..2), &nodes, &junctions);
+        let arcs: Vec<_> = arcs
+            .iter()
+            .map(|arc| (arc.from.index(), arc.to.index(), arc.info.barrier))
+            .collect();
+        assert_eq!(
+            arcs,
+            vec![(0, 1, false), (1, 0, true), (1, 3, true), (3, 1, false)]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn bollard_on_cycleway() {
+        // The untagged bollard blocks the cars, but not the bicycles
+        let arcs = |mode| {
+            let bollard = parse_barrier([("barrier", "bollard")].iter().copied(), mode);
+            let mut nodes = NodesBuilder::new();
+            for (id, barrier) in [(1, Barrier::None), (2, bollard), (3, Barrier::None)] {
+                nodes.push(OSMNode {
+                    point: GeoPoint::from_degrees(42.5, 1.5 + id as f64 / 1000.),
+                    barrier,
+                    ..OSMNode::with_id(id)
+                });
+            }
+            nodes.finish_block();
+            let nodes = Nodes::from_builders(vec![nodes]);
+
+            let mut junctions = JunctionsBuilder::new();
+            junctions.handle_junction(1);
+            junctions.handle_internal(2);
+            junctions.handle_junction(3);
+            junctions.sort();
+            let junctions = Junctions::from_builders(vec![junctions]);
+            let mut parsed = ParsedWays::new();
+            let info = EdgeInfo::from(&crate::EdgeInfo::new(0, CYCLE_LEVEL));
+            parsed
+                .push(Some(info), Some(info), [1, 2, 3].iter().copied())
+                .unwrap();
+
+            let (arcs, _) = parse_ways((&parsed, 0..1), &nodes, &junctions);
+            arcs.len()
+        };
+        assert_eq!(arcs(TravelMode::Car), 0);
+        assert_eq!(arcs(TravelMode::Bicycle), 4);
+    }
+}
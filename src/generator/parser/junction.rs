@@ -1,27 +1,68 @@
 //! This file implements the second step in the processes: loading the ways,
-//! detecting which nodes are junctions and creating the junction data structure
+//! detecting which nodes are junctions and creating the junction data structure.
+//! The roads are kept already parsed, so that the ways blobs are only decoded once
 
+use super::relation::RouteWays;
+use super::Profile;
 use crate::generator::data_types::*;
+use crate::generator::progress::{Phase, PhaseCounter, Progress};
+use crate::generator::report::{GenerationWarning, WarningReason};
 use crossbeam;
+use std::io;
 
 /// Extract the nodes from a list of file, sequentially.
-/// Returns the junctions storage
-pub fn parse_file<'a>(file: &'a OSMClassifiedFile<'a>, num_threads: usize) -> (Junctions, usize) {
+/// Returns the junctions storage, the parsed roads, in one storage per thread, and the problems
+/// found in their tags. The arcs of the ways in `routes` are flagged
+pub fn parse_file<'a>(
+    file: &'a OSMClassifiedFile<'a>,
+    num_threads: usize,
+    profile: &Profile,
+    routes: &RouteWays,
+    progress: &dyn Progress,
+) -> io::Result<(Junctions, Vec<ParsedWays>, Vec<GenerationWarning>)> {
+    let counter = PhaseCounter::new(progress, Phase::Junctions, file.ways_blobs.len());
     if num_threads == 1 {
-        parse_file_sequential(file)
+        parse_file_sequential(file, profile, routes, &counter)
     } else {
-        parse_file_parallel(file, num_threads)
+        parse_file_parallel(file, num_threads, profile, routes, &counter)
     }
 }
 
 /// Parse the raw ways from a given compressed blob
-fn parse_ways(ways: &WaysBlob, builder: &mut JunctionsBuilder) -> usize {
-    let mut num_ways = 0;
+fn parse_ways(
+    ways: &WaysBlob,
+    builder: &mut JunctionsBuilder,
+    parsed: &mut ParsedWays,
+    warnings: &mut Vec<GenerationWarning>,
+    profile: &Profile,
+    routes: &RouteWays,
+) -> io::Result<()> {
+    let mut result = Ok(());
     ways.for_each(|way| {
+        if result.is_err() {
+            return;
+        }
+
         // Only consider ways that are "roads"
-        if super::parse_road_level(&way).is_some() {
+        if let Some(road_level) = super::parse_road_level(&way, profile) {
             let node_ids = way.refs();
             let len = node_ids.len();
+            let mut warn = |reason| {
+                warnings.push(GenerationWarning {
+                    way_id: way.id(),
+                    reason,
+                })
+            };
+            if len < 2 {
+                warn(WarningReason::TooFewNodes);
+                return;
+            }
+            for (key, value) in super::parse_bad_tags(&way) {
+                warn(WarningReason::BadTagValue {
+                    key: key.to_owned(),
+                    value: value.to_owned(),
+                });
+            }
 
             for (i, id) in node_ids.enumerate() {
                 if i == 0 || i == len - 1 {
@@ -30,26 +71,56 @@ fn parse_ways(ways: &WaysBlob, builder: &mut JunctionsBuilder) -> usize {
                     builder.handle_internal(id);
                 }
             }
-            num_ways += 1;
+
+            let (mut direct, mut reverse) = super::parse_arcs_info(&way, road_level, profile);
+            let name = super::parse_name(&way).map(|name| parsed.name_index(name));
+            for info in direct.iter_mut().chain(reverse.iter_mut()) {
+                if let Some(name) = name {
+                    info.name = name;
+                }
+                routes.mark(way.id(), info);
+            }
+            result = parsed.push(direct, reverse, way.refs());
         }
     });
-    num_ways
+    result
 }
 
-fn parse_file_sequential<'a>(file: &'a OSMClassifiedFile<'a>) -> (Junctions, usize) {
-    let mut num_ways = 0;
+fn parse_file_sequential<'a>(
+    file: &'a OSMClassifiedFile<'a>,
+    profile: &Profile,
+    routes: &RouteWays,
+    counter: &PhaseCounter,
+) -> io::Result<(Junctions, Vec<ParsedWays>, Vec<GenerationWarning>)> {
     let mut builder = JunctionsBuilder::new();
+    let mut parsed = ParsedWays::new();
+    let mut warnings = Vec::new();
     for ways in &file.ways_blobs {
-        num_ways += parse_ways(ways, &mut builder);
+        parse_ways(
+            ways,
+            &mut builder,
+            &mut parsed,
+            &mut warnings,
+            profile,
+            routes,
+        )?;
+        counter.inc();
     }
     builder.sort();
-    (Junctions::from_builders(vec![builder]), num_ways)
+    Ok((
+        Junctions::from_builders(vec![builder]),
+        vec![parsed],
+        warnings,
+    ))
 }
 
 fn parse_file_parallel<'a>(
     file: &'a OSMClassifiedFile<'a>,
     num_threads: usize,
-) -> (Junctions, usize) {
+    profile: &Profile,
+    routes: &RouteWays,
+    counter: &PhaseCounter,
+) -> io::Result<(Junctions, Vec<ParsedWays>, Vec<GenerationWarning>)> {
     // Create a work queue that will be filled once by this thread and will be
     // consumed by the worker ones.
     let (task_sender, task_receiver) = crossbeam::bounded(file.ways_blobs.len());
@@ -64,28 +135,39 @@ fn parse_file_parallel<'a>(
         for _ in 0..num_threads {
             // Create the channel endpoints for this thread
             let task_receiver = task_receiver.clone();
-            let thread = scope.spawn(move |_| {
+            let thread = scope.spawn(move |_| -> io::Result<_> {
                 let mut builder = JunctionsBuilder::new();
-                let mut num_ways = 0;
+                let mut parsed = ParsedWays::new();
+                let mut warnings = Vec::new();
                 for ways in task_receiver {
-                    num_ways += parse_ways(ways, &mut builder);
+                    parse_ways(
+                        ways,
+                        &mut builder,
+                        &mut parsed,
+                        &mut warnings,
+                        profile,
+                        routes,
+                    )?;
+                    counter.inc();
                 }
                 builder.sort();
-                (builder, num_ways)
+                Ok((builder, parsed, warnings))
             });
             threads.push(thread);
         }
 
         // Collect all results
         let mut builders = Vec::new();
-        let mut total_num_ways = 0;
+        let mut all_parsed = Vec::new();
+        let mut all_warnings = Vec::new();
         for thread in threads {
-            let (builder, num_ways) = thread.join().unwrap();
+            let (builder, parsed, warnings) = thread.join().unwrap()?;
             builders.push(builder);
-            total_num_ways += num_ways;
+            all_parsed.push(parsed);
+            all_warnings.extend(warnings);
         }
 
-        (Junctions::from_builders(builders), total_num_ways)
+        Ok((Junctions::from_builders(builders), all_parsed, all_warnings))
     })
     .unwrap()
 }
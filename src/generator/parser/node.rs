@@ -1,22 +1,28 @@
+use super::{Profile, TravelMode};
 use crate::generator::data_types::*;
-use crate::utils::GeoPoint;
+use crate::generator::progress::{Phase, PhaseCounter, Progress};
+use crate::GeoPoint;
 use crossbeam;
 
 pub fn parse_file<'a>(
     file: &'a OSMClassifiedFile<'a>,
     junctions: &Junctions,
     num_threads: usize,
+    profile: &Profile,
+    progress: &dyn Progress,
 ) -> Nodes {
+    let counter = PhaseCounter::new(progress, Phase::Nodes, file.nodes_blobs.len());
     if num_threads == 1 {
-        parse_file_sequential(file, junctions)
+        parse_file_sequential(file, junctions, profile.mode, &counter)
     } else {
-        parse_file_parallel(file, junctions, num_threads)
+        parse_file_parallel(file, junctions, num_threads, profile.mode, &counter)
     }
 }
 
 fn parse_nodes<'a>(
     nodes_blob: &'a NodesBlob<'a>,
     junctions: &Junctions,
+    mode: TravelMode,
     builder: &mut NodesBuilder,
 ) {
     nodes_blob.for_each(|dense_node| {
@@ -25,18 +31,24 @@ fn parse_nodes<'a>(
                 id: dense_node.id,
                 offset: 0,
                 point: GeoPoint::from_degrees(dense_node.lat(), dense_node.lon()),
-                barrier: super::parse_barrier(dense_node.tags()),
+                barrier: super::parse_barrier(dense_node.tags(), mode),
             });
         }
     });
     builder.finish_block();
 }
 
-fn parse_file_sequential<'a>(file: &'a OSMClassifiedFile<'a>, junctions: &Junctions) -> Nodes {
+fn parse_file_sequential<'a>(
+    file: &'a OSMClassifiedFile<'a>,
+    junctions: &Junctions,
+    mode: TravelMode,
+    counter: &PhaseCounter,
+) -> Nodes {
     let mut builder = NodesBuilder::new();
 
     for nodes_blob in &file.nodes_blobs {
-        parse_nodes(nodes_blob, junctions, &mut builder);
+        parse_nodes(nodes_blob, junctions, mode, &mut builder);
+        counter.inc();
     }
 
     Nodes::from_builders(vec![builder])
@@ -46,6 +58,8 @@ fn parse_file_parallel<'a>(
     file: &'a OSMClassifiedFile<'a>,
     junctions: &Junctions,
     num_threads: usize,
+    mode: TravelMode,
+    counter: &PhaseCounter,
 ) -> Nodes {
     crossbeam::scope(|scope| {
         let (task_sender, task_receiver) = crossbeam::bounded(file.nodes_blobs.len());
@@ -62,7 +76,8 @@ fn parse_file_parallel<'a>(
             threads.push(scope.spawn(move |_| {
                 let mut builder = NodesBuilder::new();
                 for nodes_blob in task_receiver {
-                    parse_nodes(nodes_blob, junctions, &mut builder);
+                    parse_nodes(nodes_blob, junctions, mode, &mut builder);
+                    counter.inc();
                 }
                 builder
             }));
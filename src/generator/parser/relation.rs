@@ -0,0 +1,60 @@
+//! This file implements the step before detecting the junctions: loading the route relations, so
+//! that the arcs of their ways are flagged when the ways are parsed
+
+use super::RouteKind;
+use crate::generator::data_types::*;
+use crate::generator::progress::{Phase, PhaseCounter, Progress};
+use osmpbf::RelMemberType;
+use std::collections::HashSet;
+
+/// The ways that are part of the signed routes, by their OSM id
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RouteWays {
+    bicycle: HashSet<i64>,
+    bus: HashSet<i64>,
+}
+
+impl RouteWays {
+    /// The number of ways that are part of a cycle route and of a bus route
+    pub fn stats(&self) -> (usize, usize) {
+        (self.bicycle.len(), self.bus.len())
+    }
+
+    /// Flag the info of an arc with the routes that its way is part of
+    pub fn mark(&self, way_id: i64, info: &mut EdgeInfo) {
+        info.bicycle_route = self.bicycle.contains(&way_id);
+        info.bus_route = self.bus.contains(&way_id);
+    }
+
+    fn insert(&mut self, kind: RouteKind, way_id: i64) {
+        match kind {
+            RouteKind::Bicycle => self.bicycle.insert(way_id),
+            RouteKind::Bus => self.bus.insert(way_id),
+        };
+    }
+}
+
+/// Collect the member ways of the route relations, sequentially, since there are far fewer
+/// relations than ways. The stops and platforms of the public transport routes are not part of the
+/// itinerary, so they are skipped
+pub fn parse_file<'a>(file: &'a OSMClassifiedFile<'a>, progress: &dyn Progress) -> RouteWays {
+    let counter = PhaseCounter::new(progress, Phase::Relations, file.relations_blobs.len());
+    let mut routes = RouteWays::default();
+    for relations in &file.relations_blobs {
+        relations.for_each(|relation| {
+            if let Some(kind) = super::parse_route(relation.tags()) {
+                for member in relation.members() {
+                    let role = member.role().unwrap_or("");
+                    if member.member_type == RelMemberType::Way
+                        && !role.starts_with("platform")
+                        && !role.starts_with("stop")
+                    {
+                        routes.insert(kind, member.member_id);
+                    }
+                }
+            }
+        });
+        counter.inc();
+    }
+    routes
+}
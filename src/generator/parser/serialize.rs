@@ -1,103 +1,68 @@
 use crate::generator::data_types::*;
-use byteorder::{LittleEndian, WriteBytesExt};
-use crossbeam;
-use flate2::write::GzEncoder;
-use flate2::Compression;
-use petgraph::visit::{EdgeRef, IntoNodeReferences};
+use crate::{Metadata, RoadLevelStyle, RoadNames};
 use std::fs::File;
 use std::io;
-use std::io::Write;
+use std::io::BufWriter;
 use std::path::Path;
 
-/// Write the final cartography graph to disk
-pub fn serialize<P: AsRef<Path>>(graph: &Graph, file_path: P) -> io::Result<()> {
-    // Open file
-    let mut writer = File::create(&file_path)?;
-
-    // Write headers
-    writer.write_all(b"PTOLEMY-v2")?;
-    writer.write_u32::<LittleEndian>(graph.node_len() as u32)?;
-    writer.write_u32::<LittleEndian>(graph.edge_len() as u32)?;
-
-    // Extract nodes and sort by (lat, lon)
-    // This code uses delta encoding, so we use i32 instead of u32, even though
-    // the original data is guaranteed to be non-negative
-    struct Node {
-        index: i32,
-        lat: i32,
-        lon: i32,
-    }
-    let mut nodes: Vec<Node> = graph
-        .graph
-        .node_references()
-        .map(|(index, info)| Node {
-            index: index.index() as i32,
-            lat: info.point.lat.as_micro_degrees(),
-            lon: info.point.lon.as_micro_degrees(),
-        })
-        .collect();
-    nodes.sort_by_key(|node| (node.lat, node.lon));
-
-    // Extract remap of node indexes:
-    // node_index_map[old_index] = new_index
-    let mut node_index_map = vec![std::i32::MAX; graph.node_len()];
-    for (i, node) in nodes.iter().enumerate() {
-        node_index_map[node.index as usize] = i as i32;
-    }
-
-    // Extract edges and sort by (source, target)
-    struct Edge {
-        source: i32,
-        target: i32,
-        distance: i32,
-        road_level: i32,
-    }
-    let mut edges: Vec<Edge> = graph
-        .graph
-        .edge_references()
-        .map(|edge| Edge {
-            source: node_index_map[edge.source().index()],
-            target: node_index_map[edge.target().index()],
-            distance: edge.weight().distance as i32,
-            road_level: edge.weight().road_level as i32,
-        })
-        .collect();
-    edges.sort_by_key(|edge| (edge.source, edge.target));
-
-    crossbeam::scope(|scope| {
-        let nodes_ref = &nodes;
-        let edges_ref = &edges;
-
-        // Compress all columns in parallel
-        let threads = vec![
-            scope.spawn(move |_| compress(nodes_ref.iter().map(|node| node.lat))),
-            scope.spawn(move |_| compress(nodes_ref.iter().map(|node| node.lat))),
-            scope.spawn(move |_| compress(edges_ref.iter().map(|edge| edge.source))),
-            scope.spawn(move |_| compress(edges_ref.iter().map(|edge| edge.target))),
-            scope.spawn(move |_| compress(edges_ref.iter().map(|edge| edge.distance))),
-            scope.spawn(move |_| compress(edges_ref.iter().map(|edge| edge.road_level))),
-        ];
-
-        // But write them sequentially
-        for thread in threads {
-            let column = thread.join().unwrap();
-            writer.write_u64::<LittleEndian>(column.len() as u64)?;
-            writer.write_all(column.as_ref())?;
-        }
-        Ok(())
-    })
-    .unwrap()
+/// Write the final cartography graph to disk, with the classification of its road levels, the names
+/// of its roads, the shapes of its contracted edges and the metadata. The OSM ids of the nodes and ways are only written with `osm_ids`
+/// and the Web Mercator coordinates of the nodes with `projected_nodes`
+pub fn serialize<P: AsRef<Path>>(
+    graph: &Graph,
+    road_levels: &[RoadLevelStyle],
+    road_names: &RoadNames,
+    metadata: &Metadata,
+    osm_ids: bool,
+    projected_nodes: bool,
+    file_path: P,
+) -> io::Result<()> {
+    let node_ids: Option<Vec<i64>> = if osm_ids {
+        Some(
+            graph
+                .graph
+                .raw_nodes()
+                .iter()
+                .map(|node| node.weight.id)
+                .collect(),
+        )
+    } else {
+        None
+    };
+    crate::write_graph(
+        BufWriter::new(File::create(&file_path)?),
+        &graph.graph,
+        |node| node.point,
+        |edge| crate::EdgeInfo {
+            way_id: if osm_ids { edge.way_id } else { 0 },
+            ..edge.into()
+        },
+        node_ids.as_deref(),
+        road_levels,
+        road_names,
+        &graph.shapes,
+        metadata,
+        projected_nodes,
+    )
 }
 
-/// Compress an iterator of i32 using delta encoding + gzip
-fn compress(mut values: impl Iterator<Item = i32>) -> Vec<u8> {
-    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-    let mut prev = values.next().unwrap();
-    encoder.write_i32::<LittleEndian>(prev).unwrap();
-    for value in values {
-        let delta = value - prev;
-        prev = value;
-        encoder.write_i32::<LittleEndian>(delta).unwrap();
+/// The bounding box of the nodes of the graph, as `min_lon,min_lat,max_lon,max_lat` in degrees, like
+/// `Metadata::BBOX`
+pub fn bbox(graph: &Graph) -> String {
+    if graph.node_len() == 0 {
+        return String::new();
+    }
+    let (mut min_lon, mut min_lat) = (f64::INFINITY, f64::INFINITY);
+    let (mut max_lon, mut max_lat) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for node in graph.graph.raw_nodes() {
+        let (lat, lon) = (node.weight.point.lat, node.weight.point.lon);
+        min_lon = min_lon.min(lon.as_degrees());
+        min_lat = min_lat.min(lat.as_degrees());
+        max_lon = max_lon.max(lon.as_degrees());
+        max_lat = max_lat.max(lat.as_degrees());
     }
-    encoder.finish().unwrap()
+    format!(
+        "{:.6},{:.6},{:.6},{:.6}",
+        min_lon, min_lat, max_lon, max_lat
+    )
 }
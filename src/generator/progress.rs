@@ -0,0 +1,143 @@
+//! Report the progress inside the long phases of the generation, that process the blobs of the
+//! OSM file one by one
+
+use indicatif::{ProgressBar, ProgressStyle};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A phase of the generation that goes through the blobs of the file, or the roads parsed from them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading the route relations to know which ways are part of them
+    Relations,
+    /// Reading the ways to detect the junctions and parse the roads
+    Junctions,
+    /// Reading the nodes that are used by the roads
+    Nodes,
+    /// Splitting the parsed roads to create the edges of the graph
+    Graph,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Relations => write!(f, "Loading routes"),
+            Phase::Junctions => write!(f, "Detecting junctions"),
+            Phase::Nodes => write!(f, "Loading nodes"),
+            Phase::Graph => write!(f, "Creating graph"),
+        }
+    }
+}
+
+/// Receive the progress of the generation. It is called from the worker threads, so it must be
+/// cheap and thread-safe
+pub trait Progress: Sync {
+    /// `processed` out of `total` blobs of `phase` were handled. It is first called with zero
+    /// processed blobs and lastly with all of them
+    fn update(&self, phase: Phase, processed: usize, total: usize);
+}
+
+/// Ignore the progress
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn update(&self, _phase: Phase, _processed: usize, _total: usize) {}
+}
+
+/// Display a progress bar in the terminal for each phase, erased when the phase is done
+#[derive(Default)]
+pub struct ProgressBars {
+    /// The current phase and its bar, that is `None` once the phase is done. The updates from
+    /// different threads can arrive out of order, so the late ones are ignored
+    current: Mutex<Option<(Phase, Option<ProgressBar>)>>,
+}
+
+impl Progress for ProgressBars {
+    fn update(&self, phase: Phase, processed: usize, total: usize) {
+        let mut current = self.current.lock().unwrap();
+        match &*current {
+            Some((current_phase, _)) if *current_phase == phase => {}
+            _ => {
+                let bar = ProgressBar::new(total as u64);
+                bar.set_style(
+                    ProgressStyle::default_bar()
+                        .template("{msg} [{bar:40}] {pos}/{len} blobs ({eta})")
+                        .progress_chars("=> "),
+                );
+                bar.set_message(&phase.to_string());
+                *current = Some((phase, Some(bar)));
+            }
+        }
+
+        let (_, bar) = current.as_mut().unwrap();
+        if let Some(progress_bar) = bar {
+            if processed as u64 > progress_bar.position() {
+                progress_bar.set_position(processed as u64);
+            }
+            if processed == total {
+                progress_bar.finish_and_clear();
+                *bar = None;
+            }
+        }
+    }
+}
+
+/// Count the blobs processed in a phase, from any thread
+pub struct PhaseCounter<'a> {
+    progress: &'a dyn Progress,
+    phase: Phase,
+    total: usize,
+    processed: AtomicUsize,
+}
+
+impl<'a> PhaseCounter<'a> {
+    pub fn new(progress: &'a dyn Progress, phase: Phase, total: usize) -> Self {
+        progress.update(phase, 0, total);
+        PhaseCounter {
+            progress,
+            phase,
+            total,
+            processed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Report that one more blob was processed
+    pub fn inc(&self) {
+        let processed = self.processed.fetch_add(1, Ordering::Relaxed) + 1;
+        self.progress.update(self.phase, processed, self.total);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Recorder(Mutex<Vec<(Phase, usize, usize)>>);
+
+    impl Progress for Recorder {
+        fn update(&self, phase: Phase, processed: usize, total: usize) {
+            self.0.lock().unwrap().push((phase, processed, total));
+        }
+    }
+
+    #[test]
+    fn phase_counter() {
+        let recorder = Recorder(Mutex::new(Vec::new()));
+        let counter = PhaseCounter::new(&recorder, Phase::Nodes, 3);
+        crossbeam::scope(|scope| {
+            for _ in 0..3 {
+                scope.spawn(|_| counter.inc());
+            }
+        })
+        .unwrap();
+
+        let mut updates = recorder.0.into_inner().unwrap();
+        assert_eq!(updates[0], (Phase::Nodes, 0, 3));
+        updates.sort_by_key(|update| update.1);
+        assert_eq!(
+            updates,
+            (0..=3).map(|i| (Phase::Nodes, i, 3)).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use serde_json::json;
+use std::fmt;
+use std::fs;
+use std::time::Duration;
+
+/// What happened during `generate()`: the size of the data at each step, how long each phase took
+/// and the problems found in the input
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GenerationReport {
+    /// The size of the input file
+    pub input_bytes: u64,
+    /// The number of ways that are roads
+    pub ways: usize,
+    /// The number of different names of the roads, from their `ref` or `name`
+    pub road_names: usize,
+    /// The nodes of the roads where they cross or end
+    pub junctions: usize,
+    /// The nodes of the roads that are not junctions
+    pub internal_nodes: usize,
+    /// The nodes of the roads with a barrier that blocks vehicles, like bollards
+    pub barriers: usize,
+    /// The nodes of the roads with a barrier that vehicles may pass, like gates with `access=yes`
+    pub passable_barriers: usize,
+    /// The number of nodes that were removed because they are unreachable or have no edge
+    pub pruned_nodes: usize,
+    /// The number of edges that were removed with the pruned nodes
+    pub pruned_edges: usize,
+    /// The number of edges invented to make the graph strongly connected
+    pub synthetic_edges: usize,
+    /// The number of nodes that only connected two others and were replaced by the shapes of
+    /// their edges
+    pub contracted_nodes: usize,
+    /// The number of edges that cross the borders given with `GenerateOptions::borders`
+    pub border_edges: usize,
+    /// The number of strongly connected components of the final graph
+    pub components: usize,
+    /// The number of nodes of the final graph
+    pub nodes: usize,
+    /// The number of edges of the final graph
+    pub edges: usize,
+    /// The size of the output file
+    pub output_bytes: u64,
+    /// The peak resident memory of the process, in bytes, when the system tells it (only on Linux)
+    pub peak_memory: Option<u64>,
+    /// Problems found in the input that did not stop the generation, sorted by way
+    pub warnings: Vec<GenerationWarning>,
+    /// Reading and classifying the blobs of the input file
+    pub read: Duration,
+    /// Detecting the nodes used by the roads and the junctions
+    pub junctions_parse: Duration,
+    /// Loading the coordinates and tags of the nodes
+    pub nodes_parse: Duration,
+    /// Splitting the ways in edges and building the graph
+    pub graph_build: Duration,
+    /// Removing the unreachable nodes
+    pub prune: Duration,
+    /// Sorting the edges, inventing the synthetic ones, contracting the chains and flagging the
+    /// borders
+    pub connect: Duration,
+    /// Writing the output file
+    pub serialize: Duration,
+}
+
+impl GenerationReport {
+    pub fn total(&self) -> Duration {
+        self.read
+            + self.junctions_parse
+            + self.nodes_parse
+            + self.graph_build
+            + self.prune
+            + self.connect
+            + self.serialize
+    }
+
+    /// The name and duration of each phase, in the order they run
+    pub fn phases(&self) -> [(&'static str, Duration); 7] {
+        [
+            ("read", self.read),
+            ("junctions parse", self.junctions_parse),
+            ("nodes parse", self.nodes_parse),
+            ("graph build", self.graph_build),
+            ("prune", self.prune),
+            ("connect", self.connect),
+            ("serialize", self.serialize),
+        ]
+    }
+
+    /// The report as JSON, for the pipelines that track the generation over time, like
+    /// `{"nodes": 1234, ..., "durations": {"read": 0.52, ...}, "total_duration": 3.1}`. The
+    /// durations are in seconds and the warnings are only counted
+    pub fn to_json(&self) -> serde_json::Value {
+        let durations: serde_json::Map<_, _> = self
+            .phases()
+            .iter()
+            .map(|(name, duration)| (name.replace(' ', "_"), json!(duration.as_secs_f64())))
+            .collect();
+        json!({
+            "input_bytes": self.input_bytes,
+            "ways": self.ways,
+            "road_names": self.road_names,
+            "junctions": self.junctions,
+            "internal_nodes": self.internal_nodes,
+            "barriers": self.barriers,
+            "passable_barriers": self.passable_barriers,
+            "pruned_nodes": self.pruned_nodes,
+            "pruned_edges": self.pruned_edges,
+            "synthetic_edges": self.synthetic_edges,
+            "contracted_nodes": self.contracted_nodes,
+            "border_edges": self.border_edges,
+            "components": self.components,
+            "nodes": self.nodes,
+            "edges": self.edges,
+            "output_bytes": self.output_bytes,
+            "peak_memory_bytes": self.peak_memory,
+            "warnings": self.warnings.len(),
+            "durations": durations,
+            "total_duration": self.total().as_secs_f64(),
+        })
+    }
+}
+
+/// The peak resident memory of the process until now, in bytes, from `VmHWM` in
+/// `/proc/self/status`. `None` on the systems without it
+pub fn peak_memory() -> Option<u64> {
+    parse_peak_memory(&fs::read_to_string("/proc/self/status").ok()?)
+}
+
+/// Parse the line like `VmHWM:    12345 kB`
+fn parse_peak_memory(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line["VmHWM:".len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// A problem found in a way of the input file
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GenerationWarning {
+    /// The OSM id of the way
+    pub way_id: i64,
+    pub reason: WarningReason,
+}
+
+/// Why a way, or part of it, was skipped
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WarningReason {
+    /// The way has less than two nodes. It is skipped
+    TooFewNodes,
+    /// The way references a node that is not in the file, with this id. It is skipped
+    MissingNode(i64),
+    /// A segment of the way starts and ends at this node without going anywhere, like when the
+    /// node is repeated. The segment is skipped
+    ZeroLengthSegment(i64),
+    /// A tag has a value that can not be parsed, like `maxweight=heavy`. The tag is ignored
+    BadTagValue { key: String, value: String },
+}
+
+impl fmt::Display for GenerationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "way {}: ", self.way_id)?;
+        match &self.reason {
+            WarningReason::TooFewNodes => write!(f, "less than two nodes"),
+            WarningReason::MissingNode(id) => write!(f, "missing node {}", id),
+            WarningReason::ZeroLengthSegment(id) => write!(f, "zero-length segment at node {}", id),
+            WarningReason::BadTagValue { key, value } => {
+                write!(f, "invalid value {:?} for the tag {}", value, key)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json() {
+        let report = GenerationReport {
+            nodes: 12,
+            output_bytes: 3456,
+            peak_memory: Some(1 << 20),
+            read: Duration::from_millis(1500),
+            serialize: Duration::from_millis(250),
+            ..GenerationReport::default()
+        };
+        let json = report.to_json();
+        assert_eq!(json["nodes"], 12);
+        assert_eq!(json["output_bytes"], 3456);
+        assert_eq!(json["peak_memory_bytes"], 1 << 20);
+        assert_eq!(json["warnings"], 0);
+        assert_eq!(json["durations"]["read"], 1.5);
+        assert_eq!(json["durations"]["junctions_parse"], 0.);
+        assert_eq!(json["total_duration"], 1.75);
+        assert!(GenerationReport::default().to_json()["peak_memory_bytes"].is_null());
+
+        let status =
+            "Name:\tptolemy\nVmPeak:\t  204800 kB\nVmHWM:\t    2048 kB\nVmRSS:\t    1024 kB\n";
+        assert_eq!(parse_peak_memory(status), Some(2048 * 1024));
+        assert_eq!(parse_peak_memory("Name:\tptolemy\n"), None);
+    }
+}
@@ -1,4 +1,10 @@
 mod cartograph;
+pub mod crs;
+/// Generate the cartography files from OpenStreetMap data. Not available in wasm32
+#[cfg(not(target_arch = "wasm32"))]
+pub mod generator;
+pub mod sampling;
+pub mod trace;
 mod utils;
 
 pub use cartograph::*;
@@ -0,0 +1,56 @@
+//! Send the log messages of all the commands, including the ones of the library and of the API,
+//! to the standard error
+
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use tracing_subscriber::EnvFilter;
+
+/// How log messages are written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// A line with the time, the level, the spans and the message, colored in a terminal
+    Text,
+    /// A JSON object by line, with the fields of the message and of its spans, like the `id`, `method`
+    /// and `path` of the API requests
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Invalid log format {}, expected text or json", s)),
+        }
+    }
+}
+
+/// Install the global logger, keeping the messages selected by `filter`, like `info` or
+/// `warn,ptolemy=debug`
+pub fn init(filter: EnvFilter, format: LogFormat) -> io::Result<()> {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+    let result = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+    result.map_err(|error| io::Error::other(error.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("text".parse(), Ok(LogFormat::Text));
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert!("xml".parse::<LogFormat>().is_err());
+        assert!("warn,ptolemy=debug".parse::<EnvFilter>().is_ok());
+        assert!("ptolemy=loud".parse::<EnvFilter>().is_err());
+    }
+}
@@ -1,12 +1,46 @@
 mod api;
-mod cartograph;
-mod generator;
-mod utils;
+mod assigner;
+mod exit;
+mod logging;
+mod matcher;
+mod matrix;
+mod precompute;
+mod router;
 
-use std::path::PathBuf;
+use exit::{ErrorFormat, ExitCode};
+use logging::LogFormat;
+use ptolemy::crs::Crs;
+use ptolemy::generator;
+use ptolemy::GeoPoint;
+use ptolemy::{AssignOptions, Bpr, Cartograph, FormatVersion, IndexConfig, PartitionOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use structopt::StructOpt;
+use tracing_subscriber::EnvFilter;
 
 /// This project exposes an API that calculates the shortest path in the road network, using data from OpenStreetMap.
+#[derive(StructOpt, Debug)]
+struct Options {
+    /// How to write errors: `text` or `json`. In both cases, the exit code tells the kind of error:
+    /// 2 = input not found, 3 = unsupported format, 4 = generation failure, 5 = bind failure
+    #[structopt(long, default_value = "text")]
+    error_format: ErrorFormat,
+
+    /// Which log messages to write to the standard error: `error`, `warn`, `info`, `debug` or
+    /// `trace`, possibly by module, like `warn,ptolemy=debug`
+    #[structopt(long, default_value = "info")]
+    log_level: EnvFilter,
+
+    /// How to write the log messages: `text` or `json`, with one object by line, like for the API
+    /// requests
+    #[structopt(long, default_value = "text")]
+    log_format: LogFormat,
+
+    #[structopt(subcommand)]
+    command: Ptolemy,
+}
+
 #[derive(StructOpt, Debug)]
 enum Ptolemy {
     /// Generate a compatible cartography data from raw OpenStreetMap data
@@ -15,7 +49,95 @@ enum Ptolemy {
         #[structopt(long)]
         threads: Option<usize>,
 
-        /// Input file, in the osm.pbf format
+        /// Only keep the nodes that can be reached from roads up to this level (0 = motorways, 5 = residential)
+        #[structopt(long, default_value = "2")]
+        prune_level: u8,
+
+        /// Keep all nodes, including isolated islands of small roads
+        #[structopt(long, conflicts_with = "prune-level")]
+        no_prune: bool,
+
+        /// Do not invent edges to make the graph strongly connected. Some routes will not exist
+        #[structopt(long)]
+        no_synthetic_edges: bool,
+
+        /// Who travels on the graph: `car` (the roads open to motor vehicles), `foot` (also the
+        /// footways, paths, crossings and steps, in both directions, without the motorways) or
+        /// `bicycle` (also the cycleways, with the contra-flow exceptions, without the motorways)
+        #[structopt(long, default_value = "car")]
+        profile: generator::TravelMode,
+
+        /// Include the tracks (`highway=track`) with a `tracktype` up to this grade, from 1 (solid) to 5
+        /// (soft), for agricultural or forestry vehicles. Tracks are ignored by default
+        #[structopt(long)]
+        max_track_grade: Option<u8>,
+
+        /// Include the pedestrian zones (`highway=pedestrian`), that vehicles usually can only use for
+        /// deliveries. They are ignored by default. Queries can avoid them with `avoid_access`
+        #[structopt(long)]
+        pedestrian_zones: bool,
+
+        /// Only keep the roads whose tags satisfy this condition, like `highway~(motorway|trunk)` or
+        /// `hgv!=no`. Use `key=value`, `key!=value`, `key~pattern`, `key!~pattern`, `key` or `!key`.
+        /// Can be repeated, to require all of them
+        #[structopt(long, number_of_values = 1)]
+        filter: Vec<generator::TagFilter>,
+
+        /// Sort the edges before inventing the synthetic ones, so that the same input always produces
+        /// exactly the same file, whatever the number of threads
+        #[structopt(long)]
+        sorted_output: bool,
+
+        /// Replace the chains of nodes that only connect two others, like where ways were split, by
+        /// single edges that keep the points of the removed nodes. The file is smaller and the
+        /// searches faster
+        #[structopt(long)]
+        contract_chains: bool,
+
+        /// What to do with the arcs between the same nodes in the same direction, like parallel
+        /// carriageways: merge them in one edge (merge), keep them all (keep-all) or keep the
+        /// shortest one (keep-shortest)
+        #[structopt(long, default_value = "merge")]
+        duplicate_arcs: generator::DuplicateArcs,
+
+        /// Store the ids of the OSM nodes and ways of the graph, to find the roads of a route in
+        /// openstreetmap.org. The file is larger
+        #[structopt(long)]
+        osm_ids: bool,
+
+        /// Store the Web Mercator coordinates of the nodes, so that opening the file skips
+        /// projecting them, which is faster for large graphs. The file is 16 bytes per node larger
+        #[structopt(long)]
+        projected_nodes: bool,
+
+        /// A GeoJSON file with the borders, like the administrative boundaries of the countries
+        /// exported from OpenStreetMap, to flag the edges that cross them. The routes count these
+        /// crossings and can avoid them with `avoid=borders`
+        #[structopt(long, parse(from_os_str))]
+        borders: Option<PathBuf>,
+
+        /// Write this key-value pair in the metadata of the file, like `region=Andorra`. Can be
+        /// repeated. The generator already writes the source file, its replication timestamp, its
+        /// version, the profile and the bounding box
+        #[structopt(long, number_of_values = 1, parse(try_from_str = parse_key_value))]
+        metadata: Vec<(String, String)>,
+
+        /// Fail when the input has problems, like ways with missing nodes or tags with invalid
+        /// values, instead of skipping them
+        #[structopt(long)]
+        strict: bool,
+
+        /// Do not display the progress bars nor the duration of each step
+        #[structopt(short, long)]
+        quiet: bool,
+
+        /// Write what was generated and how long each phase took to this JSON file, with the counts
+        /// of nodes and edges, the duration of each phase in seconds, the peak memory and the size
+        /// of the output, so that the regressions can be tracked
+        #[structopt(long, parse(from_os_str))]
+        report: Option<PathBuf>,
+
+        /// Input file, in the osm.pbf format, or OSM XML (.osm), possibly compressed with bzip2
         #[structopt(short, long, parse(from_os_str))]
         input: PathBuf,
 
@@ -23,21 +145,637 @@ enum Ptolemy {
         #[structopt(short, long, parse(from_os_str))]
         output: PathBuf,
     },
+    /// Merge several cartography files, like the extracts of adjacent countries, into a single one
+    Merge {
+        /// Input file, in the ptolemy format. Must be given at least once
+        #[structopt(short, long, parse(from_os_str), required = true, number_of_values = 1)]
+        input: Vec<PathBuf>,
+
+        /// Output file. Usually with the extension `.ptolemy`
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+
+        /// Do not invent edges to make the merged graph strongly connected
+        #[structopt(long)]
+        no_synthetic_edges: bool,
+
+        /// Do not display the duration of each step
+        #[structopt(short, long)]
+        quiet: bool,
+    },
     /// Start the Ptolemy API service
     Api {
         /// Input file, in the ptolemy format
         #[structopt(short, long, parse(from_os_str))]
         input: PathBuf,
+        /// Do not route through the edges that were invented to make the graph strongly connected.
+        /// Some routes will fail with a `NoRoute` error
+        #[structopt(long)]
+        forbid_synthetic_edges: bool,
+        /// How many expensive queries (randomized routes, steps, annotations, surface penalties)
+        /// can run at the same time. By default, half of the hyperthreads available
+        #[structopt(long)]
+        max_heavy_queries: Option<usize>,
+        /// How many route legs to keep in memory, to answer repeated requests faster. By default,
+        /// nothing is cached
+        #[structopt(long, default_value = "0")]
+        cache_size: usize,
+        /// Reject the routes with more coordinates than this
+        #[structopt(long, default_value = "100")]
+        max_waypoints: usize,
+        /// Reject the routes with more extra edges than this
+        #[structopt(long, default_value = "100")]
+        max_extra_edges: usize,
+        /// Reject the snap requests with more points than this
+        #[structopt(long, default_value = "10000")]
+        max_snap_points: usize,
+        /// Reject the round trips longer than this, in meters
+        #[structopt(long, default_value = "100000")]
+        max_round_trip_distance: usize,
+        /// Give up the route and table requests whose searches take longer than this, in
+        /// milliseconds, answering with 503 Service Unavailable. By default, there is no limit
+        #[structopt(long)]
+        max_query_time: Option<u64>,
+        /// Answer with 429 Too Many Requests when a client IP address makes more requests per
+        /// second than this. By default, there is no limit
+        #[structopt(long, parse(try_from_str = parse_rate))]
+        rate_limit: Option<f64>,
+        /// How many requests a client can make at once above `--rate-limit`. By default, one
+        /// second of requests
+        #[structopt(long)]
+        rate_limit_burst: Option<u32>,
+        /// JSON file with the experiments that the route queries can select with `experiment=`,
+        /// like `{"name": {"road_levels": [1, 1, 1.2], "edges": {"4199": 3}}}`
+        #[structopt(long, parse(from_os_str))]
+        experiments: Option<PathBuf>,
+        /// Hub table computed by `precompute-table` for this cartography. The table queries between
+        /// its points are answered without searching
+        #[structopt(long, parse(from_os_str))]
+        hub_table: Option<PathBuf>,
+        /// CSV file with the speed profiles of the road levels or edges for each hour of the day,
+        /// used by the route queries with `depart_at=`, like `level:1,1,1,1,1,1,1,0.8,0.5,...`
+        #[structopt(long, parse(from_os_str))]
+        speed_profiles: Option<PathBuf>,
+        /// CSV file with the OSM ways or edges that the route queries must avoid, optionally
+        /// while a time window lasts, like `way:4199,1700000000,1700600000`. `POST /closures`
+        /// replaces them
+        #[structopt(long, parse(from_os_str))]
+        closures: Option<PathBuf>,
+        /// Enable the requests that change the state of the server, `POST /closures`,
+        /// `POST /speeds` and `PUT /avoid_zones`, for the clients that send this token in the
+        /// header `Authorization: Bearer <token>`. By default, they are disabled
+        #[structopt(long)]
+        admin_token: Option<String>,
+        /// Print how long each phase of the loading of the cartography took
+        #[structopt(long)]
+        profile_load: bool,
+        /// Store the spatial index of the edges in single precision, which takes about half the
+        /// memory, at the cost of slightly slower projections
+        #[structopt(long)]
+        f32_index: bool,
+        /// Build the spatial index of the edges with all cores, as one tree per thread. Loading is
+        /// faster, but each projection searches all the trees
+        #[structopt(long)]
+        parallel_index: bool,
+    },
+    /// Serve the route, table and nearest queries of the API with gRPC, as described by
+    /// `proto/routing.proto`
+    #[cfg(feature = "grpc")]
+    Grpc {
+        /// Input file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+        /// The port to listen to, on localhost
+        #[structopt(long, default_value = "50051")]
+        port: u16,
+        /// Do not route through the edges that were invented to make the graph strongly connected
+        #[structopt(long)]
+        forbid_synthetic_edges: bool,
+        /// How many expensive queries can run at the same time. By default, half of the
+        /// hyperthreads available
+        #[structopt(long)]
+        max_heavy_queries: Option<usize>,
+        /// How many route legs to keep in memory. By default, nothing is cached
+        #[structopt(long, default_value = "0")]
+        cache_size: usize,
+        /// Reject the routes and tables with more coordinates than this
+        #[structopt(long, default_value = "100")]
+        max_waypoints: usize,
+        /// Give up the route and table requests whose searches take longer than this, in
+        /// milliseconds. By default, there is no limit
+        #[structopt(long)]
+        max_query_time: Option<u64>,
+    },
+    /// Compute the distances between all pairs of recurring points, like stores, and store them in a
+    /// hub table for the API
+    PrecomputeTable {
+        /// Cartography file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        /// The points, in the GPX format or a CSV with latitude and longitude columns
+        #[structopt(long, parse(from_os_str))]
+        points: PathBuf,
+
+        /// Output file. Usually with the extension `.hubs`
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Partition the graph in nested cells and compute the distances between their boundaries, for
+    /// multi-level routing
+    PrecomputeMld {
+        /// Cartography file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        /// Output file. Usually with the extension `.mld`
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+
+        /// The maximum number of nodes of the finest cells
+        #[structopt(long, default_value = "256")]
+        cell_size: usize,
+
+        /// The number of levels of cells
+        #[structopt(long, default_value = "3")]
+        levels: usize,
+    },
+    /// Print the statistics of a cartography file
+    Inspect {
+        /// Input file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+        /// Load the whole file and print how long each phase of the loading took, after the
+        /// statistics
+        #[structopt(long)]
+        profile_load: bool,
+        /// Store the spatial index of the edges in single precision, which takes about half the
+        /// memory, at the cost of slightly slower projections
+        #[structopt(long)]
+        f32_index: bool,
+        /// Build the spatial index of the edges with all cores, as one tree per thread. Loading is
+        /// faster, but each projection searches all the trees
+        #[structopt(long)]
+        parallel_index: bool,
+    },
+    /// Compare two cartography files, like the outputs of two versions of the generator, and print
+    /// their added and removed nodes and edges, changed distances and connectivity
+    Diff {
+        /// The reference file, in the ptolemy format
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+        /// The file to compare with the reference, in the ptolemy format
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+    },
+    /// Check that a cartography file is not corrupted and print its checksum
+    Checksum {
+        /// Input file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Rewrite a cartography file in the latest version of the format, like the files written by
+    /// older versions of the generator
+    Upgrade {
+        /// Input file, in any version of the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        /// Output file, in the latest version
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Find the shortest path between two points and print it, to debug the routing without the API
+    Route {
+        /// Cartography file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        /// Starting point, given as `lat,lon`
+        #[structopt(long, parse(try_from_str = parse_lat_lon))]
+        from: GeoPoint,
+
+        /// Destination, given as `lat,lon`
+        #[structopt(long, parse(try_from_str = parse_lat_lon))]
+        to: GeoPoint,
+
+        /// How to print the route: `json`, `polyline` or `gpx`
+        #[structopt(long, default_value = "json")]
+        format: router::RouteFormat,
+
+        /// Output file. By default, the route is printed after the loading messages
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Match a GPS trace to the most likely path on the road network
+    Match {
+        /// Input trace, in the GPX format or a CSV with latitude and longitude columns
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        /// Cartography file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        file: PathBuf,
+
+        /// Output file, in the GeoJSON format
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+
+        /// Coordinate reference system of the output: wgs84, web_mercator or utm
+        #[structopt(long, default_value = "wgs84")]
+        crs: Crs,
+    },
+    /// Snap each point of a GPS trace to its closest road, independently
+    Snap {
+        /// Input trace, in the GPX format or a CSV with latitude and longitude columns
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        /// Cartography file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        file: PathBuf,
+
+        /// Output file, in the GeoJSON format
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+
+        /// Coordinate reference system of the output: wgs84, web_mercator or utm
+        #[structopt(long, default_value = "wgs84")]
+        crs: Crs,
+    },
+    /// Simulate the traffic of a demand matrix, taking congestion into account
+    Assign {
+        /// Demand file, a CSV with the columns from_lat, from_lon, to_lat, to_lon and volume (vehicles/h)
+        #[structopt(short, long, parse(from_os_str))]
+        demand: PathBuf,
+
+        /// Cartography file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        file: PathBuf,
+
+        /// Output file, a CSV with the volume and congested cost of each edge
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+
+        /// Number of iterations of the assignment
+        #[structopt(long, default_value = "20")]
+        iterations: usize,
+
+        /// The alpha parameter of the BPR volume-delay function
+        #[structopt(long, default_value = "0.15")]
+        bpr_alpha: f64,
+
+        /// The beta parameter of the BPR volume-delay function
+        #[structopt(long, default_value = "4")]
+        bpr_beta: f64,
+
+        /// Close the street closest to this point, given as `lat,lon`. Can be repeated
+        #[structopt(long, parse(try_from_str = parse_lat_lon))]
+        close: Vec<GeoPoint>,
+    },
+    /// Compute the distance and duration of the shortest paths from many sources to many targets
+    Matrix {
+        /// Cartography file, in the ptolemy format
+        #[structopt(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        /// The sources, in the GPX format or a CSV with latitude and longitude columns
+        #[structopt(long, parse(from_os_str))]
+        sources: PathBuf,
+
+        /// The targets, in the same formats as the sources
+        #[structopt(long, parse(from_os_str))]
+        targets: PathBuf,
+
+        /// Output file, a CSV or a Parquet file, by its extension, with the columns source, target,
+        /// distance (m) and duration (s), where the points are given by their position in their
+        /// files
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+
+        /// How many threads to use. By default, will use all hyperthreads available
+        #[structopt(long)]
+        threads: Option<usize>,
     },
 }
 
+/// Parse a point given as `lat,lon`
+fn parse_lat_lon(s: &str) -> Result<GeoPoint, String> {
+    let values: Vec<f64> = s
+        .split(',')
+        .map(|value| value.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid point {}, expected lat,lon", s))?;
+    match values.as_slice() {
+        [lat, lon] => Ok(GeoPoint::from_degrees(*lat, *lon)),
+        _ => Err(format!("Invalid point {}, expected lat,lon", s)),
+    }
+}
+
+/// Parse a positive number of requests per second
+fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0. && rate.is_finite() => Ok(rate),
+        _ => Err(format!("Invalid rate {}, expected a positive number", s)),
+    }
+}
+
+/// Parse a metadata pair given as `key=value`
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.find('=') {
+        Some(pos) if pos > 0 => Ok((s[..pos].to_owned(), s[pos + 1..].to_owned())),
+        _ => Err(format!("Invalid metadata {}, expected key=value", s)),
+    }
+}
+
+/// Rewrite a cartography file in the latest version of the format
+fn upgrade(input: &Path, output: &Path) -> io::Result<()> {
+    let version = Cartograph::read_version(input)?;
+    Cartograph::open(input)?.save(output)?;
+    println!(
+        "Upgraded {} from {} to {}",
+        input.display(),
+        version,
+        FormatVersion::LATEST
+    );
+    Ok(())
+}
+
 fn main() {
-    match Ptolemy::from_args() {
+    let args = Options::from_args();
+    if let Err(error) = logging::init(args.log_level, args.log_format) {
+        exit::exit_with_error(&error, ExitCode::Other, args.error_format);
+    }
+    let (result, command_failure) = match args.command {
         Ptolemy::Generate {
             threads,
+            prune_level,
+            no_prune,
+            no_synthetic_edges,
+            profile,
+            max_track_grade,
+            pedestrian_zones,
+            filter,
+            sorted_output,
+            contract_chains,
+            duplicate_arcs,
+            osm_ids,
+            projected_nodes,
+            borders,
+            metadata,
+            strict,
+            quiet,
+            report,
             input,
             output,
-        } => generator::generate(threads, input, output).unwrap(),
-        Ptolemy::Api { input } => api::run_api(input).unwrap(),
+        } => {
+            let prune_level = if no_prune { None } else { Some(prune_level) };
+            let profile = generator::Profile {
+                mode: profile,
+                max_track_grade,
+                pedestrian_zones,
+                filters: filter,
+            };
+            let progress: Box<dyn generator::Progress> = if quiet {
+                Box::new(generator::NoProgress)
+            } else {
+                Box::new(generator::ProgressBars::default())
+            };
+            let options = generator::GenerateOptions {
+                num_threads: threads,
+                prune_level,
+                synthetic_edges: !no_synthetic_edges,
+                sorted_output,
+                contract_chains,
+                duplicate_arcs,
+                osm_ids,
+                projected_nodes,
+                borders,
+                profile,
+                metadata: ptolemy::Metadata(metadata.into_iter().collect()),
+                strict,
+                quiet,
+            };
+            let result = generator::generate(input, output, &options, progress.as_ref()).and_then(
+                |generated| match report {
+                    Some(path) => {
+                        std::fs::write(path, serde_json::to_string_pretty(&generated.to_json())?)
+                    }
+                    None => Ok(()),
+                },
+            );
+            (result, ExitCode::GenerationFailure)
+        }
+        Ptolemy::Merge {
+            input,
+            output,
+            no_synthetic_edges,
+            quiet,
+        } => (
+            generator::merge(&input, output, !no_synthetic_edges, quiet),
+            ExitCode::GenerationFailure,
+        ),
+        Ptolemy::Api {
+            input,
+            forbid_synthetic_edges,
+            max_heavy_queries,
+            cache_size,
+            max_waypoints,
+            max_extra_edges,
+            max_snap_points,
+            max_round_trip_distance,
+            max_query_time,
+            rate_limit,
+            rate_limit_burst,
+            experiments,
+            hub_table,
+            speed_profiles,
+            closures,
+            admin_token,
+            profile_load,
+            f32_index,
+            parallel_index,
+        } => (
+            api::run_api(
+                input,
+                forbid_synthetic_edges,
+                max_heavy_queries.unwrap_or_else(|| num_cpus::get() / 2),
+                cache_size,
+                api::ApiLimits {
+                    max_waypoints,
+                    max_extra_edges,
+                    max_snap_points,
+                    max_round_trip_distance,
+                    max_query_time: max_query_time.map(Duration::from_millis),
+                    rate_limit: rate_limit.map(|requests_per_second| api::RateLimit {
+                        requests_per_second,
+                        burst: rate_limit_burst
+                            .unwrap_or_else(|| requests_per_second.ceil() as u32),
+                    }),
+                },
+                experiments,
+                hub_table,
+                speed_profiles,
+                closures,
+                admin_token,
+                profile_load,
+                IndexConfig {
+                    f32_envelopes: f32_index,
+                    parallel: parallel_index,
+                },
+            ),
+            ExitCode::BindFailure,
+        ),
+        #[cfg(feature = "grpc")]
+        Ptolemy::Grpc {
+            input,
+            port,
+            forbid_synthetic_edges,
+            max_heavy_queries,
+            cache_size,
+            max_waypoints,
+            max_query_time,
+        } => (
+            api::run_grpc(
+                input,
+                port,
+                forbid_synthetic_edges,
+                max_heavy_queries.unwrap_or_else(|| num_cpus::get() / 2),
+                cache_size,
+                api::ApiLimits {
+                    max_waypoints,
+                    max_query_time: max_query_time.map(Duration::from_millis),
+                    ..api::ApiLimits::default()
+                },
+            ),
+            ExitCode::BindFailure,
+        ),
+        Ptolemy::Inspect {
+            input,
+            profile_load: false,
+            ..
+        } => (
+            Cartograph::read_stats(input)
+                .map(|stats| println!("{}", stats))
+                .map_err(io::Error::from),
+            ExitCode::Other,
+        ),
+        Ptolemy::Inspect {
+            input,
+            profile_load: true,
+            f32_index,
+            parallel_index,
+        } => (
+            Cartograph::open_with_config(
+                input,
+                &IndexConfig {
+                    f32_envelopes: f32_index,
+                    parallel: parallel_index,
+                },
+                |_| {},
+            )
+            .map(|(carto, report)| println!("{}\n{}", carto.stats(), report))
+            .map_err(io::Error::from),
+            ExitCode::Other,
+        ),
+        Ptolemy::PrecomputeTable {
+            input,
+            points,
+            output,
+        } => (
+            precompute::run_precompute_table(input, points, output),
+            ExitCode::Other,
+        ),
+        Ptolemy::PrecomputeMld {
+            input,
+            output,
+            cell_size,
+            levels,
+        } => (
+            precompute::run_precompute_mld(input, output, &PartitionOptions { cell_size, levels }),
+            ExitCode::Other,
+        ),
+        Ptolemy::Diff { old, new } => (
+            Cartograph::open(old)
+                .and_then(|old| Ok(old.diff(&Cartograph::open(new)?)))
+                .map(|diff| print!("{}", diff))
+                .map_err(io::Error::from),
+            ExitCode::Other,
+        ),
+        Ptolemy::Checksum { input } => (
+            Cartograph::verify_checksum(input)
+                .map(|checksum| println!("Checksum {:08x} is valid", checksum))
+                .map_err(io::Error::from),
+            ExitCode::Other,
+        ),
+        Ptolemy::Upgrade { input, output } => (upgrade(&input, &output), ExitCode::Other),
+        Ptolemy::Route {
+            input,
+            from,
+            to,
+            format,
+            output,
+        } => (
+            router::run_route(input, &from, &to, format, output),
+            ExitCode::Other,
+        ),
+        Ptolemy::Match {
+            input,
+            file,
+            output,
+            crs,
+        } => (
+            matcher::run_match(input, file, output, crs),
+            ExitCode::Other,
+        ),
+        Ptolemy::Snap {
+            input,
+            file,
+            output,
+            crs,
+        } => (matcher::run_snap(input, file, output, crs), ExitCode::Other),
+        Ptolemy::Assign {
+            demand,
+            file,
+            output,
+            iterations,
+            bpr_alpha,
+            bpr_beta,
+            close,
+        } => {
+            let options = AssignOptions {
+                iterations,
+                bpr: Bpr {
+                    alpha: bpr_alpha,
+                    beta: bpr_beta,
+                },
+                closed_edges: Vec::new(),
+            };
+            (
+                assigner::run_assign(demand, file, output, options, &close),
+                ExitCode::Other,
+            )
+        }
+        Ptolemy::Matrix {
+            input,
+            sources,
+            targets,
+            output,
+            threads,
+        } => (
+            matrix::run_matrix(
+                input,
+                sources,
+                targets,
+                output,
+                threads.unwrap_or_else(num_cpus::get),
+            ),
+            ExitCode::Other,
+        ),
+    };
+
+    if let Err(error) = result {
+        let code = ExitCode::classify(&error, command_failure);
+        exit::exit_with_error(&error, code, args.error_format);
     }
 }
@@ -0,0 +1,115 @@
+//! Offline map-matching of GPS traces, without running the HTTP service
+
+use ptolemy::crs::{Crs, Projection};
+use ptolemy::trace;
+use ptolemy::*;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Match a trace file to the road network and write the matched path and positions as GeoJSON, with
+/// the coordinates in the given system
+pub fn run_match<P: AsRef<Path>>(input: P, carto_file: P, output: P, crs: Crs) -> io::Result<()> {
+    let mut timer = DebugTime::new();
+
+    let options = MatchOptions::default();
+    let raw_trace = trace::read_trace(&input)?;
+    let trace = trace::thin_trace(&raw_trace, 2. * options.sigma);
+    timer.msg(format!(
+        "Read {} points, kept {} after thinning",
+        format_num(raw_trace.len()),
+        format_num(trace.len())
+    ));
+
+    let carto = Cartograph::open(carto_file)?;
+    timer.msg("Opened cartography");
+
+    let matched = carto
+        .match_trace(&trace, &options)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "The trace has no points"))?;
+    timer.msg(format!("Matched path with {}m", matched.path.distance));
+
+    let projection = crs.projection(&matched.path.points);
+    let mut features = vec![json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": projection.project_all(&matched.path.points),
+        },
+        "properties": {
+            "distance": matched.path.distance,
+        },
+    })];
+    features.extend(
+        matched
+            .points
+            .iter()
+            .map(|point| point_feature(point, projection)),
+    );
+    write_feature_collection(features, projection, &output)?;
+    timer.msg(format!("Wrote results to {}", output.as_ref().display()));
+
+    Ok(())
+}
+
+/// Snap each point of a trace file to its closest road and write the positions as GeoJSON, with the
+/// coordinates in the given system
+pub fn run_snap<P: AsRef<Path>>(input: P, carto_file: P, output: P, crs: Crs) -> io::Result<()> {
+    let mut timer = DebugTime::new();
+
+    let trace = trace::read_trace(&input)?;
+    timer.msg(format!("Read {} points", format_num(trace.len())));
+
+    let carto = Cartograph::open(carto_file)?;
+    timer.msg("Opened cartography");
+
+    let projection = crs.projection(&trace);
+    let features = trace
+        .iter()
+        .map(|point| point_feature(&carto.project(point), projection))
+        .collect();
+    write_feature_collection(features, projection, &output)?;
+    timer.msg(format!("Wrote results to {}", output.as_ref().display()));
+
+    Ok(())
+}
+
+/// Represent a snapped position as a GeoJSON point feature, keeping the original point as a property
+fn point_feature(point: &ProjectedPoint, projection: Projection) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": projection.project(&point.projected),
+        },
+        "properties": {
+            "original": projection.project(&point.original),
+            "distance": point.projected.haversine_distance(&point.original),
+            "edge": point.edge.index(),
+        },
+    })
+}
+
+/// Other systems than WGS84 are named with the `crs` member of the previous GeoJSON specification,
+/// that is still understood by GDAL and QGIS
+fn write_feature_collection<P: AsRef<Path>>(
+    features: Vec<Value>,
+    projection: Projection,
+    output: P,
+) -> io::Result<()> {
+    let mut collection = json!({
+        "type": "FeatureCollection",
+        "features": features,
+    });
+    if projection != Projection::Wgs84 {
+        collection["crs"] = json!({
+            "type": "name",
+            "properties": {
+                "name": projection.to_string(),
+            },
+        });
+    }
+    serde_json::to_writer(File::create(output)?, &collection)?;
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Compute the distances and durations between many points from the command line, for analyses
+//! that do not need the HTTP service
+
+use parquet::{write_parquet, Column};
+use ptolemy::trace;
+use ptolemy::*;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+mod parquet;
+
+/// The distance in meters and the duration in seconds of the shortest path from each source to each
+/// target, `None` when the target is not reachable
+type Matrix = Vec<Vec<Option<(u32, f64)>>>;
+
+/// Compute the matrix from the points of `sources` to the ones of `targets`, in the same formats as
+/// the traces, and write it as CSV or Parquet, depending on the extension of `output`, with one row
+/// per pair of points. The sources are split among `num_threads` threads
+pub fn run_matrix<P: AsRef<Path>>(
+    carto_file: P,
+    sources: P,
+    targets: P,
+    output: P,
+    num_threads: usize,
+) -> io::Result<()> {
+    let mut timer = DebugTime::new();
+
+    // Fail before the long part
+    let parquet = match output.as_ref().extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => false,
+        Some("parquet") => true,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Could not detect the matrix format of {}. Use the extension .csv or .parquet",
+                    output.as_ref().display()
+                ),
+            ))
+        }
+    };
+
+    let sources = trace::read_trace(&sources)?;
+    let targets = trace::read_trace(&targets)?;
+    timer.msg(format!(
+        "Read {} sources and {} targets",
+        format_num(sources.len()),
+        format_num(targets.len())
+    ));
+
+    let carto = Cartograph::open(carto_file)?;
+    let sources = carto.project_all(&sources, num_threads);
+    let targets = carto.project_all(&targets, num_threads);
+    timer.msg("Opened cartography and projected the points");
+
+    let matrix = compute_matrix(&carto, &sources, &targets, num_threads);
+    timer.msg(format!(
+        "Computed {} paths",
+        format_num(sources.len() * targets.len())
+    ));
+
+    let mut writer = BufWriter::new(File::create(&output)?);
+    if parquet {
+        write_matrix_parquet(&mut writer, &matrix)?;
+    } else {
+        write_csv(&mut writer, &matrix)?;
+    }
+    writer.flush()?;
+    timer.msg(format!("Wrote results to {}", output.as_ref().display()));
+
+    Ok(())
+}
+
+/// Search the paths from each source, splitting the sources among the threads
+fn compute_matrix(
+    carto: &Cartograph,
+    sources: &[ProjectedPoint],
+    targets: &[ProjectedPoint],
+    num_threads: usize,
+) -> Matrix {
+    let chunk_size = sources.len().div_ceil(num_threads.max(1));
+    if chunk_size == 0 {
+        return Vec::new();
+    }
+    crossbeam::scope(|scope| {
+        let handles: Vec<_> = sources
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move |_| {
+                    chunk
+                        .iter()
+                        .map(|source| {
+                            carto
+                                .shortest_paths_multi(source, targets)
+                                .into_iter()
+                                .map(|path| path.map(|path| (path.distance, path.duration)))
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+    .unwrap()
+}
+
+/// Write the lines `source,target,distance,duration`, with the indexes of the points in their files.
+/// The distance and duration are empty for the unreachable targets
+fn write_csv<W: Write>(writer: &mut W, matrix: &Matrix) -> io::Result<()> {
+    writeln!(writer, "source,target,distance,duration")?;
+    for (source, row) in matrix.iter().enumerate() {
+        for (target, cell) in row.iter().enumerate() {
+            match cell {
+                Some((distance, duration)) => writeln!(
+                    writer,
+                    "{},{},{},{}",
+                    source,
+                    target,
+                    distance,
+                    round_duration(*duration)
+                )?,
+                None => writeln!(writer, "{},{},,", source, target)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write the same columns as `write_csv()` in the Parquet format. The distance and duration are
+/// null for the unreachable targets
+fn write_matrix_parquet<W: Write>(writer: &mut W, matrix: &Matrix) -> io::Result<()> {
+    let cells = || {
+        matrix.iter().enumerate().flat_map(|(source, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(target, cell)| (source as i64, target as i64, *cell))
+        })
+    };
+    let columns = [
+        Column::int64("source", cells().map(|(source, _, _)| source)),
+        Column::int64("target", cells().map(|(_, target, _)| target)),
+        Column::optional_int64(
+            "distance",
+            cells().map(|(_, _, cell)| cell.map(|(distance, _)| distance as i64)),
+        ),
+        Column::optional_double(
+            "duration",
+            cells().map(|(_, _, cell)| cell.map(|(_, duration)| round_duration(duration))),
+        ),
+    ];
+    write_parquet(writer, cells().count(), &columns)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matrix() {
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let points: Vec<_> = [(42.553210, 1.588908), (42.564440, 1.685042)]
+            .iter()
+            .map(|&(lat, lon)| carto.project(&GeoPoint::from_degrees(lat, lon)))
+            .collect();
+        let matrix = compute_matrix(&carto, &points, &points, 2);
+        assert_eq!(matrix.len(), 2);
+        assert_eq!(matrix[0][1].unwrap().0, 12183);
+        assert_eq!(matrix[0][0].unwrap().0, 0);
+        assert_eq!(
+            matrix,
+            compute_matrix(&carto, &points, &points, 1),
+            "The threads do not change the results"
+        );
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &vec![vec![Some((120, 10.04)), None]]).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "source,target,distance,duration\n0,0,120,10\n0,1,,\n"
+        );
+
+        let mut parquet = Vec::new();
+        write_matrix_parquet(&mut parquet, &vec![vec![Some((120, 10.04)), None]]).unwrap();
+        assert!(parquet.starts_with(b"PAR1") && parquet.ends_with(b"PAR1"));
+        let contains = |bytes: [u8; 8]| parquet.windows(8).any(|window| window == bytes);
+        assert!(contains(120i64.to_le_bytes()));
+        assert!(contains(10f64.to_le_bytes()));
+    }
+}
@@ -0,0 +1,329 @@
+//! A minimal writer of Parquet files, enough for the matrices: a single row group, whose columns
+//! have 8-byte values in uncompressed data pages with plain encoding. The metadata is encoded with
+//! the compact protocol of Thrift, as described in https://github.com/apache/parquet-format
+
+use std::io::{self, Write};
+
+const MAGIC: &[u8] = b"PAR1";
+
+/// The physical types of the columns
+const INT64: i32 = 2;
+const DOUBLE: i32 = 5;
+
+/// The encodings of the values and levels
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+
+/// The number of rows of each data page, whose size is limited to `i32::MAX` bytes
+const PAGE_ROWS: usize = 1 << 20;
+
+/// A column, with its values already encoded
+pub struct Column {
+    name: &'static str,
+    physical_type: i32,
+    /// The definition level of each row for the optional columns, `None` for the required ones
+    defined: Option<Vec<bool>>,
+    /// The values of the rows that have one, in plain encoding
+    values: Vec<u8>,
+}
+
+impl Column {
+    pub fn int64(name: &'static str, values: impl Iterator<Item = i64>) -> Self {
+        Column {
+            name,
+            physical_type: INT64,
+            defined: None,
+            values: values.flat_map(i64::to_le_bytes).collect(),
+        }
+    }
+
+    pub fn optional_int64(name: &'static str, values: impl Iterator<Item = Option<i64>>) -> Self {
+        Column::optional(name, INT64, values.map(|value| value.map(i64::to_le_bytes)))
+    }
+
+    pub fn optional_double(name: &'static str, values: impl Iterator<Item = Option<f64>>) -> Self {
+        Column::optional(
+            name,
+            DOUBLE,
+            values.map(|value| value.map(f64::to_le_bytes)),
+        )
+    }
+
+    fn optional(
+        name: &'static str,
+        physical_type: i32,
+        values: impl Iterator<Item = Option<[u8; 8]>>,
+    ) -> Self {
+        let mut defined = Vec::new();
+        let mut bytes = Vec::new();
+        for value in values {
+            defined.push(value.is_some());
+            bytes.extend(value.iter().flatten());
+        }
+        Column {
+            name,
+            physical_type,
+            defined: Some(defined),
+            values: bytes,
+        }
+    }
+
+    /// The data of a page of rows: the definition levels, if any, then the values, which start at
+    /// `first_value`. Also return the number of values
+    fn page_data(&self, rows: &[bool], first_value: usize) -> (Vec<u8>, usize) {
+        let mut data = Vec::new();
+        let num_values = match &self.defined {
+            Some(_) => {
+                let levels = encode_levels(rows);
+                data.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+                data.extend(levels);
+                rows.iter().filter(|&&defined| defined).count()
+            }
+            None => rows.len(),
+        };
+        data.extend_from_slice(&self.values[first_value * 8..(first_value + num_values) * 8]);
+        (data, num_values)
+    }
+}
+
+/// Write the columns, which must all have `num_rows` rows, as a Parquet file
+pub fn write_parquet<W: Write>(
+    writer: &mut W,
+    num_rows: usize,
+    columns: &[Column],
+) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    let mut offset = MAGIC.len() as i64;
+
+    let mut chunks = Vec::with_capacity(columns.len());
+    for column in columns {
+        let all_defined = vec![true; num_rows];
+        let defined = column.defined.as_ref().unwrap_or(&all_defined);
+        let start = offset;
+        let mut first_value = 0;
+        for rows in defined.chunks(PAGE_ROWS) {
+            let (data, num_values) = column.page_data(rows, first_value);
+            first_value += num_values;
+            let mut header = Thrift::default();
+            header.i32(1, 0); // DATA_PAGE
+            header.i32(2, data.len() as i32);
+            header.i32(3, data.len() as i32);
+            header.begin_struct(5);
+            header.i32(1, rows.len() as i32);
+            header.i32(2, PLAIN);
+            header.i32(3, RLE);
+            header.i32(4, RLE);
+            header.end_struct();
+            header.end_struct();
+
+            writer.write_all(&header.bytes)?;
+            writer.write_all(&data)?;
+            offset += (header.bytes.len() + data.len()) as i64;
+        }
+        chunks.push((column, start, offset - start));
+    }
+
+    let mut metadata = Thrift::default();
+    metadata.i32(1, 1);
+    metadata.list(2, Thrift::STRUCT, columns.len() + 1);
+    metadata.begin_element();
+    metadata.string(4, "schema");
+    metadata.i32(5, columns.len() as i32);
+    metadata.end_struct();
+    for column in columns {
+        metadata.begin_element();
+        metadata.i32(1, column.physical_type);
+        let repetition = if column.defined.is_some() {
+            OPTIONAL
+        } else {
+            REQUIRED
+        };
+        metadata.i32(3, repetition);
+        metadata.string(4, column.name);
+        metadata.end_struct();
+    }
+    metadata.i64(3, num_rows as i64);
+    metadata.list(4, Thrift::STRUCT, 1);
+    metadata.begin_element();
+    metadata.list(1, Thrift::STRUCT, chunks.len());
+    for &(column, offset, size) in &chunks {
+        metadata.begin_element();
+        metadata.i64(2, offset);
+        metadata.begin_struct(3);
+        metadata.i32(1, column.physical_type);
+        metadata.list(2, Thrift::I32, 2);
+        metadata.list_i32(PLAIN);
+        metadata.list_i32(RLE);
+        metadata.list(3, Thrift::BINARY, 1);
+        metadata.list_string(column.name);
+        metadata.i32(4, 0); // UNCOMPRESSED
+        metadata.i64(5, num_rows as i64);
+        metadata.i64(6, size);
+        metadata.i64(7, size);
+        metadata.i64(9, offset);
+        metadata.end_struct();
+        metadata.end_struct();
+    }
+    let total_size = chunks.iter().map(|&(_, _, size)| size).sum();
+    metadata.i64(2, total_size);
+    metadata.i64(3, num_rows as i64);
+    metadata.end_struct();
+    metadata.string(6, concat!("ptolemy version ", env!("CARGO_PKG_VERSION")));
+    metadata.end_struct();
+
+    writer.write_all(&metadata.bytes)?;
+    writer.write_all(&(metadata.bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(MAGIC)
+}
+
+/// Encode the definition levels, of one bit, with the hybrid of run-length encoding and
+/// bit-packing. Only runs are used, which is compact since the values are usually all defined
+fn encode_levels(defined: &[bool]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut rest = defined;
+    while let Some(&first) = rest.first() {
+        let len = rest.iter().take_while(|&&value| value == first).count();
+        write_varint(&mut bytes, (len as u64) << 1);
+        bytes.push(first as u8);
+        rest = &rest[len..];
+    }
+    bytes
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// The compact protocol of Thrift. Each field is written with the difference between its id and
+/// the one of the previous field of the same struct
+#[derive(Default)]
+struct Thrift {
+    bytes: Vec<u8>,
+    /// The id of the last field written in each of the open structs, except the outermost one
+    last_ids: Vec<i16>,
+    last_id: i16,
+}
+
+impl Thrift {
+    const I32: u8 = 5;
+    const I64: u8 = 6;
+    const BINARY: u8 = 8;
+    const LIST: u8 = 9;
+    const STRUCT: u8 = 12;
+
+    fn field(&mut self, id: i16, field_type: u8) {
+        let delta = id - self.last_id;
+        debug_assert!(delta > 0 && delta <= 15);
+        self.bytes.push((delta as u8) << 4 | field_type);
+        self.last_id = id;
+    }
+
+    fn zigzag(&mut self, value: i64) {
+        write_varint(&mut self.bytes, ((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, Thrift::I32);
+        self.zigzag(value as i64);
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, Thrift::I64);
+        self.zigzag(value);
+    }
+
+    fn string(&mut self, id: i16, value: &str) {
+        self.field(id, Thrift::BINARY);
+        self.list_string(value);
+    }
+
+    /// Start a list field, whose elements must follow
+    fn list(&mut self, id: i16, element_type: u8, len: usize) {
+        self.field(id, Thrift::LIST);
+        if len < 15 {
+            self.bytes.push((len as u8) << 4 | element_type);
+        } else {
+            self.bytes.push(0xf0 | element_type);
+            write_varint(&mut self.bytes, len as u64);
+        }
+    }
+
+    fn list_i32(&mut self, value: i32) {
+        self.zigzag(value as i64);
+    }
+
+    fn list_string(&mut self, value: &str) {
+        write_varint(&mut self.bytes, value.len() as u64);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, Thrift::STRUCT);
+        self.begin_element();
+    }
+
+    /// Start a struct that is an element of a list
+    fn begin_element(&mut self) {
+        self.last_ids.push(self.last_id);
+        self.last_id = 0;
+    }
+
+    /// End a struct, or the outermost one when no other is open
+    fn end_struct(&mut self) {
+        self.bytes.push(0);
+        self.last_id = self.last_ids.pop().unwrap_or(0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        let defined = [true, true, true, false, true];
+        assert_eq!(encode_levels(&defined), vec![6, 1, 2, 0, 2, 1]);
+        let mut long = vec![true; 100];
+        long.push(false);
+        assert_eq!(encode_levels(&long), vec![200, 1, 1, 2, 0]);
+    }
+
+    #[test]
+    fn thrift() {
+        let mut thrift = Thrift::default();
+        thrift.i32(1, -2);
+        thrift.begin_struct(3);
+        thrift.i64(1, 300);
+        thrift.end_struct();
+        thrift.string(6, "ab");
+        thrift.end_struct();
+        assert_eq!(
+            thrift.bytes,
+            vec![0x15, 3, 0x2c, 0x16, 0xd8, 0x04, 0, 0x38, 2, b'a', b'b', 0]
+        );
+    }
+
+    #[test]
+    fn file() {
+        let columns = [
+            Column::int64("source", vec![0, 1].into_iter()),
+            Column::optional_double("duration", vec![Some(1.5), None].into_iter()),
+        ];
+        let mut bytes = Vec::new();
+        write_parquet(&mut bytes, 2, &columns).unwrap();
+        assert!(bytes.starts_with(MAGIC) && bytes.ends_with(MAGIC));
+        let footer = &bytes[bytes.len() - 8..bytes.len() - 4];
+        let metadata_len = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+        assert!((metadata_len as usize) < bytes.len() - 12);
+        assert!(bytes
+            .windows(8)
+            .any(|window| window == 1.5f64.to_le_bytes()));
+    }
+}
@@ -0,0 +1,79 @@
+//! Precompute the distances between recurring points from the command line, so that the API can
+//! answer the queries between them without searching
+
+use ptolemy::trace;
+use ptolemy::*;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Compute the distances between all pairs of points of a file, in the same formats as the traces,
+/// and write them as a hub table
+pub fn run_precompute_table<P: AsRef<Path>>(carto_file: P, points: P, output: P) -> io::Result<()> {
+    let mut timer = DebugTime::new();
+
+    let hubs = trace::read_trace(&points)?;
+    timer.msg(format!("Read {} points", format_num(hubs.len())));
+
+    // The checksum ties the table to this exact file
+    let checksum = Cartograph::verify_checksum(&carto_file)?;
+    let carto = Cartograph::open(&carto_file)?;
+    timer.msg("Opened cartography");
+
+    let table = HubTable::compute(&carto, hubs, checksum);
+    timer.msg(format!(
+        "Computed {} distances",
+        format_num(table.hubs().len() * table.hubs().len())
+    ));
+
+    let mut writer = BufWriter::new(File::create(&output)?);
+    table.write(&mut writer)?;
+    writer.flush()?;
+    timer.msg(format!("Wrote results to {}", output.as_ref().display()));
+
+    Ok(())
+}
+
+/// Partition the graph in nested cells and customize them for the distances, writing the overlay
+/// for multi-level routing
+pub fn run_precompute_mld<P: AsRef<Path>>(
+    carto_file: P,
+    output: P,
+    options: &PartitionOptions,
+) -> io::Result<()> {
+    let mut timer = DebugTime::new();
+
+    // The checksum ties the overlay to this exact file
+    let checksum = Cartograph::verify_checksum(&carto_file)?;
+    let carto = Cartograph::open(&carto_file)?;
+    timer.msg("Opened cartography");
+
+    let partition = Partition::inertial(&carto, options);
+    timer.msg(format!("Partitioned in {} levels", partition.levels()));
+
+    let overlay = MultiLevelOverlay::new(&carto, partition, checksum);
+    timer.msg("Customized the cells for the distances");
+
+    let mut writer = BufWriter::new(File::create(&output)?);
+    overlay.write(&mut writer)?;
+    writer.flush()?;
+    timer.msg(format!("Wrote results to {}", output.as_ref().display()));
+
+    Ok(())
+}
+
+/// Read a hub table, checking that it was computed for this cartography file
+pub fn load_hub_table<P: AsRef<Path>>(carto_file: P, table_file: P) -> io::Result<HubTable> {
+    let table = HubTable::read(&mut BufReader::new(File::open(&table_file)?))?;
+    if table.checksum != Cartograph::verify_checksum(&carto_file)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "The hub table {} was computed for another version of {}",
+                table_file.as_ref().display(),
+                carto_file.as_ref().display()
+            ),
+        ));
+    }
+    Ok(table)
+}
@@ -0,0 +1,126 @@
+//! Answer a single route query from the command line, without running the HTTP service
+
+use ptolemy::*;
+use serde_json::json;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How the route is printed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteFormat {
+    /// The distance, duration, polyline and snapped waypoints, like an OSRM route
+    Json,
+    /// Only the polyline-encoded geometry
+    Polyline,
+    /// GPX 1.1 document with the route as a track
+    Gpx,
+}
+
+impl FromStr for RouteFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(RouteFormat::Json),
+            "polyline" => Ok(RouteFormat::Polyline),
+            "gpx" => Ok(RouteFormat::Gpx),
+            _ => Err(format!(
+                "Invalid route format {}, expected json, polyline or gpx",
+                s
+            )),
+        }
+    }
+}
+
+/// Find the shortest path between two points and write it to `output`, or print it to the standard
+/// output, after the messages of the loading of the cartography
+pub fn run_route<P: AsRef<Path>>(
+    carto_file: P,
+    from: &GeoPoint,
+    to: &GeoPoint,
+    format: RouteFormat,
+    output: Option<P>,
+) -> io::Result<()> {
+    let carto = Cartograph::open(carto_file)?;
+    let waypoints = [carto.project(from), carto.project(to)];
+    let path = carto
+        .try_shortest_path(&waypoints[0], &waypoints[1])
+        .ok_or_else(|| io::Error::other("Could not find a route between the points"))?;
+    let route = format_route(&path, &waypoints, format);
+    match output {
+        Some(output) => fs::write(output, route),
+        None => {
+            println!("{}", route);
+            Ok(())
+        }
+    }
+}
+
+/// Describe a path between the given waypoints in the requested format
+fn format_route(path: &GraphPath, waypoints: &[ProjectedPoint], format: RouteFormat) -> String {
+    match format {
+        RouteFormat::Json => json!({
+            "distance": path.distance,
+            "duration": round_duration(path.duration),
+            "geometry": path.polyline,
+            "waypoints": waypoints
+                .iter()
+                .map(|waypoint| {
+                    json!({
+                        "location": [
+                            waypoint.projected.lon.as_degrees(),
+                            waypoint.projected.lat.as_degrees(),
+                        ],
+                        "distance": round_distance(
+                            waypoint.projected.haversine_distance(&waypoint.original),
+                        ),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        })
+        .to_string(),
+        RouteFormat::Polyline => path.polyline.clone(),
+        RouteFormat::Gpx => {
+            let points: Vec<_> = waypoints
+                .iter()
+                .map(|waypoint| waypoint.projected)
+                .collect();
+            path.to_gpx(&points, None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn route_formats() {
+        assert_eq!("gpx".parse(), Ok(RouteFormat::Gpx));
+        assert!("geojson".parse::<RouteFormat>().is_err());
+
+        let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
+        let waypoints = [
+            carto.project(&GeoPoint::from_degrees(42.553210, 1.588908)),
+            carto.project(&GeoPoint::from_degrees(42.564440, 1.685042)),
+        ];
+        let path = carto.shortest_path(&waypoints[0], &waypoints[1]);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_route(&path, &waypoints, RouteFormat::Json)).unwrap();
+        assert_eq!(json["distance"], 12183);
+        assert_eq!(json["geometry"], path.polyline.as_str());
+        assert_eq!(json["waypoints"].as_array().unwrap().len(), 2);
+        let distance = json["waypoints"][0]["distance"].as_f64().unwrap();
+        assert_eq!(distance, (distance * 10.).round() / 10.);
+        assert_eq!(
+            format_route(&path, &waypoints, RouteFormat::Polyline),
+            path.polyline
+        );
+        let gpx = format_route(&path, &waypoints, RouteFormat::Gpx);
+        assert_eq!(gpx.matches("<wpt ").count(), 2);
+        assert_eq!(gpx.matches("<trkpt ").count(), path.points.len());
+    }
+}
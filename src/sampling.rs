@@ -0,0 +1,928 @@
+//! Stable sampling of large iterators, for example to draw a representative subset of the roads,
+//! nodes or points of interest of a map region.
+//!
+//! # Determinism
+//!
+//! Whether an element is kept only depends on its hash, not on its position in the iterator. So:
+//!
+//! - The result does not depend on the order in which the elements arrive, only on which elements
+//!   there are. The elements are returned in the order they arrived
+//! - When a region is panned, the elements present in both regions tend to stay in the sample,
+//!   which avoids flickering
+//! - The same elements always give the same sample, in any run and with any number of threads.
+//!   The hash is computed with `DefaultHasher::new()`, so the sample could change with a new Rust
+//!   version, and types whose `Hash` depends on the platform (like `usize`) may give different
+//!   samples on 32 and 64-bit targets. Use `sample_by_key()` with a fixed-size key to avoid that
+//! - A seed changes which elements are kept, while keeping all the guarantees above for that seed
+//!
+//! If there are less than `max_num` elements in total, they are all returned. Otherwise, any number
+//! from 0 to `max_num` can be returned, usually more than half of `max_num`.
+//!
+//! # Options
+//!
+//! `SampleOptions` changes how the elements are picked, with the same guarantees:
+//!
+//! - `stable_hash` hashes with `StableHasher` instead of `DefaultHasher`, which gives the same
+//!   sample with any Rust version and on any platform
+//! - `fill` returns exactly `max_num` elements when there are enough, topping up from the lower
+//!   priorities. It keeps the elements whose hashes end with the most zeros, which are the ones the
+//!   default mode would keep the longest
+//! - `PrioritySample::sample_spread()` splits a viewport in a grid and shares `max_num` among its
+//!   cells, so that a dense area does not take the whole sample
+//!
+//! `PrioritySampler` keeps the same sample as `PrioritySample::sample_with_priority()`, but
+//! samplers fed with different parts of the elements can be merged, for example to sample them in
+//! parallel.
+
+use std::collections::btree_map::Entry;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+/// How the elements are picked, see the module documentation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SampleOptions {
+    /// Keep different elements for each seed
+    pub seed: Option<u64>,
+    /// Hash with `StableHasher` instead of `DefaultHasher`
+    pub stable_hash: bool,
+    /// Return exactly `max_num` elements when there are enough
+    pub fill: bool,
+}
+
+/// The viewport of `PrioritySample::sample_spread()`, split in `grid` × `grid` cells. The elements
+/// outside of it count for the closest cell
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spread {
+    pub min: [f64; 2],
+    pub max: [f64; 2],
+    pub grid: usize,
+}
+
+impl Spread {
+    fn cell(&self, xy: [f64; 2]) -> usize {
+        let grid = self.grid.max(1);
+        let index = |i: usize| {
+            let ratio = (xy[i] - self.min[i]) / (self.max[i] - self.min[i]);
+            // A NaN, for an empty viewport, is converted to 0
+            ((ratio * grid as f64).floor().max(0.) as usize).min(grid - 1)
+        };
+        index(1) * grid + index(0)
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// A hasher whose results never change, unlike the ones of `DefaultHasher`, which may change with
+/// a new Rust version. It is FNV-1a followed by a mix of the bits, since the sampler decides with
+/// the lowest ones. The integers are hashed in little-endian and `usize` as a `u64`, so that all
+/// platforms agree
+#[derive(Clone, Copy, Debug)]
+pub struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    pub fn with_seed(seed: u64) -> Self {
+        StableHasher {
+            state: FNV_OFFSET ^ mix(seed),
+        }
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher::with_seed(0)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= byte as u64;
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as i64 as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        mix(self.state)
+    }
+}
+
+/// The finalizer of SplitMix64
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+pub trait Sample: Iterator + Sized {
+    /// Sample from the iterator, returning at most `max_num` elements.
+    /// By design, this is a stable sampler, that is, the result is not dependent
+    /// on the order in which the values arrive from the iterator.
+    ///
+    /// If there are less than `max_num` elements in total, they will be returned
+    /// intact. Otherwise, due to the way the logic is implemented any value from
+    /// 0 to `max_num` can be returned.
+    fn sample(self, max_num: usize) -> Vec<Self::Item>
+    where
+        Self::Item: Hash,
+    {
+        let mut sampler = Sampler::new(max_num);
+        for el in self {
+            sampler.update(el);
+        }
+        sampler.finish()
+    }
+
+    /// Like `sample()`, but a different `seed` selects different elements
+    fn sample_seeded(self, max_num: usize, seed: u64) -> Vec<Self::Item>
+    where
+        Self::Item: Hash,
+    {
+        let mut sampler = Sampler::with_seed(max_num, seed);
+        for el in self {
+            sampler.update(el);
+        }
+        sampler.finish()
+    }
+
+    /// Like `sample()`, but hash the key returned by `get_key` instead of the element itself. This
+    /// allows sampling elements that are not `Hash`, like points with float coordinates
+    fn sample_by_key<K, F>(self, max_num: usize, get_key: F) -> Vec<Self::Item>
+    where
+        K: Hash,
+        F: Fn(&Self::Item) -> K,
+    {
+        let mut sampler = Sampler::new(max_num);
+        for el in self {
+            let key = get_key(&el);
+            sampler.update_with_key(el, &key);
+        }
+        sampler.finish()
+    }
+
+    /// Like `sample_by_key()`, with more control on how the elements are picked
+    fn sample_with_options<K, F>(
+        self,
+        max_num: usize,
+        options: &SampleOptions,
+        get_key: F,
+    ) -> Vec<Self::Item>
+    where
+        K: Hash,
+        F: Fn(&Self::Item) -> K,
+    {
+        let mut sampler = Sampler::with_options(max_num, options);
+        for el in self {
+            let key = get_key(&el);
+            sampler.update_with_key(el, &key);
+        }
+        sampler.finish()
+    }
+}
+
+impl<T: Iterator> Sample for T {}
+
+pub trait PrioritySample: Iterator + Sized {
+    /// Sample from the iterator, returning at most `max_num` elements with the highest
+    /// priority.
+    /// By design, this is a stable sampler, that is, the result is not dependent
+    /// on the order in which the values arrive from the iterator.
+    ///
+    /// If there are less than `max_num` elements in total, they will be returned
+    /// intact. Otherwise, due to the way the logic is implemented any value from
+    /// 0 to `max_num` can be returned.
+    ///
+    /// If in total, there are more than `max_num` elements with priority larger than
+    /// `k`, then no element with a lower priority will be returned
+    fn sample_with_priority<F>(
+        self,
+        max_num: usize,
+        get_priority: F,
+    ) -> BTreeMap<i32, Vec<Self::Item>>
+    where
+        Self::Item: Hash,
+        F: Fn(&Self::Item) -> i32,
+    {
+        priority_sample(
+            self,
+            max_num,
+            &SampleOptions::default(),
+            get_priority,
+            Sampler::update,
+        )
+    }
+
+    /// Like `sample_with_priority()`, but hash the key returned by `get_key` instead of the element
+    /// itself
+    fn sample_with_priority_by_key<K, F, G>(
+        self,
+        max_num: usize,
+        get_priority: F,
+        get_key: G,
+    ) -> BTreeMap<i32, Vec<Self::Item>>
+    where
+        K: Hash,
+        F: Fn(&Self::Item) -> i32,
+        G: Fn(&Self::Item) -> K,
+    {
+        priority_sample(
+            self,
+            max_num,
+            &SampleOptions::default(),
+            get_priority,
+            |sampler, el| {
+                let key = get_key(&el);
+                sampler.update_with_key(el, &key);
+            },
+        )
+    }
+
+    /// Like `sample_with_priority_by_key()`, with more control on how the elements are picked. With
+    /// `fill`, the priorities are visited from the highest until there are `max_num` elements
+    fn sample_with_priority_options<K, F, G>(
+        self,
+        max_num: usize,
+        options: &SampleOptions,
+        get_priority: F,
+        get_key: G,
+    ) -> BTreeMap<i32, Vec<Self::Item>>
+    where
+        K: Hash,
+        F: Fn(&Self::Item) -> i32,
+        G: Fn(&Self::Item) -> K,
+    {
+        priority_sample(self, max_num, options, get_priority, |sampler, el| {
+            let key = get_key(&el);
+            sampler.update_with_key(el, &key);
+        })
+    }
+
+    /// Like `sample_with_priority_options()`, but spread the elements over the viewport, with the
+    /// position returned by `get_xy`. Each cell of the grid gets the same share of `max_num`, except
+    /// the ones with less elements than that, whose rest is shared among the others. Each cell is
+    /// then sampled by priority, so a high priority only wins over the lower ones of its cell.
+    ///
+    /// All the elements are kept in memory until the end, to count them per cell
+    fn sample_spread<K, F, G, P>(
+        self,
+        max_num: usize,
+        options: &SampleOptions,
+        spread: &Spread,
+        get_priority: F,
+        get_key: G,
+        get_xy: P,
+    ) -> BTreeMap<i32, Vec<Self::Item>>
+    where
+        K: Hash,
+        F: Fn(&Self::Item) -> i32,
+        G: Fn(&Self::Item) -> K,
+        P: Fn(&Self::Item) -> [f64; 2],
+    {
+        let grid = spread.grid.max(1);
+        let mut cells: Vec<Vec<(usize, Self::Item)>> = (0..grid * grid).map(|_| vec![]).collect();
+        for (i, el) in self.enumerate() {
+            cells[spread.cell(get_xy(&el))].push((i, el));
+        }
+
+        let counts: Vec<usize> = cells.iter().map(Vec::len).collect();
+        let mut merged: BTreeMap<i32, Vec<(usize, Self::Item)>> = BTreeMap::new();
+        for (cell, quota) in cells.into_iter().zip(share_quotas(&counts, max_num)) {
+            let sampled = priority_sample(
+                cell.into_iter(),
+                quota,
+                options,
+                |(_, el)| get_priority(el),
+                |sampler, (i, el)| {
+                    let key = get_key(&el);
+                    sampler.update_with_key((i, el), &key);
+                },
+            );
+            for (priority, els) in sampled {
+                merged.entry(priority).or_default().extend(els);
+            }
+        }
+
+        // Restore the order in which they were offered
+        merged
+            .into_iter()
+            .map(|(priority, mut els)| {
+                els.sort_by_key(|&(i, _)| i);
+                (priority, els.into_iter().map(|(_, el)| el).collect())
+            })
+            .collect()
+    }
+}
+
+impl<T: Iterator> PrioritySample for T {}
+
+/// Feed the elements to one sampler per priority level, with `update`, and collect the
+/// highest priorities
+fn priority_sample<I, F, U>(
+    iter: I,
+    max_num: usize,
+    options: &SampleOptions,
+    get_priority: F,
+    update: U,
+) -> BTreeMap<i32, Vec<I::Item>>
+where
+    I: Iterator,
+    F: Fn(&I::Item) -> i32,
+    U: Fn(&mut Sampler<I::Item>, I::Item),
+{
+    let mut sampler = PrioritySampler::with_options(max_num, options);
+    for el in iter {
+        let priority = get_priority(&el);
+        sampler.offer(priority, el, &update);
+    }
+    sampler.finish()
+}
+
+/// Keep a stable sample of at most `max_num` of the elements given to it, with the highest
+/// priorities, like `PrioritySample::sample_with_priority()`. Parts of the elements can be fed to
+/// different samplers, for example in parallel, and merged into the same sample
+pub struct PrioritySampler<T> {
+    /// One sampler per priority level
+    samplers: BTreeMap<i32, Sampler<T>>,
+    max_num: usize,
+    options: SampleOptions,
+    /// A level alone could answer the full query, so the lower ones are not needed
+    min_priority: i32,
+}
+
+impl<T> PrioritySampler<T> {
+    pub fn new(max_num: usize) -> Self {
+        PrioritySampler::with_options(max_num, &SampleOptions::default())
+    }
+
+    pub fn with_options(max_num: usize, options: &SampleOptions) -> Self {
+        PrioritySampler {
+            samplers: BTreeMap::new(),
+            max_num,
+            options: *options,
+            min_priority: i32::MIN,
+        }
+    }
+
+    /// Offer a new element to the sample, with its priority
+    pub fn update(&mut self, el: T, priority: i32)
+    where
+        T: Hash,
+    {
+        self.offer(priority, el, Sampler::update);
+    }
+
+    /// Offer a new element to the sampler of its priority with `update`
+    fn offer<U: FnOnce(&mut Sampler<T>, T)>(&mut self, priority: i32, el: T, update: U) {
+        if priority < self.min_priority {
+            // We know it is useless to handle this element, since it will not
+            // be returned
+            return;
+        }
+
+        // Find corresponding sampler
+        let (max_num, options) = (self.max_num, &self.options);
+        let sampler = self
+            .samplers
+            .entry(priority)
+            .or_insert_with(|| Sampler::with_options(max_num, options));
+        update(sampler, el);
+
+        if sampler.len() >= max_num {
+            // Just this priority level alone could answer the full query
+            self.min_priority = priority;
+        }
+    }
+
+    /// Add the elements offered to another sampler with the same `max_num` and options, giving the
+    /// same sample as if they had all been offered to this one
+    pub fn merge(&mut self, other: PrioritySampler<T>) {
+        for (priority, sampler) in other.samplers {
+            match self.samplers.entry(priority) {
+                Entry::Occupied(mut entry) => entry.get_mut().merge(sampler),
+                Entry::Vacant(entry) => {
+                    entry.insert(sampler);
+                }
+            }
+        }
+        self.min_priority = self.min_priority.max(other.min_priority);
+    }
+
+    /// Return the kept elements of the highest priorities
+    pub fn finish(self) -> BTreeMap<i32, Vec<T>> {
+        let mut result = BTreeMap::new();
+        let mut total_els = 0;
+        for (priority, mut sampler) in self.samplers.into_iter().rev() {
+            sampler.resample(self.max_num - total_els);
+            // Without `fill`, what was offered counts, so that the lower priorities are not used to
+            // compensate for an unlucky sample
+            total_els += if self.options.fill {
+                sampler.result.len()
+            } else {
+                sampler.len()
+            };
+            result.insert(priority, sampler.finish());
+            if total_els >= self.max_num {
+                break;
+            }
+        }
+        result
+    }
+}
+
+/// Keep a stable sample of at most `max_num` of the elements given to it. This is what the
+/// iterator adapters use, but it can also be fed directly, for example from several sources
+pub struct Sampler<T> {
+    // Store kept values along with their hashes
+    result: Vec<(T, u64)>,
+    max_num: usize,
+    hash_mask: u64,
+    len: usize,
+    seed: Option<u64>,
+    stable_hash: bool,
+    fill: bool,
+}
+
+impl<T> Sampler<T> {
+    pub fn new(max_num: usize) -> Self {
+        Sampler {
+            result: Vec::with_capacity(max_num),
+            max_num,
+            hash_mask: 0,
+            len: 0,
+            seed: None,
+            stable_hash: false,
+            fill: false,
+        }
+    }
+
+    /// Create a sampler that keeps different elements for each seed
+    pub fn with_seed(max_num: usize, seed: u64) -> Self {
+        Sampler {
+            seed: Some(seed),
+            ..Sampler::new(max_num)
+        }
+    }
+
+    pub fn with_options(max_num: usize, options: &SampleOptions) -> Self {
+        Sampler {
+            seed: options.seed,
+            stable_hash: options.stable_hash,
+            fill: options.fill,
+            ..Sampler::new(max_num)
+        }
+    }
+
+    /// Offer a new element to the sample
+    pub fn update(&mut self, el: T)
+    where
+        T: Hash,
+    {
+        let hash = self.hash(&el);
+        self.insert(el, hash);
+    }
+
+    /// Offer a new element to the sample, identified by the hash of `key`
+    pub fn update_with_key<K: Hash + ?Sized>(&mut self, el: T, key: &K) {
+        let hash = self.hash(key);
+        self.insert(el, hash);
+    }
+
+    fn hash<K: Hash + ?Sized>(&self, key: &K) -> u64 {
+        if self.stable_hash {
+            let mut hasher = StableHasher::with_seed(self.seed.unwrap_or(0));
+            key.hash(&mut hasher);
+            return hasher.finish();
+        }
+        let mut hasher = DefaultHasher::new();
+        if let Some(seed) = self.seed {
+            hasher.write_u64(seed);
+        }
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn insert(&mut self, el: T, hash: u64) {
+        if self.fill {
+            // Trim only once in a while, to keep it linear
+            self.result.push((el, hash));
+            if self.result.len() >= 2 * self.max_num.max(1) {
+                self.keep_most_zeros(self.max_num);
+            }
+            self.len += 1;
+            return;
+        }
+
+        // Keep it
+        if hash & self.hash_mask == 0 {
+            // Make space for the new element
+            while self.result.len() == self.max_num {
+                // Drop approximately half of the elements by increasing by one the number
+                // of required zeros at the end of the hash
+                self.hash_mask = (self.hash_mask << 1) + 1;
+                let hash_mask = self.hash_mask;
+                self.result.retain(|(_el, hash)| hash & hash_mask == 0);
+            }
+
+            // We need to recheck, since it may have change in the mean time
+            if hash & self.hash_mask == 0 {
+                self.result.push((el, hash));
+            }
+        }
+
+        self.len += 1;
+    }
+
+    /// Add the elements offered to another sampler with the same `max_num` and options, giving the
+    /// same sample as if they had all been offered to this one. Only their order may differ
+    pub fn merge(&mut self, other: Sampler<T>) {
+        self.len += other.len;
+        if self.fill {
+            self.result.extend(other.result);
+            self.keep_most_zeros(self.max_num);
+            return;
+        }
+
+        // The kept elements are all the offered ones that satisfy the mask, so the merged sample
+        // is the one of the strictest mask, made stricter until it fits
+        self.hash_mask = self.hash_mask.max(other.hash_mask);
+        let hash_mask = self.hash_mask;
+        self.result.retain(|(_el, hash)| hash & hash_mask == 0);
+        self.result.extend(
+            other
+                .result
+                .into_iter()
+                .filter(|(_el, hash)| hash & hash_mask == 0),
+        );
+        self.resample(self.max_num);
+    }
+
+    fn resample(&mut self, new_max_num: usize) {
+        assert!(new_max_num <= self.max_num);
+        self.max_num = new_max_num;
+        if self.fill {
+            self.keep_most_zeros(new_max_num);
+            return;
+        }
+        while self.result.len() > self.max_num {
+            // Drop approximately half of the elements by increasing by one the number
+            // of required zeros at the end of the hash
+            self.hash_mask = (self.hash_mask << 1) + 1;
+            let hash_mask = self.hash_mask;
+            self.result.retain(|(_el, hash)| hash & hash_mask == 0);
+        }
+    }
+
+    /// Keep the `max_num` elements whose hashes end with the most zeros, that is, the ones with the
+    /// lowest reversed hash. The ties, from hash collisions, are broken by the order of arrival
+    fn keep_most_zeros(&mut self, max_num: usize) {
+        if self.result.len() <= max_num {
+            return;
+        }
+        let mut ranks: Vec<u64> = self
+            .result
+            .iter()
+            .map(|(_el, hash)| hash.reverse_bits())
+            .collect();
+        let threshold = *ranks.select_nth_unstable(max_num).1;
+        let mut ties = max_num - ranks.iter().filter(|&&rank| rank < threshold).count();
+        self.result.retain(|(_el, hash)| {
+            let rank = hash.reverse_bits();
+            if rank == threshold && ties > 0 {
+                ties -= 1;
+                return true;
+            }
+            rank < threshold
+        });
+    }
+
+    /// Return the kept elements, in the order they were offered
+    pub fn finish(mut self) -> Vec<T> {
+        if self.fill {
+            self.keep_most_zeros(self.max_num);
+        }
+        self.result.into_iter().map(|(el, _hash)| el).collect()
+    }
+
+    /// How many elements were offered so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Share `max_num` equally among the cells with the given number of elements, giving the rest of
+/// the cells with less elements than their share to the others. What can not be split equally goes
+/// to the first cells
+fn share_quotas(counts: &[usize], max_num: usize) -> Vec<usize> {
+    let mut quotas = vec![0; counts.len()];
+    let mut left = max_num;
+    let mut open: Vec<usize> = (0..counts.len()).filter(|&i| counts[i] > 0).collect();
+    while left > 0 && !open.is_empty() {
+        let share = (left / open.len()).max(1);
+        for &i in &open {
+            let extra = share.min(counts[i] - quotas[i]).min(left);
+            quotas[i] += extra;
+            left -= extra;
+        }
+        open.retain(|&i| quotas[i] < counts[i]);
+    }
+    quotas
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multiple_compressions() {
+        fn check(n1: usize, n2: usize, result: Vec<usize>) {
+            dbg!(n1);
+            assert_eq!((0..n1).sample(n2), result);
+        }
+
+        // All
+        check(6, 100, vec![0, 1, 2, 3, 4, 5]);
+        check(6, 6, vec![0, 1, 2, 3, 4, 5]);
+
+        // Single compression
+        check(6, 5, vec![2, 3, 5]);
+        check(7, 5, vec![2, 3, 5, 6]);
+        check(8, 5, vec![2, 3, 5, 6]);
+        check(9, 5, vec![2, 3, 5, 6, 8]);
+
+        // Double compression
+        check(10, 5, vec![5, 6, 9]);
+        check(11, 5, vec![5, 6, 9]);
+        check(12, 5, vec![5, 6, 9]);
+        for n in 13..23 {
+            check(n, 5, vec![5, 6, 9, 12]);
+        }
+        for n in 23..30 {
+            check(n, 5, vec![5, 6, 9, 12, 22]);
+        }
+
+        // Triple compression
+        for n in 30..36 {
+            check(n, 5, vec![6, 9, 12, 29]);
+        }
+        for n in 36..38 {
+            check(n, 5, vec![6, 9, 12, 29, 35]);
+        }
+
+        // Quadruple compression
+        for n in 38..106 {
+            check(n, 5, vec![9]);
+        }
+    }
+
+    #[test]
+    fn stability() {
+        fn check(values: Vec<usize>) {
+            let mut result = values.into_iter().sample(5);
+            result.sort();
+            assert_eq!(result, vec![6, 9, 12, 29, 35]);
+        }
+
+        check((0usize..37).collect());
+
+        check(vec![
+            26usize, 8, 29, 14, 16, 15, 11, 30, 0, 24, 13, 25, 34, 3, 1, 27, 33, 28, 7, 5, 9, 21,
+            10, 2, 23, 36, 4, 12, 20, 6, 31, 22, 35, 32, 19, 18, 17,
+        ]);
+
+        check(vec![
+            26usize, 5, 2, 35, 13, 9, 6, 19, 0, 18, 4, 23, 15, 30, 25, 11, 14, 8, 24, 28, 33, 32,
+            17, 12, 27, 34, 29, 10, 1, 20, 36, 31, 7, 3, 22, 21, 16,
+        ]);
+    }
+
+    #[test]
+    fn seeds_and_keys() {
+        // A seed gives another sample, but it is as stable as the default one
+        let sample = (0..100usize).sample_seeded(5, 42);
+        assert_ne!(sample, (0..100usize).sample(5));
+        assert_eq!((0..100usize).rev().sample_seeded(5, 42).len(), sample.len());
+        let mut reversed = (0..100usize).rev().sample_seeded(5, 42);
+        reversed.reverse();
+        assert_eq!(reversed, sample);
+
+        // Sampling by key is the same as sampling the keys
+        let points: Vec<(f64, usize)> = (0..100usize).map(|i| (i as f64 / 3., i)).collect();
+        let sample: Vec<usize> = points
+            .into_iter()
+            .sample_by_key(5, |&(_, i)| i)
+            .into_iter()
+            .map(|(_, i)| i)
+            .collect();
+        assert_eq!(sample, (0..100usize).sample(5));
+
+        // Feeding the sampler directly
+        let mut sampler = Sampler::new(5);
+        (0..50usize).for_each(|i| sampler.update(i));
+        (50..100usize).for_each(|i| sampler.update(i));
+        assert_eq!(sampler.len(), 100);
+        assert_eq!(sampler.finish(), (0..100usize).sample(5));
+    }
+
+    #[test]
+    fn single_priority() {
+        // Single priority works like the non-prioritized one
+        assert_eq!(
+            (0..38usize).sample_with_priority(5, |_| 0),
+            vec![(0, (0..38usize).sample(5))].into_iter().collect()
+        );
+        assert_eq!(
+            (0..100usize).sample_with_priority(5, |_| -17),
+            vec![(-17, (0..100usize).sample(5))].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn saturated_priority() {
+        // Only take from the top priorities if there are enough there
+        assert_eq!(
+            (0..38usize).sample_with_priority(5, |i| (i / 19) as i32),
+            vec![(1, (19..38usize).sample(5))].into_iter().collect()
+        );
+
+        assert_eq!(
+            (0..38usize).sample_with_priority(5, |i| (i / 10) as i32),
+            vec![(3, (30..38usize).sample(5))].into_iter().collect()
+        );
+
+        let p3 = (30..38usize).sample(15);
+        let p3_len = p3.len();
+        assert_eq!(
+            (0..38usize).sample_with_priority(15, |i| (i / 10) as i32),
+            vec![(3, p3), (2, (20..30usize).sample(15 - p3_len))]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn merged_samplers() {
+        // Splitting the elements in any parts gives the same sample, only in another order
+        let sorted = |mut values: Vec<usize>| {
+            values.sort();
+            values
+        };
+        for &(max_num, split) in &[(5, 3), (5, 50), (15, 97), (200, 40)] {
+            let mut first = Sampler::new(max_num);
+            let mut second = Sampler::new(max_num);
+            (0..1000usize)
+                .filter(|i| i % split < split / 2)
+                .for_each(|i| first.update(i));
+            (0..1000usize)
+                .filter(|i| i % split >= split / 2)
+                .for_each(|i| second.update(i));
+            second.merge(first);
+            assert_eq!(second.len(), 1000);
+            assert_eq!(
+                sorted(second.finish()),
+                sorted((0..1000usize).sample(max_num))
+            );
+        }
+
+        let priority = |i: &usize| (i % 7 / 3) as i32;
+        let expected = (0..1000usize).sample_with_priority(100, priority);
+        let mut samplers: Vec<_> = (0..4).map(|_| PrioritySampler::new(100)).collect();
+        for i in 0..1000usize {
+            samplers[i * 4 / 1000].update(i, priority(&i));
+        }
+        let mut merged = samplers.pop().unwrap();
+        samplers
+            .into_iter()
+            .for_each(|sampler| merged.merge(sampler));
+        let merged: BTreeMap<_, _> = merged
+            .finish()
+            .into_iter()
+            .map(|(priority, values)| (priority, sorted(values)))
+            .collect();
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn stable_hash() {
+        let options = SampleOptions {
+            stable_hash: true,
+            ..SampleOptions::default()
+        };
+        let sample = (0..100usize).sample_with_options(5, &options, |&i| i);
+        assert_eq!(sample, vec![6, 9, 28, 84]);
+        let mut reversed = (0..100usize).rev().sample_with_options(5, &options, |&i| i);
+        reversed.reverse();
+        assert_eq!(reversed, sample);
+
+        // Any integer type with the same value gives the same sample
+        let as_u64: Vec<usize> = (0..100u64)
+            .sample_with_options(5, &options, |&i| i)
+            .into_iter()
+            .map(|i| i as usize)
+            .collect();
+        assert_eq!(as_u64, sample);
+
+        let seeded = SampleOptions {
+            seed: Some(42),
+            ..options
+        };
+        assert_ne!(
+            (0..100usize).sample_with_options(5, &seeded, |&i| i),
+            sample
+        );
+    }
+
+    #[test]
+    fn fill() {
+        let options = SampleOptions {
+            fill: true,
+            ..SampleOptions::default()
+        };
+        for n in 0..200 {
+            let sample = (0..n).sample_with_options(17, &options, |&i| i);
+            assert_eq!(sample.len(), n.min(17));
+            let mut reversed = (0..n).rev().sample_with_options(17, &options, |&i| i);
+            reversed.reverse();
+            assert_eq!(reversed, sample);
+        }
+
+        // The top priority is topped up from the next ones
+        let sample =
+            (0..38usize).sample_with_priority_options(15, &options, |i| (i / 10) as i32, |&i| i);
+        assert_eq!(sample[&3], (30..38).collect::<Vec<_>>());
+        assert_eq!(sample[&2].len(), 7);
+        assert!(!sample.contains_key(&1));
+        assert_eq!(
+            sample,
+            (0..38usize)
+                .rev()
+                .sample_with_priority_options(15, &options, |i| (i / 10) as i32, |&i| i)
+                .into_iter()
+                .map(|(priority, mut els)| {
+                    els.reverse();
+                    (priority, els)
+                })
+                .collect()
+        );
+    }
+
+    #[test]
+    fn spread() {
+        // 1000 points in the bottom-left cell and 5 in each of the other three
+        let dense = (0..1000usize).map(|i| ([i as f64 / 1000., 0.], i));
+        let cells = [[3., 1.], [1., 3.], [3., 3.]];
+        let sparse = (0..15usize).map(|i| (cells[i % 3], 1000 + i));
+        let points: Vec<_> = dense.chain(sparse).collect();
+        let spread = Spread {
+            min: [0., 0.],
+            max: [4., 4.],
+            grid: 2,
+        };
+        let options = SampleOptions {
+            fill: true,
+            ..SampleOptions::default()
+        };
+        let sample =
+            points
+                .iter()
+                .sample_spread(40, &options, &spread, |_| 0, |&&(_, i)| i, |&&(xy, _)| xy);
+        let ids: Vec<usize> = sample[&0].iter().map(|&&(_, i)| i).collect();
+        assert_eq!(ids.len(), 40);
+        assert!((1000..1015).all(|i| ids.contains(&i)));
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+        assert_eq!(share_quotas(&[1000, 5, 0, 5], 40), vec![30, 5, 0, 5]);
+        assert_eq!(share_quotas(&[3, 3, 3], 5), vec![2, 2, 1]);
+        assert_eq!(share_quotas(&[2, 2], 10), vec![2, 2]);
+    }
+}
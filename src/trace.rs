@@ -0,0 +1,191 @@
+//! Read and prepare GPS traces collected by devices in the field, so that they can be matched to the
+//! road network
+
+use crate::utils::GeoPoint;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Read a GPS trace from a file, detecting its format from the extension: `.gpx` or `.csv`
+pub fn read_trace<P: AsRef<Path>>(path: P) -> io::Result<Vec<GeoPoint>> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+
+    match extension.as_deref() {
+        Some("gpx") => parse_gpx(&contents),
+        Some("csv") => parse_csv(&contents),
+        _ => Err(invalid_data(format!(
+            "Could not detect the trace format of {}. Use the extension .gpx or .csv",
+            path.display()
+        ))),
+    }
+}
+
+/// Parse the track points of a GPX document. If there are none, the route points and then the
+/// waypoints are used instead
+pub fn parse_gpx(contents: &str) -> io::Result<Vec<GeoPoint>> {
+    for tag in &["<trkpt", "<rtept", "<wpt"] {
+        let mut points = Vec::new();
+        for (start, _) in contents.match_indices(tag) {
+            let element = &contents[start..];
+            let element = &element[..element.find('>').unwrap_or(element.len())];
+            let lat = parse_gpx_attribute(element, "lat")?;
+            let lon = parse_gpx_attribute(element, "lon")?;
+            points.push(GeoPoint::from_degrees(lat, lon));
+        }
+
+        if !points.is_empty() {
+            return Ok(points);
+        }
+    }
+
+    Ok(Vec::new())
+}
+
+/// Parse a CSV with latitude and longitude columns. If the first line is a header, the columns named
+/// `lat` (or `latitude`) and `lon` (or `lng`, `longitude`) are used. Otherwise, the first two columns
+/// are taken as latitude and longitude, in this order
+pub fn parse_csv(contents: &str) -> io::Result<Vec<GeoPoint>> {
+    let mut lines = contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .peekable();
+
+    // Detect header
+    let mut columns = (0, 1);
+    if let Some(first) = lines.peek() {
+        let names: Vec<String> = first
+            .split(',')
+            .map(|name| name.trim().trim_matches('"').to_lowercase())
+            .collect();
+        if names[0].parse::<f64>().is_err() {
+            let find = |options: &[&str]| {
+                names
+                    .iter()
+                    .position(|name| options.contains(&name.as_str()))
+                    .ok_or_else(|| invalid_data(format!("Missing column {} in CSV", options[0])))
+            };
+            columns = (
+                find(&["lat", "latitude"])?,
+                find(&["lon", "lng", "longitude"])?,
+            );
+            lines.next();
+        }
+    }
+
+    lines
+        .map(|line| {
+            let values: Vec<&str> = line.split(',').map(|value| value.trim()).collect();
+            let parse = |column: usize| {
+                values
+                    .get(column)
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .ok_or_else(|| invalid_data(format!("Could not parse CSV line {}", line)))
+            };
+            Ok(GeoPoint::from_degrees(parse(columns.0)?, parse(columns.1)?))
+        })
+        .collect()
+}
+
+/// Drop the points that are closer than `min_distance` meters to the previous kept point, since
+/// their difference is mostly GPS noise. This happens a lot when the vehicle is stopped
+pub fn thin_trace(points: &[GeoPoint], min_distance: f64) -> Vec<GeoPoint> {
+    let mut result: Vec<GeoPoint> = Vec::with_capacity(points.len());
+    for point in points {
+        match result.last() {
+            Some(last) if last.haversine_distance(point) < min_distance => {}
+            _ => result.push(*point),
+        }
+    }
+    result
+}
+
+/// Read a GPX attribute value as a float
+fn parse_gpx_attribute(element: &str, name: &str) -> io::Result<f64> {
+    for quote in &['"', '\''] {
+        let prefix = format!(" {}={}", name, quote);
+        if let Some(start) = element.find(&prefix) {
+            let value = &element[start + prefix.len()..];
+            if let Some(end) = value.find(*quote) {
+                return value[..end].parse().map_err(|_| {
+                    invalid_data(format!("Invalid attribute {} in {}", name, element))
+                });
+            }
+        }
+    }
+
+    Err(invalid_data(format!(
+        "Missing attribute {} in {}",
+        name, element
+    )))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gpx() {
+        let gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test">
+  <wpt lat="42.5" lon="1.5"/>
+  <trk><trkseg>
+    <trkpt lat="42.55" lon="1.58"><ele>1200</ele></trkpt>
+    <trkpt lon='1.59' lat='42.56'/>
+  </trkseg></trk>
+</gpx>"#;
+        assert_eq!(
+            parse_gpx(gpx).unwrap(),
+            vec![
+                GeoPoint::from_degrees(42.55, 1.58),
+                GeoPoint::from_degrees(42.56, 1.59)
+            ]
+        );
+
+        let gpx = r#"<gpx><wpt lat="42.5" lon="1.5"></wpt></gpx>"#;
+        assert_eq!(
+            parse_gpx(gpx).unwrap(),
+            vec![GeoPoint::from_degrees(42.5, 1.5)]
+        );
+
+        assert!(parse_gpx(r#"<gpx><trkpt lat="42.5"/></gpx>"#).is_err());
+    }
+
+    #[test]
+    fn csv() {
+        let expected = vec![
+            GeoPoint::from_degrees(42.55, 1.58),
+            GeoPoint::from_degrees(42.56, 1.59),
+        ];
+        assert_eq!(parse_csv("42.55,1.58\n42.56,1.59\n").unwrap(), expected);
+        assert_eq!(
+            parse_csv("time,lon,lat\n0,1.58,42.55\n\n10,1.59,42.56").unwrap(),
+            expected
+        );
+        assert!(parse_csv("time,lon\n0,1.58").is_err());
+        assert!(parse_csv("42.55,banana").is_err());
+    }
+
+    #[test]
+    fn thin() {
+        let points = vec![
+            GeoPoint::from_degrees(42.55, 1.58),
+            GeoPoint::from_degrees(42.55001, 1.58),
+            GeoPoint::from_degrees(42.5501, 1.58),
+            GeoPoint::from_degrees(42.5502, 1.58),
+        ];
+        assert_eq!(
+            thin_trace(&points, 5.),
+            vec![points[0], points[2], points[3]]
+        );
+    }
+}
@@ -1,31 +1,109 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::Instant;
 
-/// Simple log helper that prepends messages with the elapsed time
+/// Simple log helper that logs messages with the elapsed time, as the `total_secs` and `phase_secs`
+/// fields
+#[cfg(not(target_arch = "wasm32"))]
 pub struct DebugTime {
     start: Instant,
     prev: Instant,
+    quiet: bool,
 }
 
+/// The system clock is not available in the browser, so messages are simply ignored
+#[cfg(target_arch = "wasm32")]
+pub struct DebugTime;
+
+#[cfg(target_arch = "wasm32")]
+impl DebugTime {
+    pub fn new() -> Self {
+        DebugTime
+    }
+
+    pub fn quiet() -> Self {
+        DebugTime
+    }
+
+    pub fn msg<T: std::fmt::Display>(&mut self, _s: T) {}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 impl DebugTime {
     pub fn new() -> Self {
         let start = Instant::now();
-        DebugTime { start, prev: start }
+        DebugTime {
+            start,
+            prev: start,
+            quiet: false,
+        }
+    }
+
+    /// Create a timer that does not log its messages
+    pub fn quiet() -> Self {
+        DebugTime {
+            quiet: true,
+            ..DebugTime::new()
+        }
     }
 
     pub fn msg<T: std::fmt::Display>(&mut self, s: T) {
+        if self.quiet {
+            return;
+        }
         let now = Instant::now();
         let total_dt = now - self.start;
         let prev_dt = now - self.prev;
         self.prev = now;
-        println!(
-            "[{:6.1}s ({:+5.1}s)] {}",
-            total_dt.as_secs_f32(),
-            prev_dt.as_secs_f32(),
+        tracing::info!(
+            total_secs = total_dt.as_secs_f32(),
+            phase_secs = prev_dt.as_secs_f32(),
+            "{}",
             s
         );
     }
 }
 
+/// Measure the duration of consecutive phases of a task
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Stopwatch {
+    prev: Instant,
+}
+
+/// The system clock is not available in the browser, so all phases take no time
+#[cfg(target_arch = "wasm32")]
+pub struct Stopwatch;
+
+#[cfg(target_arch = "wasm32")]
+impl Stopwatch {
+    pub fn start() -> Self {
+        Stopwatch
+    }
+
+    pub fn lap(&mut self) -> Duration {
+        Duration::default()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Stopwatch {
+    pub fn start() -> Self {
+        Stopwatch {
+            prev: Instant::now(),
+        }
+    }
+
+    /// The time since the previous lap, or since the start for the first one
+    pub fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let duration = now - self.prev;
+        self.prev = now;
+        duration
+    }
+}
+
 /// Pretty format a number of bytes
 pub fn format_bytes(n: u64) -> String {
     if n < 1000 {
@@ -50,8 +128,10 @@ pub fn format_num(n: usize) -> String {
     }
 }
 
-/// Represent an angle in degrees with 1e-6 precision
+/// Represent an angle in degrees with 1e-6 precision.
+/// With the `serde` feature, it is serialized as an integer number of micro degrees
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Angle(i32);
 
 impl Angle {
@@ -78,6 +158,7 @@ impl Angle {
 
 /// Represent a point on the surfase of the Earth (using the referential WGS84)
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct GeoPoint {
     pub lat: Angle,
     pub lon: Angle,
@@ -134,6 +215,33 @@ impl GeoPoint {
         let c = 2. * a.sqrt().asin();
         6_371_000.0 * c
     }
+
+    /// Get the initial bearing in degrees when going from this point to another one, that is,
+    /// the angle clockwise from the North, in the range [0, 360)
+    pub fn bearing(&self, other: &GeoPoint) -> f64 {
+        let theta1 = self.lat.as_radians();
+        let theta2 = other.lat.as_radians();
+        let delta_lambda = other.lon.as_radians() - self.lon.as_radians();
+        let y = delta_lambda.sin() * theta2.cos();
+        let x = theta1.cos() * theta2.sin() - theta1.sin() * theta2.cos() * delta_lambda.cos();
+        (y.atan2(x).to_degrees() + 360.) % 360.
+    }
+
+    /// Get the point reached when going `distance` meters from this one with the given initial
+    /// bearing, in degrees clockwise from the North, along a great circle
+    pub fn destination(&self, bearing: f64, distance: f64) -> GeoPoint {
+        let delta = distance / 6_371_000.0;
+        let bearing = bearing.to_radians();
+        let theta1 = self.lat.as_radians();
+        let lambda1 = self.lon.as_radians();
+        let theta2 =
+            (theta1.sin() * delta.cos() + theta1.cos() * delta.sin() * bearing.cos()).asin();
+        let lambda2 = lambda1
+            + (bearing.sin() * delta.sin() * theta1.cos())
+                .atan2(delta.cos() - theta1.sin() * theta2.sin());
+        let lon = (lambda2.to_degrees() + 540.) % 360. - 180.;
+        GeoPoint::from_degrees(theta2.to_degrees(), lon)
+    }
 }
 
 #[cfg(test)]
@@ -169,6 +277,18 @@ mod test {
 
         assert_eq!(a.haversine_distance(&a).round(), 0.);
         assert_eq!(a.haversine_distance(&b).round(), 2886444.);
+        assert_f64_similar(a.bearing(&b), 274.59, 1e-2);
+        assert_f64_similar(b.bearing(&a), 76.06, 1e-2);
+        assert_f64_similar(a.bearing(&GeoPoint::from_degrees(37.12, -86.67)), 0., 1e-6);
+        assert_f64_similar(
+            a.bearing(&GeoPoint::from_degrees(35.12, -86.67)),
+            180.,
+            1e-6,
+        );
+        let c = a.destination(a.bearing(&b), a.haversine_distance(&b));
+        assert_f64_similar(c.lat.as_degrees(), 33.94, 1e-5);
+        assert_f64_similar(c.lon.as_degrees(), -118.4, 1e-5);
+        assert_f64_similar(a.haversine_distance(&a.destination(42., 1000.)), 1000., 0.1);
 
         for &(lat, target_y) in &[(-36.12, -4317145.77), (0., 0.), (36.12, 4317145.77)] {
             for &(lon, target_x) in &[(86.67, 9648060.27), (0., 0.), (-86.67, -9648060.27)] {
@@ -0,0 +1,42 @@
+use ptolemy::*;
+use std::time::Instant;
+
+/// Compare the time to build the spatial index and to project points with each configuration. Run
+/// with `cargo test --release --test index_config -- --ignored --nocapture`, ideally with a file
+/// larger than the test one
+#[test]
+#[ignore]
+fn index_config() {
+    let path = std::env::var("PTOLEMY_FILE").unwrap_or_else(|_| "test_data/andorra.ptolemy".into());
+    let points: Vec<GeoPoint> = (0..100_000)
+        .map(|i| {
+            GeoPoint::from_degrees(
+                42.43 + (i % 317) as f64 * 0.00066,
+                1.42 + (i * 37 % 1000) as f64 * 0.00028,
+            )
+        })
+        .collect();
+
+    for &(f32_envelopes, parallel) in &[(false, false), (true, false), (false, true), (true, true)]
+    {
+        let config = IndexConfig {
+            f32_envelopes,
+            parallel,
+        };
+        let (carto, report) = Cartograph::open_with_config(&path, &config, |_| {}).unwrap();
+
+        let start = Instant::now();
+        let distance: f64 = points
+            .iter()
+            .map(|point| carto.project(point).projected.haversine_distance(point))
+            .sum();
+        println!(
+            "{:?}: built in {:?}, {} projections in {:?} (mean distance {:.1} m)",
+            config,
+            report.rtree_build,
+            points.len(),
+            start.elapsed(),
+            distance / points.len() as f64
+        );
+    }
+}
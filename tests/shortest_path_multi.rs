@@ -2,7 +2,7 @@ use ptolemy::*;
 
 #[test]
 #[ignore]
-fn shortest_path() {
+fn shortest_path_multi() {
     let carto = Cartograph::open("test_data/andorra.ptolemy").unwrap();
     let from = carto.project(&GeoPoint::from_degrees(42.553210, 1.588908));
     let to1 = carto.project(&GeoPoint::from_degrees(42.564440, 1.685042));